@@ -1,42 +1,994 @@
-use clap::Parser;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use clap::{Parser, ValueEnum};
+
+use super::art::ArtFit;
+use super::codel::Codel;
+use super::command;
+use super::corner::CornerStrategyKind;
+use super::effective_config::PrintConfig;
+use super::entry::{self, Region};
+use super::examples::Example;
+use super::exit_status;
+use super::expect::{ExpectEncoding, OnMismatch};
+use super::extension::{ExecConfig, Extension};
+use super::golf::GolfRules;
+use super::image::{self, CodelLayout, DetectFallback, GridSpec, Topology};
+use super::limits::{self, ImageLimits, LimitPreset, Limits};
+use super::lint;
+use super::multiplexer::SyncOutput;
+use super::pace::Speed;
+use super::program::Background;
+use super::random_input::RandomSpec;
+use super::runner::{LoadOptions, PietRunner, PietRunnerBuilder, RunnerConfig, TraceOptions};
+use super::semantics::SemanticsDescriptor;
+use super::stdin::{self, InputNewlines, NumberOverflow};
+
+/// Format of the input file.
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum Format {
+    /// Selects the format by the file extension (`.pietdev` for PietDev saves, otherwise image)
+    #[default]
+    Auto,
+    /// Image file (PNG, GIF, etc.)
+    Image,
+    /// Save file of the PietDev web editor
+    Pietdev,
+}
 
 /// Interpreter for Piet Programming Language
 #[derive(Parser, Debug)]
 #[command(author, version, about, long_about = None)]
-pub struct Args {
-    #[arg()]
+#[command(
+    after_help = format!("`piet_programming_language <IMAGE_FILE> [OPTIONS]` is the same as `piet_programming_language run <IMAGE_FILE> [OPTIONS]`.\n\n{}", exit_status::HELP)
+)]
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Subcommand,
+}
+
+//parsed only once, so the size of `Args` does not matter
+#[allow(clippy::large_enum_variant)]
+#[derive(clap::Subcommand, Debug)]
+pub enum Subcommand {
+    /// Runs a program (default)
+    Run(Args),
+    /// Prints the size metrics and the golf score of a program
+    Golf(GolfArgs),
+    /// Converts a program into the save format of the PietDev web editor
+    Export(ExportArgs),
+    /// Runs a program with both this interpreter and another interpreter binary and compares their outputs
+    Compare(CompareArgs),
+    /// Compares two path traces recorded with `--verbose` or `--trace-after` and reports the first divergence
+    TraceDiff(TraceDiffArgs),
+    /// Prints the version and the execution semantics implied by the options as JSON
+    VersionReport(VersionReportArgs),
+    /// Exports conformance test vectors for other implementations, or verifies exported ones
+    Conformance(ConformanceArgs),
+}
+
+impl Cli {
+    /// Parses the command line, inserting `run` when no subcommand is given so that the form without subcommands keeps working.
+    pub fn parse_with_default_subcommand(args: impl IntoIterator<Item = String>) -> Self {
+        Self::parse_from(Self::insert_default_subcommand(args))
+    }
+
+    /// Same as [`Self::parse_with_default_subcommand()`] but returns an error instead of exiting.
+    pub fn try_parse_with_default_subcommand(
+        args: impl IntoIterator<Item = String>,
+    ) -> Result<Self, clap::Error> {
+        Self::try_parse_from(Self::insert_default_subcommand(args))
+    }
+
+    fn insert_default_subcommand(args: impl IntoIterator<Item = String>) -> Vec<String> {
+        let mut args = args.into_iter().collect::<Vec<_>>();
+        let is_explicit = match args.get(1) {
+            None => true,
+            Some(arg) => [
+                "run",
+                "golf",
+                "export",
+                "compare",
+                "trace-diff",
+                "version-report",
+                "conformance",
+                "help",
+                "-h",
+                "--help",
+                "-V",
+                "--version",
+            ]
+            .contains(&arg.as_str()),
+        };
+        if !is_explicit {
+            args.insert(1, "run".to_string());
+        }
+        args
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        match &self.command {
+            Subcommand::Run(args) => args.validate(),
+            Subcommand::Golf(args) => args.load.validate(),
+            Subcommand::Export(args) => args.load.validate(),
+            Subcommand::Compare(args) => args.validate(),
+            Subcommand::TraceDiff(_) => Ok(()),
+            Subcommand::VersionReport(_) => Ok(()),
+            Subcommand::Conformance(args) => args.validate(),
+        }
+    }
+}
+
+/// Options which specify how a program is loaded.
+#[derive(clap::Args, Debug, Clone)]
+pub struct LoadArgs {
+    /// The program (not needed with `--example`)
+    #[arg(default_value = "", hide_default_value = true)]
     pub image_file: String,
 
+    /// Specifies the format of the input file
+    #[arg(long, value_enum, default_value_t = Format::Auto)]
+    pub format: Format,
+
     /// Specifies the codel size (default: auto detect)
     #[arg(short, long)]
     pub codel_size: Option<usize>,
 
-    /// Treats unknown colors as white instead of error
+    /// Specifies the codels as cells of WIDTH x HEIGHT pixels starting X pixels from the left and Y pixels from the top (the pixels outside of the whole codels are dropped)
+    #[arg(long, value_name = "WxH+X+Y")]
+    pub codel_grid: Option<GridSpec>,
+
+    /// Also tries margins of up to 3 pixels on each side (e.g. a border) when no codel size larger than one pixel is detected
+    #[arg(long)]
+    pub detect_grid_offset: bool,
+
+    /// Gives up the detection of the codel size after this many milliseconds (0: no limit), falling back as `--detect-fallback` tells
+    #[arg(long, value_name = "MS", default_value_t = image::DEFAULT_DETECT_BUDGET_MS)]
+    pub detect_budget: u64,
+
+    /// What is done when the detection of the codel size exceeds `--detect-budget` (a codel size used as a fallback is warned about)
+    #[arg(long, value_enum, default_value_t = DetectFallback::Partial)]
+    pub detect_fallback: DetectFallback,
+
+    /// Checks that every codel is uniformly colored when `--codel-size` or `--codel-grid` is given (otherwise only the top-left pixel of each codel is read)
+    #[arg(long)]
+    pub strict_codel_check: bool,
+
+    /// Reads only this rectangle of pixels, given by the top-left pixel and the size (a crop which cuts codels is rejected with the aligned crop suggested)
+    #[arg(long, value_name = "I,J,HEIGHT,WIDTH")]
+    pub crop: Option<Region>,
+
+    /// Runs this image (from 0) of a GIF, TIFF or ICO file which contains several of them (e.g. the pages of a TIFF), which are listed if it is not given
+    #[arg(long, value_name = "N")]
+    pub frame: Option<usize>,
+
+    /// Treats unknown colors as white instead of an error when reached
     #[arg(long)]
     pub fall_back_to_white: bool,
 
-    /// Treats unknown colors as black instead of error
+    /// Treats unknown colors as black instead of an error when reached
     #[arg(long)]
     pub fall_back_to_black: bool,
 
+    /// Reads a pixel as a color of Piet if each of its channels is within this of the color (at most 31), e.g. for a JPEG image
+    #[arg(long, value_name = "N", default_value_t = 0)]
+    pub color_tolerance: u8,
+
+    /// Maps the colors of the image to the colors of Piet as this file tells (lines of `#RRGGBB -> #RRGGBB`), warning about colors merged by the mapping (and with `--check`, about the transitions whose commands change)
+    #[arg(long, value_name = "FILE")]
+    pub palette: Option<String>,
+
+    /// Caches parsed images in this directory to skip parsing when the same image is loaded again
+    #[arg(long, value_name = "DIR")]
+    pub cache_dir: Option<String>,
+
+    /// Rejects an image with more pixels than this before decoding it
+    #[arg(long, value_name = "PIXELS", default_value_t = limits::DEFAULT_MAX_IMAGE_PIXELS)]
+    pub max_image_pixels: u64,
+
+    /// Rejects an image wider or taller than this number of pixels before decoding it
+    #[arg(long, value_name = "PIXELS", default_value_t = limits::DEFAULT_MAX_IMAGE_DIMENSION)]
+    pub max_image_dimension: u32,
+
+    /// Rejects an image whose decoded pixels take more bytes than this, and bounds the memory used by the decoder
+    #[arg(long, value_name = "BYTES", default_value_t = limits::DEFAULT_MAX_IMAGE_BYTES)]
+    pub max_image_bytes: u64,
+
+    /// Runs only the region of this entry of the manifest (see `--manifest`)
+    #[arg(long, value_name = "NAME")]
+    pub entry: Option<String>,
+
+    /// Specifies the manifest which declares the entries (default: `<IMAGE_FILE>` with the extension replaced by `manifest.toml`)
+    #[arg(long, value_name = "FILE")]
+    pub manifest: Option<String>,
+
+    /// Loads this program embedded in the binary instead of an image file (see `--list-examples`)
+    #[arg(long, value_enum, value_name = "NAME", conflicts_with_all = ["codel_size", "codel_grid", "detect_grid_offset", "detect_budget", "detect_fallback", "crop", "frame", "cache_dir", "entry", "manifest"])]
+    pub example: Option<Example>,
+}
+
+impl LoadArgs {
+    /// Returns the manifest which declares the entries.
+    pub fn manifest_path(&self) -> PathBuf {
+        match &self.manifest {
+            Some(file) => PathBuf::from(file),
+            None => entry::manifest_path(&self.image_file),
+        }
+    }
+
+    /// Returns how a program is loaded, shared with [`PietRunner`].
+    /// The other options (e.g. `--crop`) are applied only by the command line.
+    pub fn load_options(&self) -> LoadOptions {
+        LoadOptions {
+            codel_size: self.codel_size,
+            fallback: if self.fall_back_to_white {
+                Some(Codel::White)
+            } else if self.fall_back_to_black {
+                Some(Codel::Black)
+            } else {
+                None
+            },
+            image_limits: self.image_limits(),
+        }
+    }
+
+    /// Returns how the codels are laid out in the pixels.
+    pub fn codel_layout(&self) -> CodelLayout {
+        match (self.load_options().codel_size, self.codel_grid) {
+            (Some(codel_size), _) => CodelLayout::Size(codel_size),
+            (None, Some(grid)) => CodelLayout::Grid(grid),
+            (None, None) => CodelLayout::Detect {
+                search_offset: self.detect_grid_offset,
+                budget: (self.detect_budget != 0)
+                    .then(|| Duration::from_millis(self.detect_budget)),
+                fallback: self.detect_fallback,
+            },
+        }
+    }
+
+    /// Returns the limits on the size of the image.
+    pub fn image_limits(&self) -> ImageLimits {
+        ImageLimits {
+            max_pixels: self.max_image_pixels,
+            max_dimension: self.max_image_dimension,
+            max_decoded_bytes: self.max_image_bytes,
+        }
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        match (self.image_file.is_empty(), self.example.is_some()) {
+            (true, false) => {
+                return Err("an image file (or `example`) shall be given".to_string());
+            }
+            (false, true) => {
+                return Err("`example` cannot be used with an image file".to_string());
+            }
+            _ => (),
+        }
+
+        if self.fall_back_to_white && self.fall_back_to_black {
+            return Err(
+                "at most one of `fall_back_to_white` and `fall_back_to_black` can be set"
+                    .to_string(),
+            );
+        }
+
+        if self.color_tolerance > image::MAX_COLOR_TOLERANCE {
+            return Err(format!(
+                "`color_tolerance` shall be at most {}",
+                image::MAX_COLOR_TOLERANCE
+            ));
+        }
+
+        if [
+            self.codel_size.is_some(),
+            self.codel_grid.is_some(),
+            self.detect_grid_offset,
+        ]
+        .into_iter()
+        .filter(|&b| b)
+        .count()
+            > 1
+        {
+            return Err(
+                "at most one of `codel_size`, `codel_grid` and `detect_grid_offset` can be set"
+                    .to_string(),
+            );
+        }
+
+        Ok(())
+    }
+}
+
+/// Options which specify where the input of a program comes from (default: stdin).
+#[derive(clap::Args, Debug, Clone, Default)]
+pub struct InputArgs {
+    /// Reads the input of the program from this file instead of stdin
+    #[arg(long = "input")]
+    pub file: Option<String>,
+
+    /// Uses this string as the input of the program instead of stdin
+    #[arg(long = "stdin-string")]
+    pub string: Option<String>,
+
+    /// Generates the input of the program on demand instead of reading stdin (e.g. `seed=42,numbers=0..=2,count=100`, see the README)
+    #[arg(long = "input-random", value_name = "SPEC")]
+    pub random: Option<RandomSpec>,
+}
+
+impl InputArgs {
+    /// Returns `true` if the input does not come from stdin.
+    pub fn is_given(&self) -> bool {
+        self.file.is_some() || self.string.is_some() || self.random.is_some()
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        let num_sources = [
+            self.file.is_some(),
+            self.string.is_some(),
+            self.random.is_some(),
+        ]
+        .into_iter()
+        .filter(|&b| b)
+        .count();
+        if num_sources > 1 {
+            return Err(
+                "at most one of `input`, `stdin_string` and `input_random` can be set".to_string(),
+            );
+        }
+
+        Ok(())
+    }
+}
+
+/// Options of `run`.
+#[derive(Parser, Debug)]
+#[command(after_help = exit_status::HELP)]
+pub struct Args {
+    #[command(flatten)]
+    pub load: LoadArgs,
+
+    /// Applies a named bundle of limits, each of which can be overridden by its own option
+    #[arg(long, value_name = "PRESET", alias = "limit-preset")]
+    pub limits: Option<LimitPreset>,
+
     /// Terminates the program after this number of iterations
     #[arg(long)]
     pub max_iter: Option<usize>,
 
-    /// Enables debug output (path trace etc.)
+    /// Terminates the program if it neither writes output nor consumes input for this number of seconds
+    #[arg(long)]
+    pub stall_timeout: Option<f64>,
+
+    /// Enables debug output (path trace etc.); each straight part of a slide across white is printed as one line unless given three times (`-vvv`)
+    #[arg(short, long, action = clap::ArgAction::Count)]
+    pub verbose: u8,
+
+    /// Fits the image dump of verbose mode into this number of columns (the terminal width by default)
+    #[arg(long, value_name = "COLUMNS")]
+    pub art_max_width: Option<usize>,
+
+    /// Specifies how the image dump of verbose mode is printed when it is wider than the terminal
+    #[arg(long, value_enum, default_value_t = ArtFit::Scale)]
+    pub art_fit: ArtFit,
+
+    /// Prints the whole image dump to stdout instead of running the program
+    #[arg(long)]
+    pub dump_image: bool,
+
+    /// Runs at most this number of steps per second (e.g. for demonstrations with `--verbose`), or as fast as possible with `max`
+    #[arg(long, value_name = "N", default_value_t = Speed::Max)]
+    pub speed: Speed,
+
+    /// Prints the path trace (as in verbose mode) only after this number of iterations
+    #[arg(long, value_name = "N")]
+    pub trace_after: Option<usize>,
+
+    /// Keeps the program output (stdout) and the path trace (stderr) in order, with each line of the output prefixed with `| ` (the output is thus altered)
+    #[arg(long, value_enum, default_value_t = SyncOutput::Auto)]
+    pub sync_output: SyncOutput,
+
+    /// Logs every input consumption and output emission with its step number to this file (stderr if omitted or `-`)
+    #[arg(long, value_name = "FILE", num_args = 0..=1, default_missing_value = "-")]
+    pub io_trace: Option<String>,
+
+    /// Records which step wrote each part of the output and prints the index to stderr after the run
+    #[arg(long)]
+    pub attribute_output: bool,
+
+    /// Prints which step wrote the output byte at this offset (counted from 0), with the path around it, to stderr after the run
+    #[arg(long, value_name = "N")]
+    pub explain_output_byte: Option<usize>,
+
+    /// Checks the assertions in this file (e.g. `program.asserts.toml`) while running the program
+    #[arg(long, value_name = "FILE")]
+    pub assertions: Option<String>,
+
+    /// Compares the output with this file after the run, and fails with the first difference if they differ
+    #[arg(long, value_name = "FILE")]
+    pub expect_output: Option<String>,
+
+    /// Encoding of the file of `--expect-output`, which is transcoded to UTF-8 before the comparison
+    #[arg(long, value_enum, default_value_t = ExpectEncoding::Utf8)]
+    pub expect_encoding: ExpectEncoding,
+
+    /// Compares the output with `--expect-output` as it is written, in constant memory, and at the first difference stops the program or runs it to the end (without the hints about encodings)
+    #[arg(long, value_enum, value_name = "ON_MISMATCH", num_args = 0..=1, default_missing_value = "stop", requires = "expect_output")]
+    pub expect_streaming: Option<OnMismatch>,
+
+    /// Enlarges the image by this factor when printing it
+    #[arg(long, default_value_t = 1)]
+    pub scale: usize,
+
+    /// Writes the image and the executed path to this file as SVG after the run
+    #[arg(long, value_name = "FILE")]
+    pub svg: Option<String>,
+
+    /// Writes a self-contained HTML report of the run (program, statistics, path, I/O trace and path trace) to this file
+    #[arg(long, value_name = "FILE")]
+    pub report: Option<String>,
+
+    /// Writes the output of the program to this file instead of stdout
+    #[arg(long, value_name = "FILE")]
+    pub output: Option<String>,
+
+    /// Writes a checkpoint to `--checkpoint-file` every this number of steps, from which the run can be resumed with `--resume`
+    #[arg(long, value_name = "N")]
+    pub checkpoint_every: Option<usize>,
+
+    /// File to which `--checkpoint-every` writes checkpoints (replaced atomically)
+    #[arg(long, value_name = "FILE")]
+    pub checkpoint_file: Option<String>,
+
+    /// Resumes the run from this checkpoint, with the same program, options and input (the consumed input is skipped and the file of `--output` is truncated to the checkpoint)
+    #[arg(long, value_name = "FILE")]
+    pub resume: Option<String>,
+
+    /// Size of a codel in pixels in `--svg` (and the path of `--report`)
+    #[arg(long, value_name = "N", default_value_t = 20)]
+    pub svg_scale: usize,
+
+    /// Omits the executed path and the command labels from `--svg` (and `--report`)
+    #[arg(long)]
+    pub svg_no_path: bool,
+
+    /// Maximum number of command labels in `--svg` (and `--report`)
+    #[arg(long, value_name = "N", default_value_t = 100)]
+    pub svg_max_labels: usize,
+
+    /// Shows a live status line on stderr, also while the image is loaded (ignored in verbose mode or when stderr is not a terminal)
+    #[arg(long)]
+    pub progress: bool,
+
+    /// Enables an experimental dialect extension (can be given multiple times)
+    #[arg(long, value_enum, value_name = "EXTENSION")]
+    pub extension: Vec<Extension>,
+
+    /// Specifies what happens when the interpreter moves off an edge
+    #[arg(long, value_enum, default_value_t = Topology::Bounded)]
+    pub topology: Topology,
+
+    /// Chooses the codel from which the interpreter leaves a block (`entry-nearest` is an experimental dialect, see `src/corner.rs`)
+    #[arg(long, value_enum, value_name = "STRATEGY", default_value_t = CornerStrategyKind::Standard)]
+    pub corner_strategy: CornerStrategyKind,
+
+    /// Specifies what `in(number)` does with an integer out of the range of the stack values (the spec ignores the command)
+    #[arg(long, value_enum, value_name = "POLICY", default_value_t = NumberOverflow::Ignore)]
+    pub number_overflow: NumberOverflow,
+
+    /// Rings the bell and sends a terminal notification when a run lasts longer than this number of seconds (ignored in quiet mode or when stderr is not a terminal)
+    #[arg(long, value_name = "SECONDS", num_args = 0..=1, default_missing_value = "10")]
+    pub notify: Option<f64>,
+
+    /// Starts at the first non-black codel in reading order when the top-left codel is black
+    #[arg(long)]
+    pub lenient_start: bool,
+
+    /// Runs an image without any colored block (only white, black or unknown colors) with a warning instead of failing
+    #[arg(long)]
+    pub allow_empty: bool,
+
+    /// Fails the run (or `--check`) if any warning is raised
+    #[arg(long)]
+    pub deny_warnings: bool,
+
+    /// Warns about suspicious operations while running, e.g. a `Pointer` or `Switch` popping a value larger than `--large-operand-threshold` in absolute value, or every `Push` pushing a multiple of a square (a sign of a wrong codel size)
+    #[arg(long)]
+    pub check_runtime: bool,
+
+    /// Makes `--check-runtime` warn about an operand of `Pointer` or `Switch` larger than this in absolute value
+    #[arg(long, value_name = "N", default_value_t = command::DEFAULT_LARGE_OPERAND_THRESHOLD)]
+    pub large_operand_threshold: usize,
+
+    /// Lets an observer of an embedding program skip, replace or alter the commands (no observer is installed on the command line)
+    #[arg(long)]
+    pub allow_interventions: bool,
+
+    /// Prints the statistics of the run to stderr, including the approximate peak memory (with the `resource-stats` feature on Linux)
+    #[arg(long)]
+    pub stats: bool,
+
+    /// Writes the counters of the run (e.g. steps, bytes and duration) to this file in the OpenMetrics text format when the run ends, even if it fails
+    #[arg(long, value_name = "FILE")]
+    pub metrics_file: Option<String>,
+
+    /// Suppresses the summary printed to stderr when the program halts without printing anything
     #[arg(short, long)]
-    pub verbose: bool,
+    pub quiet: bool,
+
+    #[command(flatten)]
+    pub input: InputArgs,
+
+    /// Makes `in(number)` ignore a word longer than this number of characters, skipping the rest of it without buffering
+    #[arg(long, value_name = "CHARS", default_value_t = stdin::DEFAULT_MAX_TOKEN_LEN)]
+    pub max_token_length: usize,
+
+    /// Ends the input after this number of bytes, as if it ended there, e.g. to stop a program reading an endless input (`--input /dev/zero` or `--input-random` without `count`) at EOF
+    #[arg(long, value_name = "N")]
+    pub max_input_bytes: Option<usize>,
+
+    /// Reads CRLF and a lone CR of the input as LF with `lf` (their number is shown by `--stats` either way)
+    #[arg(long, value_enum, value_name = "MODE", default_value_t = InputNewlines::Raw)]
+    pub input_newlines: InputNewlines,
+
+    /// Writes the input read by the program to this file when the run ends, even if it fails, so that an interactive session can be replayed with `--input`
+    #[arg(long, value_name = "FILE")]
+    pub record_input: Option<String>,
+
+    /// Re-runs the program every time the file changes, with the same input (empty if neither `--input` nor `--stdin-string` is given)
+    #[arg(long)]
+    pub watch: bool,
+
+    /// Kills a run of `--watch` after this number of seconds
+    #[arg(long, default_value_t = 10.0)]
+    pub watch_timeout: f64,
+
+    /// Lints the program statically instead of running it
+    #[arg(long)]
+    pub check: bool,
+
+    /// Lists the entries declared in the manifest (see `--manifest`) instead of running the program
+    #[arg(long)]
+    pub list_entries: bool,
+
+    /// Lists the example programs embedded in the binary (see `--example`)
+    #[arg(long)]
+    pub list_examples: bool,
+
+    /// Checks the built-in colors, command table and rotations of DP and CC against the spec instead of running a program, e.g. for a locally patched build
+    #[arg(long)]
+    pub self_test: bool,
+
+    /// Prints what this interpreter does on each point which the spec leaves open, under the options given (e.g. `--number-overflow`), instead of running a program
+    #[arg(long)]
+    pub semantics: bool,
+
+    /// Writes a copy of the program of `--example` into this directory instead of running it
+    #[arg(long, value_name = "DIR", requires = "example")]
+    pub extract: Option<PathBuf>,
+
+    /// Reports a `Push` from a block larger than this in `--check`
+    #[arg(long, value_name = "SIZE", default_value_t = lint::DEFAULT_LARGE_PUSH_THRESHOLD)]
+    pub large_push_threshold: usize,
+
+    /// Disables the lint of `Push` from large blocks in `--check`
+    #[arg(long)]
+    pub allow_large_push: bool,
+
+    /// Reports the start block as the source of a `Push` if it is larger than this, with its bounding box and the 1-codel bridges to cut, in `--check` and on the first `Push` with `--check-runtime`
+    #[arg(long, value_name = "SIZE", default_value_t = lint::DEFAULT_START_BLOCK_THRESHOLD)]
+    pub start_block_threshold: usize,
+
+    /// Disables the report of a large start block (see `--start-block-threshold`)
+    #[arg(long)]
+    pub allow_large_start_block: bool,
+
+    /// Chooses the background block, which `--check` reports as the source of a `Push` whatever its size and `--ignore-background` excludes
+    #[arg(long, value_enum, default_value_t = Background::Auto)]
+    pub background: Background,
+
+    /// Excludes the background block (see `--background`) from the lints of `--check`, the visited blocks of `--report` and the metrics of `--golf-score`, reporting it as excluded
+    #[arg(long)]
+    pub ignore_background: bool,
+
+    /// Prints the size, the number of blocks and the background block of the program instead of running it
+    #[arg(long)]
+    pub image_info: bool,
+
+    /// Reports in `--check` which characters and numbers the program can print, and the output commands whose value is not known statically
+    #[arg(long)]
+    pub output_analysis: bool,
+
+    /// Writes the program in the PietDev save format to this file instead of running it (same as `export`)
+    #[arg(long)]
+    pub export_pietdev: Option<String>,
+
+    /// Prints the size metrics and the golf score of the program instead of running it (same as `golf`)
+    #[arg(long)]
+    pub golf_score: bool,
+
+    /// Specifies the formula of the golf score
+    #[arg(long, value_enum, default_value_t = GolfRules::Codels)]
+    pub golf_rules: GolfRules,
+
+    /// Prints machine-readable output in JSON (for `--golf-score`, `--output-analysis`, `--print-config` and `--semantics`)
+    #[arg(long)]
+    pub json: bool,
+
+    /// Prints the effective configuration (each setting with where it comes from) to stderr before running (`run`), or to stdout instead of running (`only`)
+    #[arg(long, value_enum, value_name = "WHEN", num_args = 0..=1, default_missing_value = "run")]
+    pub print_config: Option<PrintConfig>,
+
+    /// Runs the program with both this interpreter and another interpreter binary and compares their outputs (same as `compare`)
+    #[arg(long)]
+    pub compare_with: Option<String>,
+
+    /// Kills either side of `--compare-with` after this number of seconds
+    #[arg(long, default_value_t = 10)]
+    pub compare_timeout: u64,
 }
 
 impl Args {
+    /// Returns the limits of the preset overridden by the individual options.
+    pub fn limits(&self) -> Limits {
+        Limits::new(self.limits, self.max_iter, self.stall_timeout)
+    }
+
+    /// Returns the options of a run which do not depend on the command line, with which an embedding program would run the same way (see [`PietRunner`]).
+    pub fn piet_runner_builder(&self) -> PietRunnerBuilder {
+        let mut builder = PietRunner::builder()
+            .load_options(self.load.load_options())
+            .limits(self.limits())
+            .exec(ExecConfig {
+                corner_strategy: self.corner_strategy.strategy(),
+                number_overflow: self.number_overflow,
+                ..ExecConfig::new(&self.extension)
+            })
+            .verbose(self.verbose != 0)
+            .lenient_start(self.lenient_start)
+            .check_runtime(self.check_runtime)
+            .large_operand_threshold(self.large_operand_threshold)
+            .start_block_threshold(
+                (!self.allow_large_start_block).then_some(self.start_block_threshold),
+            )
+            .input_newlines(self.input_newlines);
+        if let Some(max_input_bytes) = self.max_input_bytes {
+            builder = builder.max_input_bytes(max_input_bytes);
+        }
+        builder
+    }
+
+    /// [`Self::piet_runner_builder()`] with what only the command line adds.
+    pub fn runner_config(&self) -> RunnerConfig<'static> {
+        let config = self.piet_runner_builder().runner_config();
+        RunnerConfig {
+            trace: TraceOptions {
+                print_after: self.trace_after.or(config.trace.print_after),
+                record: self.svg.is_some() || self.report.is_some(),
+                per_codel_slides: self.verbose >= 3,
+            },
+            allow_interventions: self.allow_interventions,
+            record_input: self.record_input.is_some(),
+            ..config
+        }
+    }
+
     pub fn validate(&self) -> Result<(), String> {
-        if self.fall_back_to_white && self.fall_back_to_black {
+        //nothing is loaded
+        if self.list_examples || self.self_test || self.semantics {
+            return Ok(());
+        }
+        self.load.validate()?;
+        self.input.validate()?;
+
+        if self.scale == 0 {
+            return Err("`scale` shall be a positive integer".to_string());
+        }
+
+        if self.speed == Speed::StepsPerSecond(0) {
+            return Err("`speed` shall be a positive integer or `max`".to_string());
+        }
+
+        if self.max_token_length == 0 {
+            return Err("`max_token_length` shall be a positive integer".to_string());
+        }
+
+        if self.art_max_width == Some(0) {
+            return Err("`art_max_width` shall be a positive integer".to_string());
+        }
+
+        if self.svg_scale == 0 {
+            return Err("`svg_scale` shall be a positive integer".to_string());
+        }
+
+        self.limits().validate()?;
+
+        if self.notify.is_some_and(|t| !t.is_finite() || (t < 0.0)) {
+            return Err("`notify` shall be a non-negative number".to_string());
+        }
+
+        if !self.watch_timeout.is_finite() || (self.watch_timeout <= 0.0) {
+            return Err("`watch_timeout` shall be a positive number".to_string());
+        }
+
+        if self.watch
+            && (self.export_pietdev.is_some()
+                || self.golf_score
+                || self.compare_with.is_some()
+                || self.check)
+        {
             return Err(
-                "at most one of `fall_back_to_white` and `fall_back_to_black` can be set"
+                "`watch` cannot be used with `export_pietdev`, `golf_score`, `compare_with` or `check`"
                     .to_string(),
             );
         }
 
+        if self.checkpoint_every.is_some() != self.checkpoint_file.is_some() {
+            return Err(
+                "`checkpoint_every` and `checkpoint_file` shall be given together".to_string(),
+            );
+        }
+        if self.checkpoint_every == Some(0) {
+            return Err("`checkpoint_every` shall be a positive integer".to_string());
+        }
+
+        if self.watch && self.resume.is_some() {
+            return Err("`watch` cannot be used with `resume`".to_string());
+        }
+
+        if self.compare_with.is_some() && !self.input.is_given() {
+            return Err(
+                "`compare_with` requires `input` or `stdin_string` as the input is passed to both interpreters"
+                    .to_string(),
+            );
+        }
+
+        //The other interpreter would run the whole image.
+        if self.compare_with.is_some() && self.load.entry.is_some() {
+            return Err("`compare_with` cannot be used with `entry`".to_string());
+        }
+
+        //Both need a file: to watch, or to pass to the other interpreter.
+        if (self.watch || self.compare_with.is_some()) && self.load.example.is_some() {
+            return Err("`watch` and `compare_with` cannot be used with `example`".to_string());
+        }
+
+        Ok(())
+    }
+}
+
+/// Options of `golf`.
+#[derive(clap::Args, Debug)]
+#[command(after_help = exit_status::HELP)]
+pub struct GolfArgs {
+    #[command(flatten)]
+    pub load: LoadArgs,
+
+    /// Specifies the formula of the golf score
+    #[arg(long, value_enum, default_value_t = GolfRules::Codels)]
+    pub rules: GolfRules,
+
+    /// Chooses the background block excluded by `--ignore-background`
+    #[arg(long, value_enum, default_value_t = Background::Auto)]
+    pub background: Background,
+
+    /// Excludes the background block from the metrics (but not from the total number of codels), reporting it as excluded
+    #[arg(long)]
+    pub ignore_background: bool,
+
+    /// Prints machine-readable output in JSON
+    #[arg(long)]
+    pub json: bool,
+}
+
+/// Options of `export`.
+#[derive(clap::Args, Debug)]
+#[command(after_help = exit_status::HELP)]
+pub struct ExportArgs {
+    #[command(flatten)]
+    pub load: LoadArgs,
+
+    /// Output file
+    #[arg(short, long)]
+    pub output: String,
+}
+
+/// Options of `compare`.
+#[derive(clap::Args, Debug)]
+#[command(after_help = exit_status::HELP)]
+pub struct CompareArgs {
+    #[command(flatten)]
+    pub load: LoadArgs,
+
+    /// The other interpreter binary, which is invoked as `<WITH> <IMAGE_FILE>`
+    #[arg(long)]
+    pub with: String,
+
+    /// Kills either side after this number of seconds
+    #[arg(long, default_value_t = 10)]
+    pub timeout: u64,
+
+    /// Terminates the program after this number of iterations (this interpreter only)
+    #[arg(long)]
+    pub max_iter: Option<usize>,
+
+    /// Starts at the first non-black codel in reading order when the top-left codel is black (this interpreter only)
+    #[arg(long)]
+    pub lenient_start: bool,
+
+    #[command(flatten)]
+    pub input: InputArgs,
+}
+
+impl CompareArgs {
+    pub fn validate(&self) -> Result<(), String> {
+        self.load.validate()?;
+        self.input.validate()?;
+
+        if !self.input.is_given() {
+            return Err(
+                "one of `input`, `stdin_string` and `input_random` is required as the input is passed to both interpreters"
+                    .to_string(),
+            );
+        }
+
+        //The other interpreter would run the whole image.
+        if self.load.entry.is_some() {
+            return Err("`entry` cannot be used with `compare`".to_string());
+        }
+
+        //The other interpreter needs a file.
+        if self.load.example.is_some() {
+            return Err("`example` cannot be used with `compare`".to_string());
+        }
+
+        Ok(())
+    }
+}
+
+/// Options of `trace-diff`.
+#[derive(clap::Args, Debug)]
+#[command(after_help = exit_status::HELP)]
+pub struct TraceDiffArgs {
+    /// First trace file
+    #[arg()]
+    pub a: String,
+
+    /// Second trace file
+    #[arg()]
+    pub b: String,
+}
+
+/// Options of `version-report`.
+#[derive(clap::Args, Debug)]
+#[command(after_help = exit_status::HELP)]
+pub struct VersionReportArgs {
+    #[command(flatten)]
+    pub semantics: SemanticsArgs,
+
+    /// Prints the field-by-field differences from the semantics of these options (e.g. `"--topology torus"`, or `spec` for the defaults) to those of the other options instead of the report
+    #[arg(long, value_name = "OPTIONS", allow_hyphen_values = true, value_parser = SemanticsArgs::parse_options)]
+    pub diff_with: Option<SemanticsArgs>,
+}
+
+/// Options which change the semantics (see [`SemanticsDescriptor`]).
+#[derive(clap::Args, Debug, Clone, Default, PartialEq, Eq)]
+pub struct SemanticsArgs {
+    /// Enables an experimental dialect extension (can be given multiple times)
+    #[arg(long, value_enum, value_name = "EXTENSION")]
+    pub extension: Vec<Extension>,
+
+    /// Specifies what happens when the interpreter moves off an edge
+    #[arg(long, value_enum, default_value_t = Topology::Bounded)]
+    pub topology: Topology,
+
+    /// Chooses the codel from which the interpreter leaves a block (`entry-nearest` is an experimental dialect, see `src/corner.rs`)
+    #[arg(long, value_enum, value_name = "STRATEGY", default_value_t = CornerStrategyKind::Standard)]
+    pub corner_strategy: CornerStrategyKind,
+
+    /// Specifies what `in(number)` does with an integer out of the range of the stack values (the spec ignores the command)
+    #[arg(long, value_enum, value_name = "POLICY", default_value_t = NumberOverflow::Ignore)]
+    pub number_overflow: NumberOverflow,
+}
+
+/// Parser of the options of `--diff-with`.
+#[derive(Parser)]
+#[command(no_binary_name = true)]
+struct SemanticsCli {
+    #[command(flatten)]
+    semantics: SemanticsArgs,
+}
+
+impl SemanticsArgs {
+    /// Parses options separated by whitespace, or `spec` for the defaults (i.e. the semantics of the spec).
+    pub fn parse_options(s: &str) -> Result<Self, String> {
+        if s.trim() == "spec" {
+            return Ok(Self::default());
+        }
+        SemanticsCli::try_parse_from(s.split_whitespace())
+            .map(|cli| cli.semantics)
+            .map_err(|e| {
+                let rendered = e.render().to_string();
+                let first_line = rendered.lines().next().unwrap_or_default();
+                first_line.trim_start_matches("error: ").to_string()
+            })
+    }
+
+    /// Inverse of [`Self::parse_options()`], with the options in a fixed order.
+    pub fn to_options(&self) -> String {
+        let mut v = vec![];
+        if self.topology != Topology::default() {
+            v.push(format!("--topology {}", value_name(&self.topology)));
+        }
+        for e in &self.extension {
+            v.push(format!("--extension {}", value_name(e)));
+        }
+        if self.corner_strategy != CornerStrategyKind::default() {
+            v.push(format!(
+                "--corner-strategy {}",
+                value_name(&self.corner_strategy)
+            ));
+        }
+        if self.number_overflow != NumberOverflow::default() {
+            v.push(format!(
+                "--number-overflow {}",
+                value_name(&self.number_overflow)
+            ));
+        }
+        if v.is_empty() {
+            "spec".to_string()
+        } else {
+            v.join(" ")
+        }
+    }
+
+    pub fn descriptor(&self) -> SemanticsDescriptor {
+        let config = ExecConfig {
+            corner_strategy: self.corner_strategy.strategy(),
+            number_overflow: self.number_overflow,
+            ..ExecConfig::new(&self.extension)
+        };
+        SemanticsDescriptor::new(&config, self.topology)
+    }
+}
+
+fn value_name(v: &impl ValueEnum) -> String {
+    v.to_possible_value().unwrap().get_name().to_string()
+}
+
+/// Options of `conformance`.
+#[derive(clap::Args, Debug)]
+#[command(after_help = exit_status::HELP)]
+pub struct ConformanceArgs {
+    /// Runs the cases listed in `--cases` and writes their test vectors to this directory
+    #[arg(long, value_name = "DIR")]
+    pub export_conformance: Option<String>,
+
+    /// Lists the cases to export, one `<NAME> <IMAGE_FILE> [<INPUT_FILE>]` per line with the paths relative to the list
+    #[arg(long, value_name = "FILE")]
+    pub cases: Option<String>,
+
+    /// Runs the test vectors in this directory and reports which of them pass
+    #[arg(long, value_name = "DIR")]
+    pub verify_conformance: Option<String>,
+
+    /// Terminates each case after this number of iterations (a case shall halt by itself)
+    #[arg(long, default_value_t = 1_000_000)]
+    pub max_iter: usize,
+}
+
+impl ConformanceArgs {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.export_conformance.is_some() == self.verify_conformance.is_some() {
+            return Err(
+                "exactly one of `export_conformance` and `verify_conformance` shall be given"
+                    .to_string(),
+            );
+        }
+        if self.export_conformance.is_some() != self.cases.is_some() {
+            return Err("`cases` shall be given with `export_conformance` (only)".to_string());
+        }
         Ok(())
     }
 }
@@ -49,24 +1001,320 @@ mod tests {
     // #[ignore]
     fn test01() {
         let mut args = Args {
-            image_file: String::new(),
-            codel_size: None,
-            fall_back_to_white: false,
-            fall_back_to_black: false,
+            load: LoadArgs {
+                image_file: "img.png".to_string(),
+                format: Format::Auto,
+                codel_size: None,
+                codel_grid: None,
+                detect_grid_offset: false,
+                detect_budget: image::DEFAULT_DETECT_BUDGET_MS,
+                detect_fallback: DetectFallback::Partial,
+                strict_codel_check: false,
+                crop: None,
+                frame: None,
+                fall_back_to_white: false,
+                fall_back_to_black: false,
+                color_tolerance: 0,
+                palette: None,
+                cache_dir: None,
+                max_image_pixels: limits::DEFAULT_MAX_IMAGE_PIXELS,
+                max_image_dimension: limits::DEFAULT_MAX_IMAGE_DIMENSION,
+                max_image_bytes: limits::DEFAULT_MAX_IMAGE_BYTES,
+                entry: None,
+                manifest: None,
+                example: None,
+            },
+            limits: None,
             max_iter: None,
-            verbose: false,
+            stall_timeout: None,
+            verbose: 0,
+            art_max_width: None,
+            art_fit: ArtFit::Scale,
+            dump_image: false,
+            speed: Speed::Max,
+            trace_after: None,
+            sync_output: SyncOutput::Auto,
+            io_trace: None,
+            attribute_output: false,
+            explain_output_byte: None,
+            assertions: None,
+            expect_output: None,
+            expect_encoding: ExpectEncoding::Utf8,
+            expect_streaming: None,
+            scale: 1,
+            svg: None,
+            svg_scale: 20,
+            svg_no_path: false,
+            svg_max_labels: 100,
+            report: None,
+            output: None,
+            checkpoint_every: None,
+            checkpoint_file: None,
+            resume: None,
+            stats: false,
+            metrics_file: None,
+            progress: false,
+            extension: vec![],
+            topology: Topology::Bounded,
+            corner_strategy: CornerStrategyKind::Standard,
+            number_overflow: NumberOverflow::Ignore,
+            notify: None,
+            lenient_start: false,
+            allow_empty: false,
+            deny_warnings: false,
+            check_runtime: false,
+            large_operand_threshold: 1000,
+            allow_interventions: false,
+            quiet: false,
+            input: InputArgs::default(),
+            max_token_length: stdin::DEFAULT_MAX_TOKEN_LEN,
+            max_input_bytes: None,
+            input_newlines: InputNewlines::Raw,
+            record_input: None,
+            watch: false,
+            watch_timeout: 10.0,
+            check: false,
+            list_entries: false,
+            list_examples: false,
+            self_test: false,
+            semantics: false,
+            extract: None,
+            large_push_threshold: 10000,
+            allow_large_push: false,
+            start_block_threshold: 128,
+            allow_large_start_block: false,
+            background: Background::Auto,
+            ignore_background: false,
+            image_info: false,
+            output_analysis: false,
+            export_pietdev: None,
+            golf_score: false,
+            golf_rules: GolfRules::Codels,
+            json: false,
+            print_config: None,
+            compare_with: None,
+            compare_timeout: 10,
         };
         assert!(args.validate().is_ok());
 
-        args.fall_back_to_white = true;
+        //either an image file or an example
+        args.load.example = Some(Example::Hello);
+        assert!(args.validate().is_err());
+        args.load.image_file = String::new();
+        assert!(args.validate().is_ok());
+        args.load.example = None;
+        assert!(args.validate().is_err());
+        args.list_examples = true;
         assert!(args.validate().is_ok());
+        args.list_examples = false;
+        args.self_test = true;
+        assert!(args.validate().is_ok());
+        args.self_test = false;
+        args.load.image_file = "img.png".to_string();
 
-        args.fall_back_to_white = false;
-        args.fall_back_to_black = true;
+        args.load.fall_back_to_white = true;
         assert!(args.validate().is_ok());
 
-        args.fall_back_to_white = true;
-        args.fall_back_to_black = true;
+        args.load.fall_back_to_white = false;
+        args.load.fall_back_to_black = true;
+        assert!(args.validate().is_ok());
+
+        args.load.fall_back_to_white = true;
+        args.load.fall_back_to_black = true;
+        assert!(args.validate().is_err());
+        args.load.fall_back_to_black = false;
+
+        args.compare_with = Some("npiet".to_string());
+        assert!(args.validate().is_err());
+        args.input.string = Some(String::new());
+        assert!(args.validate().is_ok());
+        args.input.file = Some("input.txt".to_string());
+        assert!(args.validate().is_err());
+        args.input.file = None;
+        args.input.random = Some("bytes,count=1".parse().unwrap());
+        assert!(args.validate().is_err());
+        args.input.string = None;
+        assert!(args.validate().is_ok());
+        args.input.random = None;
+        args.input.string = Some(String::new());
+
+        args.stall_timeout = Some(-1.0);
+        assert!(args.validate().is_err());
+        args.stall_timeout = Some(0.5);
+        assert!(args.validate().is_ok());
+        args.limits = Some(LimitPreset::Playground);
+        assert_eq!(Some(0.5), args.limits().stall_timeout);
+        assert_eq!(Some(1_000_000), args.limits().max_iter);
+        args.stall_timeout = Some(-1.0);
+        assert!(args.validate().is_err());
+        args.stall_timeout = None;
+        assert!(args.validate().is_ok());
+
+        args.watch = true;
+        assert!(args.validate().is_err());
+        args.compare_with = None;
+        assert!(args.validate().is_ok());
+        args.check = true;
+        assert!(args.validate().is_err());
+        args.check = false;
+        args.golf_score = true;
+        assert!(args.validate().is_err());
+        args.golf_score = false;
+        args.watch_timeout = 0.0;
+        assert!(args.validate().is_err());
+        args.watch_timeout = 1.0;
+
+        args.notify = Some(f64::NAN);
+        assert!(args.validate().is_err());
+        args.notify = Some(0.0);
+        assert!(args.validate().is_ok());
+
+        args.speed = Speed::StepsPerSecond(0);
         assert!(args.validate().is_err());
+        args.speed = Speed::StepsPerSecond(10);
+        assert!(args.validate().is_ok());
+
+        args.load.fall_back_to_white = false;
+        args.svg_scale = 0;
+        assert!(args.validate().is_err());
+        args.svg_scale = 1;
+        assert!(args.validate().is_ok());
+
+        args.scale = 0;
+        assert!(args.validate().is_err());
+    }
+
+    #[test]
+    fn test_semantics_options() {
+        assert_eq!(
+            Ok(SemanticsArgs::default()),
+            SemanticsArgs::parse_options("spec")
+        );
+        assert_eq!(
+            Ok(SemanticsArgs::default()),
+            SemanticsArgs::parse_options("")
+        );
+        let args = SemanticsArgs {
+            extension: vec![Extension::SecondStack],
+            topology: Topology::Torus,
+            corner_strategy: CornerStrategyKind::Standard,
+            number_overflow: NumberOverflow::Saturate,
+        };
+        assert_eq!(
+            "--topology torus --extension second-stack --number-overflow saturate",
+            args.to_options()
+        );
+        assert_eq!(
+            Ok(args.clone()),
+            SemanticsArgs::parse_options(&args.to_options())
+        );
+        assert_eq!("spec", SemanticsArgs::default().to_options());
+        assert!(SemanticsArgs::parse_options("--topology sphere").is_err());
+    }
+
+    #[test]
+    fn test_piet_runner_builder() {
+        //The defaults of the command line are those of the builder.
+        let args = Args::parse_from(["piet_programming_language", "img.png"]);
+        assert_eq!(PietRunner::builder(), args.piet_runner_builder());
+
+        let args = Args::parse_from([
+            "piet_programming_language",
+            "img.png",
+            "--codel-size",
+            "2",
+            "--max-iter",
+            "10000",
+            "--fall-back-to-white",
+            "--allow-large-start-block",
+            "--max-image-pixels",
+            "100",
+        ]);
+        assert_eq!(
+            PietRunner::builder()
+                .codel_size(2)
+                .max_iter(10_000)
+                .fallback(Codel::White)
+                .image_limits(ImageLimits {
+                    max_pixels: 100,
+                    ..ImageLimits::default()
+                })
+                .start_block_threshold(None),
+            args.piet_runner_builder()
+        );
+    }
+
+    #[test]
+    fn test_default_subcommand() {
+        let parse = |v: &[&str]| {
+            Cli::parse_with_default_subcommand(
+                ["piet_programming_language"]
+                    .iter()
+                    .chain(v)
+                    .map(|s| s.to_string()),
+            )
+        };
+
+        let cli = parse(&["img.png", "--verbose"]);
+        assert!(
+            matches!(&cli.command, Subcommand::Run(args) if (args.verbose == 1) && (args.load.image_file == "img.png"))
+        );
+        let cli = parse(&["--verbose", "img.png"]);
+        assert!(
+            matches!(&cli.command, Subcommand::Run(args) if (args.verbose == 1) && (args.load.image_file == "img.png"))
+        );
+        let cli = parse(&["run", "img.png", "--verbose"]);
+        assert!(
+            matches!(&cli.command, Subcommand::Run(args) if (args.verbose == 1) && (args.load.image_file == "img.png"))
+        );
+        let cli = parse(&["img.png", "-vvv"]);
+        assert!(
+            matches!(&cli.command, Subcommand::Run(args) if (args.verbose == 3) && args.runner_config().trace.per_codel_slides)
+        );
+
+        let cli = parse(&["img.png", "--speed", "10"]);
+        assert!(
+            matches!(&cli.command, Subcommand::Run(args) if args.speed == Speed::StepsPerSecond(10))
+        );
+        let cli = parse(&["img.png"]);
+        assert!(matches!(&cli.command, Subcommand::Run(args) if args.speed == Speed::Max));
+        assert!(matches!(
+            &cli.command,
+            Subcommand::Run(args) if args.load.codel_layout() == CodelLayout::Detect {
+                search_offset: false,
+                budget: Some(Duration::from_millis(image::DEFAULT_DETECT_BUDGET_MS)),
+                fallback: DetectFallback::Partial,
+            }
+        ));
+        let cli = parse(&[
+            "img.png",
+            "--detect-budget",
+            "0",
+            "--detect-fallback",
+            "error",
+        ]);
+        assert!(matches!(
+            &cli.command,
+            Subcommand::Run(args) if args.load.codel_layout() == CodelLayout::Detect {
+                search_offset: false,
+                budget: None,
+                fallback: DetectFallback::Error,
+            }
+        ));
+
+        let cli = parse(&["golf", "img.png", "--rules", "blocks"]);
+        assert!(matches!(&cli.command, Subcommand::Golf(args) if args.rules == GolfRules::Blocks));
+
+        let cli = parse(&["compare", "img.png", "--with", "npiet"]);
+        assert!(cli.validate().is_err());
+        let cli = parse(&[
+            "compare",
+            "img.png",
+            "--with",
+            "npiet",
+            "--stdin-string",
+            "",
+        ]);
+        assert!(cli.validate().is_ok());
     }
 }
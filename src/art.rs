@@ -0,0 +1,213 @@
+/*!
+Fitting the image dump of verbose mode into the terminal (`--art-fit`, `--art-max-width`).
+
+The dump (see `impl Display for Image`) is two columns per codel plus the row numbers, so a wide program wraps every row on a narrow terminal.
+When it does not fit, the dump is either replaced with a downsampled preview or clipped to its left part.
+*/
+
+use rustc_hash::FxHashMap;
+
+use super::codel::Codel;
+use super::coord;
+use super::image::Image;
+
+/// How an image dump wider than the terminal is printed.
+#[derive(clap::ValueEnum, Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum ArtFit {
+    /// Prints a preview where each cell is the majority color of a square of codels
+    #[default]
+    Scale,
+    /// Prints only the leftmost columns which fit
+    Clip,
+    /// Prints the whole dump anyway
+    Off,
+}
+
+/// Returns the width of the terminal, if known.
+///
+/// `COLUMNS` is used if it is exported, and otherwise `stty size` is asked about the controlling terminal (only on Unix).
+pub fn terminal_width() -> Option<usize> {
+    if let Some(w) = std::env::var("COLUMNS").ok().and_then(|s| s.parse().ok()) {
+        return Some(w).filter(|w| *w > 0);
+    }
+    #[cfg(unix)]
+    {
+        let tty = std::fs::File::open("/dev/tty").ok()?;
+        let output = std::process::Command::new("stty")
+            .arg("size")
+            .stdin(tty)
+            .stderr(std::process::Stdio::null())
+            .output()
+            .ok()?;
+        //`<rows> <columns>`
+        String::from_utf8(output.stdout)
+            .ok()?
+            .split_whitespace()
+            .nth(1)?
+            .parse()
+            .ok()
+            .filter(|w| *w > 0)
+    }
+    #[cfg(not(unix))]
+    None
+}
+
+/// Returns the number of columns of the dump of an image `height` codels high and `width` codels wide.
+fn dump_width(height: usize, width: usize) -> usize {
+    let label_width = coord::last(height).unwrap_or(0).to_string().len().max(2);
+    label_width + 1 + 2 * width
+}
+
+/// Returns the smallest factor `n` such that the preview of `n x n` codels per cell fits in `max_width` columns.
+/// The factor is at least 1 and at most the width of the image (one cell per row), even if that does not fit.
+pub fn scale_factor(height: usize, width: usize, max_width: usize) -> usize {
+    (1..width)
+        .find(|n| dump_width(height.div_ceil(*n), width.div_ceil(*n)) <= max_width)
+        .unwrap_or(width)
+}
+
+/// Returns the number of columns of codels (at least one) which fit in `max_width` columns.
+pub fn clip_width(height: usize, width: usize, max_width: usize) -> usize {
+    let fixed = dump_width(height, 0);
+    (max_width.saturating_sub(fixed) / 2).clamp(1, width)
+}
+
+/// Downsamples `img` so that each codel of the result is the most frequent color in an `n x n` square of `img` (the squares at the right and bottom edges may be smaller).
+/// Ties are broken by the first of the colors in reading order.
+pub fn downsample(img: &Image, n: usize) -> Vec<Vec<Codel>> {
+    (0..img.height().div_ceil(n))
+        .map(|bi| {
+            (0..img.width().div_ceil(n))
+                .map(|bj| {
+                    let mut counts: FxHashMap<Codel, usize> = FxHashMap::default();
+                    let mut order = vec![];
+                    for i in bi * n..((bi + 1) * n).min(img.height()) {
+                        for j in bj * n..((bj + 1) * n).min(img.width()) {
+                            let codel = *img.get_codel_at_unchecked((i, j));
+                            let count = counts.entry(codel).or_insert(0);
+                            if *count == 0 {
+                                order.push(codel);
+                            }
+                            *count += 1;
+                        }
+                    }
+                    //`max_by_key()` returns the last maximum, so the order is reversed
+                    order
+                        .into_iter()
+                        .rev()
+                        .max_by_key(|codel| counts[codel])
+                        .unwrap()
+                })
+                .collect()
+        })
+        .collect()
+}
+
+/// Returns the dump of `img` fitted into `max_width` columns (unlimited if `None`) as selected by `fit`.
+pub fn render(img: &Image, max_width: Option<usize>, fit: ArtFit) -> String {
+    let Some(max_width) = max_width.filter(|w| dump_width(img.height(), img.width()) > *w) else {
+        return img.to_string();
+    };
+    match fit {
+        ArtFit::Off => img.to_string(),
+        ArtFit::Scale => {
+            let n = scale_factor(img.height(), img.width(), max_width);
+            //The row and column numbers are those of the preview.
+            format!(
+                "preview at 1/{} scale (use `--dump-image` for the full image)\n{}",
+                n,
+                Image::from_codel_grid(downsample(img, n))
+            )
+        }
+        ArtFit::Clip => {
+            let w = clip_width(img.height(), img.width(), max_width);
+            let m = (0..img.height())
+                .map(|i| {
+                    (0..w)
+                        .map(|j| *img.get_codel_at_unchecked((i, j)))
+                        .collect()
+                })
+                .collect();
+            format!(
+                "clipped: showing columns 0..{} of {} (use `--dump-image` for the full image)\n{}",
+                w,
+                img.width(),
+                Image::from_codel_grid(m)
+            )
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fit_math() {
+        assert_eq!(3 + 20, dump_width(5, 10));
+        assert_eq!(4 + 20, dump_width(101, 10));
+        //used to underflow
+        assert_eq!(3 + 20, dump_width(0, 10));
+        assert_eq!(38, clip_width(0, 300, 80));
+
+        //300 codels need 603 columns
+        assert_eq!(1, scale_factor(10, 300, 603));
+        assert_eq!(2, scale_factor(10, 300, 602));
+        //38 cells of 8 codels (79 columns)
+        assert_eq!(8, scale_factor(10, 300, 80));
+        //nothing fits
+        assert_eq!(3, scale_factor(1, 3, 2));
+
+        assert_eq!(38, clip_width(10, 300, 80));
+        assert_eq!(300, clip_width(10, 300, 1000));
+        assert_eq!(1, clip_width(10, 300, 2));
+    }
+
+    #[test]
+    fn test_downsample() {
+        let (r, b, w) = (Codel::Red, Codel::Blue, Codel::White);
+        let img = Image::from_codel_grid(vec![
+            vec![r, b, b, w, w],
+            vec![b, b, r, w, r],
+            vec![r, r, w, b, b],
+        ]);
+        assert_eq!(vec![vec![b, w, w], vec![r, w, b]], downsample(&img, 2));
+        //a tie goes to the first color in reading order
+        assert_eq!(vec![vec![r, w], vec![r, b]], {
+            let img =
+                Image::from_codel_grid(vec![vec![r, b, w, r], vec![b, r, r, w], vec![r, b, b, r]]);
+            downsample(&img, 2)
+        });
+        assert_eq!(vec![vec![b]], downsample(&img, 5));
+        assert_eq!(
+            (0..3)
+                .map(|i| (0..5)
+                    .map(|j| *img.get_codel_at((i, j)).unwrap())
+                    .collect::<Vec<_>>())
+                .collect::<Vec<_>>(),
+            downsample(&img, 1)
+        );
+    }
+
+    #[test]
+    fn test_render() {
+        let img = Image::from_codel_grid(vec![vec![Codel::Red; 50]; 3]);
+        //fits
+        assert_eq!(img.to_string(), render(&img, Some(103), ArtFit::Scale));
+        assert_eq!(img.to_string(), render(&img, None, ArtFit::Clip));
+        //does not fit
+        assert_eq!(img.to_string(), render(&img, Some(80), ArtFit::Off));
+        let s = render(&img, Some(80), ArtFit::Scale);
+        assert!(s.starts_with("preview at 1/2 scale "));
+        assert_eq!(
+            Image::from_codel_grid(vec![vec![Codel::Red; 25]; 2]).to_string(),
+            s.split_once('\n').unwrap().1
+        );
+        let s = render(&img, Some(80), ArtFit::Clip);
+        assert!(s.starts_with("clipped: showing columns 0..38 of 50 "));
+        assert_eq!(
+            Image::from_codel_grid(vec![vec![Codel::Red; 38]; 3]).to_string(),
+            s.split_once('\n').unwrap().1
+        );
+    }
+}
@@ -0,0 +1,361 @@
+/*!
+Execution-time assertions (`--assertions`), which check the state of the interpreter at given codels.
+
+Assertions are written in a sidecar file (e.g. `program.asserts.toml`), one per line, as `key = value` pairs separated by commas:
+
+```text
+# comment
+at = [3, 7], stack_top = 72
+at = [5, 2], stack_depth = 0
+at = [9, 9], output_so_far = "Hello"
+```
+
+`at` (required) is the position `[i, j]` of a codel (`i`-th row, `j`-th column, counted in codels from zero), and the other keys are the checks:
+
+- `stack_top`: the top of the stack equals this integer
+- `stack_depth`: the stack has exactly this number of elements
+- `output_so_far`: everything the program has written to stdout so far equals this string (`\n`, `\t`, `\"` and `\\` are recognized)
+
+The checks of a line are evaluated every time the interpreter enters the codel at `at`, and the first violation terminates the run with an error.
+Note that the interpreter moves through a coloured block in a single step, so only the codel through which it enters the block (or the starting codel) is visited.
+*/
+
+use std::error::Error;
+
+use rustc_hash::FxHashMap;
+
+use super::coord;
+use super::error::PietError;
+use super::image::Image;
+use super::interpreter::Interpreter;
+
+/// One check of an assertion.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Check {
+    StackTop(isize),
+    StackDepth(usize),
+    OutputSoFar(String),
+}
+
+impl Check {
+    /// Returns a description of the violation if the check fails.
+    fn evaluate(&self, ip: &Interpreter) -> Option<String> {
+        match self {
+            Check::StackTop(expected) => match ip.stack.last() {
+                Some(top) if top == expected => None,
+                Some(top) => Some(format!(
+                    "expected the stack top to be {} but it is {}",
+                    expected, top
+                )),
+                None => Some(format!(
+                    "expected the stack top to be {} but the stack is empty",
+                    expected
+                )),
+            },
+            Check::StackDepth(expected) => (ip.stack.len() != *expected).then(|| {
+                format!(
+                    "expected the stack depth to be {} but it is {}",
+                    expected,
+                    ip.stack.len()
+                )
+            }),
+            Check::OutputSoFar(expected) => {
+                let output = String::from_utf8_lossy(ip.output_log.as_deref().unwrap_or_default());
+                (output != expected.as_str()).then(|| {
+                    format!(
+                        "expected the output so far to be {:?} but it is {:?}",
+                        expected, output
+                    )
+                })
+            }
+        }
+    }
+}
+
+/// One line of an assertion file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Assertion {
+    /// Line number in the file (1-based), used in error messages.
+    pub line: usize,
+    pub at: (usize, usize),
+    pub checks: Vec<Check>,
+}
+
+/// Splits `s` at the commas which are outside of brackets and strings.
+fn split_top_level(s: &str) -> Vec<&str> {
+    let mut ret = vec![];
+    let mut depth = 0;
+    let mut in_string = false;
+    let mut is_escaped = false;
+    let mut start = 0;
+    for (i, c) in s.char_indices() {
+        match c {
+            _ if is_escaped => is_escaped = false,
+            '\\' if in_string => is_escaped = true,
+            '"' => in_string = !in_string,
+            '[' if !in_string => depth += 1,
+            ']' if !in_string => depth -= 1,
+            ',' if !in_string && (depth == 0) => {
+                ret.push(&s[start..i]);
+                start = i + 1;
+            }
+            _ => (),
+        }
+    }
+    ret.push(&s[start..]);
+    ret
+}
+
+/// Removes the comment (if any) from a line.
+fn strip_comment(s: &str) -> &str {
+    let mut in_string = false;
+    let mut is_escaped = false;
+    for (i, c) in s.char_indices() {
+        match c {
+            _ if is_escaped => is_escaped = false,
+            '\\' if in_string => is_escaped = true,
+            '"' => in_string = !in_string,
+            '#' if !in_string => return &s[..i],
+            _ => (),
+        }
+    }
+    s
+}
+
+fn parse_string(s: &str) -> Option<String> {
+    let s = s.strip_prefix('"')?.strip_suffix('"')?;
+    let mut ret = String::new();
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            ret.push(c);
+            continue;
+        }
+        ret.push(match chars.next()? {
+            'n' => '\n',
+            't' => '\t',
+            '"' => '"',
+            '\\' => '\\',
+            _ => return None,
+        });
+    }
+    Some(ret)
+}
+
+fn parse_position(s: &str) -> Option<(usize, usize)> {
+    let s = s.strip_prefix('[')?.strip_suffix(']')?;
+    let (i, j) = s.split_once(',')?;
+    Some((i.trim().parse().ok()?, j.trim().parse().ok()?))
+}
+
+/// Parses the content of an assertion file.
+pub fn parse(s: &str) -> Result<Vec<Assertion>, Box<dyn Error>> {
+    let mut ret = vec![];
+    for (line, content) in s.lines().enumerate() {
+        let line = line + 1;
+        let content = strip_comment(content).trim();
+        if content.is_empty() {
+            continue;
+        }
+
+        let mut at = None;
+        let mut checks = vec![];
+        for pair in split_top_level(content) {
+            let (key, value) = pair
+                .split_once('=')
+                .map(|(k, v)| (k.trim(), v.trim()))
+                .ok_or(format!(
+                    "line {}: expected `key = value` but found `{}`",
+                    line,
+                    pair.trim()
+                ))?;
+            let invalid_value =
+                || format!("line {}: invalid value of `{}`: `{}`", line, key, value);
+            match key {
+                "at" => at = Some(parse_position(value).ok_or_else(invalid_value)?),
+                "stack_top" => {
+                    checks.push(Check::StackTop(value.parse().map_err(|_| invalid_value())?))
+                }
+                "stack_depth" => checks.push(Check::StackDepth(
+                    value.parse().map_err(|_| invalid_value())?,
+                )),
+                "output_so_far" => checks.push(Check::OutputSoFar(
+                    parse_string(value).ok_or_else(invalid_value)?,
+                )),
+                _ => return Err(format!("line {}: unknown key `{}`", line, key).into()),
+            }
+        }
+
+        let at = at.ok_or(format!("line {}: `at` is missing", line))?;
+        if checks.is_empty() {
+            return Err(format!("line {}: no check is given", line).into());
+        }
+        ret.push(Assertion { line, at, checks });
+    }
+    Ok(ret)
+}
+
+/// Assertions indexed by position.
+#[derive(Debug, Default)]
+pub struct Assertions {
+    m: FxHashMap<(usize, usize), Vec<Assertion>>,
+}
+
+impl Assertions {
+    /// Validates the positions of `assertions` against `img`.
+    pub fn new(assertions: Vec<Assertion>, img: &Image) -> Result<Self, Box<dyn Error>> {
+        let mut m: FxHashMap<_, Vec<_>> = FxHashMap::default();
+        for assertion in assertions {
+            coord::check_pos(
+                &format!("line {}", assertion.line),
+                assertion.at,
+                img.height(),
+                img.width(),
+            )?;
+            m.entry(assertion.at).or_default().push(assertion);
+        }
+        Ok(Self { m })
+    }
+
+    /// Returns `true` if some assertion needs the output of the program.
+    pub fn needs_output(&self) -> bool {
+        self.m
+            .values()
+            .flatten()
+            .flat_map(|a| &a.checks)
+            .any(|c| matches!(c, Check::OutputSoFar(_)))
+    }
+
+    /// Evaluates the assertions at the current position of `ip`.
+    pub fn check(&self, ip: &Interpreter) -> Result<(), PietError> {
+        for assertion in self.m.get(&ip.cur).into_iter().flatten() {
+            if let Some(message) = assertion.checks.iter().find_map(|c| c.evaluate(ip)) {
+                return Err(PietError::AssertionFailed {
+                    line: assertion.line,
+                    pos: ip.cur,
+                    step: ip.num_steps,
+                    message,
+                });
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use super::super::codel::Codel;
+
+    #[test]
+    fn test_parse() {
+        let s = r##"
+# comment
+at = [3, 7], stack_top = -72  # trailing comment
+at=[5,2],stack_depth=0
+at = [9, 9], output_so_far = "Hello, \"#1\"\n"
+"##;
+        let assertions = parse(s).unwrap();
+        assert_eq!(
+            vec![
+                Assertion {
+                    line: 3,
+                    at: (3, 7),
+                    checks: vec![Check::StackTop(-72)],
+                },
+                Assertion {
+                    line: 4,
+                    at: (5, 2),
+                    checks: vec![Check::StackDepth(0)],
+                },
+                Assertion {
+                    line: 5,
+                    at: (9, 9),
+                    checks: vec![Check::OutputSoFar("Hello, \"#1\"\n".to_string())],
+                },
+            ],
+            assertions
+        );
+
+        assert_eq!(
+            "line 1: `at` is missing",
+            parse("stack_top = 1").unwrap_err().to_string()
+        );
+        assert_eq!(
+            "line 2: unknown key `stack`",
+            parse("\nat = [0, 0], stack = 1").unwrap_err().to_string()
+        );
+        assert_eq!(
+            "line 1: invalid value of `at`: `[0]`",
+            parse("at = [0], stack_depth = 1").unwrap_err().to_string()
+        );
+        assert_eq!(
+            "line 1: no check is given",
+            parse("at = [0, 0]").unwrap_err().to_string()
+        );
+        assert!(parse("at = [0, 0], output_so_far = \"a").is_err());
+        assert!(parse("at = [0, 0] stack_top = 1").is_err());
+    }
+
+    #[test]
+    fn test_check() {
+        let img = Image::from_codel_grid(vec![vec![Codel::Red, Codel::Blue]]);
+        let assertions = parse(
+            "at = [0, 1], stack_top = 3, stack_depth = 2\nat = [0, 1], output_so_far = \"ab\"",
+        )
+        .unwrap();
+        let assertions = Assertions::new(assertions, &img).unwrap();
+        assert!(assertions.needs_output());
+
+        let mut ip = Interpreter::new_with_stdin("");
+        ip.output_log = Some(b"ab".to_vec());
+        ip.stack = vec![5, 3];
+        assert_eq!(Ok(()), assertions.check(&ip));
+
+        //no assertion at this position
+        ip.cur = (0, 0);
+        ip.stack = vec![];
+        assert_eq!(Ok(()), assertions.check(&ip));
+
+        ip.cur = (0, 1);
+        ip.num_steps = 4;
+        assert_eq!(
+            Err(PietError::AssertionFailed {
+                line: 1,
+                pos: (0, 1),
+                step: 4,
+                message: "expected the stack top to be 3 but the stack is empty".to_string(),
+            }),
+            assertions.check(&ip)
+        );
+
+        ip.stack = vec![3];
+        let e = assertions.check(&ip).unwrap_err();
+        assert!(e
+            .to_string()
+            .contains("expected the stack depth to be 2 but it is 1"));
+
+        ip.stack = vec![1, 3];
+        ip.output_log = Some(b"a".to_vec());
+        let e = assertions.check(&ip).unwrap_err();
+        assert!(e
+            .to_string()
+            .contains(r#"expected the output so far to be "ab" but it is "a""#));
+
+        let assertions = parse("at = [1, 0], stack_depth = 0").unwrap();
+        assert_eq!(
+            "line 1: the position (1, 0) exceeds the image (1 x 2 codels)",
+            Assertions::new(assertions, &img).unwrap_err().to_string()
+        );
+        for at in [[usize::MAX, 0], [0, usize::MAX]] {
+            let assertions =
+                parse(&format!("\nat = [{}, {}], stack_depth = 0", at[0], at[1])).unwrap();
+            let e = Assertions::new(assertions, &img).unwrap_err();
+            assert!(matches!(
+                e.downcast_ref::<PietError>(),
+                Some(PietError::InvalidCoordinate { input, .. }) if input == "line 2"
+            ));
+        }
+    }
+}
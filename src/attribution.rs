@@ -0,0 +1,220 @@
+/*!
+Attribution of output bytes to the commands which wrote them (`--attribute-output`, `--explain-output-byte`).
+
+Each `out(number)` or `out(char)` which writes something appends an [`OutputSpan`], so the index has one entry per output command rather than per byte.
+To show the path around the command which wrote a given byte, [`TraceWindow`] keeps only the last few steps while running and freezes them once the byte has been written, so that the memory does not grow with the length of the run.
+*/
+
+use std::collections::VecDeque;
+use std::fmt::Write;
+
+use super::command::Command;
+use super::trace::TraceEvent;
+use super::trace_diff::Step;
+
+/// Number of steps shown before and after the step which wrote the explained byte.
+pub const CONTEXT: usize = 5;
+
+/// Output written by one command, which starts at `offset` and extends to the offset of the next span.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OutputSpan {
+    /// Offset of the first byte in the whole output.
+    pub offset: usize,
+    /// Step (counted from 1 as in `--io-trace`) at which the command was executed.
+    pub step: usize,
+    /// Position from which the interpreter moved when executing the command.
+    pub pos: (usize, usize),
+    pub command: Command,
+}
+
+/// Returns the index of the span which contains the byte at `offset`, assuming `offset` is within the output.
+pub fn find(spans: &[OutputSpan], offset: usize) -> Option<usize> {
+    spans
+        .partition_point(|span| span.offset <= offset)
+        .checked_sub(1)
+}
+
+/// The steps around the one which wrote a given output byte.
+pub struct TraceWindow {
+    offset: usize,
+    num_steps: usize,
+    /// The last `CONTEXT + 1` steps, numbered.
+    recent: VecDeque<(usize, Step)>,
+    /// Steps from `CONTEXT` before to `CONTEXT` after the step which wrote the byte, once written.
+    frozen: Option<(usize, Vec<(usize, Step)>)>,
+}
+
+impl TraceWindow {
+    /// Creates a window for the byte at `offset`, which shall receive every event from the start of the run.
+    pub fn new(offset: usize) -> Self {
+        Self {
+            offset,
+            num_steps: 0,
+            recent: VecDeque::with_capacity(CONTEXT + 1),
+            frozen: None,
+        }
+    }
+
+    /// Freezes the window if the byte has been written, where `num_output_bytes` is the size of the output so far.
+    /// This shall be called before each step and at the end of the run.
+    pub fn update(&mut self, num_output_bytes: usize) {
+        if self.frozen.is_none() && (num_output_bytes > self.offset) {
+            if let Some((step, _)) = self.recent.back() {
+                self.frozen = Some((*step, self.recent.iter().cloned().collect()));
+            }
+        }
+    }
+
+    pub fn record(&mut self, event: &TraceEvent) {
+        match *event {
+            TraceEvent::State { pos, dp, cc } => {
+                self.num_steps += 1;
+                let step = Step {
+                    pos,
+                    dp,
+                    cc,
+                    commands: vec![],
+                };
+                if let Some((target, steps)) = self.frozen.as_mut() {
+                    if self.num_steps <= *target + CONTEXT {
+                        steps.push((self.num_steps, step.clone()));
+                    }
+                }
+                if self.recent.len() == CONTEXT + 1 {
+                    self.recent.pop_front();
+                }
+                self.recent.push_back((self.num_steps, step));
+            }
+            TraceEvent::Command(command) => {
+                if let Some((_, step)) = self.recent.back_mut() {
+                    step.commands.push(command);
+                }
+                if let Some((_, steps)) = self.frozen.as_mut() {
+                    if let Some((n, step)) = steps.last_mut() {
+                        if *n == self.num_steps {
+                            step.commands.push(command);
+                        }
+                    }
+                }
+            }
+            TraceEvent::Intervention { .. } => {}
+        }
+    }
+}
+
+/// Returns a human-readable description of the spans.
+pub fn report(spans: &[OutputSpan], num_output_bytes: usize) -> String {
+    let mut s = String::new();
+    for (k, span) in spans.iter().enumerate() {
+        let end = spans
+            .get(k + 1)
+            .map_or(num_output_bytes, |next| next.offset);
+        writeln!(
+            s,
+            "bytes {}..{}: step {} at {:?} ({:?})",
+            span.offset, end, span.step, span.pos, span.command
+        )
+        .unwrap();
+    }
+    s
+}
+
+/// Returns the explanation of the byte at `offset` of `output`, where `window` was created for the same offset.
+pub fn explain(offset: usize, spans: &[OutputSpan], output: &[u8], window: &TraceWindow) -> String {
+    let (Some(byte), Some(k)) = (output.get(offset), find(spans, offset)) else {
+        return format!(
+            "output byte {} was not written (the output has {} bytes)\n",
+            offset,
+            output.len()
+        );
+    };
+    let span = &spans[k];
+    let end = spans.get(k + 1).map_or(output.len(), |next| next.offset);
+    let mut s = String::new();
+    writeln!(
+        s,
+        "output byte {} ({:?}) was written by {:?} at step {} at {:?} (bytes {}..{})",
+        offset, *byte as char, span.command, span.step, span.pos, span.offset, end
+    )
+    .unwrap();
+    if let Some((_, steps)) = &window.frozen {
+        for (n, step) in steps {
+            let marker = if *n == span.step { '>' } else { ' ' };
+            writeln!(s, "{} {:6} {}", marker, n, step.to_line()).unwrap();
+        }
+    }
+    s
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::cc::CC;
+    use super::super::dp::DP;
+    use super::*;
+
+    fn span(offset: usize, step: usize) -> OutputSpan {
+        OutputSpan {
+            offset,
+            step,
+            pos: (0, step),
+            command: Command::OutNumber,
+        }
+    }
+
+    #[test]
+    fn test_find() {
+        let spans = [span(0, 3), span(2, 5), span(5, 9)];
+        assert_eq!(Some(0), find(&spans, 0));
+        assert_eq!(Some(0), find(&spans, 1));
+        assert_eq!(Some(1), find(&spans, 2));
+        assert_eq!(Some(2), find(&spans, 7));
+        assert_eq!(None, find(&[], 0));
+        assert_eq!(
+            "bytes 0..2: step 3 at (0, 3) (OutNumber)\nbytes 2..5: step 5 at (0, 5) (OutNumber)\nbytes 5..8: step 9 at (0, 9) (OutNumber)\n",
+            report(&spans, 8)
+        );
+    }
+
+    #[test]
+    fn test_trace_window() {
+        let state = |j| TraceEvent::State {
+            pos: (0, j),
+            dp: DP::Right,
+            cc: CC::Left,
+        };
+        //one byte is written at each step from the step 3
+        let mut window = TraceWindow::new(10);
+        for n in 1..=30 {
+            window.update(n.max(3) - 3);
+            window.record(&state(n));
+            window.record(&TraceEvent::Command(Command::OutChar));
+        }
+        window.update(28);
+        //written at the step 13
+        let (target, steps) = window.frozen.as_ref().unwrap();
+        assert_eq!(13, *target);
+        assert_eq!(
+            (8..=18).collect::<Vec<_>>(),
+            steps.iter().map(|(n, _)| *n).collect::<Vec<_>>()
+        );
+        assert!(steps
+            .iter()
+            .all(|(_, step)| step.commands == vec![Command::OutChar]));
+
+        //the run ends right after the byte is written
+        let mut window = TraceWindow::new(0);
+        window.record(&state(1));
+        window.update(1);
+        assert_eq!(1, window.frozen.as_ref().unwrap().1.len());
+
+        let spans = [span(0, 1)];
+        assert_eq!(
+            "output byte 0 ('7') was written by OutNumber at step 1 at (0, 1) (bytes 0..2)\n>      1 (0, 1)       DP:Right CC:Left\n",
+            explain(0, &spans, b"7\n", &window)
+        );
+        assert_eq!(
+            "output byte 2 was not written (the output has 2 bytes)\n",
+            explain(2, &spans, b"7\n", &window)
+        );
+    }
+}
@@ -15,7 +15,7 @@ Related [spec](https://www.dangermouse.net/esoteric/piet.html):
 > Blocks of colour adjacent only diagonally are not considered contiguous.
 
 */
-#[derive(Debug, Default, Clone)]
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
 pub struct Block {
     /**
     Number of codels in the block.
@@ -39,36 +39,123 @@ pub struct Block {
     left_right: (usize, usize),
     up_left: (usize, usize),
     up_right: (usize, usize),
+
+    /// Codels of the block as horizontal runs `(i, j_start, j_end)` (`j_end` exclusive) in reading order.
+    /// This is one entry per row segment rather than per codel, and `u32` is enough for any image which fits in memory.
+    runs: Box<[(u32, u32, u32)]>,
 }
 
 impl Block {
     /// Creates a new block from the list of the codels in the same connected component.
     pub fn new(s: &FxHashSet<(usize, usize)>) -> Self {
-        let i_min = s.iter().min_by_key(|(i, _)| i).unwrap().0;
-        let i_max = s.iter().max_by_key(|(i, _)| i).unwrap().0;
-        let j_min = s.iter().min_by_key(|(_, j)| j).unwrap().1;
-        let j_max = s.iter().max_by_key(|(_, j)| j).unwrap().1;
+        Self::from_sorted_codels(&s.iter().copied().sorted().collect_vec())
+    }
+
+    /// Creates a new block from the codels of a connected component in reading order (row-major).
+    ///
+    /// # Panics
+    ///
+    /// Panics if `codels` is empty.
+    pub(crate) fn from_sorted_codels(codels: &[(usize, usize)]) -> Self {
+        let mut runs: Vec<(u32, u32, u32)> = vec![];
+        for &(i, j) in codels {
+            let (i, j) = (i as u32, j as u32);
+            match runs.last_mut() {
+                Some((i_last, _, end)) if (*i_last == i) && (*end == j) => *end += 1,
+                _ => runs.push((i, j, j + 1)),
+            }
+        }
+
+        //In reading order, the first and the last codels of a row or a column are the leftmost (resp. topmost) and the rightmost (resp. bottommost) ones.
+        let first = codels[0];
+        let last = *codels.last().unwrap();
+        let up_right = *codels
+            .iter()
+            .take_while(|(i, _)| *i == first.0)
+            .last()
+            .unwrap();
+        let down_right = *codels.iter().find(|(i, _)| *i == last.0).unwrap();
+        let mut right = (first, first);
+        let mut left = (first, first);
+        for &c in codels {
+            if c.1 > right.0 .1 {
+                right = (c, c);
+            } else if c.1 == right.0 .1 {
+                right.1 = c;
+            }
+            if c.1 < left.0 .1 {
+                left = (c, c);
+            } else if c.1 == left.0 .1 {
+                left.1 = c;
+            }
+        }
         Self {
-            size: s.len(),
-            #[rustfmt::skip]
-            right_left: *s.iter().filter(|(_, j)| *j == j_max).sorted().next().unwrap(),
-            #[rustfmt::skip]
-            right_right: *s.iter().filter(|(_, j)| *j == j_max).sorted().last().unwrap(),
-            #[rustfmt::skip]
-            down_left: *s.iter().filter(|(i, _)| *i == i_max).sorted().last().unwrap(),
-            #[rustfmt::skip]
-            down_right: *s.iter().filter(|(i, _)| *i == i_max).sorted().next().unwrap(),
-            #[rustfmt::skip]
-            left_left: *s.iter().filter(|(_, j)| *j == j_min).sorted().last().unwrap(),
-            #[rustfmt::skip]
-            left_right: *s.iter().filter(|(_, j)| *j == j_min).sorted().next().unwrap(),
-            #[rustfmt::skip]
-            up_left: *s.iter().filter(|(i, _)| *i == i_min).sorted().next().unwrap(),
-            #[rustfmt::skip]
-            up_right: *s.iter().filter(|(i, _)| *i == i_min).sorted().last().unwrap(),
+            size: codels.len(),
+            right_left: right.0,
+            right_right: right.1,
+            down_left: last,
+            down_right,
+            left_left: left.1,
+            left_right: left.0,
+            up_left: first,
+            up_right,
+            runs: runs.into_boxed_slice(),
         }
     }
 
+    /// Returns the codels of the block in reading order (row-major).
+    pub fn codels(&self) -> impl Iterator<Item = (usize, usize)> + '_ {
+        self.runs
+            .iter()
+            .flat_map(|&(i, start, end)| (start..end).map(move |j| (i as usize, j as usize)))
+    }
+
+    /// Returns the 8 corners in the order of the fields (for serialization).
+    pub(crate) fn corners(&self) -> [(usize, usize); 8] {
+        [
+            self.right_left,
+            self.right_right,
+            self.down_left,
+            self.down_right,
+            self.left_left,
+            self.left_right,
+            self.up_left,
+            self.up_right,
+        ]
+    }
+
+    /// Returns the first codel in reading order (row-major), which is the leftmost codel of the top row.
+    pub(crate) fn first_codel(&self) -> (usize, usize) {
+        self.up_left
+    }
+
+    /// Inverse of [`Block::corners()`].
+    /// The codels are not set, and are to be set by [`Block::set_runs()`].
+    pub(crate) fn from_corners(size: usize, corners: [(usize, usize); 8]) -> Self {
+        let [right_left, right_right, down_left, down_right, left_left, left_right, up_left, up_right] =
+            corners;
+        Self {
+            size,
+            right_left,
+            right_right,
+            down_left,
+            down_right,
+            left_left,
+            left_right,
+            up_left,
+            up_right,
+            runs: Box::default(),
+        }
+    }
+
+    /// Sets the codels as horizontal runs `(i, j_start, j_end)` (`j_end` exclusive) in reading order.
+    pub(crate) fn set_runs(&mut self, runs: &[(usize, usize, usize)]) {
+        self.runs = runs
+            .iter()
+            .map(|&(i, start, end)| (i as u32, start as u32, end as u32))
+            .collect();
+    }
+
     pub fn get_corner_index(&self, dp: &DP, cc: &CC) -> (usize, usize) {
         match (dp, cc) {
             (DP::Right, CC::Left) => self.right_left,
@@ -115,7 +202,7 @@ mod tests {
             (4, 1),
             (4, 3),
         ];
-        let s = FxHashSet::from_iter(l);
+        let s = FxHashSet::from_iter(l.clone());
         let block = Block::new(&s);
 
         assert_eq!(block.size, 19);
@@ -137,5 +224,22 @@ mod tests {
         assert_eq!((1, 0), block.get_corner_index(&DP::Left, &CC::Right));
         assert_eq!((0, 1), block.get_corner_index(&DP::Up, &CC::Left));
         assert_eq!((0, 3), block.get_corner_index(&DP::Up, &CC::Right));
+
+        //membership, with the concave rows split into runs
+        let mut expected = l.clone();
+        expected.sort();
+        assert_eq!(expected, block.codels().collect_vec());
+        assert_eq!(
+            vec![
+                (0, 1, 2),
+                (0, 3, 4),
+                (1, 0, 6),
+                (2, 1, 4),
+                (3, 0, 6),
+                (4, 1, 2),
+                (4, 3, 4)
+            ],
+            block.runs.to_vec()
+        );
     }
 }
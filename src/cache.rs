@@ -0,0 +1,228 @@
+/*!
+On-disk cache of parsed images (`--cache-dir`).
+
+Decoding a large image and building its block map can take much longer than running a short program, so the parsed image is stored in a file and reused when the same image is loaded again.
+An entry is keyed by a hash of the image file bytes and the options which affect parsing (format, codel size and fallback color), so editing the image or changing the options never hits a stale entry.
+The key and the checksum are 64-bit FNV-1a hashes of fixed-width little-endian data, so a cache directory can be shared between platforms.
+
+An entry is a little-endian binary file:
+
+```text
+magic (b"PIETCACHE") | format version (u32)
+height (u64) | width (u64) | codels (height * width, see below)
+number of blocks (u64) | blocks (size (u64) and 8 corners (2 * u64 each))
+block index of each codel (height * width * u64)
+FNV-1a hash of all the preceding bytes (u64)
+```
+
+A codel is one byte, which is an index of [`PALETTE`], or `255` followed by the three bytes of an unknown color.
+Anything unexpected (a truncated or corrupt file, a different format version, inconsistent sizes) makes the entry ignored, in which case the image is parsed again and the entry is overwritten.
+*/
+
+use std::path::{Path, PathBuf};
+
+use super::block::Block;
+use super::codel::Codel;
+use super::image::{Image, Pixel};
+use super::pietdev::PALETTE;
+use super::stable::{self, Fnv64, Reader, Sink, OTHER_COLOR_TAG};
+
+const MAGIC: &[u8] = b"PIETCACHE";
+
+/// Incremented whenever the layout of an entry or the meaning of its contents changes.
+/// (2: the key and the checksum changed from `FxHasher` to FNV-1a.)
+const FORMAT_VERSION: u32 = 2;
+
+/// Returns the path of the entry for the image whose file content is `bytes`.
+/// `settings` shall contain every option which affects parsing.
+pub fn entry_path(dir: &str, bytes: &[u8], settings: &str) -> PathBuf {
+    let mut hasher = Fnv64::new();
+    //length-prefixed so that the boundary between the two is unambiguous
+    hasher.put_u64(bytes.len() as u64);
+    hasher.put(bytes);
+    hasher.put_u64(settings.len() as u64);
+    hasher.put(settings.as_bytes());
+    Path::new(dir).join(format!("{:016x}.bin", hasher.finish()))
+}
+
+fn checksum(bytes: &[u8]) -> u64 {
+    stable::fnv64(bytes)
+}
+
+fn push_u64(buf: &mut Vec<u8>, x: usize) {
+    buf.extend((x as u64).to_le_bytes());
+}
+
+/// Serializes `img` into an entry.
+pub fn serialize(img: &Image) -> Vec<u8> {
+    let (m, blocks, block_ids) = img.to_parts();
+
+    let mut buf = MAGIC.to_vec();
+    buf.extend(FORMAT_VERSION.to_le_bytes());
+
+    push_u64(&mut buf, m.len());
+    push_u64(&mut buf, m[0].len());
+    for codel in m.iter().flatten() {
+        stable::put_codel(&mut buf, codel);
+    }
+
+    push_u64(&mut buf, blocks.len());
+    for block in blocks {
+        push_u64(&mut buf, block.size);
+        for (i, j) in block.corners() {
+            push_u64(&mut buf, i);
+            push_u64(&mut buf, j);
+        }
+    }
+
+    for id in block_ids.iter().flatten() {
+        push_u64(&mut buf, *id);
+    }
+
+    buf.extend(checksum(&buf).to_le_bytes());
+    buf
+}
+
+/// Deserializes an entry.
+/// `None` is returned if the entry is corrupt or was written by another version.
+pub fn deserialize(bytes: &[u8]) -> Option<Image> {
+    let (body, sum) = bytes.split_at_checked(bytes.len().checked_sub(8)?)?;
+    if checksum(body) != u64::from_le_bytes(sum.try_into().ok()?) {
+        return None;
+    }
+
+    let mut r = Reader::new(body);
+    if (r.take(MAGIC.len())? != MAGIC)
+        || (u32::from_le_bytes(r.take(4)?.try_into().ok()?) != FORMAT_VERSION)
+    {
+        return None;
+    }
+
+    let height = r.len(1)?;
+    let width = r.len(1)?;
+    let mut m = Vec::with_capacity(height);
+    for _ in 0..height {
+        let mut row = Vec::with_capacity(width);
+        for _ in 0..width {
+            let codel = match r.u8()? {
+                OTHER_COLOR_TAG => Codel::Other(Pixel {
+                    r: r.u8()?,
+                    g: r.u8()?,
+                    b: r.u8()?,
+                }),
+                index => *PALETTE.get(usize::from(index))?,
+            };
+            row.push(codel);
+        }
+        m.push(row);
+    }
+
+    let num_blocks = r.len(8 * 17)?;
+    let mut blocks = Vec::with_capacity(num_blocks);
+    for _ in 0..num_blocks {
+        let size = r.usize()?;
+        let mut corners = [(0, 0); 8];
+        for corner in &mut corners {
+            *corner = (r.usize()?, r.usize()?);
+        }
+        blocks.push(Block::from_corners(size, corners));
+    }
+
+    let mut block_ids = Vec::with_capacity(height);
+    for _ in 0..height {
+        block_ids.push((0..width).map(|_| r.usize()).collect::<Option<Vec<_>>>()?);
+    }
+
+    if !r.is_empty() {
+        return None;
+    }
+    Image::from_parts(m, blocks, block_ids)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn image() -> Image {
+        Image::from_codel_grid(vec![
+            vec![Codel::Red, Codel::Red, Codel::Black],
+            vec![
+                Codel::White,
+                Codel::Other(Pixel { r: 1, g: 2, b: 3 }),
+                Codel::DarkBlue,
+            ],
+        ])
+    }
+
+    fn assert_same(expected: &Image, actual: &Image) {
+        assert_eq!(expected.to_string(), actual.to_string());
+        for i in 0..expected.height() {
+            for j in 0..expected.width() {
+                assert_eq!(
+                    expected.get_codel_at((i, j)).unwrap(),
+                    actual.get_codel_at((i, j)).unwrap()
+                );
+                assert_eq!(
+                    expected.get_block_at((i, j)).unwrap(),
+                    actual.get_block_at((i, j)).unwrap()
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let img = image();
+        assert_same(&img, &deserialize(&serialize(&img)).unwrap());
+    }
+
+    #[test]
+    fn test_corrupt_entries() {
+        let bytes = serialize(&image());
+
+        assert!(deserialize(b"").is_none());
+        assert!(deserialize(b"garbage").is_none());
+        for n in 0..bytes.len() {
+            assert!(deserialize(&bytes[..n]).is_none());
+        }
+        for i in 0..bytes.len() {
+            let mut bytes = bytes.clone();
+            bytes[i] ^= 0x40;
+            assert!(deserialize(&bytes).is_none());
+        }
+
+        //a consistent entry of another version
+        let mut bytes = bytes[..bytes.len() - 8].to_vec();
+        bytes[MAGIC.len()..MAGIC.len() + 4].copy_from_slice(&(FORMAT_VERSION + 1).to_le_bytes());
+        bytes.extend(checksum(&bytes).to_le_bytes());
+        assert!(deserialize(&bytes).is_none());
+    }
+
+    #[test]
+    fn test_entry_path() {
+        let p = entry_path("dir", b"abc", "codel_size=1");
+        assert_eq!(p, entry_path("dir", b"abc", "codel_size=1"));
+        assert_ne!(p, entry_path("dir", b"abd", "codel_size=1"));
+        assert_ne!(p, entry_path("dir", b"abc", "codel_size=2"));
+        assert!(p.starts_with("dir"));
+    }
+
+    /// The entry of `image()` as written by this version, checked in so that a change of the format (or a platform which writes it differently) is caught.
+    const GOLDEN: &[u8] = include_bytes!("../tests/test_images/original___cache_entry_v2.bin");
+
+    #[test]
+    fn test_golden() {
+        assert_eq!(GOLDEN, serialize(&image()).as_slice());
+        assert_same(&image(), &deserialize(GOLDEN).unwrap());
+        assert_eq!(
+            Path::new("d").join("b373edfccca9fede.bin"),
+            entry_path("d", b"abc", "png 1 none")
+        );
+
+        //an entry of the previous version is rejected even if its checksum were valid
+        let mut bytes = GOLDEN[..GOLDEN.len() - 8].to_vec();
+        bytes[MAGIC.len()..MAGIC.len() + 4].copy_from_slice(&1u32.to_le_bytes());
+        bytes.extend(checksum(&bytes).to_le_bytes());
+        assert!(deserialize(&bytes).is_none());
+    }
+}
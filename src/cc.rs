@@ -6,7 +6,7 @@ The default value is `CC::Left` as [the spec](https://www.dangermouse.net/esoter
 > The interpreter also maintains a Codel Chooser (CC), initially pointing left.
 
 */
-#[derive(Debug, PartialEq, Default)]
+#[derive(Hash, PartialEq, Eq, Clone, Copy, Debug, Default)]
 pub enum CC {
     #[default]
     Left,
@@ -0,0 +1,487 @@
+/*!
+Checkpoints of a long run (`--checkpoint-every`, `--checkpoint-file` and `--resume`).
+
+A checkpoint is the state of the interpreter between two steps, taken when it is about to leave a coloured block
+(the state of a slide across white, i.e. the codels visited so far, is not saved, so no checkpoint is taken during a slide).
+It records the number of bytes consumed from the input and written to the output, so that a resumed run skips the input which was already consumed
+and continues the output file of `--output` from where the checkpoint was taken (discarding anything written after it).
+
+A checkpoint is written to a temporary file which is then renamed over the previous one, so a crash while writing leaves the previous checkpoint intact.
+The output file is synced before, so it is never shorter than the checkpoint says.
+
+A checkpoint is a little-endian binary file:
+
+```text
+magic (b"PIETCKPT") | format version (u32)
+fingerprint of the program (u64) | semantics (length (u64) and the JSON of `version-report`)
+position (2 * u64) | DP (u8) | CC (u8)
+number of steps, commands and output bytes (3 * u64)
+number of input bytes consumed (u64) | input EOF (u8) | last terminator (u32, or u32::MAX for none)
+stack (length (u64) and i64 each) | second stack (same)
+FNV-1a hash of all the preceding bytes (u64)
+```
+*/
+
+use std::fs::{self, File};
+use std::io;
+use std::path::{Path, PathBuf};
+
+use super::cc::CC;
+use super::coord;
+use super::dp::DP;
+use super::error::PietError;
+use super::image::Image;
+use super::interpreter::Interpreter;
+use super::program::Fingerprint;
+use super::stable::{self, Reader};
+
+const MAGIC: &[u8] = b"PIETCKPT";
+
+/// Incremented whenever the layout of a checkpoint or the meaning of its contents changes.
+const FORMAT_VERSION: u32 = 1;
+
+const NO_TERMINATOR: u32 = u32::MAX;
+
+/// Saved state of a run.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Checkpoint {
+    pub fingerprint: Fingerprint,
+    /// The semantics of the run (see [`SemanticsDescriptor::to_json()`](super::semantics::SemanticsDescriptor::to_json)).
+    pub semantics: String,
+    pub cur: (usize, usize),
+    pub dp: DP,
+    pub cc: CC,
+    pub num_steps: usize,
+    pub num_commands: usize,
+    pub num_output_bytes: usize,
+    pub num_input_bytes: usize,
+    pub is_input_eof: bool,
+    pub last_terminator: Option<char>,
+    pub stack: Vec<isize>,
+    pub second_stack: Vec<isize>,
+}
+
+fn push_u64(buf: &mut Vec<u8>, x: usize) {
+    buf.extend((x as u64).to_le_bytes());
+}
+
+fn push_stack(buf: &mut Vec<u8>, stack: &[isize]) {
+    push_u64(buf, stack.len());
+    for x in stack {
+        buf.extend((*x as i64).to_le_bytes());
+    }
+}
+
+fn read_stack(r: &mut Reader) -> Option<Vec<isize>> {
+    let len = r.len(8)?;
+    (0..len)
+        .map(|_| isize::try_from(r.u64()? as i64).ok())
+        .collect()
+}
+
+impl Checkpoint {
+    /// Takes a checkpoint of `ip`, which is running the program `fingerprint` with `semantics`.
+    pub fn new(ip: &Interpreter, fingerprint: Fingerprint, semantics: String) -> Self {
+        Self {
+            fingerprint,
+            semantics,
+            cur: ip.cur,
+            dp: ip.dp,
+            cc: ip.cc,
+            num_steps: ip.num_steps,
+            num_commands: ip.num_commands,
+            num_output_bytes: ip.num_output_bytes,
+            num_input_bytes: ip.stdin.num_bytes_read(),
+            is_input_eof: ip.stdin.is_eof(),
+            last_terminator: ip.stdin.last_terminator(),
+            stack: ip.stack.clone(),
+            second_stack: ip.second_stack.clone(),
+        }
+    }
+
+    /// Checks that the position lies within `img`, as a checkpoint may come from anywhere.
+    /// `input` names the checkpoint in the error.
+    pub fn validate(&self, img: &Image, input: &str) -> Result<(), PietError> {
+        coord::check_pos(input, self.cur, img.height(), img.width())
+    }
+
+    /// Restores the state into `ip`, whose input shall be the same as that of the run which took the checkpoint.
+    /// The consumed part of the input is skipped, and an error is returned if the input is shorter than that.
+    pub fn restore(&self, ip: &mut Interpreter) -> Result<(), String> {
+        ip.stdin.resume(
+            self.num_input_bytes,
+            self.is_input_eof,
+            self.last_terminator,
+        )?;
+        ip.cur = self.cur;
+        ip.dp = self.dp;
+        ip.cc = self.cc;
+        ip.num_steps = self.num_steps;
+        ip.num_commands = self.num_commands;
+        ip.num_output_bytes = self.num_output_bytes;
+        ip.stack.clone_from(&self.stack);
+        ip.second_stack.clone_from(&self.second_stack);
+        Ok(())
+    }
+
+    pub fn serialize(&self) -> Vec<u8> {
+        let mut buf = MAGIC.to_vec();
+        buf.extend(FORMAT_VERSION.to_le_bytes());
+
+        buf.extend(self.fingerprint.to_u64().to_le_bytes());
+        push_u64(&mut buf, self.semantics.len());
+        buf.extend(self.semantics.as_bytes());
+
+        push_u64(&mut buf, self.cur.0);
+        push_u64(&mut buf, self.cur.1);
+        buf.push(self.dp as u8);
+        buf.push(self.cc as u8);
+
+        push_u64(&mut buf, self.num_steps);
+        push_u64(&mut buf, self.num_commands);
+        push_u64(&mut buf, self.num_output_bytes);
+        push_u64(&mut buf, self.num_input_bytes);
+        buf.push(u8::from(self.is_input_eof));
+        buf.extend(
+            self.last_terminator
+                .map_or(NO_TERMINATOR, u32::from)
+                .to_le_bytes(),
+        );
+
+        push_stack(&mut buf, &self.stack);
+        push_stack(&mut buf, &self.second_stack);
+
+        buf.extend(stable::fnv64(&buf).to_le_bytes());
+        buf
+    }
+
+    /// `None` is returned if the checkpoint is corrupt or was written by another version.
+    pub fn deserialize(bytes: &[u8]) -> Option<Self> {
+        let (body, sum) = bytes.split_at_checked(bytes.len().checked_sub(8)?)?;
+        if stable::fnv64(body) != u64::from_le_bytes(sum.try_into().ok()?) {
+            return None;
+        }
+
+        let mut r = Reader::new(body);
+        if (r.take(MAGIC.len())? != MAGIC)
+            || (u32::from_le_bytes(r.take(4)?.try_into().ok()?) != FORMAT_VERSION)
+        {
+            return None;
+        }
+
+        let fingerprint = Fingerprint::from_u64(r.u64()?);
+        let len = r.len(1)?;
+        let semantics = String::from_utf8(r.take(len)?.to_vec()).ok()?;
+        let cur = (r.usize()?, r.usize()?);
+        let dp = match r.u8()? {
+            0 => DP::Right,
+            1 => DP::Down,
+            2 => DP::Left,
+            3 => DP::Up,
+            _ => return None,
+        };
+        let cc = match r.u8()? {
+            0 => CC::Left,
+            1 => CC::Right,
+            _ => return None,
+        };
+        let num_steps = r.usize()?;
+        let num_commands = r.usize()?;
+        let num_output_bytes = r.usize()?;
+        let num_input_bytes = r.usize()?;
+        let is_input_eof = match r.u8()? {
+            0 => false,
+            1 => true,
+            _ => return None,
+        };
+        let last_terminator = match u32::from_le_bytes(r.take(4)?.try_into().ok()?) {
+            NO_TERMINATOR => None,
+            c => Some(char::from_u32(c)?),
+        };
+        let stack = read_stack(&mut r)?;
+        let second_stack = read_stack(&mut r)?;
+
+        if !r.is_empty() {
+            return None;
+        }
+        Some(Self {
+            fingerprint,
+            semantics,
+            cur,
+            dp,
+            cc,
+            num_steps,
+            num_commands,
+            num_output_bytes,
+            num_input_bytes,
+            is_input_eof,
+            last_terminator,
+            stack,
+            second_stack,
+        })
+    }
+}
+
+/// Replaces the content of `path` with `bytes` so that the file has either the old or the new content even if the process is killed meanwhile.
+pub fn write_atomically(path: &Path, bytes: &[u8]) -> io::Result<()> {
+    let mut tmp = path.as_os_str().to_owned();
+    tmp.push(".tmp");
+    let tmp = PathBuf::from(tmp);
+    {
+        let mut file = File::create(&tmp)?;
+        io::Write::write_all(&mut file, bytes)?;
+        file.sync_all()?;
+    }
+    fs::rename(&tmp, path)
+}
+
+/// Writes a checkpoint every `every` steps.
+pub struct Checkpointer {
+    path: PathBuf,
+    every: usize,
+    /// The step at or after which the next checkpoint is due.
+    next: usize,
+    fingerprint: Fingerprint,
+    semantics: String,
+}
+
+impl Checkpointer {
+    /// `num_steps` is the step at which the run starts (non-zero when it is resumed).
+    pub fn new(
+        path: impl Into<PathBuf>,
+        every: usize,
+        num_steps: usize,
+        fingerprint: Fingerprint,
+        semantics: String,
+    ) -> Self {
+        Self {
+            path: path.into(),
+            every,
+            next: (num_steps / every + 1) * every,
+            fingerprint,
+            semantics,
+        }
+    }
+
+    /// Writes a checkpoint of `ip` if one is due.
+    /// Returns whether a checkpoint was written.
+    pub fn tick(&mut self, ip: &Interpreter) -> io::Result<bool> {
+        if ip.num_steps < self.next {
+            return Ok(false);
+        }
+        self.next = (ip.num_steps / self.every + 1) * self.every;
+        if let Some(file) = &ip.output_file {
+            file.sync_data()?;
+        }
+        let checkpoint = Checkpoint::new(ip, self.fingerprint, self.semantics.clone());
+        write_atomically(&self.path, &checkpoint.serialize())?;
+        Ok(true)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn checkpoint() -> Checkpoint {
+        Checkpoint {
+            fingerprint: Fingerprint::from_u64(0x0123_4567_89ab_cdef),
+            semantics: "{}".to_string(),
+            cur: (3, 4),
+            dp: DP::Up,
+            cc: CC::Right,
+            num_steps: 100,
+            num_commands: 60,
+            num_output_bytes: 5,
+            num_input_bytes: 7,
+            is_input_eof: true,
+            last_terminator: Some('\n'),
+            stack: vec![1, -2, isize::MAX],
+            second_stack: vec![isize::MIN],
+        }
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let checkpoint = checkpoint();
+        let bytes = checkpoint.serialize();
+        assert_eq!(Some(checkpoint.clone()), Checkpoint::deserialize(&bytes));
+
+        let checkpoint = Checkpoint {
+            last_terminator: None,
+            stack: vec![],
+            ..checkpoint
+        };
+        assert_eq!(
+            Some(checkpoint.clone()),
+            Checkpoint::deserialize(&checkpoint.serialize())
+        );
+    }
+
+    #[test]
+    fn test_corrupt_checkpoints() {
+        let bytes = checkpoint().serialize();
+        for n in 0..bytes.len() {
+            assert_eq!(None, Checkpoint::deserialize(&bytes[..n]), "{}", n);
+        }
+        for k in 0..bytes.len() {
+            let mut corrupt = bytes.clone();
+            corrupt[k] ^= 0x10;
+            assert_eq!(None, Checkpoint::deserialize(&corrupt), "{}", k);
+        }
+    }
+
+    /// Replaces the bytes at `k` with `patch` and fixes the hash, as a crafted checkpoint would.
+    fn craft(bytes: &[u8], k: usize, patch: &[u8]) -> Vec<u8> {
+        let mut body = bytes[..bytes.len() - 8].to_vec();
+        body[k..k + patch.len()].copy_from_slice(patch);
+        let sum = stable::fnv64(&body);
+        body.extend(sum.to_le_bytes());
+        body
+    }
+
+    #[test]
+    fn test_crafted_checkpoints() {
+        use super::super::codel::Codel;
+
+        let img = Image::from_codel_grid(vec![vec![Codel::Red; 5]; 4]);
+        let bytes = checkpoint().serialize();
+        //every field set to extreme values, one byte or one word at a time
+        for k in 0..bytes.len() - 8 {
+            for patch in [
+                &[0x00][..],
+                &[0xff],
+                &[0x80],
+                &u64::MAX.to_le_bytes(),
+                &(i64::MIN as u64).to_le_bytes(),
+            ] {
+                if k + patch.len() > bytes.len() - 8 {
+                    continue;
+                }
+                let Some(checkpoint) = Checkpoint::deserialize(&craft(&bytes, k, patch)) else {
+                    continue;
+                };
+                if checkpoint.validate(&img, "f").is_ok() {
+                    assert!((checkpoint.cur.0 < 4) && (checkpoint.cur.1 < 5));
+                }
+            }
+        }
+
+        //the position is right after the semantics
+        let k = MAGIC.len() + 4 + 8 + 8 + checkpoint().semantics.len();
+        for (patch, cur) in [
+            (u64::MAX.to_le_bytes(), (usize::MAX, 4)),
+            (4u64.to_le_bytes(), (4, 4)),
+        ] {
+            let checkpoint = Checkpoint::deserialize(&craft(&bytes, k, &patch)).unwrap();
+            assert_eq!(cur, checkpoint.cur);
+            assert_eq!(
+                Err(PietError::InvalidCoordinate {
+                    input: "f".to_string(),
+                    value: format!("the position {:?}", cur),
+                    image_height: 4,
+                    image_width: 5,
+                }),
+                checkpoint.validate(&img, "f")
+            );
+        }
+        assert_eq!(Ok(()), checkpoint().validate(&img, "f"));
+    }
+
+    #[test]
+    fn test_restore() {
+        let mut ip = Interpreter::new_with_stdin("0123456789");
+        checkpoint().restore(&mut ip).unwrap();
+        assert_eq!(
+            checkpoint(),
+            Checkpoint::new(&ip, checkpoint().fingerprint, "{}".to_string())
+        );
+
+        let mut ip = Interpreter::new_with_stdin("0123");
+        assert!(checkpoint().restore(&mut ip).is_err());
+    }
+
+    #[test]
+    fn test_restore_state() {
+        use super::super::examples::Example;
+
+        //A checkpoint taken at any step where one can be taken (not in the middle of a slide) restores the state, from which the run continues as if uninterrupted.
+        for (example, input) in [(Example::Hello, ""), (Example::Adder, "12 30")] {
+            let program = example.program();
+            let new = || {
+                let mut ip = Interpreter::new_with_stdin(input);
+                ip.output_writer = Some(Box::new(io::sink()));
+                ip
+            };
+            let mut uninterrupted = new();
+            uninterrupted.run_for(&program, usize::MAX).unwrap();
+            let mut ip = new();
+            let mut checked = 0;
+            loop {
+                if !program.image().get_codel_at_unchecked(ip.cur).is_white() {
+                    let bytes =
+                        Checkpoint::new(&ip, program.fingerprint(), "{}".to_string()).serialize();
+                    let mut resumed = new();
+                    Checkpoint::deserialize(&bytes)
+                        .unwrap()
+                        .restore(&mut resumed)
+                        .unwrap();
+                    assert_eq!(ip.digest(), resumed.digest());
+
+                    resumed.run_for(&program, usize::MAX).unwrap();
+                    assert_eq!(uninterrupted.digest(), resumed.digest());
+                    checked += 1;
+                }
+                if let super::super::slice::SliceStatus::Finished(_) =
+                    ip.run_for(&program, 1).unwrap().status
+                {
+                    break;
+                }
+            }
+            assert!(checked >= 5, "{:?}", example);
+        }
+    }
+
+    #[test]
+    fn test_write_atomically() {
+        let path =
+            std::env::temp_dir().join(format!("piet_test_checkpoint_{}", std::process::id()));
+        write_atomically(&path, b"first").unwrap();
+        write_atomically(&path, b"second").unwrap();
+        assert_eq!(b"second".as_slice(), fs::read(&path).unwrap());
+        //the temporary file is renamed, not left behind
+        let mut tmp = path.as_os_str().to_owned();
+        tmp.push(".tmp");
+        assert!(!Path::new(&tmp).exists());
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_checkpointer() {
+        let path =
+            std::env::temp_dir().join(format!("piet_test_checkpointer_{}", std::process::id()));
+        let fingerprint = Fingerprint::from_u64(1);
+        let mut checkpointer = Checkpointer::new(&path, 10, 0, fingerprint, "{}".to_string());
+        let mut ip = Interpreter::new_with_stdin("");
+        let mut written = vec![];
+        //steps during a slide across white are skipped, so a checkpoint is taken at the first opportunity
+        for num_steps in [0, 5, 9, 12, 13, 20, 35] {
+            ip.num_steps = num_steps;
+            if checkpointer.tick(&ip).unwrap() {
+                written.push(num_steps);
+            }
+        }
+        assert_eq!(vec![12, 20, 35], written);
+        let checkpoint = Checkpoint::deserialize(&fs::read(&path).unwrap()).unwrap();
+        assert_eq!(35, checkpoint.num_steps);
+        fs::remove_file(&path).unwrap();
+
+        //a resumed run does not take a checkpoint at the step where it starts
+        let mut checkpointer = Checkpointer::new(&path, 10, 35, fingerprint, "{}".to_string());
+        ip.num_steps = 35;
+        assert!(!checkpointer.tick(&ip).unwrap());
+        ip.num_steps = 40;
+        assert!(checkpointer.tick(&ip).unwrap());
+        fs::remove_file(&path).unwrap();
+    }
+}
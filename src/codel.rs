@@ -1,6 +1,7 @@
 use std::fmt::{self, Display};
 
 use super::image::Pixel;
+use super::pietdev::PALETTE;
 
 /// Codel (LightRed, Green, White, etc.)
 #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
@@ -28,12 +29,25 @@ pub enum Codel {
 
     White, //#FFFFFF
     Black, //#000000
+
+    /// Any other color.
+    /// Codels of the same color form a block, but the program terminates with an error if it tries to enter or leave one.
+    Other(Pixel),
 }
 
 impl Display for Codel {
     /// Prints a colored square (full-width space with a background color).
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        let (r, g, b) = match self {
+        let (r, g, b) = self.rgb();
+        //by the way, `38;2` can be used to change the foreground color
+        write!(f, "\u{001B}[48;2;{};{};{}m　\u{001B}[0m", r, g, b)
+    }
+}
+
+impl Codel {
+    /// Returns the RGB value of the color.
+    pub fn rgb(&self) -> (u8, u8, u8) {
+        match self {
             Codel::LightRed => (255, 192, 192),
             Codel::LightYellow => (255, 255, 192),
             Codel::LightGreen => (192, 255, 192),
@@ -57,61 +71,77 @@ impl Display for Codel {
 
             Codel::White => (255, 255, 255),
             Codel::Black => (0, 0, 0),
-        };
-        //by the way, `38;2` can be used to change the foreground color
-        write!(f, "\u{001B}[48;2;{};{};{}m　\u{001B}[0m", r, g, b)
+
+            Codel::Other(p) => (p.r, p.g, p.b),
+        }
     }
-}
 
-impl Codel {
-    pub fn new(p: &Pixel) -> Option<Self> {
+    pub fn new(p: &Pixel) -> Self {
         match p {
             #[rustfmt::skip]
-            Pixel { r: 255, g: 192, b: 192 } => Some(Codel::LightRed),
+            Pixel { r: 255, g: 192, b: 192 } => Codel::LightRed,
             #[rustfmt::skip]
-            Pixel { r: 255, g: 255, b: 192 } => Some(Codel::LightYellow),
+            Pixel { r: 255, g: 255, b: 192 } => Codel::LightYellow,
             #[rustfmt::skip]
-            Pixel { r: 192, g: 255, b: 192 } => Some(Codel::LightGreen),
+            Pixel { r: 192, g: 255, b: 192 } => Codel::LightGreen,
             #[rustfmt::skip]
-            Pixel { r: 192, g: 255, b: 255 } => Some(Codel::LightCyan),
+            Pixel { r: 192, g: 255, b: 255 } => Codel::LightCyan,
             #[rustfmt::skip]
-            Pixel { r: 192, g: 192, b: 255 } => Some(Codel::LightBlue),
+            Pixel { r: 192, g: 192, b: 255 } => Codel::LightBlue,
             #[rustfmt::skip]
-            Pixel { r: 255, g: 192, b: 255 } => Some(Codel::LightMagenta),
+            Pixel { r: 255, g: 192, b: 255 } => Codel::LightMagenta,
 
             #[rustfmt::skip]
-            Pixel { r: 255, g: 0, b: 0 } => Some(Codel::Red),
+            Pixel { r: 255, g: 0, b: 0 } => Codel::Red,
             #[rustfmt::skip]
-            Pixel { r: 255, g: 255, b: 0 } => Some(Codel::Yellow),
+            Pixel { r: 255, g: 255, b: 0 } => Codel::Yellow,
             #[rustfmt::skip]
-            Pixel { r: 0, g: 255, b: 0 } => Some(Codel::Green),
+            Pixel { r: 0, g: 255, b: 0 } => Codel::Green,
             #[rustfmt::skip]
-            Pixel { r: 0, g: 255, b: 255 } => Some(Codel::Cyan),
+            Pixel { r: 0, g: 255, b: 255 } => Codel::Cyan,
             #[rustfmt::skip]
-            Pixel { r: 0, g: 0, b: 255 } => Some(Codel::Blue),
+            Pixel { r: 0, g: 0, b: 255 } => Codel::Blue,
             #[rustfmt::skip]
-            Pixel { r: 255, g: 0, b: 255 } => Some(Codel::Magenta),
+            Pixel { r: 255, g: 0, b: 255 } => Codel::Magenta,
 
             #[rustfmt::skip]
-            Pixel { r: 192, g: 0, b: 0 } => Some(Codel::DarkRed),
+            Pixel { r: 192, g: 0, b: 0 } => Codel::DarkRed,
             #[rustfmt::skip]
-            Pixel { r: 192, g: 192, b: 0 } => Some(Codel::DarkYellow),
+            Pixel { r: 192, g: 192, b: 0 } => Codel::DarkYellow,
             #[rustfmt::skip]
-            Pixel { r: 0, g: 192, b: 0 } => Some(Codel::DarkGreen),
+            Pixel { r: 0, g: 192, b: 0 } => Codel::DarkGreen,
             #[rustfmt::skip]
-            Pixel { r: 0, g: 192, b: 192 } => Some(Codel::DarkCyan),
+            Pixel { r: 0, g: 192, b: 192 } => Codel::DarkCyan,
             #[rustfmt::skip]
-            Pixel { r: 0, g: 0, b: 192 } => Some(Codel::DarkBlue),
+            Pixel { r: 0, g: 0, b: 192 } => Codel::DarkBlue,
             #[rustfmt::skip]
-            Pixel { r: 192, g: 0, b: 192 } => Some(Codel::DarkMagenta),
+            Pixel { r: 192, g: 0, b: 192 } => Codel::DarkMagenta,
 
             #[rustfmt::skip]
-            Pixel { r: 255, g: 255, b: 255 } => Some(Codel::White),
+            Pixel { r: 255, g: 255, b: 255 } => Codel::White,
             #[rustfmt::skip]
-            Pixel { r: 0, g: 0, b: 0 } => Some(Codel::Black),
+            Pixel { r: 0, g: 0, b: 0 } => Codel::Black,
+
+            _ => Codel::Other(*p),
+        }
+    }
 
-            _ => None,
+    /// Same as [`Self::new()`] but reads `p` as a color of Piet if each of its channels is within `tolerance` of it (`--color-tolerance`).
+    /// As two colors of Piet differ by at least `0x3F` in a channel, at most one color matches if `tolerance` is at most [`MAX_COLOR_TOLERANCE`](super::image::MAX_COLOR_TOLERANCE).
+    pub fn new_with_tolerance(p: &Pixel, tolerance: u8) -> Self {
+        let codel = Self::new(p);
+        if !codel.is_other() || (tolerance == 0) {
+            return codel;
         }
+        PALETTE
+            .into_iter()
+            .find(|c| {
+                let (r, g, b) = c.rgb();
+                (p.r.abs_diff(r) <= tolerance)
+                    && (p.g.abs_diff(g) <= tolerance)
+                    && (p.b.abs_diff(b) <= tolerance)
+            })
+            .unwrap_or(codel)
     }
 
     pub fn is_black(&self) -> bool {
@@ -122,8 +152,53 @@ impl Codel {
         self == &Codel::White
     }
 
+    pub fn is_other(&self) -> bool {
+        matches!(self, Codel::Other(_))
+    }
+
+    /// Returns the codel of `hue` (`0..6` in the order red, yellow, green, cyan, blue, magenta) and `lightness` (`0..3` in the order light, normal, dark).
+    /// This is the inverse of [`Self::get_hue()`] and [`Self::get_lightness()`].
+    ///
+    /// # Panics
+    ///
+    /// Panics if `hue` or `lightness` is out of range.
+    pub fn from_hue_lightness(hue: usize, lightness: usize) -> Self {
+        const COLORS: [[Codel; 6]; 3] = [
+            [
+                Codel::LightRed,
+                Codel::LightYellow,
+                Codel::LightGreen,
+                Codel::LightCyan,
+                Codel::LightBlue,
+                Codel::LightMagenta,
+            ],
+            [
+                Codel::Red,
+                Codel::Yellow,
+                Codel::Green,
+                Codel::Cyan,
+                Codel::Blue,
+                Codel::Magenta,
+            ],
+            [
+                Codel::DarkRed,
+                Codel::DarkYellow,
+                Codel::DarkGreen,
+                Codel::DarkCyan,
+                Codel::DarkBlue,
+                Codel::DarkMagenta,
+            ],
+        ];
+        COLORS[lightness][hue]
+    }
+
+    /// Returns the position of the color in the hue cycle (`0..6` in the order red, yellow, green, cyan, blue, magenta).
+    ///
+    /// # Panics
+    ///
+    /// Panics if the codel is white, black or of an unknown color.
     //ref: https://www.dangermouse.net/esoteric/piet.html
-    fn get_hue(&self) -> usize {
+    pub fn get_hue(&self) -> usize {
         match self {
             Codel::LightRed | Codel::Red | Codel::DarkRed => 0,
             Codel::LightYellow | Codel::Yellow | Codel::DarkYellow => 1,
@@ -135,8 +210,13 @@ impl Codel {
         }
     }
 
+    /// Returns the position of the color in the lightness cycle (`0..3` in the order light, normal, dark).
+    ///
+    /// # Panics
+    ///
+    /// Panics if the codel is white, black or of an unknown color.
     //ref: https://www.dangermouse.net/esoteric/piet.html
-    fn get_lightness(&self) -> usize {
+    pub fn get_lightness(&self) -> usize {
         match self {
             Codel::LightRed
             | Codel::LightYellow
@@ -175,6 +255,10 @@ impl Codel {
 
 #[cfg(test)]
 mod tests {
+    use std::collections::HashSet;
+
+    use itertools::Itertools;
+
     use super::*;
 
     #[test]
@@ -201,4 +285,44 @@ mod tests {
             Codel::get_lightness_difference(&Codel::Red, &Codel::LightGreen)
         );
     }
+
+    #[test]
+    fn test_hue_lightness() {
+        let colors = (0..6)
+            .cartesian_product(0..3)
+            .map(|(hue, lightness)| {
+                let codel = Codel::from_hue_lightness(hue, lightness);
+                assert_eq!((hue, lightness), (codel.get_hue(), codel.get_lightness()));
+                codel
+            })
+            .collect::<HashSet<_>>();
+        assert_eq!(18, colors.len());
+        assert!(colors
+            .iter()
+            .all(|c| !(c.is_white() || c.is_black() || c.is_other())));
+        assert_eq!(Codel::DarkBlue, Codel::from_hue_lightness(4, 2));
+    }
+
+    #[test]
+    fn test_new_with_tolerance() {
+        let new = |r, g, b, tolerance| Codel::new_with_tolerance(&Pixel { r, g, b }, tolerance);
+        assert_eq!(Codel::Red, new(255, 0, 0, 0));
+        assert_eq!(
+            Codel::Other(Pixel { r: 250, g: 3, b: 0 }),
+            new(250, 3, 0, 0)
+        );
+        assert_eq!(Codel::Red, new(250, 3, 0, 5));
+        assert_eq!(
+            Codel::Other(Pixel { r: 250, g: 3, b: 0 }),
+            new(250, 3, 0, 4)
+        );
+        //half way between light yellow and white
+        assert_eq!(Codel::LightYellow, new(255, 255, 223, 31));
+        assert_eq!(Codel::White, new(255, 255, 224, 31));
+        //at most one color is within the largest tolerance
+        for c in PALETTE {
+            let (r, g, b) = c.rgb();
+            assert_eq!(c, new(r.saturating_sub(31), g.saturating_add(31), b, 31));
+        }
+    }
 }
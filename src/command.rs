@@ -1,12 +1,17 @@
 use std::collections::VecDeque;
-
-use num::Integer;
+use std::time::Instant;
 
 use super::codel::Codel;
+use super::error::PietError;
+use super::extension;
 use super::interpreter::Interpreter;
+use super::trace::IoEvent;
+
+/// Default of `--large-operand-threshold`.
+pub const DEFAULT_LARGE_OPERAND_THRESHOLD: usize = 1_000;
 
 /// Piet Commands (Push, Mod, Roll, etc.)
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum Command {
     Push,
     Pop,
@@ -49,6 +54,8 @@ impl Command {
         assert!(!from.is_black());
         assert!(!to.is_white());
         assert!(!to.is_black());
+        assert!(!from.is_other());
+        assert!(!to.is_other());
 
         let hue_difference = Codel::get_hue_difference(from, to);
         let lightness_difference = Codel::get_lightness_difference(from, to);
@@ -80,6 +87,20 @@ impl Command {
         }
     }
 
+    /// Returns the command table of the spec, where `table()[hue][lightness]` is the command of a transition by `hue` steps along the hue cycle and `lightness` steps along the lightness cycle
+    /// (see [`Codel::get_hue_difference()`] and [`Codel::get_lightness_difference()`]), or `None` for no change, which has no command.
+    ///
+    /// The table is derived from [`Self::new()`] rather than written down again.
+    pub fn table() -> [[Option<Command>; 3]; 6] {
+        let from = Codel::from_hue_lightness(0, 0);
+        std::array::from_fn(|hue| {
+            std::array::from_fn(|lightness| {
+                let to = Codel::from_hue_lightness(hue, lightness);
+                (to != from).then(|| Command::new(&from, &to))
+            })
+        })
+    }
+
     /**
     Executes the command represented by `self`.
 
@@ -88,8 +109,13 @@ impl Command {
     As [the spec](https://www.dangermouse.net/esoteric/piet.html) says,
 
     >  Any operations which cannot be performed (such as popping values when not enough are on the stack) are simply ignored, and processing continues with the next command.
+
+    # Errors
+
+    Returns [`PietError::WriteFailed`] if the output (or the I/O trace) cannot be written, after which the command has been executed except for what was not written,
+    and [`PietError::ReadFailed`] if the input cannot be read, after which nothing has been pushed.
     */
-    pub fn execute(&self, ip: &mut Interpreter, block_size: usize) {
+    pub fn execute(&self, ip: &mut Interpreter, block_size: usize) -> Result<(), PietError> {
         assert!(block_size > 0);
         let block_size = block_size as isize;
         let stack = &mut ip.stack;
@@ -109,7 +135,7 @@ impl Command {
                 if stack.len() >= 2 {
                     let x = stack.pop().unwrap();
                     let y = stack.pop().unwrap();
-                    stack.push(x + y);
+                    stack.push(x.wrapping_add(y));
                 }
             }
 
@@ -118,7 +144,7 @@ impl Command {
                 if stack.len() >= 2 {
                     let x = stack.pop().unwrap();
                     let y = stack.pop().unwrap();
-                    stack.push(y - x);
+                    stack.push(y.wrapping_sub(x));
                 }
             }
 
@@ -127,7 +153,7 @@ impl Command {
                 if stack.len() >= 2 {
                     let x = stack.pop().unwrap();
                     let y = stack.pop().unwrap();
-                    stack.push(x * y);
+                    stack.push(x.wrapping_mul(y));
                 }
             }
 
@@ -139,11 +165,11 @@ impl Command {
             Command::Divide => {
                 if stack.len() >= 2 {
                     if *stack.last().unwrap() == 0 {
-                        return; //zero-division
+                        return Ok(()); //zero-division
                     }
                     let x = stack.pop().unwrap();
                     let y = stack.pop().unwrap();
-                    stack.push(y / x);
+                    stack.push(y.wrapping_div(x)); //`isize::MIN / -1` overflows
                 }
             }
 
@@ -157,12 +183,14 @@ impl Command {
             Command::Mod => {
                 if stack.len() >= 2 {
                     if *stack.last().unwrap() == 0 {
-                        return; //zero-division
+                        return Ok(()); //zero-division
                     }
                     let x = stack.pop().unwrap();
                     let y = stack.pop().unwrap();
-                    #[allow(unstable_name_collisions)]
-                    stack.push(y - (y.div_floor(&x) * x)); //Python-style mod
+                    //Python-style mod
+                    //`wrapping_rem_euclid()` is used as `isize::MIN % -1` overflows (the result is `0` anyway).
+                    let r = y.wrapping_rem_euclid(x);
+                    stack.push(if (r != 0) && (x < 0) { r + x } else { r });
                 }
             }
 
@@ -203,7 +231,8 @@ impl Command {
             Command::Switch => {
                 if !stack.is_empty() {
                     let x = stack.pop().unwrap();
-                    if x.abs() % 2 == 1 {
+                    //`rem_euclid()` instead of `abs()`, which overflows for `isize::MIN`
+                    if x.rem_euclid(2) == 1 {
                         ip.cc = ip.cc.flip();
                     }
                 }
@@ -228,31 +257,35 @@ impl Command {
             //it is handled as an implementation-dependent error, though simply ignoring the command is recommended.
             Command::Roll => {
                 if stack.len() < 2 {
-                    return;
+                    return Ok(());
                 }
 
                 let num_roll = stack[stack.len() - 1];
                 let depth = stack[stack.len() - 2];
-                if (depth < 0) || (stack.len() - 2 < depth as usize) {
-                    return;
+                if depth < 0 {
+                    if ip.config.second_stack {
+                        extension::roll_between_stacks(ip, depth, num_roll);
+                    }
+                    return Ok(());
+                }
+                if stack.len() - 2 < depth as usize {
+                    return Ok(());
                 }
                 for _ in 0..2 {
                     stack.pop().unwrap();
                 }
                 //if operation can be done but virtually nothing happens
                 if (depth <= 1) || (num_roll == 0) {
-                    return;
+                    return Ok(());
                 }
 
                 let mut buf = VecDeque::with_capacity(depth as usize);
                 for _ in 0..depth {
                     buf.push_front(stack.pop().unwrap());
                 }
-                if num_roll > 0 {
-                    buf.rotate_right((num_roll % depth) as usize);
-                } else {
-                    buf.rotate_left((num_roll.abs() % depth) as usize);
-                }
+                //`rem_euclid()` instead of `abs()`, which overflows for `isize::MIN`
+                //A negative number of rolls `-n` is the same as `depth - n % depth` positive rolls.
+                buf.rotate_right(num_roll.rem_euclid(depth) as usize);
                 for e in buf {
                     stack.push(e);
                 }
@@ -264,9 +297,32 @@ impl Command {
             //If no input is waiting on STDIN, this is an error and the command is ignored.
             //If an integer read does not receive an integer value, this is an error and the command is ignored.
             Command::InNumber => {
-                if let Some(n) = ip.stdin.read_integer() {
-                    stack.push(n);
+                let num_bytes = ip.stdin.num_bytes_read();
+                let read = ip
+                    .stdin
+                    .read_integer_with_overflow(ip.config.number_overflow);
+                if let Some(e) = ip.stdin.take_read_error() {
+                    return Err(PietError::ReadFailed {
+                        message: e.to_string(),
+                    });
                 }
+                let Some((word, value)) = read else {
+                    ip.trace_io(IoEvent::EndOfInput { command: *self })
+                        .map_err(|e| PietError::write_failed("the I/O trace", e))?;
+                    return Ok(());
+                };
+                if let Some(n) = value {
+                    ip.stack.push(n);
+                    ip.last_io = Instant::now();
+                }
+                let event = IoEvent::InNumber {
+                    word,
+                    value,
+                    num_bytes: ip.stdin.num_bytes_read() - num_bytes,
+                    terminator: ip.stdin.last_terminator(),
+                };
+                ip.trace_io(event)
+                    .map_err(|e| PietError::write_failed("the I/O trace", e))?;
             }
 
             //[spec]
@@ -275,9 +331,23 @@ impl Command {
             //If no input is waiting on STDIN, this is an error and the command is ignored.
             //If an integer read does not receive an integer value, this is an error and the command is ignored.
             Command::InChar => {
-                if let Some(c) = ip.stdin.read_char() {
-                    stack.push(c as isize);
+                let num_bytes = ip.stdin.num_bytes_read();
+                let read = ip.stdin.read_char();
+                if let Some(e) = ip.stdin.take_read_error() {
+                    return Err(PietError::ReadFailed {
+                        message: e.to_string(),
+                    });
                 }
+                let Some(c) = read else {
+                    ip.trace_io(IoEvent::EndOfInput { command: *self })
+                        .map_err(|e| PietError::write_failed("the I/O trace", e))?;
+                    return Ok(());
+                };
+                ip.stack.push(c as isize);
+                ip.last_io = Instant::now();
+                let num_bytes = ip.stdin.num_bytes_read() - num_bytes;
+                ip.trace_io(IoEvent::InChar { c, num_bytes })
+                    .map_err(|e| PietError::write_failed("the I/O trace", e))?;
             }
 
             //[spec]
@@ -286,7 +356,10 @@ impl Command {
             Command::OutNumber => {
                 if !stack.is_empty() {
                     let x = stack.pop().unwrap();
-                    ip.output(&format!("{}\n", x));
+                    ip.output(&format!("{}\n", x))
+                        .map_err(|e| PietError::write_failed("the output", e))?;
+                    ip.trace_io(IoEvent::OutNumber(x))
+                        .map_err(|e| PietError::write_failed("the I/O trace", e))?;
                 }
             }
 
@@ -296,13 +369,18 @@ impl Command {
             Command::OutChar => {
                 if !stack.is_empty() {
                     let x = *stack.last().unwrap();
-                    if (0 <= x) && (x <= char::MAX as isize) {
+                    //Not only out of range values but also surrogates (`0xD800..=0xDFFF`) are not `char`.
+                    if let Some(c) = u32::try_from(x).ok().and_then(char::from_u32) {
                         stack.pop().unwrap();
-                        ip.output(&format!("{}", char::from_u32(x as u32).unwrap()));
+                        ip.output(&format!("{}", c))
+                            .map_err(|e| PietError::write_failed("the output", e))?;
+                        ip.trace_io(IoEvent::OutChar(c))
+                            .map_err(|e| PietError::write_failed("the I/O trace", e))?;
                     }
                 }
             }
         }
+        Ok(())
     }
 }
 
@@ -314,12 +392,48 @@ mod tests {
     use super::super::dp::DP;
     use super::*;
 
+    #[test]
+    fn test_table() {
+        let table = Command::table();
+        assert_eq!(None, table[0][0]);
+        assert_eq!(Some(Command::Push), table[0][1]);
+        assert_eq!(Some(Command::Pointer), table[3][1]);
+        assert_eq!(Some(Command::OutChar), table[5][2]);
+        //every command appears exactly once
+        let commands = table.iter().flatten().flatten().collect_vec();
+        assert_eq!(17, commands.len());
+        assert_eq!(17, commands.iter().unique().count());
+
+        //consistent with `Command::new()` for every ordered pair of the 18 colors
+        let colors = (0..6)
+            .cartesian_product(0..3)
+            .map(|(hue, lightness)| Codel::from_hue_lightness(hue, lightness))
+            .collect_vec();
+        for (from, to) in colors.iter().cartesian_product(&colors) {
+            let hue = (to.get_hue() + 6 - from.get_hue()) % 6;
+            let lightness = (to.get_lightness() + 3 - from.get_lightness()) % 3;
+            assert_eq!(hue, Codel::get_hue_difference(from, to));
+            assert_eq!(lightness, Codel::get_lightness_difference(from, to));
+            if from == to {
+                assert_eq!(None, table[hue][lightness]);
+            } else {
+                assert_eq!(
+                    Some(Command::new(from, to)),
+                    table[hue][lightness],
+                    "{:?} -> {:?}",
+                    from,
+                    to
+                );
+            }
+        }
+    }
+
     #[test]
     fn test_push() {
         let command = Command::Push;
         let mut ip = Interpreter::new();
         ip.stack = vec![1, 2];
-        command.execute(&mut ip, 3);
+        command.execute(&mut ip, 3).unwrap();
         assert_eq!(vec![1, 2, 3], ip.stack);
     }
 
@@ -328,12 +442,12 @@ mod tests {
         let command = Command::Pop;
 
         let mut ip = Interpreter::new();
-        command.execute(&mut ip, 1);
+        command.execute(&mut ip, 1).unwrap();
         assert!(ip.stack.is_empty());
 
         let mut ip = Interpreter::new();
         ip.stack = vec![1, 2];
-        command.execute(&mut ip, 1);
+        command.execute(&mut ip, 1).unwrap();
         assert_eq!(ip.stack, vec![1]);
     }
 
@@ -342,18 +456,24 @@ mod tests {
         let command = Command::Add;
 
         let mut ip = Interpreter::new();
-        command.execute(&mut ip, 1);
+        command.execute(&mut ip, 1).unwrap();
         assert!(ip.stack.is_empty());
 
         let mut ip = Interpreter::new();
         ip.stack = vec![1];
-        command.execute(&mut ip, 1);
+        command.execute(&mut ip, 1).unwrap();
         assert_eq!(vec![1], ip.stack);
 
         let mut ip = Interpreter::new();
         ip.stack = vec![1, 2];
-        command.execute(&mut ip, 1);
+        command.execute(&mut ip, 1).unwrap();
         assert_eq!(vec![3], ip.stack);
+
+        //overflow wraps around
+        let mut ip = Interpreter::new();
+        ip.stack = vec![isize::MAX, 1];
+        command.execute(&mut ip, 1).unwrap();
+        assert_eq!(vec![isize::MIN], ip.stack);
     }
 
     #[test]
@@ -361,18 +481,24 @@ mod tests {
         let command = Command::Subtract;
 
         let mut ip = Interpreter::new();
-        command.execute(&mut ip, 1);
+        command.execute(&mut ip, 1).unwrap();
         assert!(ip.stack.is_empty());
 
         let mut ip = Interpreter::new();
         ip.stack = vec![1];
-        command.execute(&mut ip, 1);
+        command.execute(&mut ip, 1).unwrap();
         assert_eq!(vec![1], ip.stack);
 
         let mut ip = Interpreter::new();
         ip.stack = vec![1, 2];
-        command.execute(&mut ip, 1);
+        command.execute(&mut ip, 1).unwrap();
         assert_eq!(vec![-1], ip.stack);
+
+        //overflow wraps around
+        let mut ip = Interpreter::new();
+        ip.stack = vec![isize::MIN, 1];
+        command.execute(&mut ip, 1).unwrap();
+        assert_eq!(vec![isize::MAX], ip.stack);
     }
 
     #[test]
@@ -380,18 +506,24 @@ mod tests {
         let command = Command::Multiply;
 
         let mut ip = Interpreter::new();
-        command.execute(&mut ip, 1);
+        command.execute(&mut ip, 1).unwrap();
         assert!(ip.stack.is_empty());
 
         let mut ip = Interpreter::new();
         ip.stack = vec![1];
-        command.execute(&mut ip, 1);
+        command.execute(&mut ip, 1).unwrap();
         assert_eq!(vec![1], ip.stack);
 
         let mut ip = Interpreter::new();
         ip.stack = vec![2, 3];
-        command.execute(&mut ip, 1);
+        command.execute(&mut ip, 1).unwrap();
         assert_eq!(vec![6], ip.stack);
+
+        //overflow wraps around
+        let mut ip = Interpreter::new();
+        ip.stack = vec![isize::MAX, 2];
+        command.execute(&mut ip, 1).unwrap();
+        assert_eq!(vec![-2], ip.stack);
     }
 
     #[test]
@@ -399,24 +531,30 @@ mod tests {
         let command = Command::Divide;
 
         let mut ip = Interpreter::new();
-        command.execute(&mut ip, 1);
+        command.execute(&mut ip, 1).unwrap();
         assert!(ip.stack.is_empty());
 
         let mut ip = Interpreter::new();
         ip.stack = vec![1];
-        command.execute(&mut ip, 1);
+        command.execute(&mut ip, 1).unwrap();
         assert_eq!(vec![1], ip.stack);
 
         let mut ip = Interpreter::new();
         ip.stack = vec![7, 3];
-        command.execute(&mut ip, 1);
+        command.execute(&mut ip, 1).unwrap();
         assert_eq!(vec![2], ip.stack);
 
         //zero-division
         let mut ip = Interpreter::new();
         ip.stack = vec![2, 7, 0];
-        command.execute(&mut ip, 1);
+        command.execute(&mut ip, 1).unwrap();
         assert_eq!(vec![2, 7, 0], ip.stack);
+
+        //overflow
+        let mut ip = Interpreter::new();
+        ip.stack = vec![isize::MIN, -1];
+        command.execute(&mut ip, 1).unwrap();
+        assert_eq!(vec![isize::MIN], ip.stack);
     }
 
     #[test]
@@ -424,43 +562,64 @@ mod tests {
         let command = Command::Mod;
 
         let mut ip = Interpreter::new();
-        command.execute(&mut ip, 1);
+        command.execute(&mut ip, 1).unwrap();
         assert!(ip.stack.is_empty());
 
         let mut ip = Interpreter::new();
         ip.stack = vec![1];
-        command.execute(&mut ip, 1);
+        command.execute(&mut ip, 1).unwrap();
         assert_eq!(vec![1], ip.stack);
 
         let mut ip = Interpreter::new();
         ip.stack = vec![5, 3];
-        command.execute(&mut ip, 1);
+        command.execute(&mut ip, 1).unwrap();
         assert_eq!(vec![2], ip.stack);
 
         let mut ip = Interpreter::new();
         ip.stack = vec![2, 3];
-        command.execute(&mut ip, 1);
+        command.execute(&mut ip, 1).unwrap();
         assert_eq!(vec![2], ip.stack);
 
         let mut ip = Interpreter::new();
         ip.stack = vec![-1, 3];
-        command.execute(&mut ip, 1);
+        command.execute(&mut ip, 1).unwrap();
         assert_eq!(vec![2], ip.stack);
 
         let mut ip = Interpreter::new();
         ip.stack = vec![-5, 3];
-        command.execute(&mut ip, 1);
+        command.execute(&mut ip, 1).unwrap();
         assert_eq!(vec![1], ip.stack);
 
         let mut ip = Interpreter::new();
         ip.stack = vec![-5, -3];
-        command.execute(&mut ip, 1);
+        command.execute(&mut ip, 1).unwrap();
         assert_eq!(vec![-2], ip.stack);
 
+        let mut ip = Interpreter::new();
+        ip.stack = vec![5, -3];
+        command.execute(&mut ip, 1).unwrap();
+        assert_eq!(vec![-1], ip.stack);
+
+        let mut ip = Interpreter::new();
+        ip.stack = vec![6, -3];
+        command.execute(&mut ip, 1).unwrap();
+        assert_eq!(vec![0], ip.stack);
+
+        //overflow
+        let mut ip = Interpreter::new();
+        ip.stack = vec![isize::MIN, -1];
+        command.execute(&mut ip, 1).unwrap();
+        assert_eq!(vec![0], ip.stack);
+
+        let mut ip = Interpreter::new();
+        ip.stack = vec![isize::MIN, isize::MAX];
+        command.execute(&mut ip, 1).unwrap();
+        assert_eq!(vec![isize::MAX - 1], ip.stack);
+
         //zero-division
         let mut ip = Interpreter::new();
         ip.stack = vec![2, 7, 0];
-        command.execute(&mut ip, 1);
+        command.execute(&mut ip, 1).unwrap();
         assert_eq!(vec![2, 7, 0], ip.stack);
     }
 
@@ -469,22 +628,22 @@ mod tests {
         let command = Command::Not;
 
         let mut ip = Interpreter::new();
-        command.execute(&mut ip, 1);
+        command.execute(&mut ip, 1).unwrap();
         assert!(ip.stack.is_empty());
 
         let mut ip = Interpreter::new();
         ip.stack = vec![0];
-        command.execute(&mut ip, 1);
+        command.execute(&mut ip, 1).unwrap();
         assert_eq!(vec![1], ip.stack);
 
         let mut ip = Interpreter::new();
         ip.stack = vec![1];
-        command.execute(&mut ip, 1);
+        command.execute(&mut ip, 1).unwrap();
         assert_eq!(vec![0], ip.stack);
 
         let mut ip = Interpreter::new();
         ip.stack = vec![2];
-        command.execute(&mut ip, 1);
+        command.execute(&mut ip, 1).unwrap();
         assert_eq!(vec![0], ip.stack);
     }
 
@@ -493,27 +652,27 @@ mod tests {
         let command = Command::Greater;
 
         let mut ip = Interpreter::new();
-        command.execute(&mut ip, 1);
+        command.execute(&mut ip, 1).unwrap();
         assert!(ip.stack.is_empty());
 
         let mut ip = Interpreter::new();
         ip.stack = vec![0];
-        command.execute(&mut ip, 1);
+        command.execute(&mut ip, 1).unwrap();
         assert_eq!(vec![0], ip.stack);
 
         let mut ip = Interpreter::new();
         ip.stack = vec![1, 0];
-        command.execute(&mut ip, 1);
+        command.execute(&mut ip, 1).unwrap();
         assert_eq!(vec![1], ip.stack);
 
         let mut ip = Interpreter::new();
         ip.stack = vec![1, 1];
-        command.execute(&mut ip, 1);
+        command.execute(&mut ip, 1).unwrap();
         assert_eq!(vec![0], ip.stack);
 
         let mut ip = Interpreter::new();
         ip.stack = vec![1, 2];
-        command.execute(&mut ip, 1);
+        command.execute(&mut ip, 1).unwrap();
         assert_eq!(vec![0], ip.stack);
     }
 
@@ -522,26 +681,42 @@ mod tests {
         let command = Command::Pointer;
 
         let mut ip = Interpreter::new();
-        command.execute(&mut ip, 1);
+        command.execute(&mut ip, 1).unwrap();
         assert_eq!(DP::Right, ip.dp);
 
         let mut ip = Interpreter::new();
         ip.stack = vec![0];
-        command.execute(&mut ip, 1);
+        command.execute(&mut ip, 1).unwrap();
         assert!(ip.stack.is_empty());
         assert_eq!(DP::Right, ip.dp);
 
         let mut ip = Interpreter::new();
         ip.stack = vec![2];
-        command.execute(&mut ip, 1);
+        command.execute(&mut ip, 1).unwrap();
         assert!(ip.stack.is_empty());
         assert_eq!(DP::Left, ip.dp);
 
         let mut ip = Interpreter::new();
         ip.stack = vec![-1];
-        command.execute(&mut ip, 1);
+        command.execute(&mut ip, 1).unwrap();
+        assert!(ip.stack.is_empty());
+        assert_eq!(DP::Up, ip.dp);
+
+        //`isize::MIN` is a multiple of `4`
+        let mut ip = Interpreter::new();
+        ip.dp = DP::Up;
+        ip.stack = vec![isize::MIN];
+        command.execute(&mut ip, 1).unwrap();
         assert!(ip.stack.is_empty());
         assert_eq!(DP::Up, ip.dp);
+
+        //`isize::MAX` is `3` modulo `4`
+        let mut ip = Interpreter::new();
+        ip.dp = DP::Up;
+        ip.stack = vec![isize::MAX];
+        command.execute(&mut ip, 1).unwrap();
+        assert!(ip.stack.is_empty());
+        assert_eq!(DP::Left, ip.dp);
     }
 
     #[test]
@@ -549,36 +724,49 @@ mod tests {
         let command = Command::Switch;
 
         let mut ip = Interpreter::new();
-        command.execute(&mut ip, 1);
+        command.execute(&mut ip, 1).unwrap();
         assert_eq!(CC::Left, ip.cc);
 
         let mut ip = Interpreter::new();
         ip.stack = vec![0];
-        command.execute(&mut ip, 1);
+        command.execute(&mut ip, 1).unwrap();
         assert!(ip.stack.is_empty());
         assert_eq!(CC::Left, ip.cc);
 
         let mut ip = Interpreter::new();
         ip.stack = vec![1];
-        command.execute(&mut ip, 1);
+        command.execute(&mut ip, 1).unwrap();
         assert!(ip.stack.is_empty());
         assert_eq!(CC::Right, ip.cc);
 
         let mut ip = Interpreter::new();
         ip.stack = vec![2];
-        command.execute(&mut ip, 1);
+        command.execute(&mut ip, 1).unwrap();
         assert!(ip.stack.is_empty());
         assert_eq!(CC::Left, ip.cc);
 
         let mut ip = Interpreter::new();
         ip.stack = vec![3];
-        command.execute(&mut ip, 1);
+        command.execute(&mut ip, 1).unwrap();
         assert!(ip.stack.is_empty());
         assert_eq!(CC::Right, ip.cc);
 
         let mut ip = Interpreter::new();
         ip.stack = vec![-1];
-        command.execute(&mut ip, 1);
+        command.execute(&mut ip, 1).unwrap();
+        assert!(ip.stack.is_empty());
+        assert_eq!(CC::Right, ip.cc);
+
+        //the absolute value of `isize::MIN` is not representable, but it is even anyway
+        let mut ip = Interpreter::new();
+        ip.stack = vec![isize::MIN];
+        command.execute(&mut ip, 1).unwrap();
+        assert!(ip.stack.is_empty());
+        assert_eq!(CC::Left, ip.cc);
+
+        let mut ip = Interpreter::new();
+        ip.stack = vec![isize::MIN + 1];
+        command.execute(&mut ip, 1).unwrap();
         assert!(ip.stack.is_empty());
         assert_eq!(CC::Right, ip.cc);
     }
@@ -588,12 +776,12 @@ mod tests {
         let command = Command::Duplicate;
 
         let mut ip = Interpreter::new();
-        command.execute(&mut ip, 1);
+        command.execute(&mut ip, 1).unwrap();
         assert!(ip.stack.is_empty());
 
         let mut ip = Interpreter::new();
         ip.stack = vec![1];
-        command.execute(&mut ip, 1);
+        command.execute(&mut ip, 1).unwrap();
         assert_eq!(vec![1, 1], ip.stack);
     }
 
@@ -605,37 +793,37 @@ mod tests {
         //the length of stack is insufficient
         let mut ip = Interpreter::new();
         ip.stack = vec![9];
-        command.execute(&mut ip, 1);
+        command.execute(&mut ip, 1).unwrap();
         assert_eq!(vec![9], ip.stack);
 
         //negative depth
         let mut ip = Interpreter::new();
         ip.stack = vec![9, 8, 7, 1, 2, 3, 4, -2, 5];
-        command.execute(&mut ip, 1);
+        command.execute(&mut ip, 1).unwrap();
         assert_eq!(vec![9, 8, 7, 1, 2, 3, 4, -2, 5], ip.stack);
 
         //zero depth
         let mut ip = Interpreter::new();
         ip.stack = vec![9, 8, 7, 1, 2, 3, 4, 0, 5];
-        command.execute(&mut ip, 1);
+        command.execute(&mut ip, 1).unwrap();
         assert_eq!(vec![9, 8, 7, 1, 2, 3, 4], ip.stack);
 
         //one depth
         let mut ip = Interpreter::new();
         ip.stack = vec![9, 8, 7, 1, 2, 3, 4, 1, 5];
-        command.execute(&mut ip, 1);
+        command.execute(&mut ip, 1).unwrap();
         assert_eq!(vec![9, 8, 7, 1, 2, 3, 4], ip.stack);
 
         //depth is too large
         let mut ip = Interpreter::new();
         ip.stack = vec![9, 8, 7, 1, 2, 3, 4, 8, 5];
-        command.execute(&mut ip, 1);
+        command.execute(&mut ip, 1).unwrap();
         assert_eq!(vec![9, 8, 7, 1, 2, 3, 4, 8, 5], ip.stack);
 
         //zero number of rotations
         let mut ip = Interpreter::new();
         ip.stack = vec![9, 8, 7, 1, 2, 3, 4, 4, 0];
-        command.execute(&mut ip, 1);
+        command.execute(&mut ip, 1).unwrap();
         assert_eq!(vec![9, 8, 7, 1, 2, 3, 4], ip.stack);
     }
 
@@ -646,28 +834,28 @@ mod tests {
 
         let mut ip = Interpreter::new();
         ip.stack = vec![9, 1, 2, 3, 4, 4, 1];
-        command.execute(&mut ip, 1);
+        command.execute(&mut ip, 1).unwrap();
         assert_eq!(vec![9, 4, 1, 2, 3], ip.stack);
 
         let mut ip = Interpreter::new();
         ip.stack = vec![9, 1, 2, 3, 4, 4, 2];
-        command.execute(&mut ip, 1);
+        command.execute(&mut ip, 1).unwrap();
         assert_eq!(vec![9, 3, 4, 1, 2], ip.stack);
 
         let mut ip = Interpreter::new();
         ip.stack = vec![9, 1, 2, 3, 4, 4, 3];
-        command.execute(&mut ip, 1);
+        command.execute(&mut ip, 1).unwrap();
         assert_eq!(vec![9, 2, 3, 4, 1], ip.stack);
 
         let mut ip = Interpreter::new();
         ip.stack = vec![9, 1, 2, 3, 4, 4, 4];
-        command.execute(&mut ip, 1);
+        command.execute(&mut ip, 1).unwrap();
         assert_eq!(vec![9, 1, 2, 3, 4], ip.stack);
 
         //expects the complexity is independent of `num_roll`
         let mut ip = Interpreter::new();
         ip.stack = vec![9, 1, 2, 3, 4, 4, 4 * 10isize.pow(8) + 1];
-        command.execute(&mut ip, 1);
+        command.execute(&mut ip, 1).unwrap();
         assert_eq!(vec![9, 4, 1, 2, 3], ip.stack);
     }
 
@@ -678,29 +866,40 @@ mod tests {
 
         let mut ip = Interpreter::new();
         ip.stack = vec![9, 1, 2, 3, 4, 4, -1];
-        command.execute(&mut ip, 1);
+        command.execute(&mut ip, 1).unwrap();
         assert_eq!(vec![9, 2, 3, 4, 1], ip.stack);
 
         let mut ip = Interpreter::new();
         ip.stack = vec![9, 1, 2, 3, 4, 4, -2];
-        command.execute(&mut ip, 1);
+        command.execute(&mut ip, 1).unwrap();
         assert_eq!(vec![9, 3, 4, 1, 2], ip.stack);
 
         let mut ip = Interpreter::new();
         ip.stack = vec![9, 1, 2, 3, 4, 4, -3];
-        command.execute(&mut ip, 1);
+        command.execute(&mut ip, 1).unwrap();
         assert_eq!(vec![9, 4, 1, 2, 3], ip.stack);
 
         let mut ip = Interpreter::new();
         ip.stack = vec![9, 1, 2, 3, 4, 4, -4];
-        command.execute(&mut ip, 1);
+        command.execute(&mut ip, 1).unwrap();
         assert_eq!(vec![9, 1, 2, 3, 4], ip.stack);
 
         //expects the complexity is independent of `num_roll`
         let mut ip = Interpreter::new();
         ip.stack = vec![9, 1, 2, 3, 4, 4, -4 * 10isize.pow(8) - 1];
-        command.execute(&mut ip, 1);
+        command.execute(&mut ip, 1).unwrap();
         assert_eq!(vec![9, 2, 3, 4, 1], ip.stack);
+
+        //`isize::MIN` (whose absolute value is not representable) is `-2^63`, which is a multiple of `4`
+        let mut ip = Interpreter::new();
+        ip.stack = vec![9, 1, 2, 3, 4, 4, isize::MIN];
+        command.execute(&mut ip, 1).unwrap();
+        assert_eq!(vec![9, 1, 2, 3, 4], ip.stack);
+
+        let mut ip = Interpreter::new();
+        ip.stack = vec![9, 1, 2, 3, 3, isize::MIN];
+        command.execute(&mut ip, 1).unwrap();
+        assert_eq!(vec![9, 3, 1, 2], ip.stack);
     }
 
     #[test]
@@ -708,26 +907,55 @@ mod tests {
         let command = Command::InNumber;
         let mut ip = Interpreter::new_with_stdin(" -100 abc 🍄🌷 100 ");
 
-        command.execute(&mut ip, 1);
+        command.execute(&mut ip, 1).unwrap();
         assert_eq!(vec![-100], ip.stack);
 
-        command.execute(&mut ip, 1);
+        command.execute(&mut ip, 1).unwrap();
         assert_eq!(vec![-100], ip.stack);
 
-        command.execute(&mut ip, 1);
+        command.execute(&mut ip, 1).unwrap();
         assert_eq!(vec![-100], ip.stack);
 
-        command.execute(&mut ip, 1);
+        command.execute(&mut ip, 1).unwrap();
         assert_eq!(vec![-100, 100], ip.stack);
 
         for _ in 0..2 {
-            command.execute(&mut ip, 1);
+            command.execute(&mut ip, 1).unwrap();
             assert_eq!(vec![-100, 100], ip.stack);
-            command.execute(&mut ip, 1);
+            command.execute(&mut ip, 1).unwrap();
             assert_eq!(vec![-100, 100], ip.stack);
         }
     }
 
+    #[test]
+    fn test_read_number_overflow() {
+        use super::super::stdin::NumberOverflow;
+
+        let command = Command::InNumber;
+        let input = "1 999999999999999999999999 -999999999999999999999999 2";
+        let run = |overflow| {
+            let mut ip = Interpreter::new_with_stdin(input);
+            ip.config.number_overflow = overflow;
+            let mut positions = vec![];
+            for _ in 0..3 {
+                command.execute(&mut ip, 1).unwrap();
+                positions.push(ip.stdin.num_bytes_read());
+            }
+            (ip.stack, positions)
+        };
+
+        let (stack, positions) = run(NumberOverflow::Ignore);
+        assert_eq!(vec![1], stack);
+        let (stack, saturate_positions) = run(NumberOverflow::Saturate);
+        assert_eq!(vec![1, isize::MAX, isize::MIN], stack);
+        let (stack, bigmod_positions) = run(NumberOverflow::Bigmod);
+        assert_eq!(vec![1, 2003764205206896639, -2003764205206896639], stack);
+        //the token is consumed under every policy
+        assert_eq!(vec![2, 27, 53], positions);
+        assert_eq!(positions, saturate_positions);
+        assert_eq!(positions, bigmod_positions);
+    }
+
     #[test]
     fn test_read_char() {
         let command = Command::InChar;
@@ -735,46 +963,46 @@ mod tests {
 
         let f = |v: Vec<char>| -> Vec<isize> { v.into_iter().map(|c| c as isize).collect_vec() };
 
-        command.execute(&mut ip, 1);
+        command.execute(&mut ip, 1).unwrap();
         assert_eq!(f(vec!['-']), ip.stack);
 
-        command.execute(&mut ip, 1);
+        command.execute(&mut ip, 1).unwrap();
         assert_eq!(f(vec!['-', '1']), ip.stack);
 
-        command.execute(&mut ip, 1);
+        command.execute(&mut ip, 1).unwrap();
         assert_eq!(f(vec!['-', '1', 'a']), ip.stack);
 
-        command.execute(&mut ip, 1);
+        command.execute(&mut ip, 1).unwrap();
         assert_eq!(f(vec!['-', '1', 'a', '🌷']), ip.stack);
 
-        command.execute(&mut ip, 1);
+        command.execute(&mut ip, 1).unwrap();
         assert_eq!(f(vec!['-', '1', 'a', '🌷', '🍄']), ip.stack);
 
-        command.execute(&mut ip, 1);
+        command.execute(&mut ip, 1).unwrap();
         assert_eq!(f(vec!['-', '1', 'a', '🌷', '🍄', 'a']), ip.stack);
 
-        command.execute(&mut ip, 1);
+        command.execute(&mut ip, 1).unwrap();
         assert_eq!(f(vec!['-', '1', 'a', '🌷', '🍄', 'a', '🍄']), ip.stack);
 
-        command.execute(&mut ip, 1);
+        command.execute(&mut ip, 1).unwrap();
         assert_eq!(
             f(vec!['-', '1', 'a', '🌷', '🍄', 'a', '🍄', '🍄']),
             ip.stack
         );
 
-        command.execute(&mut ip, 1);
+        command.execute(&mut ip, 1).unwrap();
         assert_eq!(
             f(vec!['-', '1', 'a', '🌷', '🍄', 'a', '🍄', '🍄', 'a']),
             ip.stack
         );
 
         for _ in 0..2 {
-            command.execute(&mut ip, 1);
+            command.execute(&mut ip, 1).unwrap();
             assert_eq!(
                 f(vec!['-', '1', 'a', '🌷', '🍄', 'a', '🍄', '🍄', 'a']),
                 ip.stack
             );
-            command.execute(&mut ip, 1);
+            command.execute(&mut ip, 1).unwrap();
             assert_eq!(
                 f(vec!['-', '1', 'a', '🌷', '🍄', 'a', '🍄', '🍄', 'a']),
                 ip.stack
@@ -787,18 +1015,18 @@ mod tests {
         let command = Command::OutNumber;
 
         let mut ip = Interpreter::new_with_stdin("");
-        command.execute(&mut ip, 1);
+        command.execute(&mut ip, 1).unwrap();
         assert!(ip.stack.is_empty());
 
         let mut ip = Interpreter::new_with_stdin("");
         ip.stack = vec![1];
-        command.execute(&mut ip, 1);
+        command.execute(&mut ip, 1).unwrap();
         assert!(ip.stack.is_empty());
         assert_eq!("1\n".as_bytes(), &ip.output_buf);
 
         let mut ip = Interpreter::new_with_stdin("");
         ip.stack = vec![-1];
-        command.execute(&mut ip, 1);
+        command.execute(&mut ip, 1).unwrap();
         assert!(ip.stack.is_empty());
         assert_eq!("-1\n".as_bytes(), &ip.output_buf);
     }
@@ -808,27 +1036,101 @@ mod tests {
         let command = Command::OutChar;
 
         let mut ip = Interpreter::new();
-        command.execute(&mut ip, 1);
+        command.execute(&mut ip, 1).unwrap();
         assert!(ip.stack.is_empty());
 
         let mut ip = Interpreter::new();
         ip.stack = vec![char::MAX as isize + 1, -1, 'a' as isize, '🍄' as isize];
 
-        command.execute(&mut ip, 1);
+        command.execute(&mut ip, 1).unwrap();
         assert_eq!(vec![char::MAX as isize + 1, -1, 'a' as isize], ip.stack);
         assert_eq!("🍄".as_bytes(), &ip.output_buf);
 
-        command.execute(&mut ip, 1);
+        command.execute(&mut ip, 1).unwrap();
         assert_eq!(vec![char::MAX as isize + 1, -1], ip.stack);
         assert_eq!("🍄a".as_bytes(), &ip.output_buf);
 
-        command.execute(&mut ip, 1);
+        command.execute(&mut ip, 1).unwrap();
         assert_eq!(vec![char::MAX as isize + 1, -1], ip.stack);
         assert_eq!("🍄a".as_bytes(), &ip.output_buf);
 
         ip.stack.pop().unwrap();
-        command.execute(&mut ip, 1);
+        command.execute(&mut ip, 1).unwrap();
         assert_eq!(vec![char::MAX as isize + 1], ip.stack);
         assert_eq!("🍄a".as_bytes(), &ip.output_buf);
+
+        //surrogates are in the range of `char` but are not `char`
+        for x in [0xD800, 0xDFFF] {
+            let mut ip = Interpreter::new();
+            ip.stack = vec![x];
+            command.execute(&mut ip, 1).unwrap();
+            assert_eq!(vec![x], ip.stack);
+            assert!(ip.output_buf.is_empty());
+        }
+    }
+
+    #[test]
+    fn test_io_trace_write_failed() {
+        /// Writer whose every write fails, e.g. a full disk.
+        struct FailingWriter;
+
+        impl std::io::Write for FailingWriter {
+            fn write(&mut self, _: &[u8]) -> std::io::Result<usize> {
+                Err(std::io::ErrorKind::StorageFull.into())
+            }
+
+            fn flush(&mut self) -> std::io::Result<()> {
+                Ok(())
+            }
+        }
+
+        //the command is executed but the run ends with an error instead of a panic
+        let mut ip = Interpreter::new_with_stdin("7");
+        ip.io_trace = Some(Box::new(FailingWriter));
+        let e = Command::InNumber.execute(&mut ip, 1).unwrap_err();
+        assert_eq!(vec![7], ip.stack);
+        assert!(matches!(
+            e,
+            PietError::WriteFailed {
+                what: "the I/O trace",
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    fn test_read_failed() {
+        /// Reader which fails after the bytes of `0`, e.g. a redirected directory (`EISDIR`) after none.
+        struct FailingReader(&'static [u8]);
+
+        impl std::io::Read for FailingReader {
+            fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+                match self.0.split_first() {
+                    Some((&b, rest)) => {
+                        buf[0] = b;
+                        self.0 = rest;
+                        Ok(1)
+                    }
+                    None => Err(std::io::ErrorKind::IsADirectory.into()),
+                }
+            }
+        }
+
+        let error = || PietError::ReadFailed {
+            message: std::io::Error::from(std::io::ErrorKind::IsADirectory).to_string(),
+        };
+
+        //the run ends with an error instead of a panic, and the word cut by the error is not pushed
+        let mut ip = Interpreter::new();
+        ip.stdin.reset_with_reader(Box::new(FailingReader(b"12")));
+        assert_eq!(Err(error()), Command::InNumber.execute(&mut ip, 1));
+        assert!(ip.stack.is_empty());
+
+        let mut ip = Interpreter::new();
+        ip.stdin.reset_with_reader(Box::new(FailingReader(b"1")));
+        Command::InChar.execute(&mut ip, 1).unwrap();
+        assert_eq!(vec!['1' as isize], ip.stack);
+        assert_eq!(Err(error()), Command::InChar.execute(&mut ip, 1));
+        assert_eq!(vec!['1' as isize], ip.stack);
     }
 }
@@ -0,0 +1,332 @@
+/*!
+Comparison run against another Piet interpreter (`compare` or `run --compare-with`).
+
+Both this interpreter and the other one are spawned as child processes with the same program and the same input, and their stdout is compared byte by byte.
+Each side is killed if it does not finish within the timeout so that a hang in either of them does not wedge the comparison.
+*/
+
+use std::error::Error;
+use std::fmt::{self, Display, Formatter};
+use std::io::{self, Read, Write};
+use std::process::{Command, Stdio};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use itertools::{EitherOrBoth, Itertools};
+
+use super::args::{CompareArgs, Format};
+use super::image::{self, DetectFallback};
+use super::limits;
+
+/// Outcome of one side of a comparison.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Side {
+    pub stdout: Vec<u8>,
+    /// `None` if the process was killed (by us on timeout, or by a signal).
+    pub exit_code: Option<i32>,
+    pub timed_out: bool,
+}
+
+impl Side {
+    /// Spawns `command`, writes `input` to its stdin and collects its stdout.
+    pub fn run(mut command: Command, input: &[u8], timeout: Duration) -> io::Result<Self> {
+        let mut child = command
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()?;
+
+        //Writing and reading are done in other threads so that neither a full pipe nor a hang blocks the timeout check.
+        let mut stdin = child.stdin.take().unwrap();
+        let input = input.to_vec();
+        thread::spawn(move || {
+            //the child may exit without reading the whole input
+            let _ = stdin.write_all(&input);
+        });
+        let mut stdout = child.stdout.take().unwrap();
+        let reader = thread::spawn(move || {
+            let mut buf = vec![];
+            let _ = stdout.read_to_end(&mut buf);
+            buf
+        });
+
+        let deadline = Instant::now() + timeout;
+        let (status, timed_out) = loop {
+            if let Some(status) = child.try_wait()? {
+                break (status, false);
+            }
+            if Instant::now() >= deadline {
+                child.kill()?;
+                break (child.wait()?, true);
+            }
+            thread::sleep(Duration::from_millis(10));
+        };
+
+        Ok(Self {
+            stdout: reader.join().unwrap(),
+            exit_code: if timed_out { None } else { status.code() },
+            timed_out,
+        })
+    }
+}
+
+impl Display for Side {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match (self.timed_out, self.exit_code) {
+            (true, _) => write!(f, "timed out"),
+            (false, Some(code)) => write!(f, "exit code {}", code),
+            (false, None) => write!(f, "killed by a signal"),
+        }
+    }
+}
+
+/// Result of running a program with both interpreters.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Comparison {
+    pub ours: Side,
+    pub theirs: Side,
+}
+
+impl Comparison {
+    /// Returns `true` if both sides finished in time and printed the same output.
+    /// Exit codes are reported but not compared as they differ between implementations for no good reason.
+    pub fn is_match(&self) -> bool {
+        !self.ours.timed_out && !self.theirs.timed_out && (self.ours.stdout == self.theirs.stdout)
+    }
+
+    /// Line-by-line diff of the outputs, where `-` is ours and `+` is theirs.
+    pub fn diff(&self) -> String {
+        let ours = String::from_utf8_lossy(&self.ours.stdout);
+        let theirs = String::from_utf8_lossy(&self.theirs.stdout);
+        let mut s = String::new();
+        for (i, e) in ours.lines().zip_longest(theirs.lines()).enumerate() {
+            match e {
+                EitherOrBoth::Both(l, r) if l == r => (),
+                EitherOrBoth::Both(l, r) => s += &format!("line {}:\n- {}\n+ {}\n", i + 1, l, r),
+                EitherOrBoth::Left(l) => s += &format!("line {}:\n- {}\n", i + 1, l),
+                EitherOrBoth::Right(r) => s += &format!("line {}:\n+ {}\n", i + 1, r),
+            }
+        }
+        //e.g. only a trailing newline differs
+        if s.is_empty() && !self.is_match() {
+            s = format!("- {:?}\n+ {:?}\n", ours, theirs);
+        }
+        s
+    }
+}
+
+/// Returns the options of `args` which affect execution, so that our side runs the program exactly as requested.
+fn forwarded_args(args: &CompareArgs) -> Vec<String> {
+    let load = &args.load;
+    let mut v = vec![
+        "run".to_string(),
+        load.image_file.clone(),
+        "--quiet".to_string(),
+    ];
+    match load.format {
+        Format::Auto => (),
+        Format::Image => v.extend(["--format".to_string(), "image".to_string()]),
+        Format::Pietdev => v.extend(["--format".to_string(), "pietdev".to_string()]),
+    }
+    if let Some(codel_size) = load.codel_size {
+        v.extend(["--codel-size".to_string(), codel_size.to_string()]);
+    }
+    if let Some(grid) = load.codel_grid {
+        v.extend(["--codel-grid".to_string(), grid.to_string()]);
+    }
+    if load.detect_grid_offset {
+        v.push("--detect-grid-offset".to_string());
+    }
+    if load.detect_budget != image::DEFAULT_DETECT_BUDGET_MS {
+        v.extend([
+            "--detect-budget".to_string(),
+            load.detect_budget.to_string(),
+        ]);
+    }
+    if load.detect_fallback != DetectFallback::default() {
+        v.extend([
+            "--detect-fallback".to_string(),
+            load.detect_fallback.to_string(),
+        ]);
+    }
+    if load.strict_codel_check {
+        v.push("--strict-codel-check".to_string());
+    }
+    if let Some(crop) = load.crop {
+        v.extend(["--crop".to_string(), crop.to_string()]);
+    }
+    if let Some(frame) = load.frame {
+        v.extend(["--frame".to_string(), frame.to_string()]);
+    }
+    if load.fall_back_to_white {
+        v.push("--fall-back-to-white".to_string());
+    }
+    if load.fall_back_to_black {
+        v.push("--fall-back-to-black".to_string());
+    }
+    if load.color_tolerance != 0 {
+        v.extend([
+            "--color-tolerance".to_string(),
+            load.color_tolerance.to_string(),
+        ]);
+    }
+    if let Some(palette) = &load.palette {
+        v.extend(["--palette".to_string(), palette.clone()]);
+    }
+    if let Some(dir) = &load.cache_dir {
+        v.extend(["--cache-dir".to_string(), dir.clone()]);
+    }
+    if load.max_image_pixels != limits::DEFAULT_MAX_IMAGE_PIXELS {
+        v.extend([
+            "--max-image-pixels".to_string(),
+            load.max_image_pixels.to_string(),
+        ]);
+    }
+    if load.max_image_dimension != limits::DEFAULT_MAX_IMAGE_DIMENSION {
+        v.extend([
+            "--max-image-dimension".to_string(),
+            load.max_image_dimension.to_string(),
+        ]);
+    }
+    if load.max_image_bytes != limits::DEFAULT_MAX_IMAGE_BYTES {
+        v.extend([
+            "--max-image-bytes".to_string(),
+            load.max_image_bytes.to_string(),
+        ]);
+    }
+    //`entry` and `example` are rejected by `CompareArgs::validate()`, and the manifest is read only for `entry`.
+    if let Some(max_iter) = args.max_iter {
+        v.extend(["--max-iter".to_string(), max_iter.to_string()]);
+    }
+    if args.lenient_start {
+        v.push("--lenient-start".to_string());
+    }
+    v
+}
+
+/// Runs the program in `args.load.image_file` with this interpreter and `args.with`, passing `input` to both.
+pub fn compare(args: &CompareArgs, input: &[u8]) -> Result<Comparison, Box<dyn Error>> {
+    let timeout = Duration::from_secs(args.timeout);
+    let other = &args.with;
+
+    let mut ours = Command::new(std::env::current_exe()?);
+    ours.args(forwarded_args(args));
+    let mut theirs = Command::new(other);
+    theirs.arg(&args.load.image_file);
+
+    //Both sides run concurrently so that the comparison takes at most one timeout.
+    let ours = thread::spawn({
+        let input = input.to_vec();
+        move || Side::run(ours, &input, timeout)
+    });
+    let theirs = Side::run(theirs, input, timeout)
+        .map_err(|e| format!("failed to run `{}`: {}", other, e))?;
+    let ours = ours.join().unwrap()?;
+
+    Ok(Comparison { ours, theirs })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::args::{Cli, Subcommand};
+    use super::*;
+
+    fn side(stdout: &str) -> Side {
+        Side {
+            stdout: stdout.as_bytes().to_vec(),
+            exit_code: Some(0),
+            timed_out: false,
+        }
+    }
+
+    #[test]
+    fn test_diff() {
+        let c = Comparison {
+            ours: side("1\n2\n3\n"),
+            theirs: side("1\n5\n3\n4\n"),
+        };
+        assert!(!c.is_match());
+        assert_eq!("line 2:\n- 2\n+ 5\nline 4:\n+ 4\n", c.diff());
+
+        let c = Comparison {
+            ours: side("1"),
+            theirs: side("1\n"),
+        };
+        assert!(!c.is_match());
+        assert_eq!("- \"1\"\n+ \"1\\n\"\n", c.diff());
+
+        let c = Comparison {
+            ours: side("1\n"),
+            theirs: side("1\n"),
+        };
+        assert!(c.is_match());
+        assert_eq!("", c.diff());
+    }
+
+    #[test]
+    fn test_timeout() {
+        let mut command = Command::new("sleep");
+        command.arg("10");
+        let start = Instant::now();
+        let side = Side::run(command, b"", Duration::from_millis(100)).unwrap();
+        assert!(start.elapsed() < Duration::from_secs(5));
+        assert!(side.timed_out);
+        assert_eq!(None, side.exit_code);
+        assert_eq!("timed out", side.to_string());
+    }
+
+    #[test]
+    fn test_input_is_passed() {
+        let side = Side::run(Command::new("cat"), b"abc", Duration::from_secs(10)).unwrap();
+        assert_eq!(b"abc".to_vec(), side.stdout);
+        assert_eq!(Some(0), side.exit_code);
+    }
+
+    #[test]
+    fn test_forwarded_args() {
+        let parse = |v: &[&str]| {
+            let mut args = vec![
+                "piet_programming_language",
+                "compare",
+                "img.png",
+                "--with",
+                "echo",
+                "--stdin-string",
+                "",
+            ];
+            args.extend(v);
+            match Cli::parse_with_default_subcommand(args.iter().map(|s| s.to_string())).command {
+                Subcommand::Compare(args) => forwarded_args(&args),
+                _ => unreachable!(),
+            }
+        };
+        assert_eq!(vec!["run", "img.png", "--quiet"], parse(&[]));
+
+        //every option of loading reaches our side
+        let options = [
+            "--format",
+            "image",
+            "--codel-size",
+            "2",
+            "--strict-codel-check",
+            "--crop",
+            "0,0,10,20",
+            "--frame",
+            "1",
+            "--fall-back-to-white",
+            "--color-tolerance",
+            "8",
+            "--palette",
+            "colors.palette",
+            "--cache-dir",
+            "cache",
+            "--max-image-pixels",
+            "100",
+            "--max-image-dimension",
+            "10",
+            "--max-image-bytes",
+            "1000",
+        ];
+        assert_eq!(&options, &parse(&options)[3..]);
+    }
+}
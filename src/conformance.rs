@@ -0,0 +1,416 @@
+/*!
+Conformance test vectors for other implementations (`conformance --export-conformance` and `--verify-conformance`).
+
+The cases to export are listed in a file with one case per line (`#` starts a comment):
+
+```text
+# name  image                               input (optional)
+hi      test_images/original___hi.pietdev
+adder   test_images/adder.png               adder.input
+```
+
+The paths are relative to the list, and the names shall consist of ASCII alphanumerics, `_` and `-`.
+Each case is run with this interpreter and written to its own directory with the expected results (see [`readme()`] for the layout),
+so that another implementation can check itself against them without building this crate.
+*/
+
+use std::error::Error;
+use std::path::{Path, PathBuf};
+
+use super::report;
+use super::run_result::TerminationReason;
+use super::stable::{Fnv64, Sink};
+use super::trace::TraceEvent;
+
+/// Incremented whenever the layout of an exported directory or the meaning of its contents changes.
+pub const SCHEMA_VERSION: u32 = 1;
+
+/// File of the list of the cases in an exported directory.
+pub const INDEX_FILE: &str = "index.json";
+/// Documentation generated in an exported directory.
+pub const README_FILE: &str = "README.md";
+/// Program of a case, with one pixel per codel.
+pub const PROGRAM_FILE: &str = "program.png";
+/// Input of a case (possibly empty).
+pub const INPUT_FILE: &str = "input.bin";
+/// Expected output of a case.
+pub const OUTPUT_FILE: &str = "output.bin";
+/// Expected results of a case other than the output.
+pub const EXPECTED_FILE: &str = "expected.json";
+
+/// One line of a list of cases.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Case {
+    pub name: String,
+    pub image: PathBuf,
+    pub input: Option<PathBuf>,
+}
+
+fn is_valid_name(name: &str) -> bool {
+    !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || (c == '_') || (c == '-'))
+}
+
+/// Parses a list of cases, whose paths are relative to `dir`.
+pub fn parse_cases(s: &str, dir: &Path) -> Result<Vec<Case>, Box<dyn Error>> {
+    let mut cases: Vec<Case> = vec![];
+    for (line, content) in s.lines().enumerate() {
+        let line = line + 1;
+        let content = content.split_once('#').map_or(content, |(s, _)| s);
+        let words = content.split_whitespace().collect::<Vec<_>>();
+        let (name, image, input) = match words[..] {
+            [] => continue,
+            [name, image] => (name, image, None),
+            [name, image, input] => (name, image, Some(input)),
+            _ => {
+                return Err(format!(
+                    "line {}: expected `<name> <image> [<input>]` but found `{}`",
+                    line,
+                    content.trim()
+                )
+                .into())
+            }
+        };
+        if !is_valid_name(name) {
+            return Err(format!("line {}: invalid case name `{}`", line, name).into());
+        }
+        if cases.iter().any(|c| c.name == name) {
+            return Err(format!("line {}: case `{}` is listed twice", line, name).into());
+        }
+        cases.push(Case {
+            name: name.to_string(),
+            image: dir.join(image),
+            input: input.map(|input| dir.join(input)),
+        });
+    }
+    if cases.is_empty() {
+        return Err("no cases are listed".into());
+    }
+    Ok(cases)
+}
+
+/// Results of a run which another implementation is expected to reproduce (other than the output, which is kept as it is in [`OUTPUT_FILE`]).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Expected {
+    pub reason: TerminationReason,
+    /// Number of iterations (moves from a codel).
+    pub num_steps: usize,
+    /// Number of executed commands.
+    pub num_commands: usize,
+    pub num_output_bytes: usize,
+    /// See [`trace_digest()`].
+    pub trace_digest: u64,
+}
+
+fn reason_name(reason: TerminationReason) -> &'static str {
+    match reason {
+        TerminationReason::NoExit => "no-exit",
+        TerminationReason::WhiteRetrace => "white-retrace",
+        TerminationReason::MaxIter => "max-iter",
+        TerminationReason::Stall => "stall",
+        TerminationReason::Cancelled => "cancelled",
+    }
+}
+
+/// Returns the raw value of `key` in a single-line JSON object written by this module.
+/// This is not a general JSON parser: it relies on the values being numbers or strings without `,`, `"` or `}` (the names are restricted accordingly).
+fn json_value<'a>(json: &'a str, key: &str) -> Option<&'a str> {
+    let (_, rest) = json.split_once(&format!(r#""{}":"#, key))?;
+    let end = rest.find([',', '}'])?;
+    Some(rest[..end].trim_matches('"'))
+}
+
+impl Expected {
+    pub fn to_json(&self, name: &str) -> String {
+        format!(
+            r#"{{"schema_version":{},"name":"{}","termination":"{}","steps":{},"commands":{},"output_bytes":{},"trace_digest":"{:016x}"}}"#,
+            SCHEMA_VERSION,
+            name,
+            reason_name(self.reason),
+            self.num_steps,
+            self.num_commands,
+            self.num_output_bytes,
+            self.trace_digest
+        )
+    }
+
+    pub fn from_json(json: &str) -> Result<Self, Box<dyn Error>> {
+        check_schema_version(json)?;
+        let value = |key: &str| json_value(json, key).ok_or(format!("`{}` is missing", key));
+        let number = |key: &str| -> Result<usize, Box<dyn Error>> {
+            Ok(value(key)?
+                .parse()
+                .map_err(|_| format!("`{}` is not a number", key))?)
+        };
+        let reason = [
+            TerminationReason::NoExit,
+            TerminationReason::WhiteRetrace,
+            TerminationReason::MaxIter,
+            TerminationReason::Stall,
+            TerminationReason::Cancelled,
+        ]
+        .into_iter()
+        .find(|&r| Ok(reason_name(r)) == value("termination"))
+        .ok_or("`termination` is invalid")?;
+        Ok(Self {
+            reason,
+            num_steps: number("steps")?,
+            num_commands: number("commands")?,
+            num_output_bytes: number("output_bytes")?,
+            trace_digest: u64::from_str_radix(value("trace_digest")?, 16)
+                .map_err(|_| "`trace_digest` is not a hexadecimal number")?,
+        })
+    }
+
+    /// Returns a description of each difference of `actual` from `self`.
+    pub fn differences(&self, actual: &Expected) -> Vec<String> {
+        let mut v = vec![];
+        if self.reason != actual.reason {
+            v.push(format!(
+                "termination: expected {}, found {}",
+                reason_name(self.reason),
+                reason_name(actual.reason)
+            ));
+        }
+        let mut compare = |what: &str, expected: usize, found: usize| {
+            if expected != found {
+                v.push(format!("{}: expected {}, found {}", what, expected, found));
+            }
+        };
+        compare("steps", self.num_steps, actual.num_steps);
+        compare("commands", self.num_commands, actual.num_commands);
+        compare(
+            "output bytes",
+            self.num_output_bytes,
+            actual.num_output_bytes,
+        );
+        if self.trace_digest != actual.trace_digest {
+            v.push(format!(
+                "trace digest: expected {:016x}, found {:016x}",
+                self.trace_digest, actual.trace_digest
+            ));
+        }
+        v
+    }
+}
+
+fn check_schema_version(json: &str) -> Result<(), Box<dyn Error>> {
+    match json_value(json, "schema_version") {
+        Some(v) if v == SCHEMA_VERSION.to_string() => Ok(()),
+        Some(v) => Err(format!(
+            "schema version {} is not supported (expected {})",
+            v, SCHEMA_VERSION
+        )
+        .into()),
+        None => Err("`schema_version` is missing".into()),
+    }
+}
+
+/// Returns the 64-bit FNV-1a hash of the path trace, where each step is written as a line of the path trace printed in verbose mode (e.g. `(0, 1)       DP:Right CC:Left OutChar`) followed by `\n`.
+pub fn trace_digest(trace: &[TraceEvent]) -> u64 {
+    let mut hasher = Fnv64::new();
+    for step in report::steps(trace) {
+        hasher.put(step.to_line().as_bytes());
+        hasher.put(b"\n");
+    }
+    hasher.finish()
+}
+
+/// Returns the content of [`INDEX_FILE`].
+pub fn index_json(names: &[&str]) -> String {
+    format!(
+        r#"{{"schema_version":{},"generator":"{} {}","cases":[{}]}}"#,
+        SCHEMA_VERSION,
+        env!("CARGO_PKG_NAME"),
+        env!("CARGO_PKG_VERSION"),
+        names
+            .iter()
+            .map(|name| format!(r#""{}""#, name))
+            .collect::<Vec<_>>()
+            .join(",")
+    )
+}
+
+/// Returns the names of the cases in the content of [`INDEX_FILE`].
+pub fn parse_index(json: &str) -> Result<Vec<String>, Box<dyn Error>> {
+    check_schema_version(json)?;
+    let (_, rest) = json
+        .split_once(r#""cases":["#)
+        .ok_or("`cases` is missing")?;
+    let (list, _) = rest.split_once(']').ok_or("`cases` is not closed")?;
+    list.split(',')
+        .map(str::trim)
+        .filter(|s| !s.is_empty())
+        .map(|s| {
+            s.strip_prefix('"')
+                .and_then(|s| s.strip_suffix('"'))
+                .filter(|name| is_valid_name(name))
+                .map(str::to_string)
+                .ok_or_else(|| format!("invalid case name {}", s).into())
+        })
+        .collect()
+}
+
+/// Returns the content of [`README_FILE`], which documents the layout and lists the cases.
+pub fn readme(cases: &[(&str, &Expected)]) -> String {
+    let mut s = format!(
+        "# Piet conformance test vectors
+
+Generated by {name} {version}. Schema version: {schema}.
+
+## Layout
+
+- `{index}`: `schema_version`, `generator` and `cases` (the names of the cases in order).
+- `<case>/{program}`: the program, with one pixel per codel.
+- `<case>/{input}`: the input of the program (possibly empty), to be given as stdin.
+- `<case>/{output}`: the expected output of the program.
+- `<case>/{expected}`: the expected results other than the output:
+  - `schema_version`, `name`
+  - `termination`: `no-exit` (all of the eight attempts to leave a colored block failed) or `white-retrace` (the interpreter retraced its route within a white block)
+  - `steps`: the number of iterations, i.e. moves from a codel (a slide across white counts one step per codel)
+  - `commands`: the number of executed commands
+  - `output_bytes`: the size of `{output}`
+  - `trace_digest`: the 64-bit FNV-1a hash of the path trace in 16 lowercase hexadecimal digits
+
+The semantics are the defaults of {name} (see the output of `{name} version-report`).
+
+## Path trace
+
+The path trace has one line per step, each followed by `\\n`:
+the position of the codel before the move as `(i, j)` (row and column counted from zero) left-aligned in 12 columns,
+a space, `DP:` and the direction pointer (`Right`, `Down`, `Left` or `Up`) left-aligned in 5 columns,
+a space, `CC:` and the codel chooser (`Left` or `Right`),
+and a space and the name of each command executed on the move (`Push`, `Pop`, `Add`, `Subtract`, `Multiply`, `Divide`, `Mod`, `Not`, `Greater`, `Pointer`, `Switch`, `Duplicate`, `Roll`, `InNumber`, `InChar`, `OutNumber` or `OutChar`), e.g.
+
+```text
+(0, 0)       DP:Right CC:Left Push
+(0, 1)       DP:Down  CC:Left
+```
+
+## Cases
+
+| case | termination | steps | commands | output bytes | trace digest |
+|------|-------------|-------|----------|--------------|--------------|
+",
+        name = env!("CARGO_PKG_NAME"),
+        version = env!("CARGO_PKG_VERSION"),
+        schema = SCHEMA_VERSION,
+        index = INDEX_FILE,
+        program = PROGRAM_FILE,
+        input = INPUT_FILE,
+        output = OUTPUT_FILE,
+        expected = EXPECTED_FILE,
+    );
+    for (name, e) in cases {
+        s += &format!(
+            "| {} | {} | {} | {} | {} | {:016x} |\n",
+            name,
+            reason_name(e.reason),
+            e.num_steps,
+            e.num_commands,
+            e.num_output_bytes,
+            e.trace_digest
+        );
+    }
+    s
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::cc::CC;
+    use super::super::command::Command;
+    use super::super::dp::DP;
+    use super::*;
+
+    #[test]
+    fn test_parse_cases() {
+        let s = "
+# name  image  input
+hi      a/hi.png
+adder   adder.pietdev   adder.input  # with input
+";
+        assert_eq!(
+            vec![
+                Case {
+                    name: "hi".to_string(),
+                    image: PathBuf::from("dir/a/hi.png"),
+                    input: None,
+                },
+                Case {
+                    name: "adder".to_string(),
+                    image: PathBuf::from("dir/adder.pietdev"),
+                    input: Some(PathBuf::from("dir/adder.input")),
+                },
+            ],
+            parse_cases(s, Path::new("dir")).unwrap()
+        );
+
+        let error = |s: &str| parse_cases(s, Path::new("")).unwrap_err().to_string();
+        assert_eq!("no cases are listed", error("# nothing\n"));
+        assert_eq!(
+            "line 1: expected `<name> <image> [<input>]` but found `hi`",
+            error("hi")
+        );
+        assert_eq!("line 1: invalid case name `h.i`", error("h.i a.png"));
+        assert_eq!(
+            "line 2: case `hi` is listed twice",
+            error("hi a.png\nhi b.png")
+        );
+    }
+
+    #[test]
+    fn test_json() {
+        let expected = Expected {
+            reason: TerminationReason::WhiteRetrace,
+            num_steps: 31,
+            num_commands: 12,
+            num_output_bytes: 2,
+            trace_digest: 0x00ab_cdef_0123_4567,
+        };
+        let json = expected.to_json("hi");
+        assert_eq!(
+            r#"{"schema_version":1,"name":"hi","termination":"white-retrace","steps":31,"commands":12,"output_bytes":2,"trace_digest":"00abcdef01234567"}"#,
+            json
+        );
+        assert_eq!(expected, Expected::from_json(&json).unwrap());
+        assert_eq!(
+            "schema version 2 is not supported (expected 1)",
+            Expected::from_json(&json.replace(r#""schema_version":1"#, r#""schema_version":2"#))
+                .unwrap_err()
+                .to_string()
+        );
+
+        let actual = Expected {
+            num_steps: 30,
+            ..expected.clone()
+        };
+        assert_eq!(
+            vec!["steps: expected 31, found 30".to_string()],
+            expected.differences(&actual)
+        );
+        assert!(expected.differences(&expected).is_empty());
+
+        let index = index_json(&["hi", "adder"]);
+        assert_eq!(vec!["hi", "adder"], parse_index(&index).unwrap());
+        assert!(parse_index(&index_json(&[])).unwrap().is_empty());
+        assert!(parse_index(&index.replace("adder", "a,\"")).is_err());
+    }
+
+    #[test]
+    fn test_trace_digest() {
+        let state = |j: usize| TraceEvent::State {
+            pos: (0, j),
+            dp: DP::Right,
+            cc: CC::Left,
+        };
+        let trace = [state(0), TraceEvent::Command(Command::Push), state(1)];
+        let text = "(0, 0)       DP:Right CC:Left Push\n(0, 1)       DP:Right CC:Left\n";
+        assert_eq!(
+            super::super::stable::fnv64(text.as_bytes()),
+            trace_digest(&trace)
+        );
+        assert_ne!(trace_digest(&trace), trace_digest(&trace[..2]));
+    }
+}
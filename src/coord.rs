@@ -0,0 +1,203 @@
+/*!
+Checked arithmetic on codel coordinates.
+
+Coordinates are `usize`, so `i - 1` on the top row (or `height - 1` of an empty range) underflows: a panic in debug builds and a wrap-around in release builds.
+Every move between codels and every "last index" goes through the functions here, which return `None` instead.
+A bare `- 1` on a coordinate elsewhere in the crate is rejected by `test_no_bare_decrement`.
+
+Positions and regions read from files (checkpoints, assertion files and manifests) may be anything up to `usize::MAX`,
+so they are checked against the image by [`check_pos()`] and [`check_region()`] before they are used in any arithmetic.
+*/
+
+use super::entry::Region;
+use super::error::PietError;
+
+/// Returns the last index of a range of length `len`, or `None` if it is empty.
+pub(crate) fn last(len: usize) -> Option<usize> {
+    len.checked_sub(1)
+}
+
+/// Moves `pos` by `(di, dj)` within a `height x width` grid.
+/// Returns `None` if the result is outside the grid.
+pub(crate) fn offset(
+    (i, j): (usize, usize),
+    (di, dj): (isize, isize),
+    height: usize,
+    width: usize,
+) -> Option<(usize, usize)> {
+    let next = (i.checked_add_signed(di)?, j.checked_add_signed(dj)?);
+    ((next.0 < height) && (next.1 < width)).then_some(next)
+}
+
+/// Same as [`offset()`] but wraps around the edges (a torus), where `(di, dj)` is at most one codel in each direction.
+/// Returns `None` if `pos` itself is outside the grid.
+pub(crate) fn offset_wrapping(
+    (i, j): (usize, usize),
+    (di, dj): (isize, isize),
+    height: usize,
+    width: usize,
+) -> Option<(usize, usize)> {
+    if (i >= height) || (j >= width) {
+        return None;
+    }
+    Some((
+        (i + height).checked_add_signed(di)? % height,
+        (j + width).checked_add_signed(dj)? % width,
+    ))
+}
+
+/// Checks that `pos`, read from `input`, lies within a `height x width` grid.
+pub(crate) fn check_pos(
+    input: &str,
+    pos: (usize, usize),
+    height: usize,
+    width: usize,
+) -> Result<(), PietError> {
+    if (pos.0 < height) && (pos.1 < width) {
+        return Ok(());
+    }
+    Err(PietError::InvalidCoordinate {
+        input: input.to_string(),
+        value: format!("the position {:?}", pos),
+        image_height: height,
+        image_width: width,
+    })
+}
+
+/// Checks that `region` (described by `what`), read from `input`, lies within a `height x width` grid.
+pub(crate) fn check_region(
+    input: &str,
+    what: &str,
+    region: &Region,
+    height: usize,
+    width: usize,
+) -> Result<(), PietError> {
+    if region.fits_in(height, width) {
+        return Ok(());
+    }
+    Err(PietError::InvalidCoordinate {
+        input: input.to_string(),
+        value: what.to_string(),
+        image_height: height,
+        image_width: width,
+    })
+}
+
+/// Returns the (up to four) neighbours of `pos` within a `height x width` grid, in the order up, down, left, right.
+pub(crate) fn neighbours(
+    pos: (usize, usize),
+    height: usize,
+    width: usize,
+) -> impl Iterator<Item = (usize, usize)> {
+    [(-1, 0), (1, 0), (0, -1), (0, 1)]
+        .into_iter()
+        .filter_map(move |delta| offset(pos, delta, height, width))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+
+    #[test]
+    fn test_offset() {
+        assert_eq!(None, last(0));
+        assert_eq!(Some(4), last(5));
+
+        assert_eq!(None, offset((0, 0), (-1, 0), 3, 3));
+        assert_eq!(None, offset((0, 2), (0, 1), 3, 3));
+        assert_eq!(Some((1, 2)), offset((0, 2), (1, 0), 3, 3));
+        assert_eq!(None, offset((0, 0), (0, 1), 0, 0));
+
+        assert_eq!(Some((2, 0)), offset_wrapping((0, 0), (-1, 0), 3, 3));
+        assert_eq!(Some((0, 0)), offset_wrapping((0, 2), (0, 1), 3, 3));
+        assert_eq!(Some((0, 0)), offset_wrapping((0, 0), (0, -1), 1, 1));
+        assert_eq!(None, offset_wrapping((3, 0), (1, 0), 3, 3));
+        assert_eq!(None, offset_wrapping((0, 0), (1, 0), 0, 0));
+    }
+
+    #[test]
+    fn test_neighbours() {
+        let v = |pos, h, w| neighbours(pos, h, w).collect::<Vec<_>>();
+        assert_eq!(vec![(0, 1), (2, 1), (1, 0), (1, 2)], v((1, 1), 3, 3));
+        assert_eq!(vec![(1, 0), (0, 1)], v((0, 0), 3, 3));
+        //degenerate grids
+        assert!(v((0, 0), 1, 1).is_empty());
+        assert_eq!(vec![(0, 0), (0, 2)], v((0, 1), 1, 3));
+    }
+
+    #[test]
+    fn test_check() {
+        assert_eq!(Ok(()), check_pos("f", (2, 4), 3, 5));
+        for pos in [(3, 0), (0, 5), (usize::MAX, 0), (0, usize::MAX)] {
+            assert_eq!(
+                Err(PietError::InvalidCoordinate {
+                    input: "f".to_string(),
+                    value: format!("the position {:?}", pos),
+                    image_height: 3,
+                    image_width: 5,
+                }),
+                check_pos("f", pos, 3, 5)
+            );
+        }
+        assert!(check_pos("f", (0, 0), 0, 0).is_err());
+
+        let region = |pos, height, width| Region { pos, height, width };
+        assert_eq!(Ok(()), check_region("f", "r", &region((1, 1), 2, 4), 3, 5));
+        for r in [
+            region((1, 1), 3, 4),
+            region((usize::MAX, 0), 1, 1),
+            region((0, 1), 1, usize::MAX),
+            region((usize::MAX, usize::MAX), usize::MAX, usize::MAX),
+        ] {
+            assert_eq!(
+                "f: r exceeds the image (3 x 5 codels)",
+                check_region("f", "r", &r, 3, 5).unwrap_err().to_string()
+            );
+        }
+    }
+
+    /// Rejects a bare `- 1` on a coordinate (`i - 1`, `pos.0 - 1`, `height - 1`, ...) outside this module.
+    #[test]
+    fn test_no_bare_decrement() {
+        let pattern = |line: &str| {
+            let line = line.split("//").next().unwrap();
+            let words = line
+                .split(|c: char| !(c.is_alphanumeric() || (c == '_') || (c == '.') || (c == '-')))
+                .filter(|w| !w.is_empty())
+                .collect::<Vec<_>>();
+            words.windows(3).any(|w| {
+                let coordinate = ["i", "j", "h", "w", "height", "width"].contains(&w[0])
+                    || w[0].ends_with(".0")
+                    || w[0].ends_with(".1")
+                    || w[0].ends_with(".height")
+                    || w[0].ends_with(".width");
+                coordinate && (w[1] == "-") && (w[2] == "1")
+            })
+        };
+        assert!(pattern("ret.push((i - 1, j));"));
+        assert!(pattern("if j != self.width - 1 {"));
+        assert!(pattern("(pos.0 - 1, pos.1)"));
+        assert!(!pattern("let n = len - 1;"));
+        assert!(!pattern("//i - 1"));
+
+        let mut violations = vec![];
+        for entry in fs::read_dir("src").unwrap() {
+            let path = entry.unwrap().path();
+            if path.extension().is_none_or(|e| e != "rs") || path.ends_with("coord.rs") {
+                continue;
+            }
+            for (n, line) in fs::read_to_string(&path).unwrap().lines().enumerate() {
+                if pattern(line) {
+                    violations.push(format!("{}:{}: {}", path.display(), n + 1, line.trim()));
+                }
+            }
+        }
+        assert!(
+            violations.is_empty(),
+            "use `coord` instead:\n{}",
+            violations.join("\n")
+        );
+    }
+}
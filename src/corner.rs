@@ -0,0 +1,296 @@
+/*!
+Choice of the codel from which the interpreter leaves a coloured block (`--corner-strategy`).
+
+The spec chooses the codel by the DP and the CC alone ([`Standard`]), which is what every run does by default.
+[`CornerStrategy`] is a seam for research on dialects with other exit rules: the movement asks the strategy of [`ExecConfig`](super::extension::ExecConfig) for the corner every time it leaves a block,
+and everything after the choice (the restrictions, the retries and the slides across white) is the same as in the spec.
+
+```
+use std::sync::Arc;
+
+use piet_programming_language::corner::{CornerContext, CornerStrategy, SharedCornerStrategy};
+use piet_programming_language::extension::ExecConfig;
+
+/// Leaves every block from the codel where it was entered (which is mostly restricted by the block itself).
+struct Stay;
+
+impl CornerStrategy for Stay {
+    fn name(&self) -> &str {
+        "stay"
+    }
+
+    fn corner(&self, ctx: &CornerContext) -> (usize, usize) {
+        ctx.entry
+    }
+}
+
+let config = ExecConfig {
+    corner_strategy: SharedCornerStrategy::new(Arc::new(Stay)),
+    ..ExecConfig::default()
+};
+assert_eq!("stay", config.corner_strategy.name());
+```
+
+The outcomes of [`Standard`] depend only on the image, so they are memoized by [`Program`](super::program::Program), while those of the other strategies are computed on each move.
+The static analyses (e.g. `--lint` and `--analyze-output`) always follow the spec.
+
+# `entry-nearest` (experimental)
+
+[`EntryNearest`] leaves from the codel of the furthest edge in the direction of the DP which is the nearest to the codel where the block was entered (measured along the edge),
+the CC choosing between two codels at the same distance as in the spec.
+So a block is left in line with where it was entered when possible, rather than from one of its corners.
+This is an example of a strategy, and is not a dialect anyone is known to use.
+*/
+
+use std::fmt::{self, Debug, Formatter};
+use std::ops::Deref;
+use std::sync::Arc;
+
+use clap::ValueEnum;
+
+use super::block::Block;
+use super::cc::CC;
+use super::dp::DP;
+
+/// What a [`CornerStrategy`] knows when the interpreter leaves a block.
+#[derive(Debug, Clone, Copy)]
+pub struct CornerContext<'a> {
+    pub block: &'a Block,
+    /// The codel where the interpreter entered the block (or started).
+    pub entry: (usize, usize),
+    pub dp: DP,
+    pub cc: CC,
+}
+
+/// Rule which chooses the codel of a block from which the interpreter tries to move in the direction of the DP.
+pub trait CornerStrategy: Send + Sync {
+    /// Name of the strategy, which identifies it (e.g. in `version-report`).
+    fn name(&self) -> &str;
+
+    /// Returns a codel of `ctx.block`.
+    ///
+    /// A codel outside of the block is a bug of the strategy, which is caught by a debug assertion but otherwise makes the interpreter move from that codel as if it were in the block.
+    fn corner(&self, ctx: &CornerContext) -> (usize, usize);
+}
+
+/// The rule of the spec.
+///
+/// Related [spec](https://www.dangermouse.net/esoteric/piet.html):
+///
+/// > The interpreter finds the edge of the current colour block which is furthest in the direction of the DP. (This edge may be disjoint if the block is of a complex shape.)
+/// > The interpreter selects the colour block codel from that edge which is furthest to the CC's direction of the DP's direction of travel.
+#[derive(Debug, Clone, Copy)]
+pub struct Standard;
+
+impl CornerStrategy for Standard {
+    fn name(&self) -> &str {
+        "standard"
+    }
+
+    fn corner(&self, ctx: &CornerContext) -> (usize, usize) {
+        ctx.block.get_corner_index(&ctx.dp, &ctx.cc)
+    }
+}
+
+/// Experimental rule which leaves in line with the entry (see the [module documentation](self)).
+#[derive(Debug, Clone, Copy)]
+pub struct EntryNearest;
+
+impl CornerStrategy for EntryNearest {
+    fn name(&self) -> &str {
+        "entry-nearest"
+    }
+
+    fn corner(&self, ctx: &CornerContext) -> (usize, usize) {
+        let (di, dj) = ctx.dp.get_displacement();
+        //the direction to the left of the DP, to which CC left points
+        let (li, lj) = (-dj, di);
+        let (si, sj) = match ctx.cc {
+            CC::Left => (li, lj),
+            CC::Right => (-li, -lj),
+        };
+        let dot =
+            |(i, j): (usize, usize), (a, b): (isize, isize)| (i as isize) * a + (j as isize) * b;
+        let depth = ctx.block.codels().map(|c| dot(c, (di, dj))).max().unwrap();
+        let entry = dot(ctx.entry, (li, lj));
+        ctx.block
+            .codels()
+            .filter(|&c| dot(c, (di, dj)) == depth)
+            .min_by_key(|&c| ((dot(c, (li, lj)) - entry).abs(), -dot(c, (si, sj))))
+            .unwrap()
+    }
+}
+
+/// A [`CornerStrategy`] shared by the configurations of runs.
+/// Two of them are equal if their names are.
+#[derive(Clone)]
+pub struct SharedCornerStrategy {
+    strategy: Arc<dyn CornerStrategy>,
+    /// Cached, as this is checked on every move.
+    is_standard: bool,
+}
+
+impl SharedCornerStrategy {
+    pub fn new(strategy: Arc<dyn CornerStrategy>) -> Self {
+        let is_standard = strategy.name() == Standard.name();
+        Self {
+            strategy,
+            is_standard,
+        }
+    }
+
+    /// Returns `true` if this is [`Standard`], whose outcomes can be memoized.
+    pub fn is_standard(&self) -> bool {
+        self.is_standard
+    }
+}
+
+impl Default for SharedCornerStrategy {
+    fn default() -> Self {
+        Self::new(Arc::new(Standard))
+    }
+}
+
+impl Deref for SharedCornerStrategy {
+    type Target = dyn CornerStrategy;
+
+    fn deref(&self) -> &Self::Target {
+        self.strategy.as_ref()
+    }
+}
+
+impl Debug for SharedCornerStrategy {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "SharedCornerStrategy({:?})", self.name())
+    }
+}
+
+impl PartialEq for SharedCornerStrategy {
+    fn eq(&self, other: &Self) -> bool {
+        self.name() == other.name()
+    }
+}
+
+impl Eq for SharedCornerStrategy {}
+
+/// The strategies built into this crate (`--corner-strategy`).
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum CornerStrategyKind {
+    /// The rule of the spec
+    #[default]
+    Standard,
+    /// Experimental: leaves in line with where the block was entered
+    EntryNearest,
+}
+
+impl CornerStrategyKind {
+    pub fn strategy(self) -> SharedCornerStrategy {
+        match self {
+            CornerStrategyKind::Standard => SharedCornerStrategy::default(),
+            CornerStrategyKind::EntryNearest => SharedCornerStrategy::new(Arc::new(EntryNearest)),
+        }
+    }
+
+    /// Returns the built-in strategy named `name`, if any.
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::value_variants()
+            .iter()
+            .copied()
+            .find(|k| k.to_possible_value().unwrap().get_name() == name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rustc_hash::FxHashSet;
+
+    use super::*;
+
+    //   ■   ■
+    // ■ ■ ■ ■ ■ ■
+    //   ■ ■ ■
+    // ■ ■ ■ ■ ■ ■
+    //   ■   ■
+    fn block() -> Block {
+        let mut s = FxHashSet::default();
+        for (i, row) in ["-#-#--", "######", "-###--", "######", "-#-#--"]
+            .iter()
+            .enumerate()
+        {
+            for (j, c) in row.chars().enumerate() {
+                if c == '#' {
+                    s.insert((i, j));
+                }
+            }
+        }
+        Block::new(&s)
+    }
+
+    fn corner(
+        strategy: &dyn CornerStrategy,
+        entry: (usize, usize),
+        dp: DP,
+        cc: CC,
+    ) -> (usize, usize) {
+        strategy.corner(&CornerContext {
+            block: &block(),
+            entry,
+            dp,
+            cc,
+        })
+    }
+
+    #[test]
+    fn test_standard() {
+        let block = block();
+        for dp in [DP::Right, DP::Down, DP::Left, DP::Up] {
+            for cc in [CC::Left, CC::Right] {
+                assert_eq!(
+                    block.get_corner_index(&dp, &cc),
+                    corner(&Standard, (2, 2), dp, cc)
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_entry_nearest() {
+        //the edge of the DP is the same as in the spec
+        assert_eq!((3, 5), corner(&EntryNearest, (3, 0), DP::Right, CC::Left));
+        assert_eq!((1, 5), corner(&EntryNearest, (1, 0), DP::Right, CC::Right));
+        assert_eq!((4, 3), corner(&EntryNearest, (0, 3), DP::Down, CC::Right));
+        assert_eq!((0, 1), corner(&EntryNearest, (4, 1), DP::Up, CC::Right));
+        //the nearest codel of a disjoint edge
+        assert_eq!((4, 3), corner(&EntryNearest, (2, 3), DP::Down, CC::Right));
+        assert_eq!((4, 1), corner(&EntryNearest, (2, 2), DP::Down, CC::Right));
+
+        //a tie is broken by the CC as in the spec
+        assert_eq!((1, 0), corner(&EntryNearest, (2, 2), DP::Left, CC::Right));
+        assert_eq!((3, 0), corner(&EntryNearest, (2, 2), DP::Left, CC::Left));
+        //and the corner of the spec is chosen when the entry is in line with it
+        let block = block();
+        for dp in [DP::Right, DP::Down, DP::Left, DP::Up] {
+            for cc in [CC::Left, CC::Right] {
+                let c = block.get_corner_index(&dp, &cc);
+                assert_eq!(c, corner(&EntryNearest, c, dp, cc));
+            }
+        }
+    }
+
+    #[test]
+    fn test_shared() {
+        assert!(SharedCornerStrategy::default().is_standard());
+        assert_eq!(
+            SharedCornerStrategy::default(),
+            CornerStrategyKind::Standard.strategy()
+        );
+        let entry_nearest = CornerStrategyKind::EntryNearest.strategy();
+        assert!(!entry_nearest.is_standard());
+        assert_ne!(SharedCornerStrategy::default(), entry_nearest);
+        assert_eq!(
+            Some(CornerStrategyKind::EntryNearest),
+            CornerStrategyKind::from_name(entry_nearest.name())
+        );
+        assert_eq!(None, CornerStrategyKind::from_name("stay"));
+    }
+}
@@ -23,12 +23,8 @@ impl DP {
     }
 
     pub fn rotate_clockwise_by(&self, i: isize) -> Self {
-        let i = (*self as isize) + i;
-        if i >= 0 {
-            Self::from_isize(i % 4).unwrap()
-        } else {
-            Self::from_isize((i % 4 + 4) % 4).unwrap()
-        }
+        //reduced before the addition, which overflows for `i` near `isize::MAX`
+        Self::from_isize(((*self as isize) + i.rem_euclid(4)) % 4).unwrap()
     }
 
     /// Returns the index displacement when you go straight one step in the direction of DP.
@@ -95,6 +91,13 @@ mod tests {
         assert_eq!(DP::Right.rotate_clockwise_by(-6), DP::Left);
         assert_eq!(DP::Right.rotate_clockwise_by(-7), DP::Down);
         assert_eq!(DP::Right.rotate_clockwise_by(-8), DP::Right);
+
+        //`isize::MAX` is `2^63 - 1`, which is `3` modulo `4`, and `isize::MIN` is `-2^63`, which is a multiple of `4`
+        assert_eq!(DP::Right.rotate_clockwise_by(isize::MAX), DP::Up);
+        assert_eq!(DP::Up.rotate_clockwise_by(isize::MAX), DP::Left);
+        assert_eq!(DP::Right.rotate_clockwise_by(isize::MIN), DP::Right);
+        assert_eq!(DP::Up.rotate_clockwise_by(isize::MIN), DP::Up);
+        assert_eq!(DP::Down.rotate_clockwise_by(isize::MIN + 1), DP::Left);
     }
 
     #[test]
@@ -0,0 +1,800 @@
+/*!
+The configuration which a run actually uses, after every default has been resolved (`--print-config`).
+
+The settings of a run come from several layers:
+the built-in defaults, a limit preset (`--limits`), the manifest of `--entry`, what is detected (the format, the codel size) or found in the environment (whether stdout and stderr are terminals, the terminal width), and the explicit options.
+[`EffectiveConfig`] lists every resolved setting with the layer it came from ([`Source`]), as aligned text or as JSON,
+and the same listing is embedded in the report of `--report`.
+
+The precedence is that of the options themselves: an individual limit (`--max-iter`, `--stall-timeout`) overrides the preset, which overrides the default,
+and an explicit option overrides what would be detected (e.g. `--codel-size` or `--art-max-width`).
+A value equal to the default is reported as the default even if it was given explicitly.
+
+The settings which depend on the environment are resolved by the functions of this module ([`is_synchronized()`], [`art_max_width()`] and [`shows_progress()`]), which the run itself uses, so that the listing cannot drift from the behavior.
+*/
+
+use std::cell::OnceCell;
+use std::fmt::{self, Display, Formatter};
+use std::io::{self, IsTerminal};
+use std::path::PathBuf;
+
+use clap::ValueEnum;
+
+use super::args::{Args, Format, LoadArgs};
+use super::art;
+use super::command;
+use super::entry::Entry;
+use super::limits::{self, LimitPreset};
+use super::lint;
+use super::multiplexer::SyncOutput;
+use super::stdin;
+
+/// When `--print-config` prints the configuration.
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PrintConfig {
+    /// Prints it to stderr and runs the program
+    Run,
+    /// Prints it to stdout and exits without running the program
+    Only,
+}
+
+/// What the settings which depend on the environment are resolved against.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Environment {
+    pub stdout_is_terminal: bool,
+    pub stderr_is_terminal: bool,
+    /// Width of the terminal in columns, looked up on first use as it may spawn `stty`.
+    terminal_width: OnceCell<Option<usize>>,
+}
+
+impl Environment {
+    /// Returns the environment of this process.
+    pub fn current() -> Self {
+        Self {
+            stdout_is_terminal: io::stdout().is_terminal(),
+            stderr_is_terminal: io::stderr().is_terminal(),
+            terminal_width: OnceCell::new(),
+        }
+    }
+
+    /// Returns the given environment, e.g. for a test.
+    pub fn new(
+        stdout_is_terminal: bool,
+        stderr_is_terminal: bool,
+        terminal_width: Option<usize>,
+    ) -> Self {
+        Self {
+            stdout_is_terminal,
+            stderr_is_terminal,
+            terminal_width: OnceCell::from(terminal_width),
+        }
+    }
+
+    /// Returns the width of the terminal if stderr is one.
+    pub fn terminal_width(&self) -> Option<usize> {
+        *self
+            .terminal_width
+            .get_or_init(|| self.stderr_is_terminal.then(art::terminal_width).flatten())
+    }
+}
+
+/// Returns `true` if the output on stdout and the trace are kept in order (`--sync-output`).
+/// This only matters when the trace is printed and the output goes to stdout.
+pub fn is_synchronized(sync_output: SyncOutput, env: &Environment) -> bool {
+    match sync_output {
+        SyncOutput::Auto => env.stdout_is_terminal && env.stderr_is_terminal,
+        SyncOutput::Always => true,
+        SyncOutput::Never => false,
+    }
+}
+
+/// Returns the number of columns into which the image dump of verbose mode is fitted (`--art-max-width`).
+/// A dump redirected to a file is not fitted unless asked.
+pub fn art_max_width(args: &Args, env: &Environment) -> Option<usize> {
+    args.art_max_width.or_else(|| env.terminal_width())
+}
+
+/// Returns `true` if the live status line of `--progress` is shown.
+/// It would be mixed up with the trace, so it is disabled in verbose mode.
+pub fn shows_progress(args: &Args, env: &Environment) -> bool {
+    args.progress && (args.verbose == 0) && args.trace_after.is_none() && env.stderr_is_terminal
+}
+
+/// What was resolved while loading the program.
+#[derive(Debug, Clone, Default)]
+pub struct LoadResolution {
+    /// Size of the image (or of `--crop`) in pixels, or `None` for a PietDev save or an example (or if the header could not be read).
+    /// The size of the whole image is read from its header only when it is shown (e.g. by `--print-config`), and is `None` otherwise.
+    pub pixels: Option<(usize, usize)>,
+    /// Size of the whole program in codels, before the region of `--entry` is cut out.
+    pub codels: (usize, usize),
+    /// The entry of `--entry` as declared in the manifest.
+    pub entry: Option<(PathBuf, Entry)>,
+}
+
+/// Layer from which the value of a setting comes.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Source {
+    Default,
+    /// An explicit option.
+    Option,
+    Preset(LimitPreset),
+    /// The manifest at the path.
+    Manifest(PathBuf),
+    /// Detected from the program file.
+    Detected,
+    /// Resolved against the terminal (see [`Environment`]).
+    Terminal,
+}
+
+impl Display for Source {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            Source::Default => write!(f, "default"),
+            Source::Option => write!(f, "option"),
+            Source::Preset(preset) => write!(f, "preset {}", preset),
+            Source::Manifest(path) => write!(f, "manifest {}", path.display()),
+            Source::Detected => write!(f, "detected"),
+            Source::Terminal => write!(f, "terminal"),
+        }
+    }
+}
+
+/// A resolved setting.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Setting {
+    pub section: &'static str,
+    pub name: &'static str,
+    /// The value as JSON.
+    pub value: String,
+    pub source: Source,
+}
+
+/// Every resolved setting of a run, grouped by section in a fixed order.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EffectiveConfig {
+    pub settings: Vec<Setting>,
+}
+
+/// Returns `s` as a JSON string.
+fn string(s: &str) -> String {
+    let mut ret = String::from('"');
+    for c in s.chars() {
+        match c {
+            '"' => ret.push_str("\\\""),
+            '\\' => ret.push_str("\\\\"),
+            c if c.is_control() => ret.push_str(&format!("\\u{:04x}", c as u32)),
+            c => ret.push(c),
+        }
+    }
+    ret.push('"');
+    ret
+}
+
+fn value_name(v: &impl ValueEnum) -> String {
+    v.to_possible_value().unwrap().get_name().to_string()
+}
+
+fn optional_string(s: Option<&str>) -> String {
+    s.map_or("null".to_string(), string)
+}
+
+fn optional_number(n: Option<impl ToString>) -> String {
+    n.map_or("null".to_string(), |n| n.to_string())
+}
+
+/// Source of a value which has a default.
+fn given_if<T: PartialEq>(value: T, default: T) -> Source {
+    if value == default {
+        Source::Default
+    } else {
+        Source::Option
+    }
+}
+
+impl EffectiveConfig {
+    /// Resolves the configuration of `args` against `env`.
+    /// The settings resolved while loading are taken from `load` if it is given (they are otherwise left out, e.g. for a program loaded by an embedding program).
+    pub fn new(args: &Args, load: Option<&LoadResolution>, env: &Environment) -> Self {
+        let mut config = Self { settings: vec![] };
+        config.push_load(&args.load, load);
+        config.push_limits(args);
+        config.push_exec(args);
+        config.push_io(args, env);
+        config.push_trace(args, env);
+        config
+    }
+
+    fn push(&mut self, section: &'static str, name: &'static str, value: String, source: Source) {
+        self.settings.push(Setting {
+            section,
+            name,
+            value,
+            source,
+        });
+    }
+
+    fn push_load(&mut self, load: &LoadArgs, resolution: Option<&LoadResolution>) {
+        let s = "load";
+        match load.example {
+            Some(example) => self.push(s, "example", string(&example.to_string()), Source::Option),
+            None => self.push(s, "image", string(&load.image_file), Source::Option),
+        }
+        let is_pietdev = super::is_pietdev(load);
+        let format = if is_pietdev { "pietdev" } else { "image" };
+        let format_source = match load.format {
+            Format::Auto => Source::Detected,
+            _ => Source::Option,
+        };
+        self.push(s, "format", string(format), format_source);
+
+        if let Some(resolution) = resolution {
+            let (value, source) = match (load.codel_size, load.codel_grid, resolution.pixels) {
+                _ if is_pietdev => ("1".to_string(), Source::Detected),
+                (Some(codel_size), _, _) => (codel_size.to_string(), Source::Option),
+                (None, Some(grid), _) => (string(&grid.to_string()), Source::Option),
+                (None, None, Some((height, width))) => {
+                    let (num_rows, num_columns) = resolution.codels;
+                    let value = if (height % num_rows == 0)
+                        && (width % num_columns == 0)
+                        && (height / num_rows == width / num_columns)
+                    {
+                        (height / num_rows).to_string()
+                    } else {
+                        //a grid with an offset (`--detect-grid-offset`)
+                        string(&format!(
+                            "{} x {} codels in {} x {} pixels",
+                            num_columns, num_rows, width, height
+                        ))
+                    };
+                    (value, Source::Detected)
+                }
+                (None, None, None) => ("null".to_string(), Source::Detected),
+            };
+            self.push(s, "codel_size", value, source);
+            let (height, width) = resolution.codels;
+            self.push(
+                s,
+                "codels",
+                format!("[{},{}]", height, width),
+                Source::Detected,
+            );
+        }
+        self.push(
+            s,
+            "crop",
+            optional_string(load.crop.map(|r| r.to_string()).as_deref()),
+            given_if(load.crop, None),
+        );
+        match resolution.and_then(|r| r.entry.as_ref()) {
+            Some((path, entry)) => {
+                self.push(s, "entry", string(&entry.name), Source::Option);
+                self.push(
+                    s,
+                    "entry_region",
+                    string(&entry.crop.to_string()),
+                    Source::Manifest(path.clone()),
+                );
+            }
+            None => self.push(
+                s,
+                "entry",
+                optional_string(load.entry.as_deref()),
+                given_if(load.entry.as_deref(), None),
+            ),
+        }
+        self.push(
+            s,
+            "frame",
+            optional_number(load.frame),
+            given_if(load.frame, None),
+        );
+        self.push(
+            s,
+            "color_tolerance",
+            load.color_tolerance.to_string(),
+            given_if(load.color_tolerance, 0),
+        );
+        self.push(
+            s,
+            "palette",
+            optional_string(load.palette.as_deref()),
+            given_if(load.palette.as_deref(), None),
+        );
+        let unknown_colors = if load.fall_back_to_white {
+            "white"
+        } else if load.fall_back_to_black {
+            "black"
+        } else {
+            "error"
+        };
+        self.push(
+            s,
+            "unknown_colors",
+            string(unknown_colors),
+            given_if(unknown_colors, "error"),
+        );
+        self.push(
+            s,
+            "strict_codel_check",
+            load.strict_codel_check.to_string(),
+            given_if(load.strict_codel_check, false),
+        );
+        self.push(
+            s,
+            "cache_dir",
+            optional_string(load.cache_dir.as_deref()),
+            given_if(load.cache_dir.as_deref(), None),
+        );
+    }
+
+    fn push_limits(&mut self, args: &Args) {
+        let s = "limits";
+        let limits = args.limits();
+        self.push(
+            s,
+            "preset",
+            optional_string(args.limits.map(|p| p.to_string()).as_deref()),
+            given_if(args.limits, None),
+        );
+        //an individual option overrides the preset
+        let source = |is_given: bool| match (is_given, args.limits) {
+            (true, _) => Source::Option,
+            (false, Some(preset)) => Source::Preset(preset),
+            (false, None) => Source::Default,
+        };
+        self.push(
+            s,
+            "max_iter",
+            optional_number(limits.max_iter),
+            source(args.max_iter.is_some()),
+        );
+        self.push(
+            s,
+            "stall_timeout",
+            optional_number(limits.stall_timeout),
+            source(args.stall_timeout.is_some()),
+        );
+        let load = &args.load;
+        self.push(
+            s,
+            "max_image_pixels",
+            load.max_image_pixels.to_string(),
+            given_if(load.max_image_pixels, limits::DEFAULT_MAX_IMAGE_PIXELS),
+        );
+        self.push(
+            s,
+            "max_image_dimension",
+            load.max_image_dimension.to_string(),
+            given_if(
+                load.max_image_dimension,
+                limits::DEFAULT_MAX_IMAGE_DIMENSION,
+            ),
+        );
+        self.push(
+            s,
+            "max_image_bytes",
+            load.max_image_bytes.to_string(),
+            given_if(load.max_image_bytes, limits::DEFAULT_MAX_IMAGE_BYTES),
+        );
+    }
+
+    fn push_exec(&mut self, args: &Args) {
+        let s = "exec";
+        self.push(
+            s,
+            "topology",
+            string(&value_name(&args.topology)),
+            given_if(args.topology, Default::default()),
+        );
+        self.push(
+            s,
+            "extensions",
+            format!(
+                "[{}]",
+                args.extension
+                    .iter()
+                    .map(|e| string(&value_name(e)))
+                    .collect::<Vec<_>>()
+                    .join(",")
+            ),
+            given_if(args.extension.is_empty(), true),
+        );
+        self.push(
+            s,
+            "corner_strategy",
+            string(&value_name(&args.corner_strategy)),
+            given_if(args.corner_strategy, Default::default()),
+        );
+        self.push(
+            s,
+            "number_overflow",
+            string(&value_name(&args.number_overflow)),
+            given_if(args.number_overflow, Default::default()),
+        );
+        for (name, value) in [
+            ("lenient_start", args.lenient_start),
+            ("check_runtime", args.check_runtime),
+            ("allow_interventions", args.allow_interventions),
+        ] {
+            self.push(s, name, value.to_string(), given_if(value, false));
+        }
+        self.push(
+            s,
+            "large_operand_threshold",
+            args.large_operand_threshold.to_string(),
+            given_if(
+                args.large_operand_threshold,
+                command::DEFAULT_LARGE_OPERAND_THRESHOLD,
+            ),
+        );
+        self.push(
+            s,
+            "start_block_threshold",
+            args.start_block_threshold.to_string(),
+            given_if(
+                args.start_block_threshold,
+                lint::DEFAULT_START_BLOCK_THRESHOLD,
+            ),
+        );
+        self.push(
+            s,
+            "allow_large_start_block",
+            args.allow_large_start_block.to_string(),
+            given_if(args.allow_large_start_block, false),
+        );
+    }
+
+    fn push_io(&mut self, args: &Args, env: &Environment) {
+        let s = "io";
+        let input = &args.input;
+        let value = if let Some(file) = &input.file {
+            format!("file {}", file)
+        } else if let Some(string) = &input.string {
+            format!("string of {} bytes", string.len())
+        } else if let Some(spec) = &input.random {
+            format!("random {}", spec)
+        } else {
+            "stdin".to_string()
+        };
+        let source = given_if(value.as_str(), "stdin");
+        self.push(s, "input", string(&value), source);
+        self.push(
+            s,
+            "input_newlines",
+            string(&value_name(&args.input_newlines)),
+            given_if(args.input_newlines, Default::default()),
+        );
+        self.push(
+            s,
+            "max_token_length",
+            args.max_token_length.to_string(),
+            given_if(args.max_token_length, stdin::DEFAULT_MAX_TOKEN_LEN),
+        );
+        self.push(
+            s,
+            "max_input_bytes",
+            optional_number(args.max_input_bytes),
+            given_if(args.max_input_bytes, None),
+        );
+        self.push(
+            s,
+            "record_input",
+            optional_string(args.record_input.as_deref()),
+            given_if(args.record_input.as_deref(), None),
+        );
+        self.push(
+            s,
+            "output",
+            string(args.output.as_deref().unwrap_or("stdout")),
+            given_if(args.output.as_deref(), None),
+        );
+        self.push(
+            s,
+            "sync_output",
+            is_synchronized(args.sync_output, env).to_string(),
+            match args.sync_output {
+                SyncOutput::Auto => Source::Terminal,
+                _ => Source::Option,
+            },
+        );
+        self.push(
+            s,
+            "io_trace",
+            optional_string(args.io_trace.as_deref()),
+            given_if(args.io_trace.as_deref(), None),
+        );
+    }
+
+    fn push_trace(&mut self, args: &Args, env: &Environment) {
+        let s = "trace";
+        let config = args.runner_config();
+        self.push(
+            s,
+            "verbose",
+            args.verbose.to_string(),
+            given_if(args.verbose, 0),
+        );
+        self.push(
+            s,
+            "print_after",
+            optional_number(config.trace.print_after),
+            given_if(config.trace.print_after, None),
+        );
+        self.push(
+            s,
+            "per_codel_slides",
+            config.trace.per_codel_slides.to_string(),
+            given_if(config.trace.per_codel_slides, false),
+        );
+        self.push(
+            s,
+            "speed",
+            string(&args.speed.to_string()),
+            given_if(args.speed, Default::default()),
+        );
+        self.push(
+            s,
+            "progress",
+            shows_progress(args, env).to_string(),
+            match args.progress {
+                true => Source::Terminal,
+                false => Source::Default,
+            },
+        );
+        for (name, file) in [
+            ("svg", &args.svg),
+            ("report", &args.report),
+            ("assertions", &args.assertions),
+            ("expect_output", &args.expect_output),
+            ("checkpoint_file", &args.checkpoint_file),
+        ] {
+            self.push(
+                s,
+                name,
+                optional_string(file.as_deref()),
+                given_if(file.as_deref(), None),
+            );
+        }
+        self.push(
+            s,
+            "expect_streaming",
+            args.expect_streaming
+                .map_or("null".to_string(), |m| string(&value_name(&m))),
+            given_if(args.expect_streaming, None),
+        );
+        self.push(
+            s,
+            "checkpoint_every",
+            optional_number(args.checkpoint_every),
+            given_if(args.checkpoint_every, None),
+        );
+
+        let s = "art";
+        let max_width = art_max_width(args, env);
+        self.push(
+            s,
+            "max_width",
+            optional_number(max_width),
+            match (args.art_max_width, max_width) {
+                (Some(_), _) => Source::Option,
+                (None, Some(_)) => Source::Terminal,
+                (None, None) => Source::Default,
+            },
+        );
+        self.push(
+            s,
+            "fit",
+            string(&value_name(&args.art_fit)),
+            given_if(args.art_fit, Default::default()),
+        );
+        self.push(s, "scale", args.scale.to_string(), given_if(args.scale, 1));
+    }
+
+    /// Returns the setting `name` of `section`.
+    pub fn get(&self, section: &str, name: &str) -> Option<&Setting> {
+        self.settings
+            .iter()
+            .find(|s| (s.section == section) && (s.name == name))
+    }
+
+    /// Returns the configuration as a JSON object of sections, each of which maps the names of its settings to `{"value":...,"source":"..."}`.
+    pub fn to_json(&self) -> String {
+        let mut sections: Vec<(&str, Vec<String>)> = vec![];
+        for setting in &self.settings {
+            let member = format!(
+                r#"{}:{{"value":{},"source":{}}}"#,
+                string(setting.name),
+                setting.value,
+                string(&setting.source.to_string())
+            );
+            match sections.last_mut() {
+                Some((section, members)) if *section == setting.section => members.push(member),
+                _ => sections.push((setting.section, vec![member])),
+            }
+        }
+        format!(
+            "{{{}}}",
+            sections
+                .iter()
+                .map(|(section, members)| format!("{}:{{{}}}", string(section), members.join(",")))
+                .collect::<Vec<_>>()
+                .join(",")
+        )
+    }
+}
+
+/// Prints a line for each setting, with the value and the source aligned.
+impl Display for EffectiveConfig {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        let name = |s: &Setting| format!("{}.{}", s.section, s.name);
+        let name_width = self
+            .settings
+            .iter()
+            .map(|s| name(s).len())
+            .max()
+            .unwrap_or(0);
+        let value_width = self
+            .settings
+            .iter()
+            .map(|s| s.value.len())
+            .max()
+            .unwrap_or(0);
+        for setting in &self.settings {
+            writeln!(
+                f,
+                "{:nw$}  {:vw$}  ({})",
+                name(setting),
+                setting.value,
+                setting.source,
+                nw = name_width,
+                vw = value_width
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use clap::Parser;
+
+    use super::super::entry::Region;
+    use super::*;
+
+    fn parse_args(options: &[&str]) -> Args {
+        Args::parse_from(["piet_programming_language", "a.png"].iter().chain(options))
+    }
+
+    #[test]
+    fn test_precedence() {
+        let args = parse_args(&[
+            "--limits",
+            "playground",
+            "--max-iter",
+            "500",
+            "--entry",
+            "hi",
+        ]);
+        let load = LoadResolution {
+            pixels: Some((30, 60)),
+            codels: (3, 6),
+            entry: Some((
+                PathBuf::from("a.manifest.toml"),
+                Entry {
+                    name: "hi".to_string(),
+                    line: 2,
+                    crop: Region {
+                        pos: (0, 0),
+                        height: 3,
+                        width: 2,
+                    },
+                },
+            )),
+        };
+        let config = EffectiveConfig::new(&args, Some(&load), &Environment::default());
+        let get = |section, name| {
+            let s = config.get(section, name).unwrap();
+            (s.value.as_str(), s.source.clone())
+        };
+
+        assert_eq!(("500", Source::Option), get("limits", "max_iter"));
+        assert_eq!(
+            ("2", Source::Preset(LimitPreset::Playground)),
+            get("limits", "stall_timeout")
+        );
+        assert_eq!(("\"playground\"", Source::Option), get("limits", "preset"));
+        assert_eq!(("10", Source::Detected), get("load", "codel_size"));
+        assert_eq!(
+            (
+                "\"0,0,3,2\"",
+                Source::Manifest(PathBuf::from("a.manifest.toml"))
+            ),
+            get("load", "entry_region")
+        );
+        assert_eq!(("\"bounded\"", Source::Default), get("exec", "topology"));
+
+        let config = EffectiveConfig::new(
+            &parse_args(&["--codel-size", "5", "--max-iter", "1000000"]),
+            Some(&load),
+            &Environment::default(),
+        );
+        let codel_size = config.get("load", "codel_size").unwrap();
+        assert_eq!(
+            ("5", &Source::Option),
+            (codel_size.value.as_str(), &codel_size.source)
+        );
+        //no preset
+        assert_eq!(
+            Source::Option,
+            config.get("limits", "max_iter").unwrap().source
+        );
+        assert_eq!(
+            Source::Default,
+            config.get("limits", "stall_timeout").unwrap().source
+        );
+    }
+
+    #[test]
+    fn test_environment() {
+        let args = parse_args(&["--progress"]);
+        let env = Environment::new(true, true, Some(80));
+        let config = EffectiveConfig::new(&args, None, &env);
+        assert!(config.get("load", "codel_size").is_none());
+        assert_eq!("true", config.get("io", "sync_output").unwrap().value);
+        assert_eq!("true", config.get("trace", "progress").unwrap().value);
+        assert_eq!("80", config.get("art", "max_width").unwrap().value);
+        assert_eq!(
+            Source::Terminal,
+            config.get("art", "max_width").unwrap().source
+        );
+
+        let env = Environment::new(false, false, None);
+        assert!(!is_synchronized(SyncOutput::Auto, &env));
+        assert!(is_synchronized(SyncOutput::Always, &env));
+        assert!(!shows_progress(&args, &env));
+        assert_eq!(None, art_max_width(&args, &env));
+        assert_eq!(
+            Some(40),
+            art_max_width(&parse_args(&["--art-max-width", "40"]), &env)
+        );
+    }
+
+    #[test]
+    fn test_output() {
+        let config = EffectiveConfig {
+            settings: vec![
+                Setting {
+                    section: "limits",
+                    name: "max_iter",
+                    value: "500".to_string(),
+                    source: Source::Option,
+                },
+                Setting {
+                    section: "limits",
+                    name: "stall_timeout",
+                    value: "2".to_string(),
+                    source: Source::Preset(LimitPreset::Playground),
+                },
+                Setting {
+                    section: "io",
+                    name: "output",
+                    value: string("a\"b"),
+                    source: Source::Default,
+                },
+            ],
+        };
+        assert_eq!(
+            concat!(
+                "limits.max_iter       500     (option)\n",
+                "limits.stall_timeout  2       (preset playground)\n",
+                "io.output             \"a\\\"b\"  (default)\n",
+            ),
+            config.to_string()
+        );
+        assert_eq!(
+            r#"{"limits":{"max_iter":{"value":500,"source":"option"},"stall_timeout":{"value":2,"source":"preset playground"}},"io":{"output":{"value":"a\"b","source":"default"}}}"#,
+            config.to_json()
+        );
+    }
+}
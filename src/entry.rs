@@ -0,0 +1,323 @@
+/*!
+Named entry regions of an image in which several programs are tiled (`--entry`, `--list-entries`).
+
+The regions are declared in a sidecar manifest (by default `program.manifest.toml` for `program.png`, see [`manifest_path()`]):
+
+```text
+# comment
+[entries.sort]
+crop = [0, 0, 64, 32]
+
+[entries.search]
+crop = [0, 40, 64, 32]
+```
+
+`crop` is `[i, j, height, width]` in codels, where `(i, j)` is the top-left codel of the region (`i`-th row, `j`-th column, counted from zero).
+Each region shall be non-empty, lie within the image and not overlap another one.
+Running an entry is the same as running the image cropped to its region, so the program starts at the top-left codel of the region.
+*/
+
+use std::error::Error;
+use std::fmt::{self, Display, Formatter};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use super::coord;
+
+/// Rectangle of codels (or of pixels for `--crop`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Region {
+    /// Top-left corner.
+    pub pos: (usize, usize),
+    pub height: usize,
+    pub width: usize,
+}
+
+impl FromStr for Region {
+    type Err = String;
+
+    /// Parses `i, j, height, width`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let error = || format!("expected `i,j,height,width` but found `{}`", s);
+        let v = s
+            .split(',')
+            .map(|e| e.trim().parse::<usize>().ok())
+            .collect::<Option<Vec<_>>>()
+            .ok_or_else(error)?;
+        let [i, j, height, width] = v[..] else {
+            return Err(error());
+        };
+        //The end shall be representable so that the region can be compared with others.
+        if i.checked_add(height).is_none() || j.checked_add(width).is_none() {
+            return Err(error());
+        }
+        Ok(Region {
+            pos: (i, j),
+            height,
+            width,
+        })
+    }
+}
+
+impl Display for Region {
+    /// Prints the region in the form accepted by [`Region::from_str()`].
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{},{},{},{}",
+            self.pos.0, self.pos.1, self.height, self.width
+        )
+    }
+}
+
+impl Region {
+    //A region made by hand rather than parsed may end beyond `usize::MAX`, which saturates as no image is that large.
+    fn bottom(&self) -> usize {
+        self.pos.0.saturating_add(self.height)
+    }
+
+    fn right(&self) -> usize {
+        self.pos.1.saturating_add(self.width)
+    }
+
+    /// Returns `true` if the region lies within an image of the size.
+    pub fn fits_in(&self, height: usize, width: usize) -> bool {
+        (self.bottom() <= height) && (self.right() <= width)
+    }
+
+    pub fn overlaps(&self, other: &Region) -> bool {
+        (self.pos.0 < other.bottom())
+            && (other.pos.0 < self.bottom())
+            && (self.pos.1 < other.right())
+            && (other.pos.1 < self.right())
+    }
+}
+
+/// One section of a manifest.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Entry {
+    pub name: String,
+    /// Line number of the section header in the manifest (1-based), used in error messages.
+    pub line: usize,
+    pub crop: Region,
+}
+
+/// Returns the default manifest of `image_file` (the extension is replaced with `manifest.toml`).
+pub fn manifest_path(image_file: &str) -> PathBuf {
+    Path::new(image_file).with_extension("manifest.toml")
+}
+
+fn strip_comment(s: &str) -> &str {
+    s.split_once('#').map_or(s, |(s, _)| s)
+}
+
+fn parse_crop(s: &str) -> Option<Region> {
+    s.strip_prefix('[')?.strip_suffix(']')?.parse().ok()
+}
+
+/// Parses the content of a manifest.
+pub fn parse(s: &str) -> Result<Vec<Entry>, Box<dyn Error>> {
+    //name, line of the header and crop of each section
+    let mut sections: Vec<(String, usize, Option<Region>)> = vec![];
+    for (line, content) in s.lines().enumerate() {
+        let line = line + 1;
+        let content = strip_comment(content).trim();
+        if content.is_empty() {
+            continue;
+        }
+
+        if let Some(header) = content.strip_prefix('[') {
+            let name = header
+                .strip_suffix(']')
+                .and_then(|h| h.trim().strip_prefix("entries."))
+                .filter(|name| {
+                    !name.is_empty()
+                        && name
+                            .chars()
+                            .all(|c| c.is_ascii_alphanumeric() || (c == '_') || (c == '-'))
+                })
+                .ok_or(format!(
+                    "line {}: expected `[entries.<name>]` but found `{}`",
+                    line, content
+                ))?;
+            if let Some((_, defined_at, _)) = sections.iter().find(|(n, _, _)| n == name) {
+                return Err(format!(
+                    "line {}: entry `{}` is already defined at line {}",
+                    line, name, defined_at
+                )
+                .into());
+            }
+            sections.push((name.to_string(), line, None));
+            continue;
+        }
+
+        let (key, value) = content
+            .split_once('=')
+            .map(|(k, v)| (k.trim(), v.trim()))
+            .ok_or(format!(
+                "line {}: expected `key = value` but found `{}`",
+                line, content
+            ))?;
+        let Some((_, _, crop)) = sections.last_mut() else {
+            return Err(format!("line {}: `{}` is outside of an entry", line, key).into());
+        };
+        match key {
+            "crop" => {
+                *crop = Some(parse_crop(value).ok_or(format!(
+                    "line {}: invalid value of `crop`: `{}` (expected `[i, j, height, width]`)",
+                    line, value
+                ))?)
+            }
+            _ => return Err(format!("line {}: unknown key `{}`", line, key).into()),
+        }
+    }
+
+    sections
+        .into_iter()
+        .map(|(name, line, crop)| {
+            let crop = crop.ok_or(format!("line {}: `crop` of `{}` is missing", line, name))?;
+            Ok(Entry { name, line, crop })
+        })
+        .collect()
+}
+
+/// Checks that the regions are non-empty, lie within an image of the size and don't overlap each other.
+pub fn validate(entries: &[Entry], height: usize, width: usize) -> Result<(), Box<dyn Error>> {
+    for (k, e) in entries.iter().enumerate() {
+        if (e.crop.height == 0) || (e.crop.width == 0) {
+            return Err(format!("line {}: the region of `{}` is empty", e.line, e.name).into());
+        }
+        coord::check_region(
+            &format!("line {}", e.line),
+            &format!("the region of `{}`", e.name),
+            &e.crop,
+            height,
+            width,
+        )?;
+        if let Some(other) = entries[..k].iter().find(|o| o.crop.overlaps(&e.crop)) {
+            return Err(format!(
+                "line {}: the region of `{}` overlaps that of `{}`",
+                e.line, e.name, other.name
+            )
+            .into());
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn region(i: usize, j: usize, height: usize, width: usize) -> Region {
+        Region {
+            pos: (i, j),
+            height,
+            width,
+        }
+    }
+
+    #[test]
+    fn test_parse() {
+        let s = "
+# two routines
+[entries.sort]
+crop = [0, 0, 64, 32]
+
+[entries.search] # the second one
+crop = [0,40,64,32]
+";
+        assert_eq!(
+            vec![
+                Entry {
+                    name: "sort".to_string(),
+                    line: 3,
+                    crop: region(0, 0, 64, 32),
+                },
+                Entry {
+                    name: "search".to_string(),
+                    line: 6,
+                    crop: region(0, 40, 64, 32),
+                },
+            ],
+            parse(s).unwrap()
+        );
+        assert!(parse("").unwrap().is_empty());
+
+        let error = |s: &str| parse(s).unwrap_err().to_string();
+        assert_eq!(
+            "line 1: expected `[entries.<name>]` but found `[sort]`",
+            error("[sort]")
+        );
+        assert_eq!(
+            "line 1: `crop` is outside of an entry",
+            error("crop = [0, 0, 1, 1]")
+        );
+        assert_eq!(
+            "line 1: `crop` of `a` is missing",
+            error("[entries.a]\n[entries.b]\ncrop = [0, 0, 1, 1]")
+        );
+        assert_eq!(
+            "line 3: entry `a` is already defined at line 1",
+            error("[entries.a]\ncrop = [0, 0, 1, 1]\n[entries.a]\ncrop = [0, 1, 1, 1]")
+        );
+        assert_eq!(
+            "line 2: invalid value of `crop`: `[0, 0, 1]` (expected `[i, j, height, width]`)",
+            error("[entries.a]\ncrop = [0, 0, 1]")
+        );
+        assert_eq!(
+            "line 2: unknown key `at`",
+            error("[entries.a]\nat = [0, 0]")
+        );
+        assert!(parse(&format!("[entries.a]\ncrop = [{}, 0, 1, 1]", usize::MAX)).is_err());
+    }
+
+    #[test]
+    fn test_validate() {
+        let entry = |name: &str, line: usize, crop: Region| Entry {
+            name: name.to_string(),
+            line,
+            crop,
+        };
+        let a = entry("a", 1, region(0, 0, 11, 21));
+        let b = entry("b", 3, region(0, 22, 11, 21));
+        assert!(validate(&[a.clone(), b.clone()], 11, 43).is_ok());
+        assert_eq!(Ok(b.crop), "0, 22,11,21".parse());
+        assert_eq!("0,22,11,21", b.crop.to_string());
+        assert!("0,22,11".parse::<Region>().is_err());
+        assert_eq!(
+            "line 3: the region of `b` exceeds the image (11 x 42 codels)",
+            validate(&[a.clone(), b.clone()], 11, 42)
+                .unwrap_err()
+                .to_string()
+        );
+        //a region made by hand may end beyond `usize::MAX`
+        for crop in [
+            region(usize::MAX, 0, 1, 1),
+            region(0, 1, 1, usize::MAX),
+            region(usize::MAX, usize::MAX, usize::MAX, usize::MAX),
+        ] {
+            assert_eq!(
+                "line 1: the region of `a` exceeds the image (11 x 43 codels)",
+                validate(&[entry("a", 1, crop)], 11, 43)
+                    .unwrap_err()
+                    .to_string()
+            );
+        }
+        let c = entry("c", 5, region(10, 20, 1, 3));
+        assert_eq!(
+            "line 5: the region of `c` overlaps that of `a`",
+            validate(&[a.clone(), b.clone(), c], 11, 43)
+                .unwrap_err()
+                .to_string()
+        );
+        //touching regions don't overlap
+        let d = entry("d", 5, region(11, 0, 1, 43));
+        assert!(validate(&[a.clone(), b, d], 12, 43).is_ok());
+        let e = entry("e", 5, region(0, 0, 0, 1));
+        assert_eq!(
+            "line 5: the region of `e` is empty",
+            validate(&[e], 1, 1).unwrap_err().to_string()
+        );
+    }
+}
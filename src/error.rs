@@ -0,0 +1,408 @@
+use std::{
+    error::Error,
+    fmt::{self, Display},
+    io,
+};
+
+use super::command::Command;
+use super::entry::Region;
+use super::frames::{self, Container, Frame};
+use super::image::{GridSpec, Pixel, MAX_COLOR_TOLERANCE};
+use super::limits::ImageLimit;
+use super::program::Fingerprint;
+use super::run_result::TerminationReason;
+
+/// Errors specific to this interpreter.
+///
+/// Functions in this crate generally return `Box<dyn Error>`, so callers who need to inspect the cause can use `downcast_ref::<PietError>()`.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum PietError {
+    /// The detection of the codel size didn't finish within its time budget (`--detect-budget` with `--detect-fallback error`).
+    /// `partial` is the codel grid detected from the pixels checked so far, if any.
+    DetectionTimedOut { partial: Option<GridSpec> },
+
+    /// A position or a region read from `input` (e.g. a checkpoint, an assertion file or a manifest) lies outside of the image of `image_height x image_width` codels.
+    /// `value` describes it, e.g. "the position (3, 4)".
+    InvalidCoordinate {
+        input: String,
+        value: String,
+        image_height: usize,
+        image_width: usize,
+    },
+
+    /// A position outside of the image was passed to an accessor.
+    PositionOutOfBounds {
+        pos: (usize, usize),
+        image_height: usize,
+        image_width: usize,
+    },
+
+    /// The program tried to enter or leave a block of a color which is not one of the 20 colors of Piet.
+    UnknownColorReached { pos: (usize, usize), pixel: Pixel },
+
+    /// `num_unknown` of the `num_codels` codels of a JPEG image have none of the 20 colors of Piet, even within `tolerance` (`--color-tolerance`).
+    /// The compression of JPEG alters the colors, so this is reported instead of the first unknown color reached (see [`MIN_MATCHED_PERCENT`](super::image::MIN_MATCHED_PERCENT)).
+    LossyImage {
+        num_unknown: usize,
+        num_codels: usize,
+        tolerance: u8,
+    },
+
+    /// An assertion given by `--assertions` failed.
+    /// `line` is the line of the assertion in the file and `step` is the number of iterations so far.
+    AssertionFailed {
+        line: usize,
+        pos: (usize, usize),
+        step: usize,
+        message: String,
+    },
+
+    /// An interpreter bound to one program was run against another (see [`Interpreter::bind()`](super::interpreter::Interpreter::bind)).
+    MismatchedProgram {
+        expected: Fingerprint,
+        found: Fingerprint,
+    },
+
+    /// The header of the image declares a size which exceeds one of the [`ImageLimits`](super::limits::ImageLimits).
+    /// The size is in pixels, and the image is not decoded.
+    ImageTooLarge {
+        image_height: u64,
+        image_width: u64,
+        exceeded: ImageLimit,
+    },
+
+    /// The image has no block of one of the 18 hue colors, so no command can be executed (see `--allow-empty`).
+    NoExecutableBlocks,
+
+    /// An observer tried to alter the execution at `step` without `--allow-interventions` (see [`Observer`](super::observer::Observer)).
+    InterventionNotAllowed { step: usize, command: Command },
+
+    /// The top-left codel is black (see `--lenient-start`), or every codel is black.
+    BlackStartCodel,
+
+    /// The program was terminated by `max-iter` or `stall-timeout`.
+    LimitReached(TerminationReason),
+
+    /// The outputs of the two interpreters differ (`compare`), or the output differs from the expected one (`--expect-output`).
+    OutputsDiffer,
+
+    /// The two path traces diverge (`trace-diff`).
+    TracesDiffer,
+
+    /// `num_failed` of `num_cases` conformance test vectors failed (`conformance --verify-conformance`).
+    ConformanceFailed { num_failed: usize, num_cases: usize },
+
+    /// `num_failed` of the `num_checks` checks of the built-in tables against the spec failed (`--self-test`).
+    SelfTestFailed {
+        num_failed: usize,
+        num_checks: usize,
+    },
+
+    /// The pixels of `crop` (`--crop`) don't form a grid of uniform codels of `codel_size`, but those of `suggested` do.
+    MisalignedCrop {
+        crop: Region,
+        codel_size: usize,
+        suggested: Region,
+    },
+
+    /// The image file contains several images (e.g. the pages of a TIFF) and none of them was chosen with `--frame`.
+    MultipleFrames {
+        container: Container,
+        frames: Vec<Frame>,
+    },
+
+    /// `--frame` chose an image which the image file doesn't contain.
+    /// `container` is `None` if the file is of a format which contains only one image.
+    FrameOutOfRange {
+        frame: usize,
+        container: Option<Container>,
+        frames: Vec<Frame>,
+    },
+
+    /// Warnings were raised with `--deny-warnings` (see [`Warning`](super::warning::Warning)).
+    WarningsDenied { num_warnings: usize },
+
+    /// Writing `what` (e.g. "the output") failed during the run, which ends it.
+    /// `kind` is [`io::ErrorKind::BrokenPipe`] when the reader of a pipe has gone away (e.g. `| head`), which the command line treats as a normal end.
+    WriteFailed {
+        what: &'static str,
+        kind: io::ErrorKind,
+        message: String,
+    },
+
+    /// Reading the input failed during the run (e.g. `EIO`), which ends it.
+    ReadFailed { message: String },
+}
+
+impl Display for PietError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PietError::DetectionTimedOut { partial } => {
+                write!(
+                    f,
+                    "the detection of the codel size did not finish within the time budget (`--detect-budget`); provide `--codel-size` explicitly"
+                )?;
+                match partial {
+                    Some(grid) => write!(
+                        f,
+                        " (the pixels checked so far tile with `--codel-grid {}`)",
+                        grid
+                    ),
+                    None => Ok(()),
+                }
+            }
+            PietError::InvalidCoordinate {
+                input,
+                value,
+                image_height,
+                image_width,
+            } => write!(
+                f,
+                "{}: {} exceeds the image ({} x {} codels)",
+                input, value, image_height, image_width
+            ),
+            PietError::PositionOutOfBounds {
+                pos,
+                image_height,
+                image_width,
+            } => write!(
+                f,
+                "position {:?} is out of bounds of the image ({} x {} codels)",
+                pos, image_height, image_width
+            ),
+            PietError::UnknownColorReached { pos, pixel } => write!(
+                f,
+                "the program reached the unknown color #{:02X}{:02X}{:02X} at {:?}; provide `--fall-back-to-white` or `--fall-back-to-black` to treat unknown colors as white or black",
+                pixel.r, pixel.g, pixel.b, pos
+            ),
+            PietError::LossyImage {
+                num_unknown,
+                num_codels,
+                tolerance,
+            } => {
+                write!(
+                    f,
+                    "{} of the {} codels ({:.1}%) have none of the 20 colors of Piet",
+                    num_unknown,
+                    num_codels,
+                    100.0 * (*num_unknown as f64) / (*num_codels as f64)
+                )?;
+                if *tolerance == 0 {
+                    write!(
+                        f,
+                        ", which is typical of a JPEG image as its compression alters the colors of the pixels; \
+                         provide `--color-tolerance` (e.g. `--color-tolerance 31`) to read the nearest colors, or re-export the program as PNG"
+                    )
+                } else {
+                    write!(
+                        f,
+                        " within `--color-tolerance {}`, as the compression of JPEG altered them too much; \
+                         provide a larger tolerance (at most {}), or re-export the program as PNG",
+                        tolerance, MAX_COLOR_TOLERANCE
+                    )
+                }
+            }
+            PietError::AssertionFailed {
+                line,
+                pos,
+                step,
+                message,
+            } => write!(
+                f,
+                "assertion at line {} failed at {:?} (step {}): {}",
+                line, pos, step, message
+            ),
+            PietError::MismatchedProgram { expected, found } => write!(
+                f,
+                "the interpreter is bound to the program {} but was run against the program {}; call `Interpreter::rebind()` if this is intended",
+                expected, found
+            ),
+            PietError::ImageTooLarge {
+                image_height,
+                image_width,
+                exceeded,
+            } => {
+                write!(f, "the image ({} x {} pixels) ", image_height, image_width)?;
+                match exceeded {
+                    ImageLimit::Dimension(limit) => write!(
+                        f,
+                        "exceeds the maximum width and height of {} pixels; provide a larger `--max-image-dimension` if the image is trusted",
+                        limit
+                    ),
+                    ImageLimit::Pixels(limit) => write!(
+                        f,
+                        "exceeds the maximum of {} pixels; provide a larger `--max-image-pixels` if the image is trusted",
+                        limit
+                    ),
+                    ImageLimit::DecodedBytes { bytes, limit } => write!(
+                        f,
+                        "decodes into {} bytes, which exceeds the maximum of {} bytes; provide a larger `--max-image-bytes` if the image is trusted",
+                        bytes, limit
+                    ),
+                }
+            }
+            PietError::NoExecutableBlocks => write!(
+                f,
+                "the image has no colored block (every codel is white, black or of an unknown color), so no command can be executed; provide `--allow-empty` to run it anyway"
+            ),
+            PietError::InterventionNotAllowed { step, command } => write!(
+                f,
+                "an observer tried to alter {:?} at step {}; provide `--allow-interventions` to let it",
+                command, step
+            ),
+            PietError::BlackStartCodel => write!(
+                f,
+                "the top-left codel shall not be black; provide `--lenient-start` to start at the first non-black codel"
+            ),
+            PietError::LimitReached(reason) => write!(
+                f,
+                "the program was terminated by `{}`",
+                match reason {
+                    TerminationReason::Stall => "stall-timeout",
+                    _ => "max-iter",
+                }
+            ),
+            PietError::OutputsDiffer => write!(f, "outputs differ"),
+            PietError::TracesDiffer => write!(f, "traces differ"),
+            PietError::ConformanceFailed {
+                num_failed,
+                num_cases,
+            } => write!(
+                f,
+                "{} of {} conformance case(s) failed",
+                num_failed, num_cases
+            ),
+            PietError::SelfTestFailed {
+                num_failed,
+                num_checks,
+            } => write!(
+                f,
+                "{} of {} self-test check(s) failed",
+                num_failed, num_checks
+            ),
+            PietError::MisalignedCrop {
+                crop,
+                codel_size,
+                suggested,
+            } => write!(
+                f,
+                "the crop {} cuts codels of {} pixels, which would shift the codel grid; provide `--crop {}` to align the crop with the codels",
+                crop, codel_size, suggested
+            ),
+            PietError::MultipleFrames { container, frames } => write!(
+                f,
+                "the image has {} {}s; provide `--frame <N>` to choose the program among them:{}",
+                frames.len(),
+                container.unit(),
+                frames::describe(frames)
+            ),
+            PietError::FrameOutOfRange {
+                frame,
+                container,
+                frames,
+            } => {
+                let unit = container.map_or("image", Container::unit);
+                write!(
+                    f,
+                    "`--frame {}` is out of range as the image has {} {}{}:{}",
+                    frame,
+                    frames.len(),
+                    unit,
+                    if frames.len() == 1 { "" } else { "s" },
+                    frames::describe(frames)
+                )
+            }
+            PietError::WarningsDenied { num_warnings } => write!(
+                f,
+                "{} warning(s) were raised, which are errors with `--deny-warnings`",
+                num_warnings
+            ),
+            PietError::WriteFailed { what, message, .. } => {
+                write!(f, "failed to write {}: {}", what, message)
+            }
+            PietError::ReadFailed { message } => {
+                write!(f, "failed to read the input: {}", message)
+            }
+        }
+    }
+}
+
+impl PietError {
+    /// Returns the error of a run in which writing `what` failed with `e`.
+    pub fn write_failed(what: &'static str, e: io::Error) -> Self {
+        PietError::WriteFailed {
+            what,
+            kind: e.kind(),
+            message: e.to_string(),
+        }
+    }
+
+    /// Returns `true` if the reader of the output has gone away, e.g. `| head` after it has read enough.
+    pub fn is_broken_pipe(&self) -> bool {
+        matches!(
+            self,
+            PietError::WriteFailed {
+                kind: io::ErrorKind::BrokenPipe,
+                ..
+            }
+        )
+    }
+}
+
+impl Error for PietError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test02() {
+        let e = PietError::UnknownColorReached {
+            pos: (3, 4),
+            pixel: Pixel {
+                r: 128,
+                g: 0,
+                b: 255,
+            },
+        };
+        assert!(e
+            .to_string()
+            .starts_with("the program reached the unknown color #8000FF at (3, 4);"));
+    }
+
+    #[test]
+    fn test03() {
+        let e = PietError::AssertionFailed {
+            line: 2,
+            pos: (3, 7),
+            step: 15,
+            message: "expected the stack depth to be 0 but it is 1".to_string(),
+        };
+        assert_eq!(
+            "assertion at line 2 failed at (3, 7) (step 15): expected the stack depth to be 0 but it is 1",
+            e.to_string()
+        );
+    }
+
+    #[test]
+    fn test_lossy_image() {
+        let e = PietError::LossyImage {
+            num_unknown: 710,
+            num_codels: 3312,
+            tolerance: 0,
+        };
+        let s = e.to_string();
+        assert!(s.starts_with("710 of the 3312 codels (21.4%) have none of the 20 colors of Piet, which is typical of a JPEG image"));
+        assert!(s.contains("`--color-tolerance 31`"));
+        assert!(s.ends_with("re-export the program as PNG"));
+
+        let e = PietError::LossyImage {
+            num_unknown: 74,
+            num_codels: 3312,
+            tolerance: 16,
+        };
+        assert!(e.to_string().contains(
+            "(2.2%) have none of the 20 colors of Piet within `--color-tolerance 16`, as the compression of JPEG altered them too much; provide a larger tolerance (at most 31)"
+        ));
+    }
+}
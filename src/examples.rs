@@ -0,0 +1,135 @@
+/*!
+Small example programs embedded in the binary (`--example`, `--list-examples`), so that the interpreter can be tried without finding a valid image first.
+
+The programs are PietDev saves (see [`pietdev`]) written for this crate, and are always available to tests regardless of the layout of the repository.
+`--example <NAME> --extract <DIR>` writes a copy of one to be opened in an editor.
+*/
+
+use std::fmt::{self, Display, Formatter};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use clap::ValueEnum;
+
+use super::image::Image;
+use super::pietdev;
+use super::program::Program;
+
+/// An embedded example program.
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Example {
+    /// Prints `Hello, world!`
+    Hello,
+    /// Copies the input to the output until EOF, except whitespace (which `in(char)` skips)
+    Cat,
+    /// Reads two numbers and prints their sum
+    Adder,
+    /// Prints 1 and slides into a block from which no move is possible, which terminates the program
+    Trap,
+}
+
+impl Display for Example {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "{}", self.to_possible_value().unwrap().get_name())
+    }
+}
+
+impl Example {
+    /// The PietDev save of the program.
+    pub fn source(self) -> &'static str {
+        match self {
+            Example::Hello => include_str!("examples/hello.pietdev"),
+            Example::Cat => include_str!("examples/cat.pietdev"),
+            Example::Adder => include_str!("examples/adder.pietdev"),
+            Example::Trap => include_str!("examples/trap.pietdev"),
+        }
+    }
+
+    /// One-line description of what the program does.
+    pub fn description(self) -> String {
+        self.to_possible_value()
+            .and_then(|v| v.get_help().map(|s| s.to_string()))
+            .unwrap_or_default()
+    }
+
+    pub fn file_name(self) -> String {
+        format!("{}.pietdev", self)
+    }
+
+    pub fn image(self) -> Image {
+        //checked by `test_examples`
+        Image::from_codel_grid(pietdev::parse(self.source()).unwrap())
+    }
+
+    pub fn program(self) -> Program {
+        Program::new(self.image())
+    }
+
+    /// Writes a copy of the program into `dir` (created if missing), returning the path of the file.
+    pub fn extract(self, dir: &Path) -> io::Result<PathBuf> {
+        fs::create_dir_all(dir)?;
+        let path = dir.join(self.file_name());
+        fs::write(&path, self.source())?;
+        Ok(path)
+    }
+}
+
+/// Lists the examples, one per line with the description (`--list-examples`).
+pub fn list() -> String {
+    let width = Example::value_variants()
+        .iter()
+        .map(|e| e.to_string().len())
+        .max()
+        .unwrap_or(0);
+    Example::value_variants()
+        .iter()
+        .map(|e| format!("{:w$}  {}\n", e.to_string(), e.description(), w = width))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::run_result::TerminationReason;
+    use super::super::runner::{Output, Runner, RunnerConfig};
+    use super::*;
+
+    /// Runs `example` with `input`, returning the termination and the output.
+    fn run(example: Example, input: &str) -> (TerminationReason, String) {
+        let mut runner = Runner::new(RunnerConfig {
+            input: Some(input.as_bytes().to_vec()),
+            output: Output::Capture,
+            ..RunnerConfig::default()
+        });
+        let result = runner.run(&example.program()).unwrap();
+        (
+            result.reason,
+            String::from_utf8(runner.output().to_vec()).unwrap(),
+        )
+    }
+
+    #[test]
+    fn test_examples() {
+        use TerminationReason::*;
+
+        assert_eq!(
+            (NoExit, "Hello, world!\n".to_string()),
+            run(Example::Hello, "")
+        );
+        assert_eq!(
+            (NoExit, "abcxyz".to_string()),
+            run(Example::Cat, "abc\nxyz")
+        );
+        assert_eq!((NoExit, String::new()), run(Example::Cat, ""));
+        assert_eq!((NoExit, "42\n".to_string()), run(Example::Adder, "12 30"));
+        assert_eq!((NoExit, "1\n".to_string()), run(Example::Trap, ""));
+    }
+
+    #[test]
+    fn test_list() {
+        let list = list();
+        assert_eq!(Example::value_variants().len(), list.lines().count());
+        assert!(list.starts_with("hello  Prints `Hello, world!`\n"));
+        assert!(list.contains("\nadder  Reads two numbers and prints their sum\n"));
+    }
+}
@@ -0,0 +1,132 @@
+/*!
+Exit status of the command line, which is a stable contract for scripts.
+
+The status is determined only by the type of the error (see [`ExitStatus::of_error()`]), so every subcommand follows the same mapping.
+A panic exits with `101` as usual for Rust programs.
+*/
+
+use std::error::Error;
+use std::process::ExitCode;
+
+use super::error::PietError;
+
+/// Exit status of the command line.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExitStatus {
+    Success = 0,
+    /// The image is not a valid program (e.g. an unknown color was reached or the start codel is black), or a warning was raised with `--deny-warnings`.
+    ProgramError = 2,
+    /// A file could not be read or parsed, or the command line is invalid.
+    IoOrUsageError = 3,
+    /// A limit was exceeded (e.g. `max-iter`, `stall-timeout`, `--detect-budget` or the size of the image).
+    LimitExceeded = 4,
+    /// A check failed (`--assertions`, `compare`, `trace-diff`, `conformance` or `--self-test`).
+    VerificationFailed = 5,
+}
+
+/// Description of the exit statuses shown in `--help`.
+pub const HELP: &str = "Exit status:
+  0    success, also when the reader of the output goes away (e.g. `| head`)
+  2    the image is not a valid program (e.g. an unknown color was reached or the start codel is black), or a warning was raised with `--deny-warnings`
+  3    a file could not be read or parsed, the output could not be written, or the command line is invalid
+  4    a limit was exceeded (`max-iter`, `stall-timeout`, `--detect-budget` with `--detect-fallback error`, or the size of the image)
+  5    a check failed (`--assertions`, `--expect-output`, `compare`, `trace-diff`, `conformance` or `--self-test`)
+  101  internal error (panic)";
+
+impl ExitStatus {
+    /// Returns the exit status for `e`.
+    /// An error which is not a [`PietError`] (e.g. an I/O error) is [`ExitStatus::IoOrUsageError`].
+    pub fn of_error(e: &(dyn Error + 'static)) -> Self {
+        let Some(e) = e.downcast_ref::<PietError>() else {
+            return ExitStatus::IoOrUsageError;
+        };
+        match e {
+            PietError::UnknownColorReached { .. }
+            | PietError::LossyImage { .. }
+            | PietError::NoExecutableBlocks
+            | PietError::BlackStartCodel
+            | PietError::WarningsDenied { .. } => ExitStatus::ProgramError,
+            PietError::PositionOutOfBounds { .. }
+            | PietError::InvalidCoordinate { .. }
+            | PietError::MismatchedProgram { .. }
+            | PietError::InterventionNotAllowed { .. }
+            | PietError::MisalignedCrop { .. }
+            | PietError::MultipleFrames { .. }
+            | PietError::FrameOutOfRange { .. }
+            | PietError::WriteFailed { .. }
+            | PietError::ReadFailed { .. } => ExitStatus::IoOrUsageError,
+            PietError::ImageTooLarge { .. }
+            | PietError::LimitReached(_)
+            | PietError::DetectionTimedOut { .. } => ExitStatus::LimitExceeded,
+            PietError::AssertionFailed { .. }
+            | PietError::OutputsDiffer
+            | PietError::TracesDiffer
+            | PietError::ConformanceFailed { .. }
+            | PietError::SelfTestFailed { .. } => ExitStatus::VerificationFailed,
+        }
+    }
+
+    pub fn code(self) -> u8 {
+        self as u8
+    }
+}
+
+impl From<ExitStatus> for ExitCode {
+    fn from(status: ExitStatus) -> Self {
+        ExitCode::from(status.code())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::run_result::TerminationReason;
+    use super::*;
+
+    #[test]
+    fn test_of_error() {
+        let status = |e: Box<dyn Error>| ExitStatus::of_error(e.as_ref()).code();
+        assert_eq!(2, status(PietError::BlackStartCodel.into()));
+        assert_eq!(2, status(PietError::NoExecutableBlocks.into()));
+        assert_eq!(
+            2,
+            status(PietError::WarningsDenied { num_warnings: 1 }.into())
+        );
+        assert_eq!(3, status("file not found".into()));
+        assert_eq!(
+            3,
+            status(
+                PietError::InvalidCoordinate {
+                    input: "line 1".to_string(),
+                    value: "the position (0, 0)".to_string(),
+                    image_height: 0,
+                    image_width: 0,
+                }
+                .into()
+            )
+        );
+        assert_eq!(
+            3,
+            status(std::io::Error::from(std::io::ErrorKind::NotFound).into())
+        );
+        assert_eq!(
+            3,
+            status(
+                PietError::write_failed(
+                    "the output",
+                    std::io::Error::from(std::io::ErrorKind::BrokenPipe)
+                )
+                .into()
+            )
+        );
+        assert_eq!(
+            4,
+            status(PietError::LimitReached(TerminationReason::MaxIter).into())
+        );
+        assert_eq!(
+            4,
+            status(PietError::DetectionTimedOut { partial: None }.into())
+        );
+        assert_eq!(5, status(PietError::OutputsDiffer.into()));
+        assert_eq!(5, status(PietError::TracesDiffer.into()));
+    }
+}
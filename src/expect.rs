@@ -0,0 +1,635 @@
+/*!
+Comparison of the output with an expected output file (`--expect-output`).
+
+The output of a program is UTF-8, while an expected output file may have been saved by another tool with a byte order mark or in Latin-1.
+So the file is decoded as `--expect-encoding` before it is compared byte by byte, a UTF-8 byte order mark being stripped.
+
+When the two sides differ, the report shows the first difference with its surroundings (as hex if a side is not valid UTF-8, so that binary data is safe to print),
+and tells whether the two sides would match in another encoding, or by replacing CRLF with LF, as such a mismatch is hard to see in the excerpts.
+
+This needs the whole output, which is too much for a program printing hundreds of megabytes.
+So with `--expect-streaming`, an [`ExpectStream`] compares each byte as it is written with the file read (and decoded) incrementally, in memory which doesn't depend on the size of the output.
+The report shows the first difference the same way, from the bytes kept around it, but without the hint.
+At the difference, the run is stopped or continues to the end for its statistics, as [`OnMismatch`] tells.
+*/
+
+use std::collections::VecDeque;
+use std::fmt::{self, Display, Formatter};
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Read};
+
+use clap::ValueEnum;
+use itertools::Itertools;
+
+/// How the expected output file is decoded.
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum ExpectEncoding {
+    /// UTF-8, with a leading byte order mark stripped
+    #[default]
+    Utf8,
+    /// Latin-1 (ISO-8859-1), transcoded to UTF-8
+    Latin1,
+    /// Compared as it is
+    Bytes,
+}
+
+impl Display for ExpectEncoding {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "{}", self.to_possible_value().unwrap().get_name())
+    }
+}
+
+/// What `--expect-streaming` does at the first difference.
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum OnMismatch {
+    /// Stops the program
+    #[default]
+    Stop,
+    /// Runs the program to the end (e.g. for `--stats`), reporting the difference after it
+    Complete,
+}
+
+impl Display for OnMismatch {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "{}", self.to_possible_value().unwrap().get_name())
+    }
+}
+
+const BOM: &[u8] = b"\xEF\xBB\xBF";
+
+/// Number of bytes shown on each side of the first difference.
+const CONTEXT: usize = 16;
+
+/// Decodes `bytes` as `encoding` into UTF-8 (or leaves them as they are for [`ExpectEncoding::Bytes`]).
+pub fn decode(bytes: &[u8], encoding: ExpectEncoding) -> Vec<u8> {
+    match encoding {
+        ExpectEncoding::Utf8 => bytes.strip_prefix(BOM).unwrap_or(bytes).to_vec(),
+        ExpectEncoding::Latin1 => bytes
+            .iter()
+            .map(|&b| char::from(b))
+            .collect::<String>()
+            .into_bytes(),
+        ExpectEncoding::Bytes => bytes.to_vec(),
+    }
+}
+
+/// Replaces CRLF with LF.
+fn normalize(bytes: &[u8]) -> Vec<u8> {
+    let mut ret = Vec::with_capacity(bytes.len());
+    for (k, &b) in bytes.iter().enumerate() {
+        if !((b == b'\r') && (bytes.get(k + 1) == Some(&b'\n'))) {
+            ret.push(b);
+        }
+    }
+    ret
+}
+
+/// Difference between the expected output and the output.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Mismatch {
+    /// The expected output file decoded.
+    pub expected: Vec<u8>,
+    pub output: Vec<u8>,
+    /// Offset of the first differing byte (or the length of the shorter side if it is a prefix of the other).
+    pub offset: usize,
+    /// Why the two sides may differ, if they match after transcoding or normalization.
+    pub hint: Option<String>,
+}
+
+/// Compares `output` with `expected_file` (the contents of an expected output file) decoded as `encoding`.
+pub fn compare(
+    expected_file: &[u8],
+    output: &[u8],
+    encoding: ExpectEncoding,
+) -> Result<(), Mismatch> {
+    let expected = decode(expected_file, encoding);
+    if expected == output {
+        return Ok(());
+    }
+    let offset = expected
+        .iter()
+        .zip(output)
+        .position(|(a, b)| a != b)
+        .unwrap_or(expected.len().min(output.len()));
+    Err(Mismatch {
+        hint: hint(expected_file, output, encoding),
+        expected,
+        output: output.to_vec(),
+        offset,
+    })
+}
+
+/// Tells how the two sides can match, trying the other encodings first and then each encoding with CRLF replaced with LF.
+fn hint(expected_file: &[u8], output: &[u8], encoding: ExpectEncoding) -> Option<String> {
+    for is_normalized in [false, true] {
+        let normalized = |bytes: Vec<u8>| {
+            if is_normalized {
+                normalize(&bytes)
+            } else {
+                bytes
+            }
+        };
+        let output = normalized(output.to_vec());
+        let how = |encoding_hint: Option<String>| match (encoding_hint, is_normalized) {
+            (Some(s), false) => format!("differ only by encoding: {}", s),
+            (Some(s), true) => format!(
+                "differ only by encoding and normalization: {} and CRLF is replaced with LF",
+                s
+            ),
+            (None, _) => {
+                "differ only by normalization: they match if CRLF is replaced with LF".to_string()
+            }
+        };
+        for &e in ExpectEncoding::value_variants() {
+            if ((e != encoding) || is_normalized)
+                && (normalized(decode(expected_file, e)) == output)
+            {
+                let s =
+                    (e != encoding).then(|| format!("they match with `--expect-encoding {}`", e));
+                return Some(format!("the two sides {}", how(s)));
+            }
+        }
+        //the expected file was made by reading the UTF-8 output as Latin-1 and saving it as UTF-8
+        let double_encoded = normalized(decode(output.as_slice(), ExpectEncoding::Latin1));
+        if normalized(decode(expected_file, ExpectEncoding::Utf8)) == double_encoded {
+            let s =
+                "the expected file is the output read as Latin-1 and saved as UTF-8".to_string();
+            return Some(format!("the two sides {}", how(Some(s))));
+        }
+    }
+    None
+}
+
+/// Shows the bytes of `bytes` around `offset`, as a string literal if `bytes` is valid UTF-8 and as hex otherwise.
+fn excerpt(bytes: &[u8], offset: usize) -> String {
+    let mut start = offset.saturating_sub(CONTEXT);
+    let mut end = (offset + CONTEXT).min(bytes.len());
+    match std::str::from_utf8(bytes) {
+        Ok(s) => {
+            while !s.is_char_boundary(start) {
+                start -= 1;
+            }
+            while !s.is_char_boundary(end) {
+                end += 1;
+            }
+            format!("{:?} (bytes {}..{})", &s[start..end], start, end)
+        }
+        Err(_) => hex_excerpt(&bytes[start..end], start),
+    }
+}
+
+fn hex_excerpt(window: &[u8], start: usize) -> String {
+    format!(
+        "hex {} (bytes {}..{}, not UTF-8)",
+        window.iter().map(|b| format!("{:02x}", b)).join(" "),
+        start,
+        start + window.len()
+    )
+}
+
+/// Same as [`excerpt()`] but shows `window`, the bytes from `start` kept by an [`ExpectStream`].
+/// As the window may cut characters at its ends, the cut bytes are dropped before it is read as UTF-8.
+fn window_excerpt(window: &[u8], start: usize) -> String {
+    let head = window
+        .iter()
+        .take(3)
+        .take_while(|&&b| (b & 0xC0) == 0x80)
+        .count();
+    let trimmed = &window[head..];
+    let valid = match std::str::from_utf8(trimmed) {
+        Ok(s) => Some(s),
+        //an incomplete character at the end
+        Err(e) if e.error_len().is_none() => {
+            Some(std::str::from_utf8(&trimmed[..e.valid_up_to()]).unwrap())
+        }
+        Err(_) => None,
+    };
+    match valid {
+        Some(s) => format!(
+            "{:?} (bytes {}..{})",
+            s,
+            start + head,
+            start + head + s.len()
+        ),
+        None => hex_excerpt(window, start),
+    }
+}
+
+/// Shows the first difference, where `-` is the expected output and `+` is the output.
+impl Display for Mismatch {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        writeln!(
+            f,
+            "first difference at byte {} (expected {} bytes, got {})",
+            self.offset,
+            self.expected.len(),
+            self.output.len()
+        )?;
+        writeln!(f, "- {}", excerpt(&self.expected, self.offset))?;
+        writeln!(f, "+ {}", excerpt(&self.output, self.offset))?;
+        if let Some(hint) = &self.hint {
+            writeln!(f, "hint: {}", hint)?;
+        }
+        Ok(())
+    }
+}
+
+/// Comparison of the output with an expected output file as the output is written (`--expect-streaming`).
+///
+/// Only the last 16 bytes before the position compared are kept (and as many after the first difference), so the memory used is constant.
+pub struct ExpectStream {
+    reader: BufReader<Box<dyn Read + Send>>,
+    encoding: ExpectEncoding,
+    on_mismatch: OnMismatch,
+    /// The second byte of a character decoded from Latin-1, not compared yet.
+    pending: Option<u8>,
+    /// Whether the reading has started (and a byte order mark has been stripped).
+    has_started: bool,
+    /// Number of the bytes of the output written so far.
+    num_output_bytes: usize,
+    /// The last bytes before the first difference, which are the same on the two sides.
+    before: VecDeque<u8>,
+    mismatch: Option<StreamMismatch>,
+}
+
+/// The first difference found by an [`ExpectStream`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StreamMismatch {
+    /// Offset of the first differing byte (or the length of the shorter side if it is a prefix of the other).
+    pub offset: usize,
+    /// The bytes of the expected output around the offset.
+    pub expected: Vec<u8>,
+    /// The bytes of the output around the offset.
+    pub output: Vec<u8>,
+    /// Offset of the first byte of [`Self::expected`] and [`Self::output`].
+    pub start: usize,
+    /// Length of the expected output.
+    pub expected_len: usize,
+    /// Length of the output, or of the output before the run was stopped if `is_stopped`.
+    pub output_len: usize,
+    pub is_stopped: bool,
+}
+
+impl ExpectStream {
+    /// Compares the output with `file` decoded as `encoding`.
+    pub fn open(
+        file: &str,
+        encoding: ExpectEncoding,
+        on_mismatch: OnMismatch,
+    ) -> Result<Self, String> {
+        let f = File::open(file).map_err(|e| format!("{}: {}", file, e))?;
+        Ok(Self::new(Box::new(f), encoding, on_mismatch))
+    }
+
+    /// Compares the output with the bytes read from `reader` decoded as `encoding`.
+    pub fn new(
+        reader: Box<dyn Read + Send>,
+        encoding: ExpectEncoding,
+        on_mismatch: OnMismatch,
+    ) -> Self {
+        Self {
+            reader: BufReader::new(reader),
+            encoding,
+            on_mismatch,
+            pending: None,
+            has_started: false,
+            num_output_bytes: 0,
+            before: VecDeque::with_capacity(CONTEXT),
+            mismatch: None,
+        }
+    }
+
+    /// Reads the next byte of the expected output decoded, or `None` at its end.
+    fn next_expected(&mut self) -> io::Result<Option<u8>> {
+        if let Some(b) = self.pending.take() {
+            return Ok(Some(b));
+        }
+        if !self.has_started {
+            self.has_started = true;
+            if (self.encoding == ExpectEncoding::Utf8) && self.reader.fill_buf()?.starts_with(BOM) {
+                self.reader.consume(BOM.len());
+            }
+        }
+        let Some(&b) = self.reader.fill_buf()?.first() else {
+            return Ok(None);
+        };
+        self.reader.consume(1);
+        if (self.encoding == ExpectEncoding::Latin1) && (b >= 0x80) {
+            let mut buf = [0; 2];
+            char::from(b).encode_utf8(&mut buf);
+            self.pending = Some(buf[1]);
+            return Ok(Some(buf[0]));
+        }
+        Ok(Some(b))
+    }
+
+    /// Compares `bytes`, the next bytes of the output.
+    pub fn write(&mut self, bytes: &[u8]) -> io::Result<()> {
+        for &b in bytes {
+            self.num_output_bytes += 1;
+            if let Some(mismatch) = self.mismatch.as_mut() {
+                if mismatch.output.len() < mismatch.offset - mismatch.start + CONTEXT {
+                    mismatch.output.push(b);
+                }
+                continue;
+            }
+            match self.next_expected()? {
+                Some(e) if e == b => {
+                    if self.before.len() == CONTEXT {
+                        self.before.pop_front();
+                    }
+                    self.before.push_back(b);
+                }
+                e => self.differ(e.into_iter().collect(), vec![b])?,
+            }
+        }
+        Ok(())
+    }
+
+    /// Records the first difference, where `expected` and `output` are the bytes of the two sides at the offset (an empty side has ended).
+    fn differ(&mut self, mut expected: Vec<u8>, mut output: Vec<u8>) -> io::Result<()> {
+        let offset = self.num_output_bytes - output.len();
+        let before = self.before.drain(..).collect::<Vec<_>>();
+        if !expected.is_empty() {
+            while expected.len() < CONTEXT {
+                match self.next_expected()? {
+                    Some(b) => expected.push(b),
+                    None => break,
+                }
+            }
+        }
+        expected.splice(0..0, before.iter().copied());
+        output.splice(0..0, before.iter().copied());
+        self.mismatch = Some(StreamMismatch {
+            offset,
+            start: offset - before.len(),
+            expected,
+            output,
+            expected_len: 0,
+            output_len: 0,
+            is_stopped: false,
+        });
+        Ok(())
+    }
+
+    /// Returns `true` if the program should be stopped, i.e. the output has differed and [`OnMismatch::Stop`] is given.
+    pub fn should_stop(&self) -> bool {
+        self.mismatch.is_some() && (self.on_mismatch == OnMismatch::Stop)
+    }
+
+    /// Finishes the comparison at the end of the output, reading the rest of the expected output to tell its length.
+    pub fn finish(mut self) -> io::Result<Result<(), StreamMismatch>> {
+        let is_stopped = self.should_stop();
+        if self.mismatch.is_none() {
+            match self.next_expected()? {
+                None => return Ok(Ok(())),
+                Some(e) => self.differ(vec![e], vec![])?,
+            }
+        }
+        let mut mismatch = self.mismatch.take().unwrap();
+        let mut expected_len = mismatch.start + mismatch.expected.len();
+        //each decoded byte is counted, while only those around the offset were kept
+        while self.next_expected()?.is_some() {
+            expected_len += 1;
+        }
+        mismatch.expected_len = expected_len;
+        mismatch.output_len = self.num_output_bytes;
+        mismatch.is_stopped = is_stopped;
+        Ok(Err(mismatch))
+    }
+}
+
+/// Shows the first difference as [`Mismatch`] does.
+impl Display for StreamMismatch {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        writeln!(
+            f,
+            "first difference at byte {} (expected {} bytes, got {}{})",
+            self.offset,
+            self.expected_len,
+            self.output_len,
+            if self.is_stopped {
+                " before the run was stopped"
+            } else {
+                ""
+            }
+        )?;
+        writeln!(f, "- {}", window_excerpt(&self.expected, self.start))?;
+        writeln!(f, "+ {}", window_excerpt(&self.output, self.start))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decode() {
+        assert_eq!(
+            b"Hi".to_vec(),
+            decode(b"\xEF\xBB\xBFHi", ExpectEncoding::Utf8)
+        );
+        assert_eq!(
+            b"\xEF\xBB\xBFHi".to_vec(),
+            decode(b"\xEF\xBB\xBFHi", ExpectEncoding::Bytes)
+        );
+        assert_eq!(
+            "caf\u{e9}".as_bytes().to_vec(),
+            decode(b"caf\xE9", ExpectEncoding::Latin1)
+        );
+        assert_eq!(b"a\nb\r".to_vec(), normalize(b"a\r\nb\r"));
+    }
+
+    #[test]
+    fn test_compare() {
+        let output = "caf\u{e9}".as_bytes();
+        assert_eq!(
+            Ok(()),
+            compare(b"\xEF\xBB\xBFcaf\xC3\xA9", output, ExpectEncoding::Utf8)
+        );
+        assert_eq!(Ok(()), compare(b"caf\xE9", output, ExpectEncoding::Latin1));
+
+        let mismatch = compare(b"caf\xE9", output, ExpectEncoding::Utf8).unwrap_err();
+        assert_eq!(3, mismatch.offset);
+        assert_eq!(
+            Some(
+                "the two sides differ only by encoding: they match with `--expect-encoding latin1`"
+            ),
+            mismatch.hint.as_deref()
+        );
+        //the expected file is not UTF-8, so it is shown as hex
+        assert_eq!(
+            "first difference at byte 3 (expected 4 bytes, got 5)\n- hex 63 61 66 e9 (bytes 0..4, not UTF-8)\n+ \"café\" (bytes 0..5)\nhint: the two sides differ only by encoding: they match with `--expect-encoding latin1`\n",
+            mismatch.to_string()
+        );
+
+        let mismatch = compare(b"caf\xC3\x83\xC2\xA9", output, ExpectEncoding::Utf8).unwrap_err();
+        assert!(mismatch
+            .hint
+            .unwrap()
+            .contains("read as Latin-1 and saved as UTF-8"));
+
+        let mismatch = compare(b"a\r\nb\r\n", b"a\nb\n", ExpectEncoding::Utf8).unwrap_err();
+        assert!(mismatch
+            .hint
+            .unwrap()
+            .starts_with("the two sides differ only by normalization"));
+
+        let mismatch = compare(
+            b"caf\xE9\r\n",
+            "caf\u{e9}\n".as_bytes(),
+            ExpectEncoding::Utf8,
+        )
+        .unwrap_err();
+        assert!(mismatch
+            .hint
+            .unwrap()
+            .contains("`--expect-encoding latin1` and CRLF is replaced with LF"));
+
+        let mismatch = compare(b"cafe", output, ExpectEncoding::Latin1).unwrap_err();
+        assert_eq!(None, mismatch.hint);
+    }
+
+    #[test]
+    fn test_excerpt() {
+        let s = "0123456789abcdef\u{e9}0123456789abcdef\u{e9}";
+        //widened to the char boundaries
+        assert_eq!(
+            format!("{:?} (bytes 16..36)", &s[16..]),
+            excerpt(s.as_bytes(), 33)
+        );
+        assert_eq!(
+            format!("{:?} (bytes 0..18)", &s[..18]),
+            excerpt(s.as_bytes(), 1)
+        );
+        assert_eq!("\"\" (bytes 0..0)", excerpt(b"", 0));
+        assert_eq!("hex ff 00 (bytes 0..2, not UTF-8)", excerpt(b"\xFF\x00", 1));
+    }
+
+    fn stream(
+        expected_file: &[u8],
+        chunks: &[&[u8]],
+        encoding: ExpectEncoding,
+        on_mismatch: OnMismatch,
+    ) -> Result<(), StreamMismatch> {
+        let reader = Box::new(io::Cursor::new(expected_file.to_vec()));
+        let mut stream = ExpectStream::new(reader, encoding, on_mismatch);
+        for chunk in chunks {
+            if stream.should_stop() {
+                break;
+            }
+            stream.write(chunk).unwrap();
+        }
+        stream.finish().unwrap()
+    }
+
+    #[test]
+    fn test_stream() {
+        let output = "caf\u{e9}".as_bytes();
+        for (expected_file, encoding) in [
+            (&b"\xEF\xBB\xBFcaf\xC3\xA9"[..], ExpectEncoding::Utf8),
+            (b"caf\xE9", ExpectEncoding::Latin1),
+            (b"caf\xC3\xA9", ExpectEncoding::Bytes),
+        ] {
+            assert_eq!(
+                Ok(()),
+                stream(
+                    expected_file,
+                    &[&output[..2], &output[2..]],
+                    encoding,
+                    OnMismatch::Stop
+                )
+            );
+        }
+
+        //the same difference as by `compare()` for each split of the output
+        for (expected_file, output) in [
+            (&b"caf\xE9"[..], output),
+            (b"abc", b"abcd"),
+            (b"abcd", b"abc"),
+            (b"", b"a"),
+            (b"a\r\nb\r\n", b"a\nb\n"),
+        ] {
+            let mismatch = compare(expected_file, output, ExpectEncoding::Utf8).unwrap_err();
+            for k in 0..=output.len() {
+                let m = stream(
+                    expected_file,
+                    &[&output[..k], &output[k..]],
+                    ExpectEncoding::Utf8,
+                    OnMismatch::Complete,
+                )
+                .unwrap_err();
+                assert_eq!(mismatch.offset, m.offset);
+                assert_eq!(
+                    (mismatch.expected.len(), mismatch.output.len(), false),
+                    (m.expected_len, m.output_len, m.is_stopped)
+                );
+            }
+        }
+
+        //stopped at the first difference, where the rest of the chunk is kept
+        let m = stream(
+            b"0123456789",
+            &[b"012", b"3x5", b"6789"],
+            ExpectEncoding::Bytes,
+            OnMismatch::Stop,
+        )
+        .unwrap_err();
+        assert_eq!(
+            "first difference at byte 4 (expected 10 bytes, got 6 before the run was stopped)\n- \"0123456789\" (bytes 0..10)\n+ \"0123x5\" (bytes 0..6)\n",
+            m.to_string()
+        );
+        let m = stream(
+            b"0123456789",
+            &[b"012", b"3x5", b"6789"],
+            ExpectEncoding::Bytes,
+            OnMismatch::Complete,
+        )
+        .unwrap_err();
+        assert_eq!((4, 10, false), (m.offset, m.output_len, m.is_stopped));
+        assert_eq!(b"0123x56789".to_vec(), m.output);
+    }
+
+    #[test]
+    fn test_stream_large() {
+        //a few megabytes with a difference near the end, of which only the bytes around it are kept
+        let line = "bottles of beer on the wall\n".as_bytes();
+        let expected = line.repeat(100_000);
+        let mut output = expected.clone();
+        let offset = expected.len() - 100;
+        output[offset] = b'B';
+        let m = stream(
+            &expected,
+            &output.chunks(line.len()).collect::<Vec<_>>(),
+            ExpectEncoding::Utf8,
+            OnMismatch::Complete,
+        )
+        .unwrap_err();
+        assert_eq!(offset, m.offset);
+        assert_eq!(
+            (expected.len(), output.len()),
+            (m.expected_len, m.output_len)
+        );
+        assert_eq!(offset - CONTEXT, m.start);
+        assert_eq!(2 * CONTEXT, m.expected.len());
+        assert_eq!(2 * CONTEXT, m.output.len());
+    }
+
+    #[test]
+    fn test_window_excerpt() {
+        let s = "\u{e9}0123\u{e9}";
+        //the characters cut at the ends are dropped
+        assert_eq!(
+            "\"0123\" (bytes 2..6)",
+            window_excerpt(&s.as_bytes()[1..7], 1)
+        );
+        assert_eq!(
+            format!("{:?} (bytes 0..8)", s),
+            window_excerpt(s.as_bytes(), 0)
+        );
+        assert_eq!(
+            "hex ff 30 (bytes 5..7, not UTF-8)",
+            window_excerpt(b"\xFF0", 5)
+        );
+    }
+}
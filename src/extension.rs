@@ -0,0 +1,132 @@
+/*!
+Experimental dialect extensions of Piet (`--extension`), all off by default.
+
+An extension only gives a meaning to an operation which the spec says is ignored, so that programs valid in standard Piet behave the same with or without it.
+Each extension is a flag of [`ExecConfig`], and the core paths only call into this module where the spec would ignore the command.
+
+# `second-stack`
+
+A second stack, initially empty, which can be accessed only by `Roll` with a negative depth (ignored by the spec).
+`Roll` pops the number of rolls `r` and the depth `-n` as usual, and then
+
+- if `r > 0`, moves the top `n` values of the main stack onto the second stack,
+- if `r < 0`, moves the top `n` values of the second stack back onto the main stack,
+- if `r == 0`, does nothing.
+
+The moved values keep their order (the top stays the top).
+If the source stack has fewer than `n` values, the command is ignored as a whole (nothing is popped) as with the other commands which cannot be performed.
+*/
+
+use super::corner::SharedCornerStrategy;
+use super::interpreter::Interpreter;
+use super::stdin::NumberOverflow;
+
+/// Experimental extensions.
+#[derive(clap::ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Extension {
+    /// Second stack accessed by `Roll` with a negative depth
+    SecondStack,
+}
+
+/// Options which change the semantics of execution.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ExecConfig {
+    /// Enables [`Extension::SecondStack`].
+    pub second_stack: bool,
+    /// Chooses the codel from which a block is left (see [`corner`](super::corner)).
+    pub corner_strategy: SharedCornerStrategy,
+    /// What `in(number)` does with an integer out of the range of `isize`.
+    pub number_overflow: NumberOverflow,
+}
+
+impl ExecConfig {
+    /// Returns the configuration with `extensions`, the standard corner strategy and the spec's handling of an overflowing `in(number)`.
+    pub fn new(extensions: &[Extension]) -> Self {
+        Self {
+            second_stack: extensions.contains(&Extension::SecondStack),
+            corner_strategy: SharedCornerStrategy::default(),
+            number_overflow: NumberOverflow::default(),
+        }
+    }
+}
+
+/// `Roll` with a negative depth in [`Extension::SecondStack`].
+/// `depth` and `num_roll` are still on the top of the main stack when this is called.
+pub fn roll_between_stacks(ip: &mut Interpreter, depth: isize, num_roll: isize) {
+    let n = depth.unsigned_abs();
+    let num_available = match num_roll {
+        0 => n,
+        1.. => ip.stack.len() - 2,
+        _ => ip.second_stack.len(),
+    };
+    if num_available < n {
+        return;
+    }
+    ip.stack.truncate(ip.stack.len() - 2);
+
+    let (from, to) = match num_roll {
+        0 => return,
+        1.. => (&mut ip.stack, &mut ip.second_stack),
+        _ => (&mut ip.second_stack, &mut ip.stack),
+    };
+    to.extend(from.drain(from.len() - n..));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::command::Command;
+    use super::*;
+
+    fn roll(stack: Vec<isize>, second_stack: Vec<isize>) -> (Vec<isize>, Vec<isize>) {
+        let mut ip = Interpreter::new();
+        ip.config.second_stack = true;
+        ip.stack = stack;
+        ip.second_stack = second_stack;
+        Command::Roll.execute(&mut ip, 1).unwrap();
+        (ip.stack, ip.second_stack)
+    }
+
+    #[test]
+    fn test_roll_between_stacks() {
+        //out
+        assert_eq!(
+            (vec![1], vec![9, 2, 3]),
+            roll(vec![1, 2, 3, -2, 1], vec![9])
+        );
+        //the number of rolls only selects the direction
+        assert_eq!((vec![1], vec![2, 3]), roll(vec![1, 2, 3, -2, 7], vec![]));
+        //in
+        assert_eq!(
+            (vec![1, 8, 9], vec![7]),
+            roll(vec![1, -2, -1], vec![7, 8, 9])
+        );
+        //nothing moves but the arguments are popped
+        assert_eq!((vec![1], vec![7]), roll(vec![1, -3, 0], vec![7]));
+
+        //insufficient values
+        assert_eq!((vec![1, 2, -3, 1], vec![]), roll(vec![1, 2, -3, 1], vec![]));
+        assert_eq!((vec![1, -2, -1], vec![7]), roll(vec![1, -2, -1], vec![7]));
+        assert_eq!(
+            (vec![1, isize::MIN, 1], vec![]),
+            roll(vec![1, isize::MIN, 1], vec![])
+        );
+
+        //non-negative depths are not affected
+        assert_eq!((vec![2, 1], vec![5]), roll(vec![1, 2, 2, 1], vec![5]));
+    }
+
+    #[test]
+    fn test_disabled() {
+        let mut ip = Interpreter::new();
+        ip.stack = vec![1, 2, 3, -2, 1];
+        Command::Roll.execute(&mut ip, 1).unwrap();
+        assert_eq!(vec![1, 2, 3, -2, 1], ip.stack);
+        assert!(ip.second_stack.is_empty());
+    }
+
+    #[test]
+    fn test_exec_config() {
+        assert_eq!(ExecConfig::default(), ExecConfig::new(&[]));
+        assert!(ExecConfig::new(&[Extension::SecondStack]).second_stack);
+    }
+}
@@ -0,0 +1,522 @@
+/*!
+Images which contain several images: the frames of a GIF, the pages of a TIFF and the sizes of an ICO (`--frame`).
+
+The generic decoder of the `image` crate reads one of them without telling (the first frame or page, or the largest icon),
+so such a file is listed first (see [`list()`]) and the image to run shall be chosen with `--frame` if there are several of them.
+The listing reads only the headers of a TIFF or an ICO, but decodes every frame of a GIF, as a GIF has no index of its frames.
+*/
+
+use std::error::Error;
+use std::fmt::{self, Display, Formatter};
+use std::io::Cursor;
+
+use image::codecs::gif::GifDecoder;
+use image::codecs::ico::IcoDecoder;
+use image::codecs::tiff::TiffDecoder;
+use image::{AnimationDecoder, DynamicImage, ImageDecoder, ImageFormat};
+
+use super::limits::ImageLimits;
+
+/// Format which can contain several images.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Container {
+    Gif,
+    Tiff,
+    Ico,
+}
+
+impl Container {
+    pub fn of(format: ImageFormat) -> Option<Self> {
+        match format {
+            ImageFormat::Gif => Some(Container::Gif),
+            ImageFormat::Tiff => Some(Container::Tiff),
+            ImageFormat::Ico => Some(Container::Ico),
+            _ => None,
+        }
+    }
+
+    /// Returns what one of the images is called in the format.
+    pub fn unit(self) -> &'static str {
+        match self {
+            Container::Gif => "frame",
+            Container::Tiff => "page",
+            Container::Ico => "image",
+        }
+    }
+}
+
+/// Size of one of the images of a container in pixels.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Frame {
+    pub height: u32,
+    pub width: u32,
+}
+
+impl Display for Frame {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "{} x {} pixels", self.height, self.width)
+    }
+}
+
+/// Lists the images of `frames` one per line with their indices (as given to `--frame`), for an error message.
+pub fn describe(frames: &[Frame]) -> String {
+    frames
+        .iter()
+        .enumerate()
+        .map(|(i, frame)| format!("\n  {}: {}", i, frame))
+        .collect()
+}
+
+/// Lists the images of the file `bytes`, which is of `container`.
+pub fn list(
+    bytes: &[u8],
+    container: Container,
+    limits: &ImageLimits,
+) -> Result<Vec<Frame>, Box<dyn Error>> {
+    match container {
+        Container::Gif => {
+            let decoder = gif_decoder(bytes, limits)?;
+            let (width, height) = decoder.dimensions();
+            //Each frame is composed onto the whole screen.
+            let num_frames = decoder.into_frames().try_fold(0, |n, frame| {
+                frame?;
+                Ok::<_, image::ImageError>(n + 1)
+            })?;
+            Ok(vec![Frame { height, width }; num_frames])
+        }
+        Container::Tiff => Ok(tiff_pages(bytes)?
+            .into_iter()
+            .map(|(_, frame)| frame)
+            .collect()),
+        Container::Ico => Ok(ico_entries(bytes)?
+            .into_iter()
+            .map(|(_, frame)| frame)
+            .collect()),
+    }
+}
+
+/// Decodes the image of `index` (from zero) in the file `bytes`, which is of `container` and has more than `index` images (see [`list()`]).
+pub fn decode(
+    bytes: &[u8],
+    container: Container,
+    index: usize,
+    limits: &ImageLimits,
+) -> Result<DynamicImage, Box<dyn Error>> {
+    match container {
+        Container::Gif => {
+            let frame = gif_decoder(bytes, limits)?
+                .into_frames()
+                .nth(index)
+                .ok_or("the frame is not found")??;
+            Ok(DynamicImage::ImageRgba8(frame.into_buffer()))
+        }
+        Container::Tiff => {
+            //The header is pointed at the page so that it is read as the first one.
+            let (offset, _) = *tiff_pages(bytes)?
+                .get(index)
+                .ok_or("the page is not found")?;
+            let tiff = Tiff::new(bytes)?;
+            let (at, len) = (tiff.first_directory_at(), tiff.offset_len());
+            let mut b = offset.to_le_bytes()[..len].to_vec();
+            if !tiff.is_little_endian {
+                b.reverse();
+            }
+            let mut bytes = bytes.to_vec();
+            bytes[at..at + len].copy_from_slice(&b);
+            decode_with(TiffDecoder::new(Cursor::new(bytes))?, limits)
+        }
+        Container::Ico => {
+            //An icon of the entry alone is made, as the decoder reads only the largest entry.
+            let (entry, _) = *ico_entries(bytes)?
+                .get(index)
+                .ok_or("the image is not found")?;
+            let size = read_u32_le(bytes, entry + 8)? as usize;
+            let offset = read_u32_le(bytes, entry + 12)? as usize;
+            let data = bytes
+                .get(offset..offset.saturating_add(size))
+                .ok_or("an image of the ICO file is truncated")?;
+            let mut icon = vec![0, 0, 1, 0, 1, 0];
+            icon.extend(&bytes[entry..entry + 12]);
+            icon.extend(22u32.to_le_bytes());
+            icon.extend(data);
+            decode_with(IcoDecoder::new(Cursor::new(icon))?, limits)
+        }
+    }
+}
+
+fn gif_decoder<'a>(
+    bytes: &'a [u8],
+    limits: &ImageLimits,
+) -> Result<GifDecoder<Cursor<&'a [u8]>>, Box<dyn Error>> {
+    let mut decoder = GifDecoder::new(Cursor::new(bytes))?;
+    let (width, height) = decoder.dimensions();
+    limits.check(height.into(), width.into(), Some(decoder.total_bytes()))?;
+    decoder.set_limits(limits.to_decoder_limits())?;
+    Ok(decoder)
+}
+
+fn decode_with(
+    mut decoder: impl ImageDecoder,
+    limits: &ImageLimits,
+) -> Result<DynamicImage, Box<dyn Error>> {
+    let (width, height) = decoder.dimensions();
+    limits.check(height.into(), width.into(), Some(decoder.total_bytes()))?;
+    decoder.set_limits(limits.to_decoder_limits())?;
+    Ok(DynamicImage::from_decoder(decoder)?)
+}
+
+fn read_u32_le(bytes: &[u8], at: usize) -> Result<u32, Box<dyn Error>> {
+    let b = bytes.get(at..at + 4).ok_or("the file is truncated")?;
+    Ok(u32::from_le_bytes(b.try_into().unwrap()))
+}
+
+/// Returns the offset of the directory entry and the size of each image of an ICO file.
+fn ico_entries(bytes: &[u8]) -> Result<Vec<(usize, Frame)>, Box<dyn Error>> {
+    if bytes.len() < 6 {
+        return Err("the ICO file is truncated".into());
+    }
+    let num_entries = usize::from(u16::from_le_bytes([bytes[4], bytes[5]]));
+    (0..num_entries)
+        .map(|i| {
+            let entry = 6 + 16 * i;
+            let b = bytes
+                .get(entry..entry + 16)
+                .ok_or("the directory of the ICO file is truncated")?;
+            //zero means 256
+            let size = |x: u8| if x == 0 { 256 } else { u32::from(x) };
+            Ok((
+                entry,
+                Frame {
+                    height: size(b[1]),
+                    width: size(b[0]),
+                },
+            ))
+        })
+        .collect()
+}
+
+/// Header of a TIFF file.
+struct Tiff<'a> {
+    bytes: &'a [u8],
+    is_little_endian: bool,
+    /// BigTIFF, whose offsets are of 64 bits.
+    is_big: bool,
+}
+
+impl<'a> Tiff<'a> {
+    fn new(bytes: &'a [u8]) -> Result<Self, Box<dyn Error>> {
+        let is_little_endian = match bytes.get(0..2) {
+            Some(b"II") => true,
+            Some(b"MM") => false,
+            _ => return Err("the TIFF file has no valid header".into()),
+        };
+        let mut tiff = Self {
+            bytes,
+            is_little_endian,
+            is_big: false,
+        };
+        tiff.is_big = match tiff.uint(2, 2)? {
+            42 => false,
+            43 => true,
+            _ => return Err("the TIFF file has no valid header".into()),
+        };
+        Ok(tiff)
+    }
+
+    /// Reads an unsigned integer of `len` bytes at `at`.
+    fn uint(&self, at: usize, len: usize) -> Result<u64, Box<dyn Error>> {
+        let b = self
+            .bytes
+            .get(at..at.saturating_add(len))
+            .ok_or("the TIFF file is truncated")?;
+        let fold = |x: u64, byte: &u8| (x << 8) | u64::from(*byte);
+        Ok(match self.is_little_endian {
+            true => b.iter().rev().fold(0, fold),
+            false => b.iter().fold(0, fold),
+        })
+    }
+
+    fn offset_len(&self) -> usize {
+        if self.is_big {
+            8
+        } else {
+            4
+        }
+    }
+
+    /// Returns where the offset of the first directory is in the header.
+    fn first_directory_at(&self) -> usize {
+        self.offset_len()
+    }
+}
+
+/// Returns the offset of the directory and the size of each page of a TIFF file.
+fn tiff_pages(bytes: &[u8]) -> Result<Vec<(u64, Frame)>, Box<dyn Error>> {
+    let tiff = Tiff::new(bytes)?;
+    let (count_len, entry_len) = if tiff.is_big { (8, 20) } else { (2, 12) };
+    let mut offset = tiff.uint(tiff.first_directory_at(), tiff.offset_len())?;
+    let mut pages = vec![];
+    while offset != 0 {
+        //A directory appears at most once, or the chain would be endless.
+        if pages.iter().any(|(o, _)| *o == offset) {
+            return Err("the directories of the TIFF file form a cycle".into());
+        }
+        let start = usize::try_from(offset)?;
+        let num_entries = usize::try_from(tiff.uint(start, count_len)?)?;
+        let (mut height, mut width) = (None, None);
+        for i in 0..num_entries {
+            let entry = start + count_len + entry_len * i;
+            let tag = tiff.uint(entry, 2)?;
+            //SHORT or LONG (or LONG8), stored in the entry itself
+            let value = match tiff.uint(entry + 2, 2)? {
+                3 => tiff.uint(entry + entry_len - tiff.offset_len(), 2)?,
+                4 => tiff.uint(entry + entry_len - tiff.offset_len(), 4)?,
+                16 => tiff.uint(entry + entry_len - tiff.offset_len(), 8)?,
+                _ => continue,
+            };
+            match tag {
+                256 => width = Some(value),
+                257 => height = Some(value),
+                _ => (),
+            }
+        }
+        let (Some(height), Some(width)) = (height, width) else {
+            return Err("a page of the TIFF file has no size".into());
+        };
+        pages.push((
+            offset,
+            Frame {
+                height: u32::try_from(height)?,
+                width: u32::try_from(width)?,
+            },
+        ));
+        offset = tiff.uint(
+            start + count_len + entry_len * num_entries,
+            tiff.offset_len(),
+        )?;
+    }
+    Ok(pages)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::{self, File};
+    use std::io::Write;
+    use std::path::Path;
+
+    use image::codecs::gif::GifEncoder;
+    use image::{ImageEncoder, Rgba, RgbaImage};
+
+    use super::super::codel::Codel;
+    use super::super::error::PietError;
+    use super::super::image::{CodelLayout, ColorMatching, Image};
+    use super::*;
+
+    const TWO_PAGES: &str = "./tests/test_images/original___two_pages.tiff";
+
+    fn load(file: &Path, frame: Option<usize>) -> Result<Image, Box<dyn Error>> {
+        Image::new_observed(
+            file,
+            CodelLayout::Size(1),
+            ColorMatching::default(),
+            false,
+            &ImageLimits::default(),
+            None,
+            frame,
+            &mut vec![],
+            None,
+        )
+    }
+
+    /// Returns an image of `height x width` pixels of `codel`.
+    fn filled(height: u32, width: u32, codel: Codel) -> RgbaImage {
+        let (r, g, b) = codel.rgb();
+        RgbaImage::from_pixel(width, height, Rgba([r, g, b, 255]))
+    }
+
+    #[test]
+    fn test_tiff() {
+        let file = Path::new(TWO_PAGES);
+        let frames = list(
+            &fs::read(file).unwrap(),
+            Container::Tiff,
+            &ImageLimits::default(),
+        )
+        .unwrap();
+        assert_eq!(
+            vec![
+                Frame {
+                    height: 11,
+                    width: 21
+                },
+                Frame {
+                    height: 13,
+                    width: 21
+                },
+            ],
+            frames
+        );
+        assert_eq!(
+            "\n  0: 11 x 21 pixels\n  1: 13 x 21 pixels",
+            describe(&frames)
+        );
+
+        assert!(matches!(
+            load(file, None).err().unwrap().downcast_ref(),
+            Some(PietError::MultipleFrames {
+                container: Container::Tiff,
+                frames: f,
+            }) if f == &frames
+        ));
+        let first = load(file, Some(0)).unwrap();
+        let second = load(file, Some(1)).unwrap();
+        assert_eq!((11, 21), (first.height(), first.width()));
+        assert_eq!((13, 21), (second.height(), second.width()));
+        assert_eq!(
+            Image::from_codel_grid(
+                crate::pietdev::parse(
+                    &fs::read_to_string("./tests/test_images/original___e_acute.pietdev").unwrap()
+                )
+                .unwrap()
+            )
+            .to_parts(),
+            second.to_parts()
+        );
+        assert_eq!(
+            Some(&PietError::FrameOutOfRange {
+                frame: 2,
+                container: Some(Container::Tiff),
+                frames,
+            }),
+            load(file, Some(2)).err().unwrap().downcast_ref()
+        );
+        assert_eq!((13, 21), Image::frame_dimensions(file, 1).unwrap());
+    }
+
+    #[test]
+    fn test_ico() {
+        //two sizes, as PNG
+        let png = |img: &RgbaImage| {
+            let mut v = vec![];
+            image::codecs::png::PngEncoder::new(&mut v)
+                .write_image(
+                    img.as_raw(),
+                    img.width(),
+                    img.height(),
+                    image::ExtendedColorType::Rgba8,
+                )
+                .unwrap();
+            v
+        };
+        let images = [
+            png(&filled(2, 3, Codel::Red)),
+            png(&filled(4, 4, Codel::Blue)),
+        ];
+        let mut ico = vec![0, 0, 1, 0, 2, 0];
+        let mut offset = 6 + 16 * 2;
+        for (data, (height, width)) in images.iter().zip([(2u8, 3u8), (4, 4)]) {
+            ico.extend([width, height, 0, 0, 1, 0, 32, 0]);
+            ico.extend((data.len() as u32).to_le_bytes());
+            ico.extend((offset as u32).to_le_bytes());
+            offset += data.len();
+        }
+        for data in &images {
+            ico.extend(data);
+        }
+        let file = std::env::temp_dir().join(format!("piet_test_ico_{}.ico", std::process::id()));
+        fs::write(&file, &ico).unwrap();
+
+        let frames = list(&ico, Container::Ico, &ImageLimits::default()).unwrap();
+        assert_eq!(
+            vec![
+                Frame {
+                    height: 2,
+                    width: 3
+                },
+                Frame {
+                    height: 4,
+                    width: 4
+                },
+            ],
+            frames
+        );
+        assert!(matches!(
+            load(&file, None).err().unwrap().downcast_ref(),
+            Some(PietError::MultipleFrames {
+                container: Container::Ico,
+                ..
+            })
+        ));
+        //not the largest one
+        let img = load(&file, Some(0)).unwrap();
+        assert_eq!((2, 3), (img.height(), img.width()));
+        assert_eq!(&Codel::Red, img.get_codel_at_unchecked((1, 2)));
+        let img = load(&file, Some(1)).unwrap();
+        assert_eq!(&Codel::Blue, img.get_codel_at_unchecked((3, 3)));
+        fs::remove_file(&file).unwrap();
+    }
+
+    #[test]
+    fn test_gif() {
+        let file = std::env::temp_dir().join(format!("piet_test_gif_{}.gif", std::process::id()));
+        {
+            let mut f = File::create(&file).unwrap();
+            let mut encoder = GifEncoder::new(&mut f);
+            for codel in [Codel::Red, Codel::Green, Codel::Blue] {
+                encoder
+                    .encode_frame(image::Frame::new(filled(2, 2, codel)))
+                    .unwrap();
+            }
+            drop(encoder);
+            f.flush().unwrap();
+        }
+        let frames = list(
+            &fs::read(&file).unwrap(),
+            Container::Gif,
+            &ImageLimits::default(),
+        )
+        .unwrap();
+        assert_eq!(
+            vec![
+                Frame {
+                    height: 2,
+                    width: 2
+                };
+                3
+            ],
+            frames
+        );
+        assert!(matches!(
+            load(&file, None).err().unwrap().downcast_ref(),
+            Some(PietError::MultipleFrames {
+                container: Container::Gif,
+                ..
+            })
+        ));
+        let img = load(&file, Some(2)).unwrap();
+        assert_eq!(&Codel::Blue, img.get_codel_at_unchecked((0, 0)));
+        assert!(matches!(
+            load(&file, Some(3)).err().unwrap().downcast_ref(),
+            Some(PietError::FrameOutOfRange { frame: 3, .. })
+        ));
+        fs::remove_file(&file).unwrap();
+    }
+
+    #[test]
+    fn test_single_image() {
+        //the only image is `0`
+        let file = Path::new("./tests/test_images/original___hi_4x5_codels.png");
+        assert!(load(file, Some(0)).is_ok());
+        assert_eq!(
+            Some(&PietError::FrameOutOfRange {
+                frame: 1,
+                container: None,
+                frames: vec![Frame {
+                    height: 55,
+                    width: 84
+                }],
+            }),
+            load(file, Some(1)).err().unwrap().downcast_ref()
+        );
+    }
+}
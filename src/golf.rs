@@ -0,0 +1,254 @@
+use std::fmt::{self, Display};
+
+use clap::ValueEnum;
+use itertools::Itertools;
+
+use super::cc::CC;
+use super::dp::DP;
+use super::image::Image;
+
+/// Formula of the composite golf score (lower is better).
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum GolfRules {
+    /// Total number of codels
+    #[default]
+    Codels,
+    /// Area of the bounding box of the non-white codels
+    Area,
+    /// Number of colored blocks
+    Blocks,
+}
+
+/// Size metrics of a Piet program, as used for golf scoring (`--golf-score`).
+#[derive(Debug, PartialEq, Eq)]
+pub struct GolfScore {
+    pub total_codels: usize,
+    /// Number of codels which are neither white nor black.
+    pub colored_codels: usize,
+    /// Number of blocks which are neither white nor black.
+    pub blocks: usize,
+    /// Number of distinct colors appearing in the image (including white and black).
+    pub distinct_colors: usize,
+    /// `(top, left, bottom, right)` of the non-white codels (inclusive), or `None` if the image is entirely white.
+    pub bounding_box: Option<(usize, usize, usize, usize)>,
+    /// First codel and size of the block excluded by [`Self::excluding()`].
+    pub excluded_block: Option<((usize, usize), usize)>,
+    pub rules: GolfRules,
+    pub score: usize,
+}
+
+impl GolfScore {
+    pub fn new(img: &Image, rules: GolfRules) -> Self {
+        Self::excluding(img, rules, None)
+    }
+
+    /// Same as [`Self::new()`] but skips the codels of the block `excluded` (e.g. the background, see `--ignore-background`) in every metric except `total_codels`.
+    pub fn excluding(img: &Image, rules: GolfRules, excluded: Option<usize>) -> Self {
+        let positions = (0..img.height())
+            .cartesian_product(0..img.width())
+            .collect_vec();
+        let codel = |pos| img.get_codel_at(pos).unwrap();
+
+        let total_codels = positions.len();
+        let positions = positions
+            .into_iter()
+            .filter(|pos| Some(img.get_block_id_at_unchecked(*pos)) != excluded)
+            .collect_vec();
+        let excluded_block = excluded.map(|id| {
+            let block = img.get_block(id).unwrap();
+            (block.first_codel(), block.size)
+        });
+        let colored_codels = positions
+            .iter()
+            .filter(|pos| !codel(**pos).is_white() && !codel(**pos).is_black())
+            .count();
+        //counts each block once, at its top-left corner
+        let blocks = positions
+            .iter()
+            .filter(|pos| !codel(**pos).is_white() && !codel(**pos).is_black())
+            .filter(|pos| {
+                img.get_block_at(**pos)
+                    .unwrap()
+                    .get_corner_index(&DP::Up, &CC::Left)
+                    == **pos
+            })
+            .count();
+        let distinct_colors = positions.iter().map(|pos| codel(*pos)).unique().count();
+
+        let non_white = positions
+            .iter()
+            .filter(|pos| !codel(**pos).is_white())
+            .collect_vec();
+        let bounding_box = if non_white.is_empty() {
+            None
+        } else {
+            Some((
+                non_white.iter().map(|(i, _)| *i).min().unwrap(),
+                non_white.iter().map(|(_, j)| *j).min().unwrap(),
+                non_white.iter().map(|(i, _)| *i).max().unwrap(),
+                non_white.iter().map(|(_, j)| *j).max().unwrap(),
+            ))
+        };
+
+        let score = match rules {
+            GolfRules::Codels => total_codels,
+            GolfRules::Area => bounding_box
+                .map(|(top, left, bottom, right)| (bottom - top + 1) * (right - left + 1))
+                .unwrap_or(0),
+            GolfRules::Blocks => blocks,
+        };
+
+        Self {
+            total_codels,
+            colored_codels,
+            blocks,
+            distinct_colors,
+            bounding_box,
+            excluded_block,
+            rules,
+            score,
+        }
+    }
+
+    /// Returns the metrics as a single-line JSON object.
+    pub fn to_json(&self) -> String {
+        let bounding_box = match self.bounding_box {
+            Some((top, left, bottom, right)) => format!(
+                r#"{{"top":{},"left":{},"bottom":{},"right":{}}}"#,
+                top, left, bottom, right
+            ),
+            None => "null".to_string(),
+        };
+        //only with an exclusion, so that the output is unchanged without one
+        let excluded = match self.excluded_block {
+            Some(((i, j), size)) => format!(
+                r#","excluded_block":{{"row":{},"col":{},"size":{}}}"#,
+                i, j, size
+            ),
+            None => String::new(),
+        };
+        format!(
+            r#"{{"total_codels":{},"colored_codels":{},"blocks":{},"distinct_colors":{},"bounding_box":{},"rules":"{}","score":{}{}}}"#,
+            self.total_codels,
+            self.colored_codels,
+            self.blocks,
+            self.distinct_colors,
+            bounding_box,
+            self.rules.to_possible_value().unwrap().get_name(),
+            self.score,
+            excluded
+        )
+    }
+}
+
+impl Display for GolfScore {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "total codels:    {}", self.total_codels)?;
+        writeln!(f, "colored codels:  {}", self.colored_codels)?;
+        writeln!(f, "blocks:          {}", self.blocks)?;
+        writeln!(f, "distinct colors: {}", self.distinct_colors)?;
+        match self.bounding_box {
+            Some((top, left, bottom, right)) => writeln!(
+                f,
+                "bounding box:    ({}, {})-({}, {}) [{} x {}]",
+                top,
+                left,
+                bottom,
+                right,
+                bottom - top + 1,
+                right - left + 1
+            )?,
+            None => writeln!(f, "bounding box:    none")?,
+        }
+        if let Some((pos, size)) = self.excluded_block {
+            writeln!(
+                f,
+                "excluded:        the background block at {:?} of size {}",
+                pos, size
+            )?;
+        }
+        write!(
+            f,
+            "score ({}):{}{}",
+            self.rules.to_possible_value().unwrap().get_name(),
+            " ".repeat(8 - self.rules.to_possible_value().unwrap().get_name().len()),
+            self.score
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::codel::Codel;
+    use super::*;
+
+    #[test]
+    fn test01() {
+        // W W W W
+        // W R R K
+        // W B W W
+        let img = Image::from_codel_grid(vec![
+            vec![Codel::White, Codel::White, Codel::White, Codel::White],
+            vec![Codel::White, Codel::Red, Codel::Red, Codel::Black],
+            vec![Codel::White, Codel::Blue, Codel::White, Codel::White],
+        ]);
+
+        let score = GolfScore::new(&img, GolfRules::Codels);
+        assert_eq!(12, score.total_codels);
+        assert_eq!(3, score.colored_codels);
+        assert_eq!(2, score.blocks);
+        assert_eq!(4, score.distinct_colors);
+        assert_eq!(Some((1, 1, 2, 3)), score.bounding_box);
+        assert_eq!(12, score.score);
+
+        assert_eq!(6, GolfScore::new(&img, GolfRules::Area).score);
+        assert_eq!(2, GolfScore::new(&img, GolfRules::Blocks).score);
+
+        assert_eq!(
+            r#"{"total_codels":12,"colored_codels":3,"blocks":2,"distinct_colors":4,"bounding_box":{"top":1,"left":1,"bottom":2,"right":3},"rules":"codels","score":12}"#,
+            score.to_json()
+        );
+    }
+
+    #[test]
+    fn test02() {
+        let img = Image::from_codel_grid(vec![vec![Codel::White; 3]; 2]);
+        let score = GolfScore::new(&img, GolfRules::Area);
+        assert_eq!(None, score.bounding_box);
+        assert_eq!(0, score.score);
+        assert_eq!(0, score.blocks);
+        assert!(score.to_json().contains(r#""bounding_box":null"#));
+        assert!(score.to_string().contains("bounding box:    none"));
+    }
+
+    #[test]
+    fn test_excluding() {
+        // Y Y Y Y
+        // Y R R K
+        // Y B Y Y
+        let (y, r, b, k) = (Codel::Yellow, Codel::Red, Codel::Blue, Codel::Black);
+        let img =
+            Image::from_codel_grid(vec![vec![y, y, y, y], vec![y, r, r, k], vec![y, b, y, y]]);
+        let score = GolfScore::new(&img, GolfRules::Area);
+        assert_eq!(
+            (11, 4, Some((0, 0, 2, 3))),
+            (score.colored_codels, score.blocks, score.bounding_box)
+        );
+
+        let background = img.get_block_id_at_unchecked((0, 0));
+        let score = GolfScore::excluding(&img, GolfRules::Area, Some(background));
+        assert_eq!(12, score.total_codels);
+        assert_eq!(5, score.colored_codels);
+        assert_eq!(3, score.blocks);
+        assert_eq!(4, score.distinct_colors);
+        assert_eq!(Some((1, 1, 2, 3)), score.bounding_box);
+        assert_eq!(6, score.score);
+        assert_eq!(Some(((0, 0), 6)), score.excluded_block);
+        assert!(score
+            .to_json()
+            .ends_with(r#""score":6,"excluded_block":{"row":0,"col":0,"size":6}}"#));
+        assert!(score
+            .to_string()
+            .contains("\nexcluded:        the background block at (0, 0) of size 6\n"));
+    }
+}
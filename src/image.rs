@@ -1,24 +1,38 @@
 use std::{
     error::Error,
     fmt::{self, Display},
+    fs,
+    io::{BufRead, BufReader, Cursor, Read, Seek},
     path::Path,
-    rc::Rc,
+    str::FromStr,
+    time::{Duration, Instant},
 };
 
-use image::{self, DynamicImage, ImageReader};
+use clap::ValueEnum;
+use image::{self, DynamicImage, ImageDecoder, ImageFormat, ImageReader};
 use itertools::Itertools;
-use rustc_hash::FxHashSet;
+use num::Integer;
 
 use super::block::Block;
 use super::cc::CC;
 use super::codel::Codel;
+use super::coord;
 use super::dp::DP;
+use super::entry::Region;
+use super::error::PietError;
+use super::frames::{self, Container, Frame};
+use super::limits::ImageLimits;
+use super::observer::{LoadObserver, LoadPhase, LoadProgress};
+use super::warning::{self, Warning};
+
+/// Block ID of a codel whose block has not been found yet while the block map is built.
+const UNASSIGNED: usize = usize::MAX;
 
 /*-------------------------------------*/
 
 /* Pixel */
 
-#[derive(PartialEq, Clone, Copy, Debug)]
+#[derive(PartialEq, Eq, Hash, Clone, Copy, Debug)]
 pub struct Pixel {
     pub r: u8,
     pub g: u8,
@@ -31,6 +45,301 @@ impl Pixel {
     }
 }
 
+/* Topology */
+
+/// How the edges of a program behave.
+#[derive(clap::ValueEnum, Clone, Copy, Debug, PartialEq, Eq, Hash, Default)]
+pub enum Topology {
+    /// The edges restrict program flow (as in the spec)
+    #[default]
+    Bounded,
+    /// Moving off an edge re-enters from the opposite edge, so that only black blocks restrict program flow
+    Torus,
+}
+
+/* GridSpec */
+
+/// Layout of the codels in the pixels (`--codel-grid WxH+X+Y`): codels of `cell_width x cell_height` pixels, the top-left one of which starts `offset_x` pixels from the left and `offset_y` pixels from the top.
+/// The pixels before the offsets and those after the last whole codel are dropped.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct GridSpec {
+    pub cell_width: usize,
+    pub cell_height: usize,
+    pub offset_x: usize,
+    pub offset_y: usize,
+}
+
+impl FromStr for GridSpec {
+    type Err = String;
+
+    /// Parses `WxH+X+Y`, or `WxH` without an offset.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let error = || format!("expected `WxH+X+Y` or `WxH` but found `{}`", s);
+        let mut v = s.split('+');
+        let (w, h) = v.next().and_then(|c| c.split_once('x')).ok_or_else(error)?;
+        let offset = v
+            .map(|n| n.parse::<usize>().ok())
+            .collect::<Option<Vec<_>>>()
+            .ok_or_else(error)?;
+        let (offset_x, offset_y) = match offset[..] {
+            [] => (0, 0),
+            [x, y] => (x, y),
+            _ => return Err(error()),
+        };
+        let cell_width = w.parse::<usize>().map_err(|_| error())?;
+        let cell_height = h.parse::<usize>().map_err(|_| error())?;
+        if (cell_width == 0) || (cell_height == 0) {
+            return Err(format!("the codels of `{}` are empty", s));
+        }
+        Ok(GridSpec {
+            cell_width,
+            cell_height,
+            offset_x,
+            offset_y,
+        })
+    }
+}
+
+impl Display for GridSpec {
+    /// Prints the grid in the form accepted by [`GridSpec::from_str()`].
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}x{}+{}+{}",
+            self.cell_width, self.cell_height, self.offset_x, self.offset_y
+        )
+    }
+}
+
+impl GridSpec {
+    /// Returns the grid of square codels of `codel_size` pixels without an offset.
+    pub fn square(codel_size: usize) -> Self {
+        GridSpec {
+            cell_width: codel_size,
+            cell_height: codel_size,
+            offset_x: 0,
+            offset_y: 0,
+        }
+    }
+
+    /// Returns the number of rows and that of columns of the whole codels in an image of `height x width` pixels.
+    pub fn num_codels(&self, height: usize, width: usize) -> (usize, usize) {
+        (
+            height.saturating_sub(self.offset_y) / self.cell_height,
+            width.saturating_sub(self.offset_x) / self.cell_width,
+        )
+    }
+
+    /// Returns the number of the dropped rows of pixels at the top and at the bottom, and that of the dropped columns at the left and at the right, in an image of `height x width` pixels.
+    pub fn margins(&self, height: usize, width: usize) -> [usize; 4] {
+        let (num_rows, num_columns) = self.num_codels(height, width);
+        let top = self.offset_y.min(height);
+        let left = self.offset_x.min(width);
+        [
+            top,
+            height - top - num_rows * self.cell_height,
+            left,
+            width - left - num_columns * self.cell_width,
+        ]
+    }
+}
+
+/// How the codels are laid out in the pixels of an image.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CodelLayout {
+    /// The codel size is detected.
+    /// If `search_offset` is `true` and no size larger than one pixel is found, margins of up to [`MAX_GRID_OFFSET`] pixels on each side are also tried (e.g. a border added by an editor).
+    /// The detection gives up after `budget` if it is `Some`, and the codels are then laid out as `fallback` tells.
+    Detect {
+        search_offset: bool,
+        budget: Option<Duration>,
+        fallback: DetectFallback,
+    },
+    /// Square codels of the size.
+    Size(usize),
+    Grid(GridSpec),
+}
+
+/// Largest margin tried on each side by [`CodelLayout::Detect`].
+pub const MAX_GRID_OFFSET: usize = 3;
+
+/// Default time budget of the detection of the codel size in milliseconds (`--detect-budget`).
+pub const DEFAULT_DETECT_BUDGET_MS: u64 = 2000;
+
+/// What is done when the detection of the codel size exceeds its time budget (`--detect-fallback`).
+/// The codel layout of a fallback is warned about, as it may be wrong.
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq, Hash, Default)]
+pub enum DetectFallback {
+    /// Uses the codel size detected from the pixels checked so far (or 1 if there is none), which may be too large for the rest of the image
+    #[default]
+    Partial,
+    /// Uses the codel size 1
+    One,
+    /// Fails, asking for `--codel-size`
+    Error,
+}
+
+/// Returns `true` if `deadline` is given and has passed.
+fn is_past(deadline: Option<Instant>) -> bool {
+    deadline.is_some_and(|deadline| Instant::now() >= deadline)
+}
+
+impl Display for DetectFallback {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.to_possible_value().unwrap().get_name())
+    }
+}
+
+/* ColorMatching */
+
+/// Largest `--color-tolerance`, below half of the smallest difference between two colors of Piet in a channel (`0x3F`).
+pub const MAX_COLOR_TOLERANCE: u8 = 31;
+
+/// Percentage of the codels of a JPEG image which shall have one of the 20 colors of Piet (see [`PietError::LossyImage`]).
+pub const MIN_MATCHED_PERCENT: usize = 99;
+
+/// How the pixels are read as colors of Piet.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub struct ColorMatching {
+    /// Replaces unknown colors if it is given.
+    pub default_color: Option<Codel>,
+    /// A pixel whose channels are each within this of a color of Piet is read as the color (see [`Codel::new_with_tolerance()`]).
+    pub tolerance: u8,
+}
+
+/*-------------------------------------*/
+
+/* PixelGrid */
+
+/// Decoded pixels of an image file, kept in the flat buffer of the decoder (row-major, `channels` bytes per pixel of which the first three are RGB).
+struct PixelGrid {
+    height: usize,
+    width: usize,
+    channels: usize,
+    data: Vec<u8>,
+}
+
+impl PixelGrid {
+    fn rgb(&self, (i, j): (usize, usize)) -> &[u8] {
+        let k = (i * self.width + j) * self.channels;
+        &self.data[k..k + 3]
+    }
+
+    fn get(&self, i: usize, j: usize) -> Pixel {
+        let p = self.rgb((i, j));
+        Pixel::new(p[0], p[1], p[2])
+    }
+
+    fn is_same(&self, a: (usize, usize), b: (usize, usize)) -> bool {
+        self.rgb(a) == self.rgb(b)
+    }
+
+    /// Replaces each pixel within `tolerance` of a color of Piet with the color, returning the number of the replaced pixels.
+    fn snap_colors(&mut self, tolerance: u8) -> usize {
+        let mut num_pixels = 0;
+        for p in self.data.chunks_exact_mut(self.channels) {
+            let (r, g, b) =
+                Codel::new_with_tolerance(&Pixel::new(p[0], p[1], p[2]), tolerance).rgb();
+            if p[..3] != [r, g, b] {
+                p[..3].copy_from_slice(&[r, g, b]);
+                num_pixels += 1;
+            }
+        }
+        num_pixels
+    }
+
+    /// Copies the pixels of `region`, which shall lie within the grid.
+    fn crop(&self, region: &Region) -> Self {
+        let (top, left) = region.pos;
+        let row_len = region.width * self.channels;
+        let mut data = Vec::with_capacity(region.height * row_len);
+        for i in top..top + region.height {
+            let k = (i * self.width + left) * self.channels;
+            data.extend_from_slice(&self.data[k..k + row_len]);
+        }
+        Self {
+            height: region.height,
+            width: region.width,
+            channels: self.channels,
+            data,
+        }
+    }
+}
+
+/*-------------------------------------*/
+
+/* CodelGrid */
+
+/// Rectangular grid of codels from which the blocks of an [`Image`] are built (see [`Image::from_codels()`]).
+pub trait CodelGrid {
+    fn height(&self) -> usize;
+    fn width(&self) -> usize;
+
+    /// Returns the codel at the `i`-th row and the `j`-th column.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `(i, j)` is out of bounds.
+    fn codel_at(&self, pos: (usize, usize)) -> Codel;
+
+    /// Returns whether every row has [`Self::width()`] codels.
+    fn is_rectangular(&self) -> bool {
+        true
+    }
+}
+
+impl CodelGrid for [Vec<Codel>] {
+    fn height(&self) -> usize {
+        self.len()
+    }
+
+    fn width(&self) -> usize {
+        self.first().map_or(0, |row| row.len())
+    }
+
+    fn codel_at(&self, (i, j): (usize, usize)) -> Codel {
+        self[i][j]
+    }
+
+    fn is_rectangular(&self) -> bool {
+        let width = self.width();
+        self.iter().all(|row| row.len() == width)
+    }
+}
+
+/// Codels borrowed from a flat buffer in reading order (row-major), e.g. one which a program generator reuses.
+#[derive(Clone, Copy, Debug)]
+pub struct CodelSlice<'a> {
+    codels: &'a [Codel],
+    width: usize,
+}
+
+impl<'a> CodelSlice<'a> {
+    /// # Panics
+    ///
+    /// Panics if `width` is zero or the length of `codels` is not a multiple of `width`.
+    pub fn new(codels: &'a [Codel], width: usize) -> Self {
+        assert!(width > 0);
+        assert!(codels.len().is_multiple_of(width));
+        Self { codels, width }
+    }
+}
+
+impl CodelGrid for CodelSlice<'_> {
+    fn height(&self) -> usize {
+        self.codels.len() / self.width
+    }
+
+    fn width(&self) -> usize {
+        self.width
+    }
+
+    fn codel_at(&self, (i, j): (usize, usize)) -> Codel {
+        assert!(j < self.width);
+        self.codels[i * self.width + j]
+    }
+}
+
 /*-------------------------------------*/
 
 /* Image */
@@ -39,29 +348,44 @@ pub struct Image {
     m: Vec<Vec<Codel>>,
     height: usize,
     width: usize,
-    block_map: Vec<Vec<Rc<Block>>>,
+    /// Blocks in the canonical order (see [`Image::get_block_id_at()`]).
+    blocks: Vec<Block>,
+    /// `block_ids[i][j]` is the index in `blocks` of the block to which the codel at `(i, j)` belongs.
+    block_ids: Vec<Vec<usize>>,
+    /// Only affects the movement between codels; blocks never extend across an edge.
+    topology: Topology,
 }
 
 impl Display for Image {
     /// Prints the input image as an ASCII art.
+    ///
+    /// Each codel is two columns wide. The column numbers are printed above the image (the tens at every tenth column, then the ones), and the row numbers on the left.
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        //wide enough for the largest row number, but at least 2 so that small images look as before
+        let label_width = coord::last(self.height)
+            .unwrap_or(0)
+            .to_string()
+            .len()
+            .max(2);
+
         //column numbers
+        //The tens are written from the tenth columns and may be wider than a codel, which is fine as the next one is 20 characters away.
+        let mut tens = vec![' '; 2 * self.width];
+        for e in (0..self.width).step_by(10) {
+            let label = (e / 10).to_string();
+            tens.resize(tens.len().max(2 * e + label.len()), ' ');
+            tens.splice(2 * e..2 * e + label.len(), label.chars());
+        }
         let mut s = format!(
-            "   {}\n  {}\n",
-            (0..self.width)
-                .map(|e| format!(
-                    "{:2}",
-                    if e % 10 == 0 {
-                        (e / 10).to_string()
-                    } else {
-                        " ".to_string()
-                    }
-                ))
-                .join(""),
+            "{:w$} {}\n{:w$}{}\n",
+            "",
+            tens.into_iter().collect::<String>(),
+            "",
             (0..self.width).map(|e| format!("{:2}", e % 10)).join(""),
+            w = label_width,
         );
         for i in 0..self.height {
-            s += &format!("{:2} ", i); //row number
+            s += &format!("{:w$} ", i, w = label_width); //row number
             for j in 0..self.width {
                 s += &format!("{}", self.m[i][j]); //see `impl Display for Codel`
             }
@@ -71,43 +395,263 @@ impl Display for Image {
     }
 }
 
+impl CodelGrid for Image {
+    fn height(&self) -> usize {
+        self.height
+    }
+
+    fn width(&self) -> usize {
+        self.width
+    }
+
+    fn codel_at(&self, (i, j): (usize, usize)) -> Codel {
+        self.m[i][j]
+    }
+}
+
+/// Returns the layout of `codel_size`, which is detected if it is `None`.
+pub(crate) fn default_layout(codel_size: Option<usize>) -> CodelLayout {
+    match codel_size {
+        None => CodelLayout::Detect {
+            search_offset: false,
+            budget: None,
+            fallback: DetectFallback::default(),
+        },
+        Some(codel_size) => CodelLayout::Size(codel_size),
+    }
+}
+
 impl Image {
+    /// Loads an image file.
+    ///
+    /// The codel size is detected if `codel_size` is `None`.
+    /// A given codel size is only checked to divide the dimensions unless `strict_codel_check` is `true`, in which case every codel shall also be uniformly colored.
+    /// Unknown colors are replaced with `default_color` if it is given.
+    ///
+    /// The default [`ImageLimits`] apply (see [`Image::new_with_limits()`]).
     pub fn new(
         file: impl AsRef<Path>,
         codel_size: Option<usize>,
         default_color: Option<Codel>,
+        strict_codel_check: bool,
+    ) -> Result<Self, Box<dyn Error>> {
+        Self::new_with_limits(
+            file,
+            default_layout(codel_size),
+            ColorMatching {
+                default_color,
+                tolerance: 0,
+            },
+            strict_codel_check,
+            &ImageLimits::default(),
+            None,
+            &mut vec![],
+        )
+    }
+
+    /// Same as [`Image::new()`] but with the codels laid out as `layout`, with the given limits on the size of the image, and cropped to `crop` (in pixels) before the codels are read.
+    /// The limits are checked against the header before the pixels are decoded, and an image which exceeds them is rejected with [`PietError::ImageTooLarge`].
+    ///
+    /// A crop which cuts codels (e.g. by a few pixels off) shifts the codel grid, so that the program would silently run with other blocks.
+    /// Thus, if a cropped image doesn't tile with uniform codels of the expected size but the crop moved back by a sub-codel offset does,
+    /// it is rejected with [`PietError::MisalignedCrop`] which suggests the aligned crop.
+    /// The expected size is the given codel size, or otherwise the common size of the runs of identical pixels which don't touch the edges of the crop.
+    ///
+    /// A [`CodelLayout::Grid`] is applied to the cropped pixels, and shall cover at least one whole codel.
+    ///
+    /// If the detection of the codel size falls back to a layout after its time budget, [`Warning::DetectionTimedOut`] is pushed to `warnings`.
+    ///
+    /// The pixels are read as colors of Piet as `colors` tells, before the codel size is detected.
+    /// The compression of JPEG alters the colors of almost every pixel, so a JPEG image of which less than [`MIN_MATCHED_PERCENT`]% of the codels have a color of Piet is rejected with [`PietError::LossyImage`].
+    ///
+    /// A GIF, TIFF or ICO file of several images is rejected with [`PietError::MultipleFrames`], as which of them is the program is not known (see [`Image::new_observed()`] to choose one).
+    pub fn new_with_limits(
+        file: impl AsRef<Path>,
+        layout: CodelLayout,
+        colors: ColorMatching,
+        strict_codel_check: bool,
+        limits: &ImageLimits,
+        crop: Option<Region>,
+        warnings: &mut Vec<Warning>,
+    ) -> Result<Self, Box<dyn Error>> {
+        Self::new_observed(
+            file,
+            layout,
+            colors,
+            strict_codel_check,
+            limits,
+            crop,
+            None,
+            warnings,
+            None,
+        )
+    }
+
+    /// Same as [`Image::new_with_limits()`] but runs the image of `frame` (from zero) of a GIF, TIFF or ICO file of several images if it is given (`--frame`),
+    /// and reports the progress of each phase of loading to `observer` if it is given (see [`LoadObserver`]).
+    /// A `frame` out of range is rejected with [`PietError::FrameOutOfRange`]; any other image file has only the image `0`.
+    /// The detection of the codel size is not reported.
+    #[allow(clippy::too_many_arguments)]
+    pub fn new_observed(
+        file: impl AsRef<Path>,
+        layout: CodelLayout,
+        colors: ColorMatching,
+        strict_codel_check: bool,
+        limits: &ImageLimits,
+        crop: Option<Region>,
+        frame: Option<usize>,
+        warnings: &mut Vec<Warning>,
+        observer: Option<&mut dyn LoadObserver>,
     ) -> Result<Self, Box<dyn Error>> {
-        let mut pixel_map = vec![];
-        if !file.as_ref().exists() {
+        let file = file.as_ref();
+        if !file.exists() {
             return Err("file not found".into());
         }
-        match ImageReader::open(file)?.decode()? {
-            DynamicImage::ImageRgb8(img) => {
-                let height = img.height();
-                let width = img.width();
-                for i in 0..height {
-                    let mut row = Vec::with_capacity(width as usize);
-                    for j in 0..width {
-                        let pixel = img.get_pixel(j, i);
-                        row.push(Pixel::new(pixel[0], pixel[1], pixel[2]));
+        Self::decode_observed(
+            ImageReader::open(file)?,
+            layout,
+            colors,
+            strict_codel_check,
+            limits,
+            crop,
+            frame,
+            warnings,
+            observer,
+        )
+    }
+
+    /// Same as [`Image::new()`] but decodes the image from `bytes`, e.g. received over a network, without a file.
+    /// The format is guessed from the contents, and an image which is not of a known format is rejected as a file of an unknown format is.
+    pub fn from_bytes(
+        bytes: &[u8],
+        codel_size: Option<usize>,
+        default_color: Option<Codel>,
+    ) -> Result<Self, Box<dyn Error>> {
+        Self::from_reader(Cursor::new(bytes), codel_size, default_color)
+    }
+
+    /// Same as [`Image::from_bytes()`] but reads the image from `reader`, from its current position.
+    pub fn from_reader(
+        reader: impl Read + Seek,
+        codel_size: Option<usize>,
+        default_color: Option<Codel>,
+    ) -> Result<Self, Box<dyn Error>> {
+        Self::decode_observed(
+            ImageReader::new(BufReader::new(reader)),
+            default_layout(codel_size),
+            ColorMatching {
+                default_color,
+                tolerance: 0,
+            },
+            false,
+            &ImageLimits::default(),
+            None,
+            None,
+            &mut vec![],
+            None,
+        )
+    }
+
+    /// Decodes the image of `reader` as [`Image::new_observed()`] does.
+    /// The format is guessed from the contents, or else is kept as it is (e.g. by the extension of the file).
+    #[allow(clippy::too_many_arguments)]
+    fn decode_observed<R: BufRead + Seek>(
+        reader: ImageReader<R>,
+        layout: CodelLayout,
+        colors: ColorMatching,
+        strict_codel_check: bool,
+        limits: &ImageLimits,
+        crop: Option<Region>,
+        frame: Option<usize>,
+        warnings: &mut Vec<Warning>,
+        observer: Option<&mut dyn LoadObserver>,
+    ) -> Result<Self, Box<dyn Error>> {
+        let mut progress = LoadProgress::new(observer);
+        let reader = reader.with_guessed_format()?;
+        let format = reader.format();
+        let is_lossy = format == Some(ImageFormat::Jpeg);
+        //The decoded buffer is used as it is (without being copied into a matrix of pixels) to keep the peak memory usage low for large images.
+        let decoded = match (format, format.and_then(Container::of)) {
+            (Some(format), Some(container)) => {
+                //The images are listed from the headers (see `frames`), for which the whole file is read.
+                let mut bytes = vec![];
+                reader.into_inner().read_to_end(&mut bytes)?;
+                let frames = frames::list(&bytes, container, limits)?;
+                match frame {
+                    None if frames.len() > 1 => {
+                        return Err(PietError::MultipleFrames { container, frames }.into());
                     }
-                    pixel_map.push(row);
-                }
-            }
-            DynamicImage::ImageRgba8(img) => {
-                let height = img.height();
-                let width = img.width();
-                for i in 0..height {
-                    let mut row = Vec::with_capacity(width as usize);
-                    for j in 0..width {
-                        let pixel = img.get_pixel(j, i);
-                        row.push(Pixel::new(pixel[0], pixel[1], pixel[2]));
+                    None => Self::decode_single(
+                        ImageReader::with_format(Cursor::new(&bytes), format),
+                        None,
+                        limits,
+                        &mut progress,
+                    )?,
+                    Some(index) if index >= frames.len() => {
+                        return Err(PietError::FrameOutOfRange {
+                            frame: index,
+                            container: Some(container),
+                            frames,
+                        }
+                        .into());
+                    }
+                    Some(index) => {
+                        progress.start(LoadPhase::Decode, 1);
+                        let decoded = frames::decode(&bytes, container, index, limits)?;
+                        progress.finish(LoadPhase::Decode);
+                        decoded
                     }
-                    pixel_map.push(row);
                 }
             }
+            _ => Self::decode_single(reader, frame, limits, &mut progress)?,
+        };
+        let mut pixels = match decoded {
+            DynamicImage::ImageRgb8(img) => PixelGrid {
+                height: img.height() as usize,
+                width: img.width() as usize,
+                channels: 3,
+                data: img.into_raw(),
+            },
+            DynamicImage::ImageRgba8(img) => PixelGrid {
+                height: img.height() as usize,
+                width: img.width() as usize,
+                channels: 4,
+                data: img.into_raw(),
+            },
             _ => return Err("unsupported file format".into()),
+        };
+        if (pixels.height == 0) || (pixels.width == 0) {
+            return Err("the image is empty".into());
+        }
+        if colors.tolerance != 0 {
+            let num_pixels = pixels.snap_colors(colors.tolerance);
+            if num_pixels != 0 {
+                warning::warn(
+                    warnings,
+                    Warning::ColorsSnapped {
+                        num_pixels,
+                        tolerance: colors.tolerance,
+                    },
+                );
+            }
         }
+        //The whole image is kept to look for an aligned crop.
+        let (pixels, whole) = match crop {
+            None => (pixels, None),
+            Some(region) => {
+                if (region.height == 0)
+                    || (region.width == 0)
+                    || !region.fits_in(pixels.height, pixels.width)
+                {
+                    return Err(format!(
+                        "the crop {} is empty or exceeds the image ({} x {} pixels)",
+                        region, pixels.height, pixels.width
+                    )
+                    .into());
+                }
+                (pixels.crop(&region), Some((pixels, region)))
+            }
+        };
 
         //[spec]
         //Piet code takes the form of graphics made up of the recognised colours.
@@ -115,179 +659,878 @@ impl Image {
         //so it is common for programs to be enlarged for viewing so that the details are easily visible.
         //In such enlarged programs, the term "codel" is used to mean a block of colour equivalent to a single pixel of code,
         //to avoid confusion with the actual pixels of the enlarged graphic, of which many may make up one codel.
-        let codel_size = if let Some(codel_size) = codel_size {
-            if !Self::check_if_codel_size_is_valid(&pixel_map, codel_size) {
-                return Err("incorrect codel size specified".into());
+        let grid = match layout {
+            CodelLayout::Size(codel_size) => {
+                //A crop which cuts codels may still be divided into codels of the size, with a shifted grid.
+                if let Some((whole, region)) = &whole {
+                    if !Self::check_if_codel_size_is_valid(&pixels, codel_size) {
+                        Self::check_crop_alignment(whole, region, codel_size)?;
+                    }
+                }
+                let is_valid = if strict_codel_check {
+                    Self::check_if_codel_size_is_valid(&pixels, codel_size)
+                } else {
+                    (codel_size != 0)
+                        && Integer::is_multiple_of(&pixels.height, &codel_size)
+                        && Integer::is_multiple_of(&pixels.width, &codel_size)
+                };
+                if !is_valid {
+                    return Err("incorrect codel size specified".into());
+                }
+                GridSpec::square(codel_size)
+            }
+            CodelLayout::Detect {
+                search_offset,
+                budget,
+                fallback,
+            } => Self::detect_grid(
+                &pixels,
+                whole.as_ref(),
+                search_offset,
+                budget,
+                fallback,
+                warnings,
+            )?,
+            CodelLayout::Grid(grid) => {
+                let (height, width) = grid.num_codels(pixels.height, pixels.width);
+                if (height == 0) || (width == 0) {
+                    return Err(format!(
+                        "the codel grid {} covers no whole codel of the image ({} x {} pixels)",
+                        grid, pixels.height, pixels.width
+                    )
+                    .into());
+                }
+                if strict_codel_check && !Self::is_grid_uniform(&pixels, &grid) {
+                    return Err("incorrect codel grid specified".into());
+                }
+                grid
             }
-            codel_size
-        } else {
-            Self::detect_codel_size(&pixel_map).ok_or("failed to detect the codel size")?
         };
 
-        let height = pixel_map.len() / codel_size;
-        let width = pixel_map[0].len() / codel_size;
-        let mut m = vec![Vec::with_capacity(width); height];
-        for i in 0..height {
-            for j in 0..width {
-                let pixel = pixel_map[i * codel_size][j * codel_size];
-                let codel = Codel::new(&pixel)
-                    .or(default_color)
-                    .ok_or(format!("invalid color at ({}, {})", i, j))?;
-                m[i].push(codel);
+        let (height, width) = grid.num_codels(pixels.height, pixels.width);
+        progress.start(LoadPhase::Sample, height);
+        let m = (0..height)
+            .map(|i| {
+                progress.advance(LoadPhase::Sample, i);
+                (0..width)
+                    .map(|j| {
+                        let pixel = pixels.get(
+                            grid.offset_y + i * grid.cell_height,
+                            grid.offset_x + j * grid.cell_width,
+                        );
+                        //Unknown colors are kept as they are unless a fallback is specified, and cause an error only if the program reaches them.
+                        match (Codel::new(&pixel), colors.default_color) {
+                            (Codel::Other(_), Some(default_color)) => default_color,
+                            (codel, _) => codel,
+                        }
+                    })
+                    .collect_vec()
+            })
+            .collect_vec();
+        progress.finish(LoadPhase::Sample);
+
+        if is_lossy {
+            let num_codels = height * width;
+            let num_unknown = m.iter().flatten().filter(|c| c.is_other()).count();
+            if num_unknown * 100 > num_codels * (100 - MIN_MATCHED_PERCENT) {
+                return Err(PietError::LossyImage {
+                    num_unknown,
+                    num_codels,
+                    tolerance: colors.tolerance,
+                }
+                .into());
+            }
+        }
+
+        Ok(Self::from_codel_grid_with(m, &mut progress))
+    }
+
+    /// Decodes the only image of `reader`, which is the image `0` of `frame`.
+    fn decode_single<R: BufRead + Seek>(
+        mut reader: ImageReader<R>,
+        frame: Option<usize>,
+        limits: &ImageLimits,
+        progress: &mut LoadProgress,
+    ) -> Result<DynamicImage, Box<dyn Error>> {
+        //The limits of the `image` crate are disabled until our own check is done, so that an image which is too large is reported as `PietError::ImageTooLarge`.
+        reader.no_limits();
+        let mut decoder = reader.into_decoder()?;
+        let (width, height) = decoder.dimensions();
+        if let Some(index) = frame.filter(|i| *i != 0) {
+            return Err(PietError::FrameOutOfRange {
+                frame: index,
+                container: None,
+                frames: vec![Frame { height, width }],
             }
+            .into());
+        }
+        limits.check(height.into(), width.into(), Some(decoder.total_bytes()))?;
+        decoder.set_limits(limits.to_decoder_limits())?;
+        progress.start(LoadPhase::Decode, 1);
+        let decoded = DynamicImage::from_decoder(decoder)?;
+        progress.finish(LoadPhase::Decode);
+        Ok(decoded)
+    }
+
+    /// Returns the height and the width of an image file in pixels, read from its header.
+    pub fn pixel_dimensions(file: impl AsRef<Path>) -> Result<(usize, usize), Box<dyn Error>> {
+        let (width, height) = ImageReader::open(file)?.into_dimensions()?;
+        Ok((height as usize, width as usize))
+    }
+
+    /// Same as [`Image::pixel_dimensions()`] but of the image of `frame` of a GIF, TIFF or ICO file of several images (see [`Image::new_observed()`]).
+    pub fn frame_dimensions(
+        file: impl AsRef<Path>,
+        frame: usize,
+    ) -> Result<(usize, usize), Box<dyn Error>> {
+        let file = file.as_ref();
+        let reader = ImageReader::open(file)?.with_guessed_format()?;
+        let frames = match reader.format().and_then(Container::of) {
+            //Each frame of a GIF is composed onto the whole screen.
+            None | Some(Container::Gif) => vec![],
+            Some(container) => frames::list(&fs::read(file)?, container, &ImageLimits::default())?,
+        };
+        match frames.get(frame) {
+            Some(f) => Ok((f.height as usize, f.width as usize)),
+            None if frames.is_empty() => Self::pixel_dimensions(file),
+            None => Err("the frame is not found".into()),
+        }
+    }
+
+    /// Returns the image with the codels of unknown colors replaced with `codel`, and the number of the replaced codels.
+    pub fn replace_unknown_colors(self, codel: Codel) -> (Self, usize) {
+        self.map_colors(|c| if c.is_other() { codel } else { *c })
+    }
+
+    /// Returns the image with the color of each codel replaced by `f`, and the number of the codels whose color changed.
+    /// The blocks are recomputed only if a color changed.
+    pub fn map_colors(self, f: impl Fn(&Codel) -> Codel) -> (Self, usize) {
+        let num_codels = self.m.iter().flatten().filter(|c| f(c) != **c).count();
+        if num_codels == 0 {
+            return (self, 0);
+        }
+        let m = self
+            .m
+            .into_iter()
+            .map(|row| row.iter().map(&f).collect_vec())
+            .collect_vec();
+        let mut img = Self::from_codel_grid(m);
+        img.topology = self.topology;
+        (img, num_codels)
+    }
+
+    /// Creates an image directly from a matrix of codels.
+    /// `m[i][j]` is the codel at the `i`-th row and the `j`-th column.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `m` is empty or its rows don't have the same non-zero length.
+    pub fn from_codel_grid(m: Vec<Vec<Codel>>) -> Self {
+        Self::from_codel_grid_with(m, &mut LoadProgress::new(None))
+    }
+
+    /// Same as [`Image::from_codel_grid()`] but reports the progress of splitting the codels into blocks to `observer` if it is given (see [`LoadObserver`]).
+    pub fn from_codel_grid_observed(
+        m: Vec<Vec<Codel>>,
+        observer: Option<&mut dyn LoadObserver>,
+    ) -> Self {
+        Self::from_codel_grid_with(m, &mut LoadProgress::new(observer))
+    }
+
+    pub(crate) fn from_codel_grid_with(m: Vec<Vec<Codel>>, progress: &mut LoadProgress) -> Self {
+        let height = m.len();
+        assert!(height > 0);
+        let width = m[0].len();
+        assert!(width > 0);
+        assert!(m.iter().all(|row| row.len() == width));
+
+        let (blocks, block_ids) = Self::create_block_map(m.as_slice(), progress);
+
+        Self {
+            m,
+            height,
+            width,
+            blocks,
+            block_ids,
+            topology: Topology::default(),
         }
+    }
 
-        let block_map = Self::create_block_map(&m);
+    /// Creates an image from a grid of codels which the caller keeps, e.g. a [`CodelSlice`] of a buffer reused for each generated program.
+    /// The result is the same as that of [`Image::from_codel_grid()`] with the same codels.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `grid` is empty or its rows don't have the same length.
+    pub fn from_codels<G: CodelGrid + ?Sized>(grid: &G) -> Self {
+        let (height, width) = (grid.height(), grid.width());
+        assert!((height > 0) && (width > 0));
+        assert!(grid.is_rectangular());
 
-        Ok(Self {
+        let (blocks, block_ids) = Self::create_block_map(grid, &mut LoadProgress::new(None));
+
+        Self {
+            m: (0..height)
+                .map(|i| (0..width).map(|j| grid.codel_at((i, j))).collect())
+                .collect(),
+            height,
+            width,
+            blocks,
+            block_ids,
+            topology: Topology::default(),
+        }
+    }
+
+    /// Returns the codel matrix, the list of the blocks and the ID of the block of each codel (for serialization).
+    pub(crate) fn to_parts(&self) -> (&[Vec<Codel>], &[Block], &[Vec<usize>]) {
+        (&self.m, &self.blocks, &self.block_ids)
+    }
+
+    /// Inverse of [`Image::to_parts()`].
+    /// `None` is returned if the parts are inconsistent or the block IDs are not canonical.
+    pub(crate) fn from_parts(
+        m: Vec<Vec<Codel>>,
+        mut blocks: Vec<Block>,
+        block_ids: Vec<Vec<usize>>,
+    ) -> Option<Self> {
+        let height = m.len();
+        let width = m.first()?.len();
+        if (width == 0)
+            || m.iter().any(|row| row.len() != width)
+            || (block_ids.len() != height)
+            || block_ids.iter().any(|row| row.len() != width)
+        {
+            return None;
+        }
+        //corners outside of the image would make the accessors panic
+        if blocks
+            .iter()
+            .flat_map(|block| block.corners())
+            .any(|(i, j)| (i >= height) || (j >= width))
+        {
+            return None;
+        }
+        //each ID shall first appear in reading order right after the previous one, and every block shall be used
+        let mut num_seen = 0;
+        for &id in block_ids.iter().flatten() {
+            if id == num_seen {
+                num_seen += 1;
+            } else if id > num_seen {
+                return None;
+            }
+        }
+        if num_seen != blocks.len() {
+            return None;
+        }
+        //membership is rebuilt from the block map, which shall agree with the sizes
+        let mut runs = vec![vec![]; blocks.len()];
+        for (i, row) in block_ids.iter().enumerate() {
+            for (id, group) in &row.iter().enumerate().chunk_by(|(_, id)| **id) {
+                let js = group.map(|(j, _)| j).collect_vec();
+                runs[id].push((i, js[0], js[js.len() - 1] + 1));
+            }
+        }
+        for (block, runs) in blocks.iter_mut().zip(&runs) {
+            block.set_runs(runs);
+            if block.codels().count() != block.size {
+                return None;
+            }
+        }
+        Some(Self {
             m,
             height,
             width,
-            block_map,
+            blocks,
+            block_ids,
+            topology: Topology::default(),
         })
     }
 
-    fn check_if_codel_size_is_valid(pixel_map: &[Vec<Pixel>], codel_size: usize) -> bool {
-        let height = pixel_map.len();
-        let width = pixel_map[0].len();
-        if (height % codel_size != 0) || (width % codel_size != 0) {
+    /// Creates an enlarged copy of the image for visualization.
+    /// Each codel is duplicated into a `factor x factor` square of identical codels, and the block map is recomputed for the new grid.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `factor` is zero.
+    pub fn scale(&self, factor: usize) -> Self {
+        assert!(factor > 0);
+        let m = self
+            .m
+            .iter()
+            .flat_map(|row| {
+                let row = row
+                    .iter()
+                    .flat_map(|codel| itertools::repeat_n(*codel, factor))
+                    .collect_vec();
+                itertools::repeat_n(row, factor)
+            })
+            .collect_vec();
+        Self::from_codel_grid(m)
+    }
+
+    /// Creates a copy of the `height x width` codels whose top-left codel is at `(i, j)`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the region is empty or exceeds the image.
+    pub fn crop(&self, (i, j): (usize, usize), height: usize, width: usize) -> Self {
+        assert!((i + height <= self.height) && (j + width <= self.width));
+        let m = self.m[i..i + height]
+            .iter()
+            .map(|row| row[j..j + width].to_vec())
+            .collect_vec();
+        Self::from_codel_grid(m)
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    pub fn topology(&self) -> Topology {
+        self.topology
+    }
+
+    pub fn set_topology(&mut self, topology: Topology) {
+        self.topology = topology;
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    fn check_if_codel_size_is_valid(pixels: &PixelGrid, codel_size: usize) -> bool {
+        if codel_size == 0 {
             return false;
         }
-        let h = height / codel_size;
-        let w = width / codel_size;
-        for i in 0..h {
-            for j in 0..w {
-                let origin_i = i * codel_size;
-                let origin_j = j * codel_size;
-                let p = &pixel_map[origin_i][origin_j];
-                for i in 0..codel_size {
-                    for j in 0..codel_size {
-                        if &pixel_map[origin_i + j][origin_j + i] != p {
-                            return false;
+        let height = pixels.height;
+        let width = pixels.width;
+        if !Integer::is_multiple_of(&height, &codel_size)
+            || !Integer::is_multiple_of(&width, &codel_size)
+        {
+            return false;
+        }
+        (0..height)
+            .cartesian_product(0..width)
+            .all(|(i, j)| pixels.is_same((i, j), (i - i % codel_size, j - j % codel_size)))
+    }
+
+    /// Returns `true` if every codel of `grid` is uniformly colored (the dropped margins are not checked).
+    fn is_grid_uniform(pixels: &PixelGrid, grid: &GridSpec) -> bool {
+        let (height, width) = grid.num_codels(pixels.height, pixels.width);
+        let top_left = |i: usize, j: usize| {
+            (
+                grid.offset_y + i / grid.cell_height * grid.cell_height,
+                grid.offset_x + j / grid.cell_width * grid.cell_width,
+            )
+        };
+        (0..height * grid.cell_height)
+            .cartesian_product(0..width * grid.cell_width)
+            .all(|(i, j)| pixels.is_same((grid.offset_y + i, grid.offset_x + j), top_left(i, j)))
+    }
+
+    /// Looks for margins of up to [`MAX_GRID_OFFSET`] pixels on each side (e.g. a border added by an editor) without which the image tiles with codels larger than one pixel.
+    /// The smallest margins are preferred, and among them the largest codel size.
+    /// The margins at the bottom and at the right shall be smaller than a codel, so that no row or column of codels is dropped.
+    ///
+    /// Fails with [`PietError::DetectionTimedOut`] (with the best grid found so far) if `deadline` passes.
+    fn detect_grid_offset(
+        pixels: &PixelGrid,
+        deadline: Option<Instant>,
+    ) -> Result<Option<GridSpec>, PietError> {
+        let margins = (0..=MAX_GRID_OFFSET)
+            .cartesian_product(0..=MAX_GRID_OFFSET)
+            .cartesian_product((0..=MAX_GRID_OFFSET).cartesian_product(0..=MAX_GRID_OFFSET))
+            .map(|((top, left), (bottom, right))| [top, left, bottom, right])
+            .filter(|m| m.iter().any(|&d| d != 0))
+            .sorted_by_key(|m| m.iter().sum::<usize>());
+        let mut found: Option<(usize, GridSpec)> = None;
+        for [top, left, bottom, right] in margins {
+            let total = top + left + bottom + right;
+            if found.is_some_and(|(t, _)| t < total) {
+                break;
+            }
+            let timed_out = || PietError::DetectionTimedOut {
+                partial: found.map(|(_, grid)| grid),
+            };
+            if is_past(deadline) {
+                return Err(timed_out());
+            }
+            if (top + bottom >= pixels.height) || (left + right >= pixels.width) {
+                continue;
+            }
+            let region = Region {
+                pos: (top, left),
+                height: pixels.height - top - bottom,
+                width: pixels.width - left - right,
+            };
+            let g = match Self::detect_codel_size(&pixels.crop(&region), deadline) {
+                Ok(g) => g,
+                Err(PietError::DetectionTimedOut { .. }) => return Err(timed_out()),
+                Err(_) => continue,
+            };
+            if (g > 1)
+                && (bottom < g)
+                && (right < g)
+                && found.is_none_or(|(_, grid)| grid.cell_width < g)
+            {
+                let grid = GridSpec {
+                    cell_width: g,
+                    cell_height: g,
+                    offset_x: left,
+                    offset_y: top,
+                };
+                found = Some((total, grid));
+            }
+        }
+        Ok(found.map(|(_, grid)| grid))
+    }
+
+    /// Returns the largest codel size which tiles the image with uniformly colored codels.
+    ///
+    /// Boundaries between different colors can only be at multiples of a valid codel size, so the length of every horizontal or vertical run of identical pixels is a multiple of it.
+    /// Conversely, if the greatest common divisor of the run lengths (and the dimensions) is `g`, every `g x g` cell is uniform as each of its rows and columns is.
+    /// Thus a single pass over the pixels is enough.
+    ///
+    /// Fails with [`PietError::DetectionTimedOut`] if `deadline` passes, with the codel size detected from the rows checked so far.
+    fn detect_codel_size(
+        pixels: &PixelGrid,
+        deadline: Option<Instant>,
+    ) -> Result<usize, PietError> {
+        let height = pixels.height;
+        let width = pixels.width;
+        let mut g = height.gcd(&width);
+        //length of the current vertical run of each column
+        let mut vertical_runs = vec![1; width];
+        let mut horizontal_run = 1;
+        for i in 0..height {
+            for (j, vertical_run) in vertical_runs.iter_mut().enumerate() {
+                match coord::offset((i, j), (0, -1), height, width) {
+                    Some(left) if pixels.is_same((i, j), left) => horizontal_run += 1,
+                    Some(_) => {
+                        g = g.gcd(&horizontal_run);
+                        horizontal_run = 1;
+                    }
+                    None => (),
+                }
+                match coord::offset((i, j), (-1, 0), height, width) {
+                    Some(up) if pixels.is_same((i, j), up) => *vertical_run += 1,
+                    Some(_) => {
+                        g = g.gcd(vertical_run);
+                        *vertical_run = 1;
+                    }
+                    None => (),
+                }
+            }
+            //the last run of a row ends at the edge, which is a multiple of `g` anyway
+            horizontal_run = 1;
+            if g == 1 {
+                break;
+            }
+            if (i + 1 < height) && is_past(deadline) {
+                return Err(PietError::DetectionTimedOut {
+                    partial: Some(GridSpec::square(g)),
+                });
+            }
+        }
+        Ok(g)
+    }
+
+    /// Detects the layout of the codels for [`CodelLayout::Detect`].
+    /// If the detection doesn't finish within `budget`, the layout is chosen by `fallback` and warned about.
+    fn detect_grid(
+        pixels: &PixelGrid,
+        whole: Option<&(PixelGrid, Region)>,
+        search_offset: bool,
+        budget: Option<Duration>,
+        fallback: DetectFallback,
+        warnings: &mut Vec<Warning>,
+    ) -> Result<GridSpec, PietError> {
+        let deadline = budget.map(|budget| Instant::now() + budget);
+        let detected = Self::detect_codel_size(pixels, deadline).and_then(|codel_size| {
+            if let Some((whole, region)) = whole {
+                let expected = Self::interior_run_gcd(pixels);
+                if expected > codel_size {
+                    Self::check_crop_alignment(whole, region, expected)?;
+                }
+            }
+            match codel_size {
+                1 if search_offset => {
+                    Ok(Self::detect_grid_offset(pixels, deadline)?.unwrap_or(GridSpec::square(1)))
+                }
+                _ => Ok(GridSpec::square(codel_size)),
+            }
+        });
+        let Err(PietError::DetectionTimedOut { partial }) = detected else {
+            return detected;
+        };
+        let grid = match fallback {
+            DetectFallback::Partial => partial.unwrap_or(GridSpec::square(1)),
+            DetectFallback::One => GridSpec::square(1),
+            DetectFallback::Error => return detected,
+        };
+        warning::warn(
+            warnings,
+            Warning::DetectionTimedOut {
+                budget: budget.unwrap_or_default(),
+                grid,
+            },
+        );
+        Ok(grid)
+    }
+
+    /// Returns the greatest common divisor of the lengths of the horizontal and vertical runs of identical pixels which don't touch the edges (`0` if there is no such run).
+    /// Unlike the runs at the edges, they are not cut by a crop, so they are multiples of the codel size of the original image.
+    fn interior_run_gcd(pixels: &PixelGrid) -> usize {
+        let (height, width) = (pixels.height, pixels.width);
+        let mut g = 0;
+        //start of the current horizontal run, and that of the current vertical run of each column
+        let mut horizontal_start = 0;
+        let mut vertical_starts = vec![0; width];
+        for i in 0..height {
+            for (j, vertical_start) in vertical_starts.iter_mut().enumerate() {
+                match coord::offset((i, j), (0, -1), height, width) {
+                    Some(left) if !pixels.is_same((i, j), left) => {
+                        if horizontal_start != 0 {
+                            g = g.gcd(&(j - horizontal_start));
+                        }
+                        horizontal_start = j;
+                    }
+                    _ => (),
+                }
+                match coord::offset((i, j), (-1, 0), height, width) {
+                    Some(up) if !pixels.is_same((i, j), up) => {
+                        if *vertical_start != 0 {
+                            g = g.gcd(&(i - *vertical_start));
                         }
+                        *vertical_start = i;
                     }
+                    _ => (),
                 }
             }
+            horizontal_start = 0;
         }
-        true
+        g
     }
 
-    /// Automatically detects the codel size.
-    /// As codel size is generally not unique, we return the largest possible codel size (if any).
-    fn detect_codel_size(pixel_map: &[Vec<Pixel>]) -> Option<usize> {
-        let height = pixel_map.len();
-        let width = pixel_map[0].len();
-        //tries all of the common divisors of `height` and `width` in descending order
-        for codel_size in (1..=(height.min(width))).rev() {
-            if !((width % codel_size == 0) && (height % codel_size == 0)) {
+    /// Fails with [`PietError::MisalignedCrop`] if `crop` of `whole` moved up and left by less than `codel_size` pixels (and shrunk to whole codels) tiles with uniform codels of `codel_size`.
+    /// The smallest offsets are tried first.
+    fn check_crop_alignment(
+        whole: &PixelGrid,
+        crop: &Region,
+        codel_size: usize,
+    ) -> Result<(), PietError> {
+        let (top, left) = crop.pos;
+        for (di, dj) in (0..codel_size.min(top + 1)).cartesian_product(0..codel_size.min(left + 1))
+        {
+            if (di, dj) == (0, 0) {
                 continue;
             }
-            if Self::check_if_codel_size_is_valid(pixel_map, codel_size) {
-                return Some(codel_size);
+            let aligned = Region {
+                pos: (top - di, left - dj),
+                height: (crop.height + di) / codel_size * codel_size,
+                width: (crop.width + dj) / codel_size * codel_size,
+            };
+            if (aligned.height != 0)
+                && (aligned.width != 0)
+                && Self::check_if_codel_size_is_valid(&whole.crop(&aligned), codel_size)
+            {
+                return Err(PietError::MisalignedCrop {
+                    crop: *crop,
+                    codel_size,
+                    suggested: aligned,
+                });
             }
         }
-        None
+        Ok(())
     }
 
     /// Splits the graph into blocks (i.e. connected components) by repeating DFS.
-    /// `returned_value[i][j]` represents the block to which the codel at `(i, j)` belongs.
-    /// As generally multiple pairs of `(i, j)` belong to the same block, we use `Rc`.
+    /// Returns the blocks and `block_ids`, where `block_ids[i][j]` is the index of the block to which the codel at `(i, j)` belongs.
     ///
     /// Related [spec](https://www.dangermouse.net/esoteric/piet.html):
     ///
     /// > A colour block is a contiguous block of any number of codels of one colour, bounded by blocks of other colours or by the edge of the program graphic.
     /// > Blocks of colour adjacent only diagonally are not considered contiguous.
     ///
-    fn create_block_map(m: &[Vec<Codel>]) -> Vec<Vec<Rc<Block>>> {
-        let mut connected_components = vec![];
-        let mut visited = FxHashSet::default();
-        for i in 0..m.len() {
-            for j in 0..m[0].len() {
-                if visited.contains(&(i, j)) {
+    /// The codels are scanned in reading order (row-major), so the blocks are numbered by the position of their first codel in reading order.
+    /// This numbering depends only on the codels, and thus is identical across runs and platforms.
+    ///
+    /// The progress is reported to `progress` after each row ([`LoadPhase::Label`]) and each block ([`LoadPhase::Corners`]).
+    fn create_block_map<G: CodelGrid + ?Sized>(
+        grid: &G,
+        progress: &mut LoadProgress,
+    ) -> (Vec<Block>, Vec<Vec<usize>>) {
+        let mut blocks = vec![];
+        let mut block_ids = vec![vec![UNASSIGNED; grid.width()]; grid.height()];
+        let mut stack = vec![];
+        let mut codels = vec![];
+        let mut num_done = 0;
+        progress.start(LoadPhase::Label, grid.height());
+        progress.start(LoadPhase::Corners, grid.height() * grid.width());
+        for i in 0..grid.height() {
+            progress.advance(LoadPhase::Label, i);
+            for j in 0..grid.width() {
+                if block_ids[i][j] != UNASSIGNED {
                     continue;
                 }
-                let visited_local = Self::dfs((i, j), &m[i][j], m);
-                visited_local.iter().for_each(|e| {
-                    visited.insert(*e);
-                });
-                connected_components.push(visited_local);
+                Self::fill(
+                    (i, j),
+                    grid,
+                    &mut block_ids,
+                    blocks.len(),
+                    &mut stack,
+                    &mut codels,
+                );
+                blocks.push(Block::from_sorted_codels(&codels));
+                num_done += codels.len();
+                progress.advance(LoadPhase::Corners, num_done);
             }
         }
+        progress.finish(LoadPhase::Label);
+        progress.finish(LoadPhase::Corners);
+        (blocks, block_ids)
+    }
 
-        let mut block_map = vec![vec![Rc::new(Block::default()); m[0].len()]; m.len()];
-        connected_components.into_iter().for_each(|s| {
-            let block = Rc::new(Block::new(&s));
-            s.into_iter().for_each(|(i, j)| {
-                block_map[i][j] = block.clone();
-            });
-        });
+    /// Changes the codel at `pos` to `codel`, and repairs the blocks without rebuilding the whole block map.
+    ///
+    /// Only the old block of `pos` and the blocks of `codel`'s color adjacent to `pos` can change (split, merge or shrink), so only their codels are recomputed.
+    /// The blocks are then renumbered so that the IDs are the same as those of an image built from scratch.
+    ///
+    /// Returns the new ID of each old block, or `None` if the block was changed.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `pos` is out of bounds.
+    pub(crate) fn set_codel(&mut self, pos: (usize, usize), codel: Codel) -> Vec<Option<usize>> {
+        if self.m[pos.0][pos.1] == codel {
+            return (0..self.blocks.len()).map(Some).collect();
+        }
 
-        block_map
-    }
+        let mut affected_ids = vec![self.block_ids[pos.0][pos.1]];
+        for (i, j) in coord::neighbours(pos, self.height, self.width) {
+            if (self.m[i][j] == codel) && !affected_ids.contains(&self.block_ids[i][j]) {
+                affected_ids.push(self.block_ids[i][j]);
+            }
+        }
+        //The affected blocks are closed under adjacency of the same color, so the new blocks consist only of their codels.
+        let mut affected_codels = vec![];
+        for id in &affected_ids {
+            affected_codels.extend(self.blocks[*id].codels());
+        }
+        affected_codels.sort();
 
-    /// Returns the four adjacent codels to the codel at `(i, j)`.
-    fn four_adjacents((i, j): (usize, usize), height: usize, width: usize) -> Vec<(usize, usize)> {
-        let mut ret = vec![];
-        if i != 0 {
-            ret.push((i - 1, j));
+        self.m[pos.0][pos.1] = codel;
+
+        for &(i, j) in &affected_codels {
+            self.block_ids[i][j] = UNASSIGNED;
         }
-        if i != height - 1 {
-            ret.push((i + 1, j));
+        let mut new_blocks = vec![];
+        let mut stack = vec![];
+        let mut codels = vec![];
+        for &(i, j) in &affected_codels {
+            if self.block_ids[i][j] != UNASSIGNED {
+                continue;
+            }
+            //temporary IDs after the old ones
+            Self::fill(
+                (i, j),
+                self.m.as_slice(),
+                &mut self.block_ids,
+                self.blocks.len() + new_blocks.len(),
+                &mut stack,
+                &mut codels,
+            );
+            new_blocks.push(Block::from_sorted_codels(&codels));
         }
-        if j != 0 {
-            ret.push((i, j - 1));
+
+        //renumbers in the canonical order, i.e. by the first codel in reading order
+        let num_old_blocks = self.blocks.len();
+        let mut blocks = std::mem::take(&mut self.blocks)
+            .into_iter()
+            .enumerate()
+            .filter(|(id, _)| !affected_ids.contains(id))
+            .chain((num_old_blocks..).zip(new_blocks))
+            .collect_vec();
+        blocks.sort_by_key(|(_, block)| block.first_codel());
+        let mut remap = vec![0; num_old_blocks + blocks.len()];
+        for (new_id, (tmp_id, _)) in blocks.iter().enumerate() {
+            remap[*tmp_id] = new_id;
         }
-        if j != width - 1 {
-            ret.push((i, j + 1));
+        let is_unaffected_unmoved = (0..num_old_blocks)
+            .filter(|id| !affected_ids.contains(id))
+            .all(|id| remap[id] == id);
+        if is_unaffected_unmoved {
+            for &(i, j) in &affected_codels {
+                self.block_ids[i][j] = remap[self.block_ids[i][j]];
+            }
+        } else {
+            for id in self.block_ids.iter_mut().flatten() {
+                *id = remap[*id];
+            }
         }
-        ret
+        self.blocks = blocks.into_iter().map(|(_, block)| block).collect();
+
+        (0..num_old_blocks)
+            .map(|id| (!affected_ids.contains(&id)).then(|| remap[id]))
+            .collect()
     }
 
-    fn dfs(start: (usize, usize), color: &Codel, m: &[Vec<Codel>]) -> FxHashSet<(usize, usize)> {
-        let mut visited = FxHashSet::default();
+    /// Assigns `id` to the codels of the color of `start` connected to it through codels without a block, and stores them into `codels` in reading order.
+    /// `stack` and `codels` are only buffers reused between the calls.
+    fn fill<G: CodelGrid + ?Sized>(
+        start: (usize, usize),
+        grid: &G,
+        block_ids: &mut [Vec<usize>],
+        id: usize,
+        stack: &mut Vec<(usize, usize)>,
+        codels: &mut Vec<(usize, usize)>,
+    ) {
+        let color = grid.codel_at(start);
+        let (height, width) = (grid.height(), grid.width());
 
-        let height = m.len();
-        let width = m[0].len();
+        codels.clear();
+        block_ids[start.0][start.1] = id;
+        stack.push(start);
+        while let Some(cur) = stack.pop() {
+            codels.push(cur);
+            for (i, j) in coord::neighbours(cur, height, width) {
+                if (block_ids[i][j] == UNASSIGNED) && (grid.codel_at((i, j)) == color) {
+                    block_ids[i][j] = id;
+                    stack.push((i, j));
+                }
+            }
+        }
+        codels.sort_unstable();
+    }
 
-        let mut q = vec![start];
-        while let Some(cur) = q.pop() {
-            visited.insert(cur);
-            Self::four_adjacents(cur, height, width)
-                .into_iter()
-                .filter(|e| !visited.contains(e))
-                .filter(|(i, j)| &m[*i][*j] == color)
-                .for_each(|e| {
-                    q.push(e);
-                });
+    /// Returns `true` iff `(i, j)` is inside the image.
+    pub fn contains(&self, (i, j): (usize, usize)) -> bool {
+        (i < self.height) && (j < self.width)
+    }
+
+    fn check_bounds(&self, pos: (usize, usize)) -> Result<(), PietError> {
+        if self.contains(pos) {
+            Ok(())
+        } else {
+            Err(PietError::PositionOutOfBounds {
+                pos,
+                image_height: self.height,
+                image_width: self.width,
+            })
         }
+    }
+
+    /// Returns the index of the first non-black codel in row-major order, if any.
+    pub fn get_first_non_black_codel_index(&self) -> Option<(usize, usize)> {
+        (0..self.height)
+            .cartesian_product(0..self.width)
+            .find(|(i, j)| !self.m[*i][*j].is_black())
+    }
 
-        visited
+    /// Returns `true` iff the image has a codel of one of the 18 hue colors (i.e. neither white, black nor an unknown color).
+    /// Without such a codel, the program cannot execute any command.
+    pub fn has_colored_codel(&self) -> bool {
+        self.m
+            .iter()
+            .flatten()
+            .any(|codel| !(codel.is_white() || codel.is_black() || codel.is_other()))
     }
 
-    pub fn get_codel_at(&self, (i, j): (usize, usize)) -> &Codel {
+    /// Returns the codel at `(i, j)`.
+    /// An error is returned if the position is out of bounds.
+    pub fn get_codel_at(&self, pos: (usize, usize)) -> Result<&Codel, PietError> {
+        self.check_bounds(pos)?;
+        Ok(self.get_codel_at_unchecked(pos))
+    }
+
+    /// Same as [`Self::get_codel_at()`] but panics if the position is out of bounds.
+    /// This is used where the position is guaranteed to be valid (e.g. the run loop).
+    pub(crate) fn get_codel_at_unchecked(&self, (i, j): (usize, usize)) -> &Codel {
         &self.m[i][j]
     }
 
-    pub fn get_block_size_at(&self, (i, j): (usize, usize)) -> usize {
-        self.block_map[i][j].size
+    /// Returns the block to which the codel at `(i, j)` belongs.
+    /// An error is returned if the position is out of bounds.
+    pub fn get_block_at(&self, pos: (usize, usize)) -> Result<&Block, PietError> {
+        self.check_bounds(pos)?;
+        Ok(&self.blocks[self.block_ids[pos.0][pos.1]])
+    }
+
+    /// Returns the ID of the block to which the codel at `(i, j)` belongs.
+    /// An error is returned if the position is out of bounds.
+    ///
+    /// IDs are `0..self.num_blocks()`, assigned in the order in which the blocks first appear in reading order (row-major),
+    /// so they are identical across runs and platforms.
+    pub fn get_block_id_at(&self, pos: (usize, usize)) -> Result<usize, PietError> {
+        self.check_bounds(pos)?;
+        Ok(self.block_ids[pos.0][pos.1])
+    }
+
+    /// Same as [`Self::get_block_id_at()`] but panics if the position is out of bounds.
+    pub(crate) fn get_block_id_at_unchecked(&self, (i, j): (usize, usize)) -> usize {
+        self.block_ids[i][j]
+    }
+
+    /// Returns the number of blocks, including white and black ones.
+    pub fn num_blocks(&self) -> usize {
+        self.blocks.len()
+    }
+
+    /// Returns the block whose ID is `id`, or `None` if there is no such block.
+    pub fn get_block(&self, id: usize) -> Option<&Block> {
+        self.blocks.get(id)
+    }
+
+    /// Returns the codels of the block whose ID is `id` in reading order (row-major).
+    /// The list is empty if there is no such block.
+    pub fn get_block_codels(&self, id: usize) -> Vec<(usize, usize)> {
+        self.blocks
+            .get(id)
+            .map(|block| block.codels().collect())
+            .unwrap_or_default()
+    }
+
+    /// Returns the size of the block to which the codel at `(i, j)` belongs.
+    /// An error is returned if the position is out of bounds.
+    pub fn get_block_size_at(&self, pos: (usize, usize)) -> Result<usize, PietError> {
+        self.check_bounds(pos)?;
+        Ok(self.get_block_size_at_unchecked(pos))
+    }
+
+    /// Same as [`Self::get_block_size_at()`] but panics if the position is out of bounds.
+    pub(crate) fn get_block_size_at_unchecked(&self, (i, j): (usize, usize)) -> usize {
+        self.blocks[self.block_ids[i][j]].size
     }
 
     /// Returns the index of the "next" codel as you traverse the blocks of the input image.
     /// See these links for more details:
     /// - <https://www.dangermouse.net/esoteric/piet.html> ("Program Execution" section)
     /// - <https://raw.githubusercontent.com/your-diary/piet_programming_language/refs/heads/master/readme_assets/spec.png>
+    ///
+    /// An error is returned if `(i, j)` is out of bounds.
     pub fn get_next_codel_index(
+        &self,
+        pos: (usize, usize),
+        dp: &DP,
+        cc: &CC,
+    ) -> Result<Option<(usize, usize)>, PietError> {
+        self.check_bounds(pos)?;
+        Ok(self.get_next_codel_index_unchecked(pos, dp, cc))
+    }
+
+    /// Same as [`Self::get_next_codel_index()`] but panics if the position is out of bounds.
+    pub(crate) fn get_next_codel_index_unchecked(
         &self,
         (i, j): (usize, usize),
         dp: &DP,
         cc: &CC,
     ) -> Option<(usize, usize)> {
-        let corner = self.block_map[i][j].get_corner_index(dp, cc);
-        self.get_next_codel_index_in_dp_direction(corner, dp)
+        let corner = self.blocks[self.block_ids[i][j]].get_corner_index(dp, cc);
+        self.get_next_codel_index_in_dp_direction_unchecked(corner, dp)
     }
 
     /// Returns the index of the codel when you move straight one step in the direction of `dp`.
-    /// `None` is returned iff the next codel is out of bounds.
+    /// `Ok(None)` is returned iff the next codel is out of bounds, and an error is returned if `(i, j)` itself is out of bounds.
     ///
     /// Related [spec](https://www.dangermouse.net/esoteric/piet.html):
     ///
@@ -295,21 +1538,761 @@ impl Image {
     /// > It does not use the procedure described above for determining where the interpreter emerges from non-white coloured blocks.
     pub fn get_next_codel_index_in_dp_direction(
         &self,
-        (i, j): (usize, usize),
+        pos: (usize, usize),
+        dp: &DP,
+    ) -> Result<Option<(usize, usize)>, PietError> {
+        self.check_bounds(pos)?;
+        Ok(self.get_next_codel_index_in_dp_direction_unchecked(pos, dp))
+    }
+
+    /// Same as [`Self::get_next_codel_index_in_dp_direction()`] but doesn't validate `(i, j)` itself.
+    /// On [`Topology::Bounded`] the arithmetic is still checked, so this never wraps around.
+    pub(crate) fn get_next_codel_index_in_dp_direction_unchecked(
+        &self,
+        pos: (usize, usize),
         dp: &DP,
     ) -> Option<(usize, usize)> {
-        let (delta_i, delta_j) = dp.get_displacement();
-        let next = (i as isize + delta_i, j as isize + delta_j);
-        if (0 <= next.0)
-            && (next.0 < self.height as isize)
-            && (0 <= next.1)
-            && (next.1 < self.width as isize)
-        {
-            Some((next.0 as usize, next.1 as usize))
-        } else {
-            None
+        let delta = dp.get_displacement();
+        match self.topology {
+            Topology::Bounded => coord::offset(pos, delta, self.height, self.width),
+            Topology::Torus => coord::offset_wrapping(pos, delta, self.height, self.width),
         }
     }
 }
 
 /*-------------------------------------*/
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pixel_map(height: usize, width: usize, f: impl Fn(usize, usize) -> Pixel) -> PixelGrid {
+        let data = (0..height)
+            .cartesian_product(0..width)
+            .flat_map(|(i, j)| {
+                let p = f(i, j);
+                [p.r, p.g, p.b]
+            })
+            .collect();
+        PixelGrid {
+            height,
+            width,
+            channels: 3,
+            data,
+        }
+    }
+
+    #[test]
+    fn test_detect_codel_size() {
+        let white = Pixel::new(255, 255, 255);
+        let red = Pixel::new(255, 0, 0);
+
+        //uniform image: the GCD itself is chosen
+        let m = pixel_map(12, 8, |_, _| white);
+        assert_eq!(Ok(4), Image::detect_codel_size(&m, None));
+
+        //checkerboard of 2x2 cells on a 12x8 image: 4 is a common divisor but doesn't tile
+        let m = pixel_map(
+            12,
+            8,
+            |i, j| if (i / 2 + j / 2) % 2 == 0 { white } else { red },
+        );
+        assert_eq!(Ok(2), Image::detect_codel_size(&m, None));
+
+        //coprime dimensions
+        let m = pixel_map(7, 5, |_, _| white);
+        assert_eq!(Ok(1), Image::detect_codel_size(&m, None));
+    }
+
+    #[test]
+    fn test_detect_budget() {
+        let white = Pixel::new(255, 255, 255);
+        let red = Pixel::new(255, 0, 0);
+        //checkerboard of 4x4 cells on a 720x720 image (720 has 30 divisors), except for a pixel in the last row which makes the codel size 1
+        let m = pixel_map(720, 720, |i, j| {
+            if ((i / 4 + j / 4) % 2 == 0) || ((i, j) == (719, 1)) {
+                white
+            } else {
+                red
+            }
+        });
+        assert_eq!(Ok(1), Image::detect_codel_size(&m, None));
+        //only the first row is checked before the deadline
+        let timed_out = PietError::DetectionTimedOut {
+            partial: Some(GridSpec::square(4)),
+        };
+        assert_eq!(
+            Err(timed_out.clone()),
+            Image::detect_codel_size(&m, Some(Instant::now()))
+        );
+
+        let detect = |budget: Option<Duration>, fallback: DetectFallback| {
+            let mut warnings = vec![];
+            let grid = Image::detect_grid(&m, None, false, budget, fallback, &mut warnings);
+            (grid, warnings)
+        };
+        assert_eq!(
+            (Ok(GridSpec::square(1)), vec![]),
+            detect(None, DetectFallback::Error)
+        );
+        assert_eq!(
+            (Ok(GridSpec::square(1)), vec![]),
+            detect(Some(Duration::from_secs(3600)), DetectFallback::Error)
+        );
+        let warning = |grid: GridSpec| Warning::DetectionTimedOut {
+            budget: Duration::ZERO,
+            grid,
+        };
+        assert_eq!(
+            (Ok(GridSpec::square(4)), vec![warning(GridSpec::square(4))]),
+            detect(Some(Duration::ZERO), DetectFallback::Partial)
+        );
+        assert_eq!(
+            (Ok(GridSpec::square(1)), vec![warning(GridSpec::square(1))]),
+            detect(Some(Duration::ZERO), DetectFallback::One)
+        );
+        assert_eq!(
+            (Err(timed_out), vec![]),
+            detect(Some(Duration::ZERO), DetectFallback::Error)
+        );
+    }
+
+    #[test]
+    fn test_crop_alignment() {
+        let white = Pixel::new(255, 255, 255);
+        let red = Pixel::new(255, 0, 0);
+        //checkerboard of 4x4 codels
+        let whole = pixel_map(
+            24,
+            24,
+            |i, j| {
+                if (i / 4 + j / 4) % 2 == 0 {
+                    white
+                } else {
+                    red
+                }
+            },
+        );
+        let region = |i: usize, j: usize, height: usize, width: usize| Region {
+            pos: (i, j),
+            height,
+            width,
+        };
+
+        //aligned
+        let crop = region(4, 8, 12, 8);
+        let pixels = whole.crop(&crop);
+        assert_eq!(Ok(4), Image::detect_codel_size(&pixels, None));
+        assert_eq!(4, Image::interior_run_gcd(&pixels));
+        assert_eq!(Ok(()), Image::check_crop_alignment(&whole, &crop, 4));
+
+        //off by 2 pixels vertically and 1 pixel horizontally: the codels at the edges are cut
+        let crop = region(6, 9, 12, 8);
+        let pixels = whole.crop(&crop);
+        assert_eq!(Ok(1), Image::detect_codel_size(&pixels, None));
+        assert_eq!(4, Image::interior_run_gcd(&pixels));
+        assert_eq!(
+            Err(PietError::MisalignedCrop {
+                crop,
+                codel_size: 4,
+                suggested: region(4, 8, 12, 8),
+            }),
+            Image::check_crop_alignment(&whole, &crop, 4)
+        );
+
+        //the offsets are bounded by the top-left corner of the image, and another size is never aligned
+        let crop = region(1, 1, 12, 8);
+        assert_eq!(
+            Err(PietError::MisalignedCrop {
+                crop,
+                codel_size: 4,
+                suggested: region(0, 0, 12, 8),
+            }),
+            Image::check_crop_alignment(&whole, &crop, 4)
+        );
+        assert_eq!(Ok(()), Image::check_crop_alignment(&whole, &crop, 3));
+
+        //no run is inside of a single codel
+        assert_eq!(0, Image::interior_run_gcd(&whole.crop(&region(1, 1, 2, 2))));
+    }
+
+    #[test]
+    fn test_grid_spec() {
+        let grid = GridSpec {
+            cell_width: 4,
+            cell_height: 5,
+            offset_x: 1,
+            offset_y: 2,
+        };
+        assert_eq!(Ok(grid), "4x5+1+2".parse());
+        assert_eq!("4x5+1+2", grid.to_string());
+        assert_eq!(Ok(GridSpec::square(3)), "3x3".parse());
+        for s in ["4x5+1", "4x5+1+2+3", "4+1+2", "4x0", "x5", "4x5+-1+2"] {
+            assert!(s.parse::<GridSpec>().is_err(), "{}", s);
+        }
+
+        //2 rows of codels in 2 + 12 pixels, and 3 columns in 1 + 14 pixels
+        assert_eq!((2, 3), grid.num_codels(14, 15));
+        assert_eq!([2, 2, 1, 2], grid.margins(14, 15));
+        assert_eq!((0, 0), grid.num_codels(1, 0));
+        assert_eq!([1, 0, 0, 0], grid.margins(1, 0));
+
+        //extreme values from the command line cover no codel rather than overflow
+        let grid: GridSpec = format!("{}x1+{}+0", usize::MAX, usize::MAX)
+            .parse()
+            .unwrap();
+        assert_eq!((14, 0), grid.num_codels(14, 15));
+        assert_eq!([0, 0, 15, 0], grid.margins(14, 15));
+    }
+
+    #[test]
+    fn test_grid() {
+        let white = Pixel::new(255, 255, 255);
+        let red = Pixel::new(255, 0, 0);
+        let grey = Pixel::new(128, 128, 128);
+        //checkerboard of 3x2 codels with a border of 1 pixel
+        let m = pixel_map(14, 10, |i, j| {
+            if (i == 0) || (j == 0) || (i == 13) || (j == 9) {
+                grey
+            } else if ((i + 5) / 3 + (j + 3) / 2) % 2 == 0 {
+                white
+            } else {
+                red
+            }
+        });
+        let grid = GridSpec {
+            cell_width: 2,
+            cell_height: 3,
+            offset_x: 1,
+            offset_y: 1,
+        };
+        assert!(Image::is_grid_uniform(&m, &grid));
+        assert!(!Image::is_grid_uniform(
+            &m,
+            &GridSpec {
+                offset_x: 0,
+                ..grid
+            }
+        ));
+        assert_eq!(Ok(1), Image::detect_codel_size(&m, None));
+        //only square codels are detected
+        assert_eq!(Ok(None), Image::detect_grid_offset(&m, None));
+
+        let m = pixel_map(14, 14, |i, j| {
+            if (i == 0) || (j == 0) || (i == 13) {
+                grey
+            } else if ((i + 7) / 4 + (j + 7) / 4) % 2 == 0 {
+                white
+            } else {
+                red
+            }
+        });
+        assert_eq!(
+            Ok(Some(GridSpec {
+                cell_width: 4,
+                cell_height: 4,
+                offset_x: 1,
+                offset_y: 1,
+            })),
+            Image::detect_grid_offset(&m, None)
+        );
+        assert_eq!(
+            Err(PietError::DetectionTimedOut { partial: None }),
+            Image::detect_grid_offset(&m, Some(Instant::now()))
+        );
+    }
+
+    #[test]
+    fn test_codel_size_zero() {
+        let m = pixel_map(2, 2, |_, _| Pixel::new(255, 255, 255));
+        assert!(!Image::check_if_codel_size_is_valid(&m, 0));
+        for strict_codel_check in [false, true] {
+            assert!(Image::new(
+                "./tests/test_images/original___no_output.png",
+                Some(0),
+                None,
+                strict_codel_check
+            )
+            .is_err());
+        }
+    }
+
+    #[test]
+    fn test_lossy_image() {
+        let load = |tolerance| {
+            Image::new_with_limits(
+                "./tests/test_images/original___hello_2x.jpg",
+                CodelLayout::Detect {
+                    search_offset: false,
+                    budget: None,
+                    fallback: DetectFallback::default(),
+                },
+                ColorMatching {
+                    default_color: None,
+                    tolerance,
+                },
+                false,
+                &ImageLimits::default(),
+                None,
+                &mut vec![],
+            )
+        };
+        assert!(matches!(
+            load(0).err().unwrap().downcast_ref(),
+            Some(PietError::LossyImage {
+                num_unknown: 2918,
+                num_codels: 3312,
+                tolerance: 0,
+            })
+        ));
+        //the colors are read within the tolerance before the codel size is detected
+        let img = load(31).unwrap();
+        let png = Image::new(
+            "./tests/test_images/original___hello_2x.png",
+            None,
+            None,
+            false,
+        )
+        .unwrap();
+        assert_eq!(png.to_parts(), img.to_parts());
+    }
+
+    #[test]
+    fn test_block_ids() {
+        use Codel::*;
+        let grid = vec![
+            vec![Black, Red, Red, White],
+            vec![Red, Red, Blue, Red],
+            vec![Blue, Blue, Blue, Red],
+        ];
+        let img = Image::from_codel_grid(grid.clone());
+        #[rustfmt::skip]
+        let expected = [
+            [0, 1, 1, 2],
+            [1, 1, 3, 4],
+            [3, 3, 3, 4],
+        ];
+        assert_eq!(5, img.num_blocks());
+        for (i, row) in expected.iter().enumerate() {
+            for (j, id) in row.iter().enumerate() {
+                assert_eq!(Ok(*id), img.get_block_id_at((i, j)));
+                assert_eq!(img.get_block(*id), img.get_block_at((i, j)).ok());
+            }
+        }
+        assert!(img.get_block_id_at((3, 0)).is_err());
+        assert_eq!(None, img.get_block(5));
+
+        assert_eq!(
+            vec![(0, 1), (0, 2), (1, 0), (1, 1)],
+            img.get_block_codels(1)
+        );
+        assert_eq!(
+            vec![(1, 2), (2, 0), (2, 1), (2, 2)],
+            img.get_block_codels(3)
+        );
+        assert!(img.get_block_codels(5).is_empty());
+
+        //identical across constructions
+        for _ in 0..10 {
+            let other = Image::from_codel_grid(grid.clone());
+            for id in 0..img.num_blocks() {
+                assert_eq!(img.get_block(id), other.get_block(id));
+                assert_eq!(
+                    img.get_block(id).unwrap().corners(),
+                    other.get_block(id).unwrap().corners()
+                );
+            }
+            assert_eq!(img.to_parts().2, other.to_parts().2);
+        }
+    }
+
+    /// Returns the lines of the ASCII art with each codel replaced with `#`.
+    fn ascii_art(img: &Image) -> Vec<String> {
+        let codel = Codel::Red.to_string();
+        img.to_string()
+            .replace(&codel, "##")
+            .lines()
+            .map(|l| l.to_string())
+            .collect()
+    }
+
+    #[test]
+    fn test_display() {
+        let img = Image::from_codel_grid(vec![vec![Codel::Red; 3]; 2]);
+        assert_eq!(
+            vec!["   0     ", "   0 1 2", " 0 ######", " 1 ######"],
+            ascii_art(&img)
+        );
+
+        //single row with three-digit column numbers
+        let img = Image::from_codel_grid(vec![vec![Codel::Red; 1001]]);
+        let lines = ascii_art(&img);
+        assert_eq!(3, lines.len());
+        assert_eq!(3 + 2 * 1000 + 3, lines[0].len());
+        assert_eq!(2 + 2 * 1001, lines[1].len());
+        assert_eq!(3 + 2 * 1001, lines[2].len());
+        assert_eq!(" 0 ", &lines[2][..3]);
+        //each label starts right above its column, whose codel starts at `3 + 2 * j`
+        assert_eq!("99 ", &lines[0][3 + 2 * 990..3 + 2 * 990 + 3]);
+        assert_eq!("100", &lines[0][3 + 2 * 1000..]);
+        assert_eq!(" 0", &lines[1][2 + 2 * 1000..]);
+
+        //single column with three-digit row numbers
+        let img = Image::from_codel_grid(vec![vec![Codel::Red]; 101]);
+        let lines = ascii_art(&img);
+        assert_eq!(2 + 101, lines.len());
+        assert_eq!(vec!["    0 ", "    0"], lines[..2]);
+        assert_eq!("  0 ##", lines[2]);
+        assert_eq!("100 ##", lines[102]);
+    }
+
+    #[test]
+    fn test_scale() {
+        let img = Image::from_codel_grid(vec![
+            vec![Codel::Red, Codel::Blue],
+            vec![Codel::Green, Codel::Red],
+        ]);
+        let scaled = img.scale(2);
+        assert_eq!(4, scaled.height());
+        assert_eq!(4, scaled.width());
+        for i in 0..2 {
+            for j in 0..2 {
+                let original = img.get_codel_at((i, j)).unwrap();
+                let copies = (0..2)
+                    .cartesian_product(0..2)
+                    .filter(|(di, dj)| {
+                        scaled.get_codel_at((i * 2 + di, j * 2 + dj)).unwrap() == original
+                    })
+                    .count();
+                assert_eq!(4, copies);
+            }
+        }
+
+        //each original block becomes a block 4 times as large
+        assert_eq!(Ok(1), img.get_block_size_at((0, 0)));
+        assert_eq!(Ok(4), scaled.get_block_size_at((0, 0)));
+        assert_eq!(Ok(4), scaled.get_block_size_at((3, 3)));
+
+        assert_eq!(img.height(), img.scale(1).height());
+    }
+
+    #[test]
+    fn test_crop() {
+        let (r, b, g) = (Codel::Red, Codel::Blue, Codel::Green);
+        let img = Image::from_codel_grid(vec![vec![r, r, b], vec![g, r, b], vec![g, g, b]]);
+        let cropped = img.crop((1, 1), 2, 2);
+        assert_eq!(2, cropped.height());
+        assert_eq!(2, cropped.width());
+        assert_eq!(Ok(&r), cropped.get_codel_at((0, 0)));
+        assert_eq!(Ok(&g), cropped.get_codel_at((1, 0)));
+        //the blocks are those of the region
+        assert_eq!(Ok(1), cropped.get_block_size_at((0, 0)));
+        assert_eq!(Ok(2), cropped.get_block_size_at((0, 1)));
+        assert_eq!(img.width(), img.crop((0, 0), 3, 3).width());
+    }
+
+    #[test]
+    fn test_out_of_bounds() {
+        let img = Image::from_codel_grid(vec![
+            vec![Codel::Red, Codel::Blue, Codel::Green],
+            vec![Codel::Green, Codel::Red, Codel::White],
+        ]);
+        let error = |pos| PietError::PositionOutOfBounds {
+            pos,
+            image_height: 2,
+            image_width: 3,
+        };
+        for pos in [(2, 0), (0, 3), (2, 3), (usize::MAX, 0), (0, usize::MAX)] {
+            assert!(!img.contains(pos));
+            assert_eq!(Err(error(pos)), img.get_codel_at(pos));
+            assert!(img.get_block_at(pos).is_err());
+            assert_eq!(Err(error(pos)), img.get_block_size_at(pos));
+            for dp in [DP::Right, DP::Down, DP::Left, DP::Up] {
+                assert_eq!(
+                    Err(error(pos)),
+                    img.get_next_codel_index_in_dp_direction(pos, &dp)
+                );
+                for cc in [CC::Left, CC::Right] {
+                    assert_eq!(Err(error(pos)), img.get_next_codel_index(pos, &dp, &cc));
+                }
+            }
+        }
+
+        assert_eq!(Ok(&Codel::White), img.get_codel_at((1, 2)));
+        assert_eq!(
+            Ok(None),
+            img.get_next_codel_index_in_dp_direction((0, 0), &DP::Up)
+        );
+        assert_eq!(
+            Ok(None),
+            img.get_next_codel_index_in_dp_direction((1, 2), &DP::Right)
+        );
+        assert_eq!(
+            Ok(Some((1, 2))),
+            img.get_next_codel_index_in_dp_direction((0, 2), &DP::Down)
+        );
+
+        //the unchecked variant never wraps around even for absurd positions
+        assert_eq!(
+            None,
+            img.get_next_codel_index_in_dp_direction_unchecked((usize::MAX, 0), &DP::Down)
+        );
+        assert_eq!(
+            None,
+            img.get_next_codel_index_in_dp_direction_unchecked((0, usize::MAX), &DP::Right)
+        );
+    }
+
+    #[test]
+    fn test_torus() {
+        let mut img = Image::from_codel_grid(vec![
+            vec![Codel::Red, Codel::Blue, Codel::Green],
+            vec![Codel::Green, Codel::Red, Codel::White],
+        ]);
+        assert_eq!(Topology::Bounded, img.topology());
+        img.set_topology(Topology::Torus);
+        for (pos, dp, expected) in [
+            ((0, 0), DP::Up, (1, 0)),
+            ((0, 0), DP::Left, (0, 2)),
+            ((1, 2), DP::Right, (1, 0)),
+            ((1, 2), DP::Down, (0, 2)),
+            ((0, 1), DP::Down, (1, 1)),
+        ] {
+            assert_eq!(
+                Ok(Some(expected)),
+                img.get_next_codel_index_in_dp_direction(pos, &dp)
+            );
+        }
+        //the corner is chosen as usual, and then the move wraps around
+        assert_eq!(
+            Ok(Some((1, 0))),
+            img.get_next_codel_index((0, 0), &DP::Up, &CC::Left)
+        );
+        assert_eq!(
+            None,
+            img.get_next_codel_index_in_dp_direction_unchecked((2, 0), &DP::Down)
+        );
+    }
+
+    #[test]
+    fn test_first_non_black_codel_index() {
+        let img = Image::from_codel_grid(vec![
+            vec![Codel::Black, Codel::Black, Codel::Black],
+            vec![Codel::Black, Codel::Black, Codel::White],
+            vec![Codel::Red, Codel::Black, Codel::Black],
+        ]);
+        assert_eq!(Some((1, 2)), img.get_first_non_black_codel_index());
+
+        let img = Image::from_codel_grid(vec![vec![Codel::Black; 2]; 2]);
+        assert_eq!(None, img.get_first_non_black_codel_index());
+    }
+
+    #[test]
+    fn test_from_codels() {
+        let (r, b, w, k) = (Codel::Red, Codel::Blue, Codel::White, Codel::Black);
+        let m = vec![vec![r, r, b, w], vec![k, r, b, b], vec![r, r, w, b]];
+        let expected = Image::from_codel_grid(m.clone());
+
+        let flat = m.iter().flatten().copied().collect_vec();
+        let grid = CodelSlice::new(&flat, 4);
+        assert_eq!((3, 4), (grid.height(), grid.width()));
+        assert_eq!(k, grid.codel_at((1, 0)));
+        for img in [
+            Image::from_codels(&grid),
+            Image::from_codels(m.as_slice()),
+            Image::from_codels(&expected),
+        ] {
+            assert_eq!(expected.to_parts(), img.to_parts());
+        }
+        //a single row
+        assert_eq!(
+            Image::from_codel_grid(vec![flat.clone()]).to_parts(),
+            Image::from_codels(&CodelSlice::new(&flat, 12)).to_parts()
+        );
+    }
+
+    /// Asserts that the fractions reported for each phase in `events` never decrease and end at 1.0, and returns the phases in the order they were first reported.
+    fn check_load_progress(events: &[(LoadPhase, f64)]) -> Vec<LoadPhase> {
+        let mut phases: Vec<LoadPhase> = vec![];
+        for &(phase, fraction) in events {
+            if !phases.contains(&phase) {
+                phases.push(phase);
+            }
+            assert!((0.0..=1.0).contains(&fraction), "{} {}", phase, fraction);
+        }
+        for &phase in &phases {
+            let fractions = events
+                .iter()
+                .filter(|(p, _)| *p == phase)
+                .map(|(_, f)| *f)
+                .collect_vec();
+            assert!(
+                fractions.windows(2).all(|w| w[0] <= w[1]),
+                "{}: {:?}",
+                phase,
+                fractions
+            );
+            assert_eq!(Some(&1.0), fractions.last(), "{}", phase);
+        }
+        phases
+    }
+
+    #[test]
+    fn test_from_bytes() {
+        use crate::args::Args;
+        use crate::interpreter::Interpreter;
+        use crate::program::Program;
+        use crate::report::IoLog;
+        use clap::Parser;
+
+        const HELLO: &[u8] = include_bytes!("../tests/test_images/original___hello_2x.png");
+        let img = Image::from_bytes(HELLO, None, None).unwrap();
+        let expected = Image::new(
+            "./tests/test_images/original___hello_2x.png",
+            None,
+            None,
+            false,
+        )
+        .unwrap();
+        assert_eq!(expected.to_parts(), img.to_parts());
+        let log = IoLog::default();
+        let mut ip = Interpreter::new_with_writer(log.clone());
+        let args = Args::parse_from(["piet_programming_language", ""]);
+        crate::run_image_with(&args, &Program::new(img), &mut ip).unwrap();
+        assert_eq!("Hello, world!\n", log.contents());
+
+        //from the current position of a reader, with a codel size and a fallback color
+        let mut reader = Cursor::new([b"garbage".as_slice(), HELLO].concat());
+        reader.set_position(7);
+        let img = Image::from_reader(reader, Some(2), Some(Codel::White)).unwrap();
+        assert_eq!(expected.to_parts(), img.to_parts());
+        //the format is told from the contents rather than the extension
+        let file =
+            std::env::temp_dir().join(format!("piet_test_from_bytes_{}.jpg", std::process::id()));
+        std::fs::write(&file, HELLO).unwrap();
+        let img = Image::new(&file, None, None, false);
+        std::fs::remove_file(&file).unwrap();
+        assert_eq!(expected.to_parts(), img.unwrap().to_parts());
+
+        //the same errors as from a file
+        let error_of_file = |bytes: &[u8], extension: &str| {
+            let file = std::env::temp_dir().join(format!(
+                "piet_test_from_bytes_{}.{}",
+                std::process::id(),
+                extension
+            ));
+            std::fs::write(&file, bytes).unwrap();
+            let e = Image::new(&file, None, None, false).err().unwrap();
+            std::fs::remove_file(&file).unwrap();
+            e.to_string()
+        };
+        let not_an_image = b"not an image";
+        assert_eq!(
+            error_of_file(not_an_image, "txt"),
+            Image::from_bytes(not_an_image, None, None)
+                .err()
+                .unwrap()
+                .to_string()
+        );
+        let mut gray = vec![];
+        image::GrayImage::from_pixel(2, 2, image::Luma([0]))
+            .write_to(&mut Cursor::new(&mut gray), ImageFormat::Png)
+            .unwrap();
+        let e = Image::from_bytes(&gray, None, None).err().unwrap();
+        assert_eq!("unsupported file format", e.to_string());
+        assert_eq!(error_of_file(&gray, "png"), e.to_string());
+    }
+
+    #[test]
+    fn test_load_progress() {
+        let all_phases = vec![
+            LoadPhase::Decode,
+            LoadPhase::Sample,
+            LoadPhase::Label,
+            LoadPhase::Corners,
+        ];
+        let load = |file: &Path, layout| {
+            let mut events = vec![];
+            let mut record = |phase, fraction| events.push((phase, fraction));
+            let img = Image::new_observed(
+                file,
+                layout,
+                ColorMatching::default(),
+                false,
+                &ImageLimits::default(),
+                None,
+                None,
+                &mut vec![],
+                Some(&mut record),
+            )
+            .unwrap();
+            (img, events)
+        };
+
+        //tiny
+        let (img, events) = load(
+            Path::new("./tests/test_images/original___hi_4x5_codels.png"),
+            CodelLayout::Detect {
+                search_offset: false,
+                budget: None,
+                fallback: DetectFallback::default(),
+            },
+        );
+        assert_eq!(all_phases, check_load_progress(&events));
+        let expected = Image::new(
+            "./tests/test_images/original___hi_4x5_codels.png",
+            None,
+            None,
+            false,
+        )
+        .unwrap();
+        assert_eq!(expected.to_parts(), img.to_parts());
+
+        //synthetically large, with many blocks
+        let (height, width) = (600, 500);
+        let file = std::env::temp_dir().join(format!(
+            "piet_test_load_progress_{}.png",
+            std::process::id()
+        ));
+        image::RgbImage::from_fn(width, height, |x, y| match (x / 7 + y / 3) % 3 {
+            0 => image::Rgb([255, 0, 0]),
+            1 => image::Rgb([0, 255, 0]),
+            _ => image::Rgb([0, 0, 255]),
+        })
+        .save(&file)
+        .unwrap();
+        let (img, events) = load(&file, CodelLayout::Size(1));
+        std::fs::remove_file(&file).unwrap();
+        assert_eq!((600, 500), (img.height(), img.width()));
+        assert_eq!(all_phases, check_load_progress(&events));
+        //reported in chunks rather than per codel or row
+        for phase in [LoadPhase::Sample, LoadPhase::Label, LoadPhase::Corners] {
+            let n = events.iter().filter(|(p, _)| *p == phase).count();
+            assert!((10..=102).contains(&n), "{} {}", phase, n);
+        }
+
+        //from a grid of codels, which is not decoded nor sampled
+        let mut events = vec![];
+        let mut record = |phase, fraction| events.push((phase, fraction));
+        let m = vec![vec![Codel::Red, Codel::Blue]; 1000];
+        let img = Image::from_codel_grid_observed(m.clone(), Some(&mut record));
+        assert_eq!(
+            vec![LoadPhase::Label, LoadPhase::Corners],
+            check_load_progress(&events)
+        );
+        assert_eq!(Image::from_codel_grid(m).to_parts(), img.to_parts());
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_codel_slice_ragged() {
+        CodelSlice::new(&[Codel::Red; 5], 2);
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_from_codels_ragged() {
+        let m = vec![vec![Codel::Red; 2], vec![Codel::Red; 3]];
+        Image::from_codels(m.as_slice());
+    }
+}
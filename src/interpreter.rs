@@ -1,9 +1,21 @@
 use std::fmt::{self, Display, Formatter};
+use std::fs::File;
 use std::io::{self, Write};
+use std::time::Instant;
+
+use rustc_hash::FxHashSet;
 
 use super::cc::CC;
 use super::dp::DP;
+use super::error::PietError;
+use super::expect::ExpectStream;
+use super::extension::ExecConfig;
+use super::multiplexer::Multiplexer;
+use super::program::{Fingerprint, Program};
+use super::push_operands::PushOperands;
+use super::state_digest::StateDigest;
 use super::stdin::Stdin;
+use super::trace::IoEvent;
 
 pub struct Interpreter {
     pub cur: (usize, usize),
@@ -11,6 +23,37 @@ pub struct Interpreter {
     pub dp: DP,
     pub cc: CC,
     pub stdin: Stdin,
+    /// Enabled extensions.
+    pub config: ExecConfig,
+    /// Second stack of [`Extension::SecondStack`](super::extension::Extension::SecondStack).
+    pub second_stack: Vec<isize>,
+
+    /// Number of iterations (moves from a codel) so far.
+    pub num_steps: usize,
+    /// Number of commands executed so far (including those ignored as specified, e.g. `pop` on an empty stack).
+    pub num_commands: usize,
+    /// Number of bytes written to stdout by the program so far.
+    pub num_output_bytes: usize,
+    /// When the program last wrote output or consumed input (used by `--stall-timeout`).
+    pub last_io: Instant,
+    /// Destination of `--io-trace`.
+    pub io_trace: Option<Box<dyn Write + Send>>,
+    /// Destination of the output of the program (`--output`), or `None` for stdout.
+    pub output_file: Option<File>,
+    /// Destination of the output of the program instead of stdout, e.g. a buffer of an embedding program (see [`Runner`](super::runner::Runner)).
+    pub output_writer: Option<Box<dyn Write + Send>>,
+    /// Destination of both the output on stdout and the path trace when they are synchronized (`--sync-output`).
+    pub terminal: Option<Multiplexer>,
+    /// Everything written to stdout so far, kept only if `Some` (used by `--assertions`).
+    pub output_log: Option<Vec<u8>>,
+    /// Compares the output with an expected output as it is written if `Some` (`--expect-streaming`).
+    pub expect_stream: Option<ExpectStream>,
+    /// Program which the state refers to, set by [`Self::bind()`].
+    program: Option<Fingerprint>,
+    /// Codels visited with each DP in the current slide across white, kept here so that a slide can be suspended in the middle (see [`Self::run_for()`]).
+    pub(crate) slide_visited: FxHashSet<((usize, usize), DP)>,
+    /// Operands of `Push` so far, recorded only with `--check-runtime`.
+    pub(crate) push_operands: PushOperands,
 
     #[cfg(test)]
     pub output_buf: Vec<u8>,
@@ -42,6 +85,22 @@ impl Interpreter {
             dp: DP::default(),
             cc: CC::default(),
             stdin: Stdin::new(),
+            config: ExecConfig::default(),
+            second_stack: vec![],
+
+            num_steps: 0,
+            num_commands: 0,
+            num_output_bytes: 0,
+            last_io: Instant::now(),
+            io_trace: None,
+            output_file: None,
+            output_writer: None,
+            terminal: None,
+            output_log: None,
+            expect_stream: None,
+            program: None,
+            slide_visited: FxHashSet::default(),
+            push_operands: PushOperands::default(),
 
             #[cfg(test)]
             output_buf: vec![],
@@ -56,19 +115,237 @@ impl Interpreter {
             dp: DP::Right,
             cc: CC::Left,
             stdin: Stdin::new_with_string(s),
+            config: ExecConfig::default(),
+            second_stack: vec![],
+
+            num_steps: 0,
+            num_commands: 0,
+            num_output_bytes: 0,
+            last_io: Instant::now(),
+            io_trace: None,
+            output_file: None,
+            output_writer: None,
+            terminal: None,
+            output_log: None,
+            expect_stream: None,
+            program: None,
+            slide_visited: FxHashSet::default(),
+            push_operands: PushOperands::default(),
 
             #[cfg(test)]
             output_buf: vec![],
         }
     }
 
-    pub fn output(&mut self, s: &str) {
-        io::stdout().write_all(s.as_bytes()).unwrap();
-        io::stdout().flush().unwrap();
+    /// Same as [`Self::new()`] but the output of the program is written to `writer` instead of stdout (see [`Self::output_writer`]).
+    /// To collect the output in memory, give a writer which shares its buffer (e.g. [`IoLog`](super::report::IoLog)), or enable [`Self::output_log`] instead.
+    pub fn new_with_writer(writer: impl Write + Send + 'static) -> Self {
+        let mut ip = Self::new();
+        ip.output_writer = Some(Box::new(writer));
+        ip
+    }
+
+    /// Binds the interpreter to `program` if it is not bound yet, and otherwise checks that it is bound to a program with the same content.
+    /// This is called before execution so that a state (e.g. a position) is never interpreted in another program.
+    pub fn bind(&mut self, program: &Program) -> Result<(), PietError> {
+        let found = program.fingerprint();
+        match self.program {
+            None => {
+                self.program = Some(found);
+                Ok(())
+            }
+            Some(expected) if expected == found => Ok(()),
+            Some(expected) => Err(PietError::MismatchedProgram { expected, found }),
+        }
+    }
+
+    /// Prepares the interpreter for a new run of `program` as if it were created by [`Self::new()`], but keeps the capacity of its buffers.
+    ///
+    /// The stacks, the position, DP and CC (and the state of a slide across white), the counters, the configuration, the I/O trace, the output file (and writer), the streaming comparison and the terminal are reset, and the interpreter is bound to `program`.
+    /// The input is the real stdin again (see [`Stdin::reset()`]), and the output log is emptied but kept enabled if it was.
+    pub fn reset_for(&mut self, program: &Program) {
+        self.cur = (0, 0);
+        self.stack.clear();
+        self.dp = DP::default();
+        self.cc = CC::default();
+        self.stdin.reset();
+        self.config = ExecConfig::default();
+        self.second_stack.clear();
+        self.num_steps = 0;
+        self.num_commands = 0;
+        self.num_output_bytes = 0;
+        self.slide_visited.clear();
+        self.push_operands = PushOperands::default();
+        self.last_io = Instant::now();
+        self.io_trace = None;
+        self.output_file = None;
+        self.output_writer = None;
+        self.terminal = None;
+        if let Some(log) = self.output_log.as_mut() {
+            log.clear();
+        }
+        self.expect_stream = None;
+        self.rebind(program);
+
+        #[cfg(test)]
+        self.output_buf.clear();
+    }
+
+    /// Returns the digest of the state, for asserting that two runs are in the same state (see [`StateDigest`]).
+    pub fn digest(&self) -> StateDigest {
+        StateDigest::new(self)
+    }
+
+    /// Binds the interpreter to `program` whichever program it is bound to, for when the state is known to be meaningful in `program` (e.g. after an edit).
+    pub fn rebind(&mut self, program: &Program) {
+        self.program = Some(program.fingerprint());
+    }
+
+    /// Logs `event` with the current step number if `--io-trace` is enabled.
+    pub fn trace_io(&mut self, event: IoEvent) -> io::Result<()> {
+        if let Some(w) = self.io_trace.as_mut() {
+            writeln!(w, "#{} {}", self.num_steps, event)?;
+        }
+        Ok(())
+    }
+
+    /// Prints a line of the path trace to stderr (or to the terminal if it is synchronized).
+    pub fn print_trace(&self, line: &str) -> io::Result<()> {
+        match &self.terminal {
+            Some(terminal) => terminal.trace(line),
+            None => writeln!(io::stderr(), "{}", line),
+        }
+    }
+
+    /// Draws a status line (e.g. of `--progress`) on stderr (or on the terminal if it is synchronized).
+    pub fn print_status(&self, line: &str) -> io::Result<()> {
+        match &self.terminal {
+            Some(terminal) => terminal.status(line),
+            None => write!(io::stderr(), "{}", line),
+        }
+    }
+
+    /// Writes `s` to the output of the program.
+    /// The output is counted (and logged) only once it has been written.
+    pub fn output(&mut self, s: &str) -> io::Result<()> {
+        match (
+            self.output_file.as_mut(),
+            self.output_writer.as_mut(),
+            &self.terminal,
+        ) {
+            (Some(file), _, _) => file.write_all(s.as_bytes())?,
+            (None, Some(w), _) => {
+                w.write_all(s.as_bytes())?;
+                w.flush()?;
+            }
+            (None, None, Some(terminal)) => terminal.output(s)?,
+            (None, None, None) => {
+                let mut stdout = io::stdout().lock();
+                stdout.write_all(s.as_bytes())?;
+                stdout.flush()?;
+            }
+        }
+        self.num_output_bytes += s.len();
+        self.last_io = Instant::now();
+        if let Some(log) = self.output_log.as_mut() {
+            log.extend(s.as_bytes());
+        }
+        if let Some(stream) = self.expect_stream.as_mut() {
+            stream.write(s.as_bytes())?;
+        }
 
         #[cfg(test)]
         {
-            self.output_buf.write_all(s.as_bytes()).unwrap();
+            self.output_buf.write_all(s.as_bytes())?;
         }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use clap::Parser;
+
+    use super::super::args::Args;
+    use super::super::codel::Codel;
+    use super::super::command::Command;
+    use super::super::image::Image;
+    use super::super::report::IoLog;
+    use super::super::run_result::TerminationReason;
+    use super::*;
+
+    #[test]
+    fn test_bind() {
+        let (r, b) = (Codel::Red, Codel::Blue);
+        let program = Program::new(Image::from_codel_grid(vec![vec![r, b]]));
+        let other = Program::new(Image::from_codel_grid(vec![vec![b, r]]));
+
+        let mut ip = Interpreter::new();
+        assert_eq!(Ok(()), ip.bind(&program));
+        assert_eq!(Ok(()), ip.bind(&program));
+        //the same content parsed again
+        assert_eq!(
+            Ok(()),
+            ip.bind(&Program::new(Image::from_codel_grid(vec![vec![r, b]])))
+        );
+        assert_eq!(
+            Err(PietError::MismatchedProgram {
+                expected: program.fingerprint(),
+                found: other.fingerprint(),
+            }),
+            ip.bind(&other)
+        );
+
+        ip.rebind(&other);
+        assert_eq!(Ok(()), ip.bind(&other));
+        assert!(ip.bind(&program).is_err());
+
+        //an edit is a change of the program
+        let mut edited = Program::new(Image::from_codel_grid(vec![vec![r, b]]));
+        let mut ip = Interpreter::new();
+        ip.bind(&edited).unwrap();
+        edited.apply_edit((0, 1), Codel::Green);
+        assert!(ip.bind(&edited).is_err());
+        ip.rebind(&edited);
+        assert_eq!(Ok(()), ip.bind(&edited));
+    }
+
+    #[test]
+    fn test_new_with_writer() {
+        let log = IoLog::default();
+        let mut ip = Interpreter::new_with_writer(log.clone());
+        Command::Push.execute(&mut ip, 72).unwrap();
+        Command::OutChar.execute(&mut ip, 1).unwrap();
+        Command::Push.execute(&mut ip, 105).unwrap();
+        Command::OutChar.execute(&mut ip, 1).unwrap();
+        Command::Push.execute(&mut ip, 42).unwrap();
+        Command::OutNumber.execute(&mut ip, 1).unwrap();
+        //out of range
+        ip.stack.push(-1);
+        Command::OutChar.execute(&mut ip, 1).unwrap();
+        assert_eq!("Hi42\n", log.contents());
+        assert_eq!(5, ip.num_output_bytes);
+        assert_eq!(vec![-1], ip.stack);
+
+        //a multi-byte character
+        Command::Push.execute(&mut ip, 0xe9).unwrap();
+        Command::OutChar.execute(&mut ip, 1).unwrap();
+        assert_eq!("Hi42\né", log.contents());
+        assert_eq!(7, ip.num_output_bytes);
+
+        //a whole run
+        let log = IoLog::default();
+        let mut ip = Interpreter::new_with_writer(log.clone());
+        let img = Image::new(
+            "./tests/test_images/original___hello_2x.png",
+            None,
+            None,
+            false,
+        );
+        let program = Program::new(img.unwrap());
+        let args = Args::parse_from(["piet_programming_language", ""]);
+        let result = crate::run_image_with(&args, &program, &mut ip).unwrap();
+        assert_eq!(TerminationReason::NoExit, result.reason);
+        assert_eq!("Hello, world!\n", log.contents());
     }
 }
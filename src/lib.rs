@@ -1,22 +1,111 @@
+#![forbid(unsafe_code)]
+
 pub mod args;
+pub mod art;
+pub mod assertion;
+pub mod attribution;
 pub mod block;
+pub mod cache;
 pub mod cc;
+pub mod checkpoint;
 pub mod codel;
 pub mod command;
+pub mod compare;
+pub mod conformance;
+mod coord;
+pub mod corner;
 pub mod dp;
+pub mod effective_config;
+pub mod entry;
+pub mod error;
+pub mod examples;
+pub mod exit_status;
+pub mod expect;
+pub mod extension;
+pub mod frames;
+pub mod golf;
 pub mod image;
 pub mod interpreter;
+pub mod limits;
+pub mod lint;
+pub mod metrics;
+pub mod multiplexer;
+pub mod notify;
+pub mod observer;
+pub mod output_analysis;
+pub mod pace;
+pub mod palette_map;
+pub mod pietdev;
+pub mod pool;
+pub mod program;
+pub mod progress;
+pub mod push_operands;
+pub mod quantization;
+pub mod random_input;
+pub mod report;
+pub mod resource;
+pub mod run_result;
+pub mod runner;
+pub mod self_test;
+pub mod semantics;
+pub mod slice;
+mod stable;
+pub mod state_digest;
 pub mod stdin;
+pub mod svg;
+pub mod trace;
+pub mod trace_diff;
+pub mod warning;
+pub mod watch;
 
 use std::error::Error;
+use std::fs::{self, File};
+use std::io::{self, BufWriter, IsTerminal, Read, Seek, SeekFrom, Write};
+use std::mem;
+use std::path::Path;
+use std::time::{Duration, Instant};
 
+use clap::Parser;
 use codel::Codel;
-use rustc_hash::FxHashSet;
 
-use crate::args::Args;
+use crate::args::{
+    Args, CompareArgs, ConformanceArgs, ExportArgs, Format, GolfArgs, InputArgs, LoadArgs,
+    TraceDiffArgs, VersionReportArgs,
+};
+use crate::assertion::Assertions;
+use crate::attribution::{OutputSpan, TraceWindow};
+use crate::checkpoint::{Checkpoint, Checkpointer};
 use crate::command::Command;
-use crate::image::Image;
+use crate::conformance::{Case, Expected};
+use crate::effective_config::{EffectiveConfig, Environment, LoadResolution, PrintConfig};
+use crate::entry::Entry;
+use crate::error::PietError;
+use crate::expect::ExpectStream;
+use crate::golf::GolfScore;
+use crate::image::{ColorMatching, Image};
 use crate::interpreter::Interpreter;
+use crate::limits::CancellationToken;
+use crate::lint::LintOptions;
+use crate::metrics::{Metrics, RunCounts};
+use crate::multiplexer::Multiplexer;
+use crate::notify::{Notifier, TerminalNotifier};
+use crate::observer::{Intervention, LoadObserver, LoadPhase, LoadProgress, Observer};
+use crate::pace::Pacer;
+use crate::palette_map::PaletteMap;
+use crate::program::{Background, Program, RetryOutcome};
+use crate::progress::{LoadStatus, Progress};
+use crate::quantization::Palette;
+use crate::random_input::{RandomInput, RandomSpec};
+use crate::report::{IoLog, Report, Tee};
+use crate::resource::ResourceUsage;
+use crate::run_result::{RunResult, TerminationReason};
+use crate::runner::RunnerConfig;
+use crate::semantics::{SemanticsDescriptor, SemanticsDiff, SemanticsTable};
+use crate::slice::SliceStatus;
+use crate::svg::SvgOptions;
+use crate::trace::TraceEvent;
+use crate::trace_diff::{Slide, Step, TraceDiff};
+use crate::warning::Warning;
 
 /// Prints the given string to stderr if `is_verbose_mode` is `true`.
 fn debug_print(is_verbose_mode: bool, s: &str) {
@@ -25,150 +114,3423 @@ fn debug_print(is_verbose_mode: bool, s: &str) {
     }
 }
 
-/// Runs a Piet program.
+/// Loads the program specified by `args`, cropped to the region of `args.entry` if it is given, with the unknown colors replaced as specified.
+/// The replacement is done after the image is loaded (and cached) so that it is warned about even on a cache hit.
+fn load_image(
+    args: &LoadArgs,
+    is_verbose_mode: bool,
+    warnings: &mut Vec<Warning>,
+) -> Result<Image, Box<dyn Error>> {
+    Ok(load_image_with(args, is_verbose_mode, warnings, None, None)?.0)
+}
+
+/// Same as [`load_image()`] but counts the lookups of the image cache in `metrics` and reports the progress of parsing the image to `observer` if they are given.
+/// What was resolved while loading (e.g. the codel size) is returned along with the program for `--print-config`.
+fn load_image_with(
+    args: &LoadArgs,
+    is_verbose_mode: bool,
+    warnings: &mut Vec<Warning>,
+    metrics: Option<&Metrics>,
+    observer: Option<&mut dyn LoadObserver>,
+) -> Result<(Image, LoadResolution), Box<dyn Error>> {
+    let (img, resolution) = load_entry(args, is_verbose_mode, warnings, metrics, observer)?;
+    if let Some(grid) = args.codel_grid.filter(|_| !is_pietdev(args)) {
+        let (height, width) = match resolution.pixels.or_else(|| pixel_dimensions(args)) {
+            Some(pixels) => pixels,
+            None => Image::pixel_dimensions(&args.image_file)?,
+        };
+        let margins = grid.margins(height, width);
+        if margins.iter().any(|&m| m != 0) {
+            warning::warn(warnings, Warning::MarginsDropped { grid, margins });
+        }
+    }
+    if let Some(palette) = gif_palette(args)? {
+        for w in quantization::palette_collisions(&palette, &img) {
+            warning::warn(warnings, w);
+        }
+    }
+    let img = match &args.palette {
+        Some(file) => {
+            let palette_map = PaletteMap::read(file)?;
+            for w in palette_map.merged_colors(&img) {
+                warning::warn(warnings, w);
+            }
+            palette_map.apply(img).0
+        }
+        None => img,
+    };
+    let Some(color) = args.load_options().fallback else {
+        return Ok((img, resolution));
+    };
+    let (img, num_codels) = img.replace_unknown_colors(color);
+    if num_codels != 0 {
+        warning::warn(
+            warnings,
+            Warning::UnknownColorsReplaced { num_codels, color },
+        );
+    }
+    Ok((img, resolution))
+}
+
+/// Returns the size in pixels of the image (or of the frame of `--frame`) specified by `args`, read from its header.
+/// `None` is returned for a PietDev save or an example, or if the header could not be read.
+fn pixel_dimensions(args: &LoadArgs) -> Option<(usize, usize)> {
+    if is_pietdev(args) {
+        return None;
+    }
+    match args.frame {
+        None => Image::pixel_dimensions(&args.image_file).ok(),
+        Some(frame) => Image::frame_dimensions(&args.image_file, frame).ok(),
+    }
+}
+
+/// Loads the program specified by `args`, cropped to the region of `args.entry` if it is given.
+/// Of the sizes in pixels, only that of `--crop` is resolved, as the size of the whole image is read only when it is shown (see [`pixel_dimensions()`]).
+fn load_entry(
+    args: &LoadArgs,
+    is_verbose_mode: bool,
+    warnings: &mut Vec<Warning>,
+    metrics: Option<&Metrics>,
+    observer: Option<&mut dyn LoadObserver>,
+) -> Result<(Image, LoadResolution), Box<dyn Error>> {
+    let img = load_whole_image(args, is_verbose_mode, warnings, metrics, observer)?;
+    let mut resolution = LoadResolution {
+        pixels: args
+            .crop
+            .filter(|_| !is_pietdev(args))
+            .map(|crop| (crop.height, crop.width)),
+        codels: (img.height(), img.width()),
+        entry: None,
+    };
+    let Some(name) = &args.entry else {
+        return Ok((img, resolution));
+    };
+    let entries = load_entries(args, &img)?;
+    let Some(entry) = entries.iter().find(|e| &e.name == name) else {
+        return Err(format!(
+            "{}: no entry `{}` (entries: {})",
+            args.manifest_path().display(),
+            name,
+            entries
+                .iter()
+                .map(|e| e.name.as_str())
+                .collect::<Vec<_>>()
+                .join(", ")
+        )
+        .into());
+    };
+    let crop = entry.crop;
+    debug_print(
+        is_verbose_mode,
+        &format!(
+            "entry `{}`: {} x {} codels at {:?}",
+            name, crop.height, crop.width, crop.pos
+        ),
+    );
+    resolution.entry = Some((args.manifest_path(), entry.clone()));
+    Ok((img.crop(crop.pos, crop.height, crop.width), resolution))
+}
+
+/// Reads the manifest of `args` and checks its entries against `img`.
+fn load_entries(args: &LoadArgs, img: &Image) -> Result<Vec<Entry>, Box<dyn Error>> {
+    let path = args.manifest_path();
+    let in_manifest = |e: Box<dyn Error>| format!("{}: {}", path.display(), e);
+    let entries = entry::parse(&fs::read_to_string(&path).map_err(|e| in_manifest(e.into()))?)
+        .map_err(in_manifest)?;
+    entry::validate(&entries, img.height(), img.width()).map_err(in_manifest)?;
+    Ok(entries)
+}
+
+/// Prints the entries declared in the manifest (`--list-entries`).
+fn list_entries(args: &LoadArgs) -> Result<(), Box<dyn Error>> {
+    let img = load_whole_image(args, false, &mut vec![], None, None)?;
+    let entries = load_entries(args, &img)?;
+    let width = entries.iter().map(|e| e.name.len()).max().unwrap_or(0);
+    for e in &entries {
+        println!(
+            "{:w$}  crop = [{}, {}, {}, {}]",
+            e.name,
+            e.crop.pos.0,
+            e.crop.pos.1,
+            e.crop.height,
+            e.crop.width,
+            w = width
+        );
+    }
+    if entries.is_empty() {
+        println!("no entries");
+    }
+    Ok(())
+}
+
+/// Loads the whole program specified by `args` in the format specified by `args.format`.
+/// An image whose codel size was not detected within the time budget is not cached, as its codels may be wrong,
+/// nor is one with colors read within `--color-tolerance`, as the warning would not be raised on a cache hit.
+fn load_whole_image(
+    args: &LoadArgs,
+    is_verbose_mode: bool,
+    warnings: &mut Vec<Warning>,
+    metrics: Option<&Metrics>,
+    observer: Option<&mut dyn LoadObserver>,
+) -> Result<Image, Box<dyn Error>> {
+    if let Some(example) = args.example {
+        return Ok(example.image());
+    }
+    let Some(dir) = &args.cache_dir else {
+        return parse_image(args, warnings, observer);
+    };
+
+    let start = Instant::now();
+    let bytes = fs::read(&args.image_file)?;
+    let mut settings = format!(
+        "format={:?} codel_layout={:?} strict_codel_check={} crop={:?} color_tolerance={}",
+        args.format,
+        args.codel_layout(),
+        args.strict_codel_check,
+        args.crop,
+        args.color_tolerance
+    );
+    //only if given, so that the entries written before `--frame` existed are still found
+    if let Some(frame) = args.frame {
+        settings += &format!(" frame={}", frame);
+    }
+    let path = cache::entry_path(dir, &bytes, &settings);
+    if let Ok(entry) = fs::read(&path) {
+        if let Some(img) = cache::deserialize(&entry) {
+            //The entry may have been written under other limits.
+            args.image_limits()
+                .check(img.height() as u64, img.width() as u64, None)?;
+            if let Some(metrics) = metrics {
+                metrics.record_image_cache(true);
+            }
+            debug_print(
+                is_verbose_mode,
+                &format!(
+                    "image cache hit: {} (loaded in {:.3} ms)",
+                    path.display(),
+                    start.elapsed().as_secs_f64() * 1000.0
+                ),
+            );
+            return Ok(img);
+        }
+        debug_print(
+            is_verbose_mode,
+            &format!(
+                "image cache entry is corrupt and ignored: {}",
+                path.display()
+            ),
+        );
+    }
+
+    if let Some(metrics) = metrics {
+        metrics.record_image_cache(false);
+    }
+    let num_warnings = warnings.len();
+    let img = parse_image(args, warnings, observer)?;
+    if warnings[num_warnings..].iter().any(|w| {
+        matches!(
+            w,
+            Warning::DetectionTimedOut { .. } | Warning::ColorsSnapped { .. }
+        )
+    }) {
+        return Ok(img);
+    }
+    //Failing to write the cache shall not prevent the program from running.
+    let written = fs::create_dir_all(dir).and_then(|_| fs::write(&path, cache::serialize(&img)));
+    debug_print(
+        is_verbose_mode,
+        &match written {
+            Ok(()) => format!(
+                "image cache miss: {} written (parsed in {:.3} ms)",
+                path.display(),
+                start.elapsed().as_secs_f64() * 1000.0
+            ),
+            Err(e) => format!(
+                "image cache miss: failed to write {}: {}",
+                path.display(),
+                e
+            ),
+        },
+    );
+    Ok(img)
+}
+
+/// Returns the global palette of the image file if it is a GIF.
+fn gif_palette(args: &LoadArgs) -> Result<Option<Palette>, Box<dyn Error>> {
+    if is_pietdev(args) {
+        return Ok(None);
+    }
+    Ok(quantization::read_gif_palette(&args.image_file)?)
+}
+
+/// Returns `true` if `args` specifies a PietDev save (including an embedded example) rather than an image file.
+pub(crate) fn is_pietdev(args: &LoadArgs) -> bool {
+    if args.example.is_some() {
+        return true;
+    }
+    match args.format {
+        Format::Auto => Path::new(&args.image_file)
+            .extension()
+            .is_some_and(|e| e == "pietdev"),
+        Format::Image => false,
+        Format::Pietdev => true,
+    }
+}
+
+/// Parses the image of `args`, reporting the progress to `observer` if it is given.
+/// The parsing of a PietDev save is reported as [`LoadPhase::Decode`], as its codels are read directly.
+fn parse_image(
+    args: &LoadArgs,
+    warnings: &mut Vec<Warning>,
+    observer: Option<&mut dyn LoadObserver>,
+) -> Result<Image, Box<dyn Error>> {
+    if is_pietdev(args) {
+        if args.frame.is_some() {
+            return Err("`frame` cannot be used with a PietDev save".into());
+        }
+        let mut progress = LoadProgress::new(observer);
+        progress.start(LoadPhase::Decode, 1);
+        let s = fs::read_to_string(&args.image_file)?;
+        let m = pietdev::parse_with_limits(&s, &args.image_limits())?;
+        progress.finish(LoadPhase::Decode);
+        let img = Image::from_codel_grid_with(m, &mut progress);
+        //A PietDev save has one pixel per codel, so the crop never cuts codels.
+        return match args.crop {
+            None => Ok(img),
+            Some(crop)
+                if crop.fits_in(img.height(), img.width())
+                    && (crop.height != 0)
+                    && (crop.width != 0) =>
+            {
+                Ok(img.crop(crop.pos, crop.height, crop.width))
+            }
+            Some(crop) => Err(format!(
+                "the crop {} is empty or exceeds the image ({} x {} codels)",
+                crop,
+                img.height(),
+                img.width()
+            )
+            .into()),
+        };
+    }
+
+    //Unknown colors are replaced by `load_image()`.
+    Image::new_observed(
+        &args.image_file,
+        args.codel_layout(),
+        ColorMatching {
+            default_color: None,
+            tolerance: args.color_tolerance,
+        },
+        args.strict_codel_check,
+        &args.image_limits(),
+        args.crop,
+        args.frame,
+        warnings,
+        observer,
+    )
+}
+
+/// Reads the whole input of the program from `--input`, `--stdin-string` or `--input-random` (which then needs `count`).
+/// `None` is returned if the program shall read the real stdin.
+fn load_input(args: &InputArgs) -> Result<Option<Vec<u8>>, Box<dyn Error>> {
+    if let Some(file) = &args.file {
+        return Ok(Some(fs::read_to_string(file)?.into_bytes()));
+    }
+    if let Some(spec) = &args.random {
+        if spec.count.is_none() {
+            return Err("`count` of `input-random` is required here as the whole input is generated in advance".into());
+        }
+        let mut input = vec![];
+        RandomInput::new(&seed_random_input(spec)).read_to_end(&mut input)?;
+        return Ok(Some(input));
+    }
+    Ok(args.string.clone().map(String::into_bytes))
+}
+
+/// Returns `spec` with its seed, which is printed to stderr if it has been chosen at random so that the input can be generated again.
+fn seed_random_input(spec: &RandomSpec) -> RandomSpec {
+    let seeded = spec.with_seed();
+    if spec.seed.is_none() {
+        eprintln!("Generating the input with `--input-random {}`.", seeded);
+    }
+    seeded
+}
+
+/// Prints the size metrics and the golf score of a program (`golf`).
+pub fn golf(args: &GolfArgs) -> Result<(), Box<dyn Error>> {
+    let program = Program::new(load_image(&args.load, false, &mut vec![])?);
+    let excluded = args
+        .ignore_background
+        .then(|| args.background.block(&program))
+        .flatten();
+    let score = GolfScore::excluding(program.image(), args.rules, excluded);
+    if args.json {
+        println!("{}", score.to_json());
+    } else {
+        println!("{}", score);
+    }
+    Ok(())
+}
+
+/// Converts a program into the PietDev save format (`export`).
+pub fn export(args: &ExportArgs) -> Result<(), Box<dyn Error>> {
+    let img = load_image(&args.load, false, &mut vec![])?;
+    fs::write(&args.output, pietdev::export(&img)?)?;
+    Ok(())
+}
+
+/// Runs a program with both this interpreter and another one and compares their outputs (`compare`).
+pub fn compare(args: &CompareArgs) -> Result<(), Box<dyn Error>> {
+    //fails early if the program cannot be loaded
+    load_image(&args.load, false, &mut vec![])?;
+
+    let input = load_input(&args.input)?.unwrap_or_default();
+    let comparison = compare::compare(args, &input)?;
+    println!("ours: {}", comparison.ours);
+    println!("theirs ({}): {}", args.with, comparison.theirs);
+    if !comparison.is_match() {
+        print!("{}", comparison.diff());
+        return Err(PietError::OutputsDiffer.into());
+    }
+    println!("outputs match");
+    Ok(())
+}
+
+/// Prints the version and the semantics implied by the options as JSON (`version-report`),
+/// or the differences from the semantics of `--diff-with` to them.
+pub fn version_report(args: &VersionReportArgs) -> Result<(), Box<dyn Error>> {
+    let descriptor = args.semantics.descriptor();
+    let Some(other) = &args.diff_with else {
+        println!("{}", semantics::version_report(&descriptor));
+        return Ok(());
+    };
+    println!("{} -> {}", other.to_options(), args.semantics.to_options());
+    print!("{}", SemanticsDiff::new(&other.descriptor(), &descriptor));
+    Ok(())
+}
+
+/// Compares two recorded path traces (`trace-diff`).
+pub fn trace_diff(args: &TraceDiffArgs) -> Result<(), Box<dyn Error>> {
+    let read = |file: &str| -> Result<_, Box<dyn Error>> {
+        Ok(trace_diff::parse(
+            &fs::read_to_string(file).map_err(|e| format!("{}: {}", file, e))?,
+        ))
+    };
+    let (a, b) = (read(&args.a)?, read(&args.b)?);
+    let diff = TraceDiff::new(&a, &b);
+    print!("{}", diff.report(&a, &b, (&args.a, &args.b)));
+    if diff.divergence.is_some() {
+        return Err(PietError::TracesDiffer.into());
+    }
+    Ok(())
+}
+
+/// Exports conformance test vectors or verifies exported ones (`conformance`).
+pub fn conformance(args: &ConformanceArgs) -> Result<(), Box<dyn Error>> {
+    match (
+        &args.export_conformance,
+        &args.cases,
+        &args.verify_conformance,
+    ) {
+        (Some(dir), Some(cases), None) => {
+            export_conformance(Path::new(dir), Path::new(cases), args.max_iter)
+        }
+        (None, None, Some(dir)) => verify_conformance(Path::new(dir), args.max_iter),
+        _ => Err(
+            "either `export_conformance` with `cases` or `verify_conformance` shall be given"
+                .into(),
+        ),
+    }
+}
+
+fn read_to_string(path: &Path) -> Result<String, Box<dyn Error>> {
+    Ok(fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))?)
+}
+
+fn export_conformance(
+    dir: &Path,
+    cases_file: &Path,
+    max_iter: usize,
+) -> Result<(), Box<dyn Error>> {
+    let cases = conformance::parse_cases(
+        &read_to_string(cases_file)?,
+        cases_file.parent().unwrap_or(Path::new("")),
+    )
+    .map_err(|e| format!("{}: {}", cases_file.display(), e))?;
+    let mut exported = vec![];
+    for case in &cases {
+        let expected = export_conformance_case(case, &dir.join(&case.name), max_iter)
+            .map_err(|e| format!("case `{}`: {}", case.name, e))?;
+        println!("exported {}", case.name);
+        exported.push((case.name.as_str(), expected));
+    }
+    let names = exported.iter().map(|(name, _)| *name).collect::<Vec<_>>();
+    fs::write(
+        dir.join(conformance::INDEX_FILE),
+        conformance::index_json(&names),
+    )?;
+    let readme = exported
+        .iter()
+        .map(|(name, e)| (*name, e))
+        .collect::<Vec<_>>();
+    fs::write(
+        dir.join(conformance::README_FILE),
+        conformance::readme(&readme),
+    )?;
+    Ok(())
+}
+
+/// Writes the program (normalized to one pixel per codel), the input and the expected results of `case` to `case_dir`.
+/// The expected results are those of the normalized program, so that they are reproduced from the files alone.
+fn export_conformance_case(
+    case: &Case,
+    case_dir: &Path,
+    max_iter: usize,
+) -> Result<Expected, Box<dyn Error>> {
+    let args = Args::try_parse_from([
+        "piet_programming_language",
+        &case.image.display().to_string(),
+    ])?;
+    let img = load_image(&args.load, false, &mut vec![])?;
+    fs::create_dir_all(case_dir)?;
+    let program = case_dir.join(conformance::PROGRAM_FILE);
+    fs::write(&program, report::png(&img, 1))?;
+    let input = case_dir.join(conformance::INPUT_FILE);
+    match &case.input {
+        Some(file) => {
+            fs::copy(file, &input).map_err(|e| format!("{}: {}", file.display(), e))?;
+        }
+        None => fs::write(&input, "")?,
+    }
+    let expected = run_conformance_case(
+        &program,
+        &input,
+        &case_dir.join(conformance::OUTPUT_FILE),
+        max_iter,
+    )?;
+    fs::write(
+        case_dir.join(conformance::EXPECTED_FILE),
+        expected.to_json(&case.name),
+    )?;
+    Ok(expected)
+}
+
+fn verify_conformance(dir: &Path, max_iter: usize) -> Result<(), Box<dyn Error>> {
+    let index = dir.join(conformance::INDEX_FILE);
+    let names = conformance::parse_index(&read_to_string(&index)?)
+        .map_err(|e| format!("{}: {}", index.display(), e))?;
+    let output =
+        std::env::temp_dir().join(format!("piet_conformance_output_{}", std::process::id()));
+    let mut num_failed = 0;
+    for name in &names {
+        let failures = verify_conformance_case(&dir.join(name), &output, max_iter)
+            .unwrap_or_else(|e| vec![e.to_string()]);
+        if failures.is_empty() {
+            println!("PASS {}", name);
+        } else {
+            num_failed += 1;
+            println!("FAIL {}: {}", name, failures.join("; "));
+        }
+    }
+    let _ = fs::remove_file(&output);
+    println!("{} passed, {} failed", names.len() - num_failed, num_failed);
+    if num_failed != 0 {
+        return Err(PietError::ConformanceFailed {
+            num_failed,
+            num_cases: names.len(),
+        }
+        .into());
+    }
+    Ok(())
+}
+
+/// Checks the built-in tables against the spec (`--self-test`).
+fn self_test() -> Result<(), Box<dyn Error>> {
+    let report = self_test::run(&self_test::Tables::BUILT_IN);
+    println!("{}", report);
+    if !report.is_ok() {
+        return Err(PietError::SelfTestFailed {
+            num_failed: report.failures.len(),
+            num_checks: report.num_checks,
+        }
+        .into());
+    }
+    Ok(())
+}
+
+/// Runs the case in `case_dir` with the output written to `output`, and returns the differences from the expected results.
+fn verify_conformance_case(
+    case_dir: &Path,
+    output: &Path,
+    max_iter: usize,
+) -> Result<Vec<String>, Box<dyn Error>> {
+    let expected_file = case_dir.join(conformance::EXPECTED_FILE);
+    let expected = Expected::from_json(&read_to_string(&expected_file)?)
+        .map_err(|e| format!("{}: {}", expected_file.display(), e))?;
+    let actual = run_conformance_case(
+        &case_dir.join(conformance::PROGRAM_FILE),
+        &case_dir.join(conformance::INPUT_FILE),
+        output,
+        max_iter,
+    )?;
+    let mut failures = expected.differences(&actual);
+    let expected_output = case_dir.join(conformance::OUTPUT_FILE);
+    if fs::read(&expected_output).map_err(|e| format!("{}: {}", expected_output.display(), e))?
+        != fs::read(output)?
+    {
+        failures.push("output differs".to_string());
+    }
+    Ok(failures)
+}
+
+/// Runs `program` (with one pixel per codel) under the default semantics with the input read from `input` and the output written to `output`, recording the path trace.
+/// Fails unless the program halts by itself within `max_iter` steps.
+fn run_conformance_case(
+    program: &Path,
+    input: &Path,
+    output: &Path,
+    max_iter: usize,
+) -> Result<Expected, Box<dyn Error>> {
+    let args = Args::try_parse_from([
+        "piet_programming_language".to_string(),
+        program.display().to_string(),
+        "--codel-size".to_string(),
+        "1".to_string(),
+        "--input".to_string(),
+        input.display().to_string(),
+        "--output".to_string(),
+        output.display().to_string(),
+        "--max-iter".to_string(),
+        max_iter.to_string(),
+    ])?;
+    let mut warnings = vec![];
+    let img = load_image(&args.load, false, &mut warnings)?;
+    check_executable(&img, false, &mut warnings)?;
+    let mut trace = vec![];
+    let result = run_image_impl(
+        &args,
+        &Program::new(img),
+        &mut Interpreter::new(),
+        None,
+        Some(&mut trace),
+        None,
+    )?;
+    if !result.halted_normally() {
+        return Err(PietError::LimitReached(result.reason).into());
+    }
+    Ok(Expected {
+        reason: result.reason,
+        num_steps: result.num_steps,
+        num_commands: result.num_commands,
+        num_output_bytes: result.num_output_bytes,
+        trace_digest: conformance::trace_digest(&trace),
+    })
+}
+
+/// Runs a Piet program (`run`).
+/// A run stopped by a limit (e.g. `max-iter`) is an error ([`PietError::LimitReached`]).
 //This functions is tested in integration tests.
 pub fn run(args: &Args) -> Result<(), Box<dyn Error>> {
-    let default_color = if args.fall_back_to_white {
-        Some(Codel::White)
-    } else if args.fall_back_to_black {
-        Some(Codel::Black)
-    } else {
-        None
+    match run_with_result(args)? {
+        Some(result) if !result.halted_normally() => {
+            Err(PietError::LimitReached(result.reason).into())
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Same as [`run()`] but returns the summary of the run, also when it was stopped by a limit, for a program embedding the interpreter.
+/// `None` is returned when `args` selects a mode which does not run the program (e.g. `--check` or `--dump-image`).
+/// An error raised while running (e.g. by `--deny-warnings`) is returned as it is rather than as a [`TerminationReason`].
+pub fn run_with_result(args: &Args) -> Result<Option<RunResult>, Box<dyn Error>> {
+    if let Some(file) = &args.export_pietdev {
+        return export(&ExportArgs {
+            load: args.load.clone(),
+            output: file.clone(),
+        })
+        .map(|()| None);
+    }
+
+    if args.golf_score {
+        return golf(&GolfArgs {
+            load: args.load.clone(),
+            rules: args.golf_rules,
+            background: args.background,
+            ignore_background: args.ignore_background,
+            json: args.json,
+        })
+        .map(|()| None);
+    }
+
+    if args.image_info {
+        return image_info(args).map(|()| None);
+    }
+
+    if args.list_entries {
+        return list_entries(&args.load).map(|()| None);
+    }
+
+    if args.self_test {
+        return self_test().map(|()| None);
+    }
+
+    if args.semantics {
+        let table = SemanticsTable::new(&args.runner_config().exec);
+        match args.json {
+            true => println!("{}", table.to_json()),
+            false => print!("{}", table),
+        }
+        return Ok(None);
+    }
+
+    if args.list_examples {
+        print!("{}", examples::list());
+        return Ok(None);
+    }
+
+    if let Some(example) = args.load.example {
+        if let Some(dir) = &args.extract {
+            println!("{}", example.extract(dir)?.display());
+            return Ok(None);
+        }
+        if args.verbose != 0 {
+            eprintln!(
+                "[example] a copy can be written with `--example {} --extract <DIR>`",
+                example
+            );
+        }
+    }
+
+    if args.check {
+        return check(args).map(|()| None);
+    }
+
+    if let Some(other) = &args.compare_with {
+        return compare(&CompareArgs {
+            load: args.load.clone(),
+            with: other.clone(),
+            timeout: args.compare_timeout,
+            max_iter: args.limits().max_iter,
+            lenient_start: args.lenient_start,
+            input: args.input.clone(),
+        })
+        .map(|()| None);
+    }
+
+    if args.watch {
+        let input = load_input(&args.input)?.unwrap_or_default();
+        return watch::watch(args, &input).map(|()| None);
+    }
+
+    let Some(file) = &args.metrics_file else {
+        return run_program(args, None);
     };
-    let img = Image::new(&args.image_file, args.codel_size, default_color)?;
-    debug_print(args.verbose, &format!("{}", img));
+    let metrics = Metrics::new();
+    let result = run_program(args, Some(&metrics));
+    //The error of the run takes precedence over that of writing the metrics.
+    let written = fs::write(file, metrics.render());
+    let result = result?;
+    written?;
+    Ok(result)
+}
+
+/// Loads and runs the program of `args`, counting the run in `metrics` if it is given (see [`run_with_result()`]).
+fn run_program(
+    args: &Args,
+    metrics: Option<&Metrics>,
+) -> Result<Option<RunResult>, Box<dyn Error>> {
+    let mut warnings = vec![];
+    let env = Environment::current();
+    let mut load_status = effective_config::shows_progress(args, &env).then(LoadStatus::default);
+    let loaded = load_image_with(
+        &args.load,
+        args.verbose != 0,
+        &mut warnings,
+        metrics,
+        load_status.as_mut().map(|s| s as &mut dyn LoadObserver),
+    );
+    if let Some(s) = load_status.as_ref().and_then(LoadStatus::finish) {
+        eprint!("{}", s);
+    }
+    let (mut img, mut resolution) = loaded?;
+    img.set_topology(args.topology);
 
-    if img.get_codel_at((0, 0)).is_black() {
-        return Err("the top-left codel shall not be black".into());
+    let config = (args.print_config.is_some() || args.report.is_some()).then(|| {
+        resolution.pixels = resolution.pixels.or_else(|| pixel_dimensions(&args.load));
+        EffectiveConfig::new(args, Some(&resolution), &env)
+    });
+    if let (Some(when), Some(config)) = (args.print_config, &config) {
+        let s = match args.json {
+            true => format!("{}\n", config.to_json()),
+            false => config.to_string(),
+        };
+        if when == PrintConfig::Only {
+            print!("{}", s);
+            return Ok(None);
+        }
+        eprint!("{}", s);
+    }
+
+    if args.dump_image {
+        print!("{}", img.scale(args.scale));
+        return Ok(None);
     }
 
+    check_executable(&img, args.allow_empty, &mut warnings)?;
+
+    if args.verbose != 0 {
+        debug_print(
+            args.verbose != 0,
+            &art::render(
+                &img.scale(args.scale),
+                effective_config::art_max_width(args, &env),
+                args.art_fit,
+            ),
+        );
+    }
+
+    let program = Program::new(img);
     let mut ip = Interpreter::new();
+    let result = match metrics {
+        None => run_image_impl(args, &program, &mut ip, None, None, config.as_ref())?,
+        Some(metrics) => {
+            let start = Instant::now();
+            metrics.record_start();
+            let result = run_image_impl(args, &program, &mut ip, None, None, config.as_ref());
+            metrics.record_finish(
+                result.as_ref().ok().map(|r| r.reason),
+                RunCounts::of(&ip),
+                start.elapsed(),
+            );
+            result?
+        }
+    };
 
-    let mut num_iter = 0;
-    loop {
-        let cur_codel = img.get_codel_at(ip.cur);
-        assert!(!cur_codel.is_black());
-        if !cur_codel.is_white() {
-            if num_iter == args.max_iter.unwrap_or(usize::MAX) {
-                println!("Program terminated by `max-iter`.");
-                return Ok(());
-            }
-            num_iter += 1;
-
-            debug_print(args.verbose, &ip.to_string());
-
-            let iter_max = 8; //changes `dp` and `cc` at most 7 times
-            for i in 0..iter_max {
-                //[spec]
-                // Black colour blocks and the edges of the program restrict program flow.
-                // If the Piet interpreter attempts to move into a black block or off an edge,
-                // it is stopped and the CC is toggled.
-                // The interpreter then attempts to move from its current block again.
-                // If it fails a second time, the DP is moved clockwise one step.
-                // These attempts are repeated, with the CC and DP being changed between alternate attempts.
-                // If after eight attempts the interpreter cannot leave its current colour block,
-                // there is no way out and the program terminates.
-                let next_index = img.get_next_codel_index(ip.cur, &ip.dp, &ip.cc);
-                if next_index.is_none() {
-                    if i % 2 == 0 {
-                        ip.cc = ip.cc.flip();
-                    } else {
-                        ip.dp = ip.dp.turn_right();
-                    }
-                    if i == iter_max - 1 {
-                        return Ok(());
-                    }
-                    continue;
-                }
-                let next_codel = img.get_codel_at(next_index.unwrap());
-                if next_codel.is_black() {
-                    if i % 2 == 0 {
-                        ip.cc = ip.cc.flip();
-                    } else {
-                        ip.dp = ip.dp.turn_right();
-                    }
-                    if i == iter_max - 1 {
-                        return Ok(());
-                    }
-                    continue;
-                }
+    if result.halted_normally() && (result.num_output_bytes == 0) && !args.quiet {
+        eprintln!("{}", result.summary());
+    }
+    if args.stats {
+        eprintln!("{}", result.stats());
+    }
+    //A limit takes precedence over the denied warnings (see `run()`).
+    let num_warnings = warnings.len() + result.warnings.len();
+    if result.halted_normally() && args.deny_warnings && (num_warnings != 0) {
+        return Err(PietError::WarningsDenied { num_warnings }.into());
+    }
 
-                if next_codel.is_white() {
-                    ip.cur = next_index.unwrap();
-                    break;
-                }
+    Ok(Some(result))
+}
 
-                let command = Command::new(cur_codel, next_codel);
-                debug_print(args.verbose, &format!("    {:?}", command));
-                let block_size = img.get_block_size_at(ip.cur);
-                command.execute(&mut ip, block_size);
+/// Prints the size, the number of blocks and the background block of the program (`--image-info`).
+fn image_info(args: &Args) -> Result<(), Box<dyn Error>> {
+    let mut img = load_image(&args.load, false, &mut vec![])?;
+    img.set_topology(args.topology);
+    let program = Program::new(img);
+    let img = program.image();
+    let num_colored = (0..img.num_blocks())
+        .filter(|id| {
+            let codel = img.get_codel_at_unchecked(img.get_block(*id).unwrap().first_codel());
+            !codel.is_white() && !codel.is_black()
+        })
+        .count();
+    println!("size:       {} x {} codels", img.width(), img.height());
+    println!("blocks:     {} ({} colored)", img.num_blocks(), num_colored);
+    match args.background.block(&program) {
+        Some(id) => {
+            let block = img.get_block(id).unwrap();
+            let borders = ["top", "right", "bottom", "left"]
+                .into_iter()
+                .zip(program.borders_of_block(id))
+                .filter_map(|(name, is_touched)| is_touched.then_some(name))
+                .collect::<Vec<_>>();
+            println!(
+                "background: the block at {:?} of size {} ({:?}, touching the {} borders)",
+                block.first_codel(),
+                block.size,
+                img.get_codel_at_unchecked(block.first_codel()),
+                borders.join(", ")
+            );
+        }
+        None if args.background == Background::None => {
+            println!("background: none (`--background none`)")
+        }
+        None => println!("background: none detected"),
+    }
+    Ok(())
+}
 
-                ip.cur = next_index.unwrap();
-                break;
-            }
+/// Fails with [`PietError::NoExecutableBlocks`] if `img` has no colored codel, or only warns if `allow_empty` is set.
+fn check_executable(
+    img: &Image,
+    allow_empty: bool,
+    warnings: &mut Vec<Warning>,
+) -> Result<(), Box<dyn Error>> {
+    if img.has_colored_codel() {
+        Ok(())
+    } else if allow_empty {
+        warning::warn(warnings, Warning::NoColoredBlock);
+        Ok(())
+    } else {
+        Err(PietError::NoExecutableBlocks.into())
+    }
+}
+
+/// Returns the codel at which execution starts, which is the top-left one unless `lenient_start` allows another.
+fn start_codel(
+    img: &Image,
+    lenient_start: bool,
+    warnings: &mut Vec<Warning>,
+) -> Result<(usize, usize), PietError> {
+    if !img.get_codel_at_unchecked((0, 0)).is_black() {
+        Ok((0, 0))
+    } else if lenient_start {
+        let start = img
+            .get_first_non_black_codel_index()
+            .ok_or(PietError::BlackStartCodel)?;
+        warning::warn(warnings, Warning::BlackStartCodel { start });
+        Ok(start)
+    } else {
+        Err(PietError::BlackStartCodel)
+    }
+}
+
+/// Lints a program statically (`--check`).
+fn check(args: &Args) -> Result<(), Box<dyn Error>> {
+    let mut warnings = vec![];
+    let mut img = load_image(&args.load, false, &mut warnings)?;
+    img.set_topology(args.topology);
+    //A GIF has been checked against its palette by `load_image()`, and a PietDev save is never quantized.
+    if !is_pietdev(&args.load) && gif_palette(&args.load)?.is_none() {
+        for w in quantization::merged_colors(&img) {
+            warning::warn(&mut warnings, w);
+        }
+    }
+    //The transitions are compared on the colors as they are, so the image is loaded again without the mapping.
+    if let Some(file) = &args.load.palette {
+        let (source, _) = load_entry(&args.load, false, &mut vec![], None, None)?;
+        for w in PaletteMap::read(file)?.changed_transitions(&source) {
+            warning::warn(&mut warnings, w);
+        }
+    }
+    check_executable(&img, args.allow_empty, &mut warnings)?;
+    let start = start_codel(&img, args.lenient_start, &mut warnings)?;
+    let options = LintOptions {
+        large_push_threshold: args.large_push_threshold,
+        allow_large_push: args.allow_large_push,
+        background: args.background,
+        ignore_background: args.ignore_background,
+        start_block_threshold: args.start_block_threshold,
+        allow_large_start_block: args.allow_large_start_block,
+    };
+    let program = Program::new(img);
+    let lint_warnings = lint::lint(&program, start, &options);
+    //Only the JSON goes to stdout so that it can be parsed as it is.
+    let is_json = args.output_analysis && args.json;
+    let mut lines = lint_warnings
+        .iter()
+        .map(|w| format!("warning: {}", w))
+        .collect::<Vec<_>>();
+    if lines.is_empty() && !is_json {
+        lines.push("no warnings".to_string());
+    }
+    if let Some(id) = args
+        .ignore_background
+        .then(|| args.background.block(&program))
+        .flatten()
+    {
+        let block = program.image().get_block(id).unwrap();
+        lines.push(format!(
+            "note: the background block at {:?} of size {} is excluded (`--ignore-background`)",
+            block.first_codel(),
+            block.size
+        ));
+    }
+    for line in &lines {
+        if is_json {
+            eprintln!("{}", line);
+        } else {
+            println!("{}", line);
+        }
+    }
+    if args.output_analysis {
+        let analysis = output_analysis::analyze(&program, start);
+        if args.json {
+            println!("{}", analysis.to_json());
         } else {
-            //See `White Blocks` section in the spec: https://www.dangermouse.net/esoteric/piet.html
+            print!("{}", analysis);
+        }
+    }
+    let num_warnings = warnings.len() + lint_warnings.len();
+    if args.deny_warnings && (num_warnings != 0) {
+        return Err(PietError::WarningsDenied { num_warnings }.into());
+    }
+    Ok(())
+}
 
-            let mut visited = FxHashSet::default();
+/// Executes an already loaded program with the execution options in `args`.
+/// The topology is that of the image of `program` (see [`Image::set_topology()`]) rather than `args.topology`, which is applied by [`run()`] when loading.
+pub fn run_image(args: &Args, program: &Program) -> Result<RunResult, Box<dyn Error>> {
+    run_image_with(args, program, &mut Interpreter::new())
+}
 
-            //FIXME: Currently, the average number of iterations needed to find a non-white codel or wall is the size of the current white block.
-            //       Ideally it should be O(1) (like `Block::get_corner_index()`).
-            loop {
-                if num_iter == args.max_iter.unwrap_or(usize::MAX) {
-                    println!("Program terminated by `max-iter`.");
-                    return Ok(());
-                }
-                num_iter += 1;
+/// Same as [`run_image()`] but on a new interpreter which reads `input` instead of stdin and writes the output to `output`.
+/// An image decoded once (e.g. by [`Image::new()`]) can thus be run with many inputs, e.g. by a grader, as `program` is only read by a run.
+/// The image is taken wrapped in a [`Program`] (see [`Program::new()`]) so that the exits memoized by a run are reused by the later runs.
+/// The input options of `args` (e.g. `--input`) take precedence over `input`, and `--output` over `output`.
+pub fn run_with_image(
+    args: &Args,
+    program: &Program,
+    input: impl Read + Send + 'static,
+    output: impl Write + Send + 'static,
+) -> Result<RunResult, Box<dyn Error>> {
+    let mut ip = Interpreter::new_with_writer(output);
+    ip.stdin.reset_with_reader(Box::new(input));
+    run_image_with(args, program, &mut ip)
+}
 
-                debug_print(args.verbose, &ip.to_string());
+/// Same as [`run_image()`] but runs on `ip`, which shall be new or reset for `program` (see [`Interpreter::reset_for()`] and [`InterpreterPool`](pool::InterpreterPool)).
+/// The input given in `args` replaces that of `ip`, reusing its buffer.
+pub fn run_image_with(
+    args: &Args,
+    program: &Program,
+    ip: &mut Interpreter,
+) -> Result<RunResult, Box<dyn Error>> {
+    run_image_impl(args, program, ip, None, None, None)
+}
 
-                if visited.contains(&(ip.cur, ip.dp)) {
-                    return Ok(());
-                }
-                visited.insert((ip.cur, ip.dp));
+/// Same as [`run_image_with()`] but calls `observer` before each command is executed.
+/// The observer can alter the execution only with `--allow-interventions` (see [`observer`]).
+pub fn run_image_observed(
+    args: &Args,
+    program: &Program,
+    ip: &mut Interpreter,
+    observer: &mut dyn Observer,
+) -> Result<RunResult, Box<dyn Error>> {
+    run_image_impl(args, program, ip, Some(observer), None, None)
+}
 
-                let next_index = img.get_next_codel_index_in_dp_direction(ip.cur, &ip.dp);
-                if next_index.is_none() {
-                    ip.cc = ip.cc.flip();
-                    ip.dp = ip.dp.turn_right();
-                    continue;
-                }
-                let next_codel = img.get_codel_at(next_index.unwrap());
-                if next_codel.is_black() {
-                    ip.cc = ip.cc.flip();
-                    ip.dp = ip.dp.turn_right();
-                    continue;
-                }
+/// Runs `program` on `ip` with `config` (see [`Runner`](runner::Runner)) until it terminates or, if `max_steps` is `Some`, for at most that many steps (see [`Interpreter::run_for()`]).
+/// The input, the output and the observer of `config` are not used, as they are wired by the caller.
+pub(crate) fn run_configured(
+    config: &RunnerConfig,
+    program: &Program,
+    ip: &mut Interpreter,
+    observer: Option<&mut dyn Observer>,
+    trace: Option<&mut Vec<TraceEvent>>,
+    warnings: &mut Vec<Warning>,
+    max_steps: Option<usize>,
+) -> Result<SliceStatus, PietError> {
+    //only at the start, as the interpreter never moves onto a black codel
+    if program.image().get_codel_at_unchecked(ip.cur).is_black() {
+        return Err(PietError::BlackStartCodel);
+    }
+    let mut progress = None;
+    let mut ctx = Context {
+        config,
+        progress: &mut progress,
+        trace,
+        output_spans: None,
+        trace_window: None,
+        assertions: None,
+        last_pos: None,
+        is_tracing: config.trace.print_after.is_some_and(|n| n <= ip.num_steps),
+        slide: None,
+        pacer: None,
+        checkpointer: None,
+        observer: observer.map(|o| o as &mut dyn Observer),
+        warnings: mem::take(warnings),
+        slice_end: max_steps.map(|n| ip.num_steps.saturating_add(n)),
+    };
+    let stop = run_until(&mut ctx, program, ip);
+    *warnings = ctx.warnings;
+    Ok(match stop? {
+        Stop::Terminated(reason) => SliceStatus::Finished(reason),
+        Stop::SliceEnded => SliceStatus::Runnable,
+        Stop::NeedsInput => SliceStatus::NeedsInput,
+    })
+}
 
-                ip.cur = next_index.unwrap();
+/// The path trace is appended to `trace` if it is given.
+/// The report of `--report` shows `effective_config`, or the configuration resolved from `args` alone if it is `None` (e.g. for a program loaded by an embedding program).
+fn run_image_impl(
+    args: &Args,
+    program: &Program,
+    ip: &mut Interpreter,
+    observer: Option<&mut dyn Observer>,
+    trace: Option<&mut Vec<TraceEvent>>,
+    effective_config: Option<&EffectiveConfig>,
+) -> Result<RunResult, Box<dyn Error>> {
+    let img = program.image();
+    let config = args.runner_config();
+    let env = Environment::current();
+    let mut warnings = vec![];
+    let start = start_codel(img, config.lenient_start, &mut warnings)?;
 
-                if next_codel.is_white() {
-                    continue;
-                }
+    let mut progress = effective_config::shows_progress(args, &env)
+        .then(|| Progress::new(Instant::now(), Duration::from_millis(250)));
+
+    //Unlike `load_input()`, the random input is generated on demand, so it can be unbounded.
+    let random_input = args.input.random.as_ref().map(seed_random_input);
+    if let Some(spec) = &random_input {
+        ip.stdin.reset_with_reader(Box::new(RandomInput::new(spec)));
+    } else if let Some(input) = load_input(&args.input)? {
+        ip.stdin.reset_with_bytes(&input);
+    }
+    ip.stdin.set_max_token_len(args.max_token_length);
+    ip.stdin.set_max_bytes(args.max_input_bytes);
+    ip.stdin.set_newlines(args.input_newlines);
+    ip.stdin.set_recording(args.record_input.is_some());
+    let assertions = match &args.assertions {
+        None => None,
+        Some(file) => Some(Assertions::new(
+            assertion::parse(&fs::read_to_string(file)?).map_err(|e| format!("{}: {}", file, e))?,
+            img,
+        )?),
+    };
+    let expected_output = match (&args.expect_output, args.expect_streaming) {
+        (Some(file), None) => Some(fs::read(file).map_err(|e| format!("{}: {}", file, e))?),
+        _ => None,
+    };
+    ip.expect_stream = match (&args.expect_output, args.expect_streaming) {
+        (Some(file), Some(on_mismatch)) => {
+            Some(ExpectStream::open(file, args.expect_encoding, on_mismatch)?)
+        }
+        _ => None,
+    };
+    if assertions.as_ref().is_some_and(|a| a.needs_output())
+        || args.explain_output_byte.is_some()
+        || expected_output.is_some()
+    {
+        ip.output_log.get_or_insert_with(Vec::new);
+    } else {
+        ip.output_log = None;
+    }
+    ip.cur = start;
+    ip.config = config.exec.clone();
+    let io_trace: Option<Box<dyn Write + Send>> = match args.io_trace.as_deref() {
+        None => None,
+        Some("-") => Some(Box::new(io::stderr())),
+        Some(file) => Some(Box::new(BufWriter::new(File::create(file)?))),
+    };
+    let io_log = args.report.is_some().then(IoLog::default);
+    ip.io_trace = match (io_trace, io_log.clone()) {
+        (Some(w), Some(log)) => Some(Box::new(Tee(w, log))),
+        (Some(w), None) => Some(w),
+        (None, Some(log)) => Some(Box::new(log)),
+        (None, None) => None,
+    };
+    //The spec is recorded so that the session can be replayed from the trace (or the report).
+    if let (Some(spec), Some(w)) = (&random_input, ip.io_trace.as_mut()) {
+        writeln!(w, "# input: --input-random {}", spec)?;
+    }
+    let semantics = SemanticsDescriptor::new(&ip.config, img.topology());
+    let checkpoint = match &args.resume {
+        None => None,
+        Some(file) => Some(load_checkpoint(file, program, &semantics, &args.input)?),
+    };
+    if let Some(checkpoint) = &checkpoint {
+        checkpoint.restore(ip)?;
+    }
+    ip.output_file = match &args.output {
+        None => None,
+        Some(file) => Some(open_output(
+            file,
+            checkpoint.as_ref().map(|c| c.num_output_bytes),
+        )?),
+    };
+    //Only the output on stdout can be mixed up with the trace.
+    let is_synchronized = effective_config::is_synchronized(args.sync_output, &env);
+    ip.terminal = (((args.verbose != 0) || args.trace_after.is_some())
+        && ip.output_file.is_none()
+        && is_synchronized)
+        .then(Multiplexer::stdio);
+    let checkpointer = args
+        .checkpoint_every
+        .zip(args.checkpoint_file.as_ref())
+        .map(|(every, file)| {
+            Checkpointer::new(
+                file,
+                every,
+                ip.num_steps,
+                program.fingerprint(),
+                semantics.to_json(),
+            )
+        });
+    if let Some(preset) = args.limits {
+        debug_print(
+            args.verbose != 0,
+            &format!("limits: {} ({})", preset, config.limits),
+        );
+    }
+    let mut own_trace = (trace.is_none() && config.trace.record).then(Vec::new);
+    let mut trace = trace.or(own_trace.as_mut());
+    let mut output_spans =
+        (args.attribute_output || args.explain_output_byte.is_some()).then(Vec::new);
+    let mut trace_window = args.explain_output_byte.map(TraceWindow::new);
+    let mut ctx = Context {
+        config: &config,
+        progress: &mut progress,
+        trace: trace.as_deref_mut(),
+        output_spans: output_spans.as_mut(),
+        trace_window: trace_window.as_mut(),
+        assertions: assertions.as_ref(),
+        last_pos: None,
+        is_tracing: config.trace.print_after.is_some_and(|n| n <= ip.num_steps),
+        slide: None,
+        pacer: Pacer::new(args.speed),
+        checkpointer,
+        observer: observer.map(|o| o as &mut dyn Observer),
+        warnings,
+        slice_end: None,
+    };
+    let start_time = Instant::now();
+    let num_allocations = resource::num_allocations();
+    let reason = execute(&mut ctx, program, ip);
+    let warnings = ctx.warnings;
+    match reason {
+        Ok(TerminationReason::MaxIter) => println!("Program terminated by `max-iter`."),
+        Ok(TerminationReason::Stall) => eprintln!(
+            "Program terminated by `stall-timeout`: no input or output for {} seconds.",
+            config.limits.stall_timeout.unwrap_or_default()
+        ),
+        _ => (),
+    }
+    let resources = ResourceUsage {
+        peak_rss_bytes: resource::peak_rss(),
+        num_allocations: num_allocations
+            .zip(resource::num_allocations())
+            .map(|(before, after)| after - before),
+    };
+
+    if let Some(s) = progress.as_ref().and_then(|p| p.finish()) {
+        ip.print_status(s)
+            .map_err(|e| PietError::write_failed("the progress", e))?;
+    }
+
+    if let Some(spans) = &output_spans {
+        if args.attribute_output {
+            eprint!("{}", attribution::report(spans, ip.num_output_bytes));
+        }
+        if let (Some(offset), Some(window)) = (args.explain_output_byte, trace_window.as_mut()) {
+            window.update(ip.num_output_bytes);
+            let output = ip.output_log.as_deref().unwrap_or_default();
+            eprint!("{}", attribution::explain(offset, spans, output, window));
+        }
+    }
 
-                //spec: If the transition between colour blocks occurs via a slide across a white block, no command is executed.
+    let svg_options = SvgOptions {
+        scale: args.svg_scale,
+        include_path: !args.svg_no_path,
+        max_labels: args.svg_max_labels,
+    };
+    if let Some(file) = &args.svg {
+        fs::write(
+            file,
+            svg::render(img, trace.as_deref().map(Vec::as_slice), &svg_options),
+        )?;
+    }
+
+    if let Some(threshold) = args.notify {
+        let elapsed = start_time.elapsed();
+        if notify::should_notify(
+            elapsed,
+            //a threshold too long to be represented is never reached
+            Duration::try_from_secs_f64(threshold).unwrap_or(Duration::MAX),
+            io::stderr().is_terminal(),
+            args.quiet,
+        ) {
+            let mut terminal = TerminalNotifier::new(io::stderr());
+            let mut notifiers: Vec<&mut dyn Notifier> = vec![&mut terminal];
+            #[cfg(feature = "notify-send")]
+            let mut notify_send = notify::NotifySend;
+            #[cfg(feature = "notify-send")]
+            notifiers.push(&mut notify_send);
+            notify::notify_all(&mut notifiers, &notify::message(&reason, elapsed));
+        }
+    }
 
-                break;
+    let result = reason.map(|reason| RunResult {
+        resources,
+        warnings,
+        ..RunResult::new(reason, ip, semantics)
+    });
+    if let Some(file) = &args.record_input {
+        fs::write(file, ip.stdin.recorded())?;
+    }
+    if let Some(file) = &args.report {
+        //flushes the I/O trace (which may also go to a file) before it is read
+        drop(ip.io_trace.take());
+        let report = Report {
+            img,
+            result: result.as_ref(),
+            trace: trace.as_deref().map(Vec::as_slice).unwrap_or_default(),
+            io_trace: &io_log.map(|log| log.contents()).unwrap_or_default(),
+            svg_options: &svg_options,
+            excluded_block: args
+                .ignore_background
+                .then(|| args.background.block(program))
+                .flatten(),
+            config: &effective_config
+                .cloned()
+                .unwrap_or_else(|| EffectiveConfig::new(args, None, &env)),
+        };
+        fs::write(file, report::render(&report))?;
+    }
+    if let (Some(stream), Some(file)) = (ip.expect_stream.take(), &args.expect_output) {
+        //An error of the run other than the difference itself is returned as it is.
+        if result.is_ok() || stream.should_stop() {
+            if let Err(mismatch) = stream.finish()? {
+                eprint!("{}: the output differs\n{}", file, mismatch);
+                //The run has been completed for its statistics (`--expect-streaming complete`).
+                if let (Ok(result), true) = (&result, args.stats) {
+                    eprintln!("{}", result.stats());
+                }
+                return Err(PietError::OutputsDiffer.into());
             }
         }
     }
+    let result = result?;
+    if let (Some(expected), Some(file)) = (&expected_output, &args.expect_output) {
+        let output = ip.output_log.as_deref().unwrap_or_default();
+        if let Err(mismatch) = expect::compare(expected, output, args.expect_encoding) {
+            eprint!("{}: the output differs\n{}", file, mismatch);
+            return Err(PietError::OutputsDiffer.into());
+        }
+    }
+    Ok(result)
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+/// Reads the checkpoint of `--resume` and checks that it was taken from a run of `program` with the same semantics and an input which can be given again.
+fn load_checkpoint(
+    file: &str,
+    program: &Program,
+    semantics: &SemanticsDescriptor,
+    input: &InputArgs,
+) -> Result<Checkpoint, Box<dyn Error>> {
+    let checkpoint =
+        Checkpoint::deserialize(&fs::read(file).map_err(|e| format!("{}: {}", file, e))?)
+            .ok_or_else(|| format!("{}: not a checkpoint or corrupt", file))?;
+    if checkpoint.fingerprint != program.fingerprint() {
+        return Err(format!(
+            "{}: the checkpoint was taken from the program {} but the program is {}",
+            file,
+            checkpoint.fingerprint,
+            program.fingerprint()
+        )
+        .into());
+    }
+    checkpoint.validate(program.image(), file)?;
+    if checkpoint.semantics != semantics.to_json() {
+        return Err(format!(
+            "{}: the checkpoint was taken with the semantics {} but the options give {}",
+            file,
+            checkpoint.semantics,
+            semantics.to_json()
+        )
+        .into());
+    }
+    //The real stdin cannot be rewound, so the consumed part cannot be skipped.
+    let has_consumed_input = (checkpoint.num_input_bytes != 0) || checkpoint.is_input_eof;
+    if !input.is_given() && has_consumed_input {
+        return Err(format!(
+            "{}: the run had consumed {} bytes of stdin, which cannot be read again; give the same input with `--input`, `--stdin-string` or `--input-random` to resume",
+            file, checkpoint.num_input_bytes
+        )
+        .into());
+    }
+    //Another seed would generate another input.
+    if input.random.as_ref().is_some_and(|r| r.seed.is_none()) && has_consumed_input {
+        return Err(format!(
+            "{}: the run had consumed {} bytes of the random input; give its `seed` to `--input-random` to resume",
+            file, checkpoint.num_input_bytes
+        )
+        .into());
+    }
+    Ok(checkpoint)
+}
 
-    #[test]
-    // #[ignore]
-    fn test01() {
-        let args = Args {
-            image_file: "./test_images/coverage.png".to_string(),
-            codel_size: None,
-            fall_back_to_white: true,
-            fall_back_to_black: false,
-            max_iter: None,
-            verbose: false,
+/// Opens the file of `--output`, which is created, or truncated to `offset` bytes when a run is resumed (discarding the output written after the checkpoint).
+fn open_output(file: &str, offset: Option<usize>) -> Result<File, Box<dyn Error>> {
+    let Some(offset) = offset else {
+        return Ok(File::create(file)?);
+    };
+    let mut f = fs::OpenOptions::new()
+        .write(true)
+        .open(file)
+        .map_err(|e| format!("{}: {}", file, e))?;
+    let len = f.metadata()?.len();
+    if len < offset as u64 {
+        return Err(format!(
+            "{}: the file has {} bytes but the checkpoint recorded {} bytes of output",
+            file, len, offset
+        )
+        .into());
+    }
+    f.set_len(offset as u64)?;
+    f.seek(SeekFrom::End(0))?;
+    Ok(f)
+}
+
+/// Bookkeeping shared by the functions which execute a program.
+struct Context<'a, 'o> {
+    config: &'a RunnerConfig<'o>,
+    progress: &'a mut Option<Progress>,
+    /// The events are appended to this if `Some`.
+    trace: Option<&'a mut Vec<TraceEvent>>,
+    /// The output commands are appended to this if `Some` (`--attribute-output`).
+    output_spans: Option<&'a mut Vec<OutputSpan>>,
+    /// Receives the events if `Some` (`--explain-output-byte`).
+    trace_window: Option<&'a mut TraceWindow>,
+    assertions: Option<&'a Assertions>,
+    /// Position at the previous iteration, used to detect when the interpreter enters a codel.
+    last_pos: Option<(usize, usize)>,
+    /// Whether the path trace is printed.
+    /// This can change during a run (`--trace-after`), and only affects what is printed from then on.
+    is_tracing: bool,
+    /// Moves across white not printed yet (see [`Context::trace_slide_step()`]).
+    slide: Option<Slide>,
+    /// Throttles the steps if `Some` (`--speed`).
+    pacer: Option<Pacer>,
+    /// Writes checkpoints if `Some` (`--checkpoint-every`).
+    checkpointer: Option<Checkpointer>,
+    /// Called before each command if `Some` (see [`run_image_observed()`]).
+    observer: Option<&'a mut dyn Observer>,
+    /// Warnings raised so far, which end up in [`RunResult::warnings`].
+    warnings: Vec<Warning>,
+    /// The run is suspended when the number of steps reaches this if `Some` (see [`run_configured()`]).
+    slice_end: Option<usize>,
+}
+
+/// Why [`run_until()`] returned.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Stop {
+    Terminated(TerminationReason),
+    /// [`Context::slice_end`] has been reached.
+    SliceEnded,
+    /// The next command reads more input than has been fed to the non-blocking input (only in a slice).
+    NeedsInput,
+}
+
+impl From<TerminationReason> for Stop {
+    fn from(reason: TerminationReason) -> Self {
+        Stop::Terminated(reason)
+    }
+}
+
+impl Context<'_, '_> {
+    /// Starts a new iteration at the current state of `ip`, where `is_on_white` tells whether the state is left to [`Self::trace_slide_step()`] to print.
+    /// Returns `Some` if `max-iter` or `stall-timeout` has been reached or the run is cancelled (or without changing anything at the end of a slice), and an error if an assertion fails or the output has differed (`--expect-streaming stop`).
+    fn next_iter(
+        &mut self,
+        ip: &mut Interpreter,
+        is_on_white: bool,
+    ) -> Result<Option<Stop>, PietError> {
+        if self.slice_end == Some(ip.num_steps) {
+            return Ok(Some(Stop::SliceEnded));
+        }
+        if let Some(assertions) = self.assertions {
+            if self.last_pos != Some(ip.cur) {
+                assertions.check(ip)?;
+            }
+        }
+        self.last_pos = Some(ip.cur);
+        //The difference is reported after the run (see `--expect-streaming`).
+        if ip
+            .expect_stream
+            .as_ref()
+            .is_some_and(ExpectStream::should_stop)
+        {
+            return Err(PietError::OutputsDiffer);
+        }
+
+        if ip.num_steps == self.config.limits.max_iter.unwrap_or(usize::MAX) {
+            return Ok(Some(TerminationReason::MaxIter.into()));
+        }
+        if self.is_cancelled() {
+            return Ok(Some(TerminationReason::Cancelled.into()));
+        }
+        //Ctrl-C (the default handler) terminates the process even while sleeping.
+        //A sleep is at most one second, so `stall-timeout` is still checked in time.
+        if let Some(pacer) = self.pacer.as_mut() {
+            std::thread::sleep(pacer.delay(ip.num_steps + 1, Instant::now()));
+        }
+        //As with `Progress`, the clock is read only once in a while, unless the steps are throttled anyway.
+        //The message goes to stderr so that the output printed so far is left as it is.
+        if let Some(stall_timeout) = self.config.limits.stall_timeout {
+            if (ip.num_steps != 0)
+                && (self.pacer.is_some()
+                    || (ip.num_steps & (Progress::CLOCK_CHECK_INTERVAL - 1) == 0))
+                && (ip.last_io.elapsed().as_secs_f64() >= stall_timeout)
+            {
+                return Ok(Some(TerminationReason::Stall.into()));
+            }
+        }
+        if self.config.trace.print_after == Some(ip.num_steps) {
+            self.is_tracing = true;
+        }
+        ip.num_steps += 1;
+
+        if let Some(line) = self
+            .progress
+            .as_mut()
+            .and_then(|p| p.tick(ip.num_steps, ip.cur, Instant::now))
+        {
+            ip.print_status(&line)
+                .map_err(|e| PietError::write_failed("the progress", e))?;
+        }
+
+        if !is_on_white || self.config.trace.per_codel_slides {
+            self.print_trace(ip, &ip.to_string())?;
+        }
+        if let Some(window) = self.trace_window.as_mut() {
+            window.update(ip.num_output_bytes);
+        }
+        self.record(TraceEvent::State {
+            pos: ip.cur,
+            dp: ip.dp,
+            cc: ip.cc,
+        });
+
+        Ok(None)
+    }
+
+    /// Asks the observer (if any) what to do with `command`, and records the intervention.
+    /// Returns the command to execute, or `None` if it is skipped.
+    fn intervene(
+        &mut self,
+        ip: &mut Interpreter,
+        command: Command,
+    ) -> Result<Option<Command>, PietError> {
+        let Some(observer) = self.observer.as_mut() else {
+            return Ok(Some(command));
+        };
+        let intervention = observer.before_command(ip, command);
+        let Some(applied) = intervention.applied() else {
+            return Ok(Some(command));
+        };
+        if !self.config.allow_interventions {
+            return Err(PietError::InterventionNotAllowed {
+                step: ip.num_steps,
+                command,
+            });
+        }
+        self.print_trace(ip, &format!("    [intervention] {:?} {}", command, applied))?;
+        self.record(TraceEvent::Intervention { command, applied });
+        Ok(match intervention {
+            Intervention::Proceed => Some(command),
+            Intervention::Skip => None,
+            Intervention::Replace(other) => Some(other),
+            Intervention::MutateStack(f) => {
+                f(&mut ip.stack);
+                Some(command)
+            }
+        })
+    }
+
+    /// Warns about a `Pointer` or `Switch` whose operand exceeds `--large-operand-threshold` in absolute value (`--check-runtime`).
+    /// It is warned about only once for each position, so that a loop doesn't flood stderr.
+    fn check_operand(&mut self, ip: &Interpreter, command: Command) {
+        if !self.config.check_runtime || !matches!(command, Command::Pointer | Command::Switch) {
+            return;
+        }
+        let Some(&value) = ip.stack.last() else {
+            return;
+        };
+        let threshold = self.config.large_operand_threshold;
+        let is_reported =
+            |w: &Warning| matches!(w, Warning::LargeOperand { pos, .. } if *pos == ip.cur);
+        if (value.unsigned_abs() > threshold) && !self.warnings.iter().any(is_reported) {
+            let warning = Warning::LargeOperand {
+                step: ip.num_steps,
+                pos: ip.cur,
+                command,
+                value,
+                threshold,
+            };
+            warning::warn(&mut self.warnings, warning);
+        }
+    }
+
+    /// Warns about the first `Push` of the run if it is from the start block larger than `--start-block-threshold` (`--check-runtime`).
+    fn check_start_block(&mut self, img: &Image, ip: &Interpreter) {
+        let Some(threshold) = self.config.start_block_threshold else {
+            return;
+        };
+        let start = if self.config.lenient_start {
+            img.get_first_non_black_codel_index()
+        } else {
+            Some((0, 0))
+        };
+        let Some(start) = start.filter(|start| {
+            img.get_block_id_at_unchecked(*start) == img.get_block_id_at_unchecked(ip.cur)
+        }) else {
+            return;
+        };
+        if let Some(block) = lint::start_block(img, start, threshold) {
+            let warning = Warning::LargeStartBlock {
+                step: ip.num_steps,
+                block,
+            };
+            warning::warn(&mut self.warnings, warning);
+        }
+    }
+
+    /// Returns `true` if no feature which has to run on every step is enabled, so that [`run_hot()`] can be used.
+    /// This can change during a run (`--trace-after`).
+    fn is_hot_path_enabled(&self) -> bool {
+        !self.is_tracing
+            && !self.config.check_runtime
+            && self.progress.is_none()
+            && self.trace.is_none()
+            && self.output_spans.is_none()
+            && self.trace_window.is_none()
+            && self.assertions.is_none()
+            && self.pacer.is_none()
+            && self.checkpointer.is_none()
+            && self.observer.is_none()
+    }
+
+    fn is_cancelled(&self) -> bool {
+        self.config
+            .cancellation
+            .as_ref()
+            .is_some_and(CancellationToken::is_cancelled)
+    }
+
+    /// Returns `true` if the step after `num_steps` steps has something to do other than moving (e.g. `max-iter` is reached).
+    fn is_step_pending(&self, num_steps: usize) -> bool {
+        (num_steps == self.config.limits.max_iter.unwrap_or(usize::MAX))
+            || self.is_cancelled()
+            || (self.slice_end == Some(num_steps))
+            || (self.config.trace.print_after == Some(num_steps))
+            || (self.config.limits.stall_timeout.is_some()
+                && (num_steps & (Progress::CLOCK_CHECK_INTERVAL - 1) == 0))
+    }
+
+    /// Prints a line of the path trace if it is enabled.
+    fn print_trace(&self, ip: &Interpreter, line: &str) -> Result<(), PietError> {
+        if self.is_tracing {
+            ip.print_trace(line)
+                .map_err(|e| PietError::write_failed("the path trace", e))?;
+        }
+        Ok(())
+    }
+
+    /// Prints the step of a slide across white from the current state of `ip` to `next` (`None` if it hits a restriction).
+    /// Unless [`TraceOptions::per_codel_slides`](crate::runner::TraceOptions::per_codel_slides), the moves in a straight line are gathered into one line printed when the line ends,
+    /// while a step which hits a restriction (or crosses an edge of a torus) is printed on its own.
+    fn trace_slide_step(
+        &mut self,
+        ip: &Interpreter,
+        next: Option<(usize, usize)>,
+    ) -> Result<(), PietError> {
+        if !self.is_tracing || self.config.trace.per_codel_slides {
+            return Ok(());
+        }
+        let delta = ip.dp.get_displacement();
+        let is_straight =
+            next.is_some() && (coord::offset(ip.cur, delta, usize::MAX, usize::MAX) == next);
+        if let Some(slide) = self.slide.as_mut() {
+            if is_straight
+                && (slide.dp == ip.dp)
+                && (slide.cc == ip.cc)
+                && (slide.to() == Some(ip.cur))
+            {
+                slide.num_codels += 1;
+                return Ok(());
+            }
+        }
+        self.flush_slide(ip)?;
+        if is_straight {
+            self.slide = Some(Slide {
+                from: ip.cur,
+                dp: ip.dp,
+                cc: ip.cc,
+                num_codels: 1,
+            });
+        } else {
+            self.print_trace(ip, &ip.to_string())?;
+        }
+        Ok(())
+    }
+
+    /// Prints the moves gathered by [`Self::trace_slide_step()`], a single one as a usual line.
+    fn flush_slide(&mut self, ip: &Interpreter) -> Result<(), PietError> {
+        let Some(slide) = self.slide.take() else {
+            return Ok(());
+        };
+        let line = if slide.num_codels == 1 {
+            Step {
+                pos: slide.from,
+                dp: slide.dp,
+                cc: slide.cc,
+                commands: vec![],
+            }
+            .to_line()
+        } else {
+            slide.to_line()
+        };
+        self.print_trace(ip, &line)
+    }
+
+    fn record(&mut self, event: TraceEvent) {
+        if let Some(window) = self.trace_window.as_mut() {
+            window.record(&event);
+        }
+        if let Some(trace) = self.trace.as_mut() {
+            trace.push(event);
+        }
+    }
+}
+
+/// Executes `program` from the current state of `ip` until it terminates.
+fn execute(
+    ctx: &mut Context,
+    program: &Program,
+    ip: &mut Interpreter,
+) -> Result<TerminationReason, PietError> {
+    match run_until(ctx, program, ip)? {
+        Stop::Terminated(reason) => Ok(reason),
+        stop => unreachable!("{:?} outside of a slice", stop),
+    }
+}
+
+/// Executes `program` from the current state of `ip` until it terminates or, in a slice, is suspended.
+fn run_until(
+    ctx: &mut Context,
+    program: &Program,
+    ip: &mut Interpreter,
+) -> Result<Stop, PietError> {
+    ip.bind(program)?;
+    let img = program.image();
+    loop {
+        if ctx.is_hot_path_enabled() {
+            run_hot(ctx, program, ip)?;
+        }
+        let cur_codel = img.get_codel_at_unchecked(ip.cur);
+        assert!(!cur_codel.is_black());
+        //The interpreter has entered (or started in) a block of an unknown color.
+        //This is checked here rather than at load time so that unknown colors which the program never reaches (e.g. decorations) are harmless.
+        if let Codel::Other(pixel) = cur_codel {
+            return Err(PietError::UnknownColorReached {
+                pos: ip.cur,
+                pixel: *pixel,
+            });
+        }
+        let stop = if cur_codel.is_white() {
+            slide_across_white(ctx, img, ip)?
+        } else {
+            leave_colored_block(ctx, program, ip)?
+        };
+        if let Some(stop) = stop {
+            if ctx.config.check_runtime && matches!(stop, Stop::Terminated(_)) {
+                if let Some(w) = ip.push_operands.warning() {
+                    warning::warn(&mut ctx.warnings, w);
+                }
+            }
+            return Ok(stop);
+        }
+    }
+}
+
+/// Moves the interpreter from coloured block to coloured block as long as nothing else is needed, i.e. the interpreter leaves a block into a block of another colour and [`Context::is_step_pending()`] is `false`.
+/// Returns (without changing the state) at the first step which is not such a case, which is then handled by the general path ([`leave_colored_block()`] or [`slide_across_white()`]).
+///
+/// This is only a faster way to do the same as the general path when [`Context::is_hot_path_enabled()`], so that compute-heavy programs spend their time in this tight loop.
+fn run_hot(ctx: &Context, program: &Program, ip: &mut Interpreter) -> Result<(), PietError> {
+    let img = program.image();
+    loop {
+        if ctx.is_step_pending(ip.num_steps)
+            || ip
+                .expect_stream
+                .as_ref()
+                .is_some_and(ExpectStream::should_stop)
+        {
+            return Ok(());
+        }
+        let cur_codel = img.get_codel_at_unchecked(ip.cur);
+        if cur_codel.is_white() || cur_codel.is_other() {
+            return Ok(());
+        }
+        let RetryOutcome::Exit {
+            next: next_index,
+            dp,
+            cc,
+        } = retry_outcome(program, ip)
+        else {
+            return Ok(());
+        };
+        let next_codel = img.get_codel_at_unchecked(next_index);
+        if next_codel.is_white() || next_codel.is_other() || (next_codel == cur_codel) {
+            return Ok(());
+        }
+
+        let command = Command::new(cur_codel, next_codel);
+        if would_block(ip, command) {
+            return Ok(());
+        }
+
+        ip.dp = dp;
+        ip.cc = cc;
+        ip.num_steps += 1;
+        command.execute(ip, img.get_block_size_at_unchecked(ip.cur))?;
+        ip.num_commands += 1;
+        ip.cur = next_index;
+    }
+}
+
+/// Returns the outcome of the attempts to leave the current coloured block, whose codel is chosen by the corner strategy of `ip` (see [`corner`]).
+/// The interpreter is on the codel where it entered the block, which the strategy may take into account.
+fn retry_outcome(program: &Program, ip: &Interpreter) -> RetryOutcome {
+    let strategy = &ip.config.corner_strategy;
+    if strategy.is_standard() {
+        program.get_retry_outcome(ip.cur, &ip.dp, &ip.cc)
+    } else {
+        program.get_retry_outcome_with(&**strategy, ip.cur, &ip.dp, &ip.cc)
+    }
+}
+
+/// Returns `true` if `command` reads more input than has been fed to the non-blocking input of `ip` (see [`Stdin::new_non_blocking()`](stdin::Stdin::new_non_blocking)).
+fn would_block(ip: &Interpreter, command: Command) -> bool {
+    match command {
+        Command::InChar => !ip.stdin.can_read_char(),
+        Command::InNumber => !ip.stdin.can_read_word(),
+        _ => false,
+    }
+}
+
+/// Returns the command which the interpreter executes when it leaves the current coloured block, if any.
+/// Nothing is changed, as the outcome of the attempts is only looked up.
+fn next_command(program: &Program, ip: &Interpreter) -> Option<Command> {
+    let img = program.image();
+    let RetryOutcome::Exit { next, .. } = retry_outcome(program, ip) else {
+        return None;
+    };
+    let (cur_codel, next_codel) = (
+        img.get_codel_at_unchecked(ip.cur),
+        img.get_codel_at_unchecked(next),
+    );
+    (!next_codel.is_white() && !next_codel.is_other() && (next_codel != cur_codel))
+        .then(|| Command::new(cur_codel, next_codel))
+}
+
+/// Moves the interpreter out of the current coloured block, executing the command of the transition (if any).
+/// Returns `Some` if the program terminates or is suspended.
+fn leave_colored_block(
+    ctx: &mut Context,
+    program: &Program,
+    ip: &mut Interpreter,
+) -> Result<Option<Stop>, PietError> {
+    //The step is not started, so that it is done from the beginning when more input has been fed.
+    if ctx.slice_end.is_some() && next_command(program, ip).is_some_and(|c| would_block(ip, c)) {
+        return Ok(Some(Stop::NeedsInput));
+    }
+    //A checkpoint is taken only here, as the state of a slide across white is not saved.
+    if let Some(checkpointer) = ctx.checkpointer.as_mut() {
+        if let Err(e) = checkpointer.tick(ip) {
+            //Failing to write a checkpoint shall not stop the run.
+            warning::warn(&mut ctx.warnings, Warning::CheckpointFailed(e.to_string()));
+        }
+    }
+    if let Some(stop) = ctx.next_iter(ip, false)? {
+        return Ok(Some(stop));
+    }
+
+    let img = program.image();
+    let cur_codel = img.get_codel_at_unchecked(ip.cur);
+
+    let RetryOutcome::Exit {
+        next: next_index,
+        dp,
+        cc,
+    } = retry_outcome(program, ip)
+    else {
+        return Ok(Some(TerminationReason::NoExit.into()));
+    };
+    ip.dp = dp;
+    ip.cc = cc;
+
+    let next_codel = img.get_codel_at_unchecked(next_index);
+    if next_codel.is_white() {
+        enter_white(ip, next_index);
+        return Ok(None);
+    }
+
+    //Only on a torus, the codel across an edge can be in another block of the same colour.
+    //No command is defined for the transition, so it is made as if via a white block.
+    //no command is defined for an unknown color either; `execute()` reports the error
+    if (next_codel == cur_codel) || next_codel.is_other() {
+        ip.cur = next_index;
+        return Ok(None);
+    }
+
+    let Some(command) = ctx.intervene(ip, Command::new(cur_codel, next_codel))? else {
+        ip.cur = next_index;
+        return Ok(None);
+    };
+    ctx.print_trace(ip, &format!("    {:?}", command))?;
+    ctx.record(TraceEvent::Command(command));
+    ctx.check_operand(ip, command);
+    let block_size = img.get_block_size_at_unchecked(ip.cur);
+    if ctx.config.check_runtime && (command == Command::Push) {
+        if ip.push_operands.is_empty() {
+            ctx.check_start_block(img, ip);
+        }
+        ip.push_operands.record(block_size);
+    }
+    let num_output_bytes = ip.num_output_bytes;
+    command.execute(ip, block_size)?;
+    ip.num_commands += 1;
+    if let Some(spans) = ctx.output_spans.as_mut() {
+        if ip.num_output_bytes != num_output_bytes {
+            spans.push(OutputSpan {
+                offset: num_output_bytes,
+                step: ip.num_steps,
+                pos: ip.cur,
+                command,
+            });
+        }
+    }
+
+    ip.cur = next_index;
+    Ok(None)
+}
+
+/// Moves the interpreter from a coloured block onto the adjacent white codel `white`.
+///
+/// Related [spec](https://www.dangermouse.net/esoteric/piet.html):
+///
+/// > White colour blocks are "free" zones through which the interpreter passes unhindered.
+/// > If it moves from a colour block into a white area, the interpreter "slides" through the white codels in the direction of the DP until it reaches a non-white colour block.
+///
+/// That is, the slide starts from `white` itself with the DP and CC unchanged: they are whatever the attempt which found `white` used
+/// (the corner of the coloured block was chosen by them, but it plays no further role), and no command is executed.
+fn enter_white(ip: &mut Interpreter, white: (usize, usize)) {
+    ip.cur = white;
+    ip.slide_visited.clear();
+}
+
+/// Slides the interpreter across the white block it is on until it enters a coloured block.
+/// Returns `Some` if the program terminates or is suspended, in which case the slide continues from where it was suspended the next time.
+///
+/// Related [spec](https://www.dangermouse.net/esoteric/piet.html) ("White Blocks" section):
+///
+/// > The interpreter "slides" across the white block in a straight line.
+/// > If it hits a restriction, the CC is toggled.
+/// > Since this results in no difference in where the interpreter is trying to go, the DP is immediately stepped clockwise.
+/// > The interpreter then begins sliding from its current white codel, in the new direction of the DP, until it either enters a coloured block or encounters another restriction.
+/// > Each time the interpreter hits a restriction while within a white block, it toggles the CC and steps the DP clockwise, then tries to slide again.
+/// > If the interpreter ever retraces its route entirely within a white block, there is no way out of the white block and execution should terminate.
+fn slide_across_white(
+    ctx: &mut Context,
+    img: &Image,
+    ip: &mut Interpreter,
+) -> Result<Option<Stop>, PietError> {
+    //FIXME: Currently, the average number of iterations needed to find a non-white codel or wall is the size of the current white block.
+    //       Ideally it should be O(1) (like `Block::get_corner_index()`).
+    loop {
+        let stop = ctx.next_iter(ip, true);
+        if !matches!(stop, Ok(None)) {
+            ctx.flush_slide(ip)?;
+        }
+        if let Some(stop) = stop? {
+            return Ok(Some(stop));
+        }
+
+        if !ip.slide_visited.insert((ip.cur, ip.dp)) {
+            ctx.trace_slide_step(ip, None)?;
+            return Ok(Some(TerminationReason::WhiteRetrace.into()));
+        }
+
+        let next_index = img
+            .get_next_codel_index_in_dp_direction_unchecked(ip.cur, &ip.dp)
+            .filter(|next_index| !img.get_codel_at_unchecked(*next_index).is_black());
+        ctx.trace_slide_step(ip, next_index)?;
+        let Some(next_index) = next_index else {
+            ip.cc = ip.cc.flip();
+            ip.dp = ip.dp.turn_right();
+            continue;
+        };
+
+        ip.cur = next_index;
+
+        //spec: If the transition between colour blocks occurs via a slide across a white block, no command is executed.
+        if !img.get_codel_at_unchecked(next_index).is_white() {
+            ctx.flush_slide(ip)?;
+            return Ok(None);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use clap::Parser;
+    use itertools::Itertools;
+
+    use super::*;
+    use crate::art::ArtFit;
+    use crate::cc::CC;
+    use crate::corner::CornerStrategyKind;
+    use crate::dp::DP;
+    use crate::expect::ExpectEncoding;
+    use crate::golf::GolfRules;
+    use crate::image::{Pixel, Topology};
+    use crate::multiplexer::SyncOutput;
+    use crate::observer::AppliedIntervention;
+    use crate::pace::Speed;
+    use crate::stdin::{InputNewlines, NumberOverflow};
+
+    #[test]
+    // #[ignore]
+    fn test01() {
+        let args = Args {
+            load: LoadArgs {
+                image_file: "./test_images/coverage.png".to_string(),
+                format: Format::Auto,
+                codel_size: None,
+                codel_grid: None,
+                detect_grid_offset: false,
+                detect_budget: crate::image::DEFAULT_DETECT_BUDGET_MS,
+                detect_fallback: crate::image::DetectFallback::Partial,
+                fall_back_to_white: true,
+                fall_back_to_black: false,
+                color_tolerance: 0,
+                palette: None,
+                strict_codel_check: false,
+                crop: None,
+                frame: None,
+                cache_dir: None,
+                max_image_pixels: limits::DEFAULT_MAX_IMAGE_PIXELS,
+                max_image_dimension: limits::DEFAULT_MAX_IMAGE_DIMENSION,
+                max_image_bytes: limits::DEFAULT_MAX_IMAGE_BYTES,
+                entry: None,
+                manifest: None,
+                example: None,
+            },
+            limits: None,
+            max_iter: None,
+            stall_timeout: None,
+            verbose: 0,
+            art_max_width: None,
+            art_fit: ArtFit::Scale,
+            dump_image: false,
+            speed: Speed::Max,
+            io_trace: None,
+            attribute_output: false,
+            explain_output_byte: None,
+            assertions: None,
+            expect_output: None,
+            expect_encoding: ExpectEncoding::Utf8,
+            expect_streaming: None,
+            trace_after: None,
+            sync_output: SyncOutput::Auto,
+            scale: 1,
+            svg: None,
+            svg_scale: 20,
+            svg_no_path: false,
+            svg_max_labels: 100,
+            report: None,
+            output: None,
+            checkpoint_every: None,
+            checkpoint_file: None,
+            resume: None,
+            stats: false,
+            metrics_file: None,
+            progress: false,
+            extension: vec![],
+            topology: Topology::Bounded,
+            corner_strategy: CornerStrategyKind::Standard,
+            number_overflow: NumberOverflow::Ignore,
+            notify: None,
+            lenient_start: false,
+            allow_empty: false,
+            deny_warnings: false,
+            check_runtime: false,
+            large_operand_threshold: 1000,
+            allow_interventions: false,
+            quiet: false,
+            input: InputArgs::default(),
+            max_token_length: stdin::DEFAULT_MAX_TOKEN_LEN,
+            max_input_bytes: None,
+            input_newlines: InputNewlines::Raw,
+            record_input: None,
+            watch: false,
+            watch_timeout: 10.0,
+            check: false,
+            list_entries: false,
+            list_examples: false,
+            self_test: false,
+            semantics: false,
+            extract: None,
+            large_push_threshold: 10000,
+            allow_large_push: false,
+            start_block_threshold: 128,
+            allow_large_start_block: false,
+            background: Background::Auto,
+            ignore_background: false,
+            image_info: false,
+            output_analysis: false,
+            export_pietdev: None,
+            golf_score: false,
+            golf_rules: GolfRules::Codels,
+            json: false,
+            print_config: None,
+            compare_with: None,
+            compare_timeout: 10,
         };
         assert!(run(&args).is_ok());
     }
+
+    /// Executes `m` from the state `(pos, dp, cc)` and returns the trace.
+    fn trace(m: Vec<Vec<Codel>>, pos: (usize, usize), dp: DP, cc: CC) -> Vec<TraceEvent> {
+        trace_with_topology(m, Topology::Bounded, pos, dp, cc)
+    }
+
+    fn trace_with_topology(
+        m: Vec<Vec<Codel>>,
+        topology: Topology,
+        pos: (usize, usize),
+        dp: DP,
+        cc: CC,
+    ) -> Vec<TraceEvent> {
+        let args = Args::parse_from(["piet_programming_language", "", "--max-iter", "100"]);
+        let mut img = Image::from_codel_grid(m);
+        img.set_topology(topology);
+        let mut ip = Interpreter::new();
+        ip.cur = pos;
+        ip.dp = dp;
+        ip.cc = cc;
+        let mut progress = None;
+        let mut trace = vec![];
+        let mut ctx = Context {
+            config: &args.runner_config(),
+            progress: &mut progress,
+            trace: Some(&mut trace),
+            output_spans: None,
+            trace_window: None,
+            assertions: None,
+            last_pos: None,
+            is_tracing: false,
+            slide: None,
+            pacer: None,
+            checkpointer: None,
+            observer: None,
+            warnings: vec![],
+            slice_end: None,
+        };
+        execute(&mut ctx, &Program::new(img), &mut ip).unwrap();
+        trace
+    }
+
+    fn state(pos: (usize, usize), dp: DP, cc: CC) -> TraceEvent {
+        TraceEvent::State { pos, dp, cc }
+    }
+
+    /// Enters the white ring around a 3x3 block from the corner selected by `(dp, cc)` and checks the whole slide until it enters the block again.
+    ///
+    /// ```text
+    /// K W W W K
+    /// W R R R W
+    /// W R R R W
+    /// W R R R W
+    /// K W W W K
+    /// ```
+    ///
+    /// As the image is symmetric, the expected trace for each DP is that for `DP::Right` rotated clockwise.
+    fn check_enter_white(dp: DP, cc: CC) {
+        let (k, w, r) = (Codel::Black, Codel::White, Codel::Red);
+        let m = vec![
+            vec![k, w, w, w, k],
+            vec![w, r, r, r, w],
+            vec![w, r, r, r, w],
+            vec![w, r, r, r, w],
+            vec![k, w, w, w, k],
+        ];
+
+        let expected = match cc {
+            //exits from (1, 3) into (1, 4), which is on the edge, so turns down and slides to the black corner, then turns left into the block
+            CC::Left => vec![
+                state((2, 2), DP::Right, CC::Left),
+                state((1, 4), DP::Right, CC::Left),
+                state((1, 4), DP::Down, CC::Right),
+                state((2, 4), DP::Down, CC::Right),
+                state((3, 4), DP::Down, CC::Right),
+                state((3, 4), DP::Left, CC::Left),
+                state((3, 3), DP::Left, CC::Left),
+            ],
+            //exits from (3, 3) into (3, 4), which is next to both the edge and the black corner
+            CC::Right => vec![
+                state((2, 2), DP::Right, CC::Right),
+                state((3, 4), DP::Right, CC::Right),
+                state((3, 4), DP::Down, CC::Left),
+                state((3, 4), DP::Left, CC::Right),
+                state((3, 3), DP::Left, CC::Right),
+            ],
+        };
+        let num_rotations = dp as isize;
+        let rotate = |e: TraceEvent| match e {
+            TraceEvent::State { mut pos, dp, cc } => {
+                for _ in 0..num_rotations {
+                    pos = (pos.1, 4 - pos.0);
+                }
+                state(pos, dp.rotate_clockwise_by(num_rotations), cc)
+            }
+            e => e,
+        };
+        let expected = expected.into_iter().map(rotate).collect_vec();
+
+        let trace = trace(m, (2, 2), dp, cc);
+        assert_eq!(expected, trace[..expected.len()]);
+    }
+
+    #[test]
+    fn test_enter_white_right_left() {
+        check_enter_white(DP::Right, CC::Left);
+    }
+
+    #[test]
+    fn test_enter_white_right_right() {
+        check_enter_white(DP::Right, CC::Right);
+    }
+
+    #[test]
+    fn test_enter_white_down_left() {
+        check_enter_white(DP::Down, CC::Left);
+    }
+
+    #[test]
+    fn test_enter_white_down_right() {
+        check_enter_white(DP::Down, CC::Right);
+    }
+
+    #[test]
+    fn test_enter_white_left_left() {
+        check_enter_white(DP::Left, CC::Left);
+    }
+
+    #[test]
+    fn test_enter_white_left_right() {
+        check_enter_white(DP::Left, CC::Right);
+    }
+
+    #[test]
+    fn test_enter_white_up_left() {
+        check_enter_white(DP::Up, CC::Left);
+    }
+
+    #[test]
+    fn test_enter_white_up_right() {
+        check_enter_white(DP::Up, CC::Right);
+    }
+
+    //one-codel-wide white corridor: no command is executed on either crossing
+    #[test]
+    fn test_enter_white_corridor() {
+        let m = vec![vec![Codel::Red, Codel::White, Codel::Blue]];
+        let trace = trace(m, (0, 0), DP::Right, CC::Left);
+        assert_eq!(
+            vec![
+                state((0, 0), DP::Right, CC::Left),
+                state((0, 1), DP::Right, CC::Left),
+                state((0, 2), DP::Right, CC::Left),
+                //the 5th attempt (DP: left, CC: left) finds the corridor again
+                state((0, 1), DP::Left, CC::Left),
+                state((0, 0), DP::Left, CC::Left),
+            ],
+            trace[..5]
+        );
+        assert!(!trace.iter().any(|e| matches!(e, TraceEvent::Command(_))));
+    }
+
+    //a white dead end touching the edge sends the interpreter back into the block it came from
+    #[test]
+    fn test_enter_white_dead_end() {
+        let m = vec![
+            vec![Codel::Red, Codel::White],
+            vec![Codel::Black, Codel::Black],
+        ];
+        let trace = trace(m, (0, 0), DP::Right, CC::Left);
+        let period = vec![
+            state((0, 0), DP::Right, CC::Left),
+            state((0, 1), DP::Right, CC::Left),
+            state((0, 1), DP::Down, CC::Right),
+            state((0, 1), DP::Left, CC::Left),
+            state((0, 0), DP::Left, CC::Left),
+            //the 5th attempt (DP: right, CC: left) finds the dead end again
+            state((0, 1), DP::Right, CC::Left),
+            state((0, 1), DP::Down, CC::Right),
+            state((0, 1), DP::Left, CC::Left),
+        ];
+        assert_eq!(period, trace[..8]);
+        //each slide has its own retrace history, so this never terminates by itself
+        assert_eq!(100, trace.len());
+        assert_eq!(trace[4..8], trace[96..100]);
+    }
+
+    /// Minimal interpreter written directly from the spec, used as the reference for [`check_white_escape()`].
+    /// It finds the block by flood fill and the exit codel by sorting the codels of the block, without any of the tables of [`Program`],
+    /// and only moves (which is enough for images without commands, i.e. a single colour besides white and black).
+    /// Returns the states of the first `num_steps` steps, or fewer if the program terminates.
+    fn reference_trace(
+        m: &[Vec<Codel>],
+        mut pos: (usize, usize),
+        mut dp: DP,
+        mut cc: CC,
+        num_steps: usize,
+    ) -> Vec<TraceEvent> {
+        let (height, width) = (m.len(), m[0].len());
+        let at = |p: (usize, usize)| m[p.0][p.1];
+        let next = |p, dp: DP| {
+            coord::offset(p, dp.get_displacement(), height, width).filter(|&q| !at(q).is_black())
+        };
+        let mut trace = vec![];
+        while trace.len() < num_steps {
+            trace.push(state(pos, dp, cc));
+            if at(pos).is_white() {
+                //spec: the interpreter "slides" across the white block in a straight line, toggling the CC and stepping the DP clockwise at each restriction
+                let mut visited = rustc_hash::FxHashSet::default();
+                loop {
+                    if !visited.insert((pos, dp)) {
+                        return trace;
+                    }
+                    match next(pos, dp) {
+                        Some(q) if at(q).is_white() => pos = q,
+                        Some(q) => {
+                            pos = q;
+                            break;
+                        }
+                        None => {
+                            cc = cc.flip();
+                            dp = dp.turn_right();
+                        }
+                    }
+                    if trace.len() == num_steps {
+                        return trace;
+                    }
+                    trace.push(state(pos, dp, cc));
+                }
+                continue;
+            }
+
+            let mut block = vec![pos];
+            let mut k = 0;
+            while k < block.len() {
+                for q in coord::neighbours(block[k], height, width) {
+                    if (at(q) == at(pos)) && !block.contains(&q) {
+                        block.push(q);
+                    }
+                }
+                k += 1;
+            }
+            //spec: the edge furthest in the direction of the DP, and the codel on it furthest to the CC's direction of the DP's direction of travel
+            let mut exit = None;
+            for i in 0..8 {
+                let side = match cc {
+                    CC::Left => dp.rotate_clockwise_by(-1),
+                    CC::Right => dp.turn_right(),
+                };
+                let extent = |d: DP, p: &(usize, usize)| {
+                    let (di, dj) = d.get_displacement();
+                    di * (p.0 as isize) + dj * (p.1 as isize)
+                };
+                let corner = *block
+                    .iter()
+                    .max_by_key(|p| (extent(dp, p), extent(side, p)))
+                    .unwrap();
+                exit = next(corner, dp);
+                if exit.is_some() {
+                    break;
+                }
+                if i % 2 == 0 {
+                    cc = cc.flip();
+                } else {
+                    dp = dp.turn_right();
+                }
+            }
+            match exit {
+                Some(q) => pos = q,
+                None => return trace,
+            }
+        }
+        trace
+    }
+
+    /// The `(DP, CC)` of each of the eight attempts to leave a block, starting with `(DP::Right, CC::Left)`.
+    const ATTEMPTS: [(DP, CC); 8] = [
+        (DP::Right, CC::Left),
+        (DP::Right, CC::Right),
+        (DP::Down, CC::Right),
+        (DP::Down, CC::Left),
+        (DP::Left, CC::Left),
+        (DP::Left, CC::Right),
+        (DP::Up, CC::Right),
+        (DP::Up, CC::Left),
+    ];
+
+    /// A 3x3 block walled by black, except a white gap where the `attempt`-th attempt (counted from 0) to leave the block moves.
+    ///
+    /// ```text
+    /// K 7 K 6 K
+    /// 5 R R R 0
+    /// K R R R K
+    /// 4 R R R 1
+    /// K 2 K 3 K
+    /// ```
+    fn white_escape(attempt: usize) -> (Vec<Vec<Codel>>, (usize, usize)) {
+        let gaps = [
+            (1, 4),
+            (3, 4),
+            (4, 1),
+            (4, 3),
+            (3, 0),
+            (1, 0),
+            (0, 3),
+            (0, 1),
+        ];
+        let mut m = vec![vec![Codel::Black; 5]; 5];
+        for row in &mut m[1..4] {
+            row[1..4].fill(Codel::Red);
+        }
+        let gap = gaps[attempt];
+        m[gap.0][gap.1] = Codel::White;
+        (m, gap)
+    }
+
+    /// Checks that the interpreter leaves the block of [`white_escape()`] only at the `attempt`-th attempt, and slides from the gap with the DP and CC of that attempt.
+    ///
+    /// The spec restricts the flow only by black blocks and the edges, so a move into white is a successful attempt like any other,
+    /// and the DP and CC keep the changes made by the failed attempts before it ("The interpreter then begins sliding from its current white codel, in the new direction of the DP").
+    fn check_white_escape(attempt: usize) {
+        let (m, gap) = white_escape(attempt);
+        let (dp, cc) = ATTEMPTS[attempt];
+        let trace = trace(m.clone(), (2, 2), DP::Right, CC::Left);
+        assert_eq!(
+            vec![state((2, 2), DP::Right, CC::Left), state(gap, dp, cc)],
+            trace[..2],
+            "attempt {}",
+            attempt
+        );
+        //the gap is a dead end, so the slide turns back into the block, which is left through the gap again and again
+        assert_eq!(100, trace.len());
+        assert!(!trace.iter().any(|e| matches!(e, TraceEvent::Command(_))));
+        assert_eq!(
+            reference_trace(&m, (2, 2), DP::Right, CC::Left, 100),
+            trace,
+            "attempt {}",
+            attempt
+        );
+    }
+
+    #[test]
+    fn test_white_escape() {
+        for attempt in 0..8 {
+            check_white_escape(attempt);
+        }
+    }
+
+    #[test]
+    fn test_reference_trace() {
+        //agrees on the other white tests too
+        let m = vec![
+            vec![
+                Codel::Black,
+                Codel::White,
+                Codel::White,
+                Codel::White,
+                Codel::Black,
+            ],
+            vec![
+                Codel::White,
+                Codel::Red,
+                Codel::Red,
+                Codel::Red,
+                Codel::White,
+            ],
+            vec![
+                Codel::White,
+                Codel::Red,
+                Codel::Red,
+                Codel::Red,
+                Codel::White,
+            ],
+            vec![
+                Codel::White,
+                Codel::Red,
+                Codel::Red,
+                Codel::Red,
+                Codel::White,
+            ],
+            vec![
+                Codel::Black,
+                Codel::White,
+                Codel::White,
+                Codel::White,
+                Codel::Black,
+            ],
+        ];
+        for (dp, cc) in ATTEMPTS {
+            assert_eq!(
+                reference_trace(&m, (2, 2), dp, cc, 100),
+                trace(m.clone(), (2, 2), dp, cc)
+            );
+        }
+        let m = vec![
+            vec![Codel::Red, Codel::White],
+            vec![Codel::Black, Codel::Black],
+        ];
+        assert_eq!(
+            reference_trace(&m, (0, 0), DP::Right, CC::Left, 100),
+            trace(m.clone(), (0, 0), DP::Right, CC::Left)
+        );
+        //a white retrace and a block with no exit terminate
+        let m = vec![vec![Codel::White, Codel::White]];
+        assert_eq!(
+            reference_trace(&m, (0, 0), DP::Right, CC::Left, 100),
+            trace(m.clone(), (0, 0), DP::Right, CC::Left)
+        );
+        let m = vec![vec![Codel::Red, Codel::Black]];
+        assert_eq!(
+            vec![state((0, 0), DP::Right, CC::Left)],
+            reference_trace(&m, (0, 0), DP::Right, CC::Left, 100)
+        );
+        assert_eq!(
+            reference_trace(&m, (0, 0), DP::Right, CC::Left, 100),
+            trace(m.clone(), (0, 0), DP::Right, CC::Left)
+        );
+    }
+
+    /// Programs which consist of a single row or column (a popular style of golfing).
+    /// They have no way out, so the interpreter bounces between both ends until `max-iter`.
+    fn check_one_dimensional(transpose: bool, with_white_gap: bool) {
+        use Command::*;
+        let (r, dr, lm, w) = (
+            Codel::Red,
+            Codel::DarkRed,
+            Codel::LightMagenta,
+            Codel::White,
+        );
+        //push 5 (dark red), then out(number) (light magenta)
+        let mut row = vec![r, r, r, r, r, dr];
+        if with_white_gap {
+            row.extend([w, w, dr]);
+        }
+        row.push(lm);
+        let n = row.len();
+
+        let right_l = |j| state((0, j), DP::Right, CC::Left);
+        let left_l = |j| state((0, j), DP::Left, CC::Left);
+        let mut expected = vec![right_l(0), TraceEvent::Command(Push), right_l(5)];
+        if with_white_gap {
+            //slides without executing a command
+            expected.extend([right_l(6), right_l(7), right_l(8)]);
+        }
+        expected.extend([
+            TraceEvent::Command(OutNumber),
+            //the 5th attempt (DP: left, CC: left) finds the way back
+            right_l(n - 1),
+            TraceEvent::Command(Multiply),
+        ]);
+        if with_white_gap {
+            expected.extend([left_l(8), left_l(7), left_l(6)]);
+        }
+        expected.extend([
+            left_l(5),
+            TraceEvent::Command(Pop),
+            //the 5th attempt (DP: right, CC: left) finds the way back
+            left_l(4),
+            TraceEvent::Command(Push),
+            right_l(5),
+        ]);
+
+        let (m, expected) = if transpose {
+            //For a column, the first move is found by the 3rd attempt (DP: down, CC: right) and the returns by the 5th attempts.
+            let transposed = |e: TraceEvent| match e {
+                TraceEvent::State {
+                    pos: (_, j), dp, ..
+                } => match (j, dp) {
+                    (0, _) => state((0, 0), DP::Right, CC::Left),
+                    (_, DP::Right) => state((j, 0), DP::Down, CC::Right),
+                    (_, _) => state((j, 0), DP::Up, CC::Right),
+                },
+                e => e,
+            };
+            (
+                row.iter().map(|c| vec![*c]).collect_vec(),
+                expected.into_iter().map(transposed).collect_vec(),
+            )
+        } else {
+            (vec![row], expected)
+        };
+
+        let trace = trace(m, (0, 0), DP::Right, CC::Left);
+        assert_eq!(expected, trace[..expected.len()]);
+
+        //from then on, the same cycle repeats
+        let period = expected.len() - 3;
+        assert_eq!(trace[2..2 + period], trace[2 + period..2 + 2 * period]);
+    }
+
+    #[test]
+    fn test_single_row() {
+        check_one_dimensional(false, false);
+    }
+
+    #[test]
+    fn test_single_column() {
+        check_one_dimensional(true, false);
+    }
+
+    #[test]
+    fn test_single_row_with_white_gap() {
+        check_one_dimensional(false, true);
+    }
+
+    #[test]
+    fn test_mismatched_program() {
+        //push 1 and then terminates
+        let args = Args::parse_from(["piet_programming_language", "", "--max-iter", "1"]);
+        let (r, dr) = (Codel::Red, Codel::DarkRed);
+        let program = Program::new(Image::from_codel_grid(vec![vec![r, dr]]));
+        let other = Program::new(Image::from_codel_grid(vec![vec![dr, r]]));
+        let mut progress = None;
+        let mut ctx = Context {
+            config: &args.runner_config(),
+            progress: &mut progress,
+            trace: None,
+            output_spans: None,
+            trace_window: None,
+            assertions: None,
+            last_pos: None,
+            is_tracing: false,
+            slide: None,
+            pacer: None,
+            checkpointer: None,
+            observer: None,
+            warnings: vec![],
+            slice_end: None,
+        };
+        let mut ip = Interpreter::new();
+        execute(&mut ctx, &program, &mut ip).unwrap();
+        assert_eq!(vec![1], ip.stack);
+        assert_eq!(
+            Err(PietError::MismatchedProgram {
+                expected: program.fingerprint(),
+                found: other.fingerprint(),
+            }),
+            execute(&mut ctx, &other, &mut ip)
+        );
+        //nothing was executed
+        assert_eq!(vec![1], ip.stack);
+    }
+
+    #[test]
+    fn test_no_executable_blocks() {
+        let args = |file: &str, options: &[&str]| {
+            let path = format!("./tests/test_images/{}", file);
+            Args::parse_from(
+                ["piet_programming_language", path.as_str(), "--quiet"]
+                    .into_iter()
+                    .chain(options.iter().copied()),
+            )
+        };
+        for file in [
+            "original___all_white.pietdev",
+            "original___all_black.pietdev",
+        ] {
+            let e = run(&args(file, &[])).unwrap_err();
+            assert_eq!(
+                Some(&PietError::NoExecutableBlocks),
+                e.downcast_ref::<PietError>()
+            );
+            let e = run(&args(file, &["--check"])).unwrap_err();
+            assert_eq!(
+                Some(&PietError::NoExecutableBlocks),
+                e.downcast_ref::<PietError>()
+            );
+        }
+        //downgraded to a warning
+        assert!(run(&args("original___all_white.pietdev", &["--allow-empty"])).is_ok());
+        //the top-left codel is still required to be non-black
+        let e = run(&args("original___all_black.pietdev", &["--allow-empty"])).unwrap_err();
+        assert_eq!(
+            Some(&PietError::BlackStartCodel),
+            e.downcast_ref::<PietError>()
+        );
+        //a white start is fine as long as there is a colored block
+        let e = run(&args(
+            "original___white_start.pietdev",
+            &["--max-iter", "100"],
+        ))
+        .unwrap_err();
+        assert_eq!(
+            Some(&PietError::LimitReached(TerminationReason::MaxIter)),
+            e.downcast_ref::<PietError>()
+        );
+    }
+
+    #[test]
+    fn test_warnings() {
+        let args = |file: &str, options: &[&str]| {
+            let path = format!("./tests/test_images/{}", file);
+            Args::parse_from(
+                ["piet_programming_language", path.as_str(), "--quiet"]
+                    .into_iter()
+                    .chain(options.iter().copied()),
+            )
+        };
+
+        let a = args(
+            "original___start_point_is_black_framed.png",
+            &["--lenient-start"],
+        );
+        let img = load_image(&a.load, false, &mut vec![]).unwrap();
+        let result = run_image(&a, &Program::new(img)).unwrap();
+        assert_eq!(
+            vec![Warning::BlackStartCodel { start: (1, 1) }],
+            result.warnings
+        );
+        assert!(run(&a).is_ok());
+
+        //raised while loading, so not in the result
+        let a = args(
+            "original___hi_with_decoration.png",
+            &["--fall-back-to-black"],
+        );
+        let mut warnings = vec![];
+        let img = load_image(&a.load, false, &mut warnings).unwrap();
+        assert_eq!(
+            vec![Warning::UnknownColorsReplaced {
+                num_codels: 21,
+                color: Codel::Black
+            }],
+            warnings
+        );
+        assert!(run_image(&a, &Program::new(img))
+            .unwrap()
+            .warnings
+            .is_empty());
+
+        for a in [
+            args(
+                "original___start_point_is_black_framed.png",
+                &["--lenient-start", "--deny-warnings"],
+            ),
+            args(
+                "original___hi_with_decoration.png",
+                &["--fall-back-to-black", "--deny-warnings"],
+            ),
+        ] {
+            assert_eq!(
+                Some(&PietError::WarningsDenied { num_warnings: 1 }),
+                run(&a).unwrap_err().downcast_ref::<PietError>()
+            );
+        }
+        let a = args("original___hi.pietdev", &["--deny-warnings"]);
+        assert!(run(&a).is_ok());
+    }
+
+    #[test]
+    fn test_reset_for() {
+        use crate::pool::InterpreterPool;
+
+        let load = |file: &str| {
+            Program::new(
+                Image::new(format!("./tests/test_images/{}", file), None, None, false).unwrap(),
+            )
+        };
+        let a = Program::new(crate::examples::Example::Adder.image());
+        let b = load("original___echo_char.png");
+        //leaves unread input, the second stack and the output log behind
+        let args_a = Args::parse_from([
+            "piet_programming_language",
+            "",
+            "--stdin-string",
+            "1 2 3",
+            "--extension",
+            "second-stack",
+            "--explain-output-byte",
+            "0",
+        ]);
+        let args_b = Args::parse_from(["piet_programming_language", "", "--stdin-string", "xy"]);
+
+        let mut fresh = Interpreter::new();
+        let expected = run_image_with(&args_b, &b, &mut fresh).unwrap();
+
+        let pool = InterpreterPool::new();
+        let mut ip = pool.checkout(&a);
+        run_image_with(&args_a, &a, &mut ip).unwrap();
+        assert_eq!(b"3\n", ip.output_buf.as_slice());
+        assert!(ip.output_log.is_some());
+        pool.checkin(ip);
+
+        let mut ip = pool.checkout(&b);
+        assert_eq!(Interpreter::new().digest(), ip.digest());
+        assert_eq!(expected, run_image_with(&args_b, &b, &mut ip).unwrap());
+        assert_eq!(fresh.digest(), ip.digest());
+        assert_eq!(fresh.output_buf, ip.output_buf);
+        assert_eq!(fresh.output_log, ip.output_log);
+        assert_eq!(fresh.second_stack, ip.second_stack);
+        assert_eq!(fresh.config, ip.config);
+        assert_eq!(fresh.stdin.num_bytes_read(), ip.stdin.num_bytes_read());
+        assert_eq!(fresh.stdin.read_char(), ip.stdin.read_char());
+    }
+
+    /// Replaces the first `OutChar` with `Pop`.
+    struct ReplaceFirstOutChar {
+        done: bool,
+    }
+
+    impl Observer for ReplaceFirstOutChar {
+        fn before_command(&mut self, _: &Interpreter, command: Command) -> Intervention {
+            if self.done || (command != Command::OutChar) {
+                return Intervention::Proceed;
+            }
+            self.done = true;
+            Intervention::Replace(Command::Pop)
+        }
+    }
+
+    #[test]
+    fn test_intervention() {
+        let args = |options: &[&str]| {
+            Args::parse_from(
+                [
+                    "piet_programming_language",
+                    "./tests/test_images/original___hi.pietdev",
+                ]
+                .into_iter()
+                .chain(options.iter().copied()),
+            )
+        };
+        let args_allowed = args(&["--allow-interventions"]);
+        let program = Program::new(parse_image(&args_allowed.load, &mut vec![], None).unwrap());
+
+        let mut ip = Interpreter::new();
+        let mut progress = None;
+        let mut trace = vec![];
+        let mut observer = ReplaceFirstOutChar { done: false };
+        let mut ctx = Context {
+            config: &args_allowed.runner_config(),
+            progress: &mut progress,
+            trace: Some(&mut trace),
+            output_spans: None,
+            trace_window: None,
+            assertions: None,
+            last_pos: None,
+            is_tracing: false,
+            slide: None,
+            pacer: None,
+            checkpointer: None,
+            observer: Some(&mut observer),
+            warnings: vec![],
+            slice_end: None,
+        };
+        execute(&mut ctx, &program, &mut ip).unwrap();
+        //`H` is popped instead of printed
+        assert_eq!(b"i", ip.output_buf.as_slice());
+        let k = trace
+            .iter()
+            .position(|e| matches!(e, TraceEvent::Intervention { .. }))
+            .unwrap();
+        assert_eq!(
+            [
+                TraceEvent::Intervention {
+                    command: Command::OutChar,
+                    applied: AppliedIntervention::Replaced(Command::Pop),
+                },
+                TraceEvent::Command(Command::Pop),
+            ],
+            trace[k..k + 2]
+        );
+        assert_eq!(
+            1,
+            trace
+                .iter()
+                .filter(|e| matches!(e, TraceEvent::Intervention { .. }))
+                .count()
+        );
+
+        //`!` is printed instead of each character, which is left on the stack
+        struct PushBeforeOutChar;
+        impl Observer for PushBeforeOutChar {
+            fn before_command(&mut self, _: &Interpreter, command: Command) -> Intervention {
+                match command {
+                    Command::OutChar => Intervention::MutateStack(|stack| stack.push('!' as isize)),
+                    _ => Intervention::Proceed,
+                }
+            }
+        }
+        let mut ip = Interpreter::new();
+        let result =
+            run_image_observed(&args_allowed, &program, &mut ip, &mut PushBeforeOutChar).unwrap();
+        assert_eq!(b"!!", ip.output_buf.as_slice());
+        assert_eq!(vec!['H' as isize, 'i' as isize], result.stack);
+
+        struct SkipOutChar;
+        impl Observer for SkipOutChar {
+            fn before_command(&mut self, _: &Interpreter, command: Command) -> Intervention {
+                match command {
+                    Command::OutChar => Intervention::Skip,
+                    _ => Intervention::Proceed,
+                }
+            }
+        }
+        let mut ip = Interpreter::new();
+        run_image_observed(&args_allowed, &program, &mut ip, &mut SkipOutChar).unwrap();
+        assert!(ip.output_buf.is_empty());
+
+        //not applied without `--allow-interventions`
+        let mut ip = Interpreter::new();
+        let e = run_image_observed(
+            &args(&[]),
+            &program,
+            &mut ip,
+            &mut ReplaceFirstOutChar { done: false },
+        )
+        .unwrap_err();
+        assert!(matches!(
+            e.downcast_ref::<PietError>(),
+            Some(PietError::InterventionNotAllowed {
+                command: Command::OutChar,
+                ..
+            })
+        ));
+        assert!(ip.output_buf.is_empty());
+    }
+
+    #[test]
+    fn test_hot_path() {
+        //`trace` is `Some` only to disable the hot path
+        let run = |file: &str, topology: &str, is_hot: bool| {
+            let path = format!("./tests/test_images/{}", file);
+            let args = Args::parse_from([
+                "piet_programming_language",
+                path.as_str(),
+                "--max-iter",
+                "10000",
+                "--topology",
+                topology,
+            ]);
+            let mut img = load_image(&args.load, false, &mut vec![]).unwrap();
+            img.set_topology(args.topology);
+            let mut ip = Interpreter::new();
+            let mut progress = None;
+            let mut trace = vec![];
+            let mut ctx = Context {
+                config: &args.runner_config(),
+                progress: &mut progress,
+                trace: (!is_hot).then_some(&mut trace),
+                output_spans: None,
+                trace_window: None,
+                assertions: None,
+                last_pos: None,
+                is_tracing: false,
+                slide: None,
+                pacer: None,
+                checkpointer: None,
+                observer: None,
+                warnings: vec![],
+                slice_end: None,
+            };
+            assert_eq!(is_hot, ctx.is_hot_path_enabled());
+            let reason = execute(&mut ctx, &Program::new(img), &mut ip);
+            (
+                reason,
+                ip.cur,
+                ip.dp,
+                ip.cc,
+                ip.stack,
+                ip.num_steps,
+                ip.num_commands,
+                ip.output_buf,
+            )
+        };
+        for (file, topology) in [
+            ("original___hi.pietdev", "bounded"),
+            ("original___abcd.png", "bounded"),
+            ("original___print_then_spin.png", "bounded"),
+            ("original___bounce.pietdev", "bounded"),
+            ("original___no_output.png", "bounded"),
+            ("original___large_push.pietdev", "bounded"),
+            ("original___single_row.pietdev", "bounded"),
+            ("original___white_start.pietdev", "bounded"),
+            ("original___unknown_color_reached.png", "bounded"),
+            ("original___torus.pietdev", "torus"),
+        ] {
+            let slow = run(file, topology, false);
+            assert!(slow.5 > 0, "{}", file);
+            assert_eq!(slow, run(file, topology, true), "{}", file);
+        }
+    }
+
+    #[test]
+    fn test_semantics_determinism() {
+        use crate::extension::{ExecConfig, Extension};
+
+        let descriptor =
+            SemanticsDescriptor::new(&ExecConfig::new(&[Extension::SecondStack]), Topology::Torus);
+        let args = Args::parse_from(["piet_programming_language", "", "--max-iter", "1000"]);
+        let record = || {
+            let mut img = Image::new(
+                "./tests/test_images/original___second_stack.png",
+                None,
+                None,
+                false,
+            )
+            .unwrap();
+            img.set_topology(descriptor.topology);
+            let mut ip = Interpreter::new();
+            ip.config = descriptor.exec_config();
+            let mut progress = None;
+            let mut trace = vec![];
+            let mut ctx = Context {
+                config: &args.runner_config(),
+                progress: &mut progress,
+                trace: Some(&mut trace),
+                output_spans: None,
+                trace_window: None,
+                assertions: None,
+                last_pos: None,
+                is_tracing: false,
+                slide: None,
+                pacer: None,
+                checkpointer: None,
+                observer: None,
+                warnings: vec![],
+                slice_end: None,
+            };
+            let topology = img.topology();
+            execute(&mut ctx, &Program::new(img), &mut ip).unwrap();
+            (
+                trace,
+                ip.stack,
+                SemanticsDescriptor::new(&ip.config, topology),
+            )
+        };
+        let first = record();
+        assert!(!first.0.is_empty());
+        assert_eq!(descriptor, first.2);
+        assert_eq!(first, record());
+    }
+
+    #[test]
+    fn test_torus() {
+        use Command::*;
+        let (r, dr) = (Codel::Red, Codel::DarkRed);
+        let right_l = |j| state((0, j), DP::Right, CC::Left);
+
+        //push 1, pop, and then re-enters from the left edge
+        let torus = trace_with_topology(
+            vec![vec![r, dr, r]],
+            Topology::Torus,
+            (0, 0),
+            DP::Right,
+            CC::Left,
+        );
+        assert_eq!(
+            vec![
+                right_l(0),
+                TraceEvent::Command(Push),
+                right_l(1),
+                TraceEvent::Command(Pop),
+                //no command between the two red blocks
+                right_l(2),
+                right_l(0),
+                TraceEvent::Command(Push),
+            ],
+            torus[..7]
+        );
+
+        //bounded: the edge restricts the flow and the interpreter turns back
+        let bounded = trace(vec![vec![r, dr, r]], (0, 0), DP::Right, CC::Left);
+        assert_eq!(
+            vec![
+                right_l(0),
+                TraceEvent::Command(Push),
+                right_l(1),
+                TraceEvent::Command(Pop),
+                right_l(2),
+                TraceEvent::Command(Push),
+                state((0, 1), DP::Left, CC::Left),
+            ],
+            bounded[..7]
+        );
+    }
+
+    #[test]
+    fn test_torus_white_row() {
+        //the slide goes around the torus forever without hitting a restriction
+        let (w, k) = (Codel::White, Codel::Black);
+        let mut img = Image::from_codel_grid(vec![vec![w; 4], vec![k; 4]]);
+        img.set_topology(Topology::Torus);
+        let program = Program::new(img);
+        let args = Args::parse_from(["piet_programming_language", "", "--max-iter", "100"]);
+        let result = run_image(&args, &program).unwrap();
+        assert_eq!(TerminationReason::WhiteRetrace, result.reason);
+        //(0, 0) to (0, 3), then back at (0, 0)
+        assert_eq!(5, result.num_steps);
+        assert_eq!((0, 0), result.pos);
+    }
+
+    //The degenerate images exercise only the attempts to leave a block (or the retrace detection of a slide) at the edges.
+    #[test]
+    fn test_single_block_images() {
+        let run = |file: &str, options: &[&str]| {
+            let path = format!("./tests/test_images/{}", file);
+            let args = Args::parse_from(
+                ["piet_programming_language", path.as_str(), "--allow-empty"]
+                    .into_iter()
+                    .chain(options.iter().copied()),
+            );
+            let mut img = load_image(&args.load, false, &mut vec![]).unwrap();
+            img.set_topology(args.topology);
+            let mut trace = vec![];
+            let result = run_image_impl(
+                &args,
+                &Program::new(img),
+                &mut Interpreter::new(),
+                None,
+                Some(&mut trace),
+                None,
+            )
+            .unwrap();
+            (result, trace)
+        };
+        let right_l = |pos| state(pos, DP::Right, CC::Left);
+
+        //Every attempt to leave a block filling the image is restricted by an edge (or, on a torus, by the block itself).
+        //The start is traced and counted as a step, and the DP and CC are back at where they were after the eight attempts.
+        for file in [
+            "original___single_block.pietdev",
+            "original___single_codel.pietdev",
+        ] {
+            for topology in ["bounded", "torus"] {
+                let (result, trace) = run(file, &["--topology", topology]);
+                assert_eq!(TerminationReason::NoExit, result.reason, "{}", file);
+                assert_eq!(vec![right_l((0, 0))], trace, "{}", file);
+                assert_eq!(
+                    (1, 0, 0),
+                    (result.num_steps, result.num_commands, result.stack.len())
+                );
+                assert_eq!(
+                    ((0, 0), DP::Right, CC::Left),
+                    (result.pos, result.dp, result.cc)
+                );
+                assert_eq!(
+                    "program halted normally after 0 commands with stack depth 0",
+                    result.summary()
+                );
+            }
+        }
+
+        //slides clockwise along the edges until the state of the start is reached again
+        let (result, trace) = run("original___all_white.pietdev", &[]);
+        assert_eq!(TerminationReason::WhiteRetrace, result.reason);
+        assert_eq!(
+            vec![
+                right_l((0, 0)),
+                right_l((0, 1)),
+                right_l((0, 2)),
+                state((0, 2), DP::Down, CC::Right),
+                state((1, 2), DP::Down, CC::Right),
+                state((1, 2), DP::Left, CC::Left),
+                state((1, 1), DP::Left, CC::Left),
+                state((1, 0), DP::Left, CC::Left),
+                state((1, 0), DP::Up, CC::Right),
+                state((0, 0), DP::Up, CC::Right),
+                right_l((0, 0)),
+            ],
+            trace
+        );
+        assert_eq!((11, 0), (result.num_steps, result.num_commands));
+        assert_eq!(
+            ((0, 0), DP::Right, CC::Left),
+            (result.pos, result.dp, result.cc)
+        );
+
+        //around the first row of the torus without any restriction
+        let (result, trace) = run("original___all_white.pietdev", &["--topology", "torus"]);
+        assert_eq!(TerminationReason::WhiteRetrace, result.reason);
+        assert_eq!(
+            vec![
+                right_l((0, 0)),
+                right_l((0, 1)),
+                right_l((0, 2)),
+                right_l((0, 0)),
+            ],
+            trace
+        );
+        assert_eq!(4, result.num_steps);
+    }
+
+    #[test]
+    fn test_run_with_image() {
+        let img = Image::new(
+            "./tests/test_images/original___adder.png",
+            None,
+            None,
+            false,
+        );
+        let program = Program::new(img.unwrap());
+        let args = Args::parse_from(["piet_programming_language", ""]);
+        for (input, expected) in [("3 4\n", "7\n"), ("10 -2\n", "8\n")] {
+            let log = IoLog::default();
+            let result =
+                run_with_image(&args, &program, io::Cursor::new(input), log.clone()).unwrap();
+            assert_eq!(TerminationReason::NoExit, result.reason);
+            assert_eq!(expected, log.contents());
+        }
+    }
+
+    #[test]
+    fn test_run_with_result() {
+        let output =
+            std::env::temp_dir().join(format!("piet_test_run_with_result_{}", std::process::id()));
+        let run_args = |extra: &[&str]| {
+            let mut v = vec![
+                "piet_programming_language",
+                "./tests/test_images/original___hello_2x.png",
+                "--quiet",
+                "--output",
+                output.to_str().unwrap(),
+            ];
+            v.extend(extra);
+            Args::parse_from(v)
+        };
+
+        let result = run_with_result(&run_args(&[])).unwrap().unwrap();
+        assert_eq!(TerminationReason::NoExit, result.reason);
+        assert!(result.stack.is_empty());
+        assert_eq!(66, result.num_commands);
+        assert_eq!("Hello, world!\n", fs::read_to_string(&output).unwrap());
+        assert!(run(&run_args(&[])).is_ok());
+
+        //a limit is a result rather than an error
+        let args = run_args(&["--max-iter", "10"]);
+        let result = run_with_result(&args).unwrap().unwrap();
+        assert_eq!(TerminationReason::MaxIter, result.reason);
+        assert_eq!(10, result.num_steps);
+        assert!(matches!(
+            run(&args).unwrap_err().downcast_ref(),
+            Some(PietError::LimitReached(TerminationReason::MaxIter))
+        ));
+        fs::remove_file(&output).unwrap();
+
+        //not a run
+        assert_eq!(None, run_with_result(&run_args(&["--check"])).unwrap());
+    }
+
+    #[test]
+    fn test_run_image_result() {
+        let (r, dr, b, k) = (Codel::Red, Codel::DarkRed, Codel::DarkBlue, Codel::Black);
+        //push 3, dup, then trapped in the dark blue block
+        let program = Program::new(Image::from_codel_grid(vec![
+            vec![r, r, dr, dr, b],
+            vec![r, k, k, b, b],
+        ]));
+        let args = Args::parse_from(["piet_programming_language", ""]);
+        let result = run_image(&args, &program).unwrap();
+        assert_eq!(TerminationReason::NoExit, result.reason);
+        assert!(result.halted_normally());
+        assert_eq!(2, result.num_commands);
+        assert_eq!(0, result.num_output_bytes);
+        assert_eq!(vec![3, 3], result.stack);
+        assert_eq!(
+            "program halted normally after 2 commands with stack depth 2",
+            result.summary()
+        );
+
+        let args = Args::parse_from(["piet_programming_language", "", "--max-iter", "1"]);
+        let result = run_image(&args, &program).unwrap();
+        assert_eq!(TerminationReason::MaxIter, result.reason);
+        assert!(!result.halted_normally());
+        assert_eq!(1, result.num_steps);
+
+        //the derived data is shared by the runs
+        assert_eq!(1, program.num_exit_table_builds());
+    }
+
+    #[test]
+    fn test_bounce_loop() {
+        //bounces between two blocks in a black-walled chamber forever, each move found by the 5th attempt
+        let args = Args::parse_from([
+            "piet_programming_language",
+            "./tests/test_images/original___bounce.pietdev",
+            "--max-iter",
+            "1000000",
+        ]);
+        let program = Program::new(load_image(&args.load, false, &mut vec![]).unwrap());
+        for _ in 0..2 {
+            let result = run_image(&args, &program).unwrap();
+            assert_eq!(TerminationReason::MaxIter, result.reason);
+            assert_eq!(1000000, result.num_steps);
+        }
+        //only for the start and the two entries into the blocks, by the first run
+        assert_eq!(3, program.num_retry_computations());
+    }
+
+    #[test]
+    fn test_large_operand() {
+        let (r, dr, lc) = (Codel::Red, Codel::DarkRed, Codel::LightCyan);
+        //push 1001, then pointer, back and forth
+        let mut row = vec![r; 1001];
+        row.extend([dr, lc]);
+        let program = Program::new(Image::from_codel_grid(vec![row]));
+        let run = |options: &[&str]| {
+            //the start block pushing 1001 is intended
+            let args = Args::parse_from(
+                [
+                    "piet_programming_language",
+                    "",
+                    "--max-iter",
+                    "20",
+                    "--allow-large-start-block",
+                ]
+                .iter()
+                .chain(options),
+            );
+            run_image(&args, &program).unwrap().warnings
+        };
+
+        //once for the position, however many times it is executed
+        assert_eq!(
+            vec![Warning::LargeOperand {
+                step: 2,
+                pos: (0, 1001),
+                command: Command::Pointer,
+                value: 1001,
+                threshold: 1000,
+            }],
+            run(&["--check-runtime"])
+        );
+        assert!(run(&["--check-runtime", "--large-operand-threshold", "1001"]).is_empty());
+        assert!(run(&[]).is_empty());
+    }
+
+    #[test]
+    fn test_square_push_operands() {
+        let run = |img: Image, options: &[&str]| {
+            let args = Args::parse_from(["piet_programming_language", ""].iter().chain(options));
+            run_image(&args, &Program::new(img)).unwrap().warnings
+        };
+
+        //every block of the scaled program is 4 times as large
+        let warnings = run(
+            examples::Example::Hello.image().scale(2),
+            &["--check-runtime"],
+        );
+        assert!(matches!(
+            warnings.as_slice(),
+            [Warning::SquarePushOperands { factor: 2, .. }]
+        ));
+        assert!(run(examples::Example::Hello.image(), &["--check-runtime"]).is_empty());
+        assert!(run(examples::Example::Hello.image().scale(2), &[]).is_empty());
+    }
+
+    #[test]
+    fn test_unknown_color() {
+        let (r, k) = (Codel::Red, Codel::Black);
+        let gray = Codel::Other(Pixel {
+            r: 128,
+            g: 128,
+            b: 128,
+        });
+        let args = Args::parse_from(["piet_programming_language", ""]);
+
+        //never reached
+        let program = Program::new(Image::from_codel_grid(vec![
+            vec![r, r, k],
+            vec![k, k, gray],
+        ]));
+        let result = run_image(&args, &program).unwrap();
+        assert_eq!(TerminationReason::NoExit, result.reason);
+
+        //reached from a coloured block
+        let program = Program::new(Image::from_codel_grid(vec![
+            vec![r, r, gray],
+            vec![k, k, k],
+        ]));
+        let e = run_image(&args, &program).unwrap_err();
+        assert_eq!(
+            Some(&PietError::UnknownColorReached {
+                pos: (0, 2),
+                pixel: Pixel {
+                    r: 128,
+                    g: 128,
+                    b: 128
+                }
+            }),
+            e.downcast_ref::<PietError>()
+        );
+
+        //reached by a slide
+        let program = Program::new(Image::from_codel_grid(vec![
+            vec![r, Codel::White, gray],
+            vec![k, k, k],
+        ]));
+        let e = run_image(&args, &program).unwrap_err();
+        assert!(matches!(
+            e.downcast_ref::<PietError>(),
+            Some(PietError::UnknownColorReached { pos: (0, 2), .. })
+        ));
+    }
+
+    #[test]
+    fn test_stall_timeout() {
+        let (r, dr, lm, lc, k) = (
+            Codel::Red,
+            Codel::DarkRed,
+            Codel::LightMagenta,
+            Codel::LightCyan,
+            Codel::Black,
+        );
+        //prints "1\n", then bounces between the light magenta and light cyan blocks forever
+        let program = Program::new(Image::from_codel_grid(vec![
+            vec![r, dr, lm, k],
+            vec![k, k, lc, k],
+        ]));
+        let args = Args::parse_from([
+            "piet_programming_language",
+            "",
+            "--stall-timeout",
+            "0",
+            "--quiet",
+        ]);
+        let result = run_image(&args, &program).unwrap();
+        assert_eq!(TerminationReason::Stall, result.reason);
+        assert!(!result.halted_normally());
+        assert_eq!(2, result.num_output_bytes);
+        assert_eq!(Progress::CLOCK_CHECK_INTERVAL, result.num_steps);
+    }
+
+    /// The hash sets and maps outside of tests are `FxHashSet` and `FxHashMap`, whose order is the same in every run,
+    /// as those of `std` are seeded randomly and their order would leak into the reports which iterate them (see `test90` of the integration tests).
+    #[test]
+    fn test_no_randomly_seeded_hashing() {
+        let mut violations = vec![];
+        for entry in std::fs::read_dir("src").unwrap() {
+            let path = entry.unwrap().path();
+            if path.extension().is_none_or(|e| e != "rs") {
+                continue;
+            }
+            let source = std::fs::read_to_string(&path).unwrap();
+            let code = source.split("#[cfg(test)]").next().unwrap();
+            for (n, line) in code.lines().enumerate() {
+                let is_std = ["HashMap", "HashSet", "RandomState"].iter().any(|name| {
+                    line.match_indices(name).any(|(k, _)| {
+                        !line[..k].ends_with("Fx") && !line.trim_start().starts_with("//")
+                    })
+                });
+                if is_std {
+                    violations.push(format!("{}:{}: {}", path.display(), n + 1, line.trim()));
+                }
+            }
+        }
+        assert!(
+            violations.is_empty(),
+            "use `FxHashMap` or `FxHashSet` (or sort before printing):\n{}",
+            violations.join("\n")
+        );
+    }
 }
@@ -0,0 +1,309 @@
+/*!
+Resource limits of a run and their named presets (`--limits`), and limits on the size of an image.
+
+A preset gives a value to every limit, and the individual flags (`--max-iter`, `--stall-timeout`) override the values of the preset.
+Without a preset, only the individual flags are in effect, which is the same as [`LimitPreset::Unlimited`].
+
+The image limits ([`ImageLimits`]) are checked against the header of an image before it is decoded, so that a small file which declares huge dimensions (a decompression bomb) is rejected without allocating its pixels.
+They are always in effect, with defaults generous enough for any practical program.
+
+A run can also be stopped from another thread with a [`CancellationToken`], which is checked where the limits are.
+*/
+
+use std::fmt;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+use super::error::PietError;
+
+/// Named bundles of limits.
+#[derive(clap::ValueEnum, Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum LimitPreset {
+    /// Tight limits for untrusted programs (1e6 iterations, 2 seconds without I/O)
+    Playground,
+    /// Moderate limits for running submissions (1e8 iterations, 10 seconds without I/O)
+    Grading,
+    /// No limits
+    #[default]
+    Unlimited,
+}
+
+impl LimitPreset {
+    /// Returns the limits of the preset.
+    pub fn limits(self) -> Limits {
+        match self {
+            LimitPreset::Playground => Limits {
+                max_iter: Some(1_000_000),
+                stall_timeout: Some(2.0),
+            },
+            LimitPreset::Grading => Limits {
+                max_iter: Some(100_000_000),
+                stall_timeout: Some(10.0),
+            },
+            LimitPreset::Unlimited => Limits {
+                max_iter: None,
+                stall_timeout: None,
+            },
+        }
+    }
+}
+
+impl fmt::Display for LimitPreset {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let s = match self {
+            LimitPreset::Playground => "playground",
+            LimitPreset::Grading => "grading",
+            LimitPreset::Unlimited => "unlimited",
+        };
+        write!(f, "{}", s)
+    }
+}
+
+/// Limits in effect during a run, where `None` means no limit.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Limits {
+    /// Maximum number of iterations.
+    pub max_iter: Option<usize>,
+    /// Maximum number of seconds without input or output.
+    pub stall_timeout: Option<f64>,
+}
+
+impl Limits {
+    /// Returns the limits of `preset` (no limits if `None`) overridden by the individual values which are `Some`.
+    pub fn new(
+        preset: Option<LimitPreset>,
+        max_iter: Option<usize>,
+        stall_timeout: Option<f64>,
+    ) -> Self {
+        let limits = preset.unwrap_or_default().limits();
+        Self {
+            max_iter: max_iter.or(limits.max_iter),
+            stall_timeout: stall_timeout.or(limits.stall_timeout),
+        }
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        if self
+            .stall_timeout
+            .is_some_and(|t| !t.is_finite() || (t < 0.0))
+        {
+            return Err("`stall_timeout` shall be a non-negative number".to_string());
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for Limits {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.max_iter {
+            Some(n) => write!(f, "max-iter {}", n)?,
+            None => write!(f, "max-iter none")?,
+        }
+        match self.stall_timeout {
+            Some(t) => write!(f, ", stall-timeout {}s", t),
+            None => write!(f, ", stall-timeout none"),
+        }
+    }
+}
+
+/// Default of [`ImageLimits::max_pixels`].
+pub const DEFAULT_MAX_IMAGE_PIXELS: u64 = 1 << 28;
+
+/// Default of [`ImageLimits::max_dimension`].
+pub const DEFAULT_MAX_IMAGE_DIMENSION: u32 = 1 << 16;
+
+/// Default of [`ImageLimits::max_decoded_bytes`].
+pub const DEFAULT_MAX_IMAGE_BYTES: u64 = 1 << 30;
+
+/// Flag by which another thread stops a run (see [`RunnerConfig::cancellation`](super::runner::RunnerConfig::cancellation)).
+/// Clones share the flag.
+///
+/// The flag is checked before each step, so the run stops within a step of [`Self::cancel()`] with [`TerminationReason::Cancelled`](super::run_result::TerminationReason::Cancelled).
+#[derive(Debug, Clone, Default)]
+pub struct CancellationToken(Arc<AtomicBool>);
+
+impl CancellationToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+
+    /// Clears the flag, so that a run cancelled by [`Runner::step()`](super::runner::Runner::step) can be resumed.
+    pub fn reset(&self) {
+        self.0.store(false, Ordering::Relaxed);
+    }
+}
+
+/// Limits on the size of an image to load.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ImageLimits {
+    /// Maximum number of pixels (width times height).
+    pub max_pixels: u64,
+    /// Maximum width and height in pixels.
+    pub max_dimension: u32,
+    /// Maximum number of bytes of the decoded pixels, which also bounds the allocations of the decoder.
+    pub max_decoded_bytes: u64,
+}
+
+impl Default for ImageLimits {
+    fn default() -> Self {
+        Self {
+            max_pixels: DEFAULT_MAX_IMAGE_PIXELS,
+            max_dimension: DEFAULT_MAX_IMAGE_DIMENSION,
+            max_decoded_bytes: DEFAULT_MAX_IMAGE_BYTES,
+        }
+    }
+}
+
+/// The limit of [`ImageLimits`] which an image exceeds.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImageLimit {
+    Dimension(u32),
+    Pixels(u64),
+    DecodedBytes { bytes: u64, limit: u64 },
+}
+
+impl ImageLimits {
+    /// Checks an image of `height x width` pixels which is decoded into `decoded_bytes` bytes (`None` if not known).
+    pub fn check(
+        &self,
+        height: u64,
+        width: u64,
+        decoded_bytes: Option<u64>,
+    ) -> Result<(), PietError> {
+        let exceeded = if height.max(width) > u64::from(self.max_dimension) {
+            Some(ImageLimit::Dimension(self.max_dimension))
+        } else if height.saturating_mul(width) > self.max_pixels {
+            Some(ImageLimit::Pixels(self.max_pixels))
+        } else {
+            decoded_bytes
+                .filter(|bytes| *bytes > self.max_decoded_bytes)
+                .map(|bytes| ImageLimit::DecodedBytes {
+                    bytes,
+                    limit: self.max_decoded_bytes,
+                })
+        };
+        match exceeded {
+            None => Ok(()),
+            Some(exceeded) => Err(PietError::ImageTooLarge {
+                image_height: height,
+                image_width: width,
+                exceeded,
+            }),
+        }
+    }
+
+    /// Returns the limits passed to the decoder of the `image` crate.
+    pub(crate) fn to_decoder_limits(self) -> image::Limits {
+        let mut limits = image::Limits::default();
+        limits.max_image_width = Some(self.max_dimension);
+        limits.max_image_height = Some(self.max_dimension);
+        limits.max_alloc = Some(self.max_decoded_bytes);
+        limits
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_presets() {
+        assert_eq!(
+            Limits {
+                max_iter: Some(1_000_000),
+                stall_timeout: Some(2.0),
+            },
+            LimitPreset::Playground.limits()
+        );
+        assert_eq!(
+            Limits {
+                max_iter: Some(100_000_000),
+                stall_timeout: Some(10.0),
+            },
+            LimitPreset::Grading.limits()
+        );
+        assert_eq!(
+            Limits {
+                max_iter: None,
+                stall_timeout: None,
+            },
+            LimitPreset::Unlimited.limits()
+        );
+        assert_eq!(
+            LimitPreset::Unlimited.limits(),
+            Limits::new(None, None, None)
+        );
+    }
+
+    #[test]
+    fn test_override() {
+        //the individual values take precedence over the preset
+        let limits = Limits::new(Some(LimitPreset::Playground), Some(5), None);
+        assert_eq!(Some(5), limits.max_iter);
+        assert_eq!(Some(2.0), limits.stall_timeout);
+        let limits = Limits::new(Some(LimitPreset::Unlimited), None, Some(0.5));
+        assert_eq!(None, limits.max_iter);
+        assert_eq!(Some(0.5), limits.stall_timeout);
+        let limits = Limits::new(None, Some(7), Some(1.0));
+        assert_eq!(Some(7), limits.max_iter);
+        assert_eq!(Some(1.0), limits.stall_timeout);
+
+        assert_eq!(
+            "max-iter 1000000, stall-timeout 2s",
+            LimitPreset::Playground.limits().to_string()
+        );
+        assert_eq!(
+            "max-iter none, stall-timeout none",
+            LimitPreset::Unlimited.limits().to_string()
+        );
+    }
+
+    #[test]
+    fn test_validate() {
+        //zero timeout with unlimited iterations is allowed
+        assert!(Limits::new(None, None, Some(0.0)).validate().is_ok());
+        assert!(Limits::new(Some(LimitPreset::Grading), None, Some(-1.0))
+            .validate()
+            .is_err());
+        assert!(Limits::new(None, None, Some(f64::NAN)).validate().is_err());
+    }
+
+    #[test]
+    fn test_image_limits() {
+        let limits = ImageLimits {
+            max_pixels: 100,
+            max_dimension: 20,
+            max_decoded_bytes: 300,
+        };
+        assert!(limits.check(10, 10, Some(300)).is_ok());
+        assert!(limits.check(20, 1, None).is_ok());
+        let exceeded = |height, width, bytes| match limits.check(height, width, bytes) {
+            Err(PietError::ImageTooLarge {
+                image_height,
+                image_width,
+                exceeded,
+            }) => {
+                assert_eq!((height, width), (image_height, image_width));
+                Some(exceeded)
+            }
+            _ => None,
+        };
+        assert_eq!(Some(ImageLimit::Dimension(20)), exceeded(1, 21, None));
+        assert_eq!(Some(ImageLimit::Pixels(100)), exceeded(11, 10, None));
+        assert_eq!(
+            Some(ImageLimit::DecodedBytes {
+                bytes: 400,
+                limit: 300
+            }),
+            exceeded(10, 10, Some(400))
+        );
+    }
+}
@@ -0,0 +1,546 @@
+/*!
+Static lints of a program (`--check`).
+
+The lints work on the blocks which may be reached from the starting codel.
+Since `Pointer`, `Switch` and the retries can select any `(DP, CC)`, every exit of a reached coloured block is assumed to be taken, and a slide across a white block is assumed to be able to end in any block next to it.
+This over-approximates the state graph, so a lint may fire for a transition which never happens in practice, but never misses one.
+
+# `large-push`
+
+A `Push` pushes the size of the block the interpreter leaves.
+A huge block at the source of a `Push` (e.g. a flood-filled background) is almost always unintended, so the lint fires for a `Push` from a block whose size exceeds a threshold, or from the background block (see [`Program::background_block()`]) whatever its size.
+With [`LintOptions::ignore_background`], the background block is not linted at all, which the caller reports so that a clean result is not mistaken for a checked background.
+
+# `large-start-block`
+
+The first `Push` of a program is usually meant to push a small constant from the block of the starting codel.
+When the start block accidentally touches a decorative region (e.g. a banner of the same colour), it pushes hundreds instead, and everything after it breaks in confusing ways.
+So the lint fires when the start block, as the source of a `Push`, is larger than a threshold, reporting its bounding box and the thin bridges to cut (see [`StartBlock`]).
+
+A bridge is a codel of the block with exactly two neighbours in it whose removal disconnects the block (an articulation point of the graph of the codels), found by Tarjan's algorithm.
+Only the bridges whose removal leaves at most the threshold connected to the starting codel are suggested, and of them those which keep the most.
+`--check-runtime` raises the same finding when the first `Push` of a run is from the start block.
+*/
+
+use std::fmt;
+
+use rustc_hash::FxHashMap;
+
+use super::cc::CC;
+use super::command::Command;
+use super::dp::DP;
+use super::image::Image;
+use super::program::{Background, Program};
+
+/// Default of [`LintOptions::large_push_threshold`].
+pub const DEFAULT_LARGE_PUSH_THRESHOLD: usize = 10_000;
+
+/// Default of [`LintOptions::start_block_threshold`], above the codes of ASCII so that a start block pushing a character is not reported.
+pub const DEFAULT_START_BLOCK_THRESHOLD: usize = 128;
+
+/// Options of [`lint()`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LintOptions {
+    /// A `Push` from a block larger than this is reported.
+    pub large_push_threshold: usize,
+    /// Disables the `large-push` lint.
+    pub allow_large_push: bool,
+    pub background: Background,
+    /// Skips the background block.
+    pub ignore_background: bool,
+    /// The start block larger than this is reported.
+    pub start_block_threshold: usize,
+    /// Disables the `large-start-block` lint.
+    pub allow_large_start_block: bool,
+}
+
+impl Default for LintOptions {
+    fn default() -> Self {
+        Self {
+            large_push_threshold: DEFAULT_LARGE_PUSH_THRESHOLD,
+            allow_large_push: false,
+            background: Background::Auto,
+            ignore_background: false,
+            start_block_threshold: DEFAULT_START_BLOCK_THRESHOLD,
+            allow_large_start_block: false,
+        }
+    }
+}
+
+/// The start block larger than a threshold, found by [`start_block()`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StartBlock {
+    /// First codel of the block.
+    pub pos: (usize, usize),
+    pub size: usize,
+    pub threshold: usize,
+    /// Top-left and bottom-right corners of the bounding box.
+    pub bounds: ((usize, usize), (usize, usize)),
+    /// Bridges, in reading order, each of which alone leaves `kept` codels connected to the starting codel when it is removed.
+    pub bridges: Vec<(usize, usize)>,
+    pub kept: usize,
+}
+
+impl fmt::Display for StartBlock {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let ((top, left), (bottom, right)) = self.bounds;
+        write!(
+            f,
+            "the start block at {:?} of size {} (more than {}) spans rows {} to {} and columns {} to {}",
+            self.pos, self.size, self.threshold, top, bottom, left, right
+        )?;
+        if self.bridges.is_empty() {
+            return write!(f, ", with no 1-codel bridge to cut");
+        }
+        let bridges = self
+            .bridges
+            .iter()
+            .map(|pos| format!("{:?}", pos))
+            .collect::<Vec<_>>()
+            .join(" or ");
+        write!(
+            f,
+            "; cutting the 1-codel bridge at {} leaves {} codels in it",
+            bridges, self.kept
+        )
+    }
+}
+
+/// A finding of [`lint()`], where `pos` is the first codel of the block in reading order.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Warning {
+    /// A block larger than the threshold is the source of a `Push`.
+    LargePush {
+        pos: (usize, usize),
+        size: usize,
+        threshold: usize,
+    },
+    /// The background block is the source of a `Push`.
+    BackgroundPush { pos: (usize, usize), size: usize },
+    /// The start block larger than the threshold is the source of a `Push`.
+    LargeStartBlock(StartBlock),
+}
+
+impl fmt::Display for Warning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Warning::LargePush {
+                pos,
+                size,
+                threshold,
+            } => write!(
+                f,
+                "large-push: the block at {:?} of size {} (more than {}) is the source of a Push",
+                pos, size, threshold
+            ),
+            Warning::BackgroundPush { pos, size } => write!(
+                f,
+                "large-push: the background block at {:?} of size {} is the source of a Push",
+                pos, size
+            ),
+            Warning::LargeStartBlock(block) => write!(f, "large-start-block: {}", block),
+        }
+    }
+}
+
+/// Returns the IDs of the blocks which may be reached from `start`, in ascending order.
+fn reachable_blocks(program: &Program, start: (usize, usize)) -> Vec<usize> {
+    let img = program.image();
+    let mut is_reached = vec![false; img.num_blocks()];
+    let mut stack = vec![img.get_block_id_at_unchecked(start)];
+    is_reached[stack[0]] = true;
+    while let Some(id) = stack.pop() {
+        let block = img.get_block(id).unwrap();
+        let first = block.first_codel();
+        let nexts = if img.get_codel_at_unchecked(first).is_white() {
+            block
+                .codels()
+                .flat_map(|pos| {
+                    [DP::Right, DP::Down, DP::Left, DP::Up]
+                        .map(|dp| img.get_next_codel_index_in_dp_direction_unchecked(pos, &dp))
+                })
+                .flatten()
+                .filter(|next| !img.get_codel_at_unchecked(*next).is_black())
+                .collect::<Vec<_>>()
+        } else {
+            [DP::Right, DP::Down, DP::Left, DP::Up]
+                .iter()
+                .flat_map(|dp| [CC::Left, CC::Right].map(|cc| program.get_exit(first, dp, &cc)))
+                .flatten()
+                .collect()
+        };
+        for next in nexts {
+            let next_id = img.get_block_id_at_unchecked(next);
+            if !is_reached[next_id] {
+                is_reached[next_id] = true;
+                stack.push(next_id);
+            }
+        }
+    }
+    (0..img.num_blocks()).filter(|id| is_reached[*id]).collect()
+}
+
+/// Returns `true` if leaving the block `id` may execute `Push`.
+fn is_push_source(program: &Program, id: usize) -> bool {
+    let img = program.image();
+    let first = img.get_block(id).unwrap().first_codel();
+    let from = img.get_codel_at_unchecked(first);
+    if from.is_white() || from.is_black() || from.is_other() {
+        return false;
+    }
+    [DP::Right, DP::Down, DP::Left, DP::Up].iter().any(|dp| {
+        [CC::Left, CC::Right].iter().any(|cc| {
+            program.get_exit(first, dp, cc).is_some_and(|next| {
+                let to = img.get_codel_at_unchecked(next);
+                //a move into a block of the same colour (only on a torus) executes nothing
+                !to.is_white()
+                    && !to.is_other()
+                    && (to != from)
+                    && (Command::new(from, to) == Command::Push)
+            })
+        })
+    })
+}
+
+/// Returns the bridges of the block of `start` which leave at most `threshold` codels connected to `start`, and the number of the codels they leave.
+/// Of such bridges, only those which leave the most are returned.
+fn bridges(img: &Image, start: (usize, usize), threshold: usize) -> (Vec<(usize, usize)>, usize) {
+    const UNVISITED: usize = usize::MAX;
+    let block = img.get_block(img.get_block_id_at_unchecked(start)).unwrap();
+    let codels = block.codels().collect::<Vec<_>>();
+    let index = codels
+        .iter()
+        .enumerate()
+        .map(|(i, pos)| (*pos, i))
+        .collect::<FxHashMap<_, _>>();
+    let neighbor = |v: usize, k: usize| {
+        let (i, j) = codels[v];
+        let pos = match k {
+            0 => (i.wrapping_sub(1), j),
+            1 => (i, j + 1),
+            2 => (i + 1, j),
+            _ => (i, j.wrapping_sub(1)),
+        };
+        index.get(&pos).copied()
+    };
+
+    //Tarjan's algorithm, iteratively as a block can have millions of codels
+    //`cut_off[v]` is the number of the codels disconnected from `start` when `v` is removed.
+    let n = codels.len();
+    let mut order = vec![UNVISITED; n];
+    let mut low = vec![0; n];
+    let mut subtree = vec![1; n];
+    let mut cut_off = vec![0; n];
+    let root = index[&start];
+    order[root] = 0;
+    let mut num_visited = 1;
+    //each entry is a codel and the direction of the next neighbour to visit
+    let mut stack = vec![(root, 0)];
+    while let Some((v, k)) = stack.last_mut() {
+        let v = *v;
+        if *k < 4 {
+            let w = neighbor(v, *k);
+            *k += 1;
+            match w {
+                Some(w) if order[w] == UNVISITED => {
+                    order[w] = num_visited;
+                    low[w] = num_visited;
+                    num_visited += 1;
+                    stack.push((w, 0));
+                }
+                Some(w) => low[v] = low[v].min(order[w]),
+                None => (),
+            }
+            continue;
+        }
+        stack.pop();
+        if let Some(&(u, _)) = stack.last() {
+            low[u] = low[u].min(low[v]);
+            subtree[u] += subtree[v];
+            if low[v] >= order[u] {
+                cut_off[u] += subtree[v];
+            }
+        }
+    }
+
+    let is_bridge = |v: usize| {
+        (v != root)
+            && (cut_off[v] != 0)
+            && ((0..4).filter(|k| neighbor(v, *k).is_some()).count() == 2)
+    };
+    let kept = |v: usize| n - 1 - cut_off[v];
+    let candidates = (0..n)
+        .filter(|v| is_bridge(*v) && (kept(*v) <= threshold))
+        .collect::<Vec<_>>();
+    let Some(max_kept) = candidates.iter().map(|v| kept(*v)).max() else {
+        return (vec![], 0);
+    };
+    let bridges = candidates
+        .into_iter()
+        .filter(|v| kept(*v) == max_kept)
+        .map(|v| codels[v])
+        .collect();
+    (bridges, max_kept)
+}
+
+/// Returns the block of `start` if it is larger than `threshold`, with the bridges to cut.
+pub fn start_block(img: &Image, start: (usize, usize), threshold: usize) -> Option<StartBlock> {
+    let block = img.get_block(img.get_block_id_at_unchecked(start)).unwrap();
+    if block.size <= threshold {
+        return None;
+    }
+    let (mut top_left, mut bottom_right) = (start, start);
+    for (i, j) in block.codels() {
+        top_left = (top_left.0.min(i), top_left.1.min(j));
+        bottom_right = (bottom_right.0.max(i), bottom_right.1.max(j));
+    }
+    let (bridges, kept) = bridges(img, start, threshold);
+    Some(StartBlock {
+        pos: block.first_codel(),
+        size: block.size,
+        threshold,
+        bounds: (top_left, bottom_right),
+        bridges,
+        kept,
+    })
+}
+
+/// Lints the program which starts at `start`.
+pub fn lint(program: &Program, start: (usize, usize), options: &LintOptions) -> Vec<Warning> {
+    let img = program.image();
+    let mut warnings = vec![];
+    if !options.allow_large_start_block
+        && is_push_source(program, img.get_block_id_at_unchecked(start))
+    {
+        warnings.extend(
+            start_block(img, start, options.start_block_threshold).map(Warning::LargeStartBlock),
+        );
+    }
+    if options.allow_large_push {
+        return warnings;
+    }
+    let background = options.background.block(program);
+    warnings.extend(
+        reachable_blocks(program, start)
+            .into_iter()
+            .filter(|id| !(options.ignore_background && (Some(*id) == background)))
+            .filter(|id| is_push_source(program, *id))
+            .filter_map(|id| {
+                let block = img.get_block(id).unwrap();
+                let (pos, size) = (block.first_codel(), block.size);
+                if Some(id) == background {
+                    Some(Warning::BackgroundPush { pos, size })
+                } else if size > options.large_push_threshold {
+                    Some(Warning::LargePush {
+                        pos,
+                        size,
+                        threshold: options.large_push_threshold,
+                    })
+                } else {
+                    None
+                }
+            }),
+    );
+    warnings
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::codel::Codel;
+    use super::*;
+
+    fn lint_grid(m: Vec<Vec<Codel>>, threshold: usize) -> Vec<Warning> {
+        let options = LintOptions {
+            large_push_threshold: threshold,
+            ..Default::default()
+        };
+        lint(&Program::new(Image::from_codel_grid(m)), (0, 0), &options)
+    }
+
+    #[test]
+    fn test_large_push() {
+        let (lr, r, dr, k) = (Codel::LightRed, Codel::Red, Codel::DarkRed, Codel::Black);
+        //light red (6) -> red (12, background) -> dark red
+        let m = vec![
+            vec![lr, lr, lr, r, r, r, dr],
+            vec![lr, lr, lr, r, r, r, dr],
+            vec![k, k, k, r, r, r, dr],
+            vec![k, k, k, r, r, r, dr],
+        ];
+        assert_eq!(
+            vec![
+                Warning::LargePush {
+                    pos: (0, 0),
+                    size: 6,
+                    threshold: 5,
+                },
+                Warning::BackgroundPush {
+                    pos: (0, 3),
+                    size: 12,
+                },
+            ],
+            lint_grid(m.clone(), 5)
+        );
+        assert_eq!(
+            vec![Warning::BackgroundPush {
+                pos: (0, 3),
+                size: 12,
+            }],
+            lint_grid(m.clone(), 6)
+        );
+        assert_eq!(
+            "large-push: the block at (0, 0) of size 6 (more than 5) is the source of a Push",
+            lint_grid(m.clone(), 5)[0].to_string()
+        );
+
+        let options = LintOptions {
+            large_push_threshold: 0,
+            allow_large_push: true,
+            ..Default::default()
+        };
+        assert!(lint(&Program::new(Image::from_codel_grid(m)), (0, 0), &options).is_empty());
+    }
+
+    #[test]
+    fn test_background() {
+        let (lr, r, dr, k) = (Codel::LightRed, Codel::Red, Codel::DarkRed, Codel::Black);
+        let program = Program::new(Image::from_codel_grid(vec![
+            vec![lr, lr, lr, r, r, r, dr],
+            vec![lr, lr, lr, r, r, r, dr],
+            vec![k, k, k, r, r, r, dr],
+            vec![k, k, k, r, r, r, dr],
+        ]));
+        let lint = |background, ignore_background| {
+            lint(
+                &program,
+                (0, 0),
+                &LintOptions {
+                    large_push_threshold: 5,
+                    background,
+                    ignore_background,
+                    ..Default::default()
+                },
+            )
+        };
+        let large_push = Warning::LargePush {
+            pos: (0, 0),
+            size: 6,
+            threshold: 5,
+        };
+        assert_eq!(vec![large_push.clone()], lint(Background::Auto, true));
+        //without a background, the large block is linted by its size
+        let large_background = Warning::LargePush {
+            pos: (0, 3),
+            size: 12,
+            threshold: 5,
+        };
+        assert_eq!(
+            vec![large_push.clone(), large_background.clone()],
+            lint(Background::None, false)
+        );
+        assert_eq!(
+            vec![large_push, large_background],
+            lint(Background::None, true)
+        );
+    }
+
+    #[test]
+    fn test_reachability() {
+        let (w, r, dr, b, k) = (
+            Codel::White,
+            Codel::Red,
+            Codel::DarkRed,
+            Codel::Blue,
+            Codel::Black,
+        );
+        //the red block on the right is reached only through the white block
+        let m = vec![
+            vec![b, w, w, r, dr],
+            vec![k, k, k, k, k],
+            vec![r, r, r, dr, b],
+        ];
+        let program = Program::new(Image::from_codel_grid(m));
+        assert_eq!(vec![0, 1, 2, 3], reachable_blocks(&program, (0, 0)));
+        //the push from the red block on the bottom row is not reachable
+        assert_eq!(
+            vec![Warning::LargePush {
+                pos: (0, 3),
+                size: 1,
+                threshold: 0,
+            }],
+            lint(
+                &program,
+                (0, 0),
+                &LintOptions {
+                    large_push_threshold: 0,
+                    ..Default::default()
+                }
+            )
+        );
+    }
+
+    #[test]
+    fn test_start_block() {
+        let (r, dr, k) = (Codel::Red, Codel::DarkRed, Codel::Black);
+        //the start block (6 codels) is joined to a banner (8 codels) through the bridge at (2, 1)
+        let m = vec![
+            vec![r, r, r, k, k],
+            vec![r, r, r, k, k],
+            vec![k, r, k, k, k],
+            vec![r, r, r, r, dr],
+            vec![r, r, r, r, dr],
+        ];
+        let program = Program::new(Image::from_codel_grid(m.clone()));
+        let lint = |threshold, allow_large_start_block| {
+            lint(
+                &program,
+                (0, 0),
+                &LintOptions {
+                    background: Background::None,
+                    start_block_threshold: threshold,
+                    allow_large_start_block,
+                    ..Default::default()
+                },
+            )
+        };
+        let expected = StartBlock {
+            pos: (0, 0),
+            size: 15,
+            threshold: 6,
+            bounds: ((0, 0), (4, 3)),
+            bridges: vec![(2, 1)],
+            kept: 6,
+        };
+        assert_eq!(
+            vec![Warning::LargeStartBlock(expected.clone())],
+            lint(6, false)
+        );
+        assert_eq!(
+            "large-start-block: the start block at (0, 0) of size 15 (more than 6) spans rows 0 to 4 and columns 0 to 3; cutting the 1-codel bridge at (2, 1) leaves 6 codels in it",
+            lint(6, false)[0].to_string()
+        );
+        assert!(lint(6, true).is_empty());
+        assert!(lint(15, false).is_empty());
+        //no bridge leaves few enough codels
+        assert_eq!(
+            Some(StartBlock {
+                threshold: 5,
+                bridges: vec![],
+                kept: 0,
+                ..expected
+            }),
+            start_block(program.image(), (0, 0), 5)
+        );
+        assert_eq!(
+            "the start block at (0, 0) of size 15 (more than 5) spans rows 0 to 4 and columns 0 to 3, with no 1-codel bridge to cut",
+            start_block(program.image(), (0, 0), 5).unwrap().to_string()
+        );
+
+        //of the bridges of a longer one, the last one before the banner is suggested
+        let mut m = m;
+        m.insert(2, vec![k, r, k, k, k]);
+        let img = Image::from_codel_grid(m);
+        let block = start_block(&img, (0, 0), 7).unwrap();
+        assert_eq!((vec![(3, 1)], 7), (block.bridges, block.kept));
+    }
+}
@@ -1,11 +1,47 @@
-use std::error::Error;
+#![forbid(unsafe_code)]
 
-use clap::Parser;
+use std::process::ExitCode;
 
-use piet_programming_language::args::Args;
+use piet_programming_language::args::{Cli, Subcommand};
+use piet_programming_language::error::PietError;
+use piet_programming_language::exit_status::ExitStatus;
 
-fn main() -> Result<(), Box<dyn Error>> {
-    let args = Args::parse();
-    args.validate()?;
-    piet_programming_language::run(&args)
+fn main() -> ExitCode {
+    let cli = match Cli::try_parse_with_default_subcommand(std::env::args()) {
+        Ok(cli) => cli,
+        Err(e) => {
+            let _ = e.print();
+            //`--help` and `--version` are also reported as errors
+            return if e.use_stderr() {
+                ExitStatus::IoOrUsageError.into()
+            } else {
+                ExitCode::SUCCESS
+            };
+        }
+    };
+    let result = cli
+        .validate()
+        .map_err(Into::into)
+        .and_then(|()| match &cli.command {
+            Subcommand::Run(args) => piet_programming_language::run(args),
+            Subcommand::Golf(args) => piet_programming_language::golf(args),
+            Subcommand::Export(args) => piet_programming_language::export(args),
+            Subcommand::Compare(args) => piet_programming_language::compare(args),
+            Subcommand::TraceDiff(args) => piet_programming_language::trace_diff(args),
+            Subcommand::VersionReport(args) => piet_programming_language::version_report(args),
+            Subcommand::Conformance(args) => piet_programming_language::conformance(args),
+        });
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        //The reader of the output has gone away (e.g. `| head`), which ends the run as it would end any other program.
+        Err(e) if e.downcast_ref().is_some_and(PietError::is_broken_pipe) => ExitCode::SUCCESS,
+        Err(e) => {
+            match e.downcast_ref() {
+                //The reason has already been printed when the program was terminated.
+                Some(PietError::LimitReached(_)) => (),
+                _ => eprintln!("Error: {}", e),
+            }
+            ExitStatus::of_error(e.as_ref()).into()
+        }
+    }
 }
@@ -0,0 +1,384 @@
+/*!
+Operational counters of the runs, in the OpenMetrics text format (`--metrics-file`).
+
+A service which runs many programs shares one [`Metrics`] between its [`Runner`](super::runner::Runner)s (see [`RunnerConfig::metrics`](super::runner::RunnerConfig::metrics)) and its [`InterpreterPool`](super::pool::InterpreterPool) (see [`InterpreterPool::with_metrics()`](super::pool::InterpreterPool::with_metrics)),
+and renders it with [`Metrics::render()`], e.g. into a textfile read by the node exporter of Prometheus or as the body of an HTTP response.
+The counters are relaxed atomics, so recording costs a few atomic additions per run and nothing per step.
+
+The names and labels of the metrics are part of the interface, and are checked by `test_render`:
+
+| Metric | Type | Labels |
+| --- | --- | --- |
+| `piet_runs_started_total` | counter | |
+| `piet_runs_finished_total` | counter | `reason`: `no_exit`, `white_retrace`, `max_iter`, `stall`, `cancelled` or `error` |
+| `piet_steps_total` | counter | |
+| `piet_commands_total` | counter | |
+| `piet_input_bytes_total` | counter | |
+| `piet_output_bytes_total` | counter | |
+| `piet_image_cache_lookups_total` | counter | `result`: `hit` or `miss` |
+| `piet_interpreter_pool_checkouts_total` | counter | `result`: `reused` or `new` |
+| `piet_run_duration_seconds` | histogram | `le`: [`DURATION_BUCKETS`] and `+Inf` |
+
+A run which fails (e.g. on an unknown color) is counted with the reason `error`, including the steps it took before the failure.
+*/
+
+use std::fmt::Write;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+use super::interpreter::Interpreter;
+use super::run_result::TerminationReason;
+
+/// Upper bounds (in seconds) of the buckets of `piet_run_duration_seconds`.
+pub const DURATION_BUCKETS: [f64; 6] = [0.001, 0.01, 0.1, 1.0, 10.0, 60.0];
+
+/// Values of the label `reason` of `piet_runs_finished_total`, in the order they are rendered.
+const REASONS: [&str; 6] = [
+    "no_exit",
+    "white_retrace",
+    "max_iter",
+    "stall",
+    "cancelled",
+    "error",
+];
+
+/// What a finished run adds to the counters.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct RunCounts {
+    pub num_steps: usize,
+    pub num_commands: usize,
+    pub num_input_bytes: usize,
+    pub num_output_bytes: usize,
+}
+
+impl RunCounts {
+    /// Counts of the current (or last) run of `ip`.
+    pub fn of(ip: &Interpreter) -> Self {
+        Self {
+            num_steps: ip.num_steps,
+            num_commands: ip.num_commands,
+            num_input_bytes: ip.stdin.num_bytes_read(),
+            num_output_bytes: ip.num_output_bytes,
+        }
+    }
+}
+
+/// Counters shared by the runs of a process.
+#[derive(Debug, Default)]
+pub struct Metrics {
+    runs_started: AtomicU64,
+    /// Indexed as [`REASONS`].
+    runs_finished: [AtomicU64; REASONS.len()],
+    steps: AtomicU64,
+    commands: AtomicU64,
+    input_bytes: AtomicU64,
+    output_bytes: AtomicU64,
+    image_cache_hits: AtomicU64,
+    image_cache_misses: AtomicU64,
+    pool_reused: AtomicU64,
+    pool_new: AtomicU64,
+    /// Number of runs in each bucket of [`DURATION_BUCKETS`] (not cumulative), the last one being `+Inf`.
+    durations: [AtomicU64; DURATION_BUCKETS.len() + 1],
+    duration_sum_micros: AtomicU64,
+}
+
+fn add(counter: &AtomicU64, n: usize) {
+    counter.fetch_add(n as u64, Ordering::Relaxed);
+}
+
+fn get(counter: &AtomicU64) -> u64 {
+    counter.load(Ordering::Relaxed)
+}
+
+impl Metrics {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn record_start(&self) {
+        add(&self.runs_started, 1);
+    }
+
+    /// Records a run which terminated by `reason`, or failed if it is `None`, after `duration`.
+    pub fn record_finish(
+        &self,
+        reason: Option<TerminationReason>,
+        counts: RunCounts,
+        duration: Duration,
+    ) {
+        let i = match reason {
+            Some(TerminationReason::NoExit) => 0,
+            Some(TerminationReason::WhiteRetrace) => 1,
+            Some(TerminationReason::MaxIter) => 2,
+            Some(TerminationReason::Stall) => 3,
+            Some(TerminationReason::Cancelled) => 4,
+            None => 5,
+        };
+        add(&self.runs_finished[i], 1);
+        add(&self.steps, counts.num_steps);
+        add(&self.commands, counts.num_commands);
+        add(&self.input_bytes, counts.num_input_bytes);
+        add(&self.output_bytes, counts.num_output_bytes);
+        let secs = duration.as_secs_f64();
+        let bucket = DURATION_BUCKETS
+            .iter()
+            .position(|&le| secs <= le)
+            .unwrap_or(DURATION_BUCKETS.len());
+        add(&self.durations[bucket], 1);
+        add(&self.duration_sum_micros, duration.as_micros() as usize);
+    }
+
+    /// Records a lookup of the image cache (`--cache-dir`).
+    pub fn record_image_cache(&self, is_hit: bool) {
+        add(
+            if is_hit {
+                &self.image_cache_hits
+            } else {
+                &self.image_cache_misses
+            },
+            1,
+        );
+    }
+
+    /// Records a checkout of an [`InterpreterPool`](super::pool::InterpreterPool).
+    pub fn record_pool_checkout(&self, is_reused: bool) {
+        add(
+            if is_reused {
+                &self.pool_reused
+            } else {
+                &self.pool_new
+            },
+            1,
+        );
+    }
+
+    /// Returns the metrics in the OpenMetrics text format, ending with `# EOF`.
+    pub fn render(&self) -> String {
+        let mut s = String::new();
+        let mut family = |name: &str, kind: &str, help: &str, samples: &[(String, u64)]| {
+            writeln!(s, "# TYPE {} {}", name, kind).unwrap();
+            writeln!(s, "# HELP {} {}", name, help).unwrap();
+            for (sample, value) in samples {
+                writeln!(s, "{}{} {}", name, sample, value).unwrap();
+            }
+        };
+        let total = |value: &AtomicU64| vec![("_total".to_string(), get(value))];
+        let labeled = |label: &str, values: &[(&str, &AtomicU64)]| {
+            values
+                .iter()
+                .map(|(v, c)| (format!("_total{{{}=\"{}\"}}", label, v), get(c)))
+                .collect::<Vec<_>>()
+        };
+
+        family(
+            "piet_runs_started",
+            "counter",
+            "Runs started.",
+            &total(&self.runs_started),
+        );
+        family(
+            "piet_runs_finished",
+            "counter",
+            "Runs finished, by the reason of the termination.",
+            &labeled(
+                "reason",
+                &REASONS
+                    .iter()
+                    .copied()
+                    .zip(&self.runs_finished)
+                    .collect::<Vec<_>>(),
+            ),
+        );
+        family(
+            "piet_steps",
+            "counter",
+            "Steps of the finished runs.",
+            &total(&self.steps),
+        );
+        family(
+            "piet_commands",
+            "counter",
+            "Commands executed by the finished runs.",
+            &total(&self.commands),
+        );
+        family(
+            "piet_input_bytes",
+            "counter",
+            "Bytes of the input read by the finished runs.",
+            &total(&self.input_bytes),
+        );
+        family(
+            "piet_output_bytes",
+            "counter",
+            "Bytes written by the finished runs.",
+            &total(&self.output_bytes),
+        );
+        family(
+            "piet_image_cache_lookups",
+            "counter",
+            "Lookups of the image cache.",
+            &labeled(
+                "result",
+                &[
+                    ("hit", &self.image_cache_hits),
+                    ("miss", &self.image_cache_misses),
+                ],
+            ),
+        );
+        family(
+            "piet_interpreter_pool_checkouts",
+            "counter",
+            "Checkouts of interpreters from the pool.",
+            &labeled(
+                "result",
+                &[("reused", &self.pool_reused), ("new", &self.pool_new)],
+            ),
+        );
+
+        let mut samples = vec![];
+        let mut count = 0;
+        for (i, n) in self.durations.iter().enumerate() {
+            count += get(n);
+            let le = DURATION_BUCKETS
+                .get(i)
+                .map_or("+Inf".to_string(), |le| format!("{:?}", le));
+            samples.push((format!("_bucket{{le=\"{}\"}}", le), count));
+        }
+        samples.push(("_count".to_string(), count));
+        family(
+            "piet_run_duration_seconds",
+            "histogram",
+            "Wall-clock durations of the finished runs.",
+            &samples,
+        );
+        let micros = get(&self.duration_sum_micros);
+        writeln!(
+            s,
+            "piet_run_duration_seconds_sum {}.{:06}",
+            micros / 1_000_000,
+            micros % 1_000_000
+        )
+        .unwrap();
+        s.push_str("# EOF\n");
+        s
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::super::codel::Codel;
+    use super::super::image::Image;
+    use super::super::limits::Limits;
+    use super::super::program::Program;
+    use super::super::runner::{Output, Runner, RunnerConfig};
+    use super::*;
+
+    /// A batch of two jobs: one stopped by `max-iter` and one which failed.
+    #[test]
+    fn test_render() {
+        let metrics = Metrics::new();
+        metrics.record_start();
+        metrics.record_pool_checkout(false);
+        metrics.record_image_cache(false);
+        metrics.record_finish(
+            Some(TerminationReason::MaxIter),
+            RunCounts {
+                num_steps: 100,
+                num_commands: 40,
+                num_input_bytes: 3,
+                num_output_bytes: 12,
+            },
+            Duration::from_micros(2500),
+        );
+        metrics.record_start();
+        metrics.record_pool_checkout(true);
+        metrics.record_image_cache(true);
+        metrics.record_finish(
+            None,
+            RunCounts {
+                num_steps: 7,
+                num_commands: 2,
+                num_input_bytes: 0,
+                num_output_bytes: 0,
+            },
+            Duration::from_millis(1500),
+        );
+        assert_eq!(
+            "\
+# TYPE piet_runs_started counter
+# HELP piet_runs_started Runs started.
+piet_runs_started_total 2
+# TYPE piet_runs_finished counter
+# HELP piet_runs_finished Runs finished, by the reason of the termination.
+piet_runs_finished_total{reason=\"no_exit\"} 0
+piet_runs_finished_total{reason=\"white_retrace\"} 0
+piet_runs_finished_total{reason=\"max_iter\"} 1
+piet_runs_finished_total{reason=\"stall\"} 0
+piet_runs_finished_total{reason=\"cancelled\"} 0
+piet_runs_finished_total{reason=\"error\"} 1
+# TYPE piet_steps counter
+# HELP piet_steps Steps of the finished runs.
+piet_steps_total 107
+# TYPE piet_commands counter
+# HELP piet_commands Commands executed by the finished runs.
+piet_commands_total 42
+# TYPE piet_input_bytes counter
+# HELP piet_input_bytes Bytes of the input read by the finished runs.
+piet_input_bytes_total 3
+# TYPE piet_output_bytes counter
+# HELP piet_output_bytes Bytes written by the finished runs.
+piet_output_bytes_total 12
+# TYPE piet_image_cache_lookups counter
+# HELP piet_image_cache_lookups Lookups of the image cache.
+piet_image_cache_lookups_total{result=\"hit\"} 1
+piet_image_cache_lookups_total{result=\"miss\"} 1
+# TYPE piet_interpreter_pool_checkouts counter
+# HELP piet_interpreter_pool_checkouts Checkouts of interpreters from the pool.
+piet_interpreter_pool_checkouts_total{result=\"reused\"} 1
+piet_interpreter_pool_checkouts_total{result=\"new\"} 1
+# TYPE piet_run_duration_seconds histogram
+# HELP piet_run_duration_seconds Wall-clock durations of the finished runs.
+piet_run_duration_seconds_bucket{le=\"0.001\"} 0
+piet_run_duration_seconds_bucket{le=\"0.01\"} 1
+piet_run_duration_seconds_bucket{le=\"0.1\"} 1
+piet_run_duration_seconds_bucket{le=\"1.0\"} 1
+piet_run_duration_seconds_bucket{le=\"10.0\"} 2
+piet_run_duration_seconds_bucket{le=\"60.0\"} 2
+piet_run_duration_seconds_bucket{le=\"+Inf\"} 2
+piet_run_duration_seconds_count 2
+piet_run_duration_seconds_sum 1.502500
+# EOF
+",
+            metrics.render()
+        );
+    }
+
+    #[test]
+    fn test_runner() {
+        //pushes 3 and prints it as a number, and then wanders forever
+        let program = Program::new(Image::from_codel_grid(vec![vec![
+            Codel::Red,
+            Codel::Red,
+            Codel::Red,
+            Codel::DarkRed,
+            Codel::LightMagenta,
+        ]]));
+        let metrics = Arc::new(Metrics::new());
+        let mut runner = Runner::new(RunnerConfig {
+            limits: Limits::new(None, Some(2), None),
+            metrics: Some(metrics.clone()),
+            output: Output::Capture,
+            ..RunnerConfig::default()
+        });
+        runner.run(&program).unwrap();
+        runner.run(&program).unwrap();
+        let s = metrics.render();
+        assert!(s.contains("\npiet_runs_started_total 2\n"));
+        assert!(s.contains("\npiet_runs_finished_total{reason=\"max_iter\"} 2\n"));
+        assert!(s.contains("\npiet_steps_total 4\n"));
+        assert!(s.contains("\npiet_output_bytes_total 4\n"));
+        assert!(s.contains("\npiet_run_duration_seconds_count 2\n"));
+    }
+}
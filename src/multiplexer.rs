@@ -0,0 +1,183 @@
+/*!
+Synchronized output of the program and the path trace for verbose sessions on a terminal (`--sync-output`).
+
+When stdout and stderr are the same terminal, the output of the program (which need not end with a newline) and the lines of the trace are glued or torn.
+A [`Multiplexer`] writes both under one lock in the order in which they are produced,
+prefixes each line of the program output with [`OUTPUT_PREFIX`], and ends a line of the output left open before a line of the trace is written.
+The status line of `--progress` goes to the trace in the same way, and is cleared before anything else is written.
+The output is thus changed (the prefixes and the added line breaks), so this is meant only for reading a session, not for capturing the output.
+*/
+
+use std::io::{self, Write};
+use std::sync::Mutex;
+
+/// Prefix of each line of the program output.
+pub const OUTPUT_PREFIX: &str = "| ";
+
+/// Escape sequence which moves the cursor to the start of the line and clears the line.
+const CLEAR_LINE: &str = "\r\u{001B}[2K";
+
+/// When the output and the trace are synchronized.
+#[derive(clap::ValueEnum, Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum SyncOutput {
+    /// In verbose mode (or with `--trace-after`) when both stdout and stderr are terminals
+    #[default]
+    Auto,
+    /// In verbose mode (or with `--trace-after`)
+    Always,
+    Never,
+}
+
+struct State {
+    output: Box<dyn Write + Send>,
+    trace: Box<dyn Write + Send>,
+    /// Whether the last line of the output has not been ended yet.
+    is_output_line_open: bool,
+    /// Whether a status line is drawn at the cursor.
+    is_status_line_drawn: bool,
+}
+
+impl State {
+    fn clear_status_line(&mut self) -> io::Result<()> {
+        if self.is_status_line_drawn {
+            self.trace.write_all(CLEAR_LINE.as_bytes())?;
+            self.trace.flush()?;
+            self.is_status_line_drawn = false;
+        }
+        Ok(())
+    }
+
+    fn end_output_line(&mut self) -> io::Result<()> {
+        if self.is_output_line_open {
+            self.output.write_all(b"\n")?;
+            self.output.flush()?;
+            self.is_output_line_open = false;
+        }
+        Ok(())
+    }
+}
+
+/// Writer of the program output and the trace which keeps them in causal order.
+pub struct Multiplexer {
+    state: Mutex<State>,
+}
+
+impl Multiplexer {
+    pub fn new(output: Box<dyn Write + Send>, trace: Box<dyn Write + Send>) -> Self {
+        Self {
+            state: Mutex::new(State {
+                output,
+                trace,
+                is_output_line_open: false,
+                is_status_line_drawn: false,
+            }),
+        }
+    }
+
+    /// Writes the output to stdout and the trace to stderr.
+    pub fn stdio() -> Self {
+        Self::new(Box::new(io::stdout()), Box::new(io::stderr()))
+    }
+
+    /// Writes a line of the trace (without the line break), after ending the line of the output if it is open.
+    pub fn trace(&self, line: &str) -> io::Result<()> {
+        let mut state = self.state.lock().unwrap();
+        state.clear_status_line()?;
+        state.end_output_line()?;
+        writeln!(state.trace, "{}", line)?;
+        state.trace.flush()
+    }
+
+    /// Draws a status line (e.g. of `--progress`, which starts with a carriage return to redraw it in place), after ending the line of the output if it is open.
+    pub fn status(&self, line: &str) -> io::Result<()> {
+        let mut state = self.state.lock().unwrap();
+        state.end_output_line()?;
+        state.trace.write_all(line.as_bytes())?;
+        state.trace.flush()?;
+        state.is_status_line_drawn = true;
+        Ok(())
+    }
+
+    /// Writes the output of the program with each of its lines prefixed.
+    pub fn output(&self, s: &str) -> io::Result<()> {
+        let mut state = self.state.lock().unwrap();
+        if !s.is_empty() {
+            state.clear_status_line()?;
+        }
+        for line in s.split_inclusive('\n') {
+            if !state.is_output_line_open {
+                state.output.write_all(OUTPUT_PREFIX.as_bytes())?;
+            }
+            state.output.write_all(line.as_bytes())?;
+            state.is_output_line_open = !line.ends_with('\n');
+        }
+        state.output.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::report::IoLog;
+    use super::*;
+
+    #[test]
+    fn test_causal_order() {
+        //Both go to one buffer as on a terminal.
+        let terminal = IoLog::default();
+        let mux = Multiplexer::new(Box::new(terminal.clone()), Box::new(terminal.clone()));
+        mux.trace("(0, 0)       DP:Right CC:Left").unwrap();
+        mux.trace("    OutChar").unwrap();
+        mux.output("H").unwrap();
+        mux.trace("(0, 1)       DP:Right CC:Left").unwrap();
+        mux.trace("    OutChar").unwrap();
+        mux.output("i\nj").unwrap();
+        mux.output("k\n").unwrap();
+        mux.output("").unwrap();
+        mux.trace("(0, 2)       DP:Right CC:Left").unwrap();
+        assert_eq!(
+            "(0, 0)       DP:Right CC:Left
+    OutChar
+| H
+(0, 1)       DP:Right CC:Left
+    OutChar
+| i
+| jk
+(0, 2)       DP:Right CC:Left
+",
+            terminal.contents()
+        );
+    }
+
+    #[test]
+    fn test_separate_streams() {
+        let (output, trace) = (IoLog::default(), IoLog::default());
+        let mux = Multiplexer::new(Box::new(output.clone()), Box::new(trace.clone()));
+        mux.output("1").unwrap();
+        mux.output("2").unwrap();
+        mux.trace("a").unwrap();
+        mux.output("\n3\n").unwrap();
+        mux.trace("b").unwrap();
+        //the line break after `12` is added as the trace came in between
+        assert_eq!("| 12\n| \n| 3\n", output.contents());
+        assert_eq!("a\nb\n", trace.contents());
+    }
+
+    #[test]
+    fn test_status_line() {
+        let terminal = IoLog::default();
+        let mux = Multiplexer::new(Box::new(terminal.clone()), Box::new(terminal.clone()));
+        mux.output("H").unwrap();
+        mux.status("\rsteps: 1").unwrap();
+        mux.status("\rsteps: 2").unwrap();
+        mux.trace("a").unwrap();
+        mux.status("\rsteps: 3").unwrap();
+        mux.output("i").unwrap();
+        assert_eq!(
+            format!(
+                "| H\n\rsteps: 1\rsteps: 2{}a\n\rsteps: 3{}| i",
+                CLEAR_LINE, CLEAR_LINE
+            ),
+            terminal.contents()
+        );
+    }
+}
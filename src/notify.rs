@@ -0,0 +1,185 @@
+/*!
+Notification on termination of long runs (`--notify`).
+
+When a run lasts longer than the threshold, a BEL character and an OSC 9 sequence (shown as a desktop notification by e.g. iTerm2, WezTerm and Windows Terminal) are written to stderr.
+With the `notify-send` feature, a `notify-send` subprocess is spawned as well.
+A failure of notification is ignored so that it never fails the run.
+*/
+
+use std::io::{self, Write};
+use std::time::Duration;
+
+use super::error::PietError;
+use super::run_result::TerminationReason;
+
+/// Destination of a notification.
+pub trait Notifier {
+    fn notify(&mut self, message: &str) -> io::Result<()>;
+}
+
+/// Writes BEL and an OSC 9 notification to a terminal.
+pub struct TerminalNotifier<W: Write> {
+    w: W,
+}
+
+impl<W: Write> TerminalNotifier<W> {
+    pub fn new(w: W) -> Self {
+        Self { w }
+    }
+}
+
+impl<W: Write> Notifier for TerminalNotifier<W> {
+    fn notify(&mut self, message: &str) -> io::Result<()> {
+        self.w.write_all(escape_sequence(message).as_bytes())?;
+        self.w.flush()
+    }
+}
+
+/// Spawns `notify-send` (Linux desktop notification).
+#[cfg(feature = "notify-send")]
+pub struct NotifySend;
+
+#[cfg(feature = "notify-send")]
+impl Notifier for NotifySend {
+    fn notify(&mut self, message: &str) -> io::Result<()> {
+        std::process::Command::new("notify-send")
+            .args(["piet", message])
+            .stdout(std::process::Stdio::null())
+            .stderr(std::process::Stdio::null())
+            .spawn()
+            .map(|_| ())
+    }
+}
+
+/// Returns `true` if the end of a run which lasted `elapsed` should be notified.
+pub fn should_notify(
+    elapsed: Duration,
+    threshold: Duration,
+    is_terminal: bool,
+    is_quiet: bool,
+) -> bool {
+    is_terminal && !is_quiet && (elapsed >= threshold)
+}
+
+/// Returns BEL followed by an OSC 9 sequence carrying `message`.
+/// Control characters in `message` are replaced with spaces so that they cannot terminate the sequence early.
+pub fn escape_sequence(message: &str) -> String {
+    let message: String = message
+        .chars()
+        .map(|c| if c.is_control() { ' ' } else { c })
+        .collect();
+    format!("\x07\x1b]9;{}\x07", message)
+}
+
+/// Returns the text of the notification.
+pub fn message(reason: &Result<TerminationReason, PietError>, elapsed: Duration) -> String {
+    let reason = match reason {
+        Ok(TerminationReason::NoExit) | Ok(TerminationReason::WhiteRetrace) => {
+            "halted normally".to_string()
+        }
+        Ok(TerminationReason::MaxIter) => "reached max-iter".to_string(),
+        Ok(TerminationReason::Stall) => "stalled".to_string(),
+        Ok(TerminationReason::Cancelled) => "cancelled".to_string(),
+        Err(e) => format!("failed: {}", e),
+    };
+    format!("piet: {} after {:.1}s", reason, elapsed.as_secs_f64())
+}
+
+/// Sends `message` to each of `notifiers`, ignoring failures.
+pub fn notify_all(notifiers: &mut [&mut dyn Notifier], message: &str) {
+    for notifier in notifiers {
+        let _ = notifier.notify(message);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_should_notify() {
+        let threshold = Duration::from_secs(10);
+        assert!(should_notify(
+            Duration::from_secs(10),
+            threshold,
+            true,
+            false
+        ));
+        assert!(!should_notify(
+            Duration::from_millis(9999),
+            threshold,
+            true,
+            false
+        ));
+        assert!(!should_notify(
+            Duration::from_secs(11),
+            threshold,
+            false,
+            false
+        ));
+        assert!(!should_notify(
+            Duration::from_secs(11),
+            threshold,
+            true,
+            true
+        ));
+    }
+
+    #[test]
+    fn test_escape_sequence() {
+        assert_eq!(
+            "\x07\x1b]9;piet: halted normally after 12.3s\x07",
+            escape_sequence(&message(
+                &Ok(TerminationReason::NoExit),
+                Duration::from_millis(12345)
+            ))
+        );
+        //an embedded BEL or newline would end the sequence
+        assert_eq!("\x07\x1b]9;a b c\x07", escape_sequence("a\x07b\nc"));
+        let e = PietError::PositionOutOfBounds {
+            pos: (3, 0),
+            image_height: 1,
+            image_width: 1,
+        };
+        assert_eq!(
+            format!("piet: failed: {} after 1.0s", e),
+            message(&Err(e), Duration::from_secs(1))
+        );
+    }
+
+    struct Mock {
+        messages: Vec<String>,
+        fails: bool,
+    }
+
+    impl Notifier for Mock {
+        fn notify(&mut self, message: &str) -> io::Result<()> {
+            self.messages.push(message.to_string());
+            if self.fails {
+                Err(io::Error::other("unsupported"))
+            } else {
+                Ok(())
+            }
+        }
+    }
+
+    #[test]
+    fn test_notify_all() {
+        let mut failing = Mock {
+            messages: vec![],
+            fails: true,
+        };
+        let mut ok = Mock {
+            messages: vec![],
+            fails: false,
+        };
+        notify_all(&mut [&mut failing, &mut ok], "done");
+        //the failure of the first one does not prevent the second one
+        assert_eq!(vec!["done"], failing.messages);
+        assert_eq!(vec!["done"], ok.messages);
+
+        let mut buf = vec![];
+        TerminalNotifier::new(&mut buf).notify("done").unwrap();
+        assert_eq!(b"\x07\x1b]9;done\x07".to_vec(), buf);
+    }
+}
@@ -0,0 +1,157 @@
+/*!
+Hooks for an embedding program to observe and alter the execution (see [`run_image_observed()`](super::run_image_observed)).
+
+An observer is called before each command is executed and returns an [`Intervention`].
+Anything but [`Intervention::Proceed`] alters the semantics of the program, so it is applied only with `--allow-interventions`,
+and otherwise the run fails with [`PietError::InterventionNotAllowed`](super::error::PietError::InterventionNotAllowed).
+Each applied intervention is recorded as [`TraceEvent::Intervention`](super::trace::TraceEvent::Intervention) and printed in verbose mode,
+so that a recorded run never silently differs from the program.
+
+Loading a huge image takes long enough that a front end needs feedback before the run starts, so a [`LoadObserver`] receives the progress of each [`LoadPhase`] of loading
+(see [`Image::new_observed()`](super::image::Image::new_observed) and [`Image::from_codel_grid_observed()`](super::image::Image::from_codel_grid_observed)).
+The progress is reported between chunks of about one percent of a phase, so that loading without an observer is not slowed down.
+*/
+
+use std::fmt::{self, Display, Formatter};
+
+use super::command::Command;
+use super::interpreter::Interpreter;
+
+/// What to do with the command about to be executed.
+#[derive(Debug, Clone, Copy)]
+pub enum Intervention {
+    /// Executes the command as is.
+    Proceed,
+    /// Doesn't execute the command (the interpreter still moves to the next block).
+    Skip,
+    /// Executes another command instead.
+    Replace(Command),
+    /// Applies the function to the stack (e.g. pushes synthetic values), and then executes the command.
+    MutateStack(fn(&mut Vec<isize>)),
+}
+
+/// Receives the commands before they are executed.
+pub trait Observer {
+    /// Called with the state before `command` is executed (`ip.num_steps` is the current step).
+    fn before_command(&mut self, ip: &Interpreter, command: Command) -> Intervention;
+}
+
+/// An applied [`Intervention`] as recorded in the trace.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AppliedIntervention {
+    Skipped,
+    Replaced(Command),
+    StackMutated,
+}
+
+impl Display for AppliedIntervention {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            AppliedIntervention::Skipped => write!(f, "skipped"),
+            AppliedIntervention::Replaced(command) => write!(f, "replaced by {:?}", command),
+            AppliedIntervention::StackMutated => write!(f, "stack mutated before execution"),
+        }
+    }
+}
+
+impl Intervention {
+    /// Returns how the intervention is recorded, or `None` for [`Intervention::Proceed`].
+    pub fn applied(&self) -> Option<AppliedIntervention> {
+        match self {
+            Intervention::Proceed => None,
+            Intervention::Skip => Some(AppliedIntervention::Skipped),
+            Intervention::Replace(command) => Some(AppliedIntervention::Replaced(*command)),
+            Intervention::MutateStack(_) => Some(AppliedIntervention::StackMutated),
+        }
+    }
+}
+
+/// Phase of loading an image.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum LoadPhase {
+    /// Decoding the image file (or parsing a PietDev save), which is reported only at its start and at its end.
+    Decode,
+    /// Reading the color of each codel from the pixels.
+    Sample,
+    /// Splitting the codels into blocks, reported by the rows scanned.
+    Label,
+    /// Computing the corners of the blocks, reported by the codels of the blocks done.
+    /// This is done along with [`LoadPhase::Label`], each block as soon as it is labeled.
+    Corners,
+}
+
+impl Display for LoadPhase {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        let s = match self {
+            LoadPhase::Decode => "decoding",
+            LoadPhase::Sample => "sampling codels",
+            LoadPhase::Label => "labeling blocks",
+            LoadPhase::Corners => "computing corners",
+        };
+        write!(f, "{}", s)
+    }
+}
+
+/// Receives the progress of loading an image.
+pub trait LoadObserver {
+    /// Called with the fraction (`0.0` to `1.0`) of `phase` done so far.
+    /// Within a phase, the fraction never decreases and the last one is `1.0`.
+    fn on_load_progress(&mut self, phase: LoadPhase, fraction: f64);
+}
+
+impl<F: FnMut(LoadPhase, f64)> LoadObserver for F {
+    fn on_load_progress(&mut self, phase: LoadPhase, fraction: f64) {
+        self(phase, fraction)
+    }
+}
+
+/// Number of the reports of a phase between its start and its end.
+const NUM_CHUNKS: usize = 100;
+
+/// Reports the progress of the phases of loading to an optional observer, once per chunk.
+pub(crate) struct LoadProgress<'a> {
+    observer: Option<&'a mut dyn LoadObserver>,
+    /// The total amount of work of each phase, and the amount done at which it is reported next (`usize::MAX` without an observer).
+    phases: [(usize, usize); 4],
+}
+
+impl<'a> LoadProgress<'a> {
+    pub(crate) fn new(observer: Option<&'a mut dyn LoadObserver>) -> Self {
+        Self {
+            observer,
+            phases: [(0, usize::MAX); 4],
+        }
+    }
+
+    /// Starts `phase`, whose amount of work is `total` (e.g. the number of rows).
+    pub(crate) fn start(&mut self, phase: LoadPhase, total: usize) {
+        if let Some(observer) = self.observer.as_mut() {
+            self.phases[phase as usize] = (total, total.div_ceil(NUM_CHUNKS).max(1));
+            observer.on_load_progress(phase, 0.0);
+        }
+    }
+
+    /// Tells that `done` of the work of `phase` is done, which is reported if a chunk has been finished since the last report.
+    #[inline]
+    pub(crate) fn advance(&mut self, phase: LoadPhase, done: usize) {
+        let (total, next) = self.phases[phase as usize];
+        if done < next {
+            return;
+        }
+        if let Some(observer) = self.observer.as_mut() {
+            let chunk = total.div_ceil(NUM_CHUNKS).max(1);
+            self.phases[phase as usize].1 = (done / chunk + 1) * chunk;
+            if done < total {
+                observer.on_load_progress(phase, done as f64 / total as f64);
+            }
+        }
+    }
+
+    /// Finishes `phase`.
+    pub(crate) fn finish(&mut self, phase: LoadPhase) {
+        if let Some(observer) = self.observer.as_mut() {
+            self.phases[phase as usize].1 = usize::MAX;
+            observer.on_load_progress(phase, 1.0);
+        }
+    }
+}
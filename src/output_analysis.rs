@@ -0,0 +1,497 @@
+/*!
+Static estimate of what a program can print (`--check --output-analysis`), e.g. for reviewing an unknown image.
+
+The analysis follows the state graph of `(block, DP, CC)` from the starting codel, where the retries and the slides across white are resolved as in a run,
+and only `Pointer` and `Switch` branch to every DP or CC (their operand is not used), so every transition which can happen is in the graph.
+
+The stack is tracked by a small abstract interpreter: the values known to be on the top of the stack (each of which may be unknown) and whether they are the whole stack.
+A value is known only along a straight line of states, i.e. each state entered from exactly one state (the start counts as one), and the stack is unknown at any other state.
+A command whose operands are all known is executed on them as in a run, an input makes the whole stack unknown, and so does a command with an unknown operand (e.g. an unresolved `Roll`).
+
+Each transition which executes `OutChar` or `OutNumber` is an *output site*, which is *static* if the value it prints is known on every path to it, and *dynamic* otherwise.
+*/
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::{self, Display, Formatter};
+
+use itertools::Itertools;
+use rustc_hash::{FxHashMap, FxHashSet};
+
+use super::cc::CC;
+use super::command::Command;
+use super::dp::DP;
+use super::image::Image;
+use super::interpreter::Interpreter;
+use super::program::{Program, RetryOutcome};
+
+/// A state of the interpreter, where `pos` is the first codel of a coloured block or the codel at which a slide across white starts.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+struct State {
+    pos: (usize, usize),
+    dp: DP,
+    cc: CC,
+}
+
+/// Stack as known statically.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct AbstractStack {
+    /// Values on the top (`None` if unknown), from the bottom to the top.
+    values: Vec<Option<isize>>,
+    /// Whether `values` is the whole stack, and otherwise there may be anything below it.
+    is_exact: bool,
+}
+
+impl AbstractStack {
+    fn empty() -> Self {
+        Self {
+            values: vec![],
+            is_exact: true,
+        }
+    }
+
+    fn unknown() -> Self {
+        Self {
+            values: vec![],
+            is_exact: false,
+        }
+    }
+
+    /// Returns the top `n` values (from the bottom to the top) if they are all known.
+    fn known_top(&self, n: usize) -> Option<Vec<isize>> {
+        let len = self.values.len();
+        (n <= len).then(|| self.values[len - n..].iter().copied().collect())?
+    }
+}
+
+/// What an executed command prints.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Printed {
+    Nothing,
+    Value(isize),
+    Dynamic,
+}
+
+/// Number of values `command` takes from `stack`, or `None` if it is not known (a `Roll` of an unknown depth).
+fn num_operands(stack: &AbstractStack, command: Command) -> Option<usize> {
+    Some(match command {
+        Command::Push | Command::InNumber | Command::InChar => 0,
+        Command::Pop
+        | Command::Not
+        | Command::Pointer
+        | Command::Switch
+        | Command::Duplicate
+        | Command::OutNumber
+        | Command::OutChar => 1,
+        Command::Add
+        | Command::Subtract
+        | Command::Multiply
+        | Command::Divide
+        | Command::Mod
+        | Command::Greater => 2,
+        Command::Roll => {
+            let len = stack.values.len();
+            if len < 2 {
+                2
+            } else {
+                2usize.saturating_add(stack.values[len - 2]?.max(0) as usize)
+            }
+        }
+    })
+}
+
+/// Applies `command` executed on leaving a block of `block_size` codels to `stack`.
+/// `scratch` is an interpreter on which a command with known operands is executed.
+fn apply(
+    stack: &AbstractStack,
+    command: Command,
+    block_size: usize,
+    scratch: &mut Interpreter,
+) -> (AbstractStack, Printed) {
+    let is_output = matches!(command, Command::OutChar | Command::OutNumber);
+    let unknown = || {
+        let printed = if is_output {
+            Printed::Dynamic
+        } else {
+            Printed::Nothing
+        };
+        (AbstractStack::unknown(), printed)
+    };
+    if matches!(command, Command::InNumber | Command::InChar) {
+        return unknown();
+    }
+    let Some(n) = num_operands(stack, command) else {
+        return unknown();
+    };
+    //spec: Any operations which cannot be performed (such as popping values when not enough are on the stack) are simply ignored
+    if stack.is_exact && (stack.values.len() < n) {
+        return (stack.clone(), Printed::Nothing);
+    }
+    let Some(operands) = stack.known_top(n) else {
+        return unknown();
+    };
+    let mut ret = stack.clone();
+    ret.values.truncate(ret.values.len() - n);
+    let printed = match command {
+        Command::OutNumber => Printed::Value(operands[0]),
+        //an invalid character is not printed and stays on the stack
+        Command::OutChar
+            if u32::try_from(operands[0])
+                .ok()
+                .and_then(char::from_u32)
+                .is_some() =>
+        {
+            Printed::Value(operands[0])
+        }
+        Command::OutChar => {
+            ret.values.push(Some(operands[0]));
+            Printed::Nothing
+        }
+        _ => {
+            scratch.stack = operands;
+            //neither reads nor writes, so it cannot fail
+            command.execute(scratch, block_size).unwrap();
+            ret.values.extend(scratch.stack.iter().map(|&x| Some(x)));
+            Printed::Nothing
+        }
+    };
+    (ret, printed)
+}
+
+/// Returns the state in which the interpreter is at `pos` (which shall not be black) with `dp` and `cc`.
+fn state_at(img: &Image, pos: (usize, usize), dp: DP, cc: CC) -> State {
+    let pos = if img.get_codel_at_unchecked(pos).is_white() {
+        pos
+    } else {
+        img.get_block(img.get_block_id_at_unchecked(pos))
+            .unwrap()
+            .first_codel()
+    };
+    State { pos, dp, cc }
+}
+
+/// Returns the states which may follow `state`, with the command executed on the way.
+fn successors(program: &Program, state: State) -> Vec<(State, Option<Command>)> {
+    let img = program.image();
+    let from = img.get_codel_at_unchecked(state.pos);
+    if from.is_other() {
+        return vec![];
+    }
+    if from.is_white() {
+        return slide(img, state).map(|s| (s, None)).into_iter().collect();
+    }
+    let RetryOutcome::Exit { next, dp, cc } =
+        program.get_retry_outcome(state.pos, &state.dp, &state.cc)
+    else {
+        return vec![];
+    };
+    let to = img.get_codel_at_unchecked(next);
+    //The run fails when it enters a block of an unknown color.
+    if to.is_other() {
+        return vec![];
+    }
+    //a move into a white block or into a block of the same colour (only on a torus) executes nothing
+    let command = (!to.is_white() && (to != from)).then(|| Command::new(from, to));
+    match command {
+        Some(Command::Pointer) => [DP::Right, DP::Down, DP::Left, DP::Up]
+            .into_iter()
+            .map(|dp| (state_at(img, next, dp, cc), command))
+            .collect(),
+        Some(Command::Switch) => [CC::Left, CC::Right]
+            .into_iter()
+            .map(|cc| (state_at(img, next, dp, cc), command))
+            .collect(),
+        _ => vec![(state_at(img, next, dp, cc), command)],
+    }
+}
+
+/// Slides across white from `state` as in a run, and returns the state in the coloured block where the slide ends (`None` if it never ends).
+fn slide(img: &Image, state: State) -> Option<State> {
+    let State {
+        mut pos,
+        mut dp,
+        mut cc,
+    } = state;
+    let mut visited = FxHashSet::default();
+    loop {
+        if !visited.insert((pos, dp)) {
+            return None;
+        }
+        let next = img
+            .get_next_codel_index_in_dp_direction_unchecked(pos, &dp)
+            .filter(|next| !img.get_codel_at_unchecked(*next).is_black());
+        let Some(next) = next else {
+            cc = cc.flip();
+            dp = dp.turn_right();
+            continue;
+        };
+        pos = next;
+        if !img.get_codel_at_unchecked(pos).is_white() {
+            return Some(state_at(img, pos, dp, cc));
+        }
+    }
+}
+
+/// A transition which executes an output command, identified by the first codels of the blocks it leaves and enters.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OutputSite {
+    pub from: (usize, usize),
+    pub to: (usize, usize),
+    pub command: Command,
+    /// The values printed at the site if they are known statically (invalid characters excluded), or `None` if the site is dynamic.
+    pub values: Option<BTreeSet<isize>>,
+}
+
+/// Result of [`analyze()`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OutputAnalysis {
+    /// Sites in the order of their positions.
+    pub sites: Vec<OutputSite>,
+}
+
+impl OutputAnalysis {
+    fn static_values(&self, command: Command) -> BTreeSet<isize> {
+        self.sites
+            .iter()
+            .filter(|site| site.command == command)
+            .filter_map(|site| site.values.as_ref())
+            .flatten()
+            .copied()
+            .collect()
+    }
+
+    /// Characters printed by the static sites.
+    pub fn chars(&self) -> BTreeSet<char> {
+        self.static_values(Command::OutChar)
+            .into_iter()
+            .filter_map(|x| u32::try_from(x).ok().and_then(char::from_u32))
+            .collect()
+    }
+
+    /// Numbers printed by the static sites.
+    pub fn numbers(&self) -> BTreeSet<isize> {
+        self.static_values(Command::OutNumber)
+    }
+
+    pub fn num_dynamic_sites(&self) -> usize {
+        self.sites
+            .iter()
+            .filter(|site| site.values.is_none())
+            .count()
+    }
+
+    /// Returns the analysis as JSON, where the characters are given as code points.
+    pub fn to_json(&self) -> String {
+        let dynamic_sites = self
+            .sites
+            .iter()
+            .filter(|site| site.values.is_none())
+            .map(|site| {
+                format!(
+                    r#"{{"from":[{},{}],"to":[{},{}],"command":"{:?}"}}"#,
+                    site.from.0, site.from.1, site.to.0, site.to.1, site.command
+                )
+            })
+            .join(",");
+        format!(
+            r#"{{"sites":{},"dynamic_sites":[{}],"chars":[{}],"numbers":[{}]}}"#,
+            self.sites.len(),
+            dynamic_sites,
+            self.chars().iter().map(|&c| u32::from(c)).join(","),
+            self.numbers().iter().join(",")
+        )
+    }
+}
+
+impl Display for OutputAnalysis {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        writeln!(
+            f,
+            "output sites: {} ({} dynamic)",
+            self.sites.len(),
+            self.num_dynamic_sites()
+        )?;
+        writeln!(
+            f,
+            "static characters: {:?}",
+            self.chars().into_iter().collect::<String>()
+        )?;
+        writeln!(f, "static numbers: [{}]", self.numbers().iter().join(", "))?;
+        for site in self.sites.iter().filter(|site| site.values.is_none()) {
+            writeln!(
+                f,
+                "dynamic: {:?} from the block at {:?} to the block at {:?}",
+                site.command, site.from, site.to
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Analyzes what the program which starts at `start` can print.
+pub fn analyze(program: &Program, start: (usize, usize)) -> OutputAnalysis {
+    let img = program.image();
+
+    //the state graph
+    let start = state_at(img, start, DP::default(), CC::default());
+    let mut ids = FxHashMap::default();
+    let mut states = vec![start];
+    ids.insert(start, 0);
+    let mut edges = vec![];
+    let mut k = 0;
+    while k < states.len() {
+        for (next, command) in successors(program, states[k]) {
+            let id = *ids.entry(next).or_insert_with(|| {
+                states.push(next);
+                states.len() - 1
+            });
+            edges.push((k, id, command));
+        }
+        k += 1;
+    }
+
+    //the stacks, which are known only along straight lines
+    let mut in_degrees = vec![0; states.len()];
+    //the start is entered from outside
+    in_degrees[0] += 1;
+    let mut outgoing = vec![vec![]; states.len()];
+    for (e, &(from, to, _)) in edges.iter().enumerate() {
+        in_degrees[to] += 1;
+        outgoing[from].push(e);
+    }
+    let mut inputs = vec![None; states.len()];
+    inputs[0] = Some(AbstractStack::empty());
+    for id in 0..states.len() {
+        if in_degrees[id] != 1 {
+            inputs[id] = Some(AbstractStack::unknown());
+        }
+    }
+    let mut worklist = (0..states.len())
+        .filter(|id| inputs[*id].is_some())
+        .collect::<Vec<_>>();
+    let mut scratch = Interpreter::new();
+    let mut sites = BTreeMap::new();
+    while let Some(id) = worklist.pop() {
+        let input = inputs[id].clone().unwrap();
+        for &e in &outgoing[id] {
+            let (from, to, command) = edges[e];
+            let (output, printed) = match command {
+                None => (input.clone(), Printed::Nothing),
+                Some(command) => {
+                    let block_size = img.get_block_size_at(states[from].pos).unwrap();
+                    apply(&input, command, block_size, &mut scratch)
+                }
+            };
+            if let Some(command @ (Command::OutChar | Command::OutNumber)) = command {
+                let key = (states[from].pos, states[to].pos);
+                let values = sites.entry(key).or_insert((command, Some(BTreeSet::new())));
+                match printed {
+                    Printed::Nothing => (),
+                    Printed::Value(x) => {
+                        if let Some(values) = values.1.as_mut() {
+                            values.insert(x);
+                        }
+                    }
+                    Printed::Dynamic => values.1 = None,
+                }
+            }
+            if in_degrees[to] == 1 {
+                inputs[to] = Some(output);
+                worklist.push(to);
+            }
+        }
+    }
+    OutputAnalysis {
+        sites: sites
+            .into_iter()
+            .map(|((from, to), (command, values))| OutputSite {
+                from,
+                to,
+                command,
+                values,
+            })
+            .collect(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::codel::Codel;
+    use super::*;
+
+    #[test]
+    fn test_apply() {
+        let mut scratch = Interpreter::new();
+        let stack = |values: &[Option<isize>], is_exact| AbstractStack {
+            values: values.to_vec(),
+            is_exact,
+        };
+        let mut apply = |s: &AbstractStack, command| apply(s, command, 3, &mut scratch);
+
+        let s = stack(&[Some(5), Some(2)], false);
+        assert_eq!(
+            (stack(&[Some(5), Some(2), Some(3)], false), Printed::Nothing),
+            apply(&s, Command::Push)
+        );
+        assert_eq!(
+            (stack(&[Some(1)], false), Printed::Nothing),
+            apply(&s, Command::Mod)
+        );
+        assert_eq!(
+            (stack(&[Some(5)], false), Printed::Value(2)),
+            apply(&s, Command::OutNumber)
+        );
+        //a command with an unknown operand, and an input
+        let s = stack(&[Some(5), None], false);
+        assert_eq!(
+            (AbstractStack::unknown(), Printed::Dynamic),
+            apply(&s, Command::OutChar)
+        );
+        assert_eq!(
+            (AbstractStack::unknown(), Printed::Nothing),
+            apply(&stack(&[Some(5)], true), Command::InChar)
+        );
+        //a roll of an unknown depth, and a roll of a known depth
+        assert_eq!(
+            (AbstractStack::unknown(), Printed::Nothing),
+            apply(&stack(&[Some(1), None, Some(1)], true), Command::Roll)
+        );
+        assert_eq!(
+            (stack(&[Some(2), Some(1)], true), Printed::Nothing),
+            apply(
+                &stack(&[Some(1), Some(2), Some(2), Some(1)], true),
+                Command::Roll
+            )
+        );
+        //ignored for lack of operands
+        let s = stack(&[None], true);
+        assert_eq!((s.clone(), Printed::Nothing), apply(&s, Command::Add));
+        //an invalid character is not printed
+        let s = stack(&[Some(-1)], true);
+        assert_eq!((s.clone(), Printed::Nothing), apply(&s, Command::OutChar));
+    }
+
+    #[test]
+    fn test_branches() {
+        let (r, dr, lm, lb, k) = (
+            Codel::Red,
+            Codel::DarkRed,
+            Codel::LightMagenta,
+            Codel::LightBlue,
+            Codel::Black,
+        );
+        //push 1 and out number, and then back and forth along the row,
+        //so that the state in the dark red block is entered from two states and the number is unknown there
+        let m = vec![vec![r, dr, lm, lb], vec![k, k, k, k]];
+        let analysis = analyze(&Program::new(Image::from_codel_grid(m)), (0, 0));
+        assert_eq!(
+            vec![OutputSite {
+                from: (0, 1),
+                to: (0, 2),
+                command: Command::OutNumber,
+                values: None,
+            }],
+            analysis.sites
+        );
+        assert_eq!(1, analysis.num_dynamic_sites());
+    }
+}
@@ -0,0 +1,125 @@
+/*!
+Throttling of the execution speed for demonstrations (`--speed`).
+
+Every step (including each step of a slide across white) is paced, so that a long white corridor is shown as it is crossed rather than in a burst.
+The schedule is relative to the first paced step rather than to the previous one, so that the time spent on printing the trace does not accumulate as a drift.
+A step which is late (e.g. after a stall on reading stdin) restarts the schedule, so that the following steps are not run in a burst to catch up.
+*/
+
+use std::fmt;
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+
+/// Maximum number of steps per second.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Speed {
+    /// No throttling
+    #[default]
+    Max,
+    StepsPerSecond(u32),
+}
+
+impl FromStr for Speed {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s == "max" {
+            return Ok(Speed::Max);
+        }
+        s.parse()
+            .map(Speed::StepsPerSecond)
+            .map_err(|_| format!("`{}` is neither `max` nor a number of steps per second", s))
+    }
+}
+
+impl fmt::Display for Speed {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Speed::Max => write!(f, "max"),
+            Speed::StepsPerSecond(n) => write!(f, "{}", n),
+        }
+    }
+}
+
+/// Schedule of the steps under a [`Speed`] other than [`Speed::Max`].
+pub struct Pacer {
+    steps_per_sec: u32,
+    /// The instant and the number of the step from which the schedule counts.
+    start: Option<(Instant, usize)>,
+}
+
+impl Pacer {
+    /// Returns `None` for [`Speed::Max`].
+    pub fn new(speed: Speed) -> Option<Self> {
+        match speed {
+            Speed::Max => None,
+            Speed::StepsPerSecond(steps_per_sec) => Some(Self {
+                steps_per_sec,
+                start: None,
+            }),
+        }
+    }
+
+    /// Returns how long to wait at `now` before the step `num_steps`.
+    /// The first call starts the schedule, and the step `num_steps` is due `k / speed` seconds after it, where `k` is the number of steps since the first one.
+    /// A step which is already past due is not delayed, and the schedule starts again from it.
+    pub fn delay(&mut self, num_steps: usize, now: Instant) -> Duration {
+        let (start, first_step) = *self.start.get_or_insert((now, num_steps));
+        let due = Duration::from_secs_f64(
+            num_steps.saturating_sub(first_step) as f64 / self.steps_per_sec as f64,
+        );
+        let elapsed = now.duration_since(start);
+        if elapsed > due {
+            self.start = Some((now, num_steps));
+        }
+        due.saturating_sub(elapsed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_speed() {
+        assert_eq!(Ok(Speed::Max), "max".parse());
+        assert_eq!(Ok(Speed::StepsPerSecond(10)), "10".parse());
+        assert!("fast".parse::<Speed>().is_err());
+        assert!("-1".parse::<Speed>().is_err());
+        assert_eq!("max", Speed::Max.to_string());
+        assert!(Pacer::new(Speed::Max).is_none());
+    }
+
+    #[test]
+    fn test_delay() {
+        let mut pacer = Pacer::new(Speed::StepsPerSecond(10)).unwrap();
+        let t0 = Instant::now();
+        let ms = Duration::from_millis;
+        //the first step is not delayed
+        assert_eq!(Duration::ZERO, pacer.delay(5, t0));
+        //the next one is due 100ms later
+        assert_eq!(ms(100), pacer.delay(6, t0));
+        assert_eq!(ms(70), pacer.delay(6, t0 + ms(30)));
+        //a step which is late is not delayed, and the schedule restarts from it
+        assert_eq!(Duration::ZERO, pacer.delay(7, t0 + ms(250)));
+        assert_eq!(ms(100), pacer.delay(8, t0 + ms(250)));
+        //10 steps per second in the long run
+        assert_eq!(ms(1_000), pacer.delay(107, t0 + ms(9_250)));
+    }
+
+    #[test]
+    fn test_delay_after_stall() {
+        let mut pacer = Pacer::new(Speed::StepsPerSecond(10)).unwrap();
+        let t0 = Instant::now();
+        let ms = Duration::from_millis;
+        assert_eq!(Duration::ZERO, pacer.delay(0, t0));
+        assert_eq!(ms(100), pacer.delay(1, t0));
+        //the step 2 stalls for 5s (e.g. waiting for stdin)
+        assert_eq!(Duration::ZERO, pacer.delay(3, t0 + ms(5_200)));
+        //the following steps are still paced instead of catching up with the 50 steps due by then
+        let t1 = t0 + ms(5_200);
+        for k in 1..=50 {
+            assert_eq!(ms(100), pacer.delay(3 + k, t1 + ms(100 * (k as u64 - 1))));
+        }
+    }
+}
@@ -0,0 +1,318 @@
+/*!
+Remapping of colors to the colors of Piet (`--palette`), and its validation.
+
+A palette file maps colors of the image to colors of Piet, e.g. for a program drawn with a palette of its own.
+Each line is `#RRGGBB -> #RRGGBB`, where the target shall be one of the 20 colors of Piet; blank lines and lines starting with `# ` are ignored.
+The mapping is applied to the codels after the image is loaded (and cached), so the codel size is detected with the colors as they are.
+A color of Piet can be mapped as well, while a color which is not mapped is left as it is.
+
+A mapping can silently change the program, which is checked against a baseline where every color is read as the nearest color of Piet:
+
+- Two colors which are distinct in the baseline but mapped to one color are merged, so their adjacent blocks become one block and `Push` pushes another value.
+  This is reported at load ([`PaletteMap::merged_colors()`]).
+- With `--check`, the transitions between adjacent blocks are also enumerated, and each transition whose command differs from the baseline is reported with the blocks ([`PaletteMap::changed_transitions()`]).
+  E.g. mapping dark red to red turns a `Push` from red to dark red into a merge, and mapping a color nearest to green to cyan turns a `Divide` into a `Greater`.
+*/
+
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fs;
+
+use rustc_hash::FxHashSet;
+
+use super::codel::Codel;
+use super::command::Command;
+use super::coord;
+use super::image::{Image, Pixel};
+use super::pietdev::PALETTE;
+use super::quantization;
+use super::warning::Warning;
+
+/// Mapping of colors (`--palette`).
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct PaletteMap {
+    entries: Vec<((u8, u8, u8), Codel)>,
+}
+
+fn parse_color(s: &str) -> Option<(u8, u8, u8)> {
+    let hex = s.strip_prefix('#').filter(|h| h.len() == 6)?;
+    let channel = |k: usize| u8::from_str_radix(hex.get(k..k + 2)?, 16).ok();
+    Some((channel(0)?, channel(2)?, channel(4)?))
+}
+
+/// Returns the codel of the color `rgb` (one of the colors of Piet or an unknown color).
+fn codel_of((r, g, b): (u8, u8, u8)) -> Codel {
+    Codel::new(&Pixel { r, g, b })
+}
+
+/// Returns the color of Piet nearest to `codel`, which is `codel` itself if it is one.
+fn nearest(codel: &Codel) -> Codel {
+    if !codel.is_other() {
+        return *codel;
+    }
+    let rgb = codel.rgb();
+    PALETTE
+        .into_iter()
+        .min_by_key(|c| quantization::distance(c.rgb(), rgb))
+        .unwrap()
+}
+
+/// Returns the command of the transition from `from` to `to`, or `None` if there is none (e.g. a slide into white).
+fn command(from: &Codel, to: &Codel) -> Option<Command> {
+    let is_colored = |c: &Codel| !c.is_white() && !c.is_black() && !c.is_other();
+    (is_colored(from) && is_colored(to) && (from != to)).then(|| Command::new(from, to))
+}
+
+impl PaletteMap {
+    /// Parses a palette file (see the module documentation).
+    pub fn parse(s: &str) -> Result<Self, String> {
+        let mut entries: Vec<((u8, u8, u8), Codel)> = vec![];
+        for (k, line) in s.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || (line == "#") || line.starts_with("# ") {
+                continue;
+            }
+            let error = |message: &str| format!("line {}: {}: `{}`", k + 1, message, line);
+            let Some((source, target)) = line.split_once("->") else {
+                return Err(error("expected `#RRGGBB -> #RRGGBB`"));
+            };
+            let (Some(source), Some(target)) =
+                (parse_color(source.trim()), parse_color(target.trim()))
+            else {
+                return Err(error("expected `#RRGGBB -> #RRGGBB`"));
+            };
+            let target = codel_of(target);
+            if target.is_other() {
+                return Err(error("the target is not a color of Piet"));
+            }
+            if entries.iter().any(|(c, _)| *c == source) {
+                return Err(error("the color is mapped twice"));
+            }
+            entries.push((source, target));
+        }
+        Ok(Self { entries })
+    }
+
+    /// Reads and parses a palette file.
+    pub fn read(file: &str) -> Result<Self, Box<dyn Error>> {
+        let s = fs::read_to_string(file).map_err(|e| format!("{}: {}", file, e))?;
+        Ok(Self::parse(&s).map_err(|e| format!("{}: {}", file, e))?)
+    }
+
+    fn get(&self, codel: &Codel) -> Option<Codel> {
+        let rgb = codel.rgb();
+        self.entries
+            .iter()
+            .find(|(c, _)| *c == rgb)
+            .map(|(_, t)| *t)
+    }
+
+    /// Returns the color to which `codel` is mapped, which is `codel` itself if it is not mapped.
+    pub fn target(&self, codel: &Codel) -> Codel {
+        self.get(codel).unwrap_or(*codel)
+    }
+
+    /// Returns `img` with the colors mapped, and the number of the codels whose color changed.
+    pub fn apply(&self, img: Image) -> (Image, usize) {
+        img.map_colors(|c| self.target(c))
+    }
+
+    /// Reports each color of Piet to which colors of `img` (before the mapping) which are distinct by the nearest colors of Piet are mapped, with the number of codels of each of them.
+    /// Colors nearest to the same color of Piet (e.g. the noise of a lossy image) can be mapped to it without a warning.
+    pub fn merged_colors(&self, img: &Image) -> Vec<Warning> {
+        let mut counts = BTreeMap::<(u8, u8, u8), (Codel, usize)>::new();
+        for i in 0..img.height() {
+            for j in 0..img.width() {
+                let codel = img.get_codel_at_unchecked((i, j));
+                counts.entry(codel.rgb()).or_insert((*codel, 0)).1 += 1;
+            }
+        }
+        let mut by_target = BTreeMap::<(u8, u8, u8), Vec<(Codel, usize)>>::new();
+        for (codel, num_codels) in counts.into_values() {
+            let target = self.target(&codel);
+            if !target.is_other() {
+                by_target
+                    .entry(target.rgb())
+                    .or_default()
+                    .push((codel, num_codels));
+            }
+        }
+        by_target
+            .into_iter()
+            .filter(|(_, sources)| {
+                sources
+                    .iter()
+                    .any(|(c, _)| nearest(c) != nearest(&sources[0].0))
+            })
+            .map(|(target, sources)| Warning::PaletteMergesColors {
+                target: codel_of(target),
+                sources,
+            })
+            .collect()
+    }
+
+    /// Enumerates the transitions between the adjacent blocks of `img` (before the mapping) from or to a mapped color,
+    /// and reports each pair of colors whose transitions execute another command than by the nearest colors of Piet (or whose blocks are merged), with the blocks.
+    pub fn changed_transitions(&self, img: &Image) -> Vec<Warning> {
+        let mut pairs = FxHashSet::default();
+        let mut changes = BTreeMap::<_, Warning>::new();
+        for i in 0..img.height() {
+            for j in 0..img.width() {
+                let (from_id, from) = (
+                    img.get_block_id_at_unchecked((i, j)),
+                    img.get_codel_at_unchecked((i, j)),
+                );
+                for pos in coord::neighbours((i, j), img.height(), img.width()) {
+                    let to_id = img.get_block_id_at_unchecked(pos);
+                    let to = img.get_codel_at_unchecked(pos);
+                    if (from_id == to_id)
+                        || (self.get(from).is_none() && self.get(to).is_none())
+                        || !pairs.insert((from_id, to_id))
+                    {
+                        continue;
+                    }
+                    let (mapped_from, mapped_to) = (self.target(from), self.target(to));
+                    let is_merged = (mapped_from == mapped_to) && (nearest(from) != nearest(to));
+                    let baseline = command(&nearest(from), &nearest(to));
+                    let mapped = command(&mapped_from, &mapped_to);
+                    if !is_merged && (baseline == mapped) {
+                        continue;
+                    }
+                    let blocks = (
+                        img.get_block(from_id).unwrap().first_codel(),
+                        img.get_block(to_id).unwrap().first_codel(),
+                    );
+                    match changes.entry((from.rgb(), to.rgb())).or_insert(
+                        Warning::PaletteChangesTransition {
+                            from: *from,
+                            to: *to,
+                            baseline,
+                            mapped,
+                            merged: is_merged,
+                            blocks: vec![],
+                        },
+                    ) {
+                        Warning::PaletteChangesTransition { blocks: v, .. } => v.push(blocks),
+                        _ => unreachable!(),
+                    }
+                }
+            }
+        }
+        changes
+            .into_values()
+            .map(|mut w| {
+                if let Warning::PaletteChangesTransition { blocks, .. } = &mut w {
+                    blocks.sort();
+                }
+                w
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let map = PaletteMap::parse(
+            "# red is drawn as #C00000\n#c00000 -> #FF0000\n\n  #123456->#000000  \n",
+        )
+        .unwrap();
+        assert_eq!(Codel::Red, map.target(&codel_of((0xC0, 0, 0))));
+        assert_eq!(Codel::Black, map.target(&codel_of((0x12, 0x34, 0x56))));
+        //not mapped
+        assert_eq!(Codel::Blue, map.target(&Codel::Blue));
+
+        let error = |s: &str| PaletteMap::parse(s).unwrap_err();
+        assert_eq!(
+            "line 1: expected `#RRGGBB -> #RRGGBB`: `#C00000 #FF0000`",
+            error("#C00000 #FF0000")
+        );
+        assert_eq!(
+            "line 1: expected `#RRGGBB -> #RRGGBB`: `#C0000 -> #FF0000`",
+            error("#C0000 -> #FF0000")
+        );
+        assert_eq!(
+            "line 1: the target is not a color of Piet: `#FF0000 -> #FE0000`",
+            error("#FF0000 -> #FE0000")
+        );
+        assert_eq!(
+            "line 2: the color is mapped twice: `#C00000 -> #000000`",
+            error("#C00000 -> #FF0000\n#C00000 -> #000000")
+        );
+    }
+
+    #[test]
+    fn test_validation() {
+        let (r, dr, g, y) = (Codel::Red, Codel::DarkRed, Codel::Green, Codel::Yellow);
+        //push (red to dark red), then add (red to yellow) and push again (dark red to red)
+        let img = Image::from_codel_grid(vec![vec![r, r, dr], vec![y, g, r]]);
+        let map = PaletteMap::parse("#C00000 -> #FF0000").unwrap();
+        assert_eq!(
+            vec![Warning::PaletteMergesColors {
+                target: r,
+                sources: vec![(dr, 1), (r, 3)],
+            }],
+            map.merged_colors(&img)
+        );
+        assert_eq!(
+            vec![
+                Warning::PaletteChangesTransition {
+                    from: dr,
+                    to: r,
+                    baseline: Some(Command::Pop),
+                    mapped: None,
+                    merged: true,
+                    blocks: vec![((0, 2), (0, 0)), ((0, 2), (1, 2))],
+                },
+                Warning::PaletteChangesTransition {
+                    from: r,
+                    to: dr,
+                    baseline: Some(Command::Push),
+                    mapped: None,
+                    merged: true,
+                    blocks: vec![((0, 0), (0, 2)), ((1, 2), (0, 2))],
+                },
+            ],
+            map.changed_transitions(&img)
+        );
+        let (mapped, num_codels) = map.apply(img);
+        assert_eq!(1, num_codels);
+        //the red blocks and the dark red one are merged
+        assert_eq!(3, mapped.num_blocks());
+
+        //a hue changed: red to green (divide) becomes red to cyan (greater)
+        let img = Image::from_codel_grid(vec![vec![r, codel_of((0, 0xF0, 0x10))]]);
+        let map = PaletteMap::parse("#00F010 -> #00FFFF").unwrap();
+        assert_eq!(Vec::<Warning>::new(), map.merged_colors(&img));
+        assert_eq!(
+            vec![
+                Warning::PaletteChangesTransition {
+                    from: codel_of((0, 0xF0, 0x10)),
+                    to: r,
+                    baseline: Some(Command::Duplicate),
+                    mapped: Some(Command::Greater),
+                    merged: false,
+                    blocks: vec![((0, 1), (0, 0))],
+                },
+                Warning::PaletteChangesTransition {
+                    from: r,
+                    to: codel_of((0, 0xF0, 0x10)),
+                    baseline: Some(Command::Divide),
+                    mapped: Some(Command::Greater),
+                    merged: false,
+                    blocks: vec![((0, 0), (0, 1))],
+                },
+            ],
+            map.changed_transitions(&img)
+        );
+
+        //colors nearest to one color of Piet, e.g. the noise of a lossy image
+        let img = Image::from_codel_grid(vec![vec![r, codel_of((0xFE, 1, 1)), g]]);
+        let map = PaletteMap::parse("#FE0101 -> #FF0000").unwrap();
+        assert_eq!(Vec::<Warning>::new(), map.merged_colors(&img));
+        assert_eq!(Vec::<Warning>::new(), map.changed_transitions(&img));
+    }
+}
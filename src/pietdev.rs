@@ -0,0 +1,190 @@
+/*!
+Loader and exporter for the save format of the PietDev web editor.
+
+A save is a text file which stores a grid of palette indices instead of pixels:
+
+```text
+<width> <height>
+<index> <index> ... <index>    (`width` indices)
+...                            (`height` rows)
+```
+
+Indices are separated by whitespace, one row per line, and empty lines are ignored.
+Each index selects a color from [`PALETTE`]: the six hues (red, yellow, green, cyan, blue, magenta) in light, normal and dark lightness, followed by white and black.
+One cell corresponds to exactly one codel.
+*/
+
+use std::error::Error;
+
+use itertools::Itertools;
+
+use super::codel::Codel;
+use super::image::Image;
+use super::limits::ImageLimits;
+
+/// Index-to-color table of the format.
+pub const PALETTE: [Codel; 20] = [
+    Codel::LightRed,
+    Codel::LightYellow,
+    Codel::LightGreen,
+    Codel::LightCyan,
+    Codel::LightBlue,
+    Codel::LightMagenta,
+    Codel::Red,
+    Codel::Yellow,
+    Codel::Green,
+    Codel::Cyan,
+    Codel::Blue,
+    Codel::Magenta,
+    Codel::DarkRed,
+    Codel::DarkYellow,
+    Codel::DarkGreen,
+    Codel::DarkCyan,
+    Codel::DarkBlue,
+    Codel::DarkMagenta,
+    Codel::White,
+    Codel::Black,
+];
+
+/// Parses a save into a codel matrix (see [`Image::from_codel_grid()`]).
+/// Errors carry the (1-based) line and column of the offending cell.
+///
+/// The default [`ImageLimits`] apply (see [`parse_with_limits()`]).
+pub fn parse(s: &str) -> Result<Vec<Vec<Codel>>, Box<dyn Error>> {
+    parse_with_limits(s, &ImageLimits::default())
+}
+
+/// Same as [`parse()`] but with the given limits on the size, where a codel counts as a pixel.
+/// The limits are checked against the header before any row is read.
+pub fn parse_with_limits(s: &str, limits: &ImageLimits) -> Result<Vec<Vec<Codel>>, Box<dyn Error>> {
+    let mut lines = s
+        .lines()
+        .enumerate()
+        .map(|(i, l)| (i + 1, l))
+        .filter(|(_, l)| !l.trim().is_empty());
+
+    let (header_line, header) = lines.next().ok_or("empty PietDev save")?;
+    let (width, height) = header
+        .split_whitespace()
+        .map(|e| e.parse::<usize>())
+        .collect_tuple()
+        .and_then(|(w, h)| Some((w.ok()?, h.ok()?)))
+        .filter(|(w, h)| (*w > 0) && (*h > 0))
+        .ok_or(format!(
+            "line {}: the header shall be `<width> <height>` with positive integers",
+            header_line
+        ))?;
+    limits.check(height as u64, width as u64, None)?;
+
+    let mut m = Vec::with_capacity(height);
+    for (line, row) in lines {
+        if m.len() == height {
+            return Err(format!("line {}: expected {} rows but found more", line, height).into());
+        }
+        let row = row
+            .split_whitespace()
+            .enumerate()
+            .map(|(column, e)| {
+                e.parse::<usize>()
+                    .ok()
+                    .and_then(|index| PALETTE.get(index).copied())
+                    .ok_or(format!(
+                        "line {}, column {}: unknown palette index `{}`",
+                        line,
+                        column + 1,
+                        e
+                    ))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        if row.len() != width {
+            return Err(format!(
+                "line {}: expected {} cells but found {}",
+                line,
+                width,
+                row.len()
+            )
+            .into());
+        }
+        m.push(row);
+    }
+    if m.len() != height {
+        return Err(format!("expected {} rows but found {}", height, m.len()).into());
+    }
+
+    Ok(m)
+}
+
+/// Serializes an image into the save format so that it can be opened in the editor again.
+pub fn export(img: &Image) -> Result<String, Box<dyn Error>> {
+    let mut s = format!("{} {}\n", img.width(), img.height());
+    for i in 0..img.height() {
+        let row = (0..img.width())
+            .map(|j| {
+                let codel = img.get_codel_at((i, j))?;
+                PALETTE
+                    .iter()
+                    .position(|e| e == codel)
+                    .ok_or_else(|| format!("color at ({}, {}) is not in the palette", i, j).into())
+            })
+            .collect::<Result<Vec<_>, Box<dyn Error>>>()?;
+        s += &row.into_iter().join(" ");
+        s += "\n";
+    }
+    Ok(s)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::error::PietError;
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let m = parse("3 2\n6 7 18\n\n19 0 17\n").unwrap();
+        assert_eq!(
+            vec![
+                vec![Codel::Red, Codel::Yellow, Codel::White],
+                vec![Codel::Black, Codel::LightRed, Codel::DarkMagenta],
+            ],
+            m
+        );
+    }
+
+    #[test]
+    fn test_parse_error() {
+        let error = |s: &str| parse(s).unwrap_err().to_string();
+        assert_eq!("empty PietDev save", error(""));
+        assert!(error("3\n6 7 18\n").starts_with("line 1: the header"));
+        assert!(error("0 1\n").starts_with("line 1: the header"));
+        assert_eq!(
+            "line 3, column 2: unknown palette index `20`",
+            error("3 2\n6 7 18\n19 20 17\n")
+        );
+        assert_eq!(
+            "line 2, column 1: unknown palette index `x`",
+            error("3 2\nx 7 18\n19 0 17\n")
+        );
+        assert_eq!(
+            "line 3: expected 3 cells but found 2",
+            error("3 2\n6 7 18\n19 0\n")
+        );
+        assert_eq!("expected 2 rows but found 1", error("3 2\n6 7 18\n"));
+        assert_eq!(
+            "line 4: expected 2 rows but found more",
+            error("3 2\n6 7 18\n6 7 18\n6 7 18\n")
+        );
+        //rejected by the header alone
+        let e = parse(&format!("{} 1\n", usize::MAX)).unwrap_err();
+        assert!(matches!(
+            e.downcast_ref::<PietError>(),
+            Some(PietError::ImageTooLarge { .. })
+        ));
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let s = "3 2\n6 7 18\n19 0 17\n";
+        let img = Image::from_codel_grid(parse(s).unwrap());
+        assert_eq!(s, export(&img).unwrap());
+    }
+}
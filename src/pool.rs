@@ -0,0 +1,111 @@
+/*!
+Reuse of interpreters across runs, for a server which runs many short programs.
+
+A run on a new [`Interpreter`] allocates its stacks and input buffer again.
+[`InterpreterPool`] keeps finished interpreters and hands them out again after [`Interpreter::reset_for()`], which keeps the capacity of the buffers but none of the state.
+*/
+
+use std::sync::{Arc, Mutex};
+
+use super::interpreter::Interpreter;
+use super::metrics::Metrics;
+use super::program::Program;
+
+/// Idle interpreters shared by threads.
+#[derive(Default)]
+pub struct InterpreterPool {
+    idle: Mutex<Vec<Interpreter>>,
+    metrics: Option<Arc<Metrics>>,
+}
+
+impl InterpreterPool {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Same as [`Self::new()`] but counts whether each checkout reuses an interpreter in `metrics`.
+    pub fn with_metrics(metrics: Arc<Metrics>) -> Self {
+        Self {
+            metrics: Some(metrics),
+            ..Self::default()
+        }
+    }
+
+    /// Returns an interpreter prepared for a run of `program`: an idle one if any, or a new one.
+    pub fn checkout(&self, program: &Program) -> Interpreter {
+        let idle = self.idle.lock().unwrap().pop();
+        if let Some(metrics) = &self.metrics {
+            metrics.record_pool_checkout(idle.is_some());
+        }
+        let mut ip = idle.unwrap_or_else(Interpreter::new);
+        ip.reset_for(program);
+        ip
+    }
+
+    /// Returns an interpreter to the pool after a run.
+    pub fn checkin(&self, ip: Interpreter) {
+        self.idle.lock().unwrap().push(ip);
+    }
+
+    /// Returns the number of idle interpreters.
+    pub fn num_idle(&self) -> usize {
+        self.idle.lock().unwrap().len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+
+    use super::super::codel::Codel;
+    use super::super::image::Image;
+    use super::*;
+
+    #[test]
+    fn test_pool() {
+        let program = Program::new(Image::from_codel_grid(vec![vec![Codel::Red]]));
+        let pool = InterpreterPool::new();
+        let mut ip = pool.checkout(&program);
+        ip.stack.extend(0..100);
+        ip.num_steps = 5;
+        pool.checkin(ip);
+        assert_eq!(1, pool.num_idle());
+
+        //the state is gone but the capacity is kept
+        let ip = pool.checkout(&program);
+        assert_eq!(0, pool.num_idle());
+        assert_eq!(Interpreter::new().digest(), ip.digest());
+        assert!(ip.stack.is_empty());
+        assert!(ip.stack.capacity() >= 100);
+        assert_eq!(0, ip.num_steps);
+        pool.checkin(ip);
+
+        thread::scope(|s| {
+            for _ in 0..4 {
+                s.spawn(|| {
+                    //`Program` caches its derived data without synchronization, so each thread has its own
+                    let program = Program::new(Image::from_codel_grid(vec![vec![Codel::Red]]));
+                    for _ in 0..10 {
+                        let ip = pool.checkout(&program);
+                        assert!(ip.stack.is_empty());
+                        pool.checkin(ip);
+                    }
+                });
+            }
+        });
+        assert!((1..=4).contains(&pool.num_idle()));
+    }
+
+    #[test]
+    fn test_metrics() {
+        let program = Program::new(Image::from_codel_grid(vec![vec![Codel::Red]]));
+        let metrics = Arc::new(Metrics::new());
+        let pool = InterpreterPool::with_metrics(metrics.clone());
+        let ip = pool.checkout(&program);
+        pool.checkin(ip);
+        pool.checkout(&program);
+        let s = metrics.render();
+        assert!(s.contains("\npiet_interpreter_pool_checkouts_total{result=\"reused\"} 1\n"));
+        assert!(s.contains("\npiet_interpreter_pool_checkouts_total{result=\"new\"} 1\n"));
+    }
+}
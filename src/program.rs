@@ -0,0 +1,808 @@
+/*!
+A loaded program: an [`Image`] plus data derived from it.
+
+Derived data is computed on first use and then shared by everything which runs or inspects the same program, so that each feature does not build the same structures again.
+*/
+
+use std::cell::{Cell, OnceCell};
+use std::cmp::Reverse;
+use std::fmt::{self, Display};
+
+use clap::ValueEnum;
+
+use super::cc::CC;
+use super::codel::Codel;
+use super::coord;
+use super::corner::{CornerContext, CornerStrategy, Standard};
+use super::dp::DP;
+use super::image::Image;
+use super::stable::{self, Fnv64, Sink};
+
+/// For each `(DP, CC)`, the codel to which the interpreter moves when it leaves a block, or `None` if the move is restricted (by an edge or a black codel, or on a torus by the block itself).
+type Exits = [Option<(usize, usize)>; 8];
+
+/// For each `(DP, CC)` with which the interpreter tries to leave a block, the outcome of the attempts, or `None` if it has not been computed yet.
+type Retries = [Cell<Option<RetryOutcome>>; 8];
+
+/// Outcome of the (up to eight) attempts to leave a block (see [`Program::get_retry_outcome()`]).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RetryOutcome {
+    /// The interpreter moves to `next` with the DP and CC of the attempt which succeeded.
+    Exit {
+        next: (usize, usize),
+        dp: DP,
+        cc: CC,
+    },
+    /// Every attempt is restricted, so the program terminates.
+    /// The DP and CC are back at where they were as each of them has been changed four times.
+    NoExit,
+}
+
+/// Identity of the content of a program (the codels and the topology).
+/// Two programs with the same content have the same fingerprint, e.g. when an image is parsed again without changes.
+///
+/// The value is the 64-bit FNV-1a hash of `height (u64) | width (u64) | topology (u8) | codels in reading order` (little-endian, with the codels encoded as in `--cache-dir`), so it is the same on every platform and can be stored.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Fingerprint(u64);
+
+impl Fingerprint {
+    /// Returns the hash, for storing the fingerprint (e.g. in a checkpoint).
+    pub(crate) fn to_u64(self) -> u64 {
+        self.0
+    }
+
+    pub(crate) fn from_u64(x: u64) -> Self {
+        Self(x)
+    }
+}
+
+impl Display for Fingerprint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:016x}", self.0)
+    }
+}
+
+/// Which block is treated as the background (`--background`), e.g. to be excluded by `--ignore-background`.
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum Background {
+    /// The block detected by `Program::background_block()`, if any
+    #[default]
+    Auto,
+    /// No block (e.g. when the largest block is a part of the program)
+    None,
+}
+
+impl Background {
+    /// Returns the ID of the background block of `program` under this choice.
+    pub fn block(self, program: &Program) -> Option<usize> {
+        match self {
+            Background::Auto => program.background_block(),
+            Background::None => None,
+        }
+    }
+}
+
+/// The background block covers at least `1 / MIN_BACKGROUND_FRACTION` of the codels.
+const MIN_BACKGROUND_FRACTION: usize = 4;
+
+pub struct Program {
+    image: Image,
+    /// Exits of each block, indexed by the block ID.
+    exits: OnceCell<Vec<Exits>>,
+    /// Memoized outcomes of the attempts to leave each block, indexed by the block ID.
+    retries: OnceCell<Vec<Retries>>,
+    fingerprint: OnceCell<Fingerprint>,
+    background: OnceCell<Option<usize>>,
+
+    /// Number of times `exits` has been built.
+    #[cfg(test)]
+    num_exit_table_builds: Cell<usize>,
+    /// Number of times an outcome in `retries` has been computed.
+    #[cfg(test)]
+    num_retry_computations: Cell<usize>,
+}
+
+impl From<Image> for Program {
+    fn from(image: Image) -> Self {
+        Self::new(image)
+    }
+}
+
+impl Program {
+    pub fn new(image: Image) -> Self {
+        Self {
+            image,
+            exits: OnceCell::new(),
+            retries: OnceCell::new(),
+            fingerprint: OnceCell::new(),
+            background: OnceCell::new(),
+            #[cfg(test)]
+            num_exit_table_builds: Cell::new(0),
+            #[cfg(test)]
+            num_retry_computations: Cell::new(0),
+        }
+    }
+
+    pub fn image(&self) -> &Image {
+        &self.image
+    }
+
+    pub fn into_image(self) -> Image {
+        self.image
+    }
+
+    fn exit_index(dp: &DP, cc: &CC) -> usize {
+        (*dp as usize) * 2 + (*cc as usize)
+    }
+
+    /// Returns the codel to which the interpreter moves from `corner` of the block `id` in the direction of `dp`, or `None` if the move is restricted.
+    fn exit_from(&self, id: usize, corner: (usize, usize), dp: &DP) -> Option<(usize, usize)> {
+        let img = &self.image;
+        img.get_next_codel_index_in_dp_direction_unchecked(corner, dp)
+            .filter(|next| !img.get_codel_at_unchecked(*next).is_black())
+            //only on a torus, e.g. a block spanning the whole width
+            .filter(|next| img.get_block_id_at_unchecked(*next) != id)
+    }
+
+    /// Exits of the block `id` with [`Standard`] (for which the entry plays no role).
+    fn block_exits(&self, id: usize) -> Exits {
+        let block = self.image.get_block(id).unwrap();
+        let mut exits = [None; 8];
+        for dp in [DP::Right, DP::Down, DP::Left, DP::Up] {
+            for cc in [CC::Left, CC::Right] {
+                let corner = Standard.corner(&CornerContext {
+                    block,
+                    entry: block.first_codel(),
+                    dp,
+                    cc,
+                });
+                exits[Self::exit_index(&dp, &cc)] = self.exit_from(id, corner, &dp);
+            }
+        }
+        exits
+    }
+
+    fn exits(&self) -> &[Exits] {
+        self.exits.get_or_init(|| {
+            #[cfg(test)]
+            self.num_exit_table_builds
+                .set(self.num_exit_table_builds.get() + 1);
+
+            (0..self.image.num_blocks())
+                .map(|id| self.block_exits(id))
+                .collect()
+        })
+    }
+
+    fn retries(&self) -> &[Retries] {
+        self.retries.get_or_init(|| {
+            (0..self.image.num_blocks())
+                .map(|_| Retries::default())
+                .collect()
+        })
+    }
+
+    fn retry(&self, id: usize, dp: DP, cc: CC) -> RetryOutcome {
+        #[cfg(test)]
+        self.num_retry_computations
+            .set(self.num_retry_computations.get() + 1);
+
+        let exits = &self.exits()[id];
+        Self::retry_with(dp, cc, |dp, cc| exits[Self::exit_index(dp, cc)])
+    }
+
+    /// Makes the (up to eight) attempts starting with `(dp, cc)`, where `exit` returns the codel to which an attempt moves or `None` if it is restricted.
+    fn retry_with(
+        mut dp: DP,
+        mut cc: CC,
+        exit: impl Fn(&DP, &CC) -> Option<(usize, usize)>,
+    ) -> RetryOutcome {
+        for i in 0..8 {
+            //[spec]
+            // Black colour blocks and the edges of the program restrict program flow.
+            // If the Piet interpreter attempts to move into a black block or off an edge,
+            // it is stopped and the CC is toggled.
+            // The interpreter then attempts to move from its current block again.
+            // If it fails a second time, the DP is moved clockwise one step.
+            // These attempts are repeated, with the CC and DP being changed between alternate attempts.
+            // If after eight attempts the interpreter cannot leave its current colour block,
+            // there is no way out and the program terminates.
+            //Only black blocks and the edges restrict the flow, so a move into white ends the attempts as well,
+            //and the slide starts with the DP and CC changed by the failed attempts ("in the new direction of the DP", see `enter_white()`).
+            if let Some(next) = exit(&dp, &cc) {
+                return RetryOutcome::Exit { next, dp, cc };
+            }
+            if i % 2 == 0 {
+                cc = cc.flip();
+            } else {
+                dp = dp.turn_right();
+            }
+        }
+        RetryOutcome::NoExit
+    }
+
+    /// Returns the fingerprint of the content, which is computed on first use.
+    pub fn fingerprint(&self) -> Fingerprint {
+        *self.fingerprint.get_or_init(|| {
+            let img = &self.image;
+            let mut hasher = Fnv64::new();
+            hasher.put_u64(img.height() as u64);
+            hasher.put_u64(img.width() as u64);
+            stable::put_topology(&mut hasher, img.topology());
+            for i in 0..img.height() {
+                for j in 0..img.width() {
+                    stable::put_codel(&mut hasher, img.get_codel_at_unchecked((i, j)));
+                }
+            }
+            Fingerprint(hasher.finish())
+        })
+    }
+
+    /// Returns the borders of the image (top, right, bottom and left) which the block `id` touches.
+    /// In an image one codel high (or wide), the top and bottom (or left and right) borders are the same line, which every block touches, so neither of them is counted.
+    pub fn borders_of_block(&self, id: usize) -> [bool; 4] {
+        let (h, w) = (self.image.height(), self.image.width());
+        let mut borders = [false; 4];
+        for (i, j) in self.image.get_block(id).unwrap().codels() {
+            borders[0] |= (i == 0) && (h > 1);
+            borders[1] |= (Some(j) == coord::last(w)) && (w > 1);
+            borders[2] |= (Some(i) == coord::last(h)) && (h > 1);
+            borders[3] |= (j == 0) && (w > 1);
+        }
+        borders
+    }
+
+    /// Returns the ID of the block which is most likely the background of an artistic program rather than a part of its logic, or `None` if there seems to be none (e.g. in a small dense program).
+    ///
+    /// The background is the largest block which touches at least two borders of the image (see [`Self::borders_of_block()`]) and covers at least a quarter of it, the first one in reading order if tied.
+    /// It may be of any colour, including white and black.
+    /// This is a heuristic: the largest block of a small program is often a part of its logic (e.g. it pushes the code of a character), in which case `--background none` overrides it.
+    pub fn background_block(&self) -> Option<usize> {
+        *self.background.get_or_init(|| {
+            let img = &self.image;
+            let num_codels = img.height() * img.width();
+            (0..img.num_blocks())
+                .filter(|id| {
+                    img.get_block(*id).unwrap().size * MIN_BACKGROUND_FRACTION >= num_codels
+                })
+                .filter(|id| self.borders_of_block(*id).iter().filter(|b| **b).count() >= 2)
+                .min_by_key(|id| {
+                    let block = img.get_block(*id).unwrap();
+                    (Reverse(block.size), block.first_codel())
+                })
+        })
+    }
+
+    /// Changes the codel at `pos` to `codel` (e.g. on an edit in an editor) without reloading the whole program.
+    ///
+    /// The blocks are repaired locally, and the derived data is recomputed only for the blocks which may have changed.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `pos` is out of bounds.
+    pub fn apply_edit(&mut self, pos: (usize, usize), codel: Codel) {
+        let new_ids = self.image.set_codel(pos, codel);
+        self.fingerprint.take();
+        self.background.take();
+
+        let old_exits = self.exits.take();
+        let old_retries = self.retries.take();
+        if old_exits.is_none() && old_retries.is_none() {
+            return;
+        }
+        //Besides the changed blocks, the blocks next to `pos` may have an exit into it, whose restriction depends on the new codel.
+        let mut is_stale = vec![false; self.image.num_blocks()];
+        is_stale[self.image.get_block_id_at_unchecked(pos)] = true;
+        for dp in [DP::Right, DP::Down, DP::Left, DP::Up] {
+            if let Some(next) = self
+                .image
+                .get_next_codel_index_in_dp_direction_unchecked(pos, &dp)
+            {
+                is_stale[self.image.get_block_id_at_unchecked(next)] = true;
+            }
+        }
+        //the old ID of each new block which can keep its derived data
+        let mut kept = vec![None; self.image.num_blocks()];
+        for (old_id, new_id) in new_ids.into_iter().enumerate() {
+            if let Some(new_id) = new_id.filter(|id| !is_stale[*id]) {
+                kept[new_id] = Some(old_id);
+            }
+        }
+        if let Some(old_exits) = old_exits {
+            let exits = kept
+                .iter()
+                .enumerate()
+                .map(|(id, old_id)| old_id.map_or_else(|| self.block_exits(id), |k| old_exits[k]))
+                .collect();
+            let _ = self.exits.set(exits);
+        }
+        //an outcome depends only on the exits of the block, so it is kept with them
+        if let Some(old_retries) = old_retries {
+            let retries = kept
+                .iter()
+                .map(|old_id| old_id.map_or_else(Retries::default, |k| old_retries[k].clone()))
+                .collect();
+            let _ = self.retries.set(retries);
+        }
+    }
+
+    /// Returns the codel to which the interpreter moves when it leaves the block of the codel at `pos` with `(dp, cc)`.
+    /// `None` is returned if the move is restricted by an edge or a black codel, or on a torus if the move would re-enter the same block.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `pos` is out of bounds.
+    pub fn get_exit(&self, pos: (usize, usize), dp: &DP, cc: &CC) -> Option<(usize, usize)> {
+        self.exits()[self.image.get_block_id_at_unchecked(pos)][Self::exit_index(dp, cc)]
+    }
+
+    /// Returns the outcome of the attempts to leave the block of the codel at `pos`, starting with `(dp, cc)`.
+    /// Each attempt which is restricted ([`get_exit()`](Self::get_exit) returns `None`) toggles the CC or steps the DP clockwise alternately, as in the spec.
+    ///
+    /// The outcome depends only on the image, so it is computed on first use and memoized for each block and `(dp, cc)`.
+    /// This makes the attempts cheap for a program which bounces off the same walls many times.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `pos` is out of bounds.
+    pub fn get_retry_outcome(&self, pos: (usize, usize), dp: &DP, cc: &CC) -> RetryOutcome {
+        let id = self.image.get_block_id_at_unchecked(pos);
+        let memo = &self.retries()[id][Self::exit_index(dp, cc)];
+        if let Some(outcome) = memo.get() {
+            return outcome;
+        }
+        let outcome = self.retry(id, *dp, *cc);
+        memo.set(Some(outcome));
+        outcome
+    }
+
+    /// Same as [`get_retry_outcome()`](Self::get_retry_outcome) but each attempt leaves the block from the codel chosen by `strategy`, the block having been entered at `entry`.
+    /// Nothing is memoized, as the outcome may depend on the entry.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `entry` is out of bounds.
+    pub fn get_retry_outcome_with(
+        &self,
+        strategy: &dyn CornerStrategy,
+        entry: (usize, usize),
+        dp: &DP,
+        cc: &CC,
+    ) -> RetryOutcome {
+        let id = self.image.get_block_id_at_unchecked(entry);
+        let block = self.image.get_block(id).unwrap();
+        Self::retry_with(*dp, *cc, |&dp, &cc| {
+            let corner = strategy.corner(&CornerContext {
+                block,
+                entry,
+                dp,
+                cc,
+            });
+            debug_assert_eq!(
+                Ok(id),
+                self.image.get_block_id_at(corner),
+                "the corner strategy `{}` chose {:?}, which is outside of the block",
+                strategy.name(),
+                corner
+            );
+            self.exit_from(id, corner, &dp)
+        })
+    }
+
+    #[cfg(test)]
+    pub(crate) fn num_exit_table_builds(&self) -> usize {
+        self.num_exit_table_builds.get()
+    }
+
+    #[cfg(test)]
+    pub(crate) fn num_retry_computations(&self) -> usize {
+        self.num_retry_computations.get()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::image::Topology;
+    use super::*;
+
+    #[test]
+    fn test_get_exit() {
+        let (r, b, k) = (Codel::Red, Codel::Blue, Codel::Black);
+        let img = Image::from_codel_grid(vec![vec![r, r, b], vec![r, k, b]]);
+        let program = Program::new(img);
+        assert_eq!(0, program.num_exit_table_builds());
+
+        assert_eq!(
+            Some((0, 2)),
+            program.get_exit((1, 0), &DP::Right, &CC::Left)
+        );
+        //off the edge
+        assert_eq!(None, program.get_exit((0, 0), &DP::Down, &CC::Left));
+        assert_eq!(None, program.get_exit((0, 0), &DP::Up, &CC::Left));
+        assert_eq!(
+            Some((0, 1)),
+            program.get_exit((1, 2), &DP::Left, &CC::Right)
+        );
+        assert_eq!(None, program.get_exit((1, 2), &DP::Left, &CC::Left));
+
+        //every codel agrees with the image
+        for (i, j) in [(0, 0), (0, 1), (0, 2), (1, 0), (1, 2)] {
+            for dp in [DP::Right, DP::Down, DP::Left, DP::Up] {
+                for cc in [CC::Left, CC::Right] {
+                    let expected = program
+                        .image()
+                        .get_next_codel_index((i, j), &dp, &cc)
+                        .unwrap()
+                        .filter(|next| !program.image().get_codel_at(*next).unwrap().is_black());
+                    assert_eq!(expected, program.get_exit((i, j), &dp, &cc));
+                }
+            }
+        }
+        assert_eq!(1, program.num_exit_table_builds());
+    }
+
+    #[test]
+    fn test_get_retry_outcome() {
+        let (r, b, k) = (Codel::Red, Codel::Blue, Codel::Black);
+        let img = Image::from_codel_grid(vec![vec![r, b, k], vec![k, k, k]]);
+        let program = Program::new(img);
+
+        assert_eq!(
+            RetryOutcome::Exit {
+                next: (0, 1),
+                dp: DP::Right,
+                cc: CC::Left
+            },
+            program.get_retry_outcome((0, 0), &DP::Right, &CC::Left)
+        );
+        //the 5th attempt
+        assert_eq!(
+            RetryOutcome::Exit {
+                next: (0, 0),
+                dp: DP::Left,
+                cc: CC::Left
+            },
+            program.get_retry_outcome((0, 1), &DP::Right, &CC::Left)
+        );
+        //the 7th attempt
+        assert_eq!(
+            RetryOutcome::Exit {
+                next: (0, 0),
+                dp: DP::Left,
+                cc: CC::Left
+            },
+            program.get_retry_outcome((0, 1), &DP::Up, &CC::Right)
+        );
+        assert_eq!(3, program.num_retry_computations());
+
+        //memoized
+        for _ in 0..10 {
+            program.get_retry_outcome((0, 1), &DP::Right, &CC::Left);
+        }
+        assert_eq!(3, program.num_retry_computations());
+
+        let program = Program::new(Image::from_codel_grid(vec![vec![r, k]]));
+        for dp in [DP::Right, DP::Down, DP::Left, DP::Up] {
+            for cc in [CC::Left, CC::Right] {
+                assert_eq!(
+                    RetryOutcome::NoExit,
+                    program.get_retry_outcome((0, 0), &dp, &cc)
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_get_retry_outcome_with() {
+        use super::super::corner::EntryNearest;
+        use super::super::examples::Example;
+
+        //the standard strategy agrees with the memoized outcomes from any entry
+        let mut torus = Example::Hello.image();
+        torus.set_topology(Topology::Torus);
+        for img in [Example::Hello.image(), Example::Adder.image(), torus] {
+            let program = Program::new(img);
+            let img = program.image();
+            for i in 0..img.height() {
+                for j in 0..img.width() {
+                    let codel = img.get_codel_at_unchecked((i, j));
+                    if codel.is_white() || codel.is_black() {
+                        continue;
+                    }
+                    for dp in [DP::Right, DP::Down, DP::Left, DP::Up] {
+                        for cc in [CC::Left, CC::Right] {
+                            assert_eq!(
+                                program.get_retry_outcome((i, j), &dp, &cc),
+                                program.get_retry_outcome_with(&Standard, (i, j), &dp, &cc)
+                            );
+                        }
+                    }
+                }
+            }
+        }
+
+        //enters the red block at (2, 0) and leaves it in line with that
+        let (r, b, g, k) = (Codel::Red, Codel::Blue, Codel::Green, Codel::Black);
+        let program = Program::new(Image::from_codel_grid(vec![
+            vec![r, r, b],
+            vec![r, r, k],
+            vec![r, r, g],
+        ]));
+        assert_eq!(
+            RetryOutcome::Exit {
+                next: (0, 2),
+                dp: DP::Right,
+                cc: CC::Left
+            },
+            program.get_retry_outcome_with(&Standard, (2, 0), &DP::Right, &CC::Left)
+        );
+        assert_eq!(
+            RetryOutcome::Exit {
+                next: (2, 2),
+                dp: DP::Right,
+                cc: CC::Left
+            },
+            program.get_retry_outcome_with(&EntryNearest, (2, 0), &DP::Right, &CC::Left)
+        );
+        //Entered at the left edge, every attempt leaves in line with the entry, so it is restricted by the black codel or the edges.
+        assert_eq!(
+            RetryOutcome::NoExit,
+            program.get_retry_outcome_with(&EntryNearest, (1, 0), &DP::Right, &CC::Left)
+        );
+        assert!(matches!(
+            program.get_retry_outcome((1, 0), &DP::Right, &CC::Left),
+            RetryOutcome::Exit { next: (0, 2), .. }
+        ));
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic(expected = "outside of the block")]
+    fn test_get_retry_outcome_with_corner_outside_of_block() {
+        struct Origin;
+
+        impl CornerStrategy for Origin {
+            fn name(&self) -> &str {
+                "origin"
+            }
+
+            fn corner(&self, _: &CornerContext) -> (usize, usize) {
+                (0, 0)
+            }
+        }
+
+        let (r, b) = (Codel::Red, Codel::Blue);
+        let program = Program::new(Image::from_codel_grid(vec![vec![r, b, b]]));
+        program.get_retry_outcome_with(&Origin, (0, 1), &DP::Right, &CC::Left);
+    }
+
+    #[test]
+    fn test_fingerprint() {
+        let (r, b) = (Codel::Red, Codel::Blue);
+        let program = Program::new(Image::from_codel_grid(vec![vec![r, b, r]]));
+        assert_eq!(
+            program.fingerprint(),
+            Program::new(Image::from_codel_grid(vec![vec![r, b, r]])).fingerprint()
+        );
+        assert_ne!(
+            program.fingerprint(),
+            Program::new(Image::from_codel_grid(vec![vec![r], vec![b], vec![r]])).fingerprint()
+        );
+        let mut img = Image::from_codel_grid(vec![vec![r, b, r]]);
+        img.set_topology(Topology::Torus);
+        assert_ne!(program.fingerprint(), Program::new(img).fingerprint());
+
+        //an edit changes the fingerprint, and reverting it restores the fingerprint
+        let mut edited = Program::new(Image::from_codel_grid(vec![vec![r, b, r]]));
+        let original = edited.fingerprint();
+        edited.apply_edit((0, 1), r);
+        assert_ne!(original, edited.fingerprint());
+        edited.apply_edit((0, 1), b);
+        assert_eq!(original, edited.fingerprint());
+
+        //golden values, which shall be the same on every platform
+        let m = vec![
+            vec![r, b, r],
+            vec![Codel::White, Codel::Black, Codel::DarkGreen],
+        ];
+        assert_eq!(
+            "08ba6d08d61aabf1",
+            Program::new(Image::from_codel_grid(m.clone()))
+                .fingerprint()
+                .to_string()
+        );
+        let mut img = Image::from_codel_grid(m);
+        img.set_topology(Topology::Torus);
+        assert_eq!(
+            "c65a33e82bed14a6",
+            Program::new(img).fingerprint().to_string()
+        );
+    }
+
+    /// Minimal linear congruential generator so that the test is reproducible without a dependency.
+    struct Lcg(u64);
+
+    impl Lcg {
+        fn next(&mut self, n: usize) -> usize {
+            self.0 = self
+                .0
+                .wrapping_mul(6364136223846793005)
+                .wrapping_add(1442695040888963407);
+            ((self.0 >> 33) % n as u64) as usize
+        }
+    }
+
+    #[test]
+    fn test_apply_edit() {
+        //few colors so that edits often split and merge blocks
+        let colors = [Codel::Red, Codel::Blue, Codel::White, Codel::Black];
+        let mut rng = Lcg(42);
+        let image = |m: &Vec<Vec<Codel>>, topology| {
+            let mut img = Image::from_codel_grid(m.clone());
+            img.set_topology(topology);
+            img
+        };
+        for (height, width, topology) in [
+            (1, 8, Topology::Bounded),
+            (8, 1, Topology::Bounded),
+            (5, 7, Topology::Bounded),
+            (9, 9, Topology::Bounded),
+            (5, 7, Topology::Torus),
+        ] {
+            let mut m = (0..height)
+                .map(|_| (0..width).map(|_| colors[rng.next(4)]).collect::<Vec<_>>())
+                .collect::<Vec<_>>();
+            let mut program = Program::new(image(&m, topology));
+            program.exits();
+
+            for _ in 0..300 {
+                let pos = (rng.next(height), rng.next(width));
+                let codel = colors[rng.next(4)];
+                m[pos.0][pos.1] = codel;
+                program.apply_edit(pos, codel);
+
+                let expected = Program::new(image(&m, topology));
+                assert_eq!(expected.image().to_parts(), program.image().to_parts());
+                assert_eq!(expected.exits(), program.exits());
+                let background = |p: &Program| {
+                    p.background_block()
+                        .map(|id| p.image().get_block(id).unwrap().first_codel())
+                };
+                assert_eq!(background(&expected), background(&program));
+                for i in 0..height {
+                    for j in 0..width {
+                        for dp in [DP::Right, DP::Down, DP::Left, DP::Up] {
+                            assert_eq!(
+                                expected.get_retry_outcome((i, j), &dp, &CC::Left),
+                                program.get_retry_outcome((i, j), &dp, &CC::Left)
+                            );
+                        }
+                    }
+                }
+            }
+            //repaired, not rebuilt
+            assert_eq!(1, program.num_exit_table_builds());
+        }
+    }
+
+    #[test]
+    fn test_from_codels() {
+        use super::super::image::CodelSlice;
+        use super::super::limits::Limits;
+        use super::super::runner::{Output, Runner, RunnerConfig, TraceOptions};
+
+        let colors = [
+            Codel::Red,
+            Codel::DarkRed,
+            Codel::Yellow,
+            Codel::Blue,
+            Codel::LightGreen,
+            Codel::White,
+            Codel::Black,
+        ];
+        let mut rng = Lcg(7);
+        let mut runner = Runner::new(RunnerConfig {
+            limits: Limits::new(None, Some(300), None),
+            input: Some(b"12 x -3".to_vec()),
+            output: Output::Capture,
+            trace: TraceOptions {
+                record: true,
+                ..TraceOptions::default()
+            },
+            ..RunnerConfig::default()
+        });
+        let mut run = |program: &Program| {
+            let result = runner
+                .run(program)
+                .map(|result| (result.reason, result.num_steps))
+                .map_err(|e| e.to_string());
+            (result, runner.trace().to_vec(), runner.output().to_vec())
+        };
+        //one buffer for every generated program, as a generator would do
+        let mut codels = vec![];
+        for _ in 0..50 {
+            let (height, width) = (1 + rng.next(8), 1 + rng.next(8));
+            codels.clear();
+            codels.extend((0..height * width).map(|_| colors[rng.next(colors.len())]));
+
+            let owned = Program::new(Image::from_codel_grid(
+                codels.chunks(width).map(|row| row.to_vec()).collect(),
+            ));
+            let borrowed = Program::new(Image::from_codels(&CodelSlice::new(&codels, width)));
+            assert_eq!(owned.image().to_parts(), borrowed.image().to_parts());
+            assert_eq!(run(&owned), run(&borrowed));
+        }
+    }
+
+    #[test]
+    fn test_background_block() {
+        let (w, k, r, dr, b, y, lm) = (
+            Codel::White,
+            Codel::Black,
+            Codel::Red,
+            Codel::DarkRed,
+            Codel::Blue,
+            Codel::Yellow,
+            Codel::LightMagenta,
+        );
+        let background = |m: Vec<Vec<Codel>>| {
+            let program = Program::new(Image::from_codel_grid(m));
+            program.background_block().map(|id| {
+                let block = program.image().get_block(id).unwrap();
+                (block.first_codel(), block.size)
+            })
+        };
+
+        //white
+        let m = vec![
+            vec![w, w, w, w, w],
+            vec![w, r, r, dr, w],
+            vec![w, r, r, b, w],
+            vec![w, w, w, w, w],
+        ];
+        assert_eq!(Some(((0, 0), 14)), background(m));
+        //colored, behind a program in the top-left corner
+        let m = vec![
+            vec![r, dr, y, y, y],
+            vec![b, k, y, y, y],
+            vec![y, y, y, y, y],
+            vec![y, y, y, y, y],
+        ];
+        assert_eq!(Some(((0, 2), 16)), background(m));
+        //absent in a small dense program
+        let m = vec![vec![r, dr, b], vec![b, y, k], vec![lm, r, dr]];
+        assert_eq!(None, background(m));
+        //too small, or touching only one border
+        let m = vec![
+            vec![r, r, r, r],
+            vec![b, dr, dr, b],
+            vec![y, lm, k, k],
+            vec![y, w, k, k],
+        ];
+        assert_eq!(Some(((0, 0), 4)), background(m));
+        let m = vec![
+            vec![r, r, r, b],
+            vec![b, dr, y, y],
+            vec![lm, k, y, y],
+            vec![w, b, r, dr],
+        ];
+        assert_eq!(None, background(m));
+        //both borders of a single row are the same line
+        assert_eq!(None, background(vec![vec![r, r, r, dr, lm]]));
+        assert_eq!(
+            None,
+            background(vec![vec![dr], vec![r], vec![r], vec![r], vec![r]])
+        );
+        assert_eq!(
+            Some(((0, 0), 3)),
+            background(vec![vec![r], vec![r], vec![r]])
+        );
+        //the first one in reading order if tied
+        let m = vec![vec![b, b, r, r], vec![b, b, r, r]];
+        assert_eq!(Some(((0, 0), 4)), background(m));
+
+        let program = Program::new(Image::from_codel_grid(vec![vec![r, r, b], vec![r, r, b]]));
+        assert!(Background::Auto.block(&program).is_some());
+        assert_eq!(None, Background::None.block(&program));
+    }
+}
@@ -0,0 +1,180 @@
+use std::time::{Duration, Instant};
+
+use super::observer::{LoadObserver, LoadPhase};
+
+/// Live progress indicator for long-running programs (`--progress`).
+///
+/// The caller reports every step via [`Progress::tick()`], which returns a status line only when it should be (re)drawn.
+/// To keep the per-step cost negligible, the clock is read only once every [`Progress::CLOCK_CHECK_INTERVAL`] steps.
+pub struct Progress {
+    start: Instant,
+    last_draw: Instant,
+    redraw_interval: Duration,
+    has_drawn: bool,
+}
+
+impl Progress {
+    /// The clock is consulted once per this number of steps.
+    pub const CLOCK_CHECK_INTERVAL: usize = 1 << 12;
+
+    pub fn new(start: Instant, redraw_interval: Duration) -> Self {
+        Self {
+            start,
+            last_draw: start,
+            redraw_interval,
+            has_drawn: false,
+        }
+    }
+
+    /// Called on every step with the number of steps executed so far.
+    /// Returns the status line to draw (already prefixed with `\r`) if it is time to redraw.
+    pub fn tick(
+        &mut self,
+        num_steps: usize,
+        pos: (usize, usize),
+        now: impl FnOnce() -> Instant,
+    ) -> Option<String> {
+        //`CLOCK_CHECK_INTERVAL` is a power of two
+        if num_steps & (Self::CLOCK_CHECK_INTERVAL - 1) != 0 {
+            return None;
+        }
+        let now = now();
+        if now.duration_since(self.last_draw) < self.redraw_interval {
+            return None;
+        }
+        self.last_draw = now;
+        self.has_drawn = true;
+
+        let elapsed = now.duration_since(self.start);
+        let steps_per_sec = if elapsed.is_zero() {
+            0
+        } else {
+            (num_steps as f64 / elapsed.as_secs_f64()) as usize
+        };
+        Some(format!(
+            "\r\u{001B}[2Ksteps: {}  steps/s: {}  elapsed: {:.1}s  pos: {:?}",
+            num_steps,
+            steps_per_sec,
+            elapsed.as_secs_f64(),
+            pos
+        ))
+    }
+
+    /// Returns the escape sequence which erases the status line, or `None` if nothing has been drawn.
+    pub fn finish(&self) -> Option<&'static str> {
+        if self.has_drawn {
+            Some("\r\u{001B}[2K")
+        } else {
+            None
+        }
+    }
+}
+
+/// Status line of the progress of loading the image (`--progress`), drawn before the run starts.
+///
+/// A line is drawn only when the percentage changes, i.e. at most about a hundred times per phase.
+#[derive(Default)]
+pub struct LoadStatus {
+    last: Option<(LoadPhase, u32)>,
+}
+
+impl LoadStatus {
+    /// Returns the status line (already prefixed with `\r`) for `fraction` of `phase`, or `None` if the line drawn last is the same.
+    pub fn line(&mut self, phase: LoadPhase, fraction: f64) -> Option<String> {
+        let percent = (fraction * 100.0) as u32;
+        if self.last == Some((phase, percent)) {
+            return None;
+        }
+        self.last = Some((phase, percent));
+        Some(format!("\r\u{001B}[2Kloading: {} {}%", phase, percent))
+    }
+
+    /// Returns the escape sequence which erases the status line, or `None` if nothing has been drawn.
+    pub fn finish(&self) -> Option<&'static str> {
+        self.last.map(|_| "\r\u{001B}[2K")
+    }
+}
+
+impl LoadObserver for LoadStatus {
+    fn on_load_progress(&mut self, phase: LoadPhase, fraction: f64) {
+        if let Some(line) = self.line(phase, fraction) {
+            eprint!("{}", line);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_throttling() {
+        let start = Instant::now();
+        let mut progress = Progress::new(start, Duration::from_millis(250));
+        let n = Progress::CLOCK_CHECK_INTERVAL;
+
+        //the clock is not even read between the check points
+        assert_eq!(
+            None,
+            progress.tick(1, (0, 0), || panic!("the clock shall not be read"))
+        );
+        assert_eq!(None, progress.tick(n - 1, (0, 0), || panic!()));
+
+        //too early
+        assert_eq!(
+            None,
+            progress.tick(n, (0, 0), || start + Duration::from_millis(100))
+        );
+
+        let line = progress
+            .tick(2 * n, (3, 4), || start + Duration::from_millis(500))
+            .unwrap();
+        assert!(line.starts_with('\r'));
+        assert!(line.contains(&format!("steps: {}", 2 * n)));
+        assert!(line.contains(&format!("steps/s: {}", 4 * n)));
+        assert!(line.contains("elapsed: 0.5s"));
+        assert!(line.contains("pos: (3, 4)"));
+
+        //the interval is measured from the last draw
+        assert_eq!(
+            None,
+            progress.tick(3 * n, (0, 0), || start + Duration::from_millis(700))
+        );
+        assert!(progress
+            .tick(4 * n, (0, 0), || start + Duration::from_millis(750))
+            .is_some());
+    }
+
+    #[test]
+    fn test_finish() {
+        let start = Instant::now();
+        let mut progress = Progress::new(start, Duration::from_millis(250));
+        assert_eq!(None, progress.finish());
+
+        progress.tick(Progress::CLOCK_CHECK_INTERVAL, (0, 0), || {
+            start + Duration::from_secs(1)
+        });
+        assert_eq!(Some("\r\u{001B}[2K"), progress.finish());
+    }
+
+    #[test]
+    fn test_load_status() {
+        let mut status = LoadStatus::default();
+        assert_eq!(None, status.finish());
+
+        assert_eq!(
+            Some("\r\u{001B}[2Kloading: decoding 0%".to_string()),
+            status.line(LoadPhase::Decode, 0.0)
+        );
+        assert_eq!(None, status.line(LoadPhase::Decode, 0.001));
+        assert_eq!(
+            Some("\r\u{001B}[2Kloading: sampling codels 0%".to_string()),
+            status.line(LoadPhase::Sample, 0.0)
+        );
+        assert_eq!(
+            Some("\r\u{001B}[2Kloading: sampling codels 42%".to_string()),
+            status.line(LoadPhase::Sample, 0.425)
+        );
+        assert_eq!(Some("\r\u{001B}[2K"), status.finish());
+    }
+}
@@ -0,0 +1,127 @@
+/*!
+Runtime hint of a wrong codel size from the operands of `Push` (`--check-runtime`).
+
+When an image scaled by `s` (e.g. an upscaled screenshot) is run with a codel size `s` times too small, each block has `s²` times as many codels,
+so every `Push` pushes a multiple of `s²`.
+This is sometimes the only sign of the wrong codel size, as the detection at load time cannot tell a scaled image from a program drawn with large blocks.
+
+The operands are summarized by their GCD as they are pushed, and at the end of the run a warning is raised if the GCD has a square factor `s² > 1`.
+A program may genuinely push only multiples of 4, so this is only a warning, and it needs [`MIN_PUSHES`] pushes as evidence.
+*/
+
+use super::warning::Warning;
+
+/// Number of pushes below which no warning is raised.
+pub const MIN_PUSHES: usize = 3;
+
+/// Number of distinct operands kept as the evidence of the warning.
+const NUM_SAMPLES: usize = 5;
+
+/// Summary of the operands of `Push` in a run.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub(crate) struct PushOperands {
+    /// GCD of the operands so far (`0` before the first push).
+    gcd: usize,
+    num_pushes: usize,
+    /// The first few distinct operands, in the order they were pushed.
+    samples: Vec<usize>,
+}
+
+fn gcd(a: usize, b: usize) -> usize {
+    if b == 0 {
+        a
+    } else {
+        gcd(b, a % b)
+    }
+}
+
+/// Returns the largest `s` such that `s²` divides `n` (`1` if `n` is square-free or `0`).
+pub(crate) fn square_factor(mut n: usize) -> usize {
+    let mut ret = 1;
+    let mut p = 2;
+    while (n != 0) && (p <= n / p) {
+        let mut e = 0;
+        while n.is_multiple_of(p) {
+            n /= p;
+            e += 1;
+        }
+        ret *= p.pow(e / 2);
+        p += 1;
+    }
+    ret
+}
+
+impl PushOperands {
+    /// Records that `value` (the size of a block) was pushed.
+    pub(crate) fn record(&mut self, value: usize) {
+        self.gcd = gcd(self.gcd, value);
+        self.num_pushes += 1;
+        if (self.samples.len() < NUM_SAMPLES) && !self.samples.contains(&value) {
+            self.samples.push(value);
+        }
+    }
+
+    /// Returns `true` if nothing has been pushed yet.
+    pub(crate) fn is_empty(&self) -> bool {
+        self.num_pushes == 0
+    }
+
+    /// Returns the warning about the operands recorded so far, if all of them share a square factor.
+    pub(crate) fn warning(&self) -> Option<Warning> {
+        let factor = square_factor(self.gcd);
+        ((self.num_pushes >= MIN_PUSHES) && (factor > 1)).then(|| Warning::SquarePushOperands {
+            factor,
+            num_pushes: self.num_pushes,
+            samples: self.samples.clone(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_square_factor() {
+        assert_eq!(1, square_factor(0));
+        assert_eq!(1, square_factor(1));
+        assert_eq!(1, square_factor(6));
+        assert_eq!(2, square_factor(4));
+        assert_eq!(2, square_factor(12));
+        assert_eq!(3, square_factor(9));
+        assert_eq!(6, square_factor(36 * 5));
+        assert_eq!(4, square_factor(16 * 3));
+        assert_eq!(1, square_factor(usize::MAX));
+    }
+
+    #[test]
+    fn test_warning() {
+        let mut operands = PushOperands::default();
+        assert_eq!(None, operands.warning());
+        for value in [8, 4, 8, 12] {
+            operands.record(value);
+        }
+        assert_eq!(
+            Some(Warning::SquarePushOperands {
+                factor: 2,
+                num_pushes: 4,
+                samples: vec![8, 4, 12],
+            }),
+            operands.warning()
+        );
+        //one odd operand clears the suspicion
+        operands.record(3);
+        assert_eq!(None, operands.warning());
+
+        //too few pushes
+        let mut operands = PushOperands::default();
+        operands.record(9);
+        operands.record(18);
+        assert_eq!(None, operands.warning());
+        operands.record(27);
+        assert!(matches!(
+            operands.warning(),
+            Some(Warning::SquarePushOperands { factor: 3, .. })
+        ));
+    }
+}
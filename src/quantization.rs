@@ -0,0 +1,242 @@
+/*!
+Detection of canonical colors which may have been merged by color quantization (e.g. a GIF exported with a tight palette).
+
+Some canonical colors are hard to tell apart, e.g. light yellow (`#FFFFC0`) and white (`#FFFFFF`) differ only in blue, to which the eye is the least sensitive.
+A quantizer may map both to one palette entry, which silently turns command blocks into white corridors (or merges two hues) with no unknown color left to report.
+
+Closeness is measured by the squared RGB distance weighted as in luma (`30:59:11`), and two colors are *confusable* if they are closer than a step of one lightness level (`0x3F`) in red alone.
+So light yellow and white, light red and light magenta, light green and light cyan, and blue and dark blue are confusable.
+
+- For a GIF whose global palette has fewer than [`MIN_PALETTE_SIZE`] entries, each canonical color is mapped to its nearest entry if they are confusable (or identical),
+  and an entry to which two or more canonical colors are mapped is reported ([`palette_collisions()`]).
+- For the other formats, which have no palette, a canonical color is reported if it is absent while a confusable one is the most frequent color other than black ([`merged_colors()`]).
+  The frequency is the number of blocks rather than of codels, since merged command blocks stay separate blocks while a large background is only one.
+  This is only a heuristic, so it is run by `--check`.
+*/
+
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::Path;
+
+use super::codel::Codel;
+use super::image::Image;
+use super::pietdev::PALETTE;
+use super::warning::Warning;
+
+/// Colors of a palette in the order of the entries.
+pub type Palette = Vec<(u8, u8, u8)>;
+
+/// A GIF palette with fewer entries than this cannot hold all the 20 canonical colors, so the image may have been quantized.
+pub const MIN_PALETTE_SIZE: usize = 20;
+
+/// Distance of a step of one lightness level (`0x3F`) in red alone, below which two colors are confusable.
+const CONFUSION_THRESHOLD: u32 = 30 * 0x3F * 0x3F;
+
+pub(crate) fn distance(a: (u8, u8, u8), b: (u8, u8, u8)) -> u32 {
+    let d = |x: u8, y: u8| u32::from(x.abs_diff(y)).pow(2);
+    30 * d(a.0, b.0) + 59 * d(a.1, b.1) + 11 * d(a.2, b.2)
+}
+
+fn is_confusable(a: (u8, u8, u8), b: (u8, u8, u8)) -> bool {
+    distance(a, b) < CONFUSION_THRESHOLD
+}
+
+/// Reads the global palette of `file` if it is a GIF with one (`None` for the other formats).
+/// The local palettes of the frames are not read.
+pub fn read_gif_palette(file: impl AsRef<Path>) -> io::Result<Option<Palette>> {
+    //header (6 bytes), logical screen descriptor (7 bytes) and a global palette of up to 256 entries
+    let mut bytes = vec![];
+    File::open(file)?
+        .take(13 + 3 * 256)
+        .read_to_end(&mut bytes)?;
+    Ok(parse_gif_palette(&bytes))
+}
+
+fn parse_gif_palette(bytes: &[u8]) -> Option<Palette> {
+    if !bytes.starts_with(b"GIF87a") && !bytes.starts_with(b"GIF89a") {
+        return None;
+    }
+    let flags = *bytes.get(10)?;
+    if flags & 0x80 == 0 {
+        return None;
+    }
+    let num_entries = 2 << (flags & 0x07);
+    let table = bytes.get(13..13 + 3 * num_entries)?;
+    Some(table.chunks(3).map(|c| (c[0], c[1], c[2])).collect())
+}
+
+/// Returns the number of codels of `img` whose color is `rgb`.
+fn count_codels(img: &Image, rgb: (u8, u8, u8)) -> usize {
+    (0..img.height())
+        .flat_map(|i| (0..img.width()).map(move |j| (i, j)))
+        .filter(|&pos| img.get_codel_at_unchecked(pos).rgb() == rgb)
+        .count()
+}
+
+/// Reports the entries of `palette` (the palette of the image file of `img`) to which two or more canonical colors are mapped, if the palette is small.
+pub fn palette_collisions(palette: &[(u8, u8, u8)], img: &Image) -> Vec<Warning> {
+    if palette.len() >= MIN_PALETTE_SIZE {
+        return vec![];
+    }
+    //canonical colors mapped to each entry
+    let mut mapped = vec![vec![]; palette.len()];
+    for color in PALETTE {
+        //the first of the nearest entries, as a duplicate entry is never used
+        let nearest = (0..palette.len()).min_by_key(|&k| distance(color.rgb(), palette[k]));
+        if let Some(k) = nearest.filter(|&k| is_confusable(color.rgb(), palette[k])) {
+            mapped[k].push(color);
+        }
+    }
+    mapped
+        .into_iter()
+        .enumerate()
+        .filter(|(_, colors)| colors.len() >= 2)
+        .filter_map(|(entry, colors)| {
+            let num_codels = count_codels(img, palette[entry]);
+            (num_codels != 0).then_some(Warning::PaletteCollision {
+                palette_size: palette.len(),
+                entry,
+                colors,
+                num_codels,
+            })
+        })
+        .collect()
+}
+
+/// Reports the canonical colors absent from `img` while a confusable one has the most blocks of the colors other than black.
+pub fn merged_colors(img: &Image) -> Vec<Warning> {
+    let mut counts = PALETTE.map(|color| (color, 0));
+    for id in 0..img.num_blocks() {
+        let pos = img.get_block_codels(id)[0];
+        if let Some(k) = PALETTE
+            .iter()
+            .position(|c| c == img.get_codel_at_unchecked(pos))
+        {
+            counts[k].1 += 1;
+        }
+    }
+    let candidates = counts.iter().filter(|(color, _)| *color != Codel::Black);
+    let Some(&(neighbor, num_blocks)) = candidates.clone().max_by_key(|(_, n)| *n) else {
+        return vec![];
+    };
+    //a tie is not an overrepresentation
+    if (num_blocks == 0) || (candidates.filter(|(_, n)| *n == num_blocks).count() > 1) {
+        return vec![];
+    }
+    counts
+        .iter()
+        .filter(|&&(color, n)| (n == 0) && is_confusable(color.rgb(), neighbor.rgb()))
+        .map(|&(absent, _)| Warning::ColorMaybeMerged {
+            absent,
+            neighbor,
+            num_blocks,
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_confusable() {
+        let pairs = PALETTE
+            .iter()
+            .enumerate()
+            .flat_map(|(k, a)| PALETTE[k + 1..].iter().map(move |b| (*a, *b)))
+            .filter(|(a, b)| is_confusable(a.rgb(), b.rgb()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            vec![
+                (Codel::LightRed, Codel::LightMagenta),
+                (Codel::LightYellow, Codel::White),
+                (Codel::LightGreen, Codel::LightCyan),
+                (Codel::Blue, Codel::DarkBlue),
+            ],
+            pairs
+        );
+    }
+
+    #[test]
+    fn test_palette_collisions() {
+        let m = vec![
+            vec![Codel::Red, Codel::White, Codel::White],
+            vec![Codel::Black, Codel::Black, Codel::Yellow],
+        ];
+        let img = Image::from_codel_grid(m);
+        //light yellow was quantized to white
+        let palette = [
+            Codel::Black,
+            Codel::Red,
+            Codel::White,
+            Codel::Yellow,
+            Codel::Black,
+        ]
+        .map(|c| c.rgb());
+        assert_eq!(
+            vec![Warning::PaletteCollision {
+                palette_size: 5,
+                entry: 2,
+                colors: vec![Codel::LightYellow, Codel::White],
+                num_codels: 2,
+            }],
+            palette_collisions(&palette, &img)
+        );
+        //an unused entry cannot have merged colors
+        let palette = [Codel::Red, Codel::LightYellow, Codel::Yellow].map(|c| c.rgb());
+        assert!(palette_collisions(&palette, &img).is_empty());
+        let palette = PALETTE.map(|c| c.rgb());
+        assert!(palette_collisions(&palette, &img).is_empty());
+    }
+
+    #[test]
+    fn test_merged_colors() {
+        let img = Image::from_codel_grid(vec![vec![
+            Codel::White,
+            Codel::Red,
+            Codel::White,
+            Codel::Black,
+            Codel::Black,
+            Codel::Black,
+        ]]);
+        assert_eq!(
+            vec![Warning::ColorMaybeMerged {
+                absent: Codel::LightYellow,
+                neighbor: Codel::White,
+                num_blocks: 2,
+            }],
+            merged_colors(&img)
+        );
+        //light yellow is present
+        let img =
+            Image::from_codel_grid(vec![vec![Codel::White, Codel::White, Codel::LightYellow]]);
+        assert!(merged_colors(&img).is_empty());
+        //a tie is not an overrepresentation
+        let img = Image::from_codel_grid(vec![vec![Codel::White, Codel::Red]]);
+        assert!(merged_colors(&img).is_empty());
+        //a large background is only one block
+        let img = Image::from_codel_grid(vec![vec![
+            Codel::White,
+            Codel::White,
+            Codel::White,
+            Codel::Red,
+            Codel::Black,
+            Codel::Red,
+        ]]);
+        assert!(merged_colors(&img).is_empty());
+    }
+
+    #[test]
+    fn test_parse_gif_palette() {
+        let mut bytes = b"GIF89a\x02\x00\x01\x00\x80\x00\x00".to_vec();
+        bytes.extend([255, 255, 255, 0, 0, 0]);
+        assert_eq!(
+            Some(vec![(255, 255, 255), (0, 0, 0)]),
+            parse_gif_palette(&bytes)
+        );
+        //no global palette
+        bytes[10] = 0;
+        assert_eq!(None, parse_gif_palette(&bytes));
+        assert_eq!(None, parse_gif_palette(b"\x89PNG\r\n\x1a\n"));
+    }
+}
@@ -0,0 +1,413 @@
+/*!
+Pseudo-random input generated on demand (`--input-random`), for stress-testing interactive programs without preparing input files.
+
+The spec is a comma-separated list of `key=value`:
+
+```text
+seed=42,numbers=0..=2,count=100
+```
+
+- `seed`: seed of the generator (if omitted, one is chosen at random and reported so that the session can be replayed)
+- exactly one of
+  - `numbers=LOW..=HIGH` (or `LOW..HIGH`): integers in the range, each followed by a newline
+  - `chars=SET`: characters of `SET`, which is a series of characters and ranges such as `a-z` (e.g. `a-zA-Z_`)
+  - `bytes`: arbitrary bytes
+- `count`: number of integers, characters or bytes, after which the input ends (unbounded if omitted)
+
+The same spec with the same seed always generates the same input on every platform.
+The input is served through [`Stdin`](super::stdin::Stdin) as any other, so a program cannot tell the difference.
+*/
+
+use std::fmt::{self, Display, Formatter};
+use std::io::{self, Read};
+use std::str::FromStr;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// What is generated.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Alphabet {
+    /// Integers in `low..=high`.
+    Numbers {
+        low: isize,
+        high: isize,
+    },
+    /// Characters in the union of the inclusive ranges.
+    Chars(Vec<(char, char)>),
+    Bytes,
+}
+
+/// Parsed value of `--input-random`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RandomSpec {
+    pub seed: Option<u64>,
+    pub alphabet: Alphabet,
+    pub count: Option<usize>,
+}
+
+fn parse_numbers(s: &str) -> Option<Alphabet> {
+    let (low, high) = match s.split_once("..=") {
+        Some((low, high)) => (low.parse().ok()?, high.parse().ok()?),
+        None => {
+            let (low, high) = s.split_once("..")?;
+            let high: isize = high.parse().ok()?;
+            (low.parse().ok()?, high.checked_sub(1)?)
+        }
+    };
+    (low <= high).then_some(Alphabet::Numbers { low, high })
+}
+
+fn parse_chars(s: &str) -> Result<Alphabet, String> {
+    let v = s.chars().collect::<Vec<_>>();
+    let mut ranges = vec![];
+    let mut rest = &v[..];
+    //`-` is a literal unless it is between two characters
+    while let [first, tail @ ..] = rest {
+        let (range, tail) = match tail {
+            ['-', last, tail @ ..] => ((*first, *last), tail),
+            _ => ((*first, *first), tail),
+        };
+        if range.0 > range.1 {
+            return Err(format!(
+                "invalid range `{}-{}` in `chars`",
+                range.0, range.1
+            ));
+        }
+        //A range across the surrogates would contain code points which are not characters.
+        if (range.0 <= '\u{D7FF}') && (range.1 >= '\u{E000}') {
+            return Err(format!(
+                "range `{}-{}` in `chars` contains surrogates",
+                range.0.escape_unicode(),
+                range.1.escape_unicode()
+            ));
+        }
+        ranges.push(range);
+        rest = tail;
+    }
+    if ranges.is_empty() {
+        return Err("`chars` is empty".to_string());
+    }
+    Ok(Alphabet::Chars(ranges))
+}
+
+impl FromStr for RandomSpec {
+    type Err = String;
+
+    /// Parses e.g. `seed=42,numbers=0..=2,count=100`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut seed = None;
+        let mut alphabet = None;
+        let mut count = None;
+        for item in s.split(',').map(str::trim) {
+            let (key, value) = item.split_once('=').unwrap_or((item, ""));
+            let invalid = || format!("invalid value of `{}`: `{}`", key, value);
+            let new = match key {
+                "seed" => seed
+                    .replace(value.parse::<u64>().map_err(|_| invalid())?)
+                    .is_none(),
+                "count" => count
+                    .replace(value.parse::<usize>().map_err(|_| invalid())?)
+                    .is_none(),
+                "numbers" => alphabet
+                    .replace(parse_numbers(value).ok_or_else(invalid)?)
+                    .is_none(),
+                "chars" => alphabet.replace(parse_chars(value)?).is_none(),
+                "bytes" if value.is_empty() && !item.contains('=') => {
+                    alphabet.replace(Alphabet::Bytes).is_none()
+                }
+                "bytes" => return Err("`bytes` takes no value".to_string()),
+                _ => return Err(format!("unknown key `{}`", key)),
+            };
+            if !new {
+                return Err(match key {
+                    "seed" | "count" => format!("`{}` is given more than once", key),
+                    _ => "at most one of `numbers`, `chars` and `bytes` can be given".to_string(),
+                });
+            }
+        }
+        let alphabet = alphabet.ok_or("one of `numbers`, `chars` and `bytes` is required")?;
+        Ok(RandomSpec {
+            seed,
+            alphabet,
+            count,
+        })
+    }
+}
+
+impl Display for RandomSpec {
+    /// Prints the spec in the form accepted by [`RandomSpec::from_str()`].
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        if let Some(seed) = self.seed {
+            write!(f, "seed={},", seed)?;
+        }
+        match &self.alphabet {
+            Alphabet::Numbers { low, high } => write!(f, "numbers={}..={}", low, high)?,
+            Alphabet::Chars(ranges) => {
+                write!(f, "chars=")?;
+                for &(first, last) in ranges {
+                    if first == last {
+                        write!(f, "{}", first)?;
+                    } else {
+                        write!(f, "{}-{}", first, last)?;
+                    }
+                }
+            }
+            Alphabet::Bytes => write!(f, "bytes")?,
+        }
+        if let Some(count) = self.count {
+            write!(f, ",count={}", count)?;
+        }
+        Ok(())
+    }
+}
+
+impl RandomSpec {
+    /// Returns the spec with the seed chosen at random if it is omitted.
+    pub fn with_seed(&self) -> RandomSpec {
+        let seed = self.seed.unwrap_or_else(|| {
+            //Only the seed itself has to be unpredictable, so the clock is enough.
+            let t = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default();
+            SplitMix64(t.as_nanos() as u64 ^ u64::from(std::process::id())).next_u64()
+        });
+        RandomSpec {
+            seed: Some(seed),
+            ..self.clone()
+        }
+    }
+}
+
+/// [SplitMix64](https://prng.di.unimi.it/splitmix64.c), chosen as it is tiny and its output is fixed by the seed alone.
+struct SplitMix64(u64);
+
+impl SplitMix64 {
+    fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    /// Returns an integer uniformly distributed in `0..n` (`n` shall be positive).
+    fn below(&mut self, n: u64) -> u64 {
+        //rejects the values of the last incomplete cycle of `0..n` to avoid the bias of `%`
+        let end = u64::MAX - (u64::MAX % n);
+        loop {
+            let x = self.next_u64();
+            if x < end {
+                return x % n;
+            }
+        }
+    }
+}
+
+/// Reader generating the input of a [`RandomSpec`].
+pub struct RandomInput {
+    rng: SplitMix64,
+    alphabet: Alphabet,
+    /// Number of items left, or `None` if unbounded.
+    remaining: Option<usize>,
+    /// Bytes of the current item not read yet.
+    pending: Vec<u8>,
+}
+
+impl RandomInput {
+    /// Creates the reader of `spec`, whose seed shall be set (see [`RandomSpec::with_seed()`]).
+    ///
+    /// # Panics
+    ///
+    /// Panics if the seed of `spec` is `None`.
+    pub fn new(spec: &RandomSpec) -> Self {
+        Self {
+            rng: SplitMix64(spec.seed.expect("the seed is not set")),
+            alphabet: spec.alphabet.clone(),
+            remaining: spec.count,
+            pending: vec![],
+        }
+    }
+
+    /// Appends the next item to `pending` (in reverse order so that it can be popped).
+    fn generate(&mut self) {
+        match &self.alphabet {
+            Alphabet::Numbers { low, high } => {
+                let span = (*high as i128 - *low as i128) as u128 + 1;
+                let n = if span > u128::from(u64::MAX) {
+                    self.rng.next_u64() as isize
+                } else {
+                    (*low as i128 + self.rng.below(span as u64) as i128) as isize
+                };
+                self.pending.extend(format!("{}\n", n).bytes().rev());
+            }
+            Alphabet::Chars(ranges) => {
+                let size =
+                    |&(first, last): &(char, char)| u64::from(last as u32 - first as u32) + 1;
+                let mut k = self.rng.below(ranges.iter().map(size).sum());
+                for range in ranges {
+                    if k < size(range) {
+                        let c = char::from_u32(range.0 as u32 + k as u32).unwrap();
+                        self.pending.extend(c.to_string().bytes().rev());
+                        return;
+                    }
+                    k -= size(range);
+                }
+                unreachable!();
+            }
+            Alphabet::Bytes => self.pending.push(self.rng.next_u64() as u8),
+        }
+    }
+}
+
+impl Read for RandomInput {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let mut n = 0;
+        while n < buf.len() {
+            if self.pending.is_empty() {
+                if self.remaining == Some(0) {
+                    break;
+                }
+                if let Some(remaining) = self.remaining.as_mut() {
+                    *remaining -= 1;
+                }
+                self.generate();
+            }
+            buf[n] = self.pending.pop().unwrap();
+            n += 1;
+        }
+        Ok(n)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::stdin::Stdin;
+    use super::*;
+
+    fn generate(spec: &str) -> Vec<u8> {
+        let mut v = vec![];
+        RandomInput::new(&spec.parse().unwrap())
+            .read_to_end(&mut v)
+            .unwrap();
+        v
+    }
+
+    #[test]
+    fn test_determinism() {
+        let s = "seed=42,numbers=0..=2,count=100";
+        let numbers = generate(s);
+        assert_eq!(numbers, generate(s));
+        let numbers = String::from_utf8(numbers).unwrap();
+        let v = numbers.lines().collect::<Vec<_>>();
+        assert_eq!(100, v.len());
+        assert!(v.iter().all(|n| ["0", "1", "2"].contains(n)));
+        //every value appears
+        assert!(["0", "1", "2"].iter().all(|n| v.contains(n)));
+        assert_ne!(
+            numbers.as_bytes(),
+            generate("seed=43,numbers=0..=2,count=100")
+        );
+        //the seed fixes the values, so a shorter input is a prefix
+        assert!(numbers
+            .starts_with(std::str::from_utf8(&generate("seed=42,numbers=0..3,count=10")).unwrap()));
+
+        let chars = String::from_utf8(generate("seed=7,chars=a-cxé,count=1000")).unwrap();
+        assert_eq!(1000, chars.chars().count());
+        assert!("abcxé".chars().all(|c| chars.contains(c)));
+        assert!(chars.chars().all(|c| "abcxé".contains(c)));
+
+        assert_eq!(5, generate("seed=1,bytes,count=5").len());
+        let wide = String::from_utf8(generate(&format!(
+            "seed=1,numbers={}..={},count=3",
+            isize::MIN,
+            isize::MAX
+        )))
+        .unwrap();
+        assert!(wide.lines().all(|n| n.parse::<isize>().is_ok()));
+
+        //unbounded
+        let mut buf = [0; 10_000];
+        let mut input = RandomInput::new(&"seed=1,bytes".parse().unwrap());
+        assert_eq!(10_000, input.read(&mut buf).unwrap());
+
+        //through `Stdin`
+        let read = |spec: &str| {
+            let mut stdin = Stdin::new();
+            stdin.reset_with_reader(Box::new(RandomInput::new(&spec.parse().unwrap())));
+            std::iter::from_fn(|| stdin.read_integer()).collect::<Vec<_>>()
+        };
+        let v = read("seed=5,numbers=-10..=10,count=50");
+        assert_eq!(50, v.len());
+        assert!(v.iter().all(|n| (-10..=10).contains(n)));
+        assert_eq!(v, read("seed=5,numbers=-10..=10,count=50"));
+    }
+
+    #[test]
+    fn test_seed() {
+        let spec = "numbers=0..=9".parse::<RandomSpec>().unwrap();
+        assert_eq!(None, spec.seed);
+        let spec = spec.with_seed();
+        assert!(spec.seed.is_some());
+        //the spec with the seed is printed to replay the session
+        assert_eq!(spec, spec.to_string().parse().unwrap());
+        assert_eq!(spec, spec.with_seed());
+    }
+
+    #[test]
+    fn test_parse() {
+        let spec = "seed=42, numbers=0..=2, count=100"
+            .parse::<RandomSpec>()
+            .unwrap();
+        assert_eq!(
+            RandomSpec {
+                seed: Some(42),
+                alphabet: Alphabet::Numbers { low: 0, high: 2 },
+                count: Some(100),
+            },
+            spec
+        );
+        assert_eq!("seed=42,numbers=0..=2,count=100", spec.to_string());
+        assert_eq!(
+            Ok(Alphabet::Numbers { low: -3, high: 2 }),
+            "numbers=-3..3".parse().map(|s: RandomSpec| s.alphabet)
+        );
+        assert_eq!(
+            Ok(Alphabet::Chars(vec![('a', 'z'), ('_', '_'), ('-', '-')])),
+            "chars=a-z_-".parse().map(|s: RandomSpec| s.alphabet)
+        );
+        assert_eq!(
+            "chars=-a-c",
+            "chars=-a-c".parse::<RandomSpec>().unwrap().to_string()
+        );
+        assert_eq!("bytes", "bytes".parse::<RandomSpec>().unwrap().to_string());
+
+        let error = |s: &str| s.parse::<RandomSpec>().unwrap_err();
+        assert_eq!(
+            "one of `numbers`, `chars` and `bytes` is required",
+            error("seed=1")
+        );
+        assert_eq!("invalid value of `seed`: `x`", error("seed=x,bytes"));
+        assert_eq!("invalid value of `count`: `-1`", error("bytes,count=-1"));
+        assert_eq!(
+            "invalid value of `numbers`: `2..=0`",
+            error("numbers=2..=0")
+        );
+        assert_eq!("invalid value of `numbers`: `0..0`", error("numbers=0..0"));
+        assert_eq!("invalid value of `numbers`: `0-2`", error("numbers=0-2"));
+        assert_eq!("invalid range `z-a` in `chars`", error("chars=z-a"));
+        assert_eq!("`chars` is empty", error("chars="));
+        assert_eq!(
+            "range `\\u{0}-\\u{10ffff}` in `chars` contains surrogates",
+            error("chars=\u{0}-\u{10FFFF}")
+        );
+        assert_eq!("`bytes` takes no value", error("bytes=1"));
+        assert_eq!("unknown key `size`", error("bytes,size=1"));
+        assert_eq!(
+            "`seed` is given more than once",
+            error("seed=1,seed=2,bytes")
+        );
+        assert_eq!(
+            "at most one of `numbers`, `chars` and `bytes` can be given",
+            error("bytes,chars=a")
+        );
+    }
+}
@@ -0,0 +1,300 @@
+/*!
+Self-contained HTML report of a run (`--report`).
+
+The report is a single file without external resources, so that a submission can be reviewed without rerunning it:
+the program as a base64 PNG, the statistics, the effective configuration (see [`effective_config`](super::effective_config)), the path overlay (the SVG of `--svg`), the I/O trace, the termination reason and a collapsible path trace.
+The path trace and the trace drawn in the path overlay are capped at [`MAX_TRACE_STEPS`] steps to keep the file small.
+*/
+
+use std::fmt::Write as _;
+use std::io::{self, Cursor, Write};
+use std::sync::{Arc, Mutex};
+
+use image::{ImageFormat, RgbImage};
+use rustc_hash::FxHashSet;
+
+use super::effective_config::EffectiveConfig;
+use super::error::PietError;
+use super::image::Image;
+use super::run_result::RunResult;
+use super::svg::{self, SvgOptions};
+use super::trace::TraceEvent;
+use super::trace_diff::Step;
+
+/// Maximum number of steps of the path trace embedded in a report.
+pub const MAX_TRACE_STEPS: usize = 10_000;
+
+/// Size of a codel in pixels in the embedded PNG.
+const PNG_SCALE: u32 = 10;
+
+/// In-memory sink of the I/O trace, which can be shared with the interpreter as its `io_trace`.
+#[derive(Clone, Default)]
+pub struct IoLog(Arc<Mutex<Vec<u8>>>);
+
+impl IoLog {
+    pub fn contents(&self) -> String {
+        String::from_utf8_lossy(&self.0.lock().unwrap()).into_owned()
+    }
+}
+
+impl Write for IoLog {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.lock().unwrap().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Writes everything to both writers (e.g. `--io-trace` and the log of `--report`).
+pub struct Tee<A, B>(pub A, pub B);
+
+impl<A: Write, B: Write> Write for Tee<A, B> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.write_all(buf)?;
+        self.1.write_all(buf)?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.0.flush()?;
+        self.1.flush()
+    }
+}
+
+/// Everything a report shows.
+pub struct Report<'a> {
+    pub img: &'a Image,
+    /// The result of the run, or the error which stopped it.
+    pub result: Result<&'a RunResult, &'a PietError>,
+    pub trace: &'a [TraceEvent],
+    pub io_trace: &'a str,
+    pub svg_options: &'a SvgOptions,
+    /// Block left out of the visited blocks (`--ignore-background`).
+    pub excluded_block: Option<usize>,
+    pub config: &'a EffectiveConfig,
+}
+
+/// Encodes `bytes` in the standard base64 alphabet with padding.
+pub fn base64(bytes: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut s = String::with_capacity(bytes.len().div_ceil(3) * 4);
+    for chunk in bytes.chunks(3) {
+        let n = chunk
+            .iter()
+            .enumerate()
+            .fold(0u32, |n, (k, b)| n | ((*b as u32) << (16 - 8 * k)));
+        for k in 0..4 {
+            if k <= chunk.len() {
+                s.push(ALPHABET[((n >> (18 - 6 * k)) & 0x3F) as usize] as char);
+            } else {
+                s.push('=');
+            }
+        }
+    }
+    s
+}
+
+/// Encodes `img` as PNG with `scale x scale` pixels per codel.
+pub fn png(img: &Image, scale: u32) -> Vec<u8> {
+    let buf = RgbImage::from_fn(
+        img.width() as u32 * scale,
+        img.height() as u32 * scale,
+        |x, y| {
+            let (r, g, b) = img
+                .get_codel_at_unchecked(((y / scale) as usize, (x / scale) as usize))
+                .rgb();
+            image::Rgb([r, g, b])
+        },
+    );
+    let mut bytes = Cursor::new(vec![]);
+    buf.write_to(&mut bytes, ImageFormat::Png).unwrap();
+    bytes.into_inner()
+}
+
+/// Groups the events into steps.
+pub(crate) fn steps(trace: &[TraceEvent]) -> Vec<Step> {
+    let mut steps: Vec<Step> = vec![];
+    for event in trace {
+        match *event {
+            TraceEvent::State { pos, dp, cc } => steps.push(Step {
+                pos,
+                dp,
+                cc,
+                commands: vec![],
+            }),
+            TraceEvent::Command(command) => {
+                if let Some(step) = steps.last_mut() {
+                    step.commands.push(command);
+                }
+            }
+            //the executed command (if any) follows as its own event
+            TraceEvent::Intervention { .. } => {}
+        }
+    }
+    steps
+}
+
+fn escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// Renders the report as an HTML document.
+pub fn render(report: &Report) -> String {
+    let img = report.img;
+    let steps = steps(report.trace);
+    let covered = steps
+        .iter()
+        .map(|step| img.get_block_id_at_unchecked(step.pos))
+        .filter(|id| Some(*id) != report.excluded_block)
+        .collect::<FxHashSet<_>>()
+        .len();
+
+    let mut s = String::new();
+    s.push_str("<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>Piet execution report</title>\n");
+    s.push_str("<style>body{font-family:sans-serif} td,th{padding:2px 8px;text-align:left} pre{background:#F4F4F4;padding:8px}</style>\n");
+    s.push_str("</head>\n<body>\n<h1>Piet execution report</h1>\n");
+
+    s.push_str("<h2 id=\"termination\">Termination</h2>\n");
+    match report.result {
+        Ok(result) => writeln!(s, "<p>{}</p>", escape(&format!("{:?}", result.reason))).unwrap(),
+        Err(e) => writeln!(s, "<p>error: {}</p>", escape(&e.to_string())).unwrap(),
+    }
+
+    s.push_str("<h2 id=\"program\">Program</h2>\n");
+    writeln!(
+        s,
+        "<img alt=\"program\" src=\"data:image/png;base64,{}\">",
+        base64(&png(img, PNG_SCALE))
+    )
+    .unwrap();
+
+    s.push_str("<h2 id=\"stats\">Statistics</h2>\n<table>\n");
+    let mut row = |name: &str, value: String| {
+        writeln!(s, "<tr><th>{}</th><td>{}</td></tr>", name, escape(&value)).unwrap();
+    };
+    row("size", format!("{} x {} codels", img.width(), img.height()));
+    match report.excluded_block {
+        None => row(
+            "visited blocks",
+            format!("{} of {}", covered, img.num_blocks()),
+        ),
+        Some(id) => {
+            let block = img.get_block(id).unwrap();
+            row(
+                "visited blocks",
+                format!(
+                    "{} of {} (the background block at {:?} of size {} is excluded)",
+                    covered,
+                    img.num_blocks() - 1,
+                    block.first_codel(),
+                    block.size
+                ),
+            )
+        }
+    }
+    if let Ok(result) = report.result {
+        row("steps", result.num_steps.to_string());
+        row("commands", result.num_commands.to_string());
+        row("output bytes", result.num_output_bytes.to_string());
+        row("input bytes", result.num_input_bytes.to_string());
+        row("stack", format!("{:?}", result.stack));
+        row(
+            "final state",
+            format!("{:?} DP:{:?} CC:{:?}", result.pos, result.dp, result.cc),
+        );
+        row("semantics", result.semantics.to_json());
+        row("resources (approximate)", result.resources.to_string());
+    }
+    s.push_str("</table>\n");
+
+    s.push_str("<h2 id=\"config\">Configuration</h2>\n<table>\n");
+    for setting in &report.config.settings {
+        writeln!(
+            s,
+            "<tr><th>{}.{}</th><td>{}</td><td>{}</td></tr>",
+            setting.section,
+            setting.name,
+            escape(&setting.value),
+            escape(&setting.source.to_string())
+        )
+        .unwrap();
+    }
+    s.push_str("</table>\n");
+
+    s.push_str("<h2 id=\"coverage\">Path</h2>\n");
+    let trace = &report.trace[..report.trace.len().min(MAX_TRACE_STEPS)];
+    s.push_str(&svg::render(img, Some(trace), report.svg_options));
+
+    s.push_str("<h2 id=\"io\">I/O trace</h2>\n");
+    writeln!(s, "<pre>{}</pre>", escape(report.io_trace)).unwrap();
+
+    s.push_str("<h2 id=\"trace\">Path trace</h2>\n<details>\n");
+    writeln!(s, "<summary>{} steps</summary>", steps.len()).unwrap();
+    if steps.len() > MAX_TRACE_STEPS {
+        writeln!(
+            s,
+            "<p>Only the first {} of {} steps are shown.</p>",
+            MAX_TRACE_STEPS,
+            steps.len()
+        )
+        .unwrap();
+    }
+    s.push_str("<pre>");
+    for step in steps.iter().take(MAX_TRACE_STEPS) {
+        writeln!(s, "{}", escape(&step.to_line())).unwrap();
+    }
+    s.push_str("</pre>\n</details>\n</body>\n</html>\n");
+    s
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::cc::CC;
+    use super::super::codel::Codel;
+    use super::super::command::Command;
+    use super::super::dp::DP;
+    use super::*;
+
+    #[test]
+    fn test_base64() {
+        assert_eq!("", base64(b""));
+        assert_eq!("Zg==", base64(b"f"));
+        assert_eq!("Zm8=", base64(b"fo"));
+        assert_eq!("Zm9v", base64(b"foo"));
+        assert_eq!("Zm9vYmFy", base64(b"foobar"));
+        assert_eq!("/+8=", base64(&[0xFF, 0xEF]));
+    }
+
+    #[test]
+    fn test_png() {
+        let img = Image::from_codel_grid(vec![vec![Codel::Red, Codel::Blue]]);
+        let decoded = image::load_from_memory(&png(&img, 3)).unwrap().to_rgb8();
+        assert_eq!((6, 3), decoded.dimensions());
+        assert_eq!(&image::Rgb([255, 0, 0]), decoded.get_pixel(2, 2));
+        assert_eq!(&image::Rgb([0, 0, 255]), decoded.get_pixel(3, 0));
+    }
+
+    #[test]
+    fn test_steps() {
+        let state = |j| TraceEvent::State {
+            pos: (0, j),
+            dp: DP::Right,
+            cc: CC::Left,
+        };
+        let steps = steps(&[
+            state(0),
+            TraceEvent::Command(Command::Push),
+            state(1),
+            state(2),
+        ]);
+        assert_eq!(3, steps.len());
+        assert_eq!(vec![Command::Push], steps[0].commands);
+        assert!(steps[1].commands.is_empty());
+    }
+}
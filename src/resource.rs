@@ -0,0 +1,117 @@
+/*!
+Approximate resource usage of a run (`--stats`).
+
+Both figures are approximate and meant for capacity planning rather than accounting:
+
+- The peak resident set size is that of the whole process so far (`VmHWM` of `/proc/self/status`), so it includes loading the image and anything run before in the same process.
+  It is probed only when built with the `resource-stats` feature on Linux; elsewhere it is `None`
+  (macOS and Windows only expose it through native APIs, which would need `unsafe` code).
+- The number of allocations is counted only if the embedding program installs a counting global allocator and registers it with [`set_allocation_counter()`]
+  (this crate forbids `unsafe` code, so it cannot install one itself).
+*/
+
+use std::fmt;
+use std::sync::OnceLock;
+
+/// Approximate resource usage of a run, where `None` means not measured.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct ResourceUsage {
+    /// Peak resident set size of the process in bytes.
+    pub peak_rss_bytes: Option<u64>,
+    /// Number of heap allocations during the execution.
+    pub num_allocations: Option<u64>,
+}
+
+impl fmt::Display for ResourceUsage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.peak_rss_bytes {
+            Some(bytes) => write!(f, "peak RSS ~{:.1} MiB", bytes as f64 / (1 << 20) as f64)?,
+            None => write!(f, "peak RSS n/a")?,
+        }
+        match self.num_allocations {
+            Some(n) => write!(f, ", allocations ~{}", n),
+            None => write!(f, ", allocations n/a"),
+        }
+    }
+}
+
+static ALLOCATION_COUNTER: OnceLock<fn() -> u64> = OnceLock::new();
+
+/// Registers a function which returns the number of heap allocations so far (e.g. read from a counting global allocator).
+/// Only the first registration takes effect.
+pub fn set_allocation_counter(counter: fn() -> u64) {
+    let _ = ALLOCATION_COUNTER.set(counter);
+}
+
+/// Returns the number of heap allocations so far, if a counter is registered.
+pub fn num_allocations() -> Option<u64> {
+    ALLOCATION_COUNTER.get().map(|counter| counter())
+}
+
+/// Extracts the peak resident set size in bytes from the content of `/proc/<pid>/status`.
+pub fn parse_proc_status(status: &str) -> Option<u64> {
+    let line = status.lines().find(|l| l.starts_with("VmHWM:"))?;
+    let mut words = line["VmHWM:".len()..].split_whitespace();
+    let n = words.next()?.parse::<u64>().ok()?;
+    match words.next()? {
+        "kB" => n.checked_mul(1024),
+        _ => None,
+    }
+}
+
+/// Returns the peak resident set size of the process in bytes, if it can be probed on this platform.
+pub fn peak_rss() -> Option<u64> {
+    #[cfg(all(feature = "resource-stats", target_os = "linux"))]
+    {
+        std::fs::read_to_string("/proc/self/status")
+            .ok()
+            .as_deref()
+            .and_then(parse_proc_status)
+    }
+    #[cfg(not(all(feature = "resource-stats", target_os = "linux")))]
+    {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_proc_status() {
+        let status = "Name:\tpiet_programming\nUmask:\t0022\nState:\tR (running)\nVmPeak:\t   12345 kB\nVmHWM:\t    4096 kB\nVmRSS:\t    3000 kB\n";
+        assert_eq!(Some(4096 * 1024), parse_proc_status(status));
+        //a kernel thread has no memory lines
+        assert_eq!(
+            None,
+            parse_proc_status("Name:\tkthreadd\nState:\tS (sleeping)\n")
+        );
+        assert_eq!(None, parse_proc_status("VmHWM:\tabc kB\n"));
+        assert_eq!(None, parse_proc_status("VmHWM:\t10 MB\n"));
+        assert_eq!(None, parse_proc_status("VmHWM:\t10\n"));
+        assert_eq!(None, parse_proc_status(""));
+    }
+
+    #[test]
+    fn test_display() {
+        assert_eq!(
+            "peak RSS ~1.5 MiB, allocations ~42",
+            ResourceUsage {
+                peak_rss_bytes: Some(3 << 19),
+                num_allocations: Some(42),
+            }
+            .to_string()
+        );
+        assert_eq!(
+            "peak RSS n/a, allocations n/a",
+            ResourceUsage::default().to_string()
+        );
+    }
+
+    #[cfg(all(feature = "resource-stats", target_os = "linux"))]
+    #[test]
+    fn test_peak_rss() {
+        assert!(peak_rss().is_some_and(|bytes| bytes > 0));
+    }
+}
@@ -0,0 +1,124 @@
+use super::cc::CC;
+use super::dp::DP;
+use super::interpreter::Interpreter;
+use super::resource::ResourceUsage;
+use super::semantics::SemanticsDescriptor;
+use super::stdin::InputNewlines;
+use super::warning::Warning;
+
+/// Why a program stopped.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TerminationReason {
+    /// All of the eight attempts to leave a coloured block failed.
+    NoExit,
+    /// The interpreter retraced its route within a white block.
+    WhiteRetrace,
+    /// `max-iter` was reached.
+    MaxIter,
+    /// The program neither wrote output nor consumed input for `stall-timeout`.
+    Stall,
+    /// The run was stopped through a [`CancellationToken`](super::limits::CancellationToken).
+    Cancelled,
+}
+
+/// Summary of a finished run.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RunResult {
+    pub reason: TerminationReason,
+    /// Number of iterations (moves from a codel).
+    pub num_steps: usize,
+    /// Number of executed commands.
+    pub num_commands: usize,
+    /// Number of bytes the program wrote to stdout.
+    pub num_output_bytes: usize,
+    /// Number of bytes of the input consumed by the program.
+    pub num_input_bytes: usize,
+    /// Whether the input was cut by `--max-input-bytes` (see [`Stdin::set_max_bytes()`](super::stdin::Stdin::set_max_bytes)).
+    pub is_input_capped: bool,
+    /// Number of CR line endings in the input read by the program (see [`InputNewlines`]).
+    pub num_cr_line_endings: usize,
+    pub input_newlines: InputNewlines,
+    pub stack: Vec<isize>,
+    pub dp: DP,
+    pub cc: CC,
+    /// Position of the codel where the program stopped.
+    pub pos: (usize, usize),
+    /// Semantics under which the program ran.
+    pub semantics: SemanticsDescriptor,
+    /// Approximate resource usage (not measured by [`Self::new()`]).
+    pub resources: ResourceUsage,
+    /// Warnings raised during the run (not collected by [`Self::new()`]).
+    pub warnings: Vec<Warning>,
+}
+
+impl RunResult {
+    pub fn new(
+        reason: TerminationReason,
+        ip: &Interpreter,
+        semantics: SemanticsDescriptor,
+    ) -> Self {
+        Self {
+            reason,
+            num_steps: ip.num_steps,
+            num_commands: ip.num_commands,
+            num_output_bytes: ip.num_output_bytes,
+            num_input_bytes: ip.stdin.num_bytes_read(),
+            is_input_capped: ip.stdin.is_capped(),
+            num_cr_line_endings: ip.stdin.num_cr_line_endings(),
+            input_newlines: ip.stdin.newlines(),
+            stack: ip.stack.clone(),
+            dp: ip.dp,
+            cc: ip.cc,
+            pos: ip.cur,
+            semantics,
+            resources: ResourceUsage::default(),
+            warnings: vec![],
+        }
+    }
+
+    /// Returns `true` if the program stopped by itself.
+    pub fn halted_normally(&self) -> bool {
+        matches!(
+            self.reason,
+            TerminationReason::NoExit | TerminationReason::WhiteRetrace
+        )
+    }
+
+    /// One-line statistics printed to stderr by `--stats`.
+    pub fn stats(&self) -> String {
+        let mut ret = format!(
+            "{} steps, {} commands, {} output bytes, {} input bytes{}, {} (approximate)",
+            self.num_steps,
+            self.num_commands,
+            self.num_output_bytes,
+            self.num_input_bytes,
+            if self.is_input_capped {
+                " (cut by `--max-input-bytes`)"
+            } else {
+                ""
+            },
+            self.resources
+        );
+        if self.num_cr_line_endings != 0 {
+            ret += &match self.input_newlines {
+                InputNewlines::Raw => format!(
+                    ", {} CR line endings read as they are (see `--input-newlines`)",
+                    self.num_cr_line_endings
+                ),
+                InputNewlines::Lf => {
+                    format!(", {} CR line endings read as LF", self.num_cr_line_endings)
+                }
+            };
+        }
+        ret
+    }
+
+    /// One-line summary printed to stderr when a program halts normally without printing anything, so that "ran and halted" can be told from "did nothing".
+    pub fn summary(&self) -> String {
+        format!(
+            "program halted normally after {} commands with stack depth {}",
+            self.num_commands,
+            self.stack.len()
+        )
+    }
+}
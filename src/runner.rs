@@ -0,0 +1,1002 @@
+/*!
+Execution of a loaded program by an embedding program, without the concerns of the command line (parsing, loading files and printing).
+
+A [`Runner`] owns an interpreter and runs programs on it with the options of a [`RunnerConfig`]: the limits, the semantics, the path trace, an observer and where the input and the output go.
+The `run` subcommand translates its arguments into a `RunnerConfig` (see [`Args::runner_config()`](super::args::Args::runner_config)) and adds what only the command line does, e.g. a progress line, checkpoints or a report.
+[`PietRunner`] also loads the program and takes its options from a [`PietRunnerBuilder`], from which the `run` subcommand builds its own.
+
+```
+use piet_programming_language::codel::Codel;
+use piet_programming_language::image::Image;
+use piet_programming_language::limits::Limits;
+use piet_programming_language::program::Program;
+use piet_programming_language::run_result::TerminationReason;
+use piet_programming_language::runner::{Output, Runner, RunnerConfig};
+
+//pushes 3 (the size of the red block) and prints it as a number (followed by a newline)
+let program = Program::new(Image::from_codel_grid(vec![vec![
+    Codel::Red,
+    Codel::Red,
+    Codel::Red,
+    Codel::DarkRed,
+    Codel::LightMagenta,
+]]));
+let mut runner = Runner::new(RunnerConfig {
+    limits: Limits { max_iter: Some(2), stall_timeout: None },
+    output: Output::Capture,
+    ..RunnerConfig::default()
+});
+let result = runner.run(&program).unwrap();
+assert_eq!(TerminationReason::MaxIter, result.reason);
+assert_eq!(b"3\n", runner.output());
+```
+*/
+
+use std::error::Error;
+use std::fs;
+use std::io::{self, Read, Write};
+use std::mem;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Instant;
+
+use super::codel::Codel;
+use super::command;
+use super::error::PietError;
+use super::extension::ExecConfig;
+use super::image::{self, ColorMatching, Image};
+use super::interpreter::Interpreter;
+use super::limits::{CancellationToken, ImageLimits, Limits};
+use super::lint;
+use super::metrics::{Metrics, RunCounts};
+use super::observer::Observer;
+use super::pietdev;
+use super::program::Program;
+use super::run_result::{RunResult, TerminationReason};
+use super::semantics::SemanticsDescriptor;
+use super::slice::{SliceResult, SliceStatus};
+use super::stdin::InputNewlines;
+use super::trace::TraceEvent;
+use super::warning::Warning;
+
+/// Where the output of the program goes.
+#[derive(Default)]
+pub enum Output {
+    #[default]
+    Stdout,
+    /// Kept in memory (see [`Runner::output()`]).
+    Capture,
+    Writer(Box<dyn Write + Send>),
+}
+
+/// How the path trace is kept.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct TraceOptions {
+    /// Prints the path trace to stderr from this step on if `Some` (`--verbose` and `--trace-after`).
+    pub print_after: Option<usize>,
+    /// Records the path trace (see [`Runner::trace()`]).
+    pub record: bool,
+    /// Prints every step of a slide across white, instead of a line for each straight part of it (`-vvv`).
+    /// Steps which hit a restriction are printed either way, and the recorded trace always has every step.
+    pub per_codel_slides: bool,
+}
+
+/// Options of a [`Runner`].
+pub struct RunnerConfig<'o> {
+    pub limits: Limits,
+    /// Options which change the semantics.
+    pub exec: ExecConfig,
+    pub trace: TraceOptions,
+    /// Called before each command if `Some` (see [`observer`](super::observer)).
+    pub observer: Option<&'o mut dyn Observer>,
+    /// Lets the observer alter the execution (`--allow-interventions`).
+    pub allow_interventions: bool,
+    /// Starts at the first non-black codel if the top-left one is black (`--lenient-start`).
+    pub lenient_start: bool,
+    /// Warns about suspicious values met during the run (`--check-runtime`), including a hint of a wrong codel size at the end (see [`push_operands`](super::push_operands)).
+    pub check_runtime: bool,
+    /// Operand of `Pointer` or `Switch` above which `check_runtime` warns (`--large-operand-threshold`).
+    pub large_operand_threshold: usize,
+    /// Size of the start block above which `check_runtime` warns about the first `Push` from it, or `None` not to (`--start-block-threshold`).
+    pub start_block_threshold: Option<usize>,
+    /// How the line endings of the input are read (`--input-newlines`).
+    pub input_newlines: InputNewlines,
+    /// Number of bytes after which the input ends, or `None` for no limit (`--max-input-bytes`).
+    pub max_input_bytes: Option<usize>,
+    /// Input of the program, or `None` for stdin.
+    pub input: Option<Vec<u8>>,
+    /// Reads the input in the non-blocking mode (see [`slice`](super::slice)), e.g. for a debugger in which the input is typed while the program runs:
+    /// `input` is only the start of it, more is fed with [`Runner::feed_input()`] until [`Runner::close_input()`], and [`Runner::step()`] returns [`SliceStatus::NeedsInput`] instead of waiting.
+    /// [`Runner::run()`] closes the input first.
+    pub interactive_input: bool,
+    /// Keeps a copy of the input read by the program (see [`Runner::recorded_input()`]).
+    pub record_input: bool,
+    pub output: Output,
+    /// Counts every run if `Some` (see [`metrics`](super::metrics)).
+    pub metrics: Option<Arc<Metrics>>,
+    /// Stops the run with [`TerminationReason::Cancelled`] when it is cancelled, e.g. from another thread.
+    /// The output written so far has been flushed, and [`Runner::step()`] resumes the run from where it stopped once the token is reset, while [`Runner::run()`] starts over.
+    pub cancellation: Option<CancellationToken>,
+}
+
+impl Default for RunnerConfig<'_> {
+    fn default() -> Self {
+        Self {
+            limits: Limits::new(None, None, None),
+            exec: ExecConfig::default(),
+            trace: TraceOptions::default(),
+            observer: None,
+            allow_interventions: false,
+            lenient_start: false,
+            check_runtime: false,
+            large_operand_threshold: command::DEFAULT_LARGE_OPERAND_THRESHOLD,
+            start_block_threshold: Some(lint::DEFAULT_START_BLOCK_THRESHOLD),
+            input_newlines: InputNewlines::Raw,
+            max_input_bytes: None,
+            input: None,
+            interactive_input: false,
+            record_input: false,
+            output: Output::Stdout,
+            metrics: None,
+            cancellation: None,
+        }
+    }
+}
+
+/// Runs programs with a [`RunnerConfig`], either to the end ([`Self::run()`]) or a number of steps at a time ([`Self::step()`]).
+pub struct Runner<'o> {
+    config: RunnerConfig<'o>,
+    ip: Interpreter,
+    trace: Vec<TraceEvent>,
+    warnings: Vec<Warning>,
+    /// Whether a run started by [`Self::step()`] has not terminated yet.
+    is_running: bool,
+    /// When the current run started, for [`RunnerConfig::metrics`].
+    started_at: Instant,
+    /// Input fed before the run started, with whether it was closed.
+    pending_input: Vec<u8>,
+    is_pending_input_closed: bool,
+}
+
+impl<'o> Runner<'o> {
+    pub fn new(mut config: RunnerConfig<'o>) -> Self {
+        let mut ip = Interpreter::new();
+        match mem::take(&mut config.output) {
+            Output::Stdout => (),
+            Output::Capture => {
+                ip.output_writer = Some(Box::new(io::sink()));
+                ip.output_log = Some(vec![]);
+            }
+            Output::Writer(w) => ip.output_writer = Some(w),
+        }
+        Self {
+            config,
+            ip,
+            trace: vec![],
+            warnings: vec![],
+            is_running: false,
+            started_at: Instant::now(),
+            pending_input: vec![],
+            is_pending_input_closed: false,
+        }
+    }
+
+    /// Runs `program` from the start until it terminates.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the program fails (e.g. the top-left codel is black or an unknown color is reached).
+    pub fn run(&mut self, program: &Program) -> Result<RunResult, PietError> {
+        self.start(program)?;
+        if self.config.interactive_input {
+            self.ip.stdin.close();
+        }
+        let reason = match self.advance(program, None)? {
+            SliceStatus::Finished(reason) => reason,
+            status => unreachable!("{:?} outside of a slice", status),
+        };
+        let semantics = SemanticsDescriptor::new(&self.ip.config, program.image().topology());
+        Ok(RunResult {
+            warnings: self.warnings.clone(),
+            ..RunResult::new(reason, &self.ip, semantics)
+        })
+    }
+
+    /// Runs `program` for at most `max_steps` steps from where the previous call stopped, or from the start if the runner is new or the previous run has terminated (see [`Interpreter::run_for()`]).
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the program fails, after which the next call starts over.
+    pub fn step(&mut self, program: &Program, max_steps: usize) -> Result<SliceResult, PietError> {
+        if !self.is_running {
+            self.start(program)?;
+        }
+        let num_steps = self.ip.num_steps;
+        let status = self.advance(program, Some(max_steps))?;
+        Ok(SliceResult {
+            num_steps: self.ip.num_steps - num_steps,
+            status,
+        })
+    }
+
+    /// State of the interpreter, e.g. the stack after a run.
+    pub fn interpreter(&self) -> &Interpreter {
+        &self.ip
+    }
+
+    /// Output of the current (or last) run with [`Output::Capture`], and otherwise nothing.
+    pub fn output(&self) -> &[u8] {
+        self.ip.output_log.as_deref().unwrap_or_default()
+    }
+
+    /// Path trace of the current (or last) run if [`TraceOptions::record`] is set.
+    pub fn trace(&self) -> &[TraceEvent] {
+        &self.trace
+    }
+
+    /// Warnings raised so far in the current (or last) run.
+    pub fn warnings(&self) -> &[Warning] {
+        &self.warnings
+    }
+
+    /// Appends `b` to the input of the current run with [`RunnerConfig::interactive_input`], or of the next one if none is running.
+    /// A program waiting for input ([`SliceStatus::NeedsInput`]) reads it on the next [`Self::step()`].
+    pub fn feed_input(&mut self, b: &[u8]) {
+        if self.is_running {
+            self.ip.stdin.feed(b);
+        } else {
+            self.pending_input.extend(b);
+        }
+    }
+
+    /// Ends the input fed by [`Self::feed_input()`], so that a read reaches EOF instead of waiting for more.
+    pub fn close_input(&mut self) {
+        if self.is_running {
+            self.ip.stdin.close();
+        } else {
+            self.is_pending_input_closed = true;
+        }
+    }
+
+    /// Input read so far by the current (or last) run with [`RunnerConfig::record_input`], and otherwise nothing.
+    /// Given as the input of a run without [`RunnerConfig::interactive_input`] (e.g. `--input`), it replays the run.
+    pub fn recorded_input(&self) -> &[u8] {
+        self.ip.stdin.recorded()
+    }
+
+    /// Prepares the interpreter for a new run of `program`, keeping where the output goes.
+    fn start(&mut self, program: &Program) -> Result<(), PietError> {
+        self.is_running = false;
+        self.started_at = Instant::now();
+        if let Some(metrics) = &self.config.metrics {
+            metrics.record_start();
+        }
+        let writer = self.ip.output_writer.take();
+        self.ip.reset_for(program);
+        self.ip.output_writer = writer;
+        self.ip.config = self.config.exec.clone();
+        if self.config.interactive_input {
+            let mut input = self.config.input.clone().unwrap_or_default();
+            input.append(&mut self.pending_input);
+            self.ip.stdin.reset_non_blocking(&input);
+            if mem::take(&mut self.is_pending_input_closed) {
+                self.ip.stdin.close();
+            }
+        } else if let Some(input) = &self.config.input {
+            self.ip.stdin.reset_with_bytes(input);
+        }
+        self.ip.stdin.set_newlines(self.config.input_newlines);
+        self.ip.stdin.set_max_bytes(self.config.max_input_bytes);
+        self.ip.stdin.set_recording(self.config.record_input);
+        self.trace.clear();
+        self.warnings.clear();
+        self.ip.cur = super::start_codel(
+            program.image(),
+            self.config.lenient_start,
+            &mut self.warnings,
+        )
+        .inspect_err(|_| self.record_finish(None))?;
+        self.is_running = true;
+        Ok(())
+    }
+
+    fn record_finish(&self, reason: Option<TerminationReason>) {
+        if let Some(metrics) = &self.config.metrics {
+            metrics.record_finish(reason, RunCounts::of(&self.ip), self.started_at.elapsed());
+        }
+    }
+
+    fn advance(
+        &mut self,
+        program: &Program,
+        max_steps: Option<usize>,
+    ) -> Result<SliceStatus, PietError> {
+        let mut observer = self.config.observer.take();
+        let trace = self.config.trace.record.then_some(&mut self.trace);
+        let status = super::run_configured(
+            &self.config,
+            program,
+            &mut self.ip,
+            observer.as_deref_mut().map(|o| o as &mut dyn Observer),
+            trace,
+            &mut self.warnings,
+            max_steps,
+        );
+        self.config.observer = observer;
+        let reason = match &status {
+            Ok(SliceStatus::Runnable | SliceStatus::NeedsInput) => return status,
+            //The state is kept for the next step.
+            Ok(SliceStatus::Finished(TerminationReason::Cancelled)) if max_steps.is_some() => {
+                return status
+            }
+            Ok(SliceStatus::Finished(reason)) => Some(*reason),
+            Err(_) => None,
+        };
+        self.is_running = false;
+        self.record_finish(reason);
+        status
+    }
+}
+
+/// How a [`PietRunner`] loads a program from a file, which is also how the `run` subcommand loads it (see [`LoadArgs::load_options()`](super::args::LoadArgs::load_options)).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct LoadOptions {
+    /// Size of a codel in pixels (`--codel-size`), or `None` to detect it from the image.
+    pub codel_size: Option<usize>,
+    /// Color which an unknown color is replaced with (`--fall-back-to-white` and `--fall-back-to-black`).
+    pub fallback: Option<Codel>,
+    /// Limits on the size of the image (`--max-image-pixels`, `--max-image-dimension` and `--max-image-bytes`).
+    pub image_limits: ImageLimits,
+}
+
+/// Options of a [`PietRunner`], which start from the defaults of the `run` subcommand.
+///
+/// The `run` subcommand builds its options with it (see [`Args::piet_runner_builder()`](super::args::Args::piet_runner_builder)), so that both have the same defaults.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PietRunnerBuilder {
+    load: LoadOptions,
+    limits: Limits,
+    exec: ExecConfig,
+    verbose: bool,
+    lenient_start: bool,
+    check_runtime: bool,
+    large_operand_threshold: usize,
+    start_block_threshold: Option<usize>,
+    input_newlines: InputNewlines,
+    max_input_bytes: Option<usize>,
+}
+
+impl Default for PietRunnerBuilder {
+    fn default() -> Self {
+        let config = RunnerConfig::default();
+        Self {
+            load: LoadOptions::default(),
+            limits: config.limits,
+            exec: config.exec,
+            verbose: config.trace.print_after.is_some(),
+            lenient_start: config.lenient_start,
+            check_runtime: config.check_runtime,
+            large_operand_threshold: config.large_operand_threshold,
+            start_block_threshold: config.start_block_threshold,
+            input_newlines: config.input_newlines,
+            max_input_bytes: config.max_input_bytes,
+        }
+    }
+}
+
+impl PietRunnerBuilder {
+    /// Size of a codel in pixels (`--codel-size`), which is guessed from the image if it is not set.
+    pub fn codel_size(mut self, codel_size: usize) -> Self {
+        self.load.codel_size = Some(codel_size);
+        self
+    }
+
+    /// Color which an unknown color is replaced with (`--fall-back-to-white` and `--fall-back-to-black`).
+    pub fn fallback(mut self, codel: Codel) -> Self {
+        self.load.fallback = Some(codel);
+        self
+    }
+
+    /// Limits on the size of an image loaded from a file.
+    pub fn image_limits(mut self, image_limits: ImageLimits) -> Self {
+        self.load.image_limits = image_limits;
+        self
+    }
+
+    /// Replaces all the options of loading.
+    pub fn load_options(mut self, load: LoadOptions) -> Self {
+        self.load = load;
+        self
+    }
+
+    /// Replaces all the limits (e.g. [`LimitPreset::limits()`](super::limits::LimitPreset::limits)).
+    pub fn limits(mut self, limits: Limits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    /// Maximum number of iterations (`--max-iter`).
+    pub fn max_iter(mut self, max_iter: usize) -> Self {
+        self.limits.max_iter = Some(max_iter);
+        self
+    }
+
+    /// Maximum number of seconds without input or output (`--stall-timeout`).
+    pub fn stall_timeout(mut self, seconds: f64) -> Self {
+        self.limits.stall_timeout = Some(seconds);
+        self
+    }
+
+    /// Options which change the semantics.
+    pub fn exec(mut self, exec: ExecConfig) -> Self {
+        self.exec = exec;
+        self
+    }
+
+    /// Prints the path trace to stderr (`--verbose`).
+    pub fn verbose(mut self, verbose: bool) -> Self {
+        self.verbose = verbose;
+        self
+    }
+
+    /// See [`RunnerConfig::lenient_start`].
+    pub fn lenient_start(mut self, lenient_start: bool) -> Self {
+        self.lenient_start = lenient_start;
+        self
+    }
+
+    /// See [`RunnerConfig::check_runtime`].
+    pub fn check_runtime(mut self, check_runtime: bool) -> Self {
+        self.check_runtime = check_runtime;
+        self
+    }
+
+    /// See [`RunnerConfig::large_operand_threshold`].
+    pub fn large_operand_threshold(mut self, threshold: usize) -> Self {
+        self.large_operand_threshold = threshold;
+        self
+    }
+
+    /// See [`RunnerConfig::start_block_threshold`].
+    pub fn start_block_threshold(mut self, threshold: Option<usize>) -> Self {
+        self.start_block_threshold = threshold;
+        self
+    }
+
+    /// See [`RunnerConfig::input_newlines`].
+    pub fn input_newlines(mut self, input_newlines: InputNewlines) -> Self {
+        self.input_newlines = input_newlines;
+        self
+    }
+
+    /// See [`RunnerConfig::max_input_bytes`].
+    pub fn max_input_bytes(mut self, max_input_bytes: usize) -> Self {
+        self.max_input_bytes = Some(max_input_bytes);
+        self
+    }
+
+    pub fn build(self) -> PietRunner {
+        PietRunner { options: self }
+    }
+
+    /// The run options as a [`RunnerConfig`], which writes to stdout.
+    pub fn runner_config(&self) -> RunnerConfig<'static> {
+        RunnerConfig {
+            limits: self.limits,
+            exec: self.exec.clone(),
+            trace: TraceOptions {
+                print_after: self.verbose.then_some(0),
+                ..TraceOptions::default()
+            },
+            lenient_start: self.lenient_start,
+            check_runtime: self.check_runtime,
+            large_operand_threshold: self.large_operand_threshold,
+            start_block_threshold: self.start_block_threshold,
+            input_newlines: self.input_newlines,
+            max_input_bytes: self.max_input_bytes,
+            ..RunnerConfig::default()
+        }
+    }
+}
+
+/// Program given to [`PietRunner::run()`]: a file to load (a `.pietdev` file or an image), a loaded image or a program.
+pub enum ProgramSource<'a> {
+    File(PathBuf),
+    Image(Image),
+    Program(&'a Program),
+}
+
+impl From<&str> for ProgramSource<'_> {
+    fn from(file: &str) -> Self {
+        Self::File(file.into())
+    }
+}
+
+impl From<&Path> for ProgramSource<'_> {
+    fn from(file: &Path) -> Self {
+        Self::File(file.into())
+    }
+}
+
+impl From<PathBuf> for ProgramSource<'_> {
+    fn from(file: PathBuf) -> Self {
+        Self::File(file)
+    }
+}
+
+impl From<Image> for ProgramSource<'_> {
+    fn from(img: Image) -> Self {
+        Self::Image(img)
+    }
+}
+
+impl<'a> From<&'a Program> for ProgramSource<'a> {
+    fn from(program: &'a Program) -> Self {
+        Self::Program(program)
+    }
+}
+
+/// Loads and runs programs with the options of a [`PietRunnerBuilder`], without anything of the command line.
+///
+/// ```no_run
+/// use piet_programming_language::codel::Codel;
+/// use piet_programming_language::runner::PietRunner;
+///
+/// let runner = PietRunner::builder()
+///     .codel_size(2)
+///     .max_iter(10_000)
+///     .fallback(Codel::White)
+///     .verbose(false)
+///     .build();
+/// let result = runner.run("hello.png", std::io::stdin(), std::io::stdout()).unwrap();
+/// println!("{} steps", result.num_steps);
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct PietRunner {
+    options: PietRunnerBuilder,
+}
+
+impl PietRunner {
+    pub fn builder() -> PietRunnerBuilder {
+        PietRunnerBuilder::default()
+    }
+
+    pub fn options(&self) -> &PietRunnerBuilder {
+        &self.options
+    }
+
+    /// Runs `program` from the start until it terminates, reading `stdin` and writing the output to `stdout`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the program cannot be loaded or fails (see [`Runner::run()`]).
+    pub fn run<'a>(
+        &self,
+        program: impl Into<ProgramSource<'a>>,
+        stdin: impl Read + Send + 'static,
+        stdout: impl Write + Send + 'static,
+    ) -> Result<RunResult, Box<dyn Error>> {
+        let loaded;
+        let program = match program.into() {
+            ProgramSource::File(file) => {
+                loaded = Program::new(self.load(&file)?);
+                &loaded
+            }
+            ProgramSource::Image(img) => {
+                loaded = Program::new(match self.options.load.fallback {
+                    Some(codel) => img.replace_unknown_colors(codel).0,
+                    None => img,
+                });
+                &loaded
+            }
+            ProgramSource::Program(program) => program,
+        };
+        let mut runner = Runner::new(RunnerConfig {
+            output: Output::Writer(Box::new(stdout)),
+            ..self.options.runner_config()
+        });
+        runner.ip.stdin.reset_with_reader(Box::new(stdin));
+        Ok(runner.run(program)?)
+    }
+
+    fn load(&self, file: &Path) -> Result<Image, Box<dyn Error>> {
+        let load = &self.options.load;
+        if file
+            .extension()
+            .is_some_and(|e| e.eq_ignore_ascii_case("pietdev"))
+        {
+            let img = Image::from_codel_grid(pietdev::parse_with_limits(
+                &fs::read_to_string(file)?,
+                &load.image_limits,
+            )?);
+            return Ok(match load.fallback {
+                Some(codel) => img.replace_unknown_colors(codel).0,
+                None => img,
+            });
+        }
+        Image::new_with_limits(
+            file,
+            image::default_layout(load.codel_size),
+            ColorMatching {
+                default_color: load.fallback,
+                tolerance: 0,
+            },
+            false,
+            &load.image_limits,
+            None,
+            &mut vec![],
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::command::Command;
+    use super::super::examples::Example;
+    use super::super::image::Pixel;
+    use super::super::observer::Intervention;
+    use super::super::report::IoLog;
+    use super::*;
+
+    /// Pushes 3 and prints it as a number, and then wanders forever.
+    fn program() -> Program {
+        Program::new(Image::from_codel_grid(vec![vec![
+            Codel::Red,
+            Codel::Red,
+            Codel::Red,
+            Codel::DarkRed,
+            Codel::LightMagenta,
+        ]]))
+    }
+
+    fn config<'o>(max_iter: usize) -> RunnerConfig<'o> {
+        RunnerConfig {
+            limits: Limits::new(None, Some(max_iter), None),
+            output: Output::Capture,
+            ..RunnerConfig::default()
+        }
+    }
+
+    #[test]
+    fn test_run() {
+        let program = program();
+        let mut runner = Runner::new(RunnerConfig {
+            trace: TraceOptions {
+                record: true,
+                ..TraceOptions::default()
+            },
+            ..config(2)
+        });
+        let result = runner.run(&program).unwrap();
+        assert_eq!(TerminationReason::MaxIter, result.reason);
+        assert_eq!(2, result.num_steps);
+        assert_eq!(b"3\n", runner.output());
+        assert!(runner
+            .trace()
+            .contains(&TraceEvent::Command(Command::OutNumber)));
+
+        //a new run starts over
+        let result = runner.run(&program).unwrap();
+        assert_eq!(2, result.num_steps);
+        assert_eq!(b"3\n", runner.output());
+    }
+
+    #[test]
+    fn test_step() {
+        let program = program();
+        let expected = {
+            let mut runner = Runner::new(config(100));
+            runner.run(&program).unwrap();
+            runner.output().to_vec()
+        };
+        let mut runner = Runner::new(config(100));
+        let mut num_steps = 0;
+        loop {
+            let result = runner.step(&program, 7).unwrap();
+            num_steps += result.num_steps;
+            if result.status == SliceStatus::Finished(TerminationReason::MaxIter) {
+                break;
+            }
+            assert_eq!(SliceStatus::Runnable, result.status);
+        }
+        assert_eq!(100, num_steps);
+        assert_eq!(expected, runner.output());
+    }
+
+    #[test]
+    fn test_cancellation() {
+        use std::sync::mpsc;
+        use std::thread;
+        use std::time::Duration;
+
+        //from another thread, in the middle of an endless run
+        let token = CancellationToken::new();
+        let (started, is_started) = mpsc::channel();
+        let (finished, result) = mpsc::channel();
+        let handle = thread::spawn({
+            let token = token.clone();
+            move || {
+                let program = program();
+                let mut runner = Runner::new(RunnerConfig {
+                    cancellation: Some(token),
+                    ..config(usize::MAX)
+                });
+                runner.step(&program, 1).unwrap();
+                started.send(()).unwrap();
+                let result = runner.step(&program, usize::MAX).unwrap();
+                finished.send((result, runner.output().to_vec())).unwrap();
+            }
+        });
+        is_started.recv().unwrap();
+        thread::sleep(Duration::from_millis(10));
+        token.cancel();
+        let (result, output) = result
+            .recv_timeout(Duration::from_secs(10))
+            .expect("the run shall stop soon after the cancellation");
+        handle.join().unwrap();
+        assert_eq!(
+            SliceStatus::Finished(TerminationReason::Cancelled),
+            result.status
+        );
+        assert!(result.num_steps > 0);
+        assert!(output.starts_with(b"3\n"));
+
+        //no step is taken once cancelled, and the run resumes from there once the token is reset
+        let program = program();
+        let expected = {
+            let mut runner = Runner::new(config(100));
+            runner.run(&program).unwrap();
+            runner.output().to_vec()
+        };
+        let token = CancellationToken::new();
+        let mut runner = Runner::new(RunnerConfig {
+            cancellation: Some(token.clone()),
+            ..config(100)
+        });
+        assert_eq!(
+            SliceStatus::Runnable,
+            runner.step(&program, 30).unwrap().status
+        );
+        token.cancel();
+        for _ in 0..2 {
+            assert_eq!(
+                SliceResult {
+                    num_steps: 0,
+                    status: SliceStatus::Finished(TerminationReason::Cancelled),
+                },
+                runner.step(&program, 30).unwrap()
+            );
+        }
+        token.reset();
+        assert_eq!(
+            SliceResult {
+                num_steps: 70,
+                status: SliceStatus::Finished(TerminationReason::MaxIter),
+            },
+            runner.step(&program, 100).unwrap()
+        );
+        assert_eq!(expected, runner.output());
+
+        //`run()` starts over, and ends there
+        token.cancel();
+        let result = runner.run(&program).unwrap();
+        assert_eq!(
+            (TerminationReason::Cancelled, 0),
+            (result.reason, result.num_steps)
+        );
+        assert!(!result.halted_normally());
+    }
+
+    #[test]
+    fn test_observer() {
+        struct Skipper;
+        impl Observer for Skipper {
+            fn before_command(&mut self, _: &Interpreter, command: Command) -> Intervention {
+                match command {
+                    Command::Push => Intervention::Skip,
+                    _ => Intervention::Proceed,
+                }
+            }
+        }
+        let program = program();
+        let mut observer = Skipper;
+        let mut runner = Runner::new(RunnerConfig {
+            observer: Some(&mut observer),
+            ..config(2)
+        });
+        assert!(matches!(
+            runner.run(&program),
+            Err(PietError::InterventionNotAllowed { step: 1, .. })
+        ));
+
+        let mut runner = Runner::new(RunnerConfig {
+            observer: Some(&mut observer),
+            allow_interventions: true,
+            ..config(2)
+        });
+        runner.run(&program).unwrap();
+        //nothing to print
+        assert_eq!(b"", runner.output());
+    }
+
+    /// Steps until the program terminates or waits for input.
+    fn step_until_blocked(runner: &mut Runner, program: &Program) -> SliceStatus {
+        loop {
+            match runner.step(program, 10).unwrap().status {
+                SliceStatus::Runnable => (),
+                status => return status,
+            }
+        }
+    }
+
+    #[test]
+    fn test_interactive_input() {
+        let interactive = || {
+            Runner::new(RunnerConfig {
+                interactive_input: true,
+                record_input: true,
+                output: Output::Capture,
+                ..RunnerConfig::default()
+            })
+        };
+
+        //the input is typed while the program waits for it
+        let program = Example::Adder.program();
+        let mut runner = interactive();
+        runner.feed_input(b"1");
+        assert_eq!(
+            SliceStatus::NeedsInput,
+            step_until_blocked(&mut runner, &program)
+        );
+        runner.feed_input(b"2\n");
+        assert_eq!(
+            SliceStatus::NeedsInput,
+            step_until_blocked(&mut runner, &program)
+        );
+        assert_eq!(b"", runner.output());
+        runner.feed_input(b"30\n");
+        assert_eq!(
+            SliceStatus::Finished(TerminationReason::NoExit),
+            step_until_blocked(&mut runner, &program)
+        );
+        assert_eq!(b"42\n", runner.output());
+        let recorded = runner.recorded_input().to_vec();
+        assert_eq!(b"12\n30\n", recorded.as_slice());
+
+        //which replays the session
+        let mut replay = Runner::new(RunnerConfig {
+            input: Some(recorded),
+            output: Output::Capture,
+            ..RunnerConfig::default()
+        });
+        replay.run(&program).unwrap();
+        assert_eq!(b"42\n", replay.output());
+
+        //EOF is sent explicitly
+        let program = Example::Cat.program();
+        let mut runner = interactive();
+        runner.feed_input(b"ab");
+        assert_eq!(
+            SliceStatus::NeedsInput,
+            step_until_blocked(&mut runner, &program)
+        );
+        assert_eq!(b"ab", runner.output());
+        runner.feed_input(b" c");
+        runner.close_input();
+        assert_eq!(
+            SliceStatus::Finished(TerminationReason::NoExit),
+            step_until_blocked(&mut runner, &program)
+        );
+        assert_eq!(b"abc", runner.output());
+        assert_eq!(b"ab c", runner.recorded_input());
+
+        //`run()` reads only what has been fed
+        runner.feed_input(b"xy");
+        runner.run(&program).unwrap();
+        assert_eq!(b"xy", runner.output());
+    }
+
+    #[test]
+    fn test_piet_runner() {
+        let runner = PietRunner::builder().max_iter(10_000).build();
+        for file in [
+            "./tests/test_images/original___hello_2x.png",
+            "./tests/test_images/original___hi.pietdev",
+        ] {
+            let log = IoLog::default();
+            let result = runner.run(file, io::empty(), log.clone()).unwrap();
+            assert_eq!(TerminationReason::NoExit, result.reason, "{}", file);
+            assert!(log.contents().starts_with('H'), "{}", file);
+        }
+
+        let log = IoLog::default();
+        let program = program();
+        let runner = PietRunner::builder().max_iter(2).build();
+        let result = runner.run(&program, io::empty(), log.clone()).unwrap();
+        assert_eq!(TerminationReason::MaxIter, result.reason);
+        assert_eq!("3\n", log.contents());
+
+        //an unknown color is replaced with the fallback
+        let img = Image::from_codel_grid(vec![vec![
+            Codel::Red,
+            Codel::Other(Pixel { r: 1, g: 2, b: 3 }),
+        ]]);
+        let runner = PietRunner::builder().max_iter(10).build();
+        assert!(runner.run(img, io::empty(), io::sink()).is_err());
+        let img = Image::from_codel_grid(vec![vec![
+            Codel::Red,
+            Codel::Other(Pixel { r: 1, g: 2, b: 3 }),
+        ]]);
+        let runner = PietRunner::builder().fallback(Codel::Black).build();
+        let result = runner.run(img, io::empty(), io::sink()).unwrap();
+        assert_eq!(TerminationReason::NoExit, result.reason);
+
+        assert!(runner
+            .run("./tests/test_images/not_found.png", io::empty(), io::sink())
+            .is_err());
+
+        //the limits on the size of the image apply to both of the formats
+        let runner = PietRunner::builder()
+            .image_limits(ImageLimits {
+                max_pixels: 100,
+                ..ImageLimits::default()
+            })
+            .build();
+        for file in [
+            "./tests/test_images/original___hello_2x.png",
+            "./tests/test_images/original___hi.pietdev",
+        ] {
+            assert!(
+                runner.run(file, io::empty(), io::sink()).is_err(),
+                "{}",
+                file
+            );
+        }
+    }
+
+    /// Writer whose every write fails, e.g. a closed connection.
+    struct FailingWriter(io::ErrorKind);
+
+    impl Write for FailingWriter {
+        fn write(&mut self, _: &[u8]) -> io::Result<usize> {
+            Err(self.0.into())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_failing_writer() {
+        //the run ends with an error instead of a panic
+        let runner = PietRunner::builder().max_iter(10).build();
+        let e = runner
+            .run(
+                &program(),
+                io::empty(),
+                FailingWriter(io::ErrorKind::ConnectionReset),
+            )
+            .unwrap_err();
+        let e = e.downcast_ref::<PietError>().unwrap();
+        assert!(matches!(
+            e,
+            PietError::WriteFailed {
+                what: "the output",
+                kind: io::ErrorKind::ConnectionReset,
+                ..
+            }
+        ));
+        assert!(!e.is_broken_pipe());
+        assert!(e.to_string().starts_with("failed to write the output: "));
+
+        let e = runner
+            .run(
+                &program(),
+                io::empty(),
+                FailingWriter(io::ErrorKind::BrokenPipe),
+            )
+            .unwrap_err();
+        assert!(e.downcast_ref::<PietError>().unwrap().is_broken_pipe());
+    }
+}
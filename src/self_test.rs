@@ -0,0 +1,456 @@
+/*!
+Consistency checks of the built-in tables against the data of the spec (`--self-test`).
+
+The colors ([`Codel::rgb()`] and [`Codel::new()`]), their hue and lightness, the command table ([`Command::new()`]) and the rotations of DP and CC are written by hand,
+so an accidental edit of one of them silently changes the language rather than failing to build.
+[`run()`] checks them against [`SPEC_COLORS`] and [`SPEC_COMMANDS`], which are copied from [the spec](https://www.dangermouse.net/esoteric/piet.html) independently of them,
+and reports every invariant which doesn't hold. The same checks run as a unit test, and `--self-test` runs them on the binary, e.g. to verify a locally patched build.
+*/
+
+use std::fmt::{self, Display, Formatter};
+
+use itertools::Itertools;
+
+use super::cc::CC;
+use super::codel::Codel;
+use super::command::Command;
+use super::dp::DP;
+use super::image::Pixel;
+use super::pietdev::PALETTE;
+
+/// RGB values of the colors of the spec, where `SPEC_COLORS[lightness][hue]` is the color of `hue` (in the order red, yellow, green, cyan, blue, magenta) and `lightness` (in the order light, normal, dark).
+#[rustfmt::skip]
+pub const SPEC_COLORS: [[(u8, u8, u8); 6]; 3] = [
+    [(0xFF, 0xC0, 0xC0), (0xFF, 0xFF, 0xC0), (0xC0, 0xFF, 0xC0), (0xC0, 0xFF, 0xFF), (0xC0, 0xC0, 0xFF), (0xFF, 0xC0, 0xFF)],
+    [(0xFF, 0x00, 0x00), (0xFF, 0xFF, 0x00), (0x00, 0xFF, 0x00), (0x00, 0xFF, 0xFF), (0x00, 0x00, 0xFF), (0xFF, 0x00, 0xFF)],
+    [(0xC0, 0x00, 0x00), (0xC0, 0xC0, 0x00), (0x00, 0xC0, 0x00), (0x00, 0xC0, 0xC0), (0x00, 0x00, 0xC0), (0xC0, 0x00, 0xC0)],
+];
+
+/// RGB value of white in the spec.
+pub const SPEC_WHITE: (u8, u8, u8) = (0xFF, 0xFF, 0xFF);
+
+/// RGB value of black in the spec.
+pub const SPEC_BLACK: (u8, u8, u8) = (0x00, 0x00, 0x00);
+
+/// Command table of the spec, where `SPEC_COMMANDS[hue][lightness]` is the command of a transition by `hue` steps along the hue cycle and `lightness` steps along the lightness cycle.
+pub const SPEC_COMMANDS: [[Option<Command>; 3]; 6] = [
+    [None, Some(Command::Push), Some(Command::Pop)],
+    [
+        Some(Command::Add),
+        Some(Command::Subtract),
+        Some(Command::Multiply),
+    ],
+    [
+        Some(Command::Divide),
+        Some(Command::Mod),
+        Some(Command::Not),
+    ],
+    [
+        Some(Command::Greater),
+        Some(Command::Pointer),
+        Some(Command::Switch),
+    ],
+    [
+        Some(Command::Duplicate),
+        Some(Command::Roll),
+        Some(Command::InNumber),
+    ],
+    [
+        Some(Command::InChar),
+        Some(Command::OutNumber),
+        Some(Command::OutChar),
+    ],
+];
+
+/// Implementations checked by [`run()`].
+/// [`Tables::BUILT_IN`] are those of the interpreter; a test replaces one of them to see that a mistake is caught.
+#[derive(Clone, Copy)]
+pub struct Tables {
+    pub rgb: fn(&Codel) -> (u8, u8, u8),
+    pub new: fn(&Pixel) -> Codel,
+    pub from_hue_lightness: fn(usize, usize) -> Codel,
+    pub hue: fn(&Codel) -> usize,
+    pub lightness: fn(&Codel) -> usize,
+    /// Only called with two different codels of the 18 hue colors.
+    pub command: fn(&Codel, &Codel) -> Command,
+    pub rotate: fn(&DP, isize) -> DP,
+    pub flip: fn(&CC) -> CC,
+}
+
+impl Tables {
+    pub const BUILT_IN: Self = Self {
+        rgb: Codel::rgb,
+        new: Codel::new,
+        from_hue_lightness: Codel::from_hue_lightness,
+        hue: Codel::get_hue,
+        lightness: Codel::get_lightness,
+        command: Command::new,
+        rotate: DP::rotate_clockwise_by,
+        flip: CC::flip,
+    };
+}
+
+/// Result of [`run()`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Report {
+    pub num_checks: usize,
+    /// Description of each invariant which doesn't hold.
+    pub failures: Vec<String>,
+}
+
+impl Report {
+    pub fn is_ok(&self) -> bool {
+        self.failures.is_empty()
+    }
+
+    fn check(&mut self, holds: bool, failure: impl FnOnce() -> String) {
+        self.num_checks += 1;
+        if !holds {
+            self.failures.push(failure());
+        }
+    }
+}
+
+impl Display for Report {
+    /// Prints one line per failure followed by the summary.
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        for failure in &self.failures {
+            writeln!(f, "FAIL {}", failure)?;
+        }
+        write!(
+            f,
+            "{} passed, {} failed",
+            self.num_checks - self.failures.len(),
+            self.failures.len()
+        )
+    }
+}
+
+fn pixel((r, g, b): (u8, u8, u8)) -> Pixel {
+    Pixel { r, g, b }
+}
+
+fn hex((r, g, b): (u8, u8, u8)) -> String {
+    format!("#{:02X}{:02X}{:02X}", r, g, b)
+}
+
+/// Checks `tables` against the spec.
+pub fn run(tables: &Tables) -> Report {
+    let mut report = Report {
+        num_checks: 0,
+        failures: vec![],
+    };
+    check_colors(tables, &mut report);
+    check_commands(tables, &mut report);
+    check_rotations(tables, &mut report);
+    report
+}
+
+fn check_colors(t: &Tables, report: &mut Report) {
+    let mut grid = vec![];
+    for (lightness, hue) in (0..3).cartesian_product(0..6) {
+        let codel = (t.from_hue_lightness)(hue, lightness);
+        let expected = SPEC_COLORS[lightness][hue];
+        let at = format!("the color of hue {} and lightness {}", hue, lightness);
+        report.check(
+            !codel.is_white() && !codel.is_black() && !codel.is_other(),
+            || format!("{} is {:?}, which has no hue", at, codel),
+        );
+        if codel.is_white() || codel.is_black() || codel.is_other() {
+            continue;
+        }
+        let rgb = (t.rgb)(&codel);
+        report.check(rgb == expected, || {
+            format!(
+                "{} ({:?}) is {} but the spec says {}",
+                at,
+                codel,
+                hex(rgb),
+                hex(expected)
+            )
+        });
+        let read = (t.new)(&pixel(expected));
+        report.check(read == codel, || {
+            format!(
+                "{} is read as {:?} instead of {:?}",
+                hex(expected),
+                read,
+                codel
+            )
+        });
+        let found = ((t.hue)(&codel), (t.lightness)(&codel));
+        report.check(found == (hue, lightness), || {
+            format!(
+                "{:?} has hue {} and lightness {} instead of {} and {}",
+                codel, found.0, found.1, hue, lightness
+            )
+        });
+        grid.push(codel);
+    }
+    let duplicates = grid.iter().duplicates().collect_vec();
+    report.check(duplicates.is_empty(), || {
+        format!(
+            "the hue and lightness grid is incomplete: {:?} appear more than once",
+            duplicates
+        )
+    });
+
+    for (codel, expected) in [(Codel::White, SPEC_WHITE), (Codel::Black, SPEC_BLACK)] {
+        let rgb = (t.rgb)(&codel);
+        report.check(rgb == expected, || {
+            format!(
+                "{:?} is {} but the spec says {}",
+                codel,
+                hex(rgb),
+                hex(expected)
+            )
+        });
+        let read = (t.new)(&pixel(expected));
+        report.check(read == codel, || {
+            format!(
+                "{} is read as {:?} instead of {:?}",
+                hex(expected),
+                read,
+                codel
+            )
+        });
+    }
+
+    let missing = grid
+        .iter()
+        .chain(&[Codel::White, Codel::Black])
+        .filter(|c| !PALETTE.contains(c))
+        .collect_vec();
+    report.check(missing.is_empty() && PALETTE.iter().all_unique(), || {
+        format!(
+            "the palette of PietDev saves doesn't have each of the 20 colors once (missing: {:?})",
+            missing
+        )
+    });
+}
+
+fn check_commands(t: &Tables, report: &mut Report) {
+    //The colors are taken from the spec rather than `t` so that a mistake in the colors is not reported again here.
+    let colors = (0..6)
+        .cartesian_product(0..3)
+        .map(|(hue, lightness)| {
+            let rgb = SPEC_COLORS[lightness][hue];
+            ((hue, lightness), Codel::new(&pixel(rgb)))
+        })
+        .collect_vec();
+    for (hue_difference, lightness_difference) in (0..6).cartesian_product(0..3) {
+        let Some(expected) = SPEC_COMMANDS[hue_difference][lightness_difference] else {
+            continue;
+        };
+        //only the first transition of the wrong command is reported
+        let wrong = colors.iter().find_map(|&((hue, lightness), from)| {
+            let to = colors
+                .iter()
+                .find(|((h, l), _)| {
+                    (*h == (hue + hue_difference) % 6)
+                        && (*l == (lightness + lightness_difference) % 3)
+                })
+                .unwrap()
+                .1;
+            let found = (t.command)(&from, &to);
+            (found != expected).then_some((from, to, found))
+        });
+        report.check(wrong.is_none(), || {
+            let (from, to, found) = wrong.unwrap();
+            format!(
+                "the command of {} hue step(s) and {} lightness step(s) is {:?} (e.g. from {:?} to {:?}) but the spec says {:?}",
+                hue_difference, lightness_difference, found, from, to, expected
+            )
+        });
+    }
+
+    let table = (0..6)
+        .cartesian_product(0..3)
+        .filter(|&d| d != (0, 0))
+        .map(|(hue, lightness)| (t.command)(&colors[0].1, &colors[hue * 3 + lightness].1))
+        .collect_vec();
+    for command in SPEC_COMMANDS.iter().flatten().flatten() {
+        let n = table.iter().filter(|c| *c == command).count();
+        report.check(n == 1, || {
+            format!(
+                "{:?} appears {} time(s) in the command table instead of once",
+                command, n
+            )
+        });
+    }
+}
+
+fn check_rotations(t: &Tables, report: &mut Report) {
+    const DPS: [DP; 4] = [DP::Right, DP::Down, DP::Left, DP::Up];
+    for (i, dp) in DPS.into_iter().enumerate() {
+        let turned = (t.rotate)(&dp, 1);
+        let expected = DPS[(i + 1) % 4];
+        report.check(turned == expected, || {
+            format!(
+                "{:?} rotated clockwise is {:?} instead of {:?}",
+                dp, turned, expected
+            )
+        });
+        let rotated = (t.rotate)(&dp, 4);
+        report.check(rotated == dp, || {
+            format!("{:?} rotated by 4 is {:?} instead of itself", dp, rotated)
+        });
+        let back = (t.rotate)(&(t.rotate)(&dp, -1), 1);
+        report.check(back == dp, || {
+            format!(
+                "{:?} rotated by -1 and then by 1 is {:?} instead of itself",
+                dp, back
+            )
+        });
+        for (a, b) in (-4..=4).cartesian_product(-4..=4) {
+            let composed = (t.rotate)(&(t.rotate)(&dp, a), b);
+            let direct = (t.rotate)(&dp, a + b);
+            report.check(composed == direct, || {
+                format!(
+                    "{:?} rotated by {} and then by {} is {:?} but rotated by {} is {:?}",
+                    dp,
+                    a,
+                    b,
+                    composed,
+                    a + b,
+                    direct
+                )
+            });
+        }
+    }
+
+    for cc in [CC::Left, CC::Right] {
+        let flipped = (t.flip)(&cc);
+        report.check(flipped != cc, || format!("{:?} flipped is itself", cc));
+        let twice = (t.flip)(&flipped);
+        report.check(twice == cc, || {
+            format!("{:?} flipped twice is {:?} instead of itself", cc, twice)
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_built_in() {
+        let report = run(&Tables::BUILT_IN);
+        assert!(report.is_ok(), "{}", report);
+        assert!(report.num_checks > 300);
+        assert_eq!(
+            format!("{} passed, 0 failed", report.num_checks),
+            report.to_string()
+        );
+    }
+
+    #[test]
+    fn test_perturbed() {
+        //a constant off by one
+        fn rgb(c: &Codel) -> (u8, u8, u8) {
+            match c {
+                Codel::DarkCyan => (0x00, 0xC0, 0xC1),
+                _ => c.rgb(),
+            }
+        }
+        let report = run(&Tables {
+            rgb,
+            ..Tables::BUILT_IN
+        });
+        assert_eq!(
+            vec!["the color of hue 3 and lightness 2 (DarkCyan) is #00C0C1 but the spec says #00C0C0"],
+            report.failures
+        );
+        assert_eq!(
+            format!(
+                "FAIL the color of hue 3 and lightness 2 (DarkCyan) is #00C0C1 but the spec says #00C0C0\n{} passed, 1 failed",
+                report.num_checks - 1
+            ),
+            report.to_string()
+        );
+
+        //two colors swapped in the reading of pixels
+        fn new(p: &Pixel) -> Codel {
+            match Codel::new(p) {
+                Codel::Blue => Codel::Magenta,
+                Codel::Magenta => Codel::Blue,
+                c => c,
+            }
+        }
+        let report = run(&Tables {
+            new,
+            ..Tables::BUILT_IN
+        });
+        assert_eq!(
+            vec![
+                "#0000FF is read as Magenta instead of Blue",
+                "#FF00FF is read as Blue instead of Magenta",
+            ],
+            report.failures[..2]
+        );
+
+        //two commands swapped, which breaks no uniqueness
+        fn command(from: &Codel, to: &Codel) -> Command {
+            match Command::new(from, to) {
+                Command::Add => Command::Subtract,
+                Command::Subtract => Command::Add,
+                c => c,
+            }
+        }
+        let report = run(&Tables {
+            command,
+            ..Tables::BUILT_IN
+        });
+        assert_eq!(
+            vec![
+                "the command of 1 hue step(s) and 0 lightness step(s) is Subtract (e.g. from LightRed to LightYellow) but the spec says Add",
+                "the command of 1 hue step(s) and 1 lightness step(s) is Add (e.g. from LightRed to Yellow) but the spec says Subtract",
+            ],
+            report.failures
+        );
+
+        //a command appearing twice
+        fn duplicated(from: &Codel, to: &Codel) -> Command {
+            match Command::new(from, to) {
+                Command::Roll => Command::Pointer,
+                c => c,
+            }
+        }
+        let report = run(&Tables {
+            command: duplicated,
+            ..Tables::BUILT_IN
+        });
+        assert!(report.failures[1..].contains(
+            &"Pointer appears 2 time(s) in the command table instead of once".to_string()
+        ));
+        assert!(report.failures[1..]
+            .contains(&"Roll appears 0 time(s) in the command table instead of once".to_string()));
+
+        //a rotation which is not of order 4
+        fn rotate(dp: &DP, i: isize) -> DP {
+            dp.rotate_clockwise_by(if i == 4 { 3 } else { i })
+        }
+        let report = run(&Tables {
+            rotate,
+            ..Tables::BUILT_IN
+        });
+        assert!(report
+            .failures
+            .contains(&"Right rotated by 4 is Up instead of itself".to_string()));
+
+        fn flip(_: &CC) -> CC {
+            CC::Left
+        }
+        let report = run(&Tables {
+            flip,
+            ..Tables::BUILT_IN
+        });
+        assert_eq!(
+            vec![
+                "Left flipped is itself",
+                "Right flipped twice is Left instead of itself",
+            ],
+            report.failures
+        );
+    }
+}
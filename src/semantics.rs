@@ -0,0 +1,605 @@
+/*!
+Machine-readable description of the execution semantics (`version-report`).
+
+A [`SemanticsDescriptor`] tells which semantics a build and a set of options imply, so that tools which record or compare runs can tell whether two runs are comparable.
+Two runs with the same descriptor and the same program and input behave identically.
+
+The fields are listed by [`SemanticsDescriptor::fields()`], from which both the JSON and the field-by-field [`SemanticsDiff`] of two descriptors (`version-report --diff-with`) are made,
+so that what differs between two sets of options is answered by the code rather than by the documentation.
+
+[`DECISIONS`] lists, as data, what this interpreter does on every point which the spec leaves open or on which implementations are known to disagree, each with the field of [`ExecConfig`] which controls it (if any) and its default.
+Every field of `ExecConfig` controls exactly one decision, so that a new option cannot be added without declaring its semantics. `run --semantics` prints the table for the options given (see [`SemanticsTable`]).
+
+[`SEMANTICS_VERSION`] is bumped whenever the behavior under some descriptor changes (e.g. a bug fix in a corner case), and each change is listed below.
+
+- 1: initial version
+*/
+
+use std::fmt::{self, Display};
+
+use clap::ValueEnum;
+
+use super::corner::CornerStrategyKind;
+use super::extension::{ExecConfig, Extension};
+use super::image::Topology;
+use super::stdin::NumberOverflow;
+
+/// Version of the semantics of this build.
+pub const SEMANTICS_VERSION: u32 = 1;
+
+/// Semantics implied by a build and the options of a run.
+///
+/// The fields of type `&str` are fixed in this build and are included so that a change of them can be detected.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SemanticsDescriptor {
+    pub version: u32,
+    /// Rounding of `divide` (toward zero).
+    pub division: &'static str,
+    /// Sign of the result of `mod` (that of the divisor, i.e. floored division).
+    pub modulo: &'static str,
+    /// What `in(number)` and `in(char)` do at the end of input (ignored).
+    pub eof: &'static str,
+    /// Encoding of `in(char)` and `out(char)`.
+    pub io_encoding: &'static str,
+    /// How a slide across white terminates (when a codel is revisited with the same DP).
+    pub white: &'static str,
+    pub topology: Topology,
+    /// Enabled extensions, in the order of [`Extension`].
+    pub extensions: Vec<Extension>,
+    /// Name of the corner strategy (see [`corner`](super::corner)).
+    pub corner_strategy: String,
+    /// What `in(number)` does with an integer out of the range of `isize`.
+    pub number_overflow: NumberOverflow,
+}
+
+impl SemanticsDescriptor {
+    pub fn new(config: &ExecConfig, topology: Topology) -> Self {
+        Self {
+            version: SEMANTICS_VERSION,
+            division: "truncate",
+            modulo: "floor",
+            eof: "ignore",
+            io_encoding: "utf-8",
+            white: "stop-on-retrace",
+            topology,
+            extensions: Extension::value_variants()
+                .iter()
+                .copied()
+                .filter(|e| match e {
+                    Extension::SecondStack => config.second_stack,
+                })
+                .collect(),
+            corner_strategy: config.corner_strategy.name().to_string(),
+            number_overflow: config.number_overflow,
+        }
+    }
+
+    /// Returns the configuration which reproduces the descriptor.
+    /// The topology is to be set on the image (see [`Image::set_topology()`](super::image::Image::set_topology)).
+    /// A corner strategy which is not built into this crate cannot be reproduced, and the standard one is used instead.
+    pub fn exec_config(&self) -> ExecConfig {
+        ExecConfig {
+            corner_strategy: CornerStrategyKind::from_name(&self.corner_strategy)
+                .unwrap_or_default()
+                .strategy(),
+            number_overflow: self.number_overflow,
+            ..ExecConfig::new(&self.extensions)
+        }
+    }
+
+    /// Lists the fields in the order of the JSON, with their values as JSON.
+    pub fn fields(&self) -> Vec<Field> {
+        let string = |s: &str| format!(r#""{}""#, s);
+        let field = |name, value, flag| Field { name, value, flag };
+        vec![
+            field("version", self.version.to_string(), None),
+            field("division", string(self.division), None),
+            field("modulo", string(self.modulo), None),
+            field("eof", string(self.eof), None),
+            field("io_encoding", string(self.io_encoding), None),
+            field("white", string(self.white), None),
+            field(
+                "topology",
+                string(&value_name(&self.topology)),
+                Some("--topology"),
+            ),
+            field(
+                "extensions",
+                format!(
+                    "[{}]",
+                    self.extensions
+                        .iter()
+                        .map(|e| string(&value_name(e)))
+                        .collect::<Vec<_>>()
+                        .join(",")
+                ),
+                Some("--extension"),
+            ),
+            field(
+                "corner_strategy",
+                string(&self.corner_strategy),
+                Some("--corner-strategy"),
+            ),
+            field(
+                "number_overflow",
+                string(&value_name(&self.number_overflow)),
+                Some("--number-overflow"),
+            ),
+        ]
+    }
+
+    pub fn to_json(&self) -> String {
+        format!(
+            "{{{}}}",
+            self.fields()
+                .iter()
+                .map(|f| format!(r#""{}":{}"#, f.name, f.value))
+                .collect::<Vec<_>>()
+                .join(",")
+        )
+    }
+}
+
+/// A field of a [`SemanticsDescriptor`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Field {
+    pub name: &'static str,
+    /// The value as JSON.
+    pub value: String,
+    /// Option which changes the field, or `None` if the field is fixed in this build.
+    pub flag: Option<&'static str>,
+}
+
+/// Field-by-field difference between two descriptors (`version-report --diff-with`).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SemanticsDiff {
+    /// Pairs of the differing fields of the two descriptors, in the order of [`SemanticsDescriptor::fields()`].
+    pub fields: Vec<(Field, Field)>,
+}
+
+impl SemanticsDiff {
+    pub fn new(a: &SemanticsDescriptor, b: &SemanticsDescriptor) -> Self {
+        Self {
+            fields: a
+                .fields()
+                .into_iter()
+                .zip(b.fields())
+                .filter(|(a, b)| a.value != b.value)
+                .collect(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.fields.is_empty()
+    }
+}
+
+/// Prints a line for each differing field, with the values of the two sides and the option which toggles the field.
+impl Display for SemanticsDiff {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.is_empty() {
+            return writeln!(f, "no difference");
+        }
+        let name_width = self.fields.iter().map(|(a, _)| a.name.len()).max().unwrap();
+        let value_width = self
+            .fields
+            .iter()
+            .map(|(a, _)| a.value.len())
+            .max()
+            .unwrap();
+        let right_width = self
+            .fields
+            .iter()
+            .map(|(_, b)| b.value.len())
+            .max()
+            .unwrap();
+        for (a, b) in &self.fields {
+            let toggle = match a.flag {
+                Some(flag) => format!("toggled by `{}`", flag),
+                None => "fixed in this build".to_string(),
+            };
+            writeln!(
+                f,
+                "{:nw$}  {:vw$}  ->  {:rw$}  ({})",
+                a.name,
+                a.value,
+                b.value,
+                toggle,
+                nw = name_width,
+                vw = value_width,
+                rw = right_width
+            )?;
+        }
+        Ok(())
+    }
+}
+
+fn value_name(v: &impl ValueEnum) -> String {
+    v.to_possible_value().unwrap().get_name().to_string()
+}
+
+impl Display for SemanticsDescriptor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.to_json())
+    }
+}
+
+/// A point which the spec leaves open or on which implementations are known to disagree, and what this interpreter does on it.
+#[derive(Debug, Clone, Copy)]
+pub struct Decision {
+    pub name: &'static str,
+    /// What is decided.
+    pub question: &'static str,
+    /// What is done under the default configuration.
+    pub default: &'static str,
+    /// What controls the decision, or `None` if it is fixed in this build.
+    pub control: Option<Control>,
+}
+
+/// Field of [`ExecConfig`] which controls a [`Decision`].
+#[derive(Debug, Clone, Copy)]
+pub struct Control {
+    pub field: &'static str,
+    /// Option of the command line which sets the field.
+    pub flag: &'static str,
+    /// Returns what is done under a configuration.
+    pub value: fn(&ExecConfig) -> String,
+}
+
+impl Decision {
+    /// Returns what is done under `config`.
+    pub fn value(&self, config: &ExecConfig) -> String {
+        match &self.control {
+            Some(control) => (control.value)(config),
+            None => self.default.to_string(),
+        }
+    }
+}
+
+/// The decisions of this interpreter, in the order of the commands they concern.
+pub const DECISIONS: &[Decision] = &[
+    Decision {
+        name: "stack_underflow",
+        question: "a command with fewer values on the stack than it pops",
+        default: "ignore",
+        control: None,
+    },
+    Decision {
+        name: "arithmetic_overflow",
+        question: "`add`, `subtract`, `multiply` or `divide` with a result out of the range of `isize`",
+        default: "wrap",
+        control: None,
+    },
+    Decision {
+        name: "division",
+        question: "rounding of `divide`",
+        default: "truncate",
+        control: None,
+    },
+    Decision {
+        name: "modulo",
+        question: "sign of the result of `mod`",
+        default: "floor",
+        control: None,
+    },
+    Decision {
+        name: "division_by_zero",
+        question: "`divide` or `mod` by zero",
+        default: "ignore",
+        control: None,
+    },
+    Decision {
+        name: "negative_roll_depth",
+        question: "`roll` with a negative depth",
+        default: "ignore",
+        control: Some(Control {
+            field: "second_stack",
+            flag: "--extension second-stack",
+            value: |config| match config.second_stack {
+                true => "second-stack".to_string(),
+                false => "ignore".to_string(),
+            },
+        }),
+    },
+    Decision {
+        name: "deep_roll",
+        question: "`roll` deeper than the stack",
+        default: "ignore",
+        control: None,
+    },
+    Decision {
+        name: "eof",
+        question: "`in(number)` or `in(char)` at the end of input",
+        default: "ignore",
+        control: None,
+    },
+    Decision {
+        name: "number_overflow",
+        question: "`in(number)` with an integer out of the range of `isize`",
+        default: "ignore",
+        control: Some(Control {
+            field: "number_overflow",
+            flag: "--number-overflow",
+            value: |config| value_name(&config.number_overflow),
+        }),
+    },
+    Decision {
+        name: "io_encoding",
+        question: "encoding of `in(char)` and `out(char)`",
+        default: "utf-8",
+        control: None,
+    },
+    Decision {
+        name: "out_number",
+        question: "what follows the number printed by `out(number)`",
+        default: "newline",
+        control: None,
+    },
+    Decision {
+        name: "out_char_range",
+        question: "`out(char)` with a value which is not a Unicode scalar value (the value is kept on the stack)",
+        default: "ignore",
+        control: None,
+    },
+    Decision {
+        name: "white",
+        question: "when a slide across white stops (a codel revisited with the same DP)",
+        default: "stop-on-retrace",
+        control: None,
+    },
+    Decision {
+        name: "corner_strategy",
+        question: "codel from which a block is left",
+        default: "standard",
+        control: Some(Control {
+            field: "corner_strategy",
+            flag: "--corner-strategy",
+            value: |config| config.corner_strategy.name().to_string(),
+        }),
+    },
+];
+
+/// [`DECISIONS`] with what is done under a configuration (`run --semantics`).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SemanticsTable {
+    /// Pairs of the name of a decision and what is done, in the order of [`DECISIONS`].
+    pub values: Vec<(&'static str, String)>,
+}
+
+impl SemanticsTable {
+    pub fn new(config: &ExecConfig) -> Self {
+        Self {
+            values: DECISIONS
+                .iter()
+                .map(|d| (d.name, d.value(config)))
+                .collect(),
+        }
+    }
+
+    pub fn to_json(&self) -> String {
+        let string = |s: &str| format!(r#""{}""#, s);
+        format!(
+            "[{}]",
+            DECISIONS
+                .iter()
+                .zip(&self.values)
+                .map(|(d, (_, value))| {
+                    let (field, flag) = match &d.control {
+                        Some(c) => (string(c.field), string(c.flag)),
+                        None => ("null".to_string(), "null".to_string()),
+                    };
+                    format!(
+                        r#"{{"name":{},"value":{},"default":{},"field":{},"flag":{},"question":{}}}"#,
+                        string(d.name),
+                        string(value),
+                        string(d.default),
+                        field,
+                        flag,
+                        string(d.question)
+                    )
+                })
+                .collect::<Vec<_>>()
+                .join(",")
+        )
+    }
+}
+
+/// Prints a line for each decision with what is done, what controls it (with the default if it is not in effect) and the question.
+impl Display for SemanticsTable {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let controls = DECISIONS
+            .iter()
+            .zip(&self.values)
+            .map(|(d, (_, value))| match &d.control {
+                None => "fixed".to_string(),
+                Some(c) if value == d.default => format!("`{}`", c.flag),
+                Some(c) => format!("`{}` (default: {})", c.flag, d.default),
+            })
+            .collect::<Vec<_>>();
+        let name_width = self.values.iter().map(|(n, _)| n.len()).max().unwrap();
+        let value_width = self.values.iter().map(|(_, v)| v.len()).max().unwrap();
+        let control_width = controls.iter().map(String::len).max().unwrap();
+        for ((d, (name, value)), control) in DECISIONS.iter().zip(&self.values).zip(&controls) {
+            writeln!(
+                f,
+                "{:nw$}  {:vw$}  {:cw$}  {}",
+                name,
+                value,
+                control,
+                d.question,
+                nw = name_width,
+                vw = value_width,
+                cw = control_width
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Returns the report of `version-report`: the version of the build and the semantics implied by the options.
+pub fn version_report(descriptor: &SemanticsDescriptor) -> String {
+    format!(
+        r#"{{"name":"{}","version":"{}","semantics":{}}}"#,
+        env!("CARGO_PKG_NAME"),
+        env!("CARGO_PKG_VERSION"),
+        descriptor.to_json()
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_descriptor() {
+        let default = SemanticsDescriptor::new(&ExecConfig::default(), Topology::Bounded);
+        assert_eq!(
+            r#"{"version":1,"division":"truncate","modulo":"floor","eof":"ignore","io_encoding":"utf-8","white":"stop-on-retrace","topology":"bounded","extensions":[],"corner_strategy":"standard","number_overflow":"ignore"}"#,
+            default.to_json()
+        );
+        assert_eq!(ExecConfig::default(), default.exec_config());
+
+        let config = ExecConfig {
+            number_overflow: NumberOverflow::Bigmod,
+            ..ExecConfig::new(&[Extension::SecondStack])
+        };
+        let descriptor = SemanticsDescriptor::new(&config, Topology::Torus);
+        assert_ne!(default, descriptor);
+        assert!(descriptor.to_json().ends_with(
+            r#""extensions":["second-stack"],"corner_strategy":"standard","number_overflow":"bigmod"}"#
+        ));
+        //round trip
+        assert_eq!(config, descriptor.exec_config());
+        assert_eq!(
+            descriptor,
+            SemanticsDescriptor::new(&descriptor.exec_config(), descriptor.topology)
+        );
+
+        assert!(version_report(&default)
+            .starts_with(r#"{"name":"piet_programming_language","version":""#));
+    }
+
+    #[test]
+    fn test_diff() {
+        let default = SemanticsDescriptor::new(&ExecConfig::default(), Topology::Bounded);
+        let torus = SemanticsDescriptor::new(&ExecConfig::default(), Topology::Torus);
+        let both =
+            SemanticsDescriptor::new(&ExecConfig::new(&[Extension::SecondStack]), Topology::Torus);
+
+        assert!(SemanticsDiff::new(&default, &default).is_empty());
+        assert_eq!(
+            "no difference\n",
+            SemanticsDiff::new(&torus, &torus).to_string()
+        );
+
+        let diff = SemanticsDiff::new(&default, &both);
+        assert_eq!(
+            vec!["topology", "extensions"],
+            diff.fields.iter().map(|(a, _)| a.name).collect::<Vec<_>>()
+        );
+        assert_eq!(
+            "topology    \"bounded\"  ->  \"torus\"           (toggled by `--topology`)\nextensions  []         ->  [\"second-stack\"]  (toggled by `--extension`)\n",
+            diff.to_string()
+        );
+        //every field which can differ between two sets of options has an option to toggle it
+        assert!(SemanticsDiff::new(&both, &default)
+            .fields
+            .iter()
+            .all(|(a, b)| a.flag.is_some() && (a.flag == b.flag)));
+    }
+
+    /// Returns the names of the fields of a struct from its derived `Debug` format.
+    fn field_names(debug: &str) -> Vec<String> {
+        let body = &debug[debug.find('{').unwrap() + 1..debug.rfind('}').unwrap()];
+        let mut names = vec![];
+        let mut depth = 0;
+        let mut is_in_string = false;
+        let mut start = 0;
+        for (i, c) in body.char_indices() {
+            match c {
+                '"' => is_in_string = !is_in_string,
+                _ if is_in_string => (),
+                '(' | '[' | '{' => depth += 1,
+                ')' | ']' | '}' => depth -= 1,
+                ',' if depth == 0 => start = i + 1,
+                ':' if depth == 0 && start <= i => {
+                    names.push(body[start..i].trim().to_string());
+                    start = body.len();
+                }
+                _ => (),
+            }
+        }
+        names
+    }
+
+    #[test]
+    fn test_decisions() {
+        //Every field of `ExecConfig` controls exactly one decision.
+        assert_eq!(
+            vec!["a", "b", "c"],
+            field_names(r#"S { a: T("x, y: z"), b: [1, 2], c: U { d: 0 } }"#)
+        );
+        let fields = field_names(&format!("{:?}", ExecConfig::default()));
+        assert!(fields.contains(&"second_stack".to_string()));
+        for field in &fields {
+            assert_eq!(
+                1,
+                DECISIONS
+                    .iter()
+                    .filter(|d| d.control.is_some_and(|c| c.field == field))
+                    .count(),
+                "{}",
+                field
+            );
+        }
+        assert_eq!(
+            fields.len(),
+            DECISIONS.iter().filter(|d| d.control.is_some()).count()
+        );
+
+        //unique names, and the defaults are those of the default configuration and of the descriptor
+        let default = SemanticsDescriptor::new(&ExecConfig::default(), Topology::Bounded);
+        for (i, d) in DECISIONS.iter().enumerate() {
+            assert!(
+                DECISIONS[..i].iter().all(|e| e.name != d.name),
+                "{}",
+                d.name
+            );
+            assert_eq!(d.default, d.value(&ExecConfig::default()), "{}", d.name);
+            if let Some(field) = default.fields().into_iter().find(|f| f.name == d.name) {
+                assert_eq!(format!(r#""{}""#, d.default), field.value, "{}", d.name);
+            }
+        }
+    }
+
+    #[test]
+    fn test_table() {
+        let table = SemanticsTable::new(&ExecConfig::default());
+        assert!(DECISIONS
+            .iter()
+            .zip(&table.values)
+            .all(|(d, (name, value))| (d.name == *name) && (d.default == value)));
+        let s = table.to_string();
+        assert_eq!(DECISIONS.len(), s.lines().count());
+        assert!(s.contains("\ndivision             truncate         fixed"));
+
+        let config = ExecConfig {
+            number_overflow: NumberOverflow::Saturate,
+            ..ExecConfig::new(&[Extension::SecondStack])
+        };
+        let table = SemanticsTable::new(&config);
+        let s = table.to_string();
+        assert!(s.contains(
+            "\nnegative_roll_depth  second-stack     `--extension second-stack` (default: ignore)"
+        ));
+        assert!(s.contains(
+            "\nnumber_overflow      saturate         `--number-overflow` (default: ignore)"
+        ));
+        assert!(table.to_json().starts_with(
+            r#"[{"name":"stack_underflow","value":"ignore","default":"ignore","field":null,"flag":null,"question":"#
+        ));
+        assert!(table.to_json().contains(
+            r#"{"name":"number_overflow","value":"saturate","default":"ignore","field":"number_overflow","flag":"--number-overflow","#
+        ));
+    }
+}
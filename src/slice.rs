@@ -0,0 +1,329 @@
+/*!
+Sliced execution, for an embedding program which runs many programs cooperatively on a few threads (see [`Interpreter::run_for()`]).
+
+A slice runs at most a given number of steps and returns, and the next slice continues exactly where it stopped, as the whole state is kept in the [`Interpreter`] (including that of a slide across white).
+Each step of a slide across white counts, so a slice ends in time even in the middle of a long slide, while the attempts to leave a coloured block are a part of one step.
+The output, the counters and the termination of a program run in slices are the same as those of an uninterrupted run.
+
+With an input in the non-blocking mode (see [`Stdin::new_non_blocking()`](super::stdin::Stdin::new_non_blocking)), a slice also returns before a command which reads more than has been fed, without executing it,
+so that the embedding program can feed more input (or close it) and resume later.
+
+A slice runs with the default options: no limit other than `max_steps`, no trace and no extension other than those set in [`Interpreter::config`].
+
+[`Interpreter::step()`] runs a slice of one step and tells what the step did, e.g. for a debugger which inspects the stack, DP and CC after each move.
+*/
+
+use super::command::Command;
+use super::error::PietError;
+use super::interpreter::Interpreter;
+use super::program::Program;
+use super::run_result::TerminationReason;
+use super::runner::RunnerConfig;
+use super::trace::TraceEvent;
+
+/// Whether the program can continue after a slice.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SliceStatus {
+    /// The program terminated, so no more slice shall be run.
+    Finished(TerminationReason),
+    /// The next command reads more input than has been fed to the non-blocking input.
+    NeedsInput,
+    /// The slice used all of its steps.
+    Runnable,
+}
+
+/// Outcome of [`Interpreter::run_for()`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SliceResult {
+    /// Number of steps executed in the slice.
+    pub num_steps: usize,
+    pub status: SliceStatus,
+}
+
+/// Outcome of [`Interpreter::step()`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StepOutcome {
+    /// The interpreter moved into another coloured block and executed the command of the transition.
+    Command(Command),
+    /// The interpreter moved into, within or out of a white block, where no command is executed.
+    Slid,
+    /// The program terminated, so no more step shall be run.
+    Terminated(TerminationReason),
+    /// The next command reads more input than has been fed to the non-blocking input, so the step was not run.
+    NeedsInput,
+}
+
+impl Interpreter {
+    /// Runs one step of `program` from the current state, i.e. a slice of one step (see [`Self::run_for()`]).
+    ///
+    /// # Errors
+    ///
+    /// Same as [`Self::run_for()`].
+    pub fn step(&mut self, program: &Program) -> Result<StepOutcome, PietError> {
+        let mut trace = vec![];
+        let status = super::run_configured(
+            &RunnerConfig::default(),
+            program,
+            self,
+            None,
+            Some(&mut trace),
+            &mut vec![],
+            Some(1),
+        )?;
+        Ok(match status {
+            SliceStatus::Finished(reason) => StepOutcome::Terminated(reason),
+            SliceStatus::NeedsInput => StepOutcome::NeedsInput,
+            SliceStatus::Runnable => trace
+                .iter()
+                .find_map(|e| match e {
+                    TraceEvent::Command(command) => Some(StepOutcome::Command(*command)),
+                    _ => None,
+                })
+                .unwrap_or(StepOutcome::Slid),
+        })
+    }
+
+    /// Runs `program` from the current state for at most `max_steps` steps.
+    /// The interpreter shall be new or reset for `program` (see [`Self::reset_for()`]) before the first slice, whose input and output are those of the interpreter.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the program fails, e.g. the top-left codel is black, in which case no more slice shall be run.
+    pub fn run_for(
+        &mut self,
+        program: &Program,
+        max_steps: usize,
+    ) -> Result<SliceResult, PietError> {
+        let num_steps = self.num_steps;
+        let status = super::run_configured(
+            &RunnerConfig::default(),
+            program,
+            self,
+            None,
+            None,
+            &mut vec![],
+            Some(max_steps),
+        )?;
+        Ok(SliceResult {
+            num_steps: self.num_steps - num_steps,
+            status,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use clap::Parser;
+
+    use super::super::args::Args;
+    use super::super::cc::CC;
+    use super::super::codel::Codel;
+    use super::super::dp::DP;
+    use super::super::examples::Example;
+    use super::super::image::Image;
+    use super::super::state_digest::StateDigest;
+    use super::super::stdin::Stdin;
+    use super::*;
+    use rustc_hash::FxHashMap;
+
+    fn load(file: &str) -> Program {
+        let path = format!("./tests/test_images/{}", file);
+        let args = Args::parse_from(["piet_programming_language", path.as_str()]);
+        Program::new(super::super::load_image(&args.load, false, &mut vec![]).unwrap())
+    }
+
+    /// Returns the termination, the counters, the stack and the output.
+    fn state(
+        reason: TerminationReason,
+        ip: &Interpreter,
+    ) -> (TerminationReason, usize, usize, Vec<isize>, Vec<u8>) {
+        (
+            reason,
+            ip.num_steps,
+            ip.num_commands,
+            ip.stack.clone(),
+            ip.output_buf.clone(),
+        )
+    }
+
+    fn run_uninterrupted(
+        program: &Program,
+        input: &str,
+        max_iter: usize,
+    ) -> (TerminationReason, usize, usize, Vec<isize>, Vec<u8>) {
+        let max_iter = max_iter.to_string();
+        let args = Args::parse_from(["piet_programming_language", "", "--max-iter", &max_iter]);
+        let mut ip = Interpreter::new_with_stdin(input);
+        let result = super::super::run_image_with(&args, program, &mut ip).unwrap();
+        state(result.reason, &ip)
+    }
+
+    /// Returns the digest of the state of an uninterrupted run stopped after `num_steps` steps.
+    fn digest_uninterrupted(program: &Program, input: &str, num_steps: usize) -> StateDigest {
+        let num_steps = num_steps.to_string();
+        let args = Args::parse_from(["piet_programming_language", "", "--max-iter", &num_steps]);
+        let mut ip = Interpreter::new_with_stdin(input);
+        super::super::run_image_with(&args, program, &mut ip).unwrap();
+        ip.digest()
+    }
+
+    #[test]
+    fn test_interleaved_slices() {
+        //The programs which never terminate are run up to `max_iter` steps, and so are cut by the slices in the middle of slides.
+        let max_iter = 1000;
+        const MAX_CHECKED_STEPS: usize = 200;
+        let cases = [
+            ("original___hi.pietdev", ""),
+            ("original___abcd.png", ""),
+            ("original___adder.png", "12 30"),
+            ("original___echo_char.png", "a"),
+            ("original___hi_with_margin.png", ""),
+            ("original___white_start.pietdev", ""),
+            ("original___print_then_spin.png", ""),
+        ]
+        .map(|(file, input)| (load(file), input));
+        let expected = cases
+            .iter()
+            .map(|(program, input)| run_uninterrupted(program, input, max_iter))
+            .collect::<Vec<_>>();
+        //the digests of the uninterrupted runs by the case and the number of steps
+        let mut digests = FxHashMap::default();
+
+        for max_steps in [1, 2, 3, 7, 50_000] {
+            //each case with the next one, round-robin
+            for k in 0..cases.len() {
+                let pair = [k, (k + 1) % cases.len()];
+                let mut ips = pair.map(|k| Interpreter::new_with_stdin(cases[k].1));
+                let mut reasons = [None; 2];
+                while reasons.contains(&None) {
+                    for (l, &k) in pair.iter().enumerate() {
+                        if reasons[l].is_some() {
+                            continue;
+                        }
+                        if ips[l].num_steps == max_iter {
+                            reasons[l] = Some(TerminationReason::MaxIter);
+                            continue;
+                        }
+                        let max_steps = max_steps.min(max_iter - ips[l].num_steps);
+                        let result = ips[l].run_for(&cases[k].0, max_steps).unwrap();
+                        assert!(result.num_steps <= max_steps);
+                        //the same state as the uninterrupted run after each slice, even in the middle of a slide
+                        //(only early in the run, as each step checked is a run of its own)
+                        let num_steps = ips[l].num_steps;
+                        if num_steps <= MAX_CHECKED_STEPS {
+                            let expected = *digests.entry((k, num_steps)).or_insert_with(|| {
+                                digest_uninterrupted(&cases[k].0, cases[k].1, num_steps)
+                            });
+                            assert_eq!(expected, ips[l].digest(), "{} at step {}", k, num_steps);
+                        }
+                        match result.status {
+                            SliceStatus::Finished(reason) => reasons[l] = Some(reason),
+                            SliceStatus::Runnable => assert_eq!(max_steps, result.num_steps),
+                            SliceStatus::NeedsInput => panic!("the input is given as a whole"),
+                        }
+                    }
+                }
+                for (l, &k) in pair.iter().enumerate() {
+                    assert_eq!(
+                        expected[k],
+                        state(reasons[l].unwrap(), &ips[l]),
+                        "{} in slices of {}",
+                        k,
+                        max_steps
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_non_blocking_input() {
+        let program = Example::Adder.program();
+        let expected = run_uninterrupted(&program, "12 30", usize::MAX);
+
+        let mut ip = Interpreter::new_with_stdin("");
+        ip.stdin = Stdin::new_non_blocking();
+        let mut input = "12 30".bytes();
+        let mut num_blocks = 0;
+        let reason = loop {
+            let result = ip.run_for(&program, 5).unwrap();
+            match result.status {
+                SliceStatus::Finished(reason) => break reason,
+                SliceStatus::Runnable => (),
+                SliceStatus::NeedsInput => {
+                    num_blocks += 1;
+                    //blocked again without moving until more input is fed
+                    assert_eq!(
+                        SliceResult {
+                            num_steps: 0,
+                            status: SliceStatus::NeedsInput
+                        },
+                        ip.run_for(&program, 5).unwrap()
+                    );
+                    match input.next() {
+                        Some(b) => ip.stdin.feed(&[b]),
+                        None => ip.stdin.close(),
+                    }
+                }
+            }
+        };
+        assert_eq!(expected, state(reason, &ip));
+        //once before each byte (as a number is complete only after a whitespace) and once before the close
+        assert_eq!(6, num_blocks);
+    }
+
+    #[test]
+    fn test_black_start() {
+        let program = Program::new(Image::from_codel_grid(vec![vec![Codel::Black, Codel::Red]]));
+        assert!(matches!(
+            Interpreter::new().run_for(&program, 10),
+            Err(PietError::BlackStartCodel)
+        ));
+    }
+
+    #[test]
+    fn test_step() {
+        let program = load("original___hello_2x.png");
+        let expected = run_uninterrupted(&program, "", usize::MAX);
+
+        let mut ip = Interpreter::new_with_stdin("");
+        let mut commands = vec![];
+        let reason = loop {
+            let num_steps = ip.num_steps;
+            match ip.step(&program).unwrap() {
+                StepOutcome::Terminated(reason) => break reason,
+                StepOutcome::Command(command) => {
+                    //the state can be inspected between the steps
+                    if commands.is_empty() {
+                        assert_eq!(1, ip.stack.len());
+                        assert_eq!((DP::Right, CC::Left), (ip.dp, ip.cc));
+                    }
+                    commands.push(command);
+                }
+                StepOutcome::Slid => (),
+                StepOutcome::NeedsInput => unreachable!(),
+            }
+            assert_eq!(num_steps + 1, ip.num_steps);
+        };
+        assert_eq!(TerminationReason::NoExit, reason);
+        assert_eq!(expected, state(reason, &ip));
+        assert!(ip.stack.is_empty());
+        assert_eq!(ip.num_commands, commands.len());
+        assert_eq!(Command::Push, commands[0]);
+        assert_eq!(
+            14,
+            commands.iter().filter(|c| **c == Command::OutChar).count()
+        );
+
+        //across white
+        let (r, w, b) = (Codel::Red, Codel::White, Codel::Blue);
+        let program = Program::new(Image::from_codel_grid(vec![vec![r, w, w, b]]));
+        let mut ip = Interpreter::new();
+        assert_eq!(Ok(StepOutcome::Slid), ip.step(&program));
+        assert_eq!((0, 1), ip.cur);
+        assert_eq!(Ok(StepOutcome::Slid), ip.step(&program));
+        assert_eq!(Ok(StepOutcome::Slid), ip.step(&program));
+        assert_eq!((0, 3), ip.cur);
+        assert_eq!(0, ip.num_commands);
+    }
+}
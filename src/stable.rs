@@ -0,0 +1,141 @@
+/*!
+Platform-independent encodings and hashes for data which outlives a process (the fingerprint of a program and the entries of `--cache-dir`).
+
+`std::hash::Hash` and `FxHasher` are not suitable for such data: `Hash` feeds `usize` and enum discriminants in the native width and byte order, and `FxHasher` is not guaranteed to give the same values across versions.
+So persisted data is encoded byte by byte (integers as fixed-width little-endian), and hashed with 64-bit FNV-1a, whose values are fixed by its definition.
+`FxHasher` is fine (and faster) for anything which stays in memory.
+*/
+
+use super::codel::Codel;
+use super::image::Topology;
+use super::pietdev::PALETTE;
+
+/// Receives an encoding.
+pub(crate) trait Sink {
+    fn put(&mut self, bytes: &[u8]);
+
+    fn put_u64(&mut self, x: u64) {
+        self.put(&x.to_le_bytes());
+    }
+}
+
+impl Sink for Vec<u8> {
+    fn put(&mut self, bytes: &[u8]) {
+        self.extend_from_slice(bytes);
+    }
+}
+
+/// 64-bit FNV-1a.
+pub(crate) struct Fnv64(u64);
+
+impl Fnv64 {
+    pub fn new() -> Self {
+        Self(0xcbf2_9ce4_8422_2325)
+    }
+
+    pub fn finish(&self) -> u64 {
+        self.0
+    }
+}
+
+impl Sink for Fnv64 {
+    fn put(&mut self, bytes: &[u8]) {
+        for b in bytes {
+            self.0 ^= u64::from(*b);
+            self.0 = self.0.wrapping_mul(0x0000_0100_0000_01b3);
+        }
+    }
+}
+
+/// Returns the FNV-1a hash of `bytes`.
+pub(crate) fn fnv64(bytes: &[u8]) -> u64 {
+    let mut hasher = Fnv64::new();
+    hasher.put(bytes);
+    hasher.finish()
+}
+
+/// Tag of a codel of an unknown color, which is followed by its three bytes.
+pub(crate) const OTHER_COLOR_TAG: u8 = 255;
+
+/// Encodes a codel as its index in [`PALETTE`], or [`OTHER_COLOR_TAG`] followed by the color.
+pub(crate) fn put_codel(sink: &mut impl Sink, codel: &Codel) {
+    match codel {
+        Codel::Other(p) => sink.put(&[OTHER_COLOR_TAG, p.r, p.g, p.b]),
+        _ => sink.put(&[PALETTE.iter().position(|e| e == codel).unwrap() as u8]),
+    }
+}
+
+pub(crate) fn put_topology(sink: &mut impl Sink, topology: Topology) {
+    sink.put(&[match topology {
+        Topology::Bounded => 0,
+        Topology::Torus => 1,
+    }]);
+}
+
+/// Bounds-checked reader of an encoding, where every method returns `None` if the bytes run out.
+pub(crate) struct Reader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    pub fn new(bytes: &'a [u8]) -> Self {
+        Self { bytes }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.bytes.is_empty()
+    }
+
+    pub fn take(&mut self, n: usize) -> Option<&'a [u8]> {
+        if self.bytes.len() < n {
+            return None;
+        }
+        let (head, tail) = self.bytes.split_at(n);
+        self.bytes = tail;
+        Some(head)
+    }
+
+    pub fn u8(&mut self) -> Option<u8> {
+        Some(self.take(1)?[0])
+    }
+
+    pub fn u64(&mut self) -> Option<u64> {
+        Some(u64::from_le_bytes(self.take(8)?.try_into().ok()?))
+    }
+
+    pub fn usize(&mut self) -> Option<usize> {
+        usize::try_from(self.u64()?).ok()
+    }
+
+    /// Reads a length, rejecting values which cannot fit in the remaining bytes
+    /// (each element occupies at least `min_bytes_per_element` bytes) so that corrupt data never causes a huge allocation.
+    pub fn len(&mut self, min_bytes_per_element: usize) -> Option<usize> {
+        let n = self.usize()?;
+        (n <= self.bytes.len() / min_bytes_per_element).then_some(n)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::image::Pixel;
+    use super::*;
+
+    #[test]
+    fn test_fnv64() {
+        //the test vectors of the reference implementation
+        assert_eq!(0xcbf29ce484222325, fnv64(b""));
+        assert_eq!(0xaf63dc4c8601ec8c, fnv64(b"a"));
+        assert_eq!(0x85944171f73967e8, fnv64(b"foobar"));
+    }
+
+    #[test]
+    fn test_encoding() {
+        let mut buf = vec![];
+        put_codel(&mut buf, &Codel::LightRed);
+        put_codel(&mut buf, &Codel::Black);
+        put_codel(&mut buf, &Codel::Other(Pixel { r: 1, g: 2, b: 3 }));
+        put_topology(&mut buf, Topology::Torus);
+        buf.put_u64(0x0102);
+        assert_eq!(vec![0, 19, 255, 1, 2, 3, 1, 2, 1, 0, 0, 0, 0, 0, 0], buf);
+    }
+}
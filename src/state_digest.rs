@@ -0,0 +1,152 @@
+/*!
+Comparable summary of the state of an interpreter (see [`Interpreter::digest()`]).
+
+Running in slices ([`Interpreter::run_for()`]), resuming from a checkpoint and reusing an interpreter ([`Interpreter::reset_for()`]) all promise the state of an uninterrupted run.
+A [`StateDigest`] lets a test assert this at any point of a run rather than only by the output at the end:
+two digests are equal if and only if the position, the DP and CC, the stacks and the counters are (up to a collision of the hashes of the stacks).
+
+Only the semantic state is digested. The writers (the output, the I/O trace and the terminal), the configuration, the state of a slide across white and the time of the last I/O are left out.
+The stacks are hashed with 64-bit FNV-1a over their values as fixed-width little-endian integers (as the fingerprint of a program), so a digest is the same on every platform and can be recorded in a golden file.
+*/
+
+use std::fmt::{self, Display, Formatter};
+
+use super::cc::CC;
+use super::dp::DP;
+use super::interpreter::Interpreter;
+use super::stable::{Fnv64, Sink};
+
+/// Digest of the state of an interpreter.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct StateDigest {
+    pub pos: (usize, usize),
+    pub dp: DP,
+    pub cc: CC,
+    pub stack_len: usize,
+    pub stack_hash: u64,
+    /// Length of the second stack of [`Extension::SecondStack`](super::extension::Extension::SecondStack).
+    pub second_stack_len: usize,
+    pub second_stack_hash: u64,
+    pub num_steps: usize,
+    pub num_commands: usize,
+    pub num_output_bytes: usize,
+    /// Number of bytes consumed from the input.
+    pub num_input_bytes: usize,
+}
+
+fn hash_stack(stack: &[isize]) -> u64 {
+    let mut hasher = Fnv64::new();
+    hasher.put_u64(stack.len() as u64);
+    for x in stack {
+        hasher.put(&(*x as i64).to_le_bytes());
+    }
+    hasher.finish()
+}
+
+impl StateDigest {
+    pub fn new(ip: &Interpreter) -> Self {
+        Self {
+            pos: ip.cur,
+            dp: ip.dp,
+            cc: ip.cc,
+            stack_len: ip.stack.len(),
+            stack_hash: hash_stack(&ip.stack),
+            second_stack_len: ip.second_stack.len(),
+            second_stack_hash: hash_stack(&ip.second_stack),
+            num_steps: ip.num_steps,
+            num_commands: ip.num_commands,
+            num_output_bytes: ip.num_output_bytes,
+            num_input_bytes: ip.stdin.num_bytes_read(),
+        }
+    }
+
+    /// Returns the whole digest as one hash, for recording it.
+    ///
+    /// The value is the 64-bit FNV-1a hash of `position (2 * u64) | DP (u8) | CC (u8) | stack (length and hash, 2 * u64) | second stack (same) | number of steps, commands, output bytes and input bytes (4 * u64)`.
+    pub fn to_u64(&self) -> u64 {
+        let mut hasher = Fnv64::new();
+        hasher.put_u64(self.pos.0 as u64);
+        hasher.put_u64(self.pos.1 as u64);
+        hasher.put(&[self.dp as u8, self.cc as u8]);
+        for x in [
+            self.stack_len as u64,
+            self.stack_hash,
+            self.second_stack_len as u64,
+            self.second_stack_hash,
+            self.num_steps as u64,
+            self.num_commands as u64,
+            self.num_output_bytes as u64,
+            self.num_input_bytes as u64,
+        ] {
+            hasher.put_u64(x);
+        }
+        hasher.finish()
+    }
+}
+
+impl Display for StateDigest {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "{:016x}", self.to_u64())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::codel::Codel;
+    use super::super::command::Command;
+    use super::super::image::Image;
+    use super::super::program::Program;
+    use super::*;
+
+    #[test]
+    fn test_digest() {
+        let drive = |ip: &mut Interpreter| {
+            Command::Push.execute(ip, 3).unwrap();
+            Command::Push.execute(ip, 4).unwrap();
+            Command::Multiply.execute(ip, 1).unwrap();
+            ip.num_steps = 3;
+            ip.num_commands = 3;
+        };
+        let mut a = Interpreter::new_with_stdin("12 ab");
+        let mut b = Interpreter::new_with_stdin("12 ab");
+        drive(&mut a);
+        drive(&mut b);
+        assert_eq!(a.digest(), b.digest());
+        assert_eq!(a.digest().to_u64(), b.digest().to_u64());
+
+        //the writers and the binding are not digested
+        b.output_log = Some(vec![]);
+        b.output_writer = Some(Box::new(std::io::sink()));
+        b.rebind(&Program::new(Image::from_codel_grid(vec![vec![
+            Codel::Red,
+        ]])));
+        assert_eq!(a.digest(), b.digest());
+
+        //one more push
+        Command::Push.execute(&mut b, 1).unwrap();
+        assert_ne!(a.digest(), b.digest());
+        assert_ne!(a.digest().to_u64(), b.digest().to_u64());
+
+        //the same length but another value
+        let mut c = Interpreter::new_with_stdin("12 ab");
+        Command::Push.execute(&mut c, 11).unwrap();
+        c.num_steps = 3;
+        c.num_commands = 3;
+        assert_eq!(a.digest().stack_len, c.digest().stack_len);
+        assert_ne!(a.digest(), c.digest());
+
+        //the consumed input
+        let mut d = Interpreter::new_with_stdin("12 ab");
+        drive(&mut d);
+        d.stdin.read_char();
+        assert_ne!(a.digest(), d.digest());
+        assert_eq!(1, d.digest().num_input_bytes);
+
+        //fixed on every platform
+        let ip = Interpreter::new();
+        assert_eq!(
+            (0, 0xa8c7_f832_281a_39c5),
+            (ip.digest().stack_len, ip.digest().stack_hash)
+        );
+    }
+}
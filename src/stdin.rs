@@ -1,14 +1,128 @@
 use std::{
     collections::VecDeque,
-    io::{self, Read},
+    fmt::{self, Display, Formatter},
+    io::{self, ErrorKind, Read},
+    ops::RangeInclusive,
 };
 
-use itertools::Itertools;
+use clap::ValueEnum;
+
+/// Where the bytes come from.
+enum Source {
+    /// The real stdin of the process.
+    Stdin,
+    Reader(Box<dyn Read + Send>),
+    /// An input given as a whole, whose buffer is reused by [`Stdin::reset_with_bytes()`].
+    Bytes(VecDeque<u8>),
+}
+
+/// Returns the length of the UTF-8 sequence which starts with `lead` and the range of its second byte, or `None` if `lead` cannot start a sequence.
+//ref: |https://doc.rust-lang.org/std/string/struct.String.html#method.from_utf8_lossy|
+fn utf8_sequence(lead: u8) -> Option<(usize, RangeInclusive<u8>)> {
+    match lead {
+        0x00..=0x7F => Some((1, 0x80..=0xBF)),
+        0xC2..=0xDF => Some((2, 0x80..=0xBF)),
+        0xE0 => Some((3, 0xA0..=0xBF)),
+        0xE1..=0xEC | 0xEE..=0xEF => Some((3, 0x80..=0xBF)),
+        0xED => Some((3, 0x80..=0x9F)),
+        0xF0 => Some((4, 0x90..=0xBF)),
+        0xF1..=0xF3 => Some((4, 0x80..=0xBF)),
+        0xF4 => Some((4, 0x80..=0x8F)),
+        _ => None,
+    }
+}
+
+/// Default of the maximum number of characters of a word (see [`Stdin::set_max_token_len()`]).
+pub const DEFAULT_MAX_TOKEN_LEN: usize = 1024;
+
+/// How the line endings of the input are read (`--input-newlines`).
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum InputNewlines {
+    /// Read as they are
+    #[default]
+    Raw,
+    /// CRLF and a lone CR are read as LF
+    Lf,
+}
+
+impl Display for InputNewlines {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "{}", self.to_possible_value().unwrap().get_name())
+    }
+}
+
+/// What `in(number)` does with an integer which does not fit in `isize` (`--number-overflow`).
+///
+/// Only a word which is an integer (an optional sign followed by decimal digits) is affected;
+/// the other words, and the words longer than the limit (see [`Stdin::set_max_token_len()`]), are parse errors under every policy.
+/// The word is consumed under every policy.
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq, Hash, Default)]
+pub enum NumberOverflow {
+    /// The command is ignored as with any other parse error
+    #[default]
+    Ignore,
+    /// `isize::MAX` or `isize::MIN` is pushed
+    Saturate,
+    /// The absolute value is reduced modulo 2^63 (`isize::MAX + 1`) and the sign is kept
+    Bigmod,
+}
+
+impl NumberOverflow {
+    /// Parses `word` as an integer, applying the policy if it is out of the range of `isize`.
+    pub fn parse(self, word: &str) -> Option<isize> {
+        if let Ok(n) = word.parse() {
+            return Some(n);
+        }
+        //`parse()` reports an overflow before it finds a later invalid digit, so the syntax is checked here
+        let (is_negative, digits) = match word.as_bytes() {
+            [b'-', rest @ ..] => (true, rest),
+            [b'+', rest @ ..] => (false, rest),
+            rest => (false, rest),
+        };
+        if digits.is_empty() || !digits.iter().all(u8::is_ascii_digit) {
+            return None;
+        }
+        match self {
+            NumberOverflow::Ignore => None,
+            NumberOverflow::Saturate => Some(if is_negative { isize::MIN } else { isize::MAX }),
+            NumberOverflow::Bigmod => {
+                //the modulus is a power of two, so wrapping arithmetic followed by a mask is exact
+                let mask = isize::MAX as usize;
+                let magnitude = digits.iter().fold(0usize, |acc, b| {
+                    acc.wrapping_mul(10).wrapping_add((b - b'0') as usize) & mask
+                }) as isize;
+                Some(if is_negative { -magnitude } else { magnitude })
+            }
+        }
+    }
+}
 
 /// Stdin reader which can read a single Unicode character.
 pub struct Stdin {
     is_eof: bool,
-    stdin: Box<dyn Read>, //`Box` is for dependency injection.
+    /// Whether more bytes may be fed (the non-blocking mode, see [`Self::new_non_blocking()`]).
+    is_open: bool,
+    stdin: Source,
+    /// Byte of the reader which has been looked at but not consumed yet (see [`Self::peek_byte()`]).
+    peeked: Option<u8>,
+    num_bytes_read: usize,
+    last_terminator: Option<char>,
+    max_token_len: usize,
+    newlines: InputNewlines,
+    /// Whether the last byte read was a CR folded into LF, so that an LF right after it is dropped.
+    is_after_cr: bool,
+    /// Number of CR line endings (CRLF or a lone CR) read so far.
+    num_cr_line_endings: usize,
+    /// Copy of the bytes read so far if recording (see [`Self::set_recording()`]).
+    record: Option<Vec<u8>>,
+    /// Number of bytes after which the input ends (see [`Self::set_max_bytes()`]).
+    max_bytes: Option<usize>,
+    /// Whether a read has been cut by `max_bytes`.
+    is_capped: bool,
+    /// Error of the reader which ended the input (see [`Self::take_read_error()`]).
+    read_error: Option<io::Error>,
+    /// Buffer of the last input given as bytes, kept by [`Self::reset()`] for [`Self::reset_with_bytes()`].
+    spare: VecDeque<u8>,
 }
 
 impl Stdin {
@@ -16,15 +130,315 @@ impl Stdin {
     pub fn new() -> Self {
         Self {
             is_eof: false,
-            stdin: Box::new(io::stdin()),
+            is_open: false,
+            stdin: Source::Stdin,
+            peeked: None,
+            num_bytes_read: 0,
+            last_terminator: None,
+            max_token_len: DEFAULT_MAX_TOKEN_LEN,
+            newlines: InputNewlines::Raw,
+            is_after_cr: false,
+            num_cr_line_endings: 0,
+            record: None,
+            max_bytes: None,
+            is_capped: false,
+            read_error: None,
+            spare: VecDeque::new(),
         }
     }
 
     //for dependency injection
     pub fn new_with_string(s: &str) -> Self {
+        Self::new_with_bytes(s.as_bytes())
+    }
+
+    //for dependency injection
+    pub fn new_with_bytes(b: &[u8]) -> Self {
         Self {
             is_eof: false,
-            stdin: Box::new(VecDeque::from(s.to_string().into_bytes())),
+            is_open: false,
+            stdin: Source::Bytes(VecDeque::from(b.to_vec())),
+            peeked: None,
+            num_bytes_read: 0,
+            last_terminator: None,
+            max_token_len: DEFAULT_MAX_TOKEN_LEN,
+            newlines: InputNewlines::Raw,
+            is_after_cr: false,
+            num_cr_line_endings: 0,
+            record: None,
+            max_bytes: None,
+            is_capped: false,
+            read_error: None,
+            spare: VecDeque::new(),
+        }
+    }
+
+    /// Creates an empty input in the non-blocking mode, to which the bytes are fed by [`Self::feed()`] while the program runs, until [`Self::close()`].
+    ///
+    /// A read which needs more bytes than have been fed would block, which [`Interpreter::run_for()`](super::interpreter::Interpreter::run_for) reports instead of reading (see [`Self::can_read_char()`] and [`Self::can_read_word()`]).
+    /// Elsewhere, the input ends where the bytes fed so far end.
+    pub fn new_non_blocking() -> Self {
+        Self {
+            is_open: true,
+            ..Self::new_with_bytes(&[])
+        }
+    }
+
+    /// Appends `b` to the input, replacing a reader (e.g. the real stdin) if it is read from.
+    pub fn feed(&mut self, b: &[u8]) {
+        match &mut self.stdin {
+            Source::Bytes(buf) => buf.extend(b),
+            Source::Stdin | Source::Reader(_) => {
+                let mut buf = std::mem::take(&mut self.spare);
+                buf.extend(b);
+                self.stdin = Source::Bytes(buf);
+                self.peeked = None;
+            }
+        }
+    }
+
+    /// Ends the input in the non-blocking mode, so that a read reaches EOF after the bytes fed so far instead of blocking.
+    pub fn close(&mut self) {
+        self.is_open = false;
+    }
+
+    /// Returns `false` if [`Self::read_char()`] would block in the non-blocking mode, i.e. the bytes fed so far are only whitespace or end in the middle of a character.
+    pub fn can_read_char(&self) -> bool {
+        self.can_read(false)
+    }
+
+    /// Returns `false` if [`Self::read_integer()`] would block in the non-blocking mode, i.e. no word of the bytes fed so far is followed by a whitespace.
+    pub fn can_read_word(&self) -> bool {
+        self.can_read(true)
+    }
+
+    /// Decodes the buffered bytes as [`Self::next()`] does, and returns `true` if the first non-whitespace character (followed by a whitespace if `is_word`) is complete.
+    fn can_read(&self, is_word: bool) -> bool {
+        let Source::Bytes(buf) = &self.stdin else {
+            return true;
+        };
+        if !self.is_open || self.is_eof || self.is_at_max_bytes() {
+            return true;
+        }
+        let mut i = 0;
+        let mut is_in_word = false;
+        while let Some(&b) = buf.get(i) {
+            if b.is_ascii_whitespace() {
+                if is_in_word {
+                    return true;
+                }
+                i += 1;
+                continue;
+            }
+            //a malformed sequence ends at the first byte which cannot continue it
+            let mut len = 1;
+            if let Some((num_bytes, second)) = utf8_sequence(b) {
+                while len < num_bytes {
+                    let Some(c) = buf.get(i + len) else {
+                        return false;
+                    };
+                    let range = if len == 1 {
+                        second.clone()
+                    } else {
+                        0x80..=0xBF
+                    };
+                    if !range.contains(c) {
+                        break;
+                    }
+                    len += 1;
+                }
+            }
+            if !is_word {
+                return true;
+            }
+            is_in_word = true;
+            i += len;
+        }
+        false
+    }
+
+    /// Rewinds the reading state for a new run, reading the real stdin from then on as [`Self::new()`] does.
+    /// The buffer of an input given as bytes is kept for the next [`Self::reset_with_bytes()`].
+    pub fn reset(&mut self) {
+        self.is_eof = false;
+        self.is_open = false;
+        self.peeked = None;
+        self.num_bytes_read = 0;
+        self.last_terminator = None;
+        self.is_after_cr = false;
+        self.num_cr_line_endings = 0;
+        self.is_capped = false;
+        self.read_error = None;
+        if let Some(record) = &mut self.record {
+            record.clear();
+        }
+        if let Source::Bytes(mut buf) = std::mem::replace(&mut self.stdin, Source::Stdin) {
+            buf.clear();
+            self.spare = buf;
+        }
+    }
+
+    /// Same as [`Self::new_with_bytes()`] but reuses the buffer of `self` if it has one.
+    pub fn reset_with_bytes(&mut self, b: &[u8]) {
+        self.reset();
+        let mut buf = std::mem::take(&mut self.spare);
+        buf.extend(b);
+        self.stdin = Source::Bytes(buf);
+    }
+
+    /// Same as [`Self::reset_with_bytes()`] but in the non-blocking mode (see [`Self::new_non_blocking()`]), with `b` as the bytes fed so far.
+    pub fn reset_non_blocking(&mut self, b: &[u8]) {
+        self.reset_with_bytes(b);
+        self.is_open = true;
+    }
+
+    /// Same as [`Self::reset()`] but reads `reader` from then on (e.g. a [`RandomInput`](super::random_input::RandomInput)).
+    pub fn reset_with_reader(&mut self, reader: Box<dyn Read + Send>) {
+        self.reset();
+        self.stdin = Source::Reader(reader);
+    }
+
+    /// Sets the maximum number of characters of a word read by [`Self::read_integer()`].
+    /// A longer word is consumed to its end but only its first `max_token_len` characters are kept, and it is read as a parse error.
+    pub fn set_max_token_len(&mut self, max_token_len: usize) {
+        self.max_token_len = max_token_len;
+    }
+
+    /// Ends the input after `max_bytes` bytes if it is `Some`, which a reset keeps (`--max-input-bytes`).
+    /// The input is cut as if it ended there, so a program reading it to EOF ends as it would with a shorter input, e.g. with an endless input source.
+    /// A character cut in the middle is read as U+FFFD, as a character truncated by EOF is.
+    pub fn set_max_bytes(&mut self, max_bytes: Option<usize>) {
+        self.max_bytes = max_bytes;
+    }
+
+    fn is_at_max_bytes(&self) -> bool {
+        self.max_bytes.is_some_and(|max| self.num_bytes_read >= max)
+    }
+
+    /// Returns (and forgets) the error of the reader which ended the input, e.g. `EIO` or `EISDIR` on a redirected input.
+    /// The input ends there as if it was EOF, so that a read never panics, and the command which read it ends the run with the error.
+    pub fn take_read_error(&mut self) -> Option<io::Error> {
+        self.read_error.take()
+    }
+
+    /// Returns `true` if the input has been cut by [`Self::set_max_bytes()`], i.e. a read reached the limit.
+    pub fn is_capped(&self) -> bool {
+        self.is_capped
+    }
+
+    /// Sets how the line endings are read from then on (see [`InputNewlines`]), which a reset keeps.
+    /// [`Self::num_bytes_read()`] still counts the bytes as they are, so that a checkpoint can be resumed in either mode.
+    pub fn set_newlines(&mut self, newlines: InputNewlines) {
+        self.newlines = newlines;
+    }
+
+    pub fn newlines(&self) -> InputNewlines {
+        self.newlines
+    }
+
+    /// Returns the number of CR line endings (CRLF or a lone CR) read so far, which are folded into LF with [`InputNewlines::Lf`].
+    pub fn num_cr_line_endings(&self) -> usize {
+        self.num_cr_line_endings
+    }
+
+    /// Starts (or stops) keeping a copy of the bytes read, which a reset clears but keeps recording (`--record-input`).
+    /// Given as the input of another run, the copy makes the program read the same, even if the bytes were fed piecemeal in the non-blocking mode.
+    pub fn set_recording(&mut self, is_recording: bool) {
+        self.record = is_recording.then(Vec::new);
+    }
+
+    /// Returns the bytes read so far while recording, or nothing.
+    pub fn recorded(&self) -> &[u8] {
+        self.record.as_deref().unwrap_or_default()
+    }
+
+    /// Returns the number of bytes consumed so far.
+    pub fn num_bytes_read(&self) -> usize {
+        self.num_bytes_read
+    }
+
+    /// Returns `true` once EOF has been reached.
+    pub fn is_eof(&self) -> bool {
+        self.is_eof
+    }
+
+    /// Continues reading where a previous run left off: skips the first `num_bytes_read` bytes of the input and restores the state.
+    /// An error is returned if the input is shorter than that.
+    pub fn resume(
+        &mut self,
+        num_bytes_read: usize,
+        is_eof: bool,
+        last_terminator: Option<char>,
+    ) -> Result<(), String> {
+        while self.num_bytes_read < num_bytes_read {
+            if self.read_byte().is_none() {
+                return Err(format!(
+                    "the input has only {} bytes but {} bytes had been consumed",
+                    self.num_bytes_read, num_bytes_read
+                ));
+            }
+        }
+        self.is_eof = is_eof;
+        self.last_terminator = last_terminator;
+        Ok(())
+    }
+
+    /// Returns the whitespace which terminated the last word read, or `None` if it was terminated by EOF.
+    pub fn last_terminator(&self) -> Option<char> {
+        self.last_terminator
+    }
+
+    /// Reads a single byte from `stdin`.
+    /// `None` is returned if EOF.
+    fn read_byte(&mut self) -> Option<u8> {
+        if self.is_at_max_bytes() {
+            self.is_capped = true;
+            return None;
+        }
+        let b = match self.peeked.take() {
+            Some(b) => b,
+            None => Self::read_byte_from(&mut self.stdin, &mut self.read_error)?,
+        };
+        self.num_bytes_read += 1;
+        if let Some(record) = &mut self.record {
+            record.push(b);
+        }
+        Some(b)
+    }
+
+    /// Returns the byte which [`Self::read_byte()`] would read next, without consuming it.
+    /// `None` is returned if EOF.
+    fn peek_byte(&mut self) -> Option<u8> {
+        if self.is_at_max_bytes() {
+            return None;
+        }
+        match &self.stdin {
+            Source::Bytes(buf) => buf.front().copied(),
+            Source::Stdin | Source::Reader(_) => {
+                if self.peeked.is_none() {
+                    self.peeked = Self::read_byte_from(&mut self.stdin, &mut self.read_error);
+                }
+                self.peeked
+            }
+        }
+    }
+
+    /// Reads a single byte from `source`, keeping an error other than EOF in `read_error`.
+    /// `None` is returned if EOF or an error.
+    fn read_byte_from(source: &mut Source, read_error: &mut Option<io::Error>) -> Option<u8> {
+        let mut buf = [0; 1];
+        let result = match source {
+            Source::Stdin => io::stdin().read_exact(&mut buf),
+            Source::Reader(reader) => reader.read_exact(&mut buf),
+            Source::Bytes(bytes) => return bytes.pop_front(),
+        };
+        match result {
+            Ok(()) => Some(buf[0]),
+            Err(e) if e.kind() == ErrorKind::UnexpectedEof => None,
+            Err(e) => {
+                *read_error = Some(e);
+                None
+            }
         }
     }
 
@@ -36,13 +450,26 @@ impl Stdin {
         if self.is_eof {
             return None;
         }
-        let next = self.stdin.as_mut().bytes().next();
+        let mut next = self.read_byte();
+        //the LF of a CRLF has already been read as the CR
+        if self.is_after_cr && (next == Some(b'\n')) {
+            next = self.read_byte();
+        }
+        self.is_after_cr = false;
         if next.is_none() {
             self.is_eof = true;
             return None;
         }
 
-        let c = next.unwrap().unwrap();
+        let c = next.unwrap();
+
+        if c == b'\r' {
+            self.num_cr_line_endings += 1;
+            if self.newlines == InputNewlines::Lf {
+                self.is_after_cr = true;
+                return Some('\n');
+            }
+        }
 
         //if ASCII
         if (c >> 7) == 0b0 {
@@ -50,19 +477,30 @@ impl Stdin {
         }
 
         //if Unicode
+        //As `String::from_utf8_lossy()` does, the longest malformed prefix of a sequence (e.g. a stray continuation byte, or a sequence truncated by EOF) is read as U+FFFD instead of panicking, and the next character starts at the first byte which cannot continue it.
         let mut l = vec![c];
-        let num_bytes = if (c >> 5) == 0b110 {
-            2
-        } else if (c >> 4) == 0b1110 {
-            3
-        } else {
-            assert_eq!(0b11110, c >> 3);
-            4
+        let Some((num_bytes, second)) = utf8_sequence(c) else {
+            return Some(char::REPLACEMENT_CHARACTER);
         };
-        for _ in 0..(num_bytes - 1) {
-            l.push(self.stdin.as_mut().bytes().next().unwrap().unwrap());
+        for i in 1..num_bytes {
+            let range = if i == 1 { second.clone() } else { 0x80..=0xBF };
+            if self.peek_byte().is_some_and(|b| !range.contains(&b)) {
+                return Some(char::REPLACEMENT_CHARACTER);
+            }
+            match self.read_byte() {
+                Some(b) => l.push(b),
+                None => {
+                    self.is_eof = true;
+                    return Some(char::REPLACEMENT_CHARACTER);
+                }
+            }
         }
-        Some(String::from_utf8(l).unwrap().chars().next().unwrap())
+        Some(
+            String::from_utf8(l)
+                .ok()
+                .and_then(|s| s.chars().next())
+                .unwrap_or(char::REPLACEMENT_CHARACTER),
+        )
     }
 
     /// Reads next non-whitespace character.
@@ -78,40 +516,66 @@ impl Stdin {
 
     /// Reads next word.
     /// "word" is a series of characters and each word is separated by one or more whitespaces.
+    /// Only the first `max_token_len` characters are kept (so that a huge word does not fill the memory), and the second value is `true` if any was dropped.
     /// `None` is returned if EOF.
-    fn read_word(&mut self) -> Option<String> {
-        let mut l = vec![];
+    fn read_word(&mut self) -> Option<(String, bool)> {
+        let mut word = String::new();
 
         //eats the preceding whitespace (if any) and reads the first character of a word
         loop {
             let next = self.next()?;
             if !next.is_ascii_whitespace() {
-                l.push(next);
+                word.push(next);
                 break;
             }
         }
 
-        //reads the remaining characters of a word
-        loop {
-            let next = self.next();
-            if next.is_none() {
-                break;
-            }
-            let next = next.unwrap();
+        //reads the remaining characters of a word, discarding those over the limit so that the next read starts after the word
+        self.last_terminator = None;
+        let mut num_chars = 1;
+        let mut is_truncated = false;
+        while let Some(next) = self.next() {
             if next.is_ascii_whitespace() {
+                self.last_terminator = Some(next);
                 break;
             }
-            l.push(next);
+            if num_chars < self.max_token_len {
+                word.push(next);
+                num_chars += 1;
+            } else {
+                is_truncated = true;
+            }
         }
 
-        Some(l.into_iter().join(""))
+        Some((word, is_truncated))
     }
 
     /// Reads next signed integer.
     /// `None` is returned if EOF or parse error because [the spec](https://www.dangermouse.net/esoteric/piet.html) says
     /// > If an integer read does not receive an integer value, this is an error and the command is ignored.
     pub fn read_integer(&mut self) -> Option<isize> {
-        self.read_word()?.parse().ok()
+        self.read_integer_with_word()?.1
+    }
+
+    /// Same as [`Stdin::read_integer()`] but also returns the word read, so that a parse error can be reported.
+    /// A word longer than the limit (see [`Self::set_max_token_len()`]) is a parse error, and only its beginning is returned.
+    /// `None` is returned only if EOF.
+    pub fn read_integer_with_word(&mut self) -> Option<(String, Option<isize>)> {
+        self.read_integer_with_overflow(NumberOverflow::Ignore)
+    }
+
+    /// Same as [`Stdin::read_integer_with_word()`] but an integer out of the range of `isize` is handled by `overflow`.
+    pub fn read_integer_with_overflow(
+        &mut self,
+        overflow: NumberOverflow,
+    ) -> Option<(String, Option<isize>)> {
+        let (word, is_truncated) = self.read_word()?;
+        let n = if is_truncated {
+            None
+        } else {
+            overflow.parse(&word)
+        };
+        Some((word, n))
     }
 }
 
@@ -127,7 +591,7 @@ mod tests {
         assert_eq!(Some('l'), stdin.read_char());
         assert_eq!(Some('l'), stdin.read_char());
         assert_eq!(Some('o'), stdin.read_char());
-        assert_eq!(Some("abc".to_string()), stdin.read_word());
+        assert_eq!(Some(("abc".to_string(), false)), stdin.read_word());
         assert_eq!(None, stdin.read_integer());
         assert_eq!(Some(-100), stdin.read_integer());
         assert_eq!(Some(15), stdin.read_integer());
@@ -145,7 +609,7 @@ mod tests {
         assert_eq!(Some('に'), stdin.read_char());
         assert_eq!(Some('ち'), stdin.read_char());
         assert_eq!(Some('は'), stdin.read_char());
-        assert_eq!(Some("🌙🌱🌸".to_string()), stdin.read_word());
+        assert_eq!(Some(("🌙🌱🌸".to_string(), false)), stdin.read_word());
         assert_eq!(None, stdin.read_integer());
         assert_eq!(Some(-100), stdin.read_integer());
         assert_eq!(Some(15), stdin.read_integer());
@@ -154,8 +618,334 @@ mod tests {
         assert_eq!(Some('あ'), stdin.read_char());
         assert_eq!(Some('a'), stdin.read_char());
         assert_eq!(Some('a'), stdin.read_char());
-        assert_eq!(Some("あ".to_owned()), stdin.read_word());
+        assert_eq!(Some(("あ".to_owned(), false)), stdin.read_word());
         assert_eq!(None, stdin.read_char());
         assert_eq!(None, stdin.read_word());
     }
+
+    #[test]
+    fn test_malformed_utf8() {
+        //a stray continuation byte, an invalid lead byte, and an invalid continuation byte, which is read as the next character
+        let mut stdin = Stdin::new_with_bytes(b"a\x80b\xFFc\xE3\x41\x42d");
+        assert_eq!(Some('a'), stdin.read_char());
+        assert_eq!(Some(char::REPLACEMENT_CHARACTER), stdin.read_char());
+        assert_eq!(Some('b'), stdin.read_char());
+        assert_eq!(Some(char::REPLACEMENT_CHARACTER), stdin.read_char());
+        assert_eq!(Some('c'), stdin.read_char());
+        assert_eq!(Some(char::REPLACEMENT_CHARACTER), stdin.read_char());
+        assert_eq!(Some('A'), stdin.read_char());
+        assert_eq!(Some('B'), stdin.read_char());
+        assert_eq!(Some('d'), stdin.read_char());
+        assert_eq!(None, stdin.read_char());
+
+        //the same characters as `String::from_utf8_lossy()`, also from a reader
+        let bytes: &[u8] = b"\xE3\x81\xF0\x9F\x98\n\xED\xA0\x80\xC0\xAF\xE3\x81\x82";
+        let expected: Vec<char> = String::from_utf8_lossy(bytes).chars().collect();
+        for mut stdin in [
+            Stdin::new_with_bytes(bytes),
+            Stdin {
+                stdin: Source::Reader(Box::new(bytes)),
+                ..Stdin::new_with_bytes(&[])
+            },
+        ] {
+            let mut chars = vec![];
+            while let Some(c) = stdin.next() {
+                chars.push(c);
+            }
+            assert_eq!(expected, chars);
+            assert_eq!(bytes.len(), stdin.num_bytes_read());
+        }
+
+        //truncated by EOF
+        let mut stdin = Stdin::new_with_bytes(b"1 \xF0\x9F");
+        assert_eq!(Some(1), stdin.read_integer());
+        assert_eq!(Some(char::REPLACEMENT_CHARACTER), stdin.read_char());
+        assert_eq!(None, stdin.read_char());
+    }
+
+    #[test]
+    fn test_reset() {
+        let mut stdin = Stdin::new_with_string("12 ab");
+        assert_eq!(Some(12), stdin.read_integer());
+        stdin.reset_with_bytes("x".as_bytes());
+        assert_eq!(0, stdin.num_bytes_read());
+        assert_eq!(None, stdin.last_terminator());
+        assert_eq!(Some('x'), stdin.read_char());
+        assert_eq!(None, stdin.read_char());
+        //EOF is forgotten
+        stdin.reset_with_bytes("7".as_bytes());
+        assert_eq!(Some(7), stdin.read_integer());
+        //the real stdin is read again, while the buffer is kept for the next input
+        let capacity = match &stdin.stdin {
+            Source::Bytes(buf) => buf.capacity(),
+            _ => unreachable!(),
+        };
+        stdin.reset();
+        assert!(matches!(stdin.stdin, Source::Stdin));
+        stdin.reset_with_bytes("y".as_bytes());
+        assert!(matches!(&stdin.stdin, Source::Bytes(buf) if buf.capacity() == capacity));
+        assert_eq!(Some('y'), stdin.read_char());
+    }
+
+    #[test]
+    fn test_number_overflow() {
+        use NumberOverflow::*;
+
+        for overflow in [Ignore, Saturate, Bigmod] {
+            assert_eq!(Some(-7), overflow.parse("-7"));
+            assert_eq!(Some(isize::MAX), overflow.parse(&isize::MAX.to_string()));
+            assert_eq!(Some(isize::MIN), overflow.parse(&isize::MIN.to_string()));
+            assert_eq!(None, overflow.parse("abc"));
+            assert_eq!(None, overflow.parse("99999999999999999999x"));
+            assert_eq!(None, overflow.parse("-"));
+        }
+
+        let max_plus_one = (isize::MAX as u128 + 1).to_string();
+        assert_eq!(None, Ignore.parse(&max_plus_one));
+        assert_eq!(Some(isize::MAX), Saturate.parse(&max_plus_one));
+        assert_eq!(Some(0), Bigmod.parse(&max_plus_one));
+        assert_eq!(Some(0), Bigmod.parse(&format!("+{}", max_plus_one)));
+
+        let min_minus_one = format!("-{}", isize::MAX as u128 + 2);
+        assert_eq!(None, Ignore.parse(&min_minus_one));
+        assert_eq!(Some(isize::MIN), Saturate.parse(&min_minus_one));
+        assert_eq!(Some(-1), Bigmod.parse(&min_minus_one));
+
+        //2^64 + 5
+        assert_eq!(Some(5), Bigmod.parse("18446744073709551621"));
+        assert_eq!(Some(-5), Bigmod.parse("-18446744073709551621"));
+
+        //a truncated word is a parse error under every policy
+        let mut stdin = Stdin::new_with_string("99999999999999999999 1");
+        stdin.set_max_token_len(5);
+        assert_eq!(
+            Some(("99999".to_string(), None)),
+            stdin.read_integer_with_overflow(Saturate)
+        );
+        assert_eq!(Some(1), stdin.read_integer());
+    }
+
+    #[test]
+    fn test_max_token_len() {
+        //10 MB of digits
+        let digits = "1".repeat(10_000_000);
+        let mut stdin = Stdin::new_with_string(&format!("{} 42\n", digits));
+        let (word, value) = stdin.read_integer_with_word().unwrap();
+        assert_eq!(None, value);
+        assert_eq!(DEFAULT_MAX_TOKEN_LEN, word.len());
+        assert!(word.capacity() <= 4 * DEFAULT_MAX_TOKEN_LEN);
+        //the rest of the word is skipped
+        assert_eq!(Some(' '), stdin.last_terminator());
+        assert_eq!(Some(42), stdin.read_integer());
+        assert_eq!(digits.len() + 4, stdin.num_bytes_read());
+        assert_eq!(None, stdin.read_integer());
+
+        let mut stdin = Stdin::new_with_string("123 1234 🌙🌱🌸 x");
+        stdin.set_max_token_len(3);
+        assert_eq!(Some(123), stdin.read_integer());
+        assert_eq!(
+            Some(("123".to_string(), None)),
+            stdin.read_integer_with_word()
+        );
+        assert_eq!(Some(("🌙🌱🌸".to_string(), false)), stdin.read_word());
+        assert_eq!(Some('x'), stdin.read_char());
+        //a word truncated by EOF
+        let mut stdin = Stdin::new_with_string("12345");
+        stdin.set_max_token_len(2);
+        assert_eq!(
+            Some(("12".to_string(), None)),
+            stdin.read_integer_with_word()
+        );
+        assert_eq!(None, stdin.last_terminator());
+        assert_eq!(None, stdin.read_char());
+    }
+
+    #[test]
+    fn test_resume() {
+        let mut stdin = Stdin::new_with_string("12 ab");
+        assert_eq!(Some(12), stdin.read_integer());
+        let (n, is_eof, terminator) = (
+            stdin.num_bytes_read(),
+            stdin.is_eof(),
+            stdin.last_terminator(),
+        );
+
+        let mut resumed = Stdin::new_with_string("12 ab");
+        assert_eq!(Ok(()), resumed.resume(n, is_eof, terminator));
+        assert_eq!(3, resumed.num_bytes_read());
+        assert_eq!(Some(' '), resumed.last_terminator());
+        assert_eq!(Some('a'), resumed.read_char());
+
+        let mut short = Stdin::new_with_string("1");
+        assert_eq!(
+            Err("the input has only 1 bytes but 3 bytes had been consumed".to_string()),
+            short.resume(3, false, None)
+        );
+    }
+
+    #[test]
+    fn test_consumption() {
+        let mut stdin = Stdin::new_with_string(" 42\nabc 🍄");
+        assert_eq!(
+            Some(("42".to_string(), Some(42))),
+            stdin.read_integer_with_word()
+        );
+        assert_eq!(4, stdin.num_bytes_read());
+        assert_eq!(Some('\n'), stdin.last_terminator());
+        assert_eq!(
+            Some(("abc".to_string(), None)),
+            stdin.read_integer_with_word()
+        );
+        assert_eq!(8, stdin.num_bytes_read());
+        assert_eq!(Some(' '), stdin.last_terminator());
+        assert_eq!(Some('🍄'), stdin.read_char());
+        assert_eq!(12, stdin.num_bytes_read());
+        assert_eq!(None, stdin.read_integer_with_word());
+    }
+
+    #[test]
+    fn test_max_bytes() {
+        let mut stdin = Stdin::new_with_string("12 345 é");
+        stdin.set_max_bytes(Some(5));
+        assert_eq!(Some(12), stdin.read_integer());
+        assert!(!stdin.is_capped());
+        //read as if the input ended there
+        assert_eq!(Some(34), stdin.read_integer());
+        assert!(stdin.is_capped());
+        assert!(stdin.is_eof());
+        assert_eq!(None, stdin.read_char());
+        assert_eq!(5, stdin.num_bytes_read());
+
+        //a character cut in the middle, from an endless reader
+        stdin.reset_with_reader(Box::new(io::repeat(0xc3)));
+        assert!(!stdin.is_capped());
+        stdin.set_max_bytes(Some(1));
+        assert_eq!(Some(char::REPLACEMENT_CHARACTER), stdin.read_char());
+        assert_eq!(None, stdin.read_char());
+        assert_eq!(1, stdin.num_bytes_read());
+
+        //a non-blocking input doesn't wait for more bytes past the limit
+        let mut stdin = Stdin::new_non_blocking();
+        stdin.set_max_bytes(Some(2));
+        stdin.feed(b"ab");
+        assert_eq!(Some('a'), stdin.read_char());
+        assert_eq!(Some('b'), stdin.read_char());
+        assert!(stdin.can_read_word());
+        assert_eq!(None, stdin.read_integer());
+    }
+
+    #[test]
+    fn test_non_blocking() {
+        let mut stdin = Stdin::new_non_blocking();
+        assert!(!stdin.can_read_char());
+        assert!(!stdin.can_read_word());
+
+        stdin.feed(b" 4");
+        assert!(stdin.can_read_char());
+        //more digits may follow
+        assert!(!stdin.can_read_word());
+        stdin.feed(b"2 ");
+        assert!(stdin.can_read_word());
+        assert_eq!(Some(42), stdin.read_integer());
+        assert!(!stdin.can_read_char());
+
+        //a character split between two feeds
+        let mushroom = "🍄".as_bytes();
+        stdin.feed(&mushroom[..2]);
+        assert!(!stdin.can_read_char());
+        stdin.feed(&mushroom[2..]);
+        assert!(stdin.can_read_char());
+        assert!(!stdin.can_read_word());
+        assert_eq!(Some('🍄'), stdin.read_char());
+        assert!(!stdin.is_eof());
+
+        //a closed input reaches EOF instead of blocking
+        stdin.feed(b"7");
+        stdin.close();
+        assert!(stdin.can_read_word());
+        assert_eq!(Some(7), stdin.read_integer());
+        assert!(stdin.can_read_char());
+        assert_eq!(None, stdin.read_char());
+
+        //the other inputs never block
+        assert!(Stdin::new_with_string("").can_read_word());
+        assert!(Stdin::new_with_string("1").can_read_word());
+    }
+
+    #[test]
+    fn test_recording() {
+        let mut stdin = Stdin::new_non_blocking();
+        stdin.set_recording(true);
+        stdin.feed(b"12 3");
+        assert_eq!(Some(12), stdin.read_integer());
+        //what has been fed but not read is not recorded
+        assert!(!stdin.can_read_word());
+        assert_eq!(b"12 ", stdin.recorded());
+        stdin.feed(b"0\nx");
+        assert_eq!(Some(30), stdin.read_integer());
+        assert_eq!(Some('x'), stdin.read_char());
+        stdin.close();
+        assert_eq!(None, stdin.read_char());
+        let recorded = stdin.recorded().to_vec();
+        assert_eq!(b"12 30\nx", recorded.as_slice());
+
+        //the recording is read in the same way
+        let mut replay = Stdin::new_with_bytes(&recorded);
+        assert_eq!(Some(12), replay.read_integer());
+        assert_eq!(Some(30), replay.read_integer());
+        assert_eq!(Some('x'), replay.read_char());
+        assert_eq!(None, replay.read_char());
+
+        //a reset clears the recording but keeps recording
+        stdin.reset_non_blocking(b"7 ");
+        assert!(stdin.recorded().is_empty());
+        assert_eq!(Some(7), stdin.read_integer());
+        assert_eq!(b"7 ", stdin.recorded());
+        assert!(!stdin.can_read_char());
+        stdin.set_recording(false);
+        assert!(stdin.recorded().is_empty());
+    }
+
+    #[test]
+    fn test_newlines() {
+        //`in(char)` skips the line endings either way
+        for newlines in [InputNewlines::Raw, InputNewlines::Lf] {
+            let mut stdin = Stdin::new_with_string("12\r\n30\rx\r\n\r\n");
+            stdin.set_newlines(newlines);
+            assert_eq!(Some(12), stdin.read_integer());
+            assert_eq!(Some(30), stdin.read_integer());
+            assert_eq!(Some('x'), stdin.read_char());
+            assert_eq!(None, stdin.read_char());
+            assert_eq!(4, stdin.num_cr_line_endings());
+            assert_eq!(12, stdin.num_bytes_read());
+        }
+
+        let mut stdin = Stdin::new_with_string("12\r\n30\r\r\n4\n");
+        assert_eq!(Some(12), stdin.read_integer());
+        assert_eq!(Some('\r'), stdin.last_terminator());
+        assert_eq!(Some('\n'), stdin.next());
+        assert_eq!(Some(30), stdin.read_integer());
+        assert_eq!(Some('\r'), stdin.next());
+        assert_eq!(Some('\n'), stdin.next());
+
+        let mut stdin = Stdin::new_with_string("12\r\n30\r\r\n4\n");
+        stdin.set_newlines(InputNewlines::Lf);
+        assert_eq!(Some(12), stdin.read_integer());
+        assert_eq!(Some('\n'), stdin.last_terminator());
+        assert_eq!(Some(30), stdin.read_integer());
+        assert_eq!(Some('\n'), stdin.last_terminator());
+        //a lone CR followed by a CRLF is two line endings
+        assert_eq!(Some('\n'), stdin.next());
+        assert_eq!(Some('4'), stdin.next());
+        assert_eq!(Some('\n'), stdin.next());
+        assert_eq!(None, stdin.next());
+        assert_eq!(3, stdin.num_cr_line_endings());
+        assert_eq!(11, stdin.num_bytes_read());
+
+        //the mode is kept over a reset but the count is not
+        stdin.reset_with_bytes(b"\r\n");
+        assert_eq!(0, stdin.num_cr_line_endings());
+        assert_eq!(Some('\n'), stdin.next());
+        assert_eq!(None, stdin.next());
+    }
 }
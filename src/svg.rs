@@ -0,0 +1,212 @@
+/*!
+SVG rendering of a program and its execution (`--svg`).
+
+Each codel is a `<rect>` with a tooltip (`<title>`) giving its color, block ID and block size.
+If a trace is given, the executed path is drawn as a `<polyline>` through the centers of the codels from which the interpreter moved, and each executed command is labeled at the codel where the transition ended.
+*/
+
+use std::fmt::Write;
+
+use super::codel::Codel;
+use super::image::Image;
+use super::trace::TraceEvent;
+
+/// Options of [`render()`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SvgOptions {
+    /// Size of a codel in pixels.
+    pub scale: usize,
+    /// Whether the path layer (the path and the command labels) is drawn.
+    pub include_path: bool,
+    /// Maximum number of command labels (the first ones in the execution are drawn).
+    pub max_labels: usize,
+}
+
+impl Default for SvgOptions {
+    fn default() -> Self {
+        Self {
+            scale: 20,
+            include_path: true,
+            max_labels: 100,
+        }
+    }
+}
+
+fn color_name(codel: &Codel) -> String {
+    match codel {
+        Codel::Other(p) => format!("#{:02X}{:02X}{:02X}", p.r, p.g, p.b),
+        c => format!("{:?}", c),
+    }
+}
+
+/// Renders `img` (and the path in `trace` if given) as an SVG document.
+pub fn render(img: &Image, trace: Option<&[TraceEvent]>, options: &SvgOptions) -> String {
+    let scale = options.scale;
+    let mut s = String::new();
+    writeln!(
+        s,
+        r#"<svg xmlns="http://www.w3.org/2000/svg" width="{}" height="{}" viewBox="0 0 {} {}">"#,
+        img.width() * scale,
+        img.height() * scale,
+        img.width() * scale,
+        img.height() * scale
+    )
+    .unwrap();
+
+    s.push_str("<g id=\"codels\" shape-rendering=\"crispEdges\">\n");
+    for i in 0..img.height() {
+        for j in 0..img.width() {
+            let codel = img.get_codel_at_unchecked((i, j));
+            let (r, g, b) = codel.rgb();
+            writeln!(
+                s,
+                r##"<rect x="{}" y="{}" width="{}" height="{}" fill="#{:02X}{:02X}{:02X}"><title>({}, {}) {}, block {}, size {}</title></rect>"##,
+                j * scale,
+                i * scale,
+                scale,
+                scale,
+                r,
+                g,
+                b,
+                i,
+                j,
+                color_name(codel),
+                img.get_block_id_at_unchecked((i, j)),
+                img.get_block_size_at_unchecked((i, j))
+            )
+            .unwrap();
+        }
+    }
+    s.push_str("</g>\n");
+
+    if let Some(trace) = trace.filter(|_| options.include_path) {
+        let center = |(i, j): (usize, usize)| (j * scale + scale / 2, i * scale + scale / 2);
+
+        let mut points = vec![];
+        let mut labels = vec![];
+        let mut pending_command = None;
+        for event in trace {
+            match event {
+                TraceEvent::State { pos, .. } => {
+                    if let Some(command) = pending_command.take() {
+                        labels.push((*pos, command));
+                    }
+                    points.push(center(*pos));
+                }
+                TraceEvent::Command(command) => pending_command = Some(*command),
+                TraceEvent::Intervention { .. } => {}
+            }
+        }
+
+        s.push_str("<g id=\"path\">\n");
+        writeln!(
+            s,
+            r##"<polyline points="{}" fill="none" stroke="#808080" stroke-width="{}" stroke-opacity="0.8"/>"##,
+            points
+                .iter()
+                .map(|(x, y)| format!("{},{}", x, y))
+                .collect::<Vec<_>>()
+                .join(" "),
+            (scale / 8).max(1)
+        )
+        .unwrap();
+        for (pos, command) in labels.into_iter().take(options.max_labels) {
+            let (x, y) = center(pos);
+            writeln!(
+                s,
+                r#"<text x="{}" y="{}" font-size="{}" text-anchor="middle" dominant-baseline="middle">{:?}</text>"#,
+                x,
+                y,
+                (scale / 3).max(1),
+                command
+            )
+            .unwrap();
+        }
+        s.push_str("</g>\n");
+    }
+
+    s.push_str("</svg>\n");
+    s
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::cc::CC;
+    use super::super::command::Command;
+    use super::super::dp::DP;
+    use super::super::image::Pixel;
+    use super::*;
+
+    fn count(s: &str, pattern: &str) -> usize {
+        s.matches(pattern).count()
+    }
+
+    fn state(pos: (usize, usize)) -> TraceEvent {
+        TraceEvent::State {
+            pos,
+            dp: DP::Right,
+            cc: CC::Left,
+        }
+    }
+
+    #[test]
+    fn test_render() {
+        let (r, dr) = (Codel::Red, Codel::DarkRed);
+        let other = Codel::Other(Pixel { r: 1, g: 2, b: 171 });
+        let img = Image::from_codel_grid(vec![vec![r, r, dr], vec![r, Codel::Black, other]]);
+
+        let svg = render(&img, None, &SvgOptions::default());
+        assert!(
+            svg.starts_with(r#"<svg xmlns="http://www.w3.org/2000/svg" width="60" height="40""#)
+        );
+        assert!(svg.ends_with("</svg>\n"));
+        assert_eq!(6, count(&svg, "<rect "));
+        assert_eq!(6, count(&svg, "<title>"));
+        assert!(svg.contains(
+            r##"<rect x="20" y="0" width="20" height="20" fill="#FF0000"><title>(0, 1) Red, block 0, size 3</title></rect>"##
+        ));
+        assert!(svg.contains("<title>(1, 2) #0102AB, block 3, size 1</title>"));
+        assert_eq!(0, count(&svg, "<polyline "));
+
+        let trace = vec![
+            state((0, 0)),
+            TraceEvent::Command(Command::Push),
+            state((0, 2)),
+            TraceEvent::Command(Command::Pop),
+            state((0, 0)),
+            TraceEvent::Command(Command::Push),
+            state((0, 2)),
+        ];
+        let options = SvgOptions {
+            scale: 10,
+            ..Default::default()
+        };
+        let svg = render(&img, Some(&trace), &options);
+        assert_eq!(6, count(&svg, "<rect "));
+        assert_eq!(1, count(&svg, "<polyline "));
+        assert!(svg.contains(r#"points="5,5 25,5 5,5 25,5""#));
+        assert_eq!(3, count(&svg, "<text "));
+        assert!(svg.contains(r#"<text x="25" y="5" font-size="3" text-anchor="middle" dominant-baseline="middle">Push</text>"#));
+
+        let svg = render(
+            &img,
+            Some(&trace),
+            &SvgOptions {
+                max_labels: 1,
+                ..options.clone()
+            },
+        );
+        assert_eq!(1, count(&svg, "<text "));
+
+        let svg = render(
+            &img,
+            Some(&trace),
+            &SvgOptions {
+                include_path: false,
+                ..options
+            },
+        );
+        assert_eq!(0, count(&svg, "<polyline "));
+        assert_eq!(0, count(&svg, "<text "));
+    }
+}
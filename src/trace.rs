@@ -0,0 +1,127 @@
+use std::fmt::{self, Display, Formatter};
+
+use super::cc::CC;
+use super::command::Command;
+use super::dp::DP;
+use super::observer::AppliedIntervention;
+
+/// An event of the execution trace.
+///
+/// The trace consists of exactly the events printed in verbose mode, so tests and tools can inspect the execution without parsing text.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TraceEvent {
+    /// The interpreter is about to move from the codel at `pos` (one iteration).
+    State { pos: (usize, usize), dp: DP, cc: CC },
+    /// A command was executed on the transition just made.
+    Command(Command),
+    /// An observer altered `command` of the transition about to be made (`--allow-interventions`).
+    /// Unless it was skipped, this is followed by the command actually executed.
+    Intervention {
+        command: Command,
+        applied: AppliedIntervention,
+    },
+}
+
+/// An input or output event (`--io-trace`).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum IoEvent {
+    /// `in(number)` read `word`, which is `value` if it is an integer (otherwise the command is ignored).
+    /// `terminator` is the whitespace which ended the word (`None` if EOF).
+    InNumber {
+        word: String,
+        value: Option<isize>,
+        num_bytes: usize,
+        terminator: Option<char>,
+    },
+    InChar {
+        c: char,
+        num_bytes: usize,
+    },
+    /// `in(number)` or `in(char)` was ignored as no input was left.
+    EndOfInput {
+        command: Command,
+    },
+    OutNumber(isize),
+    OutChar(char),
+}
+
+impl Display for IoEvent {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            IoEvent::InNumber {
+                word,
+                value: Some(_),
+                num_bytes,
+                terminator,
+            } => {
+                write!(
+                    f,
+                    "in(number) read {:?} (consumed {} bytes, ",
+                    word, num_bytes
+                )?;
+                match terminator {
+                    Some(c) => write!(f, "next char is {:?})", c),
+                    None => write!(f, "end of input)"),
+                }
+            }
+            IoEvent::InNumber {
+                word,
+                value: None,
+                num_bytes,
+                ..
+            } => write!(
+                f,
+                "in(number) ignored: parse error at {:?} (consumed {} bytes)",
+                word, num_bytes
+            ),
+            IoEvent::InChar { c, num_bytes } => {
+                write!(f, "in(char) read {:?} (consumed {} bytes)", c, num_bytes)
+            }
+            IoEvent::EndOfInput { command } => write!(
+                f,
+                "{} ignored: end of input",
+                match command {
+                    Command::InNumber => "in(number)",
+                    _ => "in(char)",
+                }
+            ),
+            IoEvent::OutNumber(x) => write!(f, "out(number) {}", x),
+            IoEvent::OutChar(c) => write!(f, "out(char) {:?}", c),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_io_event() {
+        let e = IoEvent::InNumber {
+            word: "42".to_string(),
+            value: Some(42),
+            num_bytes: 3,
+            terminator: Some('\n'),
+        };
+        assert_eq!(
+            "in(number) read \"42\" (consumed 3 bytes, next char is '\\n')",
+            e.to_string()
+        );
+        let e = IoEvent::InNumber {
+            word: "abc".to_string(),
+            value: None,
+            num_bytes: 4,
+            terminator: None,
+        };
+        assert_eq!(
+            "in(number) ignored: parse error at \"abc\" (consumed 4 bytes)",
+            e.to_string()
+        );
+        let e = IoEvent::EndOfInput {
+            command: Command::InChar,
+        };
+        assert_eq!("in(char) ignored: end of input", e.to_string());
+        assert_eq!("out(char) 'H'", IoEvent::OutChar('H').to_string());
+        assert_eq!("out(number) -1", IoEvent::OutNumber(-1).to_string());
+    }
+}
@@ -0,0 +1,413 @@
+/*!
+Comparison of two recorded path traces (`trace-diff`).
+
+A trace file is the path trace printed to stderr in verbose mode (`--verbose` or `--trace-after`), e.g. saved by `2> a.trace`.
+Lines which are not part of the path trace (such as the image printed in verbose mode) are skipped, so the whole stderr can be given as is.
+A summarized slide across white (`slide (0, 1) -> (0, 5) [4 codels, DP:Right CC:Left]`, see [`Slide`]) is expanded into its steps, so a trace printed with `-v` can be compared with one printed with `-vvv`.
+
+The traces are aligned step by step.
+After the first divergence, they are said to re-converge at the first pair of steps from which [`WINDOW`] steps (or the rest of both traces, if shorter) are identical.
+*/
+
+use std::fmt::Write;
+
+use rustc_hash::FxHashMap;
+
+use super::cc::CC;
+use super::command::Command;
+use super::coord;
+use super::dp::DP;
+
+/// Number of steps shown before and after the first divergence.
+pub const CONTEXT: usize = 5;
+
+/// Number of identical steps required to regard two traces as re-converged.
+pub const WINDOW: usize = 5;
+
+const COMMANDS: [Command; 17] = [
+    Command::Push,
+    Command::Pop,
+    Command::Add,
+    Command::Subtract,
+    Command::Multiply,
+    Command::Divide,
+    Command::Mod,
+    Command::Not,
+    Command::Greater,
+    Command::Pointer,
+    Command::Switch,
+    Command::Duplicate,
+    Command::Roll,
+    Command::InNumber,
+    Command::InChar,
+    Command::OutNumber,
+    Command::OutChar,
+];
+
+/// One iteration: the state before the move and the command executed on it (if any).
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Step {
+    pub pos: (usize, usize),
+    pub dp: DP,
+    pub cc: CC,
+    pub commands: Vec<Command>,
+}
+
+impl Step {
+    /// Same format as the path trace.
+    pub(crate) fn to_line(&self) -> String {
+        let mut s = format!(
+            "{:12} DP:{:5} CC:{:?}",
+            format!("{:?}", self.pos),
+            format!("{:?}", self.dp),
+            self.cc
+        );
+        for command in &self.commands {
+            write!(s, " {:?}", command).unwrap();
+        }
+        s
+    }
+}
+
+/// Consecutive steps of a slide across white which move in the same direction, printed as one line of the path trace below `-vvv`.
+/// A move across an edge of a torus is not part of a `Slide`, so the positions of the steps follow from the first one.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Slide {
+    /// Position at the first step.
+    pub from: (usize, usize),
+    pub dp: DP,
+    pub cc: CC,
+    /// Number of steps (i.e. codels moved).
+    pub num_codels: usize,
+}
+
+impl Slide {
+    /// Position after the last step, or `None` if it cannot be represented.
+    pub fn to(&self) -> Option<(usize, usize)> {
+        let (di, dj) = self.dp.get_displacement();
+        let n = isize::try_from(self.num_codels).ok()?;
+        coord::offset(
+            self.from,
+            (di.checked_mul(n)?, dj.checked_mul(n)?),
+            usize::MAX,
+            usize::MAX,
+        )
+    }
+
+    /// Returns `slide (0, 1) -> (0, 5) [4 codels, DP:Right CC:Left]`.
+    pub(crate) fn to_line(&self) -> String {
+        format!(
+            "slide {:?} -> {:?} [{} codels, DP:{:?} CC:{:?}]",
+            self.from,
+            self.to().unwrap_or_default(),
+            self.num_codels,
+            self.dp,
+            self.cc
+        )
+    }
+
+    /// Expands the slide into its steps.
+    fn steps(&self) -> impl Iterator<Item = Step> + '_ {
+        let delta = self.dp.get_displacement();
+        (0..self.num_codels).scan(self.from, move |pos, _| {
+            let step = Step {
+                pos: *pos,
+                dp: self.dp,
+                cc: self.cc,
+                commands: vec![],
+            };
+            *pos = coord::offset(*pos, delta, usize::MAX, usize::MAX)?;
+            Some(step)
+        })
+    }
+}
+
+/// Parses `(i, j)`, returning the rest.
+fn parse_pos(s: &str) -> Option<((usize, usize), &str)> {
+    let (pos, rest) = s.strip_prefix('(')?.split_once(')')?;
+    let (i, j) = pos.split_once(',')?;
+    Some(((i.trim().parse().ok()?, j.trim().parse().ok()?), rest))
+}
+
+/// Parses `DP:Right CC:Left` at the start of `words`.
+fn parse_dp_cc<'a>(words: &mut impl Iterator<Item = &'a str>) -> Option<(DP, CC)> {
+    let dp = words.next()?.strip_prefix("DP:")?;
+    let dp = [DP::Right, DP::Down, DP::Left, DP::Up]
+        .into_iter()
+        .find(|d| format!("{:?}", d) == dp)?;
+    let cc = words.next()?.strip_prefix("CC:")?;
+    let cc = [CC::Left, CC::Right]
+        .into_iter()
+        .find(|c| format!("{:?}", c) == cc)?;
+    Some((dp, cc))
+}
+
+/// Parses `(i, j)       DP:Right CC:Left`.
+fn parse_state(line: &str) -> Option<((usize, usize), DP, CC)> {
+    let (pos, rest) = parse_pos(line)?;
+    let mut words = rest.split_whitespace();
+    let (dp, cc) = parse_dp_cc(&mut words)?;
+    if words.next().is_some() {
+        return None;
+    }
+    Some((pos, dp, cc))
+}
+
+/// Parses `slide (0, 1) -> (0, 5) [4 codels, DP:Right CC:Left]`.
+fn parse_slide(line: &str) -> Option<Slide> {
+    let (from, rest) = parse_pos(line.strip_prefix("slide ")?)?;
+    let (to, rest) = parse_pos(rest.strip_prefix(" -> ")?)?;
+    let rest = rest.strip_prefix(" [")?.strip_suffix(']')?;
+    let mut words = rest.split_whitespace();
+    let num_codels = words.next()?.parse().ok()?;
+    if words.next()? != "codels," {
+        return None;
+    }
+    let (dp, cc) = parse_dp_cc(&mut words)?;
+    let slide = Slide {
+        from,
+        dp,
+        cc,
+        num_codels,
+    };
+    (words.next().is_none() && (slide.to() == Some(to))).then_some(slide)
+}
+
+/// Parses `    Push`.
+fn parse_command(line: &str) -> Option<Command> {
+    let name = line.strip_prefix("    ")?.trim_end();
+    COMMANDS.into_iter().find(|c| format!("{:?}", c) == name)
+}
+
+/// Extracts the steps from the content of a trace file.
+pub fn parse(s: &str) -> Vec<Step> {
+    let mut ret: Vec<Step> = vec![];
+    for line in s.lines() {
+        if let Some((pos, dp, cc)) = parse_state(line) {
+            ret.push(Step {
+                pos,
+                dp,
+                cc,
+                commands: vec![],
+            });
+        } else if let Some(slide) = parse_slide(line) {
+            ret.extend(slide.steps());
+        } else if let Some(command) = parse_command(line) {
+            //a command before the first state is from a step not recorded (e.g. cut off by `--trace-after`)
+            if let Some(step) = ret.last_mut() {
+                step.commands.push(command);
+            }
+        }
+    }
+    ret
+}
+
+/// Result of comparing two traces.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TraceDiff {
+    /// Index of the first step which differs (or exists in only one trace), `None` if the traces are identical.
+    pub divergence: Option<usize>,
+    /// Indices of the steps in each trace from which they are identical again.
+    pub reconvergence: Option<(usize, usize)>,
+}
+
+impl TraceDiff {
+    pub fn new(a: &[Step], b: &[Step]) -> Self {
+        let divergence = (0..a.len().max(b.len())).find(|&i| a.get(i) != b.get(i));
+        let reconvergence = divergence.and_then(|d| Self::find_reconvergence(a, b, d));
+        Self {
+            divergence,
+            reconvergence,
+        }
+    }
+
+    /// Returns the first pair `(i, j)` (with the smallest `i`, then `j`) after `d` from which the traces agree for [`WINDOW`] steps.
+    fn find_reconvergence(a: &[Step], b: &[Step], d: usize) -> Option<(usize, usize)> {
+        let mut indices: FxHashMap<&Step, Vec<usize>> = FxHashMap::default();
+        for (j, step) in b.iter().enumerate().skip(d + 1) {
+            indices.entry(step).or_default().push(j);
+        }
+        (d + 1..a.len()).find_map(|i| {
+            indices.get(&a[i])?.iter().find_map(|&j| {
+                let n = WINDOW.min(a.len() - i).min(b.len() - j);
+                (a[i..i + n] == b[j..j + n]).then_some((i, j))
+            })
+        })
+    }
+
+    /// Human-readable report, where `names` are the names of the two traces.
+    pub fn report(&self, a: &[Step], b: &[Step], names: (&str, &str)) -> String {
+        let mut s = String::new();
+        writeln!(s, "{}: {} steps", names.0, a.len()).unwrap();
+        writeln!(s, "{}: {} steps", names.1, b.len()).unwrap();
+        let Some(d) = self.divergence else {
+            s.push_str("traces are identical\n");
+            return s;
+        };
+
+        //steps are counted from 1 as in `--trace-after`
+        writeln!(s, "first divergence at step {}", d + 1).unwrap();
+        for (name, steps) in [(names.0, a), (names.1, b)] {
+            writeln!(s, "--- {}", name).unwrap();
+            for i in d.saturating_sub(CONTEXT)..=d + CONTEXT {
+                let marker = if i == d { '>' } else { ' ' };
+                match steps.get(i) {
+                    Some(step) => writeln!(s, "{} {:6} {}", marker, i + 1, step.to_line()).unwrap(),
+                    None => {
+                        writeln!(s, "{} {:6} (end of trace)", marker, i + 1).unwrap();
+                        break;
+                    }
+                }
+            }
+        }
+
+        match self.reconvergence {
+            Some((i, j)) => writeln!(
+                s,
+                "re-converges at step {} of {} and step {} of {}",
+                i + 1,
+                names.0,
+                j + 1,
+                names.1
+            )
+            .unwrap(),
+            None => s.push_str("does not re-converge\n"),
+        }
+        s
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn step(pos: (usize, usize), commands: &[Command]) -> Step {
+        Step {
+            pos,
+            dp: DP::Right,
+            cc: CC::Left,
+            commands: commands.to_vec(),
+        }
+    }
+
+    #[test]
+    fn test_parse() {
+        let s = " 0 image art\n\n(0, 0)       DP:Right CC:Left\n    Push\n(0, 7)       DP:Down  CC:Right\n    NotACommand\n(12, 3)      DP:Up    CC:Left\n";
+        assert_eq!(
+            vec![
+                step((0, 0), &[Command::Push]),
+                Step {
+                    pos: (0, 7),
+                    dp: DP::Down,
+                    cc: CC::Right,
+                    commands: vec![],
+                },
+                Step {
+                    pos: (12, 3),
+                    dp: DP::Up,
+                    cc: CC::Left,
+                    commands: vec![],
+                },
+            ],
+            parse(s)
+        );
+
+        //round trip
+        let steps = parse(s);
+        let lines = steps
+            .iter()
+            .map(|step| step.to_line().replace(" Push", "\n    Push"))
+            .collect::<Vec<_>>()
+            .join("\n");
+        assert_eq!(steps, parse(&lines));
+
+        assert_eq!(Vec::<Step>::new(), parse("    Push\n"));
+    }
+
+    #[test]
+    fn test_parse_slide() {
+        let slide = Slide {
+            from: (3, 10),
+            dp: DP::Left,
+            cc: CC::Right,
+            num_codels: 4,
+        };
+        let line = slide.to_line();
+        assert_eq!("slide (3, 10) -> (3, 6) [4 codels, DP:Left CC:Right]", line);
+        assert_eq!(Some(slide.clone()), parse_slide(&line));
+        let s = format!(
+            "(3, 11)      DP:Left  CC:Right\n{}\n(3, 6)       DP:Left  CC:Right\n",
+            line
+        );
+        assert_eq!(
+            [(3, 11), (3, 10), (3, 9), (3, 8), (3, 7), (3, 6)].to_vec(),
+            parse(&s).iter().map(|step| step.pos).collect::<Vec<_>>()
+        );
+        assert!(parse(&s)
+            .iter()
+            .all(|step| (step.dp == DP::Left) && (step.cc == CC::Right)));
+
+        //inconsistent or out of the grid
+        assert_eq!(
+            None,
+            parse_slide("slide (3, 10) -> (3, 7) [4 codels, DP:Left CC:Right]")
+        );
+        assert_eq!(
+            None,
+            parse_slide("slide (3, 2) -> (3, 0) [4 codels, DP:Left CC:Right]")
+        );
+        assert_eq!(
+            None,
+            parse_slide("slide (3, 10) -> (3, 6) [4 codels, DP:Left CC:Right] x")
+        );
+    }
+
+    #[test]
+    fn test_diff() {
+        let a = (0..20).map(|i| step((0, i), &[])).collect::<Vec<_>>();
+        assert_eq!(
+            TraceDiff {
+                divergence: None,
+                reconvergence: None,
+            },
+            TraceDiff::new(&a, &a)
+        );
+
+        //a detour of two steps in `b`
+        let mut b = a.clone();
+        b.splice(
+            8..9,
+            [step((5, 5), &[]), step((6, 6), &[]), step((7, 7), &[])],
+        );
+        let diff = TraceDiff::new(&a, &b);
+        assert_eq!(Some(8), diff.divergence);
+        assert_eq!(Some((9, 11)), diff.reconvergence);
+
+        //`b` is a prefix of `a`
+        let diff = TraceDiff::new(&a, &a[..10]);
+        assert_eq!(Some(10), diff.divergence);
+        assert_eq!(None, diff.reconvergence);
+        let report = diff.report(&a, &a[..10], ("a", "b"));
+        assert!(report.contains(">     11 (0, 10)"));
+        assert!(report.ends_with(">     11 (end of trace)\ndoes not re-converge\n"));
+
+        //one is empty
+        let diff = TraceDiff::new(&[], &a);
+        assert_eq!(Some(0), diff.divergence);
+        assert_eq!(None, diff.reconvergence);
+        assert_eq!(
+            TraceDiff {
+                divergence: None,
+                reconvergence: None,
+            },
+            TraceDiff::new(&[], &[])
+        );
+
+        //only the commands differ
+        let mut b = a.clone();
+        b[3].commands.push(Command::Pop);
+        let diff = TraceDiff::new(&a, &b);
+        assert_eq!(Some(3), diff.divergence);
+        assert_eq!(Some((4, 4)), diff.reconvergence);
+    }
+}
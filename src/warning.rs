@@ -0,0 +1,244 @@
+/*!
+Warnings, i.e. conditions which don't stop a run but may make it differ from what is expected.
+
+Each warning is printed to stderr with the prefix `warning: ` when it is raised and collected,
+so that a library user can inspect them in [`RunResult::warnings`](super::run_result::RunResult::warnings) and `--deny-warnings` can fail the run.
+*/
+
+use std::fmt::{self, Display, Formatter};
+use std::time::Duration;
+
+use super::codel::Codel;
+use super::command::Command;
+use super::image::GridSpec;
+use super::lint::StartBlock;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Warning {
+    /// The image has no colored block but is run anyway (`--allow-empty`).
+    NoColoredBlock,
+    /// The top-left codel is black, so the run starts at `start` instead (`--lenient-start`).
+    BlackStartCodel { start: (usize, usize) },
+    /// `num_codels` codels of unknown colors are treated as `color` (`--fall-back-to-white` or `--fall-back-to-black`).
+    UnknownColorsReplaced { num_codels: usize, color: Codel },
+    /// `num_pixels` pixels are read as the colors of Piet within `tolerance` of them (`--color-tolerance`).
+    ColorsSnapped { num_pixels: usize, tolerance: u8 },
+    /// The pixels outside of the whole codels of `grid` (`--codel-grid`) are dropped: `margins` rows at the top and at the bottom, and columns at the left and at the right.
+    MarginsDropped { grid: GridSpec, margins: [usize; 4] },
+    /// The detection of the codel size didn't finish within `budget` (`--detect-budget`), so the codels are read as `grid` (`--detect-fallback`), which may be wrong.
+    DetectionTimedOut { budget: Duration, grid: GridSpec },
+    /// The canonical `colors` are all nearest to `entry` of the small palette of a GIF, so they may have been merged by quantization (see [`quantization`](super::quantization)).
+    /// `num_codels` codels have the color of the entry.
+    PaletteCollision {
+        palette_size: usize,
+        entry: usize,
+        colors: Vec<Codel>,
+        num_codels: usize,
+    },
+    /// `absent` is absent while the confusable `neighbor` has the most blocks (`num_blocks`) of the colors other than black, so they may have been merged by quantization (`--check`).
+    ColorMaybeMerged {
+        absent: Codel,
+        neighbor: Codel,
+        num_blocks: usize,
+    },
+    /// The colors `sources` (with the number of their codels) which are distinct by the nearest colors of Piet are all mapped to `target` by `--palette`, so their adjacent blocks are merged.
+    /// See [`palette_map`](super::palette_map).
+    PaletteMergesColors {
+        target: Codel,
+        sources: Vec<(Codel, usize)>,
+    },
+    /// The transitions from `from` to `to` execute `mapped` instead of `baseline` (by the nearest colors of Piet) under `--palette`, where `None` is no command,
+    /// or the blocks are `merged` into one (`--check`).
+    /// `blocks` are the transitions by the first codels of the blocks.
+    PaletteChangesTransition {
+        from: Codel,
+        to: Codel,
+        baseline: Option<Command>,
+        mapped: Option<Command>,
+        merged: bool,
+        blocks: Vec<((usize, usize), (usize, usize))>,
+    },
+    /// `command` (`Pointer` or `Switch`) at `pos` on step `step` popped `value`, whose absolute value exceeds `threshold` (`--check-runtime`).
+    /// Such an operand is almost always a bug, though it is used as is.
+    LargeOperand {
+        step: usize,
+        pos: (usize, usize),
+        command: Command,
+        value: isize,
+        threshold: usize,
+    },
+    /// All of the `num_pushes` operands of `Push` in the run (e.g. `samples`) are multiples of `factor²`, so the codel size may be `factor` times too small (`--check-runtime`).
+    /// See [`push_operands`](super::push_operands).
+    SquarePushOperands {
+        factor: usize,
+        num_pushes: usize,
+        samples: Vec<usize>,
+    },
+    /// The first `Push` of the run, on step `step`, pushes the size of the start block larger than the threshold (`--check-runtime`).
+    /// See [`lint`](super::lint) for the bridges to cut.
+    LargeStartBlock { step: usize, block: StartBlock },
+    /// A checkpoint could not be written, which doesn't stop the run (`--checkpoint-every`).
+    CheckpointFailed(String),
+}
+
+impl Display for Warning {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            Warning::NoColoredBlock => write!(
+                f,
+                "the image has no colored block, so no command can be executed"
+            ),
+            Warning::BlackStartCodel { start } => write!(
+                f,
+                "the top-left codel is black; starting at {:?} instead",
+                start
+            ),
+            Warning::UnknownColorsReplaced { num_codels, color } => write!(
+                f,
+                "{} codel(s) of unknown colors are treated as {}",
+                num_codels,
+                color_name(color)
+            ),
+            Warning::ColorsSnapped {
+                num_pixels,
+                tolerance,
+            } => write!(
+                f,
+                "{} pixel(s) of colors within `--color-tolerance {}` of the colors of Piet are read as them",
+                num_pixels, tolerance
+            ),
+            Warning::MarginsDropped { grid, margins } => write!(
+                f,
+                "the codel grid {} drops pixels outside of its whole codels (top: {}, bottom: {}, left: {}, right: {})",
+                grid, margins[0], margins[1], margins[2], margins[3]
+            ),
+            Warning::DetectionTimedOut { budget, grid } => write!(
+                f,
+                "the detection of the codel size did not finish within {} ms (`--detect-budget`), so the codels are read as `--codel-grid {}`, WHICH MAY BE WRONG; provide `--codel-size` explicitly if the program misbehaves",
+                budget.as_millis(),
+                grid
+            ),
+            Warning::PaletteCollision {
+                palette_size,
+                entry,
+                colors,
+                num_codels,
+            } => write!(
+                f,
+                "{} are all nearest to entry {} of the GIF palette of {} entries, so they may have been merged by quantization ({} codel(s) have the color of the entry)",
+                colors.iter().map(color_name).collect::<Vec<_>>().join(", "),
+                entry,
+                palette_size,
+                num_codels
+            ),
+            Warning::ColorMaybeMerged {
+                absent,
+                neighbor,
+                num_blocks,
+            } => write!(
+                f,
+                "{} is absent while the visually close {} is the most frequent color ({} blocks), so they may have been merged by quantization",
+                color_name(absent),
+                color_name(neighbor),
+                num_blocks
+            ),
+            Warning::PaletteMergesColors { target, sources } => write!(
+                f,
+                "`--palette` maps {} to {}, so their adjacent blocks are merged",
+                sources
+                    .iter()
+                    .map(|(c, n)| format!("{} ({} codel(s))", color_name(c), n))
+                    .collect::<Vec<_>>()
+                    .join(", "),
+                color_name(target)
+            ),
+            Warning::PaletteChangesTransition {
+                from,
+                to,
+                baseline,
+                mapped,
+                merged,
+                blocks,
+            } => {
+                let command_name = |c: &Option<Command>| match c {
+                    Some(c) => format!("{:?}", c),
+                    None => "no command".to_string(),
+                };
+                write!(
+                    f,
+                    "`--palette` changes the transition from {} to {} from {} to {} at {} block(s): {}",
+                    color_name(from),
+                    color_name(to),
+                    command_name(baseline),
+                    if *merged {
+                        "a merge".to_string()
+                    } else {
+                        command_name(mapped)
+                    },
+                    blocks.len(),
+                    blocks
+                        .iter()
+                        .take(MAX_LISTED_BLOCKS)
+                        .map(|(a, b)| format!("{:?} -> {:?}", a, b))
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                )?;
+                if blocks.len() > MAX_LISTED_BLOCKS {
+                    write!(f, ", ...")?;
+                }
+                Ok(())
+            }
+            Warning::LargeOperand {
+                step,
+                pos,
+                command,
+                value,
+                threshold,
+            } => write!(
+                f,
+                "{:?} at {:?} on step {} popped {} (more than {} in absolute value), which is likely a bug",
+                command, pos, step, value, threshold
+            ),
+            Warning::SquarePushOperands {
+                factor,
+                num_pushes,
+                samples,
+            } => write!(
+                f,
+                "all {} operands of Push are multiples of {} (e.g. {}), so the image may be scaled by {} and the codel size may be {} times too small",
+                num_pushes,
+                factor * factor,
+                samples.iter().map(|v| v.to_string()).collect::<Vec<_>>().join(", "),
+                factor,
+                factor
+            ),
+            Warning::LargeStartBlock { step, block } => write!(
+                f,
+                "the first Push (on step {}) pushes the size of {}",
+                step, block
+            ),
+            Warning::CheckpointFailed(message) => {
+                write!(f, "failed to write a checkpoint: {}", message)
+            }
+        }
+    }
+}
+
+/// Number of the blocks listed by [`Warning::PaletteChangesTransition`].
+const MAX_LISTED_BLOCKS: usize = 5;
+
+fn color_name(color: &Codel) -> String {
+    match color {
+        Codel::Other(_) => {
+            let (r, g, b) = color.rgb();
+            format!("#{:02X}{:02X}{:02X}", r, g, b)
+        }
+        _ => format!("{:?}", color).to_lowercase(),
+    }
+}
+
+/// Prints `warning` to stderr and appends it to `warnings`.
+pub fn warn(warnings: &mut Vec<Warning>, warning: Warning) {
+    eprintln!("warning: {}", warning);
+    warnings.push(warning);
+}
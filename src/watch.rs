@@ -0,0 +1,422 @@
+/*!
+Watch mode (`run --watch`), which re-runs a program every time its file is saved.
+
+The file is polled for its modification time, and a run starts only when the content actually changed (so that e.g. `touch` does not trigger one).
+Each run is a child process of this interpreter fed with the same input, so that a run which hangs can be killed after `--watch-timeout` seconds before the next one starts.
+Ctrl-C is delivered to both this process and the running child, which terminates watch mode.
+*/
+
+use std::error::Error;
+use std::fs;
+use std::hash::{Hash, Hasher};
+use std::io::{self, IsTerminal, Write};
+use std::path::PathBuf;
+use std::process::{Command, ExitStatus, Stdio};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime};
+
+use clap::ValueEnum;
+use rustc_hash::FxHasher;
+
+use super::args::{Args, Format};
+use super::art::ArtFit;
+use super::corner::CornerStrategyKind;
+use super::image::{self, DetectFallback, Topology};
+use super::limits;
+use super::multiplexer::SyncOutput;
+use super::pace::Speed;
+use super::stdin::{self, InputNewlines, NumberOverflow};
+
+/// Change detector of a file.
+pub struct Watcher {
+    path: PathBuf,
+    last_modified: Option<SystemTime>,
+    last_hash: Option<u64>,
+}
+
+impl Watcher {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self {
+            path: path.into(),
+            last_modified: None,
+            last_hash: None,
+        }
+    }
+
+    /// Returns `true` if the content of the file changed since the last call (or if this is the first call).
+    /// The content is read only when the modification time changed.
+    /// A file which is missing or being written is reported as unchanged, and checked again on the next call.
+    pub fn poll(&mut self) -> bool {
+        let Ok(modified) = fs::metadata(&self.path).and_then(|m| m.modified()) else {
+            return false;
+        };
+        if self.last_modified == Some(modified) {
+            return false;
+        }
+        let Ok(bytes) = fs::read(&self.path) else {
+            return false;
+        };
+        self.last_modified = Some(modified);
+
+        let mut hasher = FxHasher::default();
+        bytes.hash(&mut hasher);
+        let hash = hasher.finish();
+        if self.last_hash == Some(hash) {
+            return false;
+        }
+        self.last_hash = Some(hash);
+        true
+    }
+}
+
+/// Spawns `command` with `input` as its stdin and its stdout and stderr inherited, and waits for it.
+/// `None` is returned if it was killed on timeout, which never happens if the deadline cannot be represented (e.g. [`Duration::MAX`]).
+pub fn run_with_timeout(
+    mut command: Command,
+    input: &[u8],
+    timeout: Duration,
+) -> io::Result<Option<ExitStatus>> {
+    let mut child = command.stdin(Stdio::piped()).spawn()?;
+
+    let mut stdin = child.stdin.take().unwrap();
+    let input = input.to_vec();
+    thread::spawn(move || {
+        //the child may exit without reading the whole input
+        let _ = stdin.write_all(&input);
+    });
+
+    let deadline = Instant::now().checked_add(timeout);
+    loop {
+        if let Some(status) = child.try_wait()? {
+            return Ok(Some(status));
+        }
+        if deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+            child.kill()?;
+            child.wait()?;
+            return Ok(None);
+        }
+        thread::sleep(Duration::from_millis(10));
+    }
+}
+
+/// Returns the options of `args` which are passed to each run.
+fn forwarded_args(args: &Args) -> Vec<String> {
+    let load = &args.load;
+    let mut v = vec!["run".to_string(), load.image_file.clone()];
+    match load.format {
+        Format::Auto => (),
+        Format::Image => v.extend(["--format".to_string(), "image".to_string()]),
+        Format::Pietdev => v.extend(["--format".to_string(), "pietdev".to_string()]),
+    }
+    if let Some(codel_size) = load.codel_size {
+        v.extend(["--codel-size".to_string(), codel_size.to_string()]);
+    }
+    if let Some(grid) = load.codel_grid {
+        v.extend(["--codel-grid".to_string(), grid.to_string()]);
+    }
+    if load.detect_grid_offset {
+        v.push("--detect-grid-offset".to_string());
+    }
+    if load.detect_budget != image::DEFAULT_DETECT_BUDGET_MS {
+        v.extend([
+            "--detect-budget".to_string(),
+            load.detect_budget.to_string(),
+        ]);
+    }
+    if load.detect_fallback != DetectFallback::default() {
+        v.extend([
+            "--detect-fallback".to_string(),
+            load.detect_fallback.to_string(),
+        ]);
+    }
+    if let Some(crop) = load.crop {
+        v.extend(["--crop".to_string(), crop.to_string()]);
+    }
+    if let Some(frame) = load.frame {
+        v.extend(["--frame".to_string(), frame.to_string()]);
+    }
+    if load.strict_codel_check {
+        v.push("--strict-codel-check".to_string());
+    }
+    if load.fall_back_to_white {
+        v.push("--fall-back-to-white".to_string());
+    }
+    if load.fall_back_to_black {
+        v.push("--fall-back-to-black".to_string());
+    }
+    if let Some(file) = &load.palette {
+        v.extend(["--palette".to_string(), file.clone()]);
+    }
+    if let Some(dir) = &load.cache_dir {
+        v.extend(["--cache-dir".to_string(), dir.clone()]);
+    }
+    if load.max_image_pixels != limits::DEFAULT_MAX_IMAGE_PIXELS {
+        v.extend([
+            "--max-image-pixels".to_string(),
+            load.max_image_pixels.to_string(),
+        ]);
+    }
+    if load.max_image_dimension != limits::DEFAULT_MAX_IMAGE_DIMENSION {
+        v.extend([
+            "--max-image-dimension".to_string(),
+            load.max_image_dimension.to_string(),
+        ]);
+    }
+    if load.max_image_bytes != limits::DEFAULT_MAX_IMAGE_BYTES {
+        v.extend([
+            "--max-image-bytes".to_string(),
+            load.max_image_bytes.to_string(),
+        ]);
+    }
+    if let Some(entry) = &load.entry {
+        v.extend(["--entry".to_string(), entry.clone()]);
+    }
+    if let Some(manifest) = &load.manifest {
+        v.extend(["--manifest".to_string(), manifest.clone()]);
+    }
+    if let Some(preset) = args.limits {
+        v.extend(["--limits".to_string(), preset.to_string()]);
+    }
+    if let Some(max_iter) = args.max_iter {
+        v.extend(["--max-iter".to_string(), max_iter.to_string()]);
+    }
+    if let Some(stall_timeout) = args.stall_timeout {
+        v.extend(["--stall-timeout".to_string(), stall_timeout.to_string()]);
+    }
+    v.extend((0..args.verbose).map(|_| "--verbose".to_string()));
+    if args.speed != Speed::Max {
+        v.extend(["--speed".to_string(), args.speed.to_string()]);
+    }
+    if let Some(w) = args.art_max_width {
+        v.extend(["--art-max-width".to_string(), w.to_string()]);
+    }
+    if args.art_fit != ArtFit::Scale {
+        let name = args
+            .art_fit
+            .to_possible_value()
+            .unwrap()
+            .get_name()
+            .to_string();
+        v.extend(["--art-fit".to_string(), name]);
+    }
+    if let Some(n) = args.trace_after {
+        v.extend(["--trace-after".to_string(), n.to_string()]);
+    }
+    if args.sync_output != SyncOutput::Auto {
+        let name = args
+            .sync_output
+            .to_possible_value()
+            .unwrap()
+            .get_name()
+            .to_string();
+        v.extend(["--sync-output".to_string(), name]);
+    }
+    if let Some(file) = &args.io_trace {
+        v.extend(["--io-trace".to_string(), file.clone()]);
+    }
+    if args.attribute_output {
+        v.push("--attribute-output".to_string());
+    }
+    if let Some(n) = args.explain_output_byte {
+        v.extend(["--explain-output-byte".to_string(), n.to_string()]);
+    }
+    if let Some(file) = &args.assertions {
+        v.extend(["--assertions".to_string(), file.clone()]);
+    }
+    v.extend(["--scale".to_string(), args.scale.to_string()]);
+    if let Some(file) = &args.report {
+        v.extend(["--report".to_string(), file.clone()]);
+    }
+    if let Some(file) = &args.output {
+        v.extend(["--output".to_string(), file.clone()]);
+    }
+    if let (Some(every), Some(file)) = (args.checkpoint_every, &args.checkpoint_file) {
+        v.extend([
+            "--checkpoint-every".to_string(),
+            every.to_string(),
+            "--checkpoint-file".to_string(),
+            file.clone(),
+        ]);
+    }
+    if let Some(file) = &args.svg {
+        v.extend(["--svg".to_string(), file.clone()]);
+    }
+    if args.svg.is_some() || args.report.is_some() {
+        v.extend([
+            "--svg-scale".to_string(),
+            args.svg_scale.to_string(),
+            "--svg-max-labels".to_string(),
+            args.svg_max_labels.to_string(),
+        ]);
+        if args.svg_no_path {
+            v.push("--svg-no-path".to_string());
+        }
+    }
+    for extension in &args.extension {
+        let name = extension
+            .to_possible_value()
+            .unwrap()
+            .get_name()
+            .to_string();
+        v.extend(["--extension".to_string(), name]);
+    }
+    if args.topology != Topology::Bounded {
+        let name = args
+            .topology
+            .to_possible_value()
+            .unwrap()
+            .get_name()
+            .to_string();
+        v.extend(["--topology".to_string(), name]);
+    }
+    if args.corner_strategy != CornerStrategyKind::Standard {
+        v.extend([
+            "--corner-strategy".to_string(),
+            args.corner_strategy
+                .to_possible_value()
+                .unwrap()
+                .get_name()
+                .to_string(),
+        ]);
+    }
+    if args.number_overflow != NumberOverflow::Ignore {
+        v.extend([
+            "--number-overflow".to_string(),
+            args.number_overflow
+                .to_possible_value()
+                .unwrap()
+                .get_name()
+                .to_string(),
+        ]);
+    }
+    if let Some(when) = args.print_config {
+        let name = when.to_possible_value().unwrap().get_name().to_string();
+        v.push(format!("--print-config={}", name));
+        if args.json {
+            v.push("--json".to_string());
+        }
+    }
+    if args.lenient_start {
+        v.push("--lenient-start".to_string());
+    }
+    if args.allow_empty {
+        v.push("--allow-empty".to_string());
+    }
+    if args.quiet {
+        v.push("--quiet".to_string());
+    }
+    if args.stats {
+        v.push("--stats".to_string());
+    }
+    if args.max_token_length != stdin::DEFAULT_MAX_TOKEN_LEN {
+        v.extend([
+            "--max-token-length".to_string(),
+            args.max_token_length.to_string(),
+        ]);
+    }
+    if let Some(n) = args.max_input_bytes {
+        v.extend(["--max-input-bytes".to_string(), n.to_string()]);
+    }
+    if args.input_newlines != InputNewlines::Raw {
+        v.extend([
+            "--input-newlines".to_string(),
+            args.input_newlines.to_string(),
+        ]);
+    }
+    v
+}
+
+/// Returns `--watch-timeout` (in seconds) as a `Duration`, which is [`Duration::MAX`] (i.e. never expires) if it is too long to be represented.
+fn timeout(secs: f64) -> Duration {
+    Duration::try_from_secs_f64(secs).unwrap_or(Duration::MAX)
+}
+
+/// Runs the program in `args.load.image_file` with `input` every time the file changes, until interrupted.
+pub fn watch(args: &Args, input: &[u8]) -> Result<(), Box<dyn Error>> {
+    let exe = std::env::current_exe()?;
+    let timeout = timeout(args.watch_timeout);
+    let mut watcher = Watcher::new(&args.load.image_file);
+    loop {
+        if watcher.poll() {
+            if io::stdout().is_terminal() {
+                //clears the screen and moves the cursor to the top-left corner
+                print!("\x1b[2J\x1b[H");
+                io::stdout().flush()?;
+            }
+            let mut command = Command::new(&exe);
+            command.args(forwarded_args(args));
+            match run_with_timeout(command, input, timeout)? {
+                Some(status) => eprintln!("[watch] {}", status),
+                None => eprintln!("[watch] killed after {} seconds", args.watch_timeout),
+            }
+            eprintln!("[watch] waiting for changes of {}", args.load.image_file);
+        }
+        thread::sleep(Duration::from_millis(200));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_watcher() {
+        let file = std::env::temp_dir().join(format!("piet_test_watcher_{}", std::process::id()));
+        let mut watcher = Watcher::new(&file);
+        assert!(!watcher.poll());
+
+        let t = SystemTime::now();
+        let write = |content: &str, modified: SystemTime| {
+            fs::write(&file, content).unwrap();
+            fs::File::options()
+                .write(true)
+                .open(&file)
+                .unwrap()
+                .set_modified(modified)
+                .unwrap();
+        };
+
+        write("a", t);
+        assert!(watcher.poll());
+        assert!(!watcher.poll());
+
+        //only the modification time changed
+        write("a", t + Duration::from_secs(1));
+        assert!(!watcher.poll());
+
+        write("b", t + Duration::from_secs(2));
+        assert!(watcher.poll());
+        assert!(!watcher.poll());
+
+        //deleted, then restored with the same content
+        fs::remove_file(&file).unwrap();
+        assert!(!watcher.poll());
+        write("b", t + Duration::from_secs(3));
+        assert!(!watcher.poll());
+
+        fs::remove_file(&file).unwrap();
+    }
+
+    #[test]
+    fn test_run_with_timeout() {
+        let mut command = Command::new("sleep");
+        command.arg("10");
+        let start = Instant::now();
+        assert_eq!(
+            None,
+            run_with_timeout(command, b"", Duration::from_millis(100)).unwrap()
+        );
+        assert!(start.elapsed() < Duration::from_secs(5));
+
+        let mut command = Command::new("sh");
+        command.args(["-c", "read x && test \"$x\" = abc"]);
+        let status = run_with_timeout(command, b"abc\n", Duration::from_secs(10)).unwrap();
+        assert!(status.unwrap().success());
+
+        //`--watch-timeout 1e30`
+        assert_eq!(Duration::from_millis(1500), timeout(1.5));
+        assert_eq!(Duration::MAX, timeout(1e30));
+        let status = run_with_timeout(Command::new("true"), b"", timeout(1e30)).unwrap();
+        assert!(status.unwrap().success());
+    }
+}
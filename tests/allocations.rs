@@ -0,0 +1,77 @@
+//Counts the heap allocations of running many short programs with and without reusing interpreters.
+//This is a separate test binary because the allocator is global and the other tests would disturb the count.
+
+use std::sync::atomic::Ordering;
+
+use clap::Parser;
+use piet_programming_language::args::Args;
+use piet_programming_language::image::Image;
+use piet_programming_language::interpreter::Interpreter;
+use piet_programming_language::pool::InterpreterPool;
+use piet_programming_language::program::Program;
+
+mod counting_allocator;
+
+use counting_allocator::{num_allocations, NUM_ALLOCATIONS};
+
+#[test]
+fn test_allocations_of_pooled_runs() {
+    let num_runs = 100;
+    let program = Program::new(
+        Image::new(
+            "./tests/test_images/original___adder.png",
+            None,
+            None,
+            false,
+        )
+        .unwrap(),
+    );
+    let args = Args::parse_from(["piet_programming_language", "", "--stdin-string", "12 30"]);
+    //warms up the lazily built data of the program and of stdout
+    Interpreter::new();
+    piet_programming_language::run_image(&args, &program).unwrap();
+
+    let count = |f: &dyn Fn()| {
+        let before = NUM_ALLOCATIONS.load(Ordering::SeqCst);
+        f();
+        NUM_ALLOCATIONS.load(Ordering::SeqCst) - before
+    };
+    let fresh = count(&|| {
+        for _ in 0..num_runs {
+            let mut ip = Interpreter::new();
+            piet_programming_language::run_image_with(&args, &program, &mut ip).unwrap();
+        }
+    });
+    let pool = InterpreterPool::new();
+    let pooled = count(&|| {
+        for _ in 0..num_runs {
+            let mut ip = pool.checkout(&program);
+            piet_programming_language::run_image_with(&args, &program, &mut ip).unwrap();
+            pool.checkin(ip);
+        }
+    });
+    println!(
+        "allocations per run: fresh {}, pooled {}",
+        fresh / num_runs,
+        pooled / num_runs
+    );
+    assert!(pooled < fresh, "fresh: {}, pooled: {}", fresh, pooled);
+}
+
+#[test]
+fn test_allocations_in_run_result() {
+    let program = Program::new(
+        Image::new(
+            "./tests/test_images/original___adder.png",
+            None,
+            None,
+            false,
+        )
+        .unwrap(),
+    );
+    let args = Args::parse_from(["piet_programming_language", "", "--stdin-string", "12 30"]);
+    piet_programming_language::resource::set_allocation_counter(num_allocations);
+    let result = piet_programming_language::run_image(&args, &program).unwrap();
+    assert!(result.resources.num_allocations.is_some_and(|n| n > 0));
+    assert!(result.stats().contains("allocations ~"));
+}
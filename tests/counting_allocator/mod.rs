@@ -0,0 +1,50 @@
+//Global allocator shared by the test binaries which count the heap allocations.
+//The allocator is global, so a test binary declaring this module should contain only the tests which count.
+#![allow(dead_code)]
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+struct CountingAllocator;
+
+//number of the calls of `alloc()` and `realloc()`
+pub static NUM_ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+//number of the bytes allocated now
+pub static CURRENT: AtomicUsize = AtomicUsize::new(0);
+//maximum of `CURRENT` since it was last reset
+pub static PEAK: AtomicUsize = AtomicUsize::new(0);
+
+fn add(size: usize) {
+    NUM_ALLOCATIONS.fetch_add(1, Ordering::SeqCst);
+    let current = CURRENT.fetch_add(size, Ordering::SeqCst) + size;
+    PEAK.fetch_max(current, Ordering::SeqCst);
+}
+
+fn sub(size: usize) {
+    CURRENT.fetch_sub(size, Ordering::SeqCst);
+}
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        add(layout.size());
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        sub(layout.size());
+        System.dealloc(ptr, layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        add(new_size);
+        sub(layout.size());
+        System.realloc(ptr, layout, new_size)
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+pub fn num_allocations() -> u64 {
+    NUM_ALLOCATIONS.load(Ordering::SeqCst) as u64
+}
@@ -40,9 +40,9 @@ mod integration_tests {
             format!("./tests/{}", image_file),
             // "--verbose".to_string(),
         ];
-        if codel_size.is_some() {
+        if let Some(codel_size) = codel_size {
             args.push("--codel-size".to_string());
-            args.push(codel_size.unwrap().to_string());
+            args.push(codel_size.to_string());
         }
         if let Some(v) = additional_args {
             v.iter().for_each(|arg| args.push(arg.clone()));
@@ -66,9 +66,9 @@ mod integration_tests {
             }
         };
 
-        if stdin.is_some() {
+        if let Some(stdin) = stdin {
             let mut stdin_ = BufWriter::new(child.stdin.take().unwrap());
-            stdin_.write(stdin.unwrap().as_bytes()).unwrap();
+            stdin_.write_all(stdin.as_bytes()).unwrap();
             drop(stdin_);
         }
 
@@ -635,7 +635,7 @@ mod integration_tests {
         if !res.success() {
             println!("{}", res.stderr);
         }
-        assert!(!res.success());
+        assert_eq!(2, res.exit_status);
         assert!(res.stdout.is_empty());
         assert!(res.stderr.contains("the top-left codel shall not be black"));
     }
@@ -648,7 +648,38 @@ mod integration_tests {
         }
         assert!(res.success());
         assert!(res.stdout.is_empty());
-        assert!(res.stderr.is_empty());
+
+        //the summary counts the commands of the path trace, and is its last line
+        let traced = run(
+            "./test_images/original___issue_02.png",
+            None,
+            Some(vec!["--verbose".to_string()]),
+        );
+        assert!(traced.success());
+        let num_commands = traced
+            .stderr
+            .lines()
+            .filter(|l| {
+                l.strip_prefix("    ")
+                    .is_some_and(|name| name.chars().all(|c| c.is_ascii_alphabetic()))
+            })
+            .count();
+        let stack_depth = res
+            .stderr
+            .trim_end()
+            .rsplit(' ')
+            .next()
+            .unwrap()
+            .parse::<usize>()
+            .unwrap();
+        assert_eq!(
+            format!(
+                "program halted normally after {} commands with stack depth {}\n",
+                num_commands, stack_depth
+            ),
+            res.stderr
+        );
+        assert!(traced.stderr.ends_with(&res.stderr));
     }
 
     #[test]
@@ -770,4 +801,2538 @@ OXX
         assert_eq!(expected_stdout.trim_start(), res.stdout);
         assert!(res.stderr.is_empty());
     }
+
+    #[test]
+    fn test42() {
+        let res = run("./test_images/original___hi.pietdev", None, None);
+        if !res.success() {
+            println!("{}", res.stderr);
+        }
+        assert!(res.success());
+        assert_eq!("Hi", res.stdout);
+        assert!(res.stderr.is_empty());
+
+        //round trip
+        let file = std::env::temp_dir().join("piet_programming_language_test42.pietdev");
+        let res = run(
+            "./test_images/original___hi.pietdev",
+            None,
+            Some(vec![
+                "--export-pietdev".to_string(),
+                file.to_str().unwrap().to_string(),
+            ]),
+        );
+        assert!(res.success());
+        assert!(res.stdout.is_empty());
+        assert_eq!(
+            fs::read_to_string("./tests/test_images/original___hi.pietdev").unwrap(),
+            fs::read_to_string(&file).unwrap()
+        );
+    }
+
+    #[test]
+    fn test43() {
+        let res = run(
+            "./test_images/original___hi.pietdev",
+            None,
+            Some(vec!["--golf-score".to_string(), "--json".to_string()]),
+        );
+        assert!(res.success());
+        assert_eq!(
+            r#"{"total_codels":231,"colored_codels":183,"blocks":5,"distinct_colors":6,"bounding_box":{"top":0,"left":0,"bottom":10,"right":20},"rules":"codels","score":231}"#,
+            res.stdout.trim_end()
+        );
+
+        let res = run(
+            "./test_images/original___hi.pietdev",
+            None,
+            Some(vec![
+                "--golf-score".to_string(),
+                "--golf-rules".to_string(),
+                "blocks".to_string(),
+            ]),
+        );
+        assert!(res.success());
+        assert!(res.stdout.contains("blocks:          5\n"));
+        assert!(res.stdout.contains("score (blocks):  5\n"));
+    }
+
+    #[test]
+    fn test44() {
+        let res = run(
+            "./test_images/original___start_point_is_black_framed.png",
+            None,
+            None,
+        );
+        assert_eq!(2, res.exit_status);
+        assert!(res.stdout.is_empty());
+        assert!(res.stderr.contains("the top-left codel shall not be black"));
+
+        let res = run(
+            "./test_images/original___start_point_is_black_framed.png",
+            None,
+            Some(vec!["--lenient-start".to_string()]),
+        );
+        if !res.success() {
+            println!("{}", res.stderr);
+        }
+        assert!(res.success());
+        assert_eq!("Hi", res.stdout);
+        assert_eq!(
+            "warning: the top-left codel is black; starting at (1, 1) instead\n",
+            res.stderr
+        );
+    }
+
+    #[test]
+    fn test45() {
+        let res = run("./test_images/original___no_output.png", None, None);
+        if !res.success() {
+            println!("{}", res.stderr);
+        }
+        assert!(res.success());
+        assert!(res.stdout.is_empty());
+        assert_eq!(
+            "program halted normally after 2 commands with stack depth 2\n",
+            res.stderr
+        );
+
+        let res = run(
+            "./test_images/original___no_output.png",
+            None,
+            Some(vec!["--quiet".to_string()]),
+        );
+        assert!(res.success());
+        assert!(res.stdout.is_empty());
+        assert!(res.stderr.is_empty());
+    }
+
+    #[test]
+    fn test46() {
+        //smoke test which compares the binary against itself
+        let res = run(
+            "./test_images/original___hi.pietdev",
+            None,
+            Some(vec![
+                "--compare-with".to_string(),
+                "./target/release/piet_programming_language".to_string(),
+                "--stdin-string".to_string(),
+                String::new(),
+            ]),
+        );
+        if !res.success() {
+            println!("{}", res.stderr);
+        }
+        assert!(res.success());
+        assert_eq!(
+            "ours: exit code 0\ntheirs (./target/release/piet_programming_language): exit code 0\noutputs match\n",
+            res.stdout
+        );
+
+        //`echo` prints the image path instead
+        let res = run(
+            "./test_images/original___hi.pietdev",
+            None,
+            Some(vec![
+                "--compare-with".to_string(),
+                "echo".to_string(),
+                "--stdin-string".to_string(),
+                String::new(),
+            ]),
+        );
+        assert!(!res.success());
+        assert!(res
+            .stdout
+            .ends_with("line 1:\n- Hi\n+ ./tests/./test_images/original___hi.pietdev\n"));
+    }
+
+    #[test]
+    fn test47() {
+        let res = run(
+            "./test_images/original___echo_char.png",
+            Some("a"),
+            Some(vec!["--stdin-string".to_string(), " x".to_string()]),
+        );
+        assert!(res.success());
+        assert_eq!("x", res.stdout);
+
+        let res = run(
+            "./test_images/original___echo_char.png",
+            Some("a"),
+            Some(vec![
+                "--input".to_string(),
+                "./tests/test_images/original___echo_char_input.txt".to_string(),
+            ]),
+        );
+        assert!(res.success());
+        assert_eq!("y", res.stdout);
+
+        let res = run(
+            "./test_images/original___echo_char.png",
+            Some("a"),
+            Some(vec!["--compare-with".to_string(), "echo".to_string()]),
+        );
+        assert!(!res.success());
+    }
+
+    #[test]
+    fn test48() {
+        //`run` is the default subcommand, so both forms work
+        let command = "./target/release/piet_programming_language";
+        let image_file = "./tests/test_images/original___hi.pietdev";
+        for args in [
+            vec![image_file, "--max-iter", "1000"],
+            vec!["run", image_file, "--max-iter", "1000"],
+            vec!["--max-iter", "1000", image_file],
+        ] {
+            let output = Command::new(command).args(args).output().unwrap();
+            assert!(output.status.success());
+            assert_eq!(b"Hi", output.stdout.as_slice());
+        }
+
+        let output = Command::new(command)
+            .args(["golf", image_file, "--json"])
+            .output()
+            .unwrap();
+        assert!(output.status.success());
+        assert!(String::from_utf8(output.stdout)
+            .unwrap()
+            .starts_with("{\"total_codels\":231,"));
+
+        let output = Command::new(command).arg("--help").output().unwrap();
+        assert!(output.status.success());
+        assert!(String::from_utf8(output.stdout)
+            .unwrap()
+            .contains("Commands:\n  run "));
+    }
+
+    #[test]
+    fn test49() {
+        //the off-palette decoration at the bottom is never reached, so no fallback is needed
+        let res = run(
+            "./test_images/original___hi_with_decoration.png",
+            None,
+            None,
+        );
+        if !res.success() {
+            println!("{}", res.stderr);
+        }
+        assert!(res.success());
+        assert_eq!("Hi", res.stdout);
+
+        let res = run(
+            "./test_images/original___unknown_color_reached.png",
+            None,
+            None,
+        );
+        assert!(!res.success());
+        assert!(res
+            .stderr
+            .contains("the program reached the unknown color #808080 at (0, 2); "));
+
+        let res = run(
+            "./test_images/original___unknown_color_reached.png",
+            None,
+            Some(vec!["--fall-back-to-black".to_string()]),
+        );
+        assert!(res.success());
+    }
+
+    #[test]
+    fn test50() {
+        //prints "1\n" and then spins forever without any input or output
+        let start = std::time::Instant::now();
+        let res = run(
+            "./test_images/original___print_then_spin.png",
+            None,
+            Some(vec!["--stall-timeout".to_string(), "0.5".to_string()]),
+        );
+        let elapsed = start.elapsed();
+        assert_eq!(4, res.exit_status);
+        assert_eq!("1\n", res.stdout);
+        assert_eq!(
+            "Program terminated by `stall-timeout`: no input or output for 0.5 seconds.\n",
+            res.stderr
+        );
+        assert!(elapsed >= std::time::Duration::from_millis(500));
+        assert!(elapsed < std::time::Duration::from_secs(30));
+    }
+
+    //Runs randomly generated programs (with random, possibly malformed UTF-8 input) and checks that none of them makes the interpreter panic.
+    #[test]
+    fn test51() {
+        //xorshift (deterministic so that a failure can be reproduced)
+        let mut state: u64 = 0x2545_F491_4F6C_DD1D;
+        let mut next = move |n: u64| {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            state % n
+        };
+
+        let dir = std::env::temp_dir().join(format!("piet_test51_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        for k in 0..200 {
+            let height = 1 + next(6);
+            let width = 1 + next(6);
+            let mut s = format!("{} {}\n", width, height);
+            for i in 0..height {
+                //indices `0..=19` are the palette, and the top-left codel is never black (`19`)
+                let row = (0..width)
+                    .map(|j| next(if (i, j) == (0, 0) { 19 } else { 20 }).to_string())
+                    .collect::<Vec<_>>();
+                s += &row.join(" ");
+                s += "\n";
+            }
+            let file = dir.join(format!("{}.pietdev", k));
+            fs::write(&file, s).unwrap();
+
+            let input = (0..next(8))
+                .map(|_| next(256) as u8)
+                .chain(b"-9223372036854775808 55296 ".iter().copied())
+                .collect::<Vec<_>>();
+            let mut child = Command::new("./target/release/piet_programming_language")
+                .args([file.to_str().unwrap(), "--max-iter", "2000", "--quiet"])
+                .stdin(Stdio::piped())
+                .stdout(Stdio::piped())
+                .stderr(Stdio::piped())
+                .spawn()
+                .unwrap();
+            //the program may terminate without reading the whole input
+            let _ = child.stdin.take().unwrap().write_all(&input);
+            let output = child.wait_with_output().unwrap();
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            assert!(!stderr.contains("panicked"), "{}: {}", k, stderr);
+            assert!(output.status.code().is_some(), "{}", k);
+        }
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test52() {
+        let res = run(
+            "./test_images/original___adder.png",
+            Some(" 40\n2 "),
+            Some(vec!["--io-trace".to_string()]),
+        );
+        assert!(res.success());
+        assert_eq!("42\n", res.stdout);
+        assert_eq!(
+            "#1 in(number) read \"40\" (consumed 4 bytes, next char is '\\n')\n\
+             #2 in(number) read \"2\" (consumed 2 bytes, next char is ' ')\n\
+             #4 out(number) 42\n",
+            res.stderr
+        );
+
+        let file = std::env::temp_dir().join(format!("piet_test52_{}.txt", std::process::id()));
+        let res = run(
+            "./test_images/original___adder.png",
+            Some("40 abc"),
+            Some(vec![
+                "--io-trace".to_string(),
+                file.to_str().unwrap().to_string(),
+            ]),
+        );
+        assert!(res.success());
+        assert_eq!("40\n", res.stdout);
+        assert_eq!(
+            "#1 in(number) read \"40\" (consumed 3 bytes, next char is ' ')\n\
+             #2 in(number) ignored: parse error at \"abc\" (consumed 3 bytes)\n\
+             #4 out(number) 40\n",
+            fs::read_to_string(&file).unwrap()
+        );
+        fs::remove_file(&file).unwrap();
+
+        let res = run(
+            "./test_images/original___adder.png",
+            Some(""),
+            Some(vec!["--io-trace".to_string(), "-".to_string()]),
+        );
+        assert!(res.success());
+        assert!(res.stderr.starts_with(
+            "#1 in(number) ignored: end of input\n#2 in(number) ignored: end of input\n"
+        ));
+    }
+
+    #[test]
+    fn test53() {
+        let dir = std::env::temp_dir().join(format!("piet_test53_{}", std::process::id()));
+        let args = |verbose: bool| {
+            let mut v = vec!["--cache-dir".to_string(), dir.to_str().unwrap().to_string()];
+            if verbose {
+                v.push("--verbose".to_string());
+            }
+            Some(v)
+        };
+
+        //first run writes the cache
+        let res = run(
+            "./test_images/original___adder.png",
+            Some("40 2"),
+            args(true),
+        );
+        assert!(res.success());
+        assert!(res.stderr.contains("image cache miss: "));
+        let entries = fs::read_dir(&dir)
+            .unwrap()
+            .map(|e| e.unwrap().path())
+            .collect::<Vec<_>>();
+        assert_eq!(1, entries.len());
+
+        //second run loads it and behaves identically
+        let uncached = run(
+            "./test_images/original___adder.png",
+            Some("40 2"),
+            args(false),
+        );
+        let res = run(
+            "./test_images/original___adder.png",
+            Some("40 2"),
+            args(true),
+        );
+        assert!(res.success());
+        assert!(res.stderr.contains("image cache hit: "));
+        let cached = run(
+            "./test_images/original___adder.png",
+            Some("40 2"),
+            args(false),
+        );
+        assert_eq!("42\n", uncached.stdout);
+        assert_eq!(uncached.stdout, cached.stdout);
+
+        //a poisoned entry is ignored and rebuilt
+        fs::write(&entries[0], b"PIETCACHE garbage").unwrap();
+        let res = run(
+            "./test_images/original___adder.png",
+            Some("40 2"),
+            args(true),
+        );
+        assert!(res.success());
+        assert_eq!("42\n", res.stdout);
+        assert!(res
+            .stderr
+            .contains("image cache entry is corrupt and ignored: "));
+        assert!(res.stderr.contains("image cache miss: "));
+        let res = run(
+            "./test_images/original___adder.png",
+            Some("40 2"),
+            args(true),
+        );
+        assert!(res.stderr.contains("image cache hit: "));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test54() {
+        let res = run(
+            "./test_images/original___hi.pietdev",
+            None,
+            Some(vec![
+                "--assertions".to_string(),
+                "./tests/test_images/original___hi.asserts.toml".to_string(),
+            ]),
+        );
+        assert!(res.success());
+        assert_eq!("Hi", res.stdout);
+
+        let res = run(
+            "./test_images/original___hi.pietdev",
+            None,
+            Some(vec![
+                "--assertions".to_string(),
+                "./tests/test_images/original___hi_failing.asserts.toml".to_string(),
+            ]),
+        );
+        assert!(!res.success());
+        assert_eq!("H", res.stdout);
+        assert!(res
+            .stderr
+            .contains("assertion at line 3 failed at (0, 18) (step 3)"));
+        assert!(res
+            .stderr
+            .contains(r#"expected the output so far to be "Hello" but it is "H""#));
+    }
+
+    //single-row and single-column programs bounce between both ends forever
+    #[test]
+    fn test55() {
+        let res = run(
+            "./test_images/original___single_row.pietdev",
+            None,
+            Some(vec!["--max-iter".to_string(), "20".to_string()]),
+        );
+        assert_eq!(4, res.exit_status);
+        assert_eq!("5\n5\nProgram terminated by `max-iter`.\n", res.stdout);
+
+        let res = run(
+            "./test_images/original___single_column.pietdev",
+            None,
+            Some(vec!["--max-iter".to_string(), "20".to_string()]),
+        );
+        assert_eq!(4, res.exit_status);
+        assert_eq!(
+            "5\n5\n5\n5\n5\nProgram terminated by `max-iter`.\n",
+            res.stdout
+        );
+
+        for file in ["single_row", "single_column"] {
+            let res = run(
+                &format!("./test_images/original___{}.pietdev", file),
+                None,
+                Some(vec![
+                    "--max-iter".to_string(),
+                    "20".to_string(),
+                    "--verbose".to_string(),
+                    "--scale".to_string(),
+                    "3".to_string(),
+                ]),
+            );
+            assert_eq!(4, res.exit_status);
+        }
+    }
+
+    #[test]
+    fn test56() {
+        let trace_after = |n: usize| {
+            run(
+                "./test_images/original___hi.pietdev",
+                None,
+                Some(vec!["--trace-after".to_string(), n.to_string()]),
+            )
+        };
+
+        //steps 1 to 3 (at (0, 0), (0, 7) and (0, 8)) are not traced
+        let res = trace_after(3);
+        assert!(res.success());
+        assert_eq!("Hi", res.stdout);
+        assert_eq!(
+            "(0, 18)      DP:Right CC:Left\n    OutChar\n(0, 20)      DP:Right CC:Left\n",
+            res.stderr
+        );
+
+        //same as the trace part of `--verbose`
+        let res = trace_after(0);
+        let verbose = run(
+            "./test_images/original___hi.pietdev",
+            None,
+            Some(vec!["--verbose".to_string()]),
+        );
+        assert!(res.stderr.starts_with("(0, 0) "));
+        assert!(verbose.stderr.ends_with(&res.stderr));
+
+        //never reached
+        let res = trace_after(100);
+        assert!(res.success());
+        assert_eq!("", res.stderr);
+    }
+
+    #[test]
+    fn test57() {
+        //pushes 5 and 6, moves 6 to the second stack, prints 5, moves 6 back and prints it
+        let res = run(
+            "./test_images/original___second_stack.png",
+            None,
+            Some(vec!["--extension".to_string(), "second-stack".to_string()]),
+        );
+        assert!(res.success());
+        assert_eq!("5\n6\n", res.stdout);
+
+        //Roll with a negative depth is ignored without the extension.
+        let res = run("./test_images/original___second_stack.png", None, None);
+        assert!(res.success());
+        assert_eq!("1\n-1\n", res.stdout);
+    }
+
+    #[test]
+    fn test58() {
+        //`b` takes a detour of two extra steps at step 7
+        let command = "./target/release/piet_programming_language";
+        let a = "./tests/test_images/original___trace_a.trace";
+        let b = "./tests/test_images/original___trace_b.trace";
+        let output = Command::new(command)
+            .args(["trace-diff", a, b])
+            .output()
+            .unwrap();
+        assert!(!output.status.success());
+        let stdout = String::from_utf8(output.stdout).unwrap();
+        assert!(stdout.contains(&format!("{}: 14 steps\n{}: 16 steps\n", a, b)));
+        assert!(stdout.contains("first divergence at step 7\n"));
+        assert!(stdout.contains(">      7 (0, 18)      DP:Right CC:Left Subtract\n"));
+        assert!(stdout.contains(">      7 (0, 18)      DP:Down  CC:Left\n"));
+        //five steps before and after
+        assert!(stdout.contains(&format!(
+            "--- {}\n       2 (0, 3)       DP:Right CC:Left Push\n",
+            b
+        )));
+        assert!(stdout.contains("       9 (2, 18)      DP:Left  CC:Left Subtract\n"));
+        assert!(stdout.contains("      12 (0, 27)      DP:Right CC:Left Greater\n"));
+        assert!(stdout.ends_with(&format!(
+            "re-converges at step 8 of {} and step 10 of {}\n",
+            a, b
+        )));
+
+        let output = Command::new(command)
+            .args(["trace-diff", a, a])
+            .output()
+            .unwrap();
+        assert!(output.status.success());
+        assert!(String::from_utf8(output.stdout)
+            .unwrap()
+            .ends_with("traces are identical\n"));
+    }
+
+    #[test]
+    fn test59() {
+        //The red codel at the top-left corner is walled by black on the right.
+        //On a torus, it leaves from the left edge and loops through push 1 and out(number) forever.
+        let topology = |t: &str| {
+            run(
+                "./test_images/original___torus.pietdev",
+                None,
+                Some(vec![
+                    "--topology".to_string(),
+                    t.to_string(),
+                    "--max-iter".to_string(),
+                    "12".to_string(),
+                ]),
+            )
+        };
+        let res = topology("bounded");
+        assert!(res.success());
+        assert_eq!("", res.stdout);
+        assert_eq!(
+            "program halted normally after 0 commands with stack depth 0\n",
+            res.stderr
+        );
+
+        let res = topology("torus");
+        assert_eq!(4, res.exit_status);
+        assert_eq!("1\n1\n1\nProgram terminated by `max-iter`.\n", res.stdout);
+    }
+
+    #[test]
+    fn test60() {
+        let file = std::env::temp_dir().join(format!("piet_test_svg_{}.svg", std::process::id()));
+        let svg = |extra_args: &[&str]| {
+            let mut args = vec!["--svg".to_string(), file.to_str().unwrap().to_string()];
+            args.extend(extra_args.iter().map(|s| s.to_string()));
+            let res = run("./test_images/original___hi.pietdev", None, Some(args));
+            assert!(res.success());
+            assert_eq!("Hi", res.stdout);
+            fs::read_to_string(&file).unwrap()
+        };
+
+        let s = svg(&[]);
+        assert!(s.starts_with("<svg "));
+        assert!(s.trim_end().ends_with("</svg>"));
+        assert_eq!(231, s.matches("<rect ").count());
+        assert_eq!(1, s.matches("<polyline ").count());
+        //push, out(char), push, out(char)
+        assert_eq!(4, s.matches("<text ").count());
+        assert!(s.contains(">OutChar</text>"));
+
+        let s = svg(&["--svg-no-path"]);
+        assert_eq!(231, s.matches("<rect ").count());
+        assert_eq!(0, s.matches("<polyline ").count());
+
+        let s = svg(&["--svg-max-labels", "1", "--svg-scale", "4"]);
+        assert_eq!(1, s.matches("<text ").count());
+        assert!(s.contains(r#"width="84" height="44""#));
+
+        fs::remove_file(&file).unwrap();
+    }
+
+    #[test]
+    fn test61() {
+        //the individual option overrides the preset
+        let limits = |options: &[&str]| {
+            run(
+                "./test_images/original___torus.pietdev",
+                None,
+                Some(
+                    ["--topology", "torus"]
+                        .iter()
+                        .chain(options)
+                        .map(|s| s.to_string())
+                        .collect(),
+                ),
+            )
+        };
+        let res = limits(&["--limits", "playground", "--max-iter", "12", "--verbose"]);
+        assert_eq!(4, res.exit_status);
+        assert_eq!("1\n1\n1\nProgram terminated by `max-iter`.\n", res.stdout);
+        assert!(res
+            .stderr
+            .contains("limits: playground (max-iter 12, stall-timeout 2s)\n"));
+
+        let res = limits(&["--limit-preset", "grading", "--max-iter", "4"]);
+        assert_eq!(4, res.exit_status);
+        assert_eq!("1\nProgram terminated by `max-iter`.\n", res.stdout);
+
+        let res = limits(&["--limits", "playground", "--stall-timeout=-1"]);
+        assert!(!res.success());
+    }
+
+    #[test]
+    fn test62() {
+        let check = |options: &[&str]| {
+            run(
+                "./test_images/original___large_push.pietdev",
+                None,
+                Some(
+                    ["--check"]
+                        .iter()
+                        .chain(options)
+                        .map(|s| s.to_string())
+                        .collect(),
+                ),
+            )
+        };
+        let res = check(&[]);
+        assert!(res.success());
+        assert_eq!(
+            "warning: large-push: the background block at (0, 3) of size 12 is the source of a Push\n",
+            res.stdout
+        );
+
+        let res = check(&["--large-push-threshold", "5"]);
+        assert!(res.success());
+        assert_eq!(
+            "warning: large-push: the block at (0, 0) of size 6 (more than 5) is the source of a Push\nwarning: large-push: the background block at (0, 3) of size 12 is the source of a Push\n",
+            res.stdout
+        );
+
+        let res = check(&["--large-push-threshold", "5", "--allow-large-push"]);
+        assert!(res.success());
+        assert_eq!("no warnings\n", res.stdout);
+    }
+
+    #[test]
+    fn test63() {
+        let res = run(
+            "./test_images/original___abcd.png",
+            None,
+            Some(vec!["--explain-output-byte".to_string(), "3".to_string()]),
+        );
+        assert!(res.success());
+        assert_eq!("abcd", res.stdout);
+        let mut lines = res.stderr.lines();
+        assert_eq!(
+            Some("output byte 3 ('d') was written by OutChar at step 15 at (0, 23) (bytes 3..4)"),
+            lines.next()
+        );
+        //five steps before, and the rest of the run after
+        assert_eq!(
+            Some("      10 (0, 18)      DP:Right CC:Left Duplicate"),
+            lines.next()
+        );
+        assert_eq!(
+            Some(">     15 (0, 23)      DP:Right CC:Left OutChar"),
+            lines.nth(4)
+        );
+        assert_eq!(Some("      16 (0, 25)      DP:Right CC:Left"), lines.next());
+        assert_eq!(None, lines.next());
+
+        let res = run(
+            "./test_images/original___abcd.png",
+            None,
+            Some(vec!["--attribute-output".to_string()]),
+        );
+        assert!(res.success());
+        assert_eq!(
+            "bytes 0..1: step 3 at (0, 11) (OutChar)\nbytes 1..2: step 7 at (0, 15) (OutChar)\nbytes 2..3: step 11 at (0, 19) (OutChar)\nbytes 3..4: step 15 at (0, 23) (OutChar)\n",
+            res.stderr
+        );
+    }
+
+    #[test]
+    fn test64() {
+        let image_file = "./tests/test_images/original___hi.pietdev";
+        let dump = Command::new("./target/release/piet_programming_language")
+            .args([image_file, "--dump-image"])
+            .output()
+            .unwrap();
+        assert!(dump.status.success());
+        let dump = String::from_utf8(dump.stdout).unwrap();
+        //21 codels wide
+        assert!(dump.lines().nth(1).unwrap().ends_with(" 9 0"));
+
+        //The full dump is printed in verbose mode when stderr is not a terminal.
+        let verbose = |options: &[&str]| {
+            let output = Command::new("./target/release/piet_programming_language")
+                .args([image_file, "--verbose"])
+                .args(options)
+                .output()
+                .unwrap();
+            assert!(output.status.success());
+            String::from_utf8(output.stderr).unwrap()
+        };
+        assert!(verbose(&[]).starts_with(&dump));
+        assert!(verbose(&["--art-max-width", "40"]).starts_with("preview at 1/2 scale "));
+        assert!(verbose(&["--art-max-width", "40", "--art-fit", "clip"])
+            .starts_with("clipped: showing columns 0..18 of 21 "));
+        assert!(verbose(&["--art-max-width", "40", "--art-fit", "off"]).starts_with(&dump));
+    }
+
+    #[test]
+    fn test65() {
+        let report = |options: &[&str]| {
+            let output = Command::new("./target/release/piet_programming_language")
+                .arg("version-report")
+                .args(options)
+                .output()
+                .unwrap();
+            assert!(output.status.success());
+            String::from_utf8(output.stdout).unwrap()
+        };
+        let default = report(&[]);
+        assert!(default.starts_with(r#"{"name":"piet_programming_language","version":""#));
+        assert!(default.ends_with(
+            r#""white":"stop-on-retrace","topology":"bounded","extensions":[],"corner_strategy":"standard","number_overflow":"ignore"}}
+"#
+        ));
+        assert_eq!(default, report(&["--topology", "bounded"]));
+        assert!(
+            report(&["--topology", "torus", "--extension", "second-stack"]).ends_with(
+                r#""topology":"torus","extensions":["second-stack"],"corner_strategy":"standard","number_overflow":"ignore"}}
+"#
+            )
+        );
+    }
+
+    //a program of a single codel has no neighbour in any direction
+    #[test]
+    fn test66() {
+        for options in [
+            vec![],
+            vec!["--topology", "torus"],
+            vec!["--check"],
+            vec!["--verbose", "--art-max-width", "1"],
+            vec!["--verbose", "--art-max-width", "1", "--art-fit", "clip"],
+            vec!["--svg", "/dev/null"],
+        ] {
+            let res = run(
+                "./test_images/original___single_codel.pietdev",
+                None,
+                Some(options.iter().map(|s| s.to_string()).collect()),
+            );
+            assert!(res.success(), "{:?}", options);
+        }
+    }
+
+    #[test]
+    fn test67() {
+        let file =
+            std::env::temp_dir().join(format!("piet_test_report_{}.html", std::process::id()));
+        let res = run(
+            "./test_images/original___hi.pietdev",
+            None,
+            Some(vec![
+                "--report".to_string(),
+                file.to_str().unwrap().to_string(),
+            ]),
+        );
+        assert!(res.success());
+        assert_eq!("Hi", res.stdout);
+        let html = fs::read_to_string(&file).unwrap();
+        fs::remove_file(&file).unwrap();
+
+        assert!(html.starts_with("<!DOCTYPE html>"));
+        for id in ["termination", "program", "stats", "coverage", "io", "trace"] {
+            assert!(html.contains(&format!("<h2 id=\"{}\">", id)), "{}", id);
+        }
+        assert!(html.contains("<p>NoExit</p>"));
+        assert!(html.contains("#2 out(char) 'H'"));
+        assert!(html.contains("<polyline "));
+        assert!(html.contains("<details>"));
+
+        let (_, rest) = html.split_once("data:image/png;base64,").unwrap();
+        let base64 = &rest[..rest.find('"').unwrap()];
+        assert_eq!(0, base64.len() % 4);
+        assert!(base64
+            .trim_end_matches('=')
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || (c == '+') || (c == '/')));
+        //the PNG signature
+        assert!(base64.starts_with("iVBORw0KGgo"));
+    }
+
+    #[test]
+    fn test68() {
+        for image in ["all_white", "all_black"] {
+            let image = format!("./test_images/original___{}.pietdev", image);
+            let res = run(&image, None, None);
+            assert!(!res.success());
+            assert!(
+                res.stderr.contains("the image has no colored block"),
+                "{}",
+                res.stderr
+            );
+        }
+
+        let res = run(
+            "./test_images/original___all_white.pietdev",
+            None,
+            Some(vec!["--allow-empty".to_string()]),
+        );
+        assert!(res.success());
+        assert!(res
+            .stderr
+            .contains("warning: the image has no colored block"));
+
+        //slides into the colored block and then bounces between it and the white forever
+        let res = run(
+            "./test_images/original___white_start.pietdev",
+            None,
+            Some(vec!["--max-iter".to_string(), "100".to_string()]),
+        );
+        assert_eq!(4, res.exit_status);
+        assert!(res.stdout.contains("max-iter"));
+        assert!(!res.stderr.contains("colored block"));
+    }
+
+    #[test]
+    fn test69() {
+        let args = |v: &[&str]| Some(v.iter().map(|s| s.to_string()).collect());
+
+        //a PNG of 167 bytes which declares 30000 x 30000 pixels
+        let res = run("./test_images/original___bomb.png", None, None);
+        assert!(!res.success());
+        assert!(res.stderr.contains(
+            "the image (30000 x 30000 pixels) exceeds the maximum of 268435456 pixels; "
+        ));
+        let res = run(
+            "./test_images/original___bomb.png",
+            None,
+            args(&["--max-image-dimension", "10000"]),
+        );
+        assert!(res
+            .stderr
+            .contains("exceeds the maximum width and height of 10000 pixels; "));
+        let res = run(
+            "./test_images/original___bomb.png",
+            None,
+            args(&["--max-image-pixels", "1000000000"]),
+        );
+        assert!(res.stderr.contains(
+            "decodes into 2700000000 bytes, which exceeds the maximum of 1073741824 bytes; "
+        ));
+
+        //a PietDev save (21 x 11 codels)
+        let res = run(
+            "./test_images/original___hi.pietdev",
+            None,
+            args(&["--max-image-pixels", "100"]),
+        );
+        assert!(res
+            .stderr
+            .contains("the image (11 x 21 pixels) exceeds the maximum of 100 pixels; "));
+        let res = run(
+            "./test_images/original___hi.pietdev",
+            None,
+            args(&["--max-image-pixels", "231"]),
+        );
+        assert!(res.success());
+        assert_eq!("Hi", res.stdout);
+    }
+
+    #[test]
+    fn test70() {
+        let tmp = |name: &str| {
+            std::env::temp_dir()
+                .join(format!(
+                    "piet_test_checkpoint_{}_{}",
+                    std::process::id(),
+                    name
+                ))
+                .to_str()
+                .unwrap()
+                .to_string()
+        };
+        let (input, checkpoint, output, expected) =
+            (tmp("input"), tmp("ckpt"), tmp("out"), tmp("expected"));
+        fs::write(&input, "héllo, checkpoint!\n").unwrap();
+
+        //echoes the input except whitespace (3 steps per character) and then spins forever on a torus
+        let run_until = |max_iter: usize, output: &str, options: &[&str]| {
+            let mut args = vec![
+                "--topology",
+                "torus",
+                "--input",
+                &input,
+                "--output",
+                output,
+                "--checkpoint-every",
+                "4",
+                "--checkpoint-file",
+                &checkpoint,
+            ]
+            .into_iter()
+            .map(|s| s.to_string())
+            .collect::<Vec<_>>();
+            args.extend(["--max-iter".to_string(), max_iter.to_string()]);
+            args.extend(options.iter().map(|s| s.to_string()));
+            run(
+                "./test_images/original___cat_forever.pietdev",
+                None,
+                Some(args),
+            )
+        };
+
+        assert_eq!(4, run_until(80, &expected, &[]).exit_status);
+        let expected = fs::read(&expected).unwrap();
+        assert_eq!("héllo,checkpoint!".as_bytes(), expected);
+
+        //interrupted at various points (some between two checkpoints, after which the output file has more than the checkpoint recorded)
+        for interrupted_at in [5, 8, 10, 11, 23, 30, 59, 79] {
+            let _ = fs::remove_file(&checkpoint);
+            assert_eq!(4, run_until(interrupted_at, &output, &[]).exit_status);
+            let res = run_until(80, &output, &["--resume", &checkpoint]);
+            assert_eq!(4, res.exit_status, "{}: {}", interrupted_at, res.stderr);
+            assert_eq!(expected, fs::read(&output).unwrap(), "{}", interrupted_at);
+        }
+
+        //a resumed run is interrupted again
+        assert_eq!(4, run_until(10, &output, &[]).exit_status);
+        assert_eq!(
+            4,
+            run_until(25, &output, &["--resume", &checkpoint]).exit_status
+        );
+        assert_eq!(
+            4,
+            run_until(80, &output, &["--resume", &checkpoint]).exit_status
+        );
+        assert_eq!(expected, fs::read(&output).unwrap());
+
+        //the output file is shorter than the checkpoint says
+        assert_eq!(4, run_until(30, &output, &[]).exit_status);
+        fs::write(&output, "h").unwrap();
+        let res = run_until(80, &output, &["--resume", &checkpoint]);
+        assert!(!res.success());
+        assert!(res.stderr.contains("the file has 1 bytes"));
+
+        //the consumed part of the real stdin cannot be skipped
+        assert_eq!(4, run_until(30, &output, &[]).exit_status);
+        let res = run(
+            "./test_images/original___cat_forever.pietdev",
+            Some("héllo, checkpoint!\n"),
+            Some(
+                ["--topology", "torus", "--resume", &checkpoint]
+                    .iter()
+                    .map(|s| s.to_string())
+                    .collect(),
+            ),
+        );
+        assert!(!res.success());
+        assert!(res.stderr.contains("cannot be read again"));
+
+        //another program (the topology is part of it)
+        let res = run(
+            "./test_images/original___cat_forever.pietdev",
+            None,
+            Some(
+                ["--input", &input, "--resume", &checkpoint]
+                    .iter()
+                    .map(|s| s.to_string())
+                    .collect(),
+            ),
+        );
+        assert!(!res.success());
+        assert!(res
+            .stderr
+            .contains("the checkpoint was taken from the program"));
+
+        for file in [input, checkpoint, output] {
+            fs::remove_file(file).unwrap();
+        }
+    }
+
+    #[test]
+    fn test71() {
+        let res = run(
+            "./test_images/original___hi.pietdev",
+            None,
+            Some(vec!["--stats".to_string()]),
+        );
+        assert!(res.success());
+        assert_eq!("Hi", res.stdout);
+        assert!(res.stderr.contains("steps"));
+        assert!(res.stderr.contains("output bytes"));
+        assert!(res.stderr.contains("(approximate)"));
+    }
+
+    #[test]
+    fn test72() {
+        //the first word is too long, so the first `in(number)` is ignored and the second one reads the next word
+        let res = run(
+            "./test_images/original___adder.png",
+            Some("1234 5 6"),
+            Some(vec!["--max-token-length".to_string(), "3".to_string()]),
+        );
+        assert!(res.success());
+        assert_eq!("5\n", res.stdout);
+
+        let res = run("./test_images/original___adder.png", Some("1234 5 6"), None);
+        assert!(res.success());
+        assert_eq!("1239\n", res.stdout);
+    }
+
+    #[test]
+    fn test73() {
+        //one scenario per exit status
+        let args = |v: &[&str]| Some(v.iter().map(|s| s.to_string()).collect());
+        let status =
+            |image_file: &str, options: &[&str]| run(image_file, None, args(options)).exit_status;
+
+        assert_eq!(0, status("./test_images/original___hi.pietdev", &[]));
+        //program errors
+        assert_eq!(
+            2,
+            status(
+                "./test_images/original___start_point_is_black_framed.png",
+                &[]
+            )
+        );
+        assert_eq!(
+            2,
+            status("./test_images/original___unknown_color_reached.png", &[])
+        );
+        assert_eq!(2, status("./test_images/original___all_white.pietdev", &[]));
+        //I/O and usage errors
+        assert_eq!(3, status("./test_images/no_such_file.png", &[]));
+        assert_eq!(
+            3,
+            status("./test_images/original___hi.pietdev", &["--no-such-option"])
+        );
+        assert_eq!(
+            3,
+            status("./test_images/original___hi.pietdev", &["--scale", "0"])
+        );
+        //limits
+        assert_eq!(
+            4,
+            status(
+                "./test_images/original___white_start.pietdev",
+                &["--max-iter", "100"]
+            )
+        );
+        assert_eq!(4, status("./test_images/original___bomb.png", &[]));
+        //checks
+        assert_eq!(
+            5,
+            status(
+                "./test_images/original___hi.pietdev",
+                &[
+                    "--assertions",
+                    "./tests/test_images/original___hi_failing.asserts.toml"
+                ]
+            )
+        );
+        let command = "./target/release/piet_programming_language";
+        let output = Command::new(command)
+            .args([
+                "trace-diff",
+                "./tests/test_images/original___trace_a.trace",
+                "./tests/test_images/original___trace_b.trace",
+            ])
+            .output()
+            .unwrap();
+        assert_eq!(Some(5), output.status.code());
+
+        //`--help` is not an error
+        let output = Command::new(command).arg("--help").output().unwrap();
+        assert_eq!(Some(0), output.status.code());
+        assert!(String::from_utf8(output.stdout)
+            .unwrap()
+            .contains("Exit status:\n  0    success, "));
+    }
+
+    #[test]
+    fn test74() {
+        //two programs side by side, which print "Hi" and "Ha" respectively
+        let image_file = "./test_images/original___two_entries.pietdev";
+        let args = |v: &[&str]| Some(v.iter().map(|s| s.to_string()).collect());
+
+        let res = run(image_file, None, args(&["--list-entries"]));
+        assert!(res.success());
+        assert_eq!(
+            "hi  crop = [0, 0, 11, 21]\nha  crop = [0, 22, 11, 21]\n",
+            res.stdout
+        );
+
+        let res = run(image_file, None, args(&["--entry", "hi"]));
+        assert!(res.success());
+        assert_eq!("Hi", res.stdout);
+        let res = run(image_file, None, args(&["--entry", "ha"]));
+        assert!(res.success());
+        assert_eq!("Ha", res.stdout);
+
+        let res = run(image_file, None, args(&["--entry", "ho"]));
+        assert_eq!(3, res.exit_status);
+        assert!(res.stderr.contains("no entry `ho` (entries: hi, ha)"));
+
+        //validation
+        let overlapping = [
+            "--manifest",
+            "./tests/test_images/original___overlapping_entries.manifest.toml",
+        ];
+        for option in ["--list-entries", "--entry=hi"] {
+            let res = run(
+                image_file,
+                None,
+                args(&[&overlapping[..], &[option]].concat()),
+            );
+            assert_eq!(3, res.exit_status);
+            assert!(res
+                .stderr
+                .contains("line 4: the region of `ha` overlaps that of `hi`"));
+        }
+        let res = run(
+            "./test_images/original___hi.pietdev",
+            None,
+            args(&[
+                "--manifest",
+                "./tests/test_images/original___two_entries.manifest.toml",
+                "--list-entries",
+            ]),
+        );
+        assert_eq!(3, res.exit_status);
+        assert!(res
+            .stderr
+            .contains("line 5: the region of `ha` exceeds the image (11 x 21 codels)"));
+    }
+
+    #[test]
+    fn test75() {
+        //`original___hi.pietdev` at codel size 4 with a white margin of 8 pixels
+        let image_file = "./test_images/original___hi_with_margin.png";
+        let args = |v: &[&str]| Some(v.iter().map(|s| s.to_string()).collect());
+
+        let res = run(image_file, None, args(&["--crop", "8,8,44,84"]));
+        assert!(res.success());
+        assert_eq!("Hi", res.stdout);
+
+        //cropped with an offset of 2 pixels, which would make the codels 2 pixels wide with shifted blocks
+        for codel_size in [None, Some("4")] {
+            let mut options = vec!["--crop", "10,10,42,82"];
+            if let Some(codel_size) = codel_size {
+                options.extend(["--codel-size", codel_size]);
+            }
+            let res = run(image_file, None, args(&options));
+            assert_eq!(3, res.exit_status);
+            assert!(res.stdout.is_empty());
+            assert!(res.stderr.contains(
+                "the crop 10,10,42,82 cuts codels of 4 pixels, which would shift the codel grid; provide `--crop 8,8,44,84` to align the crop with the codels"
+            ));
+        }
+
+        //a crop which cuts codels but divides into codels of the given size
+        let res = run(
+            image_file,
+            Some(""),
+            args(&["--crop", "10,10,40,80", "--codel-size", "4"]),
+        );
+        assert_eq!(3, res.exit_status);
+        assert!(res.stdout.is_empty());
+        assert!(res.stderr.contains(
+            "the crop 10,10,40,80 cuts codels of 4 pixels, which would shift the codel grid; provide `--crop 8,8,40,80` to align the crop with the codels"
+        ));
+
+        let res = run(image_file, None, args(&["--crop", "0,0,100,100"]));
+        assert_eq!(3, res.exit_status);
+        assert!(res
+            .stderr
+            .contains("the crop 0,0,100,100 is empty or exceeds the image (60 x 100 pixels)"));
+    }
+
+    #[test]
+    fn test76() {
+        let image_file = "./test_images/original___adder.png";
+        let args = |v: &[&str]| Some(v.iter().map(|s| s.to_string()).collect());
+        let spec = "seed=42,numbers=100..=199,count=2";
+
+        let res = run(
+            image_file,
+            None,
+            args(&["--input-random", spec, "--io-trace"]),
+        );
+        assert!(res.success());
+        let sum = res.stdout.trim().parse::<usize>().unwrap();
+        assert!((200..=398).contains(&sum));
+        assert!(res
+            .stderr
+            .starts_with("# input: --input-random seed=42,numbers=100..=199,count=2\n"));
+        //the same seed gives the same input
+        let again = run(image_file, None, args(&["--input-random", spec]));
+        assert_eq!(res.stdout, again.stdout);
+
+        //a seed chosen at random is printed
+        let res = run(
+            image_file,
+            None,
+            args(&["--input-random", "numbers=100..=199,count=2"]),
+        );
+        assert!(res.success());
+        let (_, printed) = res
+            .stderr
+            .split_once("Generating the input with `--input-random ")
+            .unwrap();
+        let printed = printed.split_once('`').unwrap().0;
+        assert!(printed.starts_with("seed="));
+        let replay = run(image_file, None, args(&["--input-random", printed]));
+        assert_eq!(res.stdout, replay.stdout);
+
+        let res = run(
+            image_file,
+            None,
+            args(&["--input-random", "seed=1,numbers=0..=9,chars=a"]),
+        );
+        assert_eq!(3, res.exit_status);
+        assert!(res
+            .stderr
+            .contains("at most one of `numbers`, `chars` and `bytes` can be given"));
+    }
+
+    #[test]
+    fn test77() {
+        let image_file = "./test_images/original___start_point_is_black_framed.png";
+        let args = |v: &[&str]| Some(v.iter().map(|s| s.to_string()).collect());
+
+        let res = run(image_file, None, args(&["--lenient-start"]));
+        assert!(res.success());
+        assert_eq!("Hi", res.stdout);
+        assert!(res
+            .stderr
+            .contains("warning: the top-left codel is black; starting at (1, 1) instead"));
+
+        //the program still runs, but the warning makes the run fail
+        let res = run(
+            image_file,
+            None,
+            args(&["--lenient-start", "--deny-warnings"]),
+        );
+        assert_eq!(2, res.exit_status);
+        assert_eq!("Hi", res.stdout);
+        assert!(res
+            .stderr
+            .contains("1 warning(s) were raised, which are errors with `--deny-warnings`"));
+
+        let res = run(
+            "./test_images/original___hi_with_decoration.png",
+            None,
+            args(&["--fall-back-to-white", "--deny-warnings", "--check"]),
+        );
+        assert_eq!(2, res.exit_status);
+        assert!(res
+            .stderr
+            .contains("warning: 21 codel(s) of unknown colors are treated as white"));
+    }
+
+    #[test]
+    fn test78() {
+        let image_file = "./test_images/original___hi.pietdev";
+        let args = |v: &[&str]| Some(v.iter().map(|s| s.to_string()).collect());
+
+        let res = run(image_file, None, args(&["-v", "--sync-output", "always"]));
+        assert!(res.success());
+        assert_eq!("| H\n| i\n", res.stdout);
+        assert!(res.stderr.contains("    OutChar\n(0, 8)"));
+
+        //not synchronized unless both are terminals, so that a captured output is unchanged
+        let res = run(image_file, None, args(&["-v"]));
+        assert!(res.success());
+        assert_eq!("Hi", res.stdout);
+        let res = run(image_file, None, args(&["--sync-output", "always"]));
+        assert_eq!("Hi", res.stdout);
+    }
+
+    #[test]
+    // #[ignore]
+    fn test79() {
+        let args = |v: &[&str]| Some(v.iter().map(|s| s.to_string()).collect());
+
+        //`hi` with codels of 10 pixels inside of a border of 1 pixel
+        let image_file = "./test_images/original___hi_with_border.png";
+        let res = run(image_file, None, None);
+        assert!(!res.success());
+        let res = run(image_file, None, args(&["--codel-grid", "10x10+1+1"]));
+        assert!(res.success());
+        assert_eq!("Hi", res.stdout);
+        assert!(res.stderr.contains(
+            "warning: the codel grid 10x10+1+1 drops pixels outside of its whole codels (top: 1, bottom: 1, left: 1, right: 1)"
+        ));
+        let res = run(image_file, None, args(&["--detect-grid-offset"]));
+        assert!(res.success());
+        assert_eq!("Hi", res.stdout);
+
+        //codels of 4 x 5 pixels
+        let image_file = "./test_images/original___hi_4x5_codels.png";
+        let res = run(
+            image_file,
+            None,
+            args(&[
+                "--codel-grid",
+                "4x5",
+                "--strict-codel-check",
+                "--deny-warnings",
+            ]),
+        );
+        assert!(res.success());
+        assert_eq!("Hi", res.stdout);
+        let res = run(image_file, None, args(&["--codel-grid", "4x100"]));
+        assert_eq!(3, res.exit_status);
+        assert!(res.stderr.contains("covers no whole codel"));
+        let res = run(
+            image_file,
+            None,
+            args(&["--codel-grid", "4x5", "--codel-size", "1"]),
+        );
+        assert_eq!(3, res.exit_status);
+    }
+
+    #[test]
+    // #[ignore]
+    fn test80() {
+        let command = "./target/release/piet_programming_language";
+        let dir =
+            std::env::temp_dir().join(format!("piet_test_conformance_{}", std::process::id()));
+        let dir_arg = dir.to_str().unwrap();
+        let _ = fs::remove_dir_all(&dir);
+
+        let output = Command::new(command)
+            .args([
+                "conformance",
+                "--export-conformance",
+                dir_arg,
+                "--cases",
+                "./tests/conformance/cases.txt",
+            ])
+            .output()
+            .unwrap();
+        assert!(output.status.success());
+        assert!(fs::read_to_string(dir.join("index.json"))
+            .unwrap()
+            .contains(r#""cases":["hi","abcd","adder","echo_char","no_output"]"#));
+        assert!(fs::read_to_string(dir.join("README.md"))
+            .unwrap()
+            .contains("| adder | no-exit |"));
+        assert_eq!("Hi", fs::read_to_string(dir.join("hi/output.bin")).unwrap());
+        assert_eq!(
+            "7\n",
+            fs::read_to_string(dir.join("adder/output.bin")).unwrap()
+        );
+        assert!(fs::read_to_string(dir.join("hi/expected.json"))
+            .unwrap()
+            .starts_with(r#"{"schema_version":1,"name":"hi","termination":"#));
+
+        let verify = || {
+            Command::new(command)
+                .args(["conformance", "--verify-conformance", dir_arg])
+                .output()
+                .unwrap()
+        };
+        let output = verify();
+        assert!(output.status.success());
+        let stdout = String::from_utf8(output.stdout).unwrap();
+        assert!(stdout.starts_with("PASS hi\nPASS abcd\n"));
+        assert!(stdout.ends_with("5 passed, 0 failed\n"));
+
+        //a wrong expectation fails verification
+        fs::write(dir.join("adder/output.bin"), "8").unwrap();
+        fs::write(dir.join("abcd/input.bin"), "x").unwrap();
+        let output = verify();
+        assert_eq!(Some(5), output.status.code());
+        let stdout = String::from_utf8(output.stdout).unwrap();
+        assert!(stdout.contains("FAIL adder: output differs\n"));
+        assert!(stdout.contains("PASS abcd\n"));
+        assert!(stdout.ends_with("4 passed, 1 failed\n"));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    // #[ignore]
+    fn test81() {
+        //`hi` whose light yellow was quantized to white, so that `H` is lost
+        let image_file = "./test_images/original___hi_quantized.gif";
+        let args = |v: &[&str]| Some(v.iter().map(|s| s.to_string()).collect());
+        let warning = "warning: lightyellow, white are all nearest to entry 4 of the GIF palette of 8 entries, so they may have been merged by quantization (72 codel(s) have the color of the entry)\n";
+
+        let res = run(image_file, None, None);
+        assert!(res.success());
+        assert_eq!("i", res.stdout);
+        assert!(res.stderr.starts_with(warning));
+
+        let res = run(image_file, None, args(&["--check"]));
+        assert!(res.success());
+        assert!(res.stderr.starts_with(warning));
+
+        let res = run(image_file, None, args(&["--deny-warnings"]));
+        assert_eq!(2, res.exit_status);
+    }
+
+    #[test]
+    // #[ignore]
+    fn test82() {
+        let args = |v: &[&str]| Some(v.iter().map(|s| s.to_string()).collect());
+
+        //the byte order mark of the expected output is stripped
+        let res = run(
+            "./test_images/original___hi.pietdev",
+            None,
+            args(&[
+                "--expect-output",
+                "./tests/test_images/original___hi_bom.txt",
+            ]),
+        );
+        assert!(res.success());
+        assert_eq!("Hi", res.stdout);
+
+        //`é` in Latin-1
+        let image_file = "./test_images/original___e_acute.pietdev";
+        let expected = "./tests/test_images/original___e_acute_latin1.txt";
+        let res = run(
+            image_file,
+            None,
+            args(&["--expect-output", expected, "--expect-encoding", "latin1"]),
+        );
+        assert!(res.success());
+        assert_eq!("\u{e9}", res.stdout);
+
+        let res = run(image_file, None, args(&["--expect-output", expected]));
+        assert_eq!(5, res.exit_status);
+        assert!(res.stderr.contains("first difference at byte 0"));
+        assert!(res.stderr.contains("- hex e9 (bytes 0..1, not UTF-8)"));
+        assert!(res.stderr.contains(
+            "hint: the two sides differ only by encoding: they match with `--expect-encoding latin1`"
+        ));
+    }
+
+    #[test]
+    // #[ignore]
+    fn test83() {
+        let args = |v: &[&str]| Some(v.iter().map(|s| s.to_string()).collect());
+
+        //prints `a` to `z` along a straight line
+        let res = run(
+            "./test_images/original___alpha.pietdev",
+            None,
+            args(&["--check", "--output-analysis"]),
+        );
+        assert!(res.success());
+        assert_eq!(
+            "no warnings\noutput sites: 26 (0 dynamic)\nstatic characters: \"abcdefghijklmnopqrstuvwxyz\"\nstatic numbers: []\n",
+            res.stdout
+        );
+
+        //prints what it reads
+        let res = run(
+            "./test_images/original___echo_char.png",
+            None,
+            args(&["--check", "--output-analysis", "--json"]),
+        );
+        assert!(res.success());
+        assert_eq!(
+            "{\"sites\":1,\"dynamic_sites\":[{\"from\":[0,1],\"to\":[0,3],\"command\":\"OutChar\"}],\"chars\":[],\"numbers\":[]}\n",
+            res.stdout
+        );
+    }
+
+    #[test]
+    fn test84() {
+        //slides right along a corridor of white, turns down and back up at its end and retraces
+        let image = "./test_images/original___white_corridor.pietdev";
+        let trace = |verbose: &str| {
+            let res = run(image, None, Some(vec![verbose.to_string()]));
+            assert!(res.success());
+            res.stderr
+                .lines()
+                .filter(|l| l.starts_with('(') || l.starts_with("slide"))
+                .collect::<Vec<_>>()
+                .join("\n")
+        };
+        let blocked = "(0, 11)      DP:Right CC:Left\n(0, 11)      DP:Down  CC:Right\n(1, 11)      DP:Down  CC:Right\n(1, 11)      DP:Left  CC:Left\n(1, 11)      DP:Up    CC:Right\n(0, 11)      DP:Up    CC:Right\n(0, 11)      DP:Right CC:Left";
+        let summarized = format!(
+            "(0, 0)       DP:Right CC:Left\nslide (0, 1) -> (0, 11) [10 codels, DP:Right CC:Left]\n{}",
+            blocked
+        );
+        assert_eq!(summarized, trace("-v"));
+        assert_eq!(summarized, trace("-vv"));
+        let per_codel = (0..11)
+            .map(|j| format!("{:12} DP:Right CC:Left\n", format!("(0, {})", j)))
+            .collect::<String>()
+            + blocked;
+        assert_eq!(per_codel, trace("-vvv"));
+
+        //a summarized trace is expanded by `trace-diff`
+        let dir = std::env::temp_dir();
+        let a = dir.join(format!("piet_test84_{}_a.trace", std::process::id()));
+        let b = dir.join(format!("piet_test84_{}_b.trace", std::process::id()));
+        fs::write(&a, trace("-v")).unwrap();
+        fs::write(&b, trace("-vvv")).unwrap();
+        let output = Command::new("./target/release/piet_programming_language")
+            .args(["trace-diff".as_ref(), a.as_os_str(), b.as_os_str()])
+            .output()
+            .unwrap();
+        fs::remove_file(&a).unwrap();
+        fs::remove_file(&b).unwrap();
+        assert!(output.status.success());
+        assert!(String::from_utf8(output.stdout)
+            .unwrap()
+            .ends_with("18 steps\ntraces are identical\n"));
+    }
+
+    #[test]
+    fn test85() {
+        let command = "./target/release/piet_programming_language";
+        let output = Command::new(command)
+            .args(["--example", "hello"])
+            .output()
+            .unwrap();
+        assert!(output.status.success());
+        assert_eq!(b"Hello, world!\n", output.stdout.as_slice());
+        assert!(output.stderr.is_empty());
+
+        //the hint to extract a copy is printed only with `--verbose`
+        let output = Command::new(command)
+            .args(["--example", "hello", "--verbose"])
+            .output()
+            .unwrap();
+        assert!(output.status.success());
+        assert!(String::from_utf8(output.stderr)
+            .unwrap()
+            .contains("`--example hello --extract <DIR>`"));
+
+        let output = Command::new(command)
+            .args(["--list-examples"])
+            .output()
+            .unwrap();
+        assert!(output.status.success());
+        let stdout = String::from_utf8(output.stdout).unwrap();
+        assert!(stdout.starts_with("hello  Prints `Hello, world!`\ncat    "));
+
+        //the extracted copy runs the same
+        let dir = std::env::temp_dir().join(format!("piet_test85_{}", std::process::id()));
+        let output = Command::new(command)
+            .args([
+                "--example".as_ref(),
+                "cat".as_ref(),
+                "--extract".as_ref(),
+                dir.as_os_str(),
+            ])
+            .output()
+            .unwrap();
+        assert!(output.status.success());
+        let file = dir.join("cat.pietdev");
+        assert_eq!(
+            format!("{}\n", file.display()),
+            String::from_utf8(output.stdout).unwrap()
+        );
+        let mut child = Command::new(command)
+            .arg(&file)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()
+            .unwrap();
+        child.stdin.take().unwrap().write_all(b"me ow\n").unwrap();
+        let output = child.wait_with_output().unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+        assert!(output.status.success());
+        assert_eq!(b"meow", output.stdout.as_slice());
+
+        let output = Command::new(command)
+            .args([
+                "--example",
+                "hello",
+                "./tests/test_images/original___hi.pietdev",
+            ])
+            .output()
+            .unwrap();
+        assert_eq!(Some(3), output.status.code());
+    }
+
+    #[test]
+    fn test86() {
+        //hello world scaled by 2, run with the codel size 1
+        let image = "./test_images/original___hello_2x.png";
+        let check_runtime = Some(vec!["--check-runtime".to_string()]);
+        let res = __run(image, None, Some(1), check_runtime.clone());
+        assert!(res.success());
+        assert!(res.stderr.contains(
+            "so the image may be scaled by 2 and the codel size may be 2 times too small"
+        ));
+        assert!(!__run(image, None, Some(1), None)
+            .stderr
+            .contains("may be scaled by"));
+
+        let res = __run(image, None, Some(2), check_runtime);
+        assert_eq!("Hello, world!\n", res.stdout);
+        assert!(!res.stderr.contains("may be scaled by"));
+    }
+
+    #[test]
+    fn test87() {
+        let diff = |options: &[&str]| {
+            let output = Command::new("./target/release/piet_programming_language")
+                .arg("version-report")
+                .args(options)
+                .output()
+                .unwrap();
+            assert!(output.status.success());
+            String::from_utf8(output.stdout).unwrap()
+        };
+        //to be updated with the semantics (see `src/semantics.rs`)
+        let expected =
+            fs::read_to_string("./tests/test_images/original___semantics_diff.txt").unwrap();
+        assert_eq!(
+            expected,
+            diff(&[
+                "--diff-with",
+                "spec",
+                "--topology",
+                "torus",
+                "--extension",
+                "second-stack"
+            ])
+        );
+        assert_eq!(
+            "--topology torus -> spec\ntopology  \"torus\"  ->  \"bounded\"  (toggled by `--topology`)\n",
+            diff(&["--diff-with", "--topology torus"])
+        );
+        assert_eq!(
+            "spec -> spec\nno difference\n",
+            diff(&["--diff-with", "--topology bounded"])
+        );
+    }
+
+    #[test]
+    fn test88() {
+        //`in(number)` skips the line endings either way, so only the terminators in the trace and the count differ
+        let run = |newlines: &str| {
+            let output = Command::new("./target/release/piet_programming_language")
+                .args([
+                    "--example",
+                    "adder",
+                    "--stdin-string",
+                    "12\r\n30\r\n",
+                    "--io-trace",
+                    "-",
+                    "--stats",
+                    "--input-newlines",
+                    newlines,
+                ])
+                .output()
+                .unwrap();
+            assert!(output.status.success());
+            (
+                String::from_utf8(output.stdout).unwrap(),
+                String::from_utf8(output.stderr).unwrap(),
+            )
+        };
+
+        let (stdout, stderr) = run("raw");
+        assert_eq!("42\n", stdout);
+        assert!(stderr.contains("#1 in(number) read \"12\" (consumed 3 bytes, next char is '\\r')"));
+        assert!(stderr.contains(", 2 CR line endings read as they are (see `--input-newlines`)"));
+
+        let (stdout, stderr) = run("lf");
+        assert_eq!("42\n", stdout);
+        assert!(stderr.contains("#1 in(number) read \"12\" (consumed 3 bytes, next char is '\\n')"));
+        assert!(stderr.contains(", 2 CR line endings read as LF"));
+    }
+
+    #[test]
+    fn test89() {
+        let image = "./test_images/original___corner_strategy.pietdev";
+        let strategy = |name: &str| {
+            Some(vec![
+                "--max-iter".to_string(),
+                "12".to_string(),
+                "--corner-strategy".to_string(),
+                name.to_string(),
+            ])
+        };
+        //The red block is entered at its top-left codel and its top-right exit is restricted.
+        //`entry-nearest` then leaves it downward in line with the entry, instead of from the bottom-right codel.
+        let res = run(image, None, strategy("standard"));
+        assert_eq!(4, res.exit_status);
+        assert_eq!("1\n1\n1\nProgram terminated by `max-iter`.\n", res.stdout);
+        let res = run(image, None, strategy("entry-nearest"));
+        assert_eq!(4, res.exit_status);
+        assert_eq!("2\nProgram terminated by `max-iter`.\n", res.stdout);
+
+        //the standard strategy is the default and follows the recorded trace
+        let recorded = "./tests/test_images/original___corner_strategy.trace";
+        for options in [vec![], vec!["--corner-strategy", "standard"]] {
+            let file = std::env::temp_dir().join("piet_programming_language_test89.trace");
+            let output = Command::new("./target/release/piet_programming_language")
+                .args([&image.replace("./", "./tests/"), "-v", "--max-iter", "12"])
+                .args(options)
+                .output()
+                .unwrap();
+            fs::write(&file, output.stderr).unwrap();
+            let output = Command::new("./target/release/piet_programming_language")
+                .args(["trace-diff", recorded, file.to_str().unwrap()])
+                .output()
+                .unwrap();
+            assert!(output.status.success());
+            assert!(String::from_utf8(output.stdout)
+                .unwrap()
+                .contains("traces are identical\n"));
+        }
+    }
+
+    //Every report which lists blocks, codels or warnings is printed in the same order by two runs, so that its output can be diffed and snapshotted.
+    #[test]
+    fn test90() {
+        let command = "./target/release/piet_programming_language";
+        let dir = std::env::temp_dir().join("piet_programming_language_test90");
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let file = |name: &str| dir.join(name).to_str().unwrap().to_string();
+        let cases: Vec<Vec<String>> = [
+            vec![
+                "./tests/test_images/original___bomb.png",
+                "-vv",
+                "--max-iter",
+                "200",
+            ],
+            vec![
+                "./tests/test_images/original___large_push.pietdev",
+                "--check",
+            ],
+            vec!["./tests/test_images/original___abcd.png", "--check"],
+            vec![
+                "./tests/test_images/original___hi_with_decoration.png",
+                "--output-analysis",
+                "--json",
+            ],
+            vec![
+                "./tests/test_images/original___hi.pietdev",
+                "--golf-score",
+                "--json",
+            ],
+            vec![
+                "./tests/test_images/original___hi.pietdev",
+                "--check-runtime",
+                "--attribute-output",
+            ],
+            vec![
+                "./tests/test_images/original___unknown_color_reached.png",
+                "--fall-back-to-white",
+                "-v",
+                "--max-iter",
+                "200",
+            ],
+            vec![
+                "./tests/test_images/original___hi.pietdev",
+                "--svg",
+                &file("hi.svg"),
+            ],
+            vec![
+                "./tests/test_images/original___hi.pietdev",
+                "--report",
+                &file("hi.html"),
+            ],
+            vec![
+                "./tests/test_images/original___hi.pietdev",
+                "--export-pietdev",
+                &file("hi.pietdev"),
+            ],
+            vec![
+                "trace-diff",
+                "./tests/test_images/original___trace_a.trace",
+                "./tests/test_images/original___trace_b.trace",
+            ],
+        ]
+        .iter()
+        .map(|case| case.iter().map(|s| s.to_string()).collect())
+        .collect();
+
+        //the output and the files written, except the resource usage which is measured
+        let run = |args: &[String]| {
+            let output = Command::new(command)
+                .args(args)
+                .stdin(Stdio::null())
+                .output()
+                .unwrap();
+            let mut files = vec![];
+            for entry in fs::read_dir(&dir).unwrap() {
+                let path = entry.unwrap().path();
+                let contents = fs::read_to_string(&path).unwrap();
+                fs::remove_file(&path).unwrap();
+                files.push((
+                    path,
+                    contents
+                        .lines()
+                        .filter(|line| !line.contains("resources (approximate)"))
+                        .collect::<Vec<_>>()
+                        .join("\n"),
+                ));
+            }
+            files.sort();
+            (output.status.code(), output.stdout, output.stderr, files)
+        };
+        for case in cases {
+            let first = run(&case);
+            assert!(!(first.1.is_empty() && first.2.is_empty() && first.3.is_empty()));
+            assert!(first == run(&case), "{:?}", case);
+        }
+    }
+
+    //`--metrics-file` writes the counters of the run, including a failed one.
+    #[test]
+    fn test91() {
+        let dir = std::env::temp_dir().join("piet_programming_language_test91");
+        let _ = fs::remove_dir_all(&dir);
+        let file = dir.join("metrics.prom");
+        let run = |image: &str, options: &[&str]| {
+            let output = Command::new("./target/release/piet_programming_language")
+                .arg(format!("./tests/test_images/{}", image))
+                .args(["--metrics-file", file.to_str().unwrap()])
+                .args(options)
+                .stdin(Stdio::null())
+                .output()
+                .unwrap();
+            let metrics = fs::read_to_string(&file).unwrap();
+            assert!(metrics.ends_with("\n# EOF\n"));
+            (output.status.code(), metrics)
+        };
+        let cache_dir = dir.join("cache");
+        let options = ["--cache-dir", cache_dir.to_str().unwrap()];
+
+        fs::create_dir_all(&dir).unwrap();
+        let (status, metrics) = run("original___hi.pietdev", &options);
+        assert_eq!(Some(0), status);
+        for line in [
+            "piet_runs_started_total 1",
+            "piet_runs_finished_total{reason=\"no_exit\"} 1",
+            "piet_steps_total 5",
+            "piet_output_bytes_total 2",
+            "piet_image_cache_lookups_total{result=\"hit\"} 0",
+            "piet_image_cache_lookups_total{result=\"miss\"} 1",
+            "piet_run_duration_seconds_count 1",
+        ] {
+            assert!(metrics.lines().any(|l| l == line), "{}", line);
+        }
+        let (_, metrics) = run("original___hi.pietdev", &options);
+        assert!(metrics
+            .lines()
+            .any(|l| l == "piet_image_cache_lookups_total{result=\"hit\"} 1"));
+
+        let (status, metrics) = run("original___unknown_color_reached.png", &[]);
+        assert_ne!(Some(0), status);
+        assert!(metrics
+            .lines()
+            .any(|l| l == "piet_runs_finished_total{reason=\"error\"} 1"));
+    }
+
+    //The background block is detected on images with a white or colored background but not on a small dense program, and can be overridden or excluded.
+    #[test]
+    fn test92() {
+        let run = |args: &[&str]| {
+            let output = Command::new("./target/release/piet_programming_language")
+                .args(args)
+                .stdin(Stdio::null())
+                .output()
+                .unwrap();
+            assert!(output.status.success());
+            String::from_utf8(output.stdout).unwrap()
+        };
+        let image = |name: &str| format!("./tests/test_images/original___{}.pietdev", name);
+        let background = |name: &str, options: &[&str]| {
+            run(&[&[image(name).as_str(), "--image-info"], options].concat())
+                .lines()
+                .find_map(|l| l.strip_prefix("background: ").map(str::to_string))
+                .unwrap()
+        };
+
+        assert_eq!(
+            "the block at (0, 2) of size 27 (White, touching the top, right, bottom, left borders)",
+            background("white_background", &[])
+        );
+        assert_eq!(
+            "the block at (0, 2) of size 26 (Yellow, touching the top, right, bottom, left borders)",
+            background("colored_background", &[])
+        );
+        assert_eq!("none detected", background("dense", &[]));
+        //the largest block of `hi` pushes the code of `i`, so it is not a background
+        assert_eq!(
+            "the block at (0, 8) of size 105 (Magenta, touching the top, bottom borders)",
+            background("hi", &[])
+        );
+        assert_eq!(
+            "none (`--background none`)",
+            background("hi", &["--background", "none"])
+        );
+        assert_eq!(
+            "warning: large-push: the background block at (0, 8) of size 105 is the source of a Push\n",
+            run(&[&image("hi"), "--check"])
+        );
+        assert_eq!(
+            "no warnings\n",
+            run(&[&image("hi"), "--check", "--background", "none"])
+        );
+
+        //excluded, and reported as such
+        assert_eq!(
+            "no warnings\nnote: the background block at (0, 8) of size 105 is excluded (`--ignore-background`)\n",
+            run(&[&image("hi"), "--check", "--ignore-background"])
+        );
+        assert!(run(&["golf", &image("colored_background"), "--json"])
+            .ends_with(r#""colored_codels":29,"blocks":4,"distinct_colors":5,"bounding_box":{"top":0,"left":0,"bottom":4,"right":5},"rules":"codels","score":30}
+"#));
+        assert!(run(&[
+            "golf",
+            &image("colored_background"),
+            "--json",
+            "--ignore-background"
+        ])
+        .ends_with(r#""colored_codels":3,"blocks":3,"distinct_colors":4,"bounding_box":{"top":0,"left":0,"bottom":1,"right":1},"rules":"codels","score":30,"excluded_block":{"row":0,"col":2,"size":26}}
+"#));
+        let file = std::env::temp_dir().join("piet_programming_language_test92.html");
+        run(&[
+            &image("hi"),
+            "--ignore-background",
+            "--report",
+            file.to_str().unwrap(),
+        ]);
+        assert!(fs::read_to_string(&file).unwrap().contains(
+            "<tr><th>visited blocks</th><td>4 of 6 (the background block at (0, 8) of size 105 is excluded)</td></tr>"
+        ));
+    }
+
+    //`--record-input` records the input which the program read (not what it left unread), which replays the session with `--input`.
+    #[test]
+    fn test93() {
+        let file = std::env::temp_dir().join("piet_programming_language_test93.txt");
+        let file = file.to_str().unwrap().to_string();
+        let result = run(
+            "test_images/original___adder.png",
+            Some("12 30\n99 unread"),
+            Some(vec!["--record-input".to_string(), file.clone()]),
+        );
+        assert_eq!("42\n", result.stdout);
+        assert_eq!(0, result.exit_status);
+        assert_eq!("12 30\n", fs::read_to_string(&file).unwrap());
+
+        let result = run(
+            "test_images/original___adder.png",
+            None,
+            Some(vec!["--input".to_string(), file]),
+        );
+        assert_eq!("42\n", result.stdout);
+        assert_eq!(0, result.exit_status);
+    }
+
+    //A JPEG re-encode of `hello_2x.png` is diagnosed as such, and runs with `--color-tolerance`.
+    #[test]
+    fn test94() {
+        let image = "test_images/original___hello_2x.jpg";
+        let tolerance = |t: u8| Some(vec!["--color-tolerance".to_string(), t.to_string()]);
+
+        let result = run(image, Some(""), None);
+        assert_eq!(2, result.exit_status);
+        assert!(result.stderr.starts_with(
+            "Error: 2918 of the 3312 codels (88.1%) have none of the 20 colors of Piet, which is typical of a JPEG image"
+        ));
+        assert!(result.stderr.contains("`--color-tolerance`"));
+        assert!(result.stderr.contains("re-export the program as PNG"));
+
+        let result = run(image, Some(""), tolerance(16));
+        assert_eq!(2, result.exit_status);
+        assert!(result
+            .stderr
+            .contains("have none of the 20 colors of Piet within `--color-tolerance 16`"));
+
+        let result = run(image, Some(""), tolerance(31));
+        assert_eq!("Hello, world!\n", result.stdout);
+        assert_eq!(0, result.exit_status);
+        assert!(result.stderr.contains(
+            "warning: 2918 pixel(s) of colors within `--color-tolerance 31` of the colors of Piet are read as them"
+        ));
+
+        let result = run(image, Some(""), tolerance(32));
+        assert_eq!(3, result.exit_status);
+    }
+
+    //The IO conformance suite (`tests/io_conformance/cases.txt`): every program is run with every transcript of it, and the output must match byte for byte.
+    #[test]
+    fn test95() {
+        let dir = "./tests/io_conformance";
+        let cases = fs::read_to_string(format!("{}/cases.txt", dir)).unwrap();
+        let mut num_cases = 0;
+        let mut programs = vec![];
+        for line in cases.lines() {
+            let line = line.split('#').next().unwrap().trim();
+            if line.is_empty() {
+                continue;
+            }
+            let mut fields = line.split_whitespace();
+            let program = fields.next().unwrap();
+            let case = fields.next().unwrap();
+            let transcript = format!("{}/transcripts/{}", dir, case);
+
+            //through stdin rather than `--input`, as an input may not be valid UTF-8
+            let mut child = Command::new("./target/release/piet_programming_language")
+                .arg(format!("{}/{}.pietdev", dir, program))
+                .args(fields)
+                .stdin(Stdio::piped())
+                .stdout(Stdio::piped())
+                .stderr(Stdio::null())
+                .spawn()
+                .unwrap();
+            let input = fs::read(format!("{}.in", transcript)).unwrap();
+            child.stdin.take().unwrap().write_all(&input).unwrap();
+            let output = child.wait_with_output().unwrap();
+            let expected = fs::read(format!("{}.out", transcript)).unwrap();
+            assert_eq!(
+                String::from_utf8_lossy(&expected),
+                String::from_utf8_lossy(&output.stdout),
+                "{}",
+                case
+            );
+            assert_eq!(expected, output.stdout, "{}", case);
+            assert!(output.status.success(), "{}", case);
+
+            num_cases += 1;
+            if !programs.contains(&program) {
+                programs.push(program);
+            }
+        }
+        assert!(num_cases >= 12);
+        assert!(programs.len() >= 4);
+    }
+
+    //`--print-config` shows the limits of the preset overridden by an explicit option, and the region of the entry read from the manifest.
+    #[test]
+    fn test96() {
+        let options = |extra: &[&str]| {
+            Some(
+                [
+                    "--limits",
+                    "playground",
+                    "--max-iter",
+                    "500",
+                    "--entry",
+                    "ha",
+                ]
+                .iter()
+                .chain(extra)
+                .map(|s| s.to_string())
+                .collect(),
+            )
+        };
+        let image = "test_images/original___two_entries.pietdev";
+
+        let result = run(image, Some(""), options(&["--print-config", "only"]));
+        assert_eq!(0, result.exit_status);
+        let lines = result.stdout.lines().collect::<Vec<_>>();
+        let line = |name: &str| {
+            lines
+                .iter()
+                .find(|l| l.starts_with(&format!("{} ", name)))
+                .unwrap()
+                .split_whitespace()
+                .collect::<Vec<_>>()
+                .join(" ")
+        };
+        assert_eq!("limits.max_iter 500 (option)", line("limits.max_iter"));
+        assert_eq!(
+            "limits.stall_timeout 2 (preset playground)",
+            line("limits.stall_timeout")
+        );
+        assert_eq!(
+            "load.entry_region \"0,22,11,21\" (manifest ./tests/test_images/original___two_entries.manifest.toml)",
+            line("load.entry_region")
+        );
+        assert_eq!("load.codel_size 1 (detected)", line("load.codel_size"));
+
+        let result = run(image, Some(""), options(&["--print-config=only", "--json"]));
+        assert_eq!(0, result.exit_status);
+        assert!(result.stdout.starts_with("{\"load\":{"));
+        assert!(result.stdout.contains(
+            r#""limits":{"preset":{"value":"playground","source":"option"},"max_iter":{"value":500,"source":"option"},"stall_timeout":{"value":2,"source":"preset playground"}"#
+        ));
+
+        //proceeds with the run
+        let result = run(image, Some(""), options(&["--print-config"]));
+        assert_eq!("Ha", result.stdout);
+        assert!(result.stderr.contains("limits.max_iter"));
+    }
+
+    //A single colored block terminates after the attempts to leave it, and a white image by the retrace detection, both with the start traced and no command executed.
+    #[test]
+    fn test97() {
+        let options = |extra: &[&str]| {
+            Some(
+                ["--allow-empty", "--stats", "--verbose", "--art-fit", "off"]
+                    .iter()
+                    .chain(extra)
+                    .map(|s| s.to_string())
+                    .collect(),
+            )
+        };
+        let trace = |stderr: &str| {
+            stderr
+                .lines()
+                .filter(|l| l.starts_with('(') || l.starts_with("slide"))
+                .map(str::to_string)
+                .collect::<Vec<_>>()
+        };
+        for image in ["single_block", "single_codel"] {
+            let image = format!("./test_images/original___{}.pietdev", image);
+            for topology in ["bounded", "torus"] {
+                let res = run(&image, Some(""), options(&["--topology", topology]));
+                assert!(res.success());
+                assert_eq!("", res.stdout);
+                assert_eq!(vec!["(0, 0)       DP:Right CC:Left"], trace(&res.stderr));
+                assert!(res.stderr.contains(
+                    "program halted normally after 0 commands with stack depth 0\n1 steps, 0 commands, 0 output bytes, 0 input bytes, "
+                ));
+            }
+        }
+
+        let res = run(
+            "./test_images/original___all_white.pietdev",
+            Some(""),
+            options(&[]),
+        );
+        assert!(res.success());
+        assert_eq!(
+            vec![
+                "slide (0, 0) -> (0, 2) [2 codels, DP:Right CC:Left]",
+                "(0, 2)       DP:Right CC:Left",
+                "(0, 2)       DP:Down  CC:Right",
+                "(1, 2)       DP:Down  CC:Right",
+                "slide (1, 2) -> (1, 0) [2 codels, DP:Left CC:Left]",
+                "(1, 0)       DP:Left  CC:Left",
+                "(1, 0)       DP:Up    CC:Right",
+                "(0, 0)       DP:Up    CC:Right",
+                "(0, 0)       DP:Right CC:Left",
+            ],
+            trace(&res.stderr)
+        );
+        assert!(res.stderr.contains(
+            "program halted normally after 0 commands with stack depth 0\n11 steps, 0 commands, 0 output bytes, 0 input bytes, "
+        ));
+    }
+    //A palette which maps a color to another color of Piet is applied at load and warned about, and `--check` names the transitions whose commands change.
+    #[test]
+    fn test98() {
+        let palette = "./tests/test_images/original___hi_merge.palette";
+        let merged = "warning: `--palette` maps darkred (1 codel(s)), red (72 codel(s)) to red, so their adjacent blocks are merged\n";
+
+        //The mapped program runs `InChar`.
+        let res = run(
+            "./test_images/original___hi.pietdev",
+            Some(""),
+            Some(vec!["--palette".to_string(), palette.to_string()]),
+        );
+        assert!(res.success());
+        //`Push` from red to dark red no longer runs, so only the second letter is printed.
+        assert_eq!("i", res.stdout);
+        assert_eq!(merged, res.stderr);
+
+        let res = run(
+            "./test_images/original___hi.pietdev",
+            None,
+            Some(vec![
+                "--palette".to_string(),
+                palette.to_string(),
+                "--check".to_string(),
+            ]),
+        );
+        assert!(res.success());
+        for line in [
+            merged,
+            "warning: `--palette` changes the transition from darkred to red from Pop to a merge at 1 block(s): (0, 7) -> (0, 0)\n",
+            "warning: `--palette` changes the transition from red to darkred from Push to a merge at 1 block(s): (0, 0) -> (0, 7)\n",
+            "warning: `--palette` changes the transition from magenta to darkred from Subtract to Add at 1 block(s): (0, 8) -> (0, 7)\n",
+        ] {
+            assert!(res.stderr.contains(line), "{}", res.stderr);
+        }
+
+        //an invalid palette file
+        let res = run(
+            "./test_images/original___hi.pietdev",
+            None,
+            Some(vec![
+                "--palette".to_string(),
+                "./tests/test_images/original___hi.asserts.toml".to_string(),
+            ]),
+        );
+        assert!(!res.success());
+        assert!(res.stderr.contains("original___hi.asserts.toml: line 2: "));
+    }
+    //`--expect-streaming` compares a multi-megabyte output as it is written, stopping at the first difference (or running to the end) and reporting it with the bytes around it.
+    #[test]
+    fn test99() {
+        let image = "./test_images/original___single_row.pietdev";
+        let file = std::env::temp_dir().join(format!("piet_test99_{}.txt", std::process::id()));
+        //`5\n` every 10 steps, with a difference near the end
+        let num_output_bytes = 2_000_000;
+        let mut expected = b"5\n".repeat(num_output_bytes / 2);
+        expected[num_output_bytes - 1000] = b'6';
+        fs::write(&file, &expected).unwrap();
+        let options = |max_iter: usize, extra: &[&str]| {
+            Some(
+                [
+                    "--max-iter",
+                    &max_iter.to_string(),
+                    "--expect-output",
+                    file.to_str().unwrap(),
+                ]
+                .iter()
+                .chain(extra)
+                .map(|s| s.to_string())
+                .collect(),
+            )
+        };
+
+        let res = run(
+            image,
+            None,
+            options(num_output_bytes * 5, &["--expect-streaming"]),
+        );
+        assert_eq!(5, res.exit_status);
+        assert_eq!(num_output_bytes - 998, res.stdout.len());
+        assert!(res.stderr.contains(&format!(
+            "the output differs\nfirst difference at byte 1999000 (expected {} bytes, got 1999002 before the run was stopped)\n- \"5\\n5\\n5\\n5\\n5\\n5\\n5\\n5\\n6\\n5\\n5\\n5\\n5\\n5\\n5\\n5\\n\" (bytes 1998984..1999016)\n+ \"5\\n5\\n5\\n5\\n5\\n5\\n5\\n5\\n5\\n\" (bytes 1998984..1999002)\n",
+            num_output_bytes
+        )));
+
+        //run to the end for the statistics, where the output is shorter than expected
+        let res = run(
+            image,
+            None,
+            options(1000, &["--expect-streaming", "complete", "--stats"]),
+        );
+        assert_eq!(5, res.exit_status);
+        assert!(res.stderr.contains(&format!(
+            "first difference at byte 200 (expected {} bytes, got 200)\n",
+            num_output_bytes
+        )));
+        assert!(res
+            .stderr
+            .contains("1000 steps, 400 commands, 200 output bytes"));
+        fs::remove_file(&file).unwrap();
+    }
+    //`--self-test` checks the built-in tables against the spec without an image.
+    #[test]
+    fn test100() {
+        let output = Command::new("./target/release/piet_programming_language")
+            .args(["--self-test"])
+            .output()
+            .unwrap();
+        assert!(output.status.success());
+        let stdout = String::from_utf8(output.stdout).unwrap();
+        assert!(!stdout.contains("FAIL"));
+        assert!(stdout.ends_with(" passed, 0 failed\n"));
+    }
+    //A TIFF of two pages is not run until one of them is chosen with `--frame`.
+    #[test]
+    fn test101() {
+        let image = "./test_images/original___two_pages.tiff";
+        let res = run(image, Some(""), None);
+        assert_eq!(3, res.exit_status);
+        assert_eq!(
+            "Error: the image has 2 pages; provide `--frame <N>` to choose the program among them:\n  0: 11 x 21 pixels\n  1: 13 x 21 pixels\n",
+            res.stderr
+        );
+        assert!(res.stdout.is_empty());
+
+        let res = run(
+            image,
+            Some(""),
+            Some(vec!["--frame".to_string(), "1".to_string()]),
+        );
+        assert!(res.success());
+        assert_eq!("é", res.stdout);
+        let res = run(
+            image,
+            Some(""),
+            Some(vec!["--frame".to_string(), "0".to_string()]),
+        );
+        assert!(res.success());
+        assert_eq!("Hi", res.stdout);
+
+        let res = run(
+            image,
+            Some(""),
+            Some(vec!["--frame".to_string(), "2".to_string()]),
+        );
+        assert_eq!(3, res.exit_status);
+        assert!(res
+            .stderr
+            .starts_with("Error: `--frame 2` is out of range as the image has 2 pages:\n"));
+    }
+    //A start block joined to a banner through a 1-codel bridge is reported with the bridge to cut, by `--check` and on the first Push with `--check-runtime`.
+    #[test]
+    fn test102() {
+        let image = "./test_images/original___hi_with_banner.pietdev";
+        let finding = "the start block at (0, 0) of size 185 (more than 128) spans rows 0 to 27 and columns 0 to 6; cutting the 1-codel bridge at (11, 0) leaves 72 codels in it";
+        let res = run(image, None, Some(vec!["--check".to_string()]));
+        assert!(res.success());
+        assert_eq!(
+            format!("warning: large-start-block: {}\n", finding),
+            res.stdout
+        );
+
+        let res = run(
+            image,
+            None,
+            Some(vec![
+                "--check".to_string(),
+                "--start-block-threshold".to_string(),
+                "185".to_string(),
+            ]),
+        );
+        assert_eq!("no warnings\n", res.stdout);
+
+        let res = run(image, Some(""), Some(vec!["--check-runtime".to_string()]));
+        assert!(res.success());
+        //185 is pushed instead of 72 (`H`)
+        assert_eq!("\u{b9}i", res.stdout);
+        assert_eq!(
+            format!(
+                "warning: the first Push (on step 1) pushes the size of {}\n",
+                finding
+            ),
+            res.stderr
+        );
+
+        let res = run(
+            image,
+            Some(""),
+            Some(vec![
+                "--check-runtime".to_string(),
+                "--allow-large-start-block".to_string(),
+            ]),
+        );
+        assert!(res.stderr.is_empty());
+    }
+    //An endless input is cut by `--max-input-bytes`, after which a program reading to EOF ends normally.
+    #[test]
+    fn test103() {
+        let image = "./test_images/original___cat_until_eof.pietdev";
+        let res = run(
+            image,
+            None,
+            Some(
+                [
+                    "--input-random",
+                    "seed=1,chars=a-z",
+                    "--max-input-bytes",
+                    "1024",
+                    "--stats",
+                ]
+                .iter()
+                .map(|s| s.to_string())
+                .collect(),
+            ),
+        );
+        assert!(res.success());
+        assert_eq!(1024, res.stdout.len());
+        assert!(res.stdout.bytes().all(|b| b.is_ascii_lowercase()));
+        assert!(res
+            .stderr
+            .contains(" output bytes, 1024 input bytes (cut by `--max-input-bytes`), "));
+
+        //a shorter input ends first
+        let res = run(
+            image,
+            Some("abc"),
+            Some(vec![
+                "--max-input-bytes".to_string(),
+                "1024".to_string(),
+                "--stats".to_string(),
+            ]),
+        );
+        assert!(res.success());
+        assert_eq!("abc", res.stdout);
+        assert!(res.stderr.contains(" 3 output bytes, 3 input bytes, "));
+    }
+    //`--semantics` prints the decisions in effect without an image.
+    #[test]
+    fn test104() {
+        let output = Command::new("./target/release/piet_programming_language")
+            .args(["--semantics", "--number-overflow", "saturate"])
+            .output()
+            .unwrap();
+        assert!(output.status.success());
+        let stdout = String::from_utf8(output.stdout).unwrap();
+        assert!(stdout.starts_with("stack_underflow  "));
+        assert!(stdout.contains(
+            "\nnumber_overflow      saturate         `--number-overflow` (default: ignore)  "
+        ));
+        assert!(stdout.contains("\ndivision             truncate         fixed  "));
+
+        let output = Command::new("./target/release/piet_programming_language")
+            .args(["--semantics", "--json"])
+            .output()
+            .unwrap();
+        assert!(output.status.success());
+        let stdout = String::from_utf8(output.stdout).unwrap();
+        assert!(stdout.starts_with(r#"[{"name":"stack_underflow","value":"ignore","#));
+        assert!(stdout.contains(r#"{"name":"corner_strategy","value":"standard","default":"standard","field":"corner_strategy","flag":"--corner-strategy","#));
+    }
+
+    //Our side of `--compare-with` runs the program mapped by `--palette`, as `run` does.
+    #[test]
+    fn test105() {
+        let res = run(
+            "./test_images/original___hi.pietdev",
+            None,
+            Some(vec![
+                "--palette".to_string(),
+                "./tests/test_images/original___hi_merge.palette".to_string(),
+                "--compare-with".to_string(),
+                "echo".to_string(),
+                "--stdin-string".to_string(),
+                String::new(),
+            ]),
+        );
+        assert_eq!(5, res.exit_status);
+        assert!(res
+            .stdout
+            .ends_with("line 1:\n- i\n+ ./tests/./test_images/original___hi.pietdev\n"));
+    }
+
+    //A reader of the output which goes away (e.g. `| head`) ends the run normally, without a panic.
+    #[test]
+    fn test106() {
+        let mut child = Command::new("./target/release/piet_programming_language")
+            .args([
+                "./tests/test_images/original___cat_forever.pietdev",
+                "--input-random",
+                "seed=1,chars=a-z",
+            ])
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .unwrap();
+        let mut stdout = child.stdout.take().unwrap();
+        let mut buf = [0; 5];
+        stdout.read_exact(&mut buf).unwrap();
+        assert!(buf.iter().all(u8::is_ascii_lowercase));
+        drop(stdout);
+        let output = child.wait_with_output().unwrap();
+        assert!(output.status.success());
+        assert!(output.stderr.is_empty());
+    }
+
+    //A threshold of `--notify` too long for a `Duration` is never reached, instead of a panic.
+    #[test]
+    fn test107() {
+        let res = run(
+            "./test_images/original___hi.pietdev",
+            Some(""),
+            Some(vec!["--notify".to_string(), "1e30".to_string()]),
+        );
+        assert_eq!(0, res.exit_status);
+        assert_eq!("Hi", res.stdout);
+        assert!(res.stderr.is_empty());
+    }
 }
@@ -0,0 +1,138 @@
+//Measures the peak heap usage of loading a large image with an allocator which counts the allocated bytes.
+//This is a separate test binary because the allocator is global and the other tests would disturb the count.
+
+use std::io;
+use std::sync::atomic::Ordering;
+use std::sync::Mutex;
+
+use clap::Parser;
+use image::{ImageReader, RgbImage};
+use piet_programming_language::args::Args;
+use piet_programming_language::error::PietError;
+use piet_programming_language::image::Image;
+use piet_programming_language::interpreter::Interpreter;
+use piet_programming_language::pietdev;
+use piet_programming_language::program::Program;
+
+mod counting_allocator;
+
+use counting_allocator::{CURRENT, PEAK};
+
+//The tests are run one at a time so that each measures only its own allocations.
+static LOCK: Mutex<()> = Mutex::new(());
+
+#[test]
+fn test_peak_memory_of_loading_large_image() {
+    let _lock = LOCK.lock().unwrap();
+    let original = "./tests/test_images/original___hi_with_decoration.png";
+    let scale = 30;
+
+    //synthetically enlarged fixture
+    let img = ImageReader::open(original)
+        .unwrap()
+        .decode()
+        .unwrap()
+        .to_rgb8();
+    let enlarged = RgbImage::from_fn(img.width() * scale, img.height() * scale, |x, y| {
+        *img.get_pixel(x / scale, y / scale)
+    });
+    let num_pixels = (enlarged.width() * enlarged.height()) as usize;
+    let file = std::env::temp_dir().join(format!("piet_test_memory_{}.png", std::process::id()));
+    enlarged.save(&file).unwrap();
+    drop(img);
+    drop(enlarged);
+
+    let base = CURRENT.load(Ordering::SeqCst);
+    PEAK.store(base, Ordering::SeqCst);
+    let loaded = Image::new(&file, None, None, false).unwrap();
+    let peak = PEAK.load(Ordering::SeqCst) - base;
+    std::fs::remove_file(&file).unwrap();
+
+    //The decoded buffer (3 bytes per pixel) is the only data proportional to the number of pixels.
+    //Copying it into a matrix of pixels would double this.
+    assert!(
+        peak < num_pixels * 3 * 3 / 2,
+        "peak: {} bytes for {} pixels",
+        peak,
+        num_pixels
+    );
+
+    //identical to the original
+    let expected = Image::new(original, None, None, false).unwrap();
+    assert_eq!(expected.height(), loaded.height());
+    assert_eq!(expected.width(), loaded.width());
+    for i in 0..expected.height() {
+        for j in 0..expected.width() {
+            assert_eq!(expected.get_codel_at((i, j)), loaded.get_codel_at((i, j)));
+            assert_eq!(
+                expected.get_block_id_at((i, j)),
+                loaded.get_block_id_at((i, j))
+            );
+        }
+    }
+}
+
+#[test]
+fn test_peak_memory_of_streaming_expected_output() {
+    let _lock = LOCK.lock().unwrap();
+    //prints `5\n` every 10 steps
+    let program = Program::new(Image::from_codel_grid(
+        pietdev::parse(
+            &std::fs::read_to_string("./tests/test_images/original___single_row.pietdev").unwrap(),
+        )
+        .unwrap(),
+    ));
+    let num_output_bytes = 500_000;
+    let mut expected = b"5\n".repeat(num_output_bytes / 2);
+    let offset = num_output_bytes - 1000;
+    expected[offset] = b'6';
+    let file = std::env::temp_dir().join(format!(
+        "piet_test_memory_{}_expected.txt",
+        std::process::id()
+    ));
+    std::fs::write(&file, &expected).unwrap();
+    drop(expected);
+    let max_iter = (num_output_bytes / 2 * 10).to_string();
+
+    for on_mismatch in ["stop", "complete"] {
+        let args = Args::parse_from([
+            "piet_programming_language",
+            "",
+            "--max-iter",
+            &max_iter,
+            "--expect-output",
+            file.to_str().unwrap(),
+            "--expect-streaming",
+            on_mismatch,
+        ]);
+        let mut ip = Interpreter::new();
+        ip.output_writer = Some(Box::new(io::sink()));
+
+        let base = CURRENT.load(Ordering::SeqCst);
+        PEAK.store(base, Ordering::SeqCst);
+        let result = piet_programming_language::run_image_with(&args, &program, &mut ip);
+        let peak = PEAK.load(Ordering::SeqCst) - base;
+
+        assert!(matches!(
+            result.unwrap_err().downcast_ref(),
+            Some(PietError::OutputsDiffer)
+        ));
+        //the run stopped right after the output of the difference (`5\n` at once), or ran to the end
+        assert_eq!(
+            if on_mismatch == "stop" {
+                offset + 2
+            } else {
+                num_output_bytes
+            },
+            ip.num_output_bytes
+        );
+        //The expected output is read through a buffer of a few kilobytes, and the output is never kept.
+        assert!(
+            peak < 32 * 1024,
+            "peak: {} bytes for {} bytes of output",
+            peak,
+            num_output_bytes
+        );
+    }
+    std::fs::remove_file(&file).unwrap();
+}